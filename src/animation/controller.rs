@@ -0,0 +1,269 @@
+use super::{AnimationClip, Skeleton, Transform};
+use crate::math::{lerp, quat_slerp};
+
+use std::collections::HashMap;
+
+/// What a state plays.
+#[derive(Debug, Clone)]
+pub enum Motion {
+	Clip(usize),
+	/// Blends between motions placed along a parameter, idle/walk/run by speed for example.
+	/// Children are `(threshold, motion)` sorted by threshold.
+	Blend1d {
+		parameter: String,
+		children: Vec<(f32, Motion)>,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+	pub name: String,
+	pub motion: Motion,
+	pub speed: f32,
+	pub looping: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Playback {
+	state: usize,
+	// normalized so every clip in a blend tree stays in sync
+	phase: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+	from: Playback,
+	elapsed: f32,
+	duration: f32,
+}
+
+/// Plays states built from clips and blend trees, cross-fading between them.
+///
+/// Events on the clips are collected during `update` and handed out by
+/// `drain_events`. In a blend tree only the clip with the highest weight fires
+/// events so blended footsteps don't double up.
+pub struct AnimationController {
+	clips: Vec<AnimationClip>,
+	rest: Vec<Transform>,
+	states: Vec<State>,
+	parameters: HashMap<String, f32>,
+	current: Option<Playback>,
+	fade: Option<Fade>,
+	events: Vec<String>,
+}
+
+impl AnimationController {
+	pub fn new(skeleton: &Skeleton, clips: Vec<AnimationClip>) -> Self {
+		AnimationController {
+			clips,
+			rest: skeleton.rest_pose(),
+			states: Vec::new(),
+			parameters: HashMap::new(),
+			current: None,
+			fade: None,
+			events: Vec::new(),
+		}
+	}
+
+	pub fn clip(&self, name: &str) -> Option<usize> {
+		self.clips.iter().position(|clip| clip.name == name)
+	}
+
+	/// Adds a looping state at normal speed and returns its index.
+	pub fn add_state(&mut self, name: &str, motion: Motion) -> usize {
+		self.states.push(State {
+			name: name.to_owned(),
+			motion,
+			speed: 1.0,
+			looping: true,
+		});
+		self.states.len() - 1
+	}
+
+	pub fn state(&self, name: &str) -> Option<usize> {
+		self.states.iter().position(|state| state.name == name)
+	}
+
+	pub fn state_mut(&mut self, index: usize) -> &mut State {
+		&mut self.states[index]
+	}
+
+	pub fn current_state(&self) -> Option<usize> {
+		self.current.map(|playback| playback.state)
+	}
+
+	pub fn set_parameter(&mut self, name: &str, value: f32) {
+		self.parameters.insert(name.to_owned(), value);
+	}
+
+	/// Value of a parameter, zero if it was never set.
+	pub fn parameter(&self, name: &str) -> f32 {
+		self.parameters.get(name).copied().unwrap_or(0.0)
+	}
+
+	/// Switches to a state immediately.
+	pub fn play(&mut self, state: usize) {
+		self.current = Some(Playback { state, phase: 0.0 });
+		self.fade = None;
+	}
+
+	/// Blends from the current state into `state` over `duration` seconds.
+	pub fn cross_fade(&mut self, state: usize, duration: f32) {
+		match self.current {
+			Some(from) if duration > 0.0 && from.state != state => {
+				self.current = Some(Playback { state, phase: 0.0 });
+				self.fade = Some(Fade {
+					from,
+					elapsed: 0.0,
+					duration,
+				});
+			}
+			_ => self.play(state),
+		}
+	}
+
+	pub fn update(&mut self, delta: f32) {
+		if let Some(mut fade) = self.fade.take() {
+			fade.elapsed += delta;
+			if fade.elapsed < fade.duration {
+				fade.from = self.advance(fade.from, delta, false);
+				self.fade = Some(fade);
+			}
+		}
+
+		if let Some(current) = self.current {
+			self.current = Some(self.advance(current, delta, true));
+		}
+	}
+
+	/// Names of the events fired since the last call, in the order they happened.
+	pub fn drain_events(&mut self) -> std::vec::Drain<'_, String> {
+		self.events.drain(..)
+	}
+
+	/// Writes the blended pose. Joints no clip animates are left at rest.
+	pub fn sample(&self, pose: &mut [Transform]) {
+		pose.copy_from_slice(&self.rest);
+
+		if let Some(current) = self.current {
+			self.sample_playback(current, pose);
+		}
+
+		if let Some(fade) = self.fade {
+			let mut from = self.rest.clone();
+			self.sample_playback(fade.from, &mut from);
+			blend_poses(&mut from, pose, fade.elapsed / fade.duration);
+			pose.copy_from_slice(&from);
+		}
+	}
+
+	fn advance(&mut self, playback: Playback, delta: f32, fire_events: bool) -> Playback {
+		let state = &self.states[playback.state];
+		let (speed, looping) = (state.speed, state.looping);
+		let weights = self.weights(&state.motion);
+		let duration: f32 = weights
+			.iter()
+			.map(|&(clip, weight)| self.clips[clip].duration * weight)
+			.sum();
+		if duration <= 0.0 {
+			return playback;
+		}
+
+		let mut end = playback.phase + delta * speed / duration;
+		if !looping {
+			end = end.clamp(0.0, 1.0);
+		}
+
+		if fire_events {
+			let clips = &self.clips;
+			let dominant = weights
+				.iter()
+				.max_by(|a, b| a.1.total_cmp(&b.1))
+				.map(|&(clip, _)| &clips[clip]);
+
+			if let Some(clip) = dominant.filter(|clip| clip.duration > 0.0) {
+				let start = playback.phase;
+				for event in &clip.events {
+					let at = event.time / clip.duration;
+					let crossed = if end >= start {
+						(at > start && at <= end) || at + 1.0 <= end
+					} else {
+						(at < start && at >= end) || at - 1.0 >= end
+					};
+					if crossed {
+						self.events.push(event.name.clone());
+					}
+				}
+			}
+		}
+
+		Playback {
+			state: playback.state,
+			phase: if looping { end.rem_euclid(1.0) } else { end },
+		}
+	}
+
+	fn sample_playback(&self, playback: Playback, pose: &mut [Transform]) {
+		let mut total = 0.0;
+		let mut clip_pose = self.rest.clone();
+
+		for (clip, weight) in self.weights(&self.states[playback.state].motion) {
+			if weight <= 0.0 {
+				continue;
+			}
+
+			let clip = &self.clips[clip];
+			clip_pose.copy_from_slice(&self.rest);
+			clip.sample(playback.phase * clip.duration, &mut clip_pose);
+
+			// running average, the first clip is copied in as is
+			total += weight;
+			blend_poses(pose, &clip_pose, weight / total);
+		}
+	}
+
+	// flattens a motion into clip weights that sum to one
+	fn weights(&self, motion: &Motion) -> Vec<(usize, f32)> {
+		let mut out = Vec::new();
+		self.collect_weights(motion, 1.0, &mut out);
+		out
+	}
+
+	fn collect_weights(&self, motion: &Motion, weight: f32, out: &mut Vec<(usize, f32)>) {
+		match motion {
+			Motion::Clip(clip) => out.push((*clip, weight)),
+			Motion::Blend1d {
+				parameter,
+				children,
+			} => {
+				if children.is_empty() {
+					return;
+				}
+
+				let value = self.parameter(parameter);
+				let last = children.len() - 1;
+				if value <= children[0].0 {
+					return self.collect_weights(&children[0].1, weight, out);
+				}
+				if value >= children[last].0 {
+					return self.collect_weights(&children[last].1, weight, out);
+				}
+
+				let next = children.partition_point(|child| child.0 <= value);
+				let (low, high) = (&children[next - 1], &children[next]);
+				let t = (value - low.0) / (high.0 - low.0);
+				self.collect_weights(&low.1, weight * (1.0 - t), out);
+				self.collect_weights(&high.1, weight * t, out);
+			}
+		}
+	}
+}
+
+/// Moves `pose` towards `target` by `weight`.
+pub fn blend_poses(pose: &mut [Transform], target: &[Transform], weight: f32) {
+	for (local, target) in pose.iter_mut().zip(target) {
+		local.translation = lerp(local.translation, target.translation, weight);
+		local.rotation = quat_slerp(local.rotation, target.rotation, weight);
+		local.scale = lerp(local.scale, target.scale, weight);
+	}
+}
@@ -0,0 +1,261 @@
+// imports a skinned mesh, its skeleton and animation clips from a gltf file.
+
+use super::{AnimationClip, Channel, Interpolation, Joint, Keyframes, Skeleton, Transform};
+use crate::geometry::MeshData;
+use crate::math::{mat4_mul, Mat4, IDENTITY};
+
+use ::gltf::animation::util::ReadOutputs;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ImportError {
+	Gltf(::gltf::Error),
+	/// The file doesn't contain a skin.
+	NoSkin,
+	/// Data the file has that can't be right, eg. an index past the end.
+	Format(&'static str),
+}
+
+impl fmt::Display for ImportError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ImportError::Gltf(error) => write!(f, "failed to load gltf: {}", error),
+			ImportError::NoSkin => write!(f, "gltf file has no skin"),
+			ImportError::Format(what) => write!(f, "invalid gltf: {}", what),
+		}
+	}
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<::gltf::Error> for ImportError {
+	fn from(error: ::gltf::Error) -> Self {
+		ImportError::Gltf(error)
+	}
+}
+
+pub struct SkinnedModel {
+	/// Every primitive that uses the skin merged into one mesh.
+	pub mesh: MeshData,
+	pub skeleton: Skeleton,
+	pub clips: Vec<AnimationClip>,
+}
+
+/// Loads the first skin in the file along with the meshes bound to it.
+///
+/// Animation channels that target nodes outside the skeleton and morph target
+/// weights are ignored.
+pub fn import<P: AsRef<Path>>(path: P) -> Result<SkinnedModel, ImportError> {
+	let (document, buffers, _) = ::gltf::import(path)?;
+	let buffer_data = |buffer: ::gltf::Buffer| Some(&*buffers[buffer.index()]);
+
+	let skin = document.skins().next().ok_or(ImportError::NoSkin)?;
+
+	let mut parents = HashMap::new();
+	for node in document.nodes() {
+		for child in node.children() {
+			parents.insert(child.index(), node.index());
+		}
+	}
+
+	let skin_joints: Vec<_> = skin.joints().collect();
+	let skin_index: HashMap<usize, usize> = skin_joints
+		.iter()
+		.enumerate()
+		.map(|(i, node)| (node.index(), i))
+		.collect();
+
+	// gltf doesn't order joints, so sort them parents first and remember where each one went
+	let mut order: Vec<usize> = Vec::with_capacity(skin_joints.len());
+	let mut remap = vec![usize::MAX; skin_joints.len()];
+	while order.len() < skin_joints.len() {
+		for (i, node) in skin_joints.iter().enumerate() {
+			if remap[i] != usize::MAX {
+				continue;
+			}
+			let parent = parents.get(&node.index()).and_then(|p| skin_index.get(p));
+			if !matches!(parent, Some(&parent) if remap[parent] == usize::MAX) {
+				remap[i] = order.len();
+				order.push(i);
+			}
+		}
+	}
+
+	let inverse_binds: Vec<Mat4> = skin
+		.reader(buffer_data)
+		.read_inverse_bind_matrices()
+		.map(|matrices| matrices.collect())
+		.unwrap_or_else(|| vec![IDENTITY; skin_joints.len()]);
+
+	let mut skeleton = Skeleton::default();
+	for &i in &order {
+		let node = &skin_joints[i];
+		let (translation, rotation, scale) = node.transform().decomposed();
+		let parent = parents
+			.get(&node.index())
+			.and_then(|p| skin_index.get(p))
+			.map(|&parent| remap[parent]);
+
+		if parent.is_none() {
+			if let Some(&above) = parents.get(&node.index()) {
+				skeleton.root = world_matrix(&document, &parents, above);
+			}
+		}
+
+		skeleton.joints.push(Joint {
+			name: node.name().unwrap_or_default().to_owned(),
+			parent,
+			inverse_bind: inverse_binds[i],
+			rest: Transform {
+				translation,
+				rotation,
+				scale,
+			},
+		});
+	}
+
+	let mut mesh = MeshData::default();
+	for node in document.nodes() {
+		let uses_skin = node.skin().map(|s| s.index()) == Some(skin.index());
+		let node_mesh = match (uses_skin, node.mesh()) {
+			(true, Some(node_mesh)) => node_mesh,
+			_ => continue,
+		};
+
+		for primitive in node_mesh.primitives() {
+			if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+				continue;
+			}
+
+			let reader = primitive.reader(buffer_data);
+			let mut part = MeshData {
+				positions: match reader.read_positions() {
+					Some(positions) => positions.collect(),
+					None => continue,
+				},
+				..Default::default()
+			};
+			let count = part.positions.len();
+
+			part.normals = reader.read_normals().map_or_else(Vec::new, |n| n.collect());
+			part.tangents = reader
+				.read_tangents()
+				.map_or_else(Vec::new, |t| t.collect());
+			part.uvs = reader
+				.read_tex_coords(0)
+				.map_or_else(|| vec![[0.0; 2]; count], |uvs| uvs.into_f32().collect());
+			part.colors = reader
+				.read_colors(0)
+				.map_or_else(Vec::new, |colors| colors.into_rgba_f32().collect());
+			part.indices = reader
+				.read_indices()
+				.map_or_else(|| (0..count as u32).collect(), |i| i.into_u32().collect());
+
+			part.joints = match reader.read_joints(0) {
+				Some(joints) => joints
+					.into_u16()
+					.map(|j| {
+						let mut remapped = [0; 4];
+						for (out, joint) in remapped.iter_mut().zip(j) {
+							*out = *remap
+								.get(joint as usize)
+								.ok_or(ImportError::Format("joint index past the skin's joints"))?
+								as u16;
+						}
+						Ok::<_, ImportError>(remapped)
+					})
+					.collect::<Result<_, _>>()?,
+				None => vec![[0; 4]; count],
+			};
+			part.weights = reader.read_weights(0).map_or_else(
+				|| vec![[1.0, 0.0, 0.0, 0.0]; count],
+				|weights| weights.into_f32().map(normalize_weights).collect(),
+			);
+
+			part.generate_missing_attributes()
+				.map_err(ImportError::Format)?;
+			mesh.append(&part);
+		}
+	}
+
+	let clips = document
+		.animations()
+		.map(|animation| {
+			let mut clip = AnimationClip {
+				name: animation.name().unwrap_or_default().to_owned(),
+				..Default::default()
+			};
+
+			for channel in animation.channels() {
+				let joint = match skin_index.get(&channel.target().node().index()) {
+					Some(&i) => remap[i],
+					None => continue,
+				};
+
+				let reader = channel.reader(buffer_data);
+				let times: Vec<f32> = match reader.read_inputs() {
+					Some(inputs) => inputs.collect(),
+					None => continue,
+				};
+				let keyframes = match reader.read_outputs() {
+					Some(ReadOutputs::Translations(values)) => {
+						Keyframes::Translation(values.collect())
+					}
+					Some(ReadOutputs::Rotations(values)) => {
+						Keyframes::Rotation(values.into_f32().collect())
+					}
+					Some(ReadOutputs::Scales(values)) => Keyframes::Scale(values.collect()),
+					_ => continue,
+				};
+				if times.is_empty() {
+					continue;
+				}
+
+				clip.duration = clip.duration.max(*times.last().unwrap());
+				clip.channels.push(Channel {
+					joint,
+					interpolation: match channel.sampler().interpolation() {
+						::gltf::animation::Interpolation::Step => Interpolation::Step,
+						::gltf::animation::Interpolation::Linear => Interpolation::Linear,
+						::gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+					},
+					times,
+					keyframes,
+				});
+			}
+
+			clip
+		})
+		.collect();
+
+	Ok(SkinnedModel {
+		mesh,
+		skeleton,
+		clips,
+	})
+}
+
+// world matrix of a node from its ancestors
+pub(crate) fn world_matrix(
+	document: &::gltf::Document,
+	parents: &HashMap<usize, usize>,
+	node: usize,
+) -> Mat4 {
+	let local = document.nodes().nth(node).unwrap().transform().matrix();
+	match parents.get(&node) {
+		Some(&parent) => mat4_mul(world_matrix(document, parents, parent), local),
+		None => local,
+	}
+}
+
+// exporters don't always write weights that sum to one
+fn normalize_weights(weights: [f32; 4]) -> [f32; 4] {
+	let sum: f32 = weights.iter().sum();
+	if sum > 0.0 {
+		weights.map(|w| w / sum)
+	} else {
+		[1.0, 0.0, 0.0, 0.0]
+	}
+}
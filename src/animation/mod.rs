@@ -0,0 +1,264 @@
+pub mod controller;
+pub mod gltf;
+pub mod skinning;
+
+use crate::math::{
+	lerp, mat4_from_trs, mat4_mul, quat_normalize, quat_slerp, Mat4, Quat, Vec3, IDENTITY,
+};
+
+/// Local transform of a joint relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+	pub translation: Vec3,
+	pub rotation: Quat,
+	pub scale: Vec3,
+}
+
+impl Default for Transform {
+	fn default() -> Self {
+		Transform {
+			translation: [0.0; 3],
+			rotation: [0.0, 0.0, 0.0, 1.0],
+			scale: [1.0; 3],
+		}
+	}
+}
+
+impl Transform {
+	pub fn matrix(&self) -> Mat4 {
+		mat4_from_trs(self.translation, self.rotation, self.scale)
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+	pub name: String,
+	pub parent: Option<usize>,
+	/// Takes mesh space into the joint's bind space.
+	pub inverse_bind: Mat4,
+	/// Local transform used when no clip animates the joint.
+	pub rest: Transform,
+}
+
+/// Joint hierarchy of a skinned mesh.
+///
+/// Parents always come before their children so a pose can be resolved in a
+/// single pass.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+	pub joints: Vec<Joint>,
+	/// Transform of whatever sits above the root joints (an armature node for example).
+	pub root: Mat4,
+}
+
+impl Default for Skeleton {
+	fn default() -> Self {
+		Skeleton {
+			joints: Vec::new(),
+			root: IDENTITY,
+		}
+	}
+}
+
+impl Skeleton {
+	pub fn rest_pose(&self) -> Vec<Transform> {
+		self.joints.iter().map(|joint| joint.rest).collect()
+	}
+
+	/// Resolves a local pose into skinning matrices (joint world * inverse bind)
+	/// ready to be uploaded for the skinned vertex shader.
+	pub fn compute_palette(&self, pose: &[Transform], palette: &mut Vec<Mat4>) {
+		assert_eq!(pose.len(), self.joints.len());
+
+		// world transforms first, then fold the inverse bind in place
+		palette.clear();
+		for (joint, local) in self.joints.iter().zip(pose) {
+			let parent = joint.parent.map_or(self.root, |parent| palette[parent]);
+			palette.push(mat4_mul(parent, local.matrix()));
+		}
+
+		for (matrix, joint) in palette.iter_mut().zip(&self.joints) {
+			*matrix = mat4_mul(*matrix, joint.inverse_bind);
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+	Step,
+	Linear,
+	/// Hermite spline, values are stored as (in tangent, value, out tangent) per key.
+	CubicSpline,
+}
+
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+	Translation(Vec<Vec3>),
+	Rotation(Vec<Quat>),
+	Scale(Vec<Vec3>),
+}
+
+/// Keyframes animating one property of one joint.
+#[derive(Debug, Clone)]
+pub struct Channel {
+	pub joint: usize,
+	pub interpolation: Interpolation,
+	pub times: Vec<f32>,
+	pub keyframes: Keyframes,
+}
+
+/// Named marker on a clip's timeline, a footstep for example.
+#[derive(Debug, Clone)]
+pub struct AnimationEvent {
+	/// Seconds from the start of the clip.
+	pub time: f32,
+	pub name: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnimationClip {
+	pub name: String,
+	/// Length of the clip in seconds.
+	pub duration: f32,
+	pub channels: Vec<Channel>,
+	pub events: Vec<AnimationEvent>,
+}
+
+impl AnimationClip {
+	/// Overwrites the animated properties of `pose` with the clip sampled at `time`.
+	/// Joints the clip doesn't touch are left alone.
+	pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+		for channel in &self.channels {
+			let local = &mut pose[channel.joint];
+			match &channel.keyframes {
+				Keyframes::Translation(values) => {
+					local.translation = sample_vec3(channel, values, time);
+				}
+				Keyframes::Rotation(values) => {
+					local.rotation = sample_quat(channel, values, time);
+				}
+				Keyframes::Scale(values) => {
+					local.scale = sample_vec3(channel, values, time);
+				}
+			}
+		}
+	}
+}
+
+/// Playback state for a single clip.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+	pub time: f32,
+	/// Multiplier on the delta time, negative plays backwards.
+	pub speed: f32,
+	pub looping: bool,
+	pub playing: bool,
+}
+
+impl Default for AnimationPlayer {
+	fn default() -> Self {
+		AnimationPlayer {
+			time: 0.0,
+			speed: 1.0,
+			looping: true,
+			playing: true,
+		}
+	}
+}
+
+impl AnimationPlayer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Advances the playhead. Non looping playback stops at the end it's
+	/// moving towards, the start when `speed` is negative.
+	pub fn update(&mut self, clip: &AnimationClip, delta: f32) {
+		if !self.playing {
+			return;
+		}
+
+		self.time += delta * self.speed;
+
+		if clip.duration <= 0.0 {
+			self.time = 0.0;
+		} else if self.looping {
+			self.time = self.time.rem_euclid(clip.duration);
+		} else {
+			let finished = (self.speed > 0.0 && self.time >= clip.duration)
+				|| (self.speed < 0.0 && self.time <= 0.0);
+			self.time = self.time.clamp(0.0, clip.duration);
+			if finished {
+				self.playing = false;
+			}
+		}
+	}
+
+	pub fn sample(&self, clip: &AnimationClip, pose: &mut [Transform]) {
+		clip.sample(self.time, pose);
+	}
+}
+
+// finds the keyframes around `time` and how far between them it is
+fn keyframe(times: &[f32], time: f32) -> (usize, usize, f32) {
+	let last = times.len() - 1;
+	if time <= times[0] {
+		return (0, 0, 0.0);
+	}
+	if time >= times[last] {
+		return (last, last, 0.0);
+	}
+
+	let next = times.partition_point(|&t| t <= time);
+	let previous = next - 1;
+	let span = times[next] - times[previous];
+	(previous, next, (time - times[previous]) / span)
+}
+
+// hermite basis applied per component, `span` scales the tangents from per second to per key
+fn hermite(v0: f32, out0: f32, v1: f32, in1: f32, span: f32, t: f32) -> f32 {
+	let t2 = t * t;
+	let t3 = t2 * t;
+	(2.0 * t3 - 3.0 * t2 + 1.0) * v0
+		+ (t3 - 2.0 * t2 + t) * out0 * span
+		+ (-2.0 * t3 + 3.0 * t2) * v1
+		+ (t3 - t2) * in1 * span
+}
+
+fn sample_vec3(channel: &Channel, values: &[Vec3], time: f32) -> Vec3 {
+	let (previous, next, t) = keyframe(&channel.times, time);
+	match channel.interpolation {
+		Interpolation::Step => values[previous],
+		Interpolation::Linear => lerp(values[previous], values[next], t),
+		Interpolation::CubicSpline => {
+			if previous == next {
+				return values[previous * 3 + 1];
+			}
+			let span = channel.times[next] - channel.times[previous];
+			let v0 = values[previous * 3 + 1];
+			let out0 = values[previous * 3 + 2];
+			let v1 = values[next * 3 + 1];
+			let in1 = values[next * 3];
+			[0, 1, 2].map(|i| hermite(v0[i], out0[i], v1[i], in1[i], span, t))
+		}
+	}
+}
+
+fn sample_quat(channel: &Channel, values: &[Quat], time: f32) -> Quat {
+	let (previous, next, t) = keyframe(&channel.times, time);
+	match channel.interpolation {
+		Interpolation::Step => values[previous],
+		Interpolation::Linear => quat_slerp(values[previous], values[next], t),
+		Interpolation::CubicSpline => {
+			if previous == next {
+				return values[previous * 3 + 1];
+			}
+			let span = channel.times[next] - channel.times[previous];
+			let v0 = values[previous * 3 + 1];
+			let out0 = values[previous * 3 + 2];
+			let v1 = values[next * 3 + 1];
+			let in1 = values[next * 3];
+			quat_normalize([0, 1, 2, 3].map(|i| hermite(v0[i], out0[i], v1[i], in1[i], span, t)))
+		}
+	}
+}
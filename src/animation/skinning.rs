@@ -0,0 +1,123 @@
+use crate::geometry::MeshData;
+use crate::math::Mat4;
+
+use vulkano::buffer::{BufferUsage, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet, UnsafeDescriptorSetLayout};
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+
+use std::sync::Arc;
+
+/// Largest palette the skinned vertex shader accepts.
+pub const MAX_JOINTS: usize = 128;
+
+crate::vertex_layout!(
+	pub struct SkinnedVertex {
+		position,
+		normal,
+		tangent,
+		uv,
+		joints,
+		weights,
+	}
+);
+
+/// Interleaves a skinned mesh into vertices for the skinned vertex shader.
+pub fn vertices(mesh: &MeshData) -> Vec<SkinnedVertex> {
+	assert!(mesh.is_skinned(), "mesh has no joint influences");
+	mesh.interleave()
+}
+
+// vertex shader for skinned meshes with up to 4 influences per vertex.
+// outputs match the static mesh path so the same fragment shaders can be used.
+pub mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec3 normal;
+			layout(location = 2) in vec4 tangent;
+			layout(location = 3) in vec2 uv;
+			layout(location = 4) in uvec4 joints;
+			layout(location = 5) in vec4 weights;
+
+			layout(set = 0, binding = 0) uniform Palette {
+				mat4 joints[128];
+			} palette;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				mat4 model;
+			} push_constants;
+
+			layout(location = 0) out vec3 v_normal;
+			layout(location = 1) out vec4 v_tangent;
+			layout(location = 2) out vec2 v_uv;
+
+			void main() {
+				mat4 skin =
+					weights.x * palette.joints[joints.x] +
+					weights.y * palette.joints[joints.y] +
+					weights.z * palette.joints[joints.z] +
+					weights.w * palette.joints[joints.w];
+
+				mat4 model = push_constants.model * skin;
+				mat3 normal_matrix = mat3(model);
+
+				v_normal = normalize(normal_matrix * normal);
+				v_tangent = vec4(normalize(normal_matrix * tangent.xyz), tangent.w);
+				v_uv = uv;
+				gl_Position = push_constants.view_projection * model * vec4(position, 1.0);
+			}
+		"
+	}
+}
+
+/// Per frame upload of joint palettes.
+///
+/// Backed by a buffer pool so the previous frames' palettes can stay in use by
+/// the gpu while the next one is written.
+pub struct JointPalette {
+	pool: CpuBufferPool<vs::ty::Palette>,
+}
+
+impl JointPalette {
+	pub fn new(device: Arc<Device>) -> Self {
+		JointPalette {
+			pool: CpuBufferPool::new(device, BufferUsage::uniform_buffer()),
+		}
+	}
+
+	/// Uploads the palette and returns a descriptor set for set 0 of the skinned pipeline.
+	///
+	/// `layout` comes from `pipeline.descriptor_set_layout(0)`.
+	pub fn upload(
+		&self,
+		layout: &Arc<UnsafeDescriptorSetLayout>,
+		palette: &[Mat4],
+	) -> Arc<dyn DescriptorSet + Send + Sync> {
+		assert!(
+			palette.len() <= MAX_JOINTS,
+			"skeleton has {} joints, at most {} are supported",
+			palette.len(),
+			MAX_JOINTS
+		);
+
+		let mut data = vs::ty::Palette {
+			joints: [crate::math::IDENTITY; MAX_JOINTS],
+		};
+		data.joints[..palette.len()].copy_from_slice(palette);
+
+		let buffer = self.pool.next(data).unwrap();
+
+		Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_buffer(buffer)
+				.unwrap()
+				.build()
+				.unwrap(),
+		)
+	}
+}
@@ -0,0 +1,215 @@
+// command line flags every app built on the engine understands.
+//
+// --width <px> --height <px>   window size
+// --fullscreen                 borderless fullscreen
+// --gpu <name or index>        discrete, integrated, any or a device index
+// --no-vsync                   present without waiting for vertical blank
+// --validation                 enable the vulkan validation layer
+// --headless                   run without a window
+// --record <file>              record input and frame times to a file
+// --replay <file>              play back a recording instead of live input
+
+use crate::config::{EngineConfig, GpuPreference};
+use crate::replay::ReplayMode;
+
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgsError {
+	/// A flag that takes a value was last.
+	MissingValue(&'static str),
+	InvalidValue {
+		flag: &'static str,
+		value: String,
+	},
+}
+
+impl fmt::Display for ArgsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ArgsError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+			ArgsError::InvalidValue { flag, value } => {
+				write!(f, "invalid value for {}: {}", flag, value)
+			}
+		}
+	}
+}
+
+impl std::error::Error for ArgsError {}
+
+/// Engine options from the command line. Only the flags that were given are
+/// set, `apply` puts them over the config from `opal.toml`:
+///
+/// `Engine::new(&event_loop, |config| EngineArgs::from_env().apply(config))`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EngineArgs {
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	pub fullscreen: bool,
+	pub gpu: Option<GpuPreference>,
+	pub no_vsync: bool,
+	pub validation: bool,
+	pub headless: bool,
+	pub record: Option<PathBuf>,
+	pub replay: Option<PathBuf>,
+	/// Arguments that aren't engine flags, in order, for the app to handle.
+	pub rest: Vec<String>,
+}
+
+impl EngineArgs {
+	/// Parses the process arguments. Prints the problem and exits on an
+	/// invalid flag, like most command line tools.
+	pub fn from_env() -> EngineArgs {
+		EngineArgs::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+			eprintln!("{}", error);
+			std::process::exit(2);
+		})
+	}
+
+	/// Parses `args`, which shouldn't include the program name. Values can be
+	/// given as `--width 800` or `--width=800`.
+	pub fn parse<I>(args: I) -> Result<EngineArgs, ArgsError>
+	where
+		I: IntoIterator<Item = String>,
+	{
+		let mut parsed = EngineArgs::default();
+		let mut args = args.into_iter();
+
+		while let Some(arg) = args.next() {
+			let (name, inline) = match arg.split_once('=') {
+				Some((name, value)) if name.starts_with("--") => {
+					(name.to_string(), Some(value.to_string()))
+				}
+				_ => (arg.clone(), None),
+			};
+			let mut value = |flag: &'static str| {
+				inline
+					.clone()
+					.or_else(|| args.next())
+					.ok_or(ArgsError::MissingValue(flag))
+			};
+
+			match name.as_str() {
+				"--width" => parsed.width = Some(dimension("--width", value("--width")?)?),
+				"--height" => parsed.height = Some(dimension("--height", value("--height")?)?),
+				"--gpu" => {
+					let value = value("--gpu")?;
+					parsed.gpu = Some(GpuPreference::from_name(&value).ok_or(
+						ArgsError::InvalidValue {
+							flag: "--gpu",
+							value,
+						},
+					)?);
+				}
+				"--fullscreen" => parsed.fullscreen = true,
+				"--no-vsync" => parsed.no_vsync = true,
+				"--validation" => parsed.validation = true,
+				"--headless" => parsed.headless = true,
+				"--record" => parsed.record = Some(PathBuf::from(value("--record")?)),
+				"--replay" => parsed.replay = Some(PathBuf::from(value("--replay")?)),
+				_ => parsed.rest.push(arg),
+			}
+		}
+
+		Ok(parsed)
+	}
+
+	/// Overrides the parts of `config` that were given on the command line.
+	pub fn apply(&self, config: &mut EngineConfig) {
+		if let Some(width) = self.width {
+			config.width = width;
+		}
+		if let Some(height) = self.height {
+			config.height = height;
+		}
+		if let Some(gpu) = self.gpu {
+			config.gpu = gpu;
+		}
+		config.fullscreen |= self.fullscreen;
+		config.vsync &= !self.no_vsync;
+		config.validation |= self.validation;
+		config.headless |= self.headless;
+		if let Some(path) = &self.replay {
+			config.replay = ReplayMode::Play(path.clone());
+		} else if let Some(path) = &self.record {
+			config.replay = ReplayMode::Record(path.clone());
+		}
+	}
+}
+
+fn dimension(flag: &'static str, value: String) -> Result<u32, ArgsError> {
+	match value.parse() {
+		Ok(n) if n > 0 => Ok(n),
+		_ => Err(ArgsError::InvalidValue { flag, value }),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(args: &[&str]) -> Result<EngineArgs, ArgsError> {
+		EngineArgs::parse(args.iter().map(|arg| arg.to_string()))
+	}
+
+	#[test]
+	fn switches_and_separate_values() {
+		let args = parse(&[
+			"--fullscreen",
+			"--width",
+			"800",
+			"--gpu",
+			"integrated",
+			"--headless",
+		])
+		.unwrap();
+		assert!(args.fullscreen);
+		assert!(args.headless);
+		assert!(!args.no_vsync);
+		assert_eq!(args.width, Some(800));
+		assert_eq!(args.height, None);
+		assert_eq!(args.gpu, Some(GpuPreference::Integrated));
+	}
+
+	#[test]
+	fn inline_values() {
+		let args = parse(&["--height=600", "--gpu=1", "--record=run.replay"]).unwrap();
+		assert_eq!(args.height, Some(600));
+		assert_eq!(args.gpu, Some(GpuPreference::Index(1)));
+		assert_eq!(args.record, Some(PathBuf::from("run.replay")));
+	}
+
+	#[test]
+	fn unknown_arguments_are_left_for_the_app() {
+		let args = parse(&["level.map", "--god-mode", "--no-vsync", "-v"]).unwrap();
+		assert!(args.no_vsync);
+		assert_eq!(args.rest, ["level.map", "--god-mode", "-v"]);
+	}
+
+	#[test]
+	fn bad_numbers_are_rejected() {
+		for (args, value) in [
+			(&["--width", "wide"][..], "wide"),
+			(&["--width=0"][..], "0"),
+			(&["--height", "-600"][..], "-600"),
+		] {
+			match parse(args) {
+				Err(ArgsError::InvalidValue { value: invalid, .. }) => assert_eq!(invalid, value),
+				other => panic!("{:?} parsed as {:?}", args, other),
+			}
+		}
+		assert_eq!(
+			parse(&["--gpu", "fastest"]),
+			Err(ArgsError::InvalidValue {
+				flag: "--gpu",
+				value: "fastest".to_string(),
+			})
+		);
+	}
+
+	#[test]
+	fn trailing_flag_needs_its_value() {
+		assert_eq!(parse(&["--width"]), Err(ArgsError::MissingValue("--width")));
+	}
+}
@@ -0,0 +1,154 @@
+// basis universal textures, transcoded on load to whichever block format the
+// device can sample so one file works on desktop and mobile gpus.
+
+use super::{supports_format, AssetError, CompressedImage};
+
+use basis_universal::{
+	DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscodeParameters, Transcoder,
+	TranscoderBlockFormat, TranscoderTextureFormat,
+};
+use vulkano::device::Device;
+use vulkano::format::Format;
+
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+
+/// What basis textures are transcoded to, best quality first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscodeTarget {
+	Bc7,
+	Astc4x4,
+	Etc2,
+	/// Decoded to plain rgba8, for devices without any of the block formats.
+	Rgba8,
+}
+
+impl TranscodeTarget {
+	/// The best target `device` can sample.
+	pub fn select(device: &Device) -> Self {
+		[
+			TranscodeTarget::Bc7,
+			TranscodeTarget::Astc4x4,
+			TranscodeTarget::Etc2,
+		]
+		.iter()
+		.copied()
+		.find(|target| supports_format(device, target.format(false)))
+		.unwrap_or(TranscodeTarget::Rgba8)
+	}
+
+	pub fn format(self, srgb: bool) -> Format {
+		match (self, srgb) {
+			(TranscodeTarget::Bc7, false) => Format::BC7UnormBlock,
+			(TranscodeTarget::Bc7, true) => Format::BC7SrgbBlock,
+			(TranscodeTarget::Astc4x4, false) => Format::ASTC_4x4UnormBlock,
+			(TranscodeTarget::Astc4x4, true) => Format::ASTC_4x4SrgbBlock,
+			(TranscodeTarget::Etc2, false) => Format::ETC2_R8G8B8A8UnormBlock,
+			(TranscodeTarget::Etc2, true) => Format::ETC2_R8G8B8A8SrgbBlock,
+			(TranscodeTarget::Rgba8, false) => Format::R8G8B8A8Unorm,
+			(TranscodeTarget::Rgba8, true) => Format::R8G8B8A8Srgb,
+		}
+	}
+
+	fn texture_format(self) -> TranscoderTextureFormat {
+		match self {
+			TranscodeTarget::Bc7 => TranscoderTextureFormat::BC7_RGBA,
+			TranscodeTarget::Astc4x4 => TranscoderTextureFormat::ASTC_4x4_RGBA,
+			TranscodeTarget::Etc2 => TranscoderTextureFormat::ETC2_RGBA,
+			TranscodeTarget::Rgba8 => TranscoderTextureFormat::RGBA32,
+		}
+	}
+
+	fn block_format(self) -> TranscoderBlockFormat {
+		match self {
+			TranscodeTarget::Bc7 => TranscoderBlockFormat::BC7,
+			TranscodeTarget::Astc4x4 => TranscoderBlockFormat::ASTC_4x4,
+			TranscodeTarget::Etc2 => TranscoderBlockFormat::ETC2_RGBA,
+			TranscodeTarget::Rgba8 => TranscoderBlockFormat::RGBA32,
+		}
+	}
+}
+
+/// Transcodes the first image of a `.basis` file with all its levels. The
+/// file doesn't say whether color is srgb, so `srgb` decides.
+pub(super) fn transcode_basis(
+	bytes: &[u8],
+	srgb: bool,
+	target: TranscodeTarget,
+) -> Result<CompressedImage, AssetError> {
+	INIT.call_once(basis_universal::transcoder_init);
+
+	let mut transcoder = Transcoder::new();
+	if !transcoder.validate_header(bytes) || transcoder.image_count(bytes) == 0 {
+		return Err(AssetError::Format("not a basis file"));
+	}
+	let description = transcoder
+		.image_level_description(bytes, 0, 0)
+		.ok_or(AssetError::Format("basis file has no levels"))?;
+	transcoder
+		.prepare_transcoding(bytes)
+		.map_err(|_| AssetError::Format("basis file is corrupt"))?;
+
+	let mut levels = Vec::new();
+	for level in 0..transcoder.image_level_count(bytes, 0) {
+		let data = transcoder
+			.transcode_image_level(
+				bytes,
+				target.texture_format(),
+				TranscodeParameters {
+					image_index: 0,
+					level_index: level,
+					..Default::default()
+				},
+			)
+			.map_err(|_| AssetError::Format("basis transcoding failed"))?;
+		levels.push(data);
+	}
+	transcoder.end_transcoding();
+
+	Ok(CompressedImage {
+		format: target.format(srgb),
+		dimensions: [description.original_width, description.original_height],
+		levels,
+	})
+}
+
+/// Transcodes the uastc levels of a ktx2 file, largest first.
+pub(super) fn transcode_uastc(
+	levels: &[Vec<u8>],
+	dimensions: [u32; 2],
+	has_alpha: bool,
+	srgb: bool,
+	target: TranscodeTarget,
+) -> Result<CompressedImage, AssetError> {
+	INIT.call_once(basis_universal::transcoder_init);
+
+	let transcoder = LowLevelUastcTranscoder::new();
+	let mut transcoded = Vec::with_capacity(levels.len());
+	for (level, data) in levels.iter().enumerate() {
+		let width = (dimensions[0] >> level).max(1);
+		let height = (dimensions[1] >> level).max(1);
+		let data = transcoder
+			.transcode_slice(
+				data,
+				SliceParametersUastc {
+					num_blocks_x: width.div_ceil(4),
+					num_blocks_y: height.div_ceil(4),
+					has_alpha,
+					original_width: width,
+					original_height: height,
+				},
+				DecodeFlags::empty(),
+				target.block_format(),
+			)
+			.map_err(|_| AssetError::Format("uastc transcoding failed"))?;
+		transcoded.push(data);
+	}
+
+	Ok(CompressedImage {
+		format: target.format(srgb),
+		dimensions,
+		levels: transcoded,
+	})
+}
@@ -0,0 +1,370 @@
+// dds, ktx2 and basis containers. Block compressed textures stay compressed on
+// the gpu, uploaded as is or transcoded from basis.
+
+use super::basis::{transcode_basis, transcode_uastc, TranscodeTarget};
+use super::AssetError;
+
+use crate::render2d::Texture;
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+	AutoCommandBuffer, AutoCommandBufferBuilder, CommandBuffer, CommandBufferExecFuture,
+};
+use vulkano::device::{Device, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{
+	ImageCreateFlags, ImageDimensions, ImageLayout, ImageUsage, ImmutableImage, MipmapsCount,
+};
+use vulkano::sync::{self, NowFuture};
+
+use std::sync::Arc;
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX2_MAGIC: &[u8] = &[
+	0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+
+/// Texture data in a gpu format with its mip levels, largest first.
+#[derive(Debug, Clone)]
+pub struct CompressedImage {
+	pub format: Format,
+	pub dimensions: [u32; 2],
+	pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+	/// Parses a dds file. Bc1 to bc7 and 8 bit rgba, 2d only. Legacy headers
+	/// don't say whether color is srgb, so `srgb` decides for those.
+	pub fn from_dds(bytes: &[u8], srgb: bool) -> Result<CompressedImage, AssetError> {
+		if !bytes.starts_with(DDS_MAGIC) || bytes.len() < 128 {
+			return Err(AssetError::Format("not a dds file"));
+		}
+		let height = read_u32(bytes, 12)?;
+		let width = read_u32(bytes, 16)?;
+		let mip_count = read_u32(bytes, 28)?.max(1);
+		let pixel_flags = read_u32(bytes, 80)?;
+		let four_cc = &bytes[84..88];
+		let caps2 = read_u32(bytes, 112)?;
+		// cube maps and volumes
+		if caps2 & (0x200 | 0x20_0000) != 0 {
+			return Err(AssetError::Format("only 2d dds textures are supported"));
+		}
+
+		const FOUR_CC: u32 = 0x4;
+		let (format, data_start) = if pixel_flags & FOUR_CC != 0 && four_cc == b"DX10" {
+			let format = dxgi_format(read_u32(bytes, 128)?)
+				.ok_or(AssetError::Format("unsupported dds format"))?;
+			if read_u32(bytes, 132)? != 3 || read_u32(bytes, 140)? > 1 {
+				return Err(AssetError::Format("only 2d dds textures are supported"));
+			}
+			(format, 148)
+		} else if pixel_flags & FOUR_CC != 0 {
+			let format = match four_cc {
+				b"DXT1" if srgb => Format::BC1_RGBASrgbBlock,
+				b"DXT1" => Format::BC1_RGBAUnormBlock,
+				b"DXT3" if srgb => Format::BC2SrgbBlock,
+				b"DXT3" => Format::BC2UnormBlock,
+				b"DXT5" if srgb => Format::BC3SrgbBlock,
+				b"DXT5" => Format::BC3UnormBlock,
+				b"ATI1" | b"BC4U" => Format::BC4UnormBlock,
+				b"ATI2" | b"BC5U" => Format::BC5UnormBlock,
+				_ => return Err(AssetError::Format("unsupported dds format")),
+			};
+			(format, 128)
+		} else {
+			// uncompressed, only 32 bit rgba is common enough to bother
+			const RGB: u32 = 0x40;
+			let red_mask = read_u32(bytes, 92)?;
+			if pixel_flags & RGB == 0 || read_u32(bytes, 88)? != 32 {
+				return Err(AssetError::Format("unsupported dds format"));
+			}
+			let format = match (red_mask, srgb) {
+				(0xff, true) => Format::R8G8B8A8Srgb,
+				(0xff, false) => Format::R8G8B8A8Unorm,
+				(0xff_0000, true) => Format::B8G8R8A8Srgb,
+				(0xff_0000, false) => Format::B8G8R8A8Unorm,
+				_ => return Err(AssetError::Format("unsupported dds format")),
+			};
+			(format, 128)
+		};
+
+		let mut levels = Vec::with_capacity(mip_count as usize);
+		let mut offset = data_start;
+		for level in 0..mip_count {
+			let size = level_size(format, [width, height], level);
+			let data = bytes
+				.get(offset..offset + size)
+				.ok_or(AssetError::Format("truncated dds file"))?;
+			levels.push(data.to_vec());
+			offset += size;
+		}
+
+		Ok(CompressedImage {
+			format,
+			dimensions: [width, height],
+			levels,
+		})
+	}
+
+	/// Parses a ktx2 file. Bc1 to bc7 and 8 bit rgba are uploaded as they
+	/// are, uastc is transcoded to the best format `device` supports. 2d only,
+	/// levels may be zstd supercompressed.
+	pub fn from_ktx2(bytes: &[u8], device: &Device) -> Result<CompressedImage, AssetError> {
+		if !bytes.starts_with(KTX2_MAGIC) || bytes.len() < 80 {
+			return Err(AssetError::Format("not a ktx2 file"));
+		}
+		let vk_format = read_u32(bytes, 12)?;
+		let width = read_u32(bytes, 20)?;
+		let height = read_u32(bytes, 24)?;
+		let depth = read_u32(bytes, 28)?;
+		let layers = read_u32(bytes, 32)?;
+		let faces = read_u32(bytes, 36)?;
+		// 0 asks the loader to generate mips, the base level is all there is
+		let level_count = read_u32(bytes, 40)?.max(1);
+		let supercompression = read_u32(bytes, 44)?;
+
+		const BASIS_LZ: u32 = 1;
+		const ZSTD: u32 = 2;
+		if supercompression == BASIS_LZ {
+			return Err(AssetError::Format(
+				"basis-lz ktx2 isn't supported, encode etc1s textures as .basis",
+			));
+		}
+		if supercompression > ZSTD {
+			return Err(AssetError::Format("unsupported ktx2 supercompression"));
+		}
+		if depth > 1 || layers > 1 || faces > 1 {
+			return Err(AssetError::Format("only 2d ktx2 textures are supported"));
+		}
+
+		let mut levels = Vec::with_capacity(level_count as usize);
+		for level in 0..level_count as usize {
+			let entry = 80 + level * 24;
+			let offset = read_u64(bytes, entry)? as usize;
+			let length = read_u64(bytes, entry + 8)? as usize;
+			let uncompressed = read_u64(bytes, entry + 16)? as usize;
+			let data = bytes
+				.get(offset..offset + length)
+				.ok_or(AssetError::Format("truncated ktx2 file"))?;
+			if supercompression == ZSTD {
+				levels.push(zstd::bulk::decompress(data, uncompressed)?);
+			} else {
+				levels.push(data.to_vec());
+			}
+		}
+
+		if vk_format != 0 {
+			let format = vk_format_from_num(vk_format)
+				.ok_or(AssetError::Format("unsupported ktx2 format"))?;
+			return Ok(CompressedImage {
+				format,
+				dimensions: [width, height],
+				levels,
+			});
+		}
+
+		// no format means basis, the data format descriptor says which kind
+		const UASTC: u8 = 166;
+		const SRGB: u8 = 2;
+		const RGBA: u8 = 3;
+		let dfd = read_u32(bytes, 48)? as usize;
+		let block = bytes
+			.get(dfd + 4..dfd + 32)
+			.ok_or(AssetError::Format("truncated ktx2 file"))?;
+		if block[8] != UASTC {
+			return Err(AssetError::Format("unsupported ktx2 color model"));
+		}
+		let srgb = block[10] == SRGB;
+		let has_alpha = block[27] & 0xf == RGBA;
+		transcode_uastc(
+			&levels,
+			[width, height],
+			has_alpha,
+			srgb,
+			TranscodeTarget::select(device),
+		)
+	}
+
+	/// Transcodes a `.basis` file to the best format `device` supports. The
+	/// file doesn't say whether color is srgb, so `srgb` decides.
+	pub fn from_basis(
+		bytes: &[u8],
+		srgb: bool,
+		device: &Device,
+	) -> Result<CompressedImage, AssetError> {
+		transcode_basis(bytes, srgb, TranscodeTarget::select(device))
+	}
+
+	/// Uploads every level as is. The future has to be joined with the frame
+	/// that first samples the texture.
+	pub fn upload(
+		&self,
+		queue: Arc<Queue>,
+	) -> Result<
+		(
+			Texture,
+			CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+		),
+		AssetError,
+	> {
+		let device = queue.device().clone();
+		if !supports_format(&device, self.format) {
+			return Err(AssetError::Format(
+				"texture format isn't supported by the device",
+			));
+		}
+
+		let (image, init) = ImmutableImage::uninitialized(
+			device.clone(),
+			ImageDimensions::Dim2d {
+				width: self.dimensions[0],
+				height: self.dimensions[1],
+				array_layers: 1,
+			},
+			self.format,
+			MipmapsCount::Specific(self.levels.len() as u32),
+			ImageUsage {
+				transfer_destination: true,
+				sampled: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			ImageLayout::ShaderReadOnlyOptimal,
+			Some(queue.family()),
+		)
+		.unwrap();
+		let init = Arc::new(init);
+
+		let mut builder =
+			AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family())
+				.unwrap();
+		for (level, data) in self.levels.iter().enumerate() {
+			let [width, height] = level_dimensions(self.dimensions, level as u32);
+			let buffer = CpuAccessibleBuffer::from_iter(
+				device.clone(),
+				BufferUsage::transfer_source(),
+				false,
+				data.iter().cloned(),
+			)
+			.unwrap();
+			builder
+				.copy_buffer_to_image_dimensions(
+					buffer,
+					init.clone(),
+					[0, 0, 0],
+					[width, height, 1],
+					0,
+					1,
+					level as u32,
+				)
+				.unwrap();
+		}
+		let future = builder
+			.build()
+			.unwrap()
+			.execute_after(sync::now(device), queue)
+			.unwrap();
+
+		Ok((ImageView::new(image).unwrap(), future))
+	}
+}
+
+/// Whether the device can sample images of `format`, block compressed
+/// formats also need their `texture_compression_*` feature enabled.
+pub fn supports_format(device: &Device, format: Format) -> bool {
+	let features = format
+		.properties(device.physical_device())
+		.optimal_tiling_features;
+	let enabled = device.enabled_features();
+	let compression = match format {
+		_ if format.block_dimensions() == (1, 1) => true,
+		Format::ASTC_4x4UnormBlock | Format::ASTC_4x4SrgbBlock => {
+			enabled.texture_compression_astc_ldr
+		}
+		Format::ETC2_R8G8B8A8UnormBlock | Format::ETC2_R8G8B8A8SrgbBlock => {
+			enabled.texture_compression_etc2
+		}
+		_ => enabled.texture_compression_bc,
+	};
+	features.sampled_image && features.transfer_dst && compression
+}
+
+fn level_dimensions(dimensions: [u32; 2], level: u32) -> [u32; 2] {
+	[
+		(dimensions[0] >> level).max(1),
+		(dimensions[1] >> level).max(1),
+	]
+}
+
+// bytes in one mip level
+fn level_size(format: Format, dimensions: [u32; 2], level: u32) -> usize {
+	let [width, height] = level_dimensions(dimensions, level);
+	let (block_width, block_height) = format.block_dimensions();
+	let blocks = width.div_ceil(block_width) * height.div_ceil(block_height);
+	blocks as usize * format.size().unwrap()
+}
+
+fn dxgi_format(format: u32) -> Option<Format> {
+	Some(match format {
+		10 => Format::R16G16B16A16Sfloat,
+		28 => Format::R8G8B8A8Unorm,
+		29 => Format::R8G8B8A8Srgb,
+		71 => Format::BC1_RGBAUnormBlock,
+		72 => Format::BC1_RGBASrgbBlock,
+		74 => Format::BC2UnormBlock,
+		75 => Format::BC2SrgbBlock,
+		77 => Format::BC3UnormBlock,
+		78 => Format::BC3SrgbBlock,
+		80 => Format::BC4UnormBlock,
+		81 => Format::BC4SnormBlock,
+		83 => Format::BC5UnormBlock,
+		84 => Format::BC5SnormBlock,
+		87 => Format::B8G8R8A8Unorm,
+		91 => Format::B8G8R8A8Srgb,
+		95 => Format::BC6HUfloatBlock,
+		96 => Format::BC6HSfloatBlock,
+		98 => Format::BC7UnormBlock,
+		99 => Format::BC7SrgbBlock,
+		_ => return None,
+	})
+}
+
+// `VkFormat` values, vulkano keeps its conversion private
+fn vk_format_from_num(format: u32) -> Option<Format> {
+	Some(match format {
+		37 => Format::R8G8B8A8Unorm,
+		43 => Format::R8G8B8A8Srgb,
+		44 => Format::B8G8R8A8Unorm,
+		50 => Format::B8G8R8A8Srgb,
+		97 => Format::R16G16B16A16Sfloat,
+		131 => Format::BC1_RGBUnormBlock,
+		132 => Format::BC1_RGBSrgbBlock,
+		133 => Format::BC1_RGBAUnormBlock,
+		134 => Format::BC1_RGBASrgbBlock,
+		135 => Format::BC2UnormBlock,
+		136 => Format::BC2SrgbBlock,
+		137 => Format::BC3UnormBlock,
+		138 => Format::BC3SrgbBlock,
+		139 => Format::BC4UnormBlock,
+		140 => Format::BC4SnormBlock,
+		141 => Format::BC5UnormBlock,
+		142 => Format::BC5SnormBlock,
+		143 => Format::BC6HUfloatBlock,
+		144 => Format::BC6HSfloatBlock,
+		145 => Format::BC7UnormBlock,
+		146 => Format::BC7SrgbBlock,
+		_ => return None,
+	})
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, AssetError> {
+	bytes
+		.get(offset..offset + 4)
+		.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+		.ok_or(AssetError::Format("truncated texture header"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, AssetError> {
+	Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
@@ -0,0 +1,439 @@
+// high dynamic range images from radiance .hdr and openexr files, kept as
+// floats for environment maps, lightmaps and luts.
+
+use super::AssetError;
+
+use crate::compute::group_counts;
+use crate::render2d::Texture;
+
+use vulkano::command_buffer::{
+	AutoCommandBuffer, AutoCommandBufferBuilder, CommandBufferExecFuture,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::format::Format;
+use vulkano::half::f16;
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::image::{
+	ImageCreateFlags, ImageDimensions, ImageUsage, ImmutableImage, MipmapsCount, StorageImage,
+};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::NowFuture;
+
+use std::path::Path;
+use std::sync::Arc;
+
+/// Format hdr images are uploaded and cubemaps converted to.
+pub const HDR_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+/// Linear rgba float pixels, top row first.
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+	pub dimensions: [u32; 2],
+	pub pixels: Vec<f32>,
+}
+
+impl HdrImage {
+	/// Parses a radiance rgbe file, flat or run length encoded, stored in the
+	/// usual `-Y height +X width` orientation.
+	pub fn from_radiance(bytes: &[u8]) -> Result<HdrImage, AssetError> {
+		if !bytes.starts_with(b"#?") {
+			return Err(AssetError::Format("not a radiance hdr file"));
+		}
+
+		// text header ending in an empty line, then the resolution line
+		let mut position = 0;
+		let mut line = || -> Result<&[u8], AssetError> {
+			let start = position;
+			let length = bytes[start..]
+				.iter()
+				.position(|&b| b == b'\n')
+				.ok_or(AssetError::Format("truncated hdr header"))?;
+			position = start + length + 1;
+			Ok(&bytes[start..start + length])
+		};
+		loop {
+			let header = line()?;
+			if header.is_empty() {
+				break;
+			}
+			if header.starts_with(b"FORMAT=") && header != b"FORMAT=32-bit_rle_rgbe" {
+				return Err(AssetError::Format("only rgbe hdr files are supported"));
+			}
+		}
+		let resolution = std::str::from_utf8(line()?)
+			.map_err(|_| AssetError::Format("invalid hdr resolution"))?;
+		let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+			["-Y", height, "+X", width] => (height.parse::<u32>(), width.parse::<u32>()),
+			_ => return Err(AssetError::Format("unsupported hdr orientation")),
+		};
+		let (height, width) = match (height, width) {
+			(Ok(height), Ok(width)) => (height, width),
+			_ => return Err(AssetError::Format("invalid hdr resolution")),
+		};
+
+		// the resolution is untrusted, don't let it wrap into a small buffer
+		let floats = (width as usize)
+			.checked_mul(height as usize)
+			.and_then(|texels| texels.checked_mul(4))
+			.ok_or(AssetError::Format("hdr resolution too large"))?;
+
+		let mut data = &bytes[position..];
+		let mut rgbe = vec![0u8; width as usize * 4];
+		let mut pixels = Vec::with_capacity(floats);
+		for _ in 0..height {
+			read_scanline(&mut data, &mut rgbe)?;
+			for texel in rgbe.chunks_exact(4) {
+				let scale = if texel[3] == 0 {
+					0.0
+				} else {
+					2f32.powi(texel[3] as i32 - 136)
+				};
+				pixels.extend_from_slice(&[
+					texel[0] as f32 * scale,
+					texel[1] as f32 * scale,
+					texel[2] as f32 * scale,
+					1.0,
+				]);
+			}
+		}
+
+		Ok(HdrImage {
+			dimensions: [width, height],
+			pixels,
+		})
+	}
+
+	/// Loads the first rgba layer of an exr file.
+	pub fn from_exr<P: AsRef<Path>>(path: P) -> Result<HdrImage, AssetError> {
+		use exr::prelude::*;
+
+		let image = read_first_rgba_layer_from_file(
+			path,
+			|size, _| (size.width(), vec![0.0; size.width() * size.height() * 4]),
+			|(width, pixels), position, (r, g, b, a): (f32, f32, f32, f32)| {
+				let index = (position.y() * *width + position.x()) * 4;
+				pixels[index..index + 4].copy_from_slice(&[r, g, b, a]);
+			},
+		)?;
+
+		let size = image.layer_data.size;
+		let (_, pixels) = image.layer_data.channel_data.pixels;
+		Ok(HdrImage {
+			dimensions: [size.width() as u32, size.height() as u32],
+			pixels,
+		})
+	}
+
+	/// Uploads the pixels as a half float texture without mipmaps.
+	pub fn upload(
+		&self,
+		queue: Arc<Queue>,
+	) -> (
+		Texture,
+		CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+	) {
+		let (image, future) = ImmutableImage::from_iter(
+			self.pixels.iter().map(|&value| f16::from_f32(value)),
+			ImageDimensions::Dim2d {
+				width: self.dimensions[0],
+				height: self.dimensions[1],
+				array_layers: 1,
+			},
+			MipmapsCount::One,
+			HDR_FORMAT,
+			queue,
+		)
+		.unwrap();
+		(ImageView::new(image).unwrap(), future)
+	}
+}
+
+// decodes one scanline of rgbe texels into `out`
+fn read_scanline(data: &mut &[u8], out: &mut [u8]) -> Result<(), AssetError> {
+	let width = out.len() / 4;
+
+	// run length encoded scanlines start with 2, 2 and the width
+	let rle = (8..0x8000).contains(&width)
+		&& data.len() >= 4
+		&& data[0] == 2
+		&& data[1] == 2
+		&& ((data[2] as usize) << 8 | data[3] as usize) == width;
+	if !rle {
+		let flat = data
+			.get(..out.len())
+			.ok_or(AssetError::Format("truncated hdr file"))?;
+		out.copy_from_slice(flat);
+		*data = &data[out.len()..];
+		return Ok(());
+	}
+	*data = &data[4..];
+
+	// each channel is encoded separately as runs and literal spans
+	for channel in 0..4 {
+		let mut x = 0;
+		while x < width {
+			let (&count, rest) = data
+				.split_first()
+				.ok_or(AssetError::Format("truncated hdr file"))?;
+			if count > 128 {
+				let count = count as usize - 128;
+				let value = *rest
+					.first()
+					.ok_or(AssetError::Format("truncated hdr file"))?;
+				if x + count > width {
+					return Err(AssetError::Format("invalid hdr run"));
+				}
+				for texel in x..x + count {
+					out[texel * 4 + channel] = value;
+				}
+				x += count;
+				*data = &rest[1..];
+			} else {
+				let count = count as usize;
+				if count == 0 || x + count > width {
+					return Err(AssetError::Format("invalid hdr run"));
+				}
+				let values = rest
+					.get(..count)
+					.ok_or(AssetError::Format("truncated hdr file"))?;
+				for (i, &value) in values.iter().enumerate() {
+					out[(x + i) * 4 + channel] = value;
+				}
+				x += count;
+				*data = &rest[count..];
+			}
+		}
+	}
+	Ok(())
+}
+
+mod equirect_to_cube {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+			layout(set = 0, binding = 0) uniform sampler2D equirect;
+			layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray faces;
+
+			layout(push_constant) uniform PushConstants {
+				uint size;
+			} pc;
+
+			// direction through the texel, same face layout as vulkan cube sampling
+			vec3 cube_direction(uint face, vec2 uv) {
+				switch (face) {
+					case 0u: return vec3(1.0, -uv.y, -uv.x);
+					case 1u: return vec3(-1.0, -uv.y, uv.x);
+					case 2u: return vec3(uv.x, 1.0, uv.y);
+					case 3u: return vec3(uv.x, -1.0, -uv.y);
+					case 4u: return vec3(uv.x, -uv.y, 1.0);
+					default: return vec3(-uv.x, -uv.y, -1.0);
+				}
+			}
+
+			void main() {
+				uvec3 id = gl_GlobalInvocationID;
+				if (id.x >= pc.size || id.y >= pc.size) {
+					return;
+				}
+
+				vec2 uv = (vec2(id.xy) + 0.5) / float(pc.size) * 2.0 - 1.0;
+				vec3 d = normalize(cube_direction(id.z, uv));
+
+				// longitude around +y, latitude from the top row down
+				vec2 equirect_uv = vec2(
+					atan(d.z, d.x) / 6.2831853 + 0.5,
+					acos(clamp(d.y, -1.0, 1.0)) / 3.1415927
+				);
+				imageStore(faces, ivec3(id), vec4(textureLod(equirect, equirect_uv, 0.0).rgb, 1.0));
+			}
+		"
+	}
+}
+
+/// Converts equirectangular (latitude-longitude) environment maps into
+/// cubemaps with a compute pass, +y is up.
+pub struct EquirectToCube {
+	device: Arc<Device>,
+	pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	sampler: Arc<Sampler>,
+}
+
+impl EquirectToCube {
+	pub fn new(device: Arc<Device>) -> Self {
+		let shader = equirect_to_cube::Shader::load(device.clone()).unwrap();
+		let pipeline = Arc::new(
+			ComputePipeline::new(device.clone(), &shader.main_entry_point(), &(), None).unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		// wraps around horizontally, the poles shouldn't bleed into each other
+		let sampler = Sampler::new(
+			device.clone(),
+			Filter::Linear,
+			Filter::Linear,
+			MipmapMode::Nearest,
+			SamplerAddressMode::Repeat,
+			SamplerAddressMode::ClampToEdge,
+			SamplerAddressMode::ClampToEdge,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		)
+		.unwrap();
+
+		EquirectToCube {
+			device,
+			pipeline,
+			sampler,
+		}
+	}
+
+	/// Records the conversion of `equirect` into a new cubemap with `size`
+	/// pixel faces and returns it as a `samplerCube`. Must be recorded
+	/// outside of a render pass.
+	pub fn convert(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		equirect: Texture,
+		size: u32,
+	) -> Texture {
+		let image = StorageImage::with_usage(
+			self.device.clone(),
+			ImageDimensions::Dim2d {
+				width: size,
+				height: size,
+				array_layers: 6,
+			},
+			HDR_FORMAT,
+			ImageUsage {
+				storage: true,
+				sampled: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags {
+				cube_compatible: true,
+				..ImageCreateFlags::none()
+			},
+			self.device.active_queue_families(),
+		)
+		.unwrap();
+
+		let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(equirect, self.sampler.clone())
+				.unwrap()
+				.add_image(ImageView::with_type(image.clone(), ImageViewType::Dim2dArray).unwrap())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		builder
+			.dispatch(
+				group_counts([size, size, 6], [8, 8, 1]),
+				self.pipeline.clone(),
+				set,
+				equirect_to_cube::ty::PushConstants { size },
+				vec![],
+			)
+			.unwrap();
+
+		ImageView::with_type(image, ImageViewType::Cubemap).unwrap()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn file(resolution: &str, data: &[u8]) -> Vec<u8> {
+		let mut bytes =
+			format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+		bytes.extend_from_slice(data);
+		bytes
+	}
+
+	fn format_error(bytes: &[u8]) -> &'static str {
+		match HdrImage::from_radiance(bytes) {
+			Err(AssetError::Format(what)) => what,
+			other => panic!("expected a format error, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn flat_scanlines() {
+		// 128 * 2^(129 - 136) = 1, a zero exponent is black whatever the mantissa
+		let data = [
+			128, 64, 0, 129, 255, 255, 255, 0, 128, 128, 128, 136, 0, 32, 0, 130,
+		];
+		let image = HdrImage::from_radiance(&file("-Y 2 +X 2", &data)).unwrap();
+		assert_eq!(image.dimensions, [2, 2]);
+		assert_eq!(
+			image.pixels,
+			[
+				1.0, 0.5, 0.0, 1.0, //
+				0.0, 0.0, 0.0, 1.0, //
+				128.0, 128.0, 128.0, 1.0, //
+				0.0, 0.5, 0.0, 1.0,
+			]
+		);
+	}
+
+	#[test]
+	fn run_length_encoded_scanline() {
+		let mut data = vec![2, 2, 0, 8];
+		// red as a run of 8
+		data.extend_from_slice(&[128 + 8, 64]);
+		// green as literals
+		data.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+		// blue as a run then literals
+		data.extend_from_slice(&[128 + 6, 0, 2, 128, 255]);
+		// exponent as a single run
+		data.extend_from_slice(&[128 + 8, 136]);
+
+		let image = HdrImage::from_radiance(&file("-Y 1 +X 8", &data)).unwrap();
+		assert_eq!(image.dimensions, [8, 1]);
+		let texels: Vec<_> = image.pixels.chunks_exact(4).collect();
+		assert_eq!(texels[0], [64.0, 0.0, 0.0, 1.0]);
+		assert_eq!(texels[3], [64.0, 48.0, 0.0, 1.0]);
+		assert_eq!(texels[6], [64.0, 96.0, 128.0, 1.0]);
+		assert_eq!(texels[7], [64.0, 112.0, 255.0, 1.0]);
+	}
+
+	#[test]
+	fn broken_files() {
+		assert_eq!(format_error(b"P6\n"), "not a radiance hdr file");
+		assert_eq!(
+			format_error(&file("+X 2 -Y 2", &[])),
+			"unsupported hdr orientation"
+		);
+		assert_eq!(
+			format_error(&file("-Y 2 +X two", &[])),
+			"invalid hdr resolution"
+		);
+		assert_eq!(
+			format_error(&file("-Y 2 +X 2", &[0; 12])),
+			"truncated hdr file"
+		);
+		// a run past the end of the scanline
+		assert_eq!(
+			format_error(&file("-Y 1 +X 8", &[2, 2, 0, 8, 128 + 9, 0])),
+			"invalid hdr run"
+		);
+	}
+
+	#[test]
+	fn huge_resolutions_are_rejected() {
+		assert_eq!(
+			format_error(&file("-Y 4294967295 +X 4294967295", &[])),
+			"hdr resolution too large"
+		);
+	}
+}
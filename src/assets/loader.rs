@@ -0,0 +1,154 @@
+// loader threads reading, decoding and uploading assets off the main thread.
+
+use super::{AssetError, AsyncAsset};
+
+use vulkano::device::{Device, Queue};
+use vulkano::sync::GpuFuture;
+
+use std::any::{Any, TypeId};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Upload = Box<dyn GpuFuture + Send>;
+type Job = Box<dyn FnOnce() -> Loaded + Send>;
+
+/// What an `AsyncAsset` gets to load itself with, on whichever thread.
+pub struct AsyncContext {
+	path: PathBuf,
+	file: PathBuf,
+	device: Arc<Device>,
+	queue: Arc<Queue>,
+	uploads: Vec<Upload>,
+}
+
+impl AsyncContext {
+	pub(super) fn new(
+		path: PathBuf,
+		file: PathBuf,
+		device: Arc<Device>,
+		queue: Arc<Queue>,
+	) -> Self {
+		AsyncContext {
+			path,
+			file,
+			device,
+			queue,
+			uploads: Vec::new(),
+		}
+	}
+
+	/// Path of the asset relative to the asset directories.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// The file on disk.
+	pub fn file(&self) -> &Path {
+		&self.file
+	}
+
+	pub fn read(&self) -> Result<Vec<u8>, AssetError> {
+		Ok(std::fs::read(&self.file)?)
+	}
+
+	pub fn device(&self) -> &Arc<Device> {
+		&self.device
+	}
+
+	/// Queue to upload on, see `Assets::set_upload_queue`.
+	pub fn queue(&self) -> &Arc<Queue> {
+		&self.queue
+	}
+
+	/// Has the frame after the asset is stored wait for `future`.
+	pub fn upload<F: GpuFuture + Send + 'static>(&mut self, future: F) {
+		self.uploads.push(Box::new(future));
+	}
+
+	pub(super) fn into_uploads(self) -> Vec<Upload> {
+		self.uploads
+	}
+}
+
+// an asset finished by a loader thread
+pub(super) struct Loaded {
+	pub type_id: TypeId,
+	pub id: u64,
+	pub result: Result<(Box<dyn Any + Send>, Vec<Upload>), AssetError>,
+}
+
+pub(super) struct Loader {
+	jobs: Sender<Job>,
+	results: Receiver<Loaded>,
+	pending: usize,
+}
+
+impl Loader {
+	pub fn new() -> Self {
+		let (jobs, job_receiver) = mpsc::channel::<Job>();
+		let (result_sender, results) = mpsc::channel();
+		let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+		// leave a core for the main thread
+		let threads = thread::available_parallelism()
+			.map_or(2, |n| n.get().saturating_sub(1))
+			.clamp(1, 4);
+		for i in 0..threads {
+			let job_receiver = job_receiver.clone();
+			let result_sender = result_sender.clone();
+			thread::Builder::new()
+				.name(format!("asset loader {}", i))
+				.spawn(move || loop {
+					// the lock is only held while waiting, not while loading
+					let job = match job_receiver.lock().unwrap().recv() {
+						Ok(job) => job,
+						Err(_) => return,
+					};
+					if result_sender.send(job()).is_err() {
+						return;
+					}
+				})
+				.unwrap();
+		}
+
+		Loader {
+			jobs,
+			results,
+			pending: 0,
+		}
+	}
+
+	/// Loads `T` from the context on a loader thread.
+	pub fn spawn<T: AsyncAsset>(&mut self, id: u64, mut context: AsyncContext) {
+		self.pending += 1;
+		self.jobs
+			.send(Box::new(move || {
+				let result = T::load_async(&mut context).map(|asset| {
+					(
+						Box::new(asset) as Box<dyn Any + Send>,
+						context.into_uploads(),
+					)
+				});
+				Loaded {
+					type_id: TypeId::of::<T>(),
+					id,
+					result,
+				}
+			}))
+			.unwrap();
+	}
+
+	/// Assets finished since the last call.
+	pub fn finished(&mut self) -> Vec<Loaded> {
+		let finished: Vec<Loaded> = self.results.try_iter().collect();
+		self.pending -= finished.len();
+		finished
+	}
+
+	/// Number of assets still being loaded.
+	pub fn pending(&self) -> usize {
+		self.pending
+	}
+}
@@ -0,0 +1,148 @@
+use super::{Asset, AssetError, Handle, LoadContext, Texture2DArray};
+
+use crate::color::Color;
+use crate::render2d::Texture;
+use crate::sampler::SamplerDesc;
+
+/// Metallic-roughness material parameters and textures.
+///
+/// Loaded from `.toml` files, texture paths are relative to the file. The
+/// textures load in the background, see `Assets::load_async`:
+///
+/// ```toml
+/// base_color = [1.0, 1.0, 1.0, 1.0]
+/// metallic = 0.0
+/// roughness = 0.5
+/// emissive = "#000000"
+/// base_color_texture = "brick.png"
+/// normal_texture = "brick_normal.png"
+/// metallic_roughness_texture = "brick_mr_linear.png"
+/// # or a layer of a texture array
+/// layer_texture = "terrain_layers.toml"
+/// layer = 2
+///
+/// [sampler]
+/// wrap = "mirror"
+/// ```
+///
+/// Colors are either linear rgb(a) numbers or srgb hex codes as picked in an
+/// image editor. The `[sampler]` table is optional, see `SamplerDesc::from_toml`.
+#[derive(Debug, Clone)]
+pub struct Material {
+	pub base_color: Color,
+	pub metallic: f32,
+	pub roughness: f32,
+	/// Alpha is ignored.
+	pub emissive: Color,
+	pub base_color_texture: Option<Handle<Texture>>,
+	pub normal_texture: Option<Handle<Texture>>,
+	/// Roughness in green and metallic in blue, like gltf.
+	pub metallic_roughness_texture: Option<Handle<Texture>>,
+	/// Texture array sampled at `layer`, see `Texture2DArray`.
+	pub layer_texture: Option<Handle<Texture2DArray>>,
+	/// Layer of `layer_texture`, handed to the shader as a float.
+	pub layer: u32,
+	/// How the textures are sampled, `None` uses `Renderer::sampler`. Get
+	/// the sampler from `Renderer::sampler_for`.
+	pub sampler: Option<SamplerDesc>,
+}
+
+impl Default for Material {
+	fn default() -> Self {
+		Material {
+			base_color: Color::WHITE,
+			metallic: 0.0,
+			roughness: 0.5,
+			emissive: Color::BLACK,
+			base_color_texture: None,
+			normal_texture: None,
+			metallic_roughness_texture: None,
+			layer_texture: None,
+			layer: 0,
+			sampler: None,
+		}
+	}
+}
+
+impl Asset for Material {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		let source = String::from_utf8(context.read()?)
+			.map_err(|_| AssetError::Format("material isn't utf-8"))?;
+		let root: toml::Value = source.parse()?;
+		let number = |key| match root.get(key) {
+			Some(value) => value
+				.as_float()
+				.or_else(|| value.as_integer().map(|n| n as f64))
+				.map(|n| Some(n as f32))
+				.ok_or(AssetError::Format("material value should be a number")),
+			None => Ok(None),
+		};
+		let color = |key, default: Color| -> Result<Color, AssetError> {
+			let value = match root.get(key) {
+				Some(value) => value,
+				None => return Ok(default),
+			};
+			if let Some(hex) = value.as_str() {
+				return Color::from_hex(hex)
+					.ok_or(AssetError::Format("material color isn't a hex code"));
+			}
+			let values = value
+				.as_array()
+				.filter(|values| values.len() == 3 || values.len() == 4)
+				.ok_or(AssetError::Format("material color has the wrong length"))?;
+			let mut out = [1.0; 4];
+			for (out, value) in out.iter_mut().zip(values) {
+				*out = value
+					.as_float()
+					.or_else(|| value.as_integer().map(|n| n as f64))
+					.ok_or(AssetError::Format("material color should be numbers"))? as f32;
+			}
+			Ok(Color::from(out))
+		};
+
+		let mut material = Material::default();
+		material.base_color = color("base_color", material.base_color)?;
+		material.emissive = color("emissive", material.emissive)?;
+		material.metallic = number("metallic")?.unwrap_or(material.metallic);
+		material.roughness = number("roughness")?.unwrap_or(material.roughness);
+
+		if let Some(sampler) = root.get("sampler") {
+			material.sampler = Some(SamplerDesc::from_toml(sampler).map_err(AssetError::Format)?);
+		}
+
+		let texture_path = |key| match root.get(key) {
+			Some(value) => value
+				.as_str()
+				.map(|path| Some(path.to_string()))
+				.ok_or(AssetError::Format("material texture should be a path")),
+			None => Ok(None),
+		};
+		let textures = [
+			texture_path("base_color_texture")?,
+			texture_path("normal_texture")?,
+			texture_path("metallic_roughness_texture")?,
+		];
+		let mut load = |path: &Option<String>| match path {
+			Some(path) => context.load_async::<Texture>(path).map(Some),
+			None => Ok(None),
+		};
+		material.base_color_texture = load(&textures[0])?;
+		material.normal_texture = load(&textures[1])?;
+		material.metallic_roughness_texture = load(&textures[2])?;
+
+		if let Some(path) = texture_path("layer_texture")? {
+			material.layer_texture = Some(context.load_async::<Texture2DArray>(&path)?);
+		}
+		if let Some(layer) = root.get("layer") {
+			material.layer =
+				layer
+					.as_integer()
+					.filter(|&layer| layer >= 0)
+					.ok_or(AssetError::Format(
+						"material layer should be a positive integer",
+					))? as u32;
+		}
+
+		Ok(material)
+	}
+}
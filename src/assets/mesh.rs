@@ -0,0 +1,100 @@
+use super::{Asset, AssetError, AsyncAsset, AsyncContext, LoadContext};
+
+use crate::geometry::MeshData;
+
+use vulkano::buffer::{BufferUsage, ImmutableBuffer};
+use vulkano::device::Queue;
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+crate::vertex_layout!(pub struct MeshVertex { position, normal, tangent, uv });
+
+/// A static mesh uploaded to the gpu, with the cpu copy kept around for
+/// collision and picking.
+pub struct Mesh {
+	pub data: MeshData,
+	pub vertices: Arc<ImmutableBuffer<[MeshVertex]>>,
+	pub indices: Arc<ImmutableBuffer<[u32]>>,
+}
+
+impl Mesh {
+	/// Uploads `data`, the returned future has to be joined with the frame
+	/// that first draws the mesh.
+	pub fn upload(data: MeshData, queue: Arc<Queue>) -> (Mesh, impl GpuFuture) {
+		let (vertices, vertex_future) = ImmutableBuffer::from_iter(
+			data.interleave::<MeshVertex>().into_iter(),
+			BufferUsage::vertex_buffer(),
+			queue.clone(),
+		)
+		.unwrap();
+		let (indices, index_future) = ImmutableBuffer::from_iter(
+			data.indices.iter().cloned(),
+			BufferUsage::index_buffer(),
+			queue,
+		)
+		.unwrap();
+
+		let mesh = Mesh {
+			data,
+			vertices,
+			indices,
+		};
+		(mesh, vertex_future.join(index_future))
+	}
+}
+
+/// Gltf and glb files. Every triangle primitive in the file is merged into
+/// one mesh in its own space, node transforms are ignored.
+impl AsyncAsset for Mesh {
+	fn load_async(context: &mut AsyncContext) -> Result<Self, AssetError> {
+		let (document, buffers, _) = ::gltf::import(context.file())?;
+		let buffer_data = |buffer: ::gltf::Buffer| Some(&*buffers[buffer.index()]);
+
+		let mut data = MeshData::default();
+		for primitive in document.meshes().flat_map(|mesh| mesh.primitives()) {
+			if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+				continue;
+			}
+
+			let reader = primitive.reader(buffer_data);
+			let mut part = MeshData {
+				positions: match reader.read_positions() {
+					Some(positions) => positions.collect(),
+					None => continue,
+				},
+				..Default::default()
+			};
+			let count = part.positions.len();
+			part.normals = reader.read_normals().map_or_else(Vec::new, |n| n.collect());
+			part.tangents = reader
+				.read_tangents()
+				.map_or_else(Vec::new, |t| t.collect());
+			part.uvs = reader
+				.read_tex_coords(0)
+				.map_or_else(|| vec![[0.0; 2]; count], |uvs| uvs.into_f32().collect());
+			part.colors = reader
+				.read_colors(0)
+				.map_or_else(Vec::new, |colors| colors.into_rgba_f32().collect());
+			part.indices = reader
+				.read_indices()
+				.map_or_else(|| (0..count as u32).collect(), |i| i.into_u32().collect());
+			part.generate_missing_attributes()
+				.map_err(AssetError::Format)?;
+			data.append(&part);
+		}
+
+		if data.vertex_count() == 0 {
+			return Err(AssetError::Format("no triangles in the file"));
+		}
+		let (mesh, future) = Mesh::upload(data, context.queue().clone());
+		context.upload(future);
+		Ok(mesh)
+	}
+}
+
+impl Asset for Mesh {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		context.load_blocking()
+	}
+}
@@ -0,0 +1,733 @@
+// loading textures, meshes, shaders and materials from disk once and sharing
+// them through typed handles, freeing them when the last handle is gone.
+
+mod basis;
+mod compressed;
+mod hdr;
+mod loader;
+mod material;
+mod mesh;
+mod streaming;
+mod texture;
+mod texture3d;
+mod texture_array;
+mod watcher;
+
+pub use basis::TranscodeTarget;
+pub use compressed::{supports_format, CompressedImage};
+pub use hdr::{EquirectToCube, HdrImage, HDR_FORMAT};
+pub use loader::AsyncContext;
+pub use material::Material;
+pub use mesh::{Mesh, MeshVertex};
+pub use streaming::{screen_size, TextureStreamer};
+pub use texture::Shader;
+pub use texture3d::Texture3D;
+pub use texture_array::Texture2DArray;
+
+use vulkano::device::{Device, Queue};
+use vulkano::sync::GpuFuture;
+
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use loader::Loader;
+use watcher::Watcher;
+
+type Reloader = fn(&mut Assets, u64, &Path, &Path);
+
+#[derive(Debug)]
+pub enum AssetError {
+	/// The path isn't in any of the asset directories.
+	NotFound(PathBuf),
+	Io(std::io::Error),
+	Image(image::ImageError),
+	Gltf(::gltf::Error),
+	Exr(exr::error::Error),
+	Toml(toml::de::Error),
+	Json(serde_json::Error),
+	Format(&'static str),
+}
+
+impl fmt::Display for AssetError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			AssetError::NotFound(path) => write!(f, "asset not found: {}", path.display()),
+			AssetError::Io(error) => write!(f, "failed to read asset: {}", error),
+			AssetError::Image(error) => write!(f, "failed to decode image: {}", error),
+			AssetError::Gltf(error) => write!(f, "failed to load gltf: {}", error),
+			AssetError::Exr(error) => write!(f, "failed to load exr: {}", error),
+			AssetError::Toml(error) => write!(f, "invalid asset file: {}", error),
+			AssetError::Json(error) => write!(f, "invalid asset json: {}", error),
+			AssetError::Format(what) => write!(f, "invalid asset: {}", what),
+		}
+	}
+}
+
+impl std::error::Error for AssetError {}
+
+impl From<std::io::Error> for AssetError {
+	fn from(error: std::io::Error) -> Self {
+		AssetError::Io(error)
+	}
+}
+
+impl From<image::ImageError> for AssetError {
+	fn from(error: image::ImageError) -> Self {
+		AssetError::Image(error)
+	}
+}
+
+impl From<::gltf::Error> for AssetError {
+	fn from(error: ::gltf::Error) -> Self {
+		AssetError::Gltf(error)
+	}
+}
+
+impl From<exr::error::Error> for AssetError {
+	fn from(error: exr::error::Error) -> Self {
+		AssetError::Exr(error)
+	}
+}
+
+impl From<toml::de::Error> for AssetError {
+	fn from(error: toml::de::Error) -> Self {
+		AssetError::Toml(error)
+	}
+}
+
+impl From<serde_json::Error> for AssetError {
+	fn from(error: serde_json::Error) -> Self {
+		AssetError::Json(error)
+	}
+}
+
+/// Something `Assets` can load from a file.
+pub trait Asset: Sized + 'static {
+	/// Creates the asset from the file at `context.file()`.
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError>;
+}
+
+/// Assets that can be read, decoded and uploaded on a loader thread with
+/// `Assets::load_async`. They can't load other assets while doing so.
+pub trait AsyncAsset: Asset + Send {
+	fn load_async(context: &mut AsyncContext) -> Result<Self, AssetError>;
+}
+
+/// Where an asset is in loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+	/// Still on a loader thread, `Assets::get` gives the placeholder.
+	Loading,
+	Loaded,
+	/// Loading failed and the error was logged, `Assets::get` keeps giving
+	/// the placeholder.
+	Failed,
+}
+
+/// A reference to an asset in `Assets`. The asset stays loaded while any
+/// clone of its handle is alive.
+pub struct Handle<T> {
+	id: u64,
+	token: Arc<()>,
+	marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+	/// Number of live handles to the asset, including this one.
+	pub fn ref_count(&self) -> usize {
+		Arc::strong_count(&self.token)
+	}
+}
+
+impl<T> Clone for Handle<T> {
+	fn clone(&self) -> Self {
+		Handle {
+			id: self.id,
+			token: self.token.clone(),
+			marker: PhantomData,
+		}
+	}
+}
+
+impl<T> PartialEq for Handle<T> {
+	fn eq(&self, other: &Self) -> bool {
+		self.id == other.id
+	}
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.id.hash(state);
+	}
+}
+
+impl<T> fmt::Debug for Handle<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Handle({})", self.id)
+	}
+}
+
+struct Entry<T> {
+	// `None` until loaded
+	asset: Option<T>,
+	state: LoadState,
+	// relative to the asset directories, and on disk
+	path: Option<PathBuf>,
+	file: Option<PathBuf>,
+	token: Weak<()>,
+}
+
+// every asset of one type
+struct Storage<T> {
+	entries: HashMap<u64, Entry<T>>,
+	paths: HashMap<PathBuf, u64>,
+	placeholder: Option<T>,
+}
+
+trait AnyStorage {
+	fn collect_garbage(&mut self) -> usize;
+	// stores an asset from a loader thread
+	fn finish(&mut self, id: u64, result: Result<Box<dyn Any + Send>, AssetError>);
+	// assets loaded from `file`, with their paths
+	fn find_file(&self, file: &Path) -> Vec<(u64, PathBuf)>;
+	fn files(&self) -> Vec<PathBuf>;
+	fn as_any(&self) -> &dyn Any;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+	fn collect_garbage(&mut self) -> usize {
+		let unused: Vec<u64> = self
+			.entries
+			.iter()
+			.filter(|(_, entry)| entry.token.strong_count() == 0)
+			.map(|(&id, _)| id)
+			.collect();
+		for id in unused.iter() {
+			if let Some(path) = self.entries.remove(id).and_then(|entry| entry.path) {
+				self.paths.remove(&path);
+			}
+		}
+		unused.len()
+	}
+
+	fn finish(&mut self, id: u64, result: Result<Box<dyn Any + Send>, AssetError>) {
+		// dropped while loading
+		let entry = match self.entries.get_mut(&id) {
+			Some(entry) => entry,
+			None => return,
+		};
+		match result {
+			Ok(asset) => {
+				entry.asset = Some(*asset.downcast().unwrap());
+				entry.state = LoadState::Loaded;
+			}
+			Err(error) => {
+				let path = entry.path.as_deref().unwrap_or(Path::new(""));
+				log::warn!("Failed to load {}: {}", path.display(), error);
+				entry.state = LoadState::Failed;
+			}
+		}
+	}
+
+	fn find_file(&self, file: &Path) -> Vec<(u64, PathBuf)> {
+		self.entries
+			.iter()
+			.filter(|(_, entry)| entry.file.as_deref() == Some(file))
+			.filter_map(|(&id, entry)| Some((id, entry.path.clone()?)))
+			.collect()
+	}
+
+	fn files(&self) -> Vec<PathBuf> {
+		self.entries
+			.values()
+			.filter_map(|entry| entry.file.clone())
+			.collect()
+	}
+
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+}
+
+/// Registry of loaded assets.
+///
+/// Loading the same path twice gives handles to the same asset. Assets with
+/// no handles left are dropped by `collect_garbage`, along with their gpu
+/// resources once no command buffer uses them anymore.
+///
+/// `load_async` returns right away and loads on background threads, call
+/// `poll` every frame to pick up what they finished. With `watch` files that
+/// change on disk are reloaded in `poll` too, keeping their handles.
+pub struct Assets {
+	device: Arc<Device>,
+	queue: Arc<Queue>,
+	upload_queue: Arc<Queue>,
+	roots: Vec<PathBuf>,
+	storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+	// loads a `T` again in place, by type
+	reloaders: HashMap<TypeId, Reloader>,
+	uploads: Option<Box<dyn GpuFuture>>,
+	// started on the first `load_async`
+	loader: Option<Loader>,
+	watcher: Option<Watcher>,
+	// ids whose asset was replaced by the last `poll`
+	changed: HashSet<u64>,
+	next_id: u64,
+}
+
+impl Assets {
+	/// Assets are looked up in `roots` in order, eg. `EngineConfig::asset_paths`.
+	pub fn new(device: Arc<Device>, queue: Arc<Queue>, roots: Vec<PathBuf>) -> Self {
+		Assets {
+			device,
+			upload_queue: queue.clone(),
+			queue,
+			roots,
+			storages: HashMap::new(),
+			reloaders: HashMap::new(),
+			uploads: None,
+			loader: None,
+			watcher: None,
+			changed: HashSet::new(),
+			next_id: 0,
+		}
+	}
+
+	/// Uploads from loader threads go through `queue` instead of the main
+	/// queue, so they don't hold up rendering. It has to be from the same
+	/// family as the main queue, vulkano doesn't transfer image ownership.
+	pub fn set_upload_queue(&mut self, queue: Arc<Queue>) {
+		assert_eq!(
+			queue.family().id(),
+			self.queue.family().id(),
+			"upload queue is from another family"
+		);
+		self.upload_queue = queue;
+	}
+
+	pub fn device(&self) -> &Arc<Device> {
+		&self.device
+	}
+
+	pub fn queue(&self) -> &Arc<Queue> {
+		&self.queue
+	}
+
+	/// Loads the asset at `path`, relative to the asset directories, or
+	/// returns another handle to it when it's already loaded.
+	pub fn load<T: Asset>(&mut self, path: impl AsRef<Path>) -> Result<Handle<T>, AssetError> {
+		let path = normalize(path.as_ref());
+		if let Some(handle) = self.find(&path) {
+			return Ok(handle);
+		}
+
+		let file = self
+			.resolve(&path)
+			.ok_or_else(|| AssetError::NotFound(path.clone()))?;
+		let asset = T::load(&mut LoadContext {
+			assets: self,
+			path: &path,
+			file: &file,
+		})?;
+		Ok(self.insert(Some(asset), Some((path, file))))
+	}
+
+	/// Like `load` but reads, decodes and uploads the asset on a loader thread.
+	/// The handle is returned right away and `get` gives the placeholder until
+	/// `poll` picks up the asset. Missing files fail right away.
+	pub fn load_async<T: AsyncAsset>(
+		&mut self,
+		path: impl AsRef<Path>,
+	) -> Result<Handle<T>, AssetError> {
+		let path = normalize(path.as_ref());
+		if let Some(handle) = self.find(&path) {
+			return Ok(handle);
+		}
+
+		let file = self
+			.resolve(&path)
+			.ok_or_else(|| AssetError::NotFound(path.clone()))?;
+		let context = AsyncContext::new(
+			path.clone(),
+			file.clone(),
+			self.device.clone(),
+			self.upload_queue.clone(),
+		);
+		let handle = self.insert::<T>(None, Some((path, file)));
+		self.loader
+			.get_or_insert_with(Loader::new)
+			.spawn::<T>(handle.id, context);
+		Ok(handle)
+	}
+
+	/// Adds an asset that didn't come from a file, eg. one generated at runtime.
+	pub fn add<T: Asset>(&mut self, asset: T) -> Handle<T> {
+		self.insert(Some(asset), None)
+	}
+
+	/// What `get` returns for assets of type `T` that are still loading or
+	/// failed to load, eg. a checkerboard texture.
+	pub fn set_placeholder<T: Asset>(&mut self, placeholder: T) {
+		self.storage_mut::<T>().placeholder = Some(placeholder);
+	}
+
+	/// The asset behind `handle`, or the placeholder while it isn't loaded.
+	/// Panics when there's neither, or the handle is from another `Assets`.
+	pub fn get<T: Asset>(&self, handle: &Handle<T>) -> &T {
+		self.try_get(handle)
+			.expect("asset isn't loaded and there's no placeholder")
+	}
+
+	/// Like `get` but `None` instead of panicking.
+	pub fn try_get<T: Asset>(&self, handle: &Handle<T>) -> Option<&T> {
+		let storage = self.storage::<T>()?;
+		storage
+			.entries
+			.get(&handle.id)?
+			.asset
+			.as_ref()
+			.or(storage.placeholder.as_ref())
+	}
+
+	/// The loaded asset behind `handle`, never the placeholder. Panics when
+	/// it isn't loaded.
+	pub fn get_mut<T: Asset>(&mut self, handle: &Handle<T>) -> &mut T {
+		self.storage_mut::<T>()
+			.entries
+			.get_mut(&handle.id)
+			.and_then(|entry| entry.asset.as_mut())
+			.expect("asset isn't loaded")
+	}
+
+	pub fn state<T: Asset>(&self, handle: &Handle<T>) -> LoadState {
+		self.storage::<T>().unwrap().entries[&handle.id].state
+	}
+
+	/// Number of assets still on loader threads.
+	pub fn loading(&self) -> usize {
+		self.loader.as_ref().map_or(0, |loader| loader.pending())
+	}
+
+	/// Reloads assets whose files change on disk from now on, checking
+	/// every `interval`. Meant for development, eg. behind a debug flag.
+	pub fn watch(&mut self, interval: Duration) {
+		let watcher = Watcher::new(interval);
+		for file in self.storages.values().flat_map(|storage| storage.files()) {
+			watcher.watch(&file);
+		}
+		self.watcher = Some(watcher);
+	}
+
+	/// Stores the assets loader threads finished and reloads the ones that
+	/// changed on disk since the last call, see `changed`. Returns how many
+	/// assets were replaced. Call once per frame, before drawing, and join
+	/// `flush_uploads` with the frame.
+	pub fn poll(&mut self) -> usize {
+		self.changed.clear();
+
+		let finished = self
+			.loader
+			.as_mut()
+			.map_or_else(Vec::new, |loader| loader.finished());
+		for loaded in finished {
+			let result = match loaded.result {
+				Ok((asset, uploads)) => {
+					for upload in uploads {
+						self.upload(upload);
+					}
+					self.changed.insert(loaded.id);
+					Ok(asset)
+				}
+				Err(error) => Err(error),
+			};
+			if let Some(storage) = self.storages.get_mut(&loaded.type_id) {
+				storage.finish(loaded.id, result);
+			}
+		}
+
+		let modified = self
+			.watcher
+			.as_ref()
+			.map_or_else(Vec::new, |watcher| watcher.changed());
+		for file in modified {
+			let assets: Vec<(TypeId, u64, PathBuf)> = self
+				.storages
+				.iter()
+				.flat_map(|(&type_id, storage)| {
+					storage
+						.find_file(&file)
+						.into_iter()
+						.map(move |(id, path)| (type_id, id, path))
+				})
+				.collect();
+			for (type_id, id, path) in assets {
+				let reload = self.reloaders[&type_id];
+				reload(self, id, &path, &file);
+			}
+		}
+
+		self.changed.len()
+	}
+
+	/// Whether the last `poll` replaced the asset, because it finished
+	/// loading or was reloaded. Descriptor sets using it have to be rebuilt.
+	pub fn changed<T: Asset>(&self, handle: &Handle<T>) -> bool {
+		self.changed.contains(&handle.id)
+	}
+
+	/// Path the asset was loaded from, `None` for assets from `add`.
+	pub fn path<T: Asset>(&self, handle: &Handle<T>) -> Option<&Path> {
+		self.storage::<T>()?
+			.entries
+			.get(&handle.id)?
+			.path
+			.as_deref()
+	}
+
+	/// Number of assets of type `T` currently loaded, including unused ones
+	/// that haven't been collected yet.
+	pub fn count<T: Asset>(&self) -> usize {
+		self.storage::<T>()
+			.map_or(0, |storage| storage.entries.len())
+	}
+
+	/// Drops every asset without handles and returns how many there were.
+	/// Call once per frame.
+	pub fn collect_garbage(&mut self) -> usize {
+		self.storages
+			.values_mut()
+			.map(|storage| storage.collect_garbage())
+			.sum()
+	}
+
+	/// Has the next frame wait for `future`, for uploads made outside of loads.
+	pub fn upload<F: GpuFuture + 'static>(&mut self, future: F) {
+		self.uploads = Some(match self.uploads.take() {
+			Some(uploads) => Box::new(uploads.join(future)),
+			None => Box::new(future),
+		});
+	}
+
+	/// Swaps in a new version of a loaded asset, eg. one built at runtime.
+	/// Counts as changed until the next `poll`.
+	pub fn replace<T: Asset>(&mut self, handle: &Handle<T>, asset: T) {
+		let entry = self.storage_mut::<T>().entries.get_mut(&handle.id).unwrap();
+		entry.asset = Some(asset);
+		entry.state = LoadState::Loaded;
+		self.changed.insert(handle.id);
+	}
+
+	/// Queue background uploads go through, see `set_upload_queue`.
+	pub fn upload_queue(&self) -> &Arc<Queue> {
+		&self.upload_queue
+	}
+
+	/// Uploads started by loads since the last call, join them with the next
+	/// frame so it waits for them.
+	pub fn flush_uploads(&mut self) -> Option<Box<dyn GpuFuture>> {
+		self.uploads.take()
+	}
+
+	// another handle to an asset loaded from `path`, reviving it if it was
+	// unused but not collected yet
+	fn find<T: Asset>(&mut self, path: &Path) -> Option<Handle<T>> {
+		let storage = self.storage_mut::<T>();
+		let id = *storage.paths.get(path)?;
+		let entry = storage.entries.get_mut(&id).unwrap();
+		let token = entry.token.upgrade().unwrap_or_else(|| {
+			let token = Arc::new(());
+			entry.token = Arc::downgrade(&token);
+			token
+		});
+		Some(Handle {
+			id,
+			token,
+			marker: PhantomData,
+		})
+	}
+
+	// `source` is the path relative to the asset directories and the file
+	fn insert<T: Asset>(
+		&mut self,
+		asset: Option<T>,
+		source: Option<(PathBuf, PathBuf)>,
+	) -> Handle<T> {
+		let id = self.next_id;
+		self.next_id += 1;
+		let token = Arc::new(());
+
+		self.reloaders
+			.entry(TypeId::of::<T>())
+			.or_insert(reload::<T> as Reloader);
+		if let (Some(watcher), Some((_, file))) = (self.watcher.as_ref(), source.as_ref()) {
+			watcher.watch(file);
+		}
+
+		let (path, file) = source.unzip();
+		let storage = self.storage_mut::<T>();
+		if let Some(path) = path.clone() {
+			storage.paths.insert(path, id);
+		}
+		storage.entries.insert(
+			id,
+			Entry {
+				state: if asset.is_some() {
+					LoadState::Loaded
+				} else {
+					LoadState::Loading
+				},
+				asset,
+				path,
+				file,
+				token: Arc::downgrade(&token),
+			},
+		);
+		Handle {
+			id,
+			token,
+			marker: PhantomData,
+		}
+	}
+
+	fn resolve(&self, path: &Path) -> Option<PathBuf> {
+		self.roots
+			.iter()
+			.map(|root| root.join(path))
+			.find(|file| file.is_file())
+	}
+
+	fn storage<T: Asset>(&self) -> Option<&Storage<T>> {
+		self.storages
+			.get(&TypeId::of::<T>())
+			.map(|storage| storage.as_any().downcast_ref().unwrap())
+	}
+
+	fn storage_mut<T: Asset>(&mut self) -> &mut Storage<T> {
+		self.storages
+			.entry(TypeId::of::<T>())
+			.or_insert_with(|| {
+				Box::new(Storage::<T> {
+					entries: HashMap::new(),
+					paths: HashMap::new(),
+					placeholder: None,
+				})
+			})
+			.as_any_mut()
+			.downcast_mut()
+			.unwrap()
+	}
+}
+
+/// What an `Asset` gets to load itself with.
+pub struct LoadContext<'a> {
+	assets: &'a mut Assets,
+	path: &'a Path,
+	file: &'a Path,
+}
+
+impl<'a> LoadContext<'a> {
+	/// Path of the asset relative to the asset directories.
+	pub fn path(&self) -> &Path {
+		self.path
+	}
+
+	/// The file on disk.
+	pub fn file(&self) -> &Path {
+		self.file
+	}
+
+	pub fn read(&self) -> Result<Vec<u8>, AssetError> {
+		Ok(std::fs::read(self.file)?)
+	}
+
+	pub fn device(&self) -> &Arc<Device> {
+		&self.assets.device
+	}
+
+	pub fn queue(&self) -> &Arc<Queue> {
+		&self.assets.queue
+	}
+
+	/// Has the next frame wait for `future`, eg. an image upload.
+	pub fn upload<F: GpuFuture + 'static>(&mut self, future: F) {
+		self.assets.upload(future);
+	}
+
+	/// Loads an asset this one depends on, `path` is relative to this asset's
+	/// directory.
+	pub fn load<T: Asset>(&mut self, path: impl AsRef<Path>) -> Result<Handle<T>, AssetError> {
+		let path = self.path.parent().unwrap_or(Path::new("")).join(path);
+		self.assets.load(path)
+	}
+
+	/// Like `load` but on a loader thread, see `Assets::load_async`.
+	pub fn load_async<T: AsyncAsset>(
+		&mut self,
+		path: impl AsRef<Path>,
+	) -> Result<Handle<T>, AssetError> {
+		let path = self.path.parent().unwrap_or(Path::new("")).join(path);
+		self.assets.load_async(path)
+	}
+
+	/// Runs `T::load_async` on this thread, so async assets can implement
+	/// `Asset::load` with it.
+	pub fn load_blocking<T: AsyncAsset>(&mut self) -> Result<T, AssetError> {
+		let mut context = AsyncContext::new(
+			self.path.to_path_buf(),
+			self.file.to_path_buf(),
+			self.assets.device.clone(),
+			self.assets.queue.clone(),
+		);
+		let asset = T::load_async(&mut context)?;
+		for upload in context.into_uploads() {
+			self.assets.upload(upload);
+		}
+		Ok(asset)
+	}
+}
+
+// loads the asset with `id` again and swaps it in, keeping the old one when
+// that fails, eg. because the file is still being written
+fn reload<T: Asset>(assets: &mut Assets, id: u64, path: &Path, file: &Path) {
+	let result = T::load(&mut LoadContext { assets, path, file });
+	match result {
+		Ok(asset) => {
+			let entry = assets.storage_mut::<T>().entries.get_mut(&id).unwrap();
+			entry.asset = Some(asset);
+			entry.state = LoadState::Loaded;
+			assets.changed.insert(id);
+			log::info!("Reloaded {}", path.display());
+		}
+		Err(error) => log::warn!("Failed to reload {}: {}", path.display(), error),
+	}
+}
+
+// drops `.` and resolves `..` so the same file always has the same key
+fn normalize(path: &Path) -> PathBuf {
+	use std::path::Component;
+
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				normalized.pop();
+			}
+			component => normalized.push(component),
+		}
+	}
+	normalized
+}
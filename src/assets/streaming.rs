@@ -0,0 +1,298 @@
+// keeps only the mip levels of large textures that the camera can actually
+// see in gpu memory, within a budget.
+//
+// vulkano has no sparse images, so changing a texture's resident levels
+// recreates it: the top resident level is uploaded from system memory and the
+// gpu generates the levels below it.
+
+use super::{AssetError, Assets, Handle};
+
+use crate::render2d::Texture;
+
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How many frames a texture keeps its detail after it was last requested.
+const KEEP_FRAMES: u64 = 60;
+
+struct Streamed {
+	handle: Handle<Texture>,
+	// full resolution rgba8 pixels
+	pixels: Vec<u8>,
+	dimensions: [u32; 2],
+	format: Format,
+	// first full resolution level on the gpu, 0 is everything
+	first_level: u32,
+	// the lowest detail the texture is ever reduced to
+	min_first_level: u32,
+	// largest size on screen requested recently, in pixels
+	screen_size: f32,
+	requested_frame: u64,
+}
+
+impl Streamed {
+	// first level sharp enough for `screen_size`
+	fn wanted_level(&self) -> u32 {
+		let largest = self.dimensions[0].max(self.dimensions[1]) as f32;
+		let level = (largest / self.screen_size.max(1.0))
+			.log2()
+			.floor()
+			.max(0.0) as u32;
+		level.min(self.min_first_level)
+	}
+}
+
+/// Streams the high mip levels of registered textures in and out based on
+/// how large they are on screen and a gpu memory budget. Low levels up to
+/// `resident_size` always stay resident.
+///
+/// Each frame: `request` the visible textures after `Assets::poll`, then
+/// `update`. Textures that changed show up in `Assets::changed`.
+pub struct TextureStreamer {
+	textures: HashMap<Handle<Texture>, Streamed>,
+	/// Gpu memory all streamed textures may use together, in bytes.
+	pub budget: u64,
+	/// Bytes uploaded per frame at most, beyond one texture.
+	pub upload_budget: u64,
+	/// Largest dimension of the levels that are never streamed out.
+	pub resident_size: u32,
+	frame: u64,
+}
+
+impl TextureStreamer {
+	pub fn new(budget: u64) -> Self {
+		TextureStreamer {
+			textures: HashMap::new(),
+			budget,
+			upload_budget: 16 << 20,
+			resident_size: 128,
+			frame: 0,
+		}
+	}
+
+	/// Reads and decodes an image and starts it out with only its low levels.
+	/// The pixels stay in system memory to stream levels in from.
+	pub fn load(
+		&mut self,
+		assets: &mut Assets,
+		path: impl AsRef<Path>,
+		srgb: bool,
+	) -> Result<Handle<Texture>, AssetError> {
+		let path = path.as_ref();
+		let file = assets
+			.resolve(path)
+			.ok_or_else(|| AssetError::NotFound(path.to_path_buf()))?;
+		let image = image::load_from_memory(&std::fs::read(file)?)?.into_rgba8();
+		let (width, height) = image.dimensions();
+		Ok(self.add(assets, [width, height], image.into_raw(), srgb))
+	}
+
+	/// Registers tightly packed rgba8 pixels for streaming.
+	pub fn add(
+		&mut self,
+		assets: &mut Assets,
+		dimensions: [u32; 2],
+		pixels: Vec<u8>,
+		srgb: bool,
+	) -> Handle<Texture> {
+		assert_eq!(
+			pixels.len(),
+			(dimensions[0] * dimensions[1] * 4) as usize,
+			"pixel data doesn't match the texture size"
+		);
+		let largest = dimensions[0].max(dimensions[1]);
+		let min_first_level = (largest / self.resident_size.max(1))
+			.checked_next_power_of_two()
+			.map_or(0, |n| n.trailing_zeros());
+
+		let format = if srgb {
+			Format::R8G8B8A8Srgb
+		} else {
+			Format::R8G8B8A8Unorm
+		};
+		let texture = upload(assets, &pixels, dimensions, format, min_first_level);
+		let handle = assets.add(texture);
+
+		self.textures.insert(
+			handle.clone(),
+			Streamed {
+				handle: handle.clone(),
+				pixels,
+				dimensions,
+				format,
+				first_level: min_first_level,
+				min_first_level,
+				screen_size: 0.0,
+				requested_frame: 0,
+			},
+		);
+		handle
+	}
+
+	/// Stops streaming the texture, it keeps its current levels.
+	pub fn remove(&mut self, handle: &Handle<Texture>) {
+		self.textures.remove(handle);
+	}
+
+	/// The texture covers about `screen_size` pixels on screen this frame,
+	/// see `screen_size`. Call for every visible streamed texture.
+	pub fn request(&mut self, handle: &Handle<Texture>, screen_size: f32) {
+		if let Some(streamed) = self.textures.get_mut(handle) {
+			if streamed.requested_frame != self.frame {
+				streamed.screen_size = 0.0;
+			}
+			streamed.screen_size = streamed.screen_size.max(screen_size);
+			streamed.requested_frame = self.frame;
+		}
+	}
+
+	/// Gpu memory used by the streamed textures right now.
+	pub fn resident_bytes(&self) -> u64 {
+		self.textures
+			.values()
+			.map(|streamed| level_bytes(streamed, streamed.first_level))
+			.sum()
+	}
+
+	/// Fits the requested levels into the budget, drops levels that are no
+	/// longer wanted and uploads missing ones, largest on screen first.
+	pub fn update(&mut self, assets: &mut Assets) {
+		let frame = self.frame;
+		self.frame += 1;
+
+		// most screen pixels per texel first
+		let mut order: Vec<&mut Streamed> = self.textures.values_mut().collect();
+		for streamed in order.iter_mut() {
+			if frame.saturating_sub(streamed.requested_frame) > KEEP_FRAMES {
+				streamed.screen_size = 0.0;
+			}
+		}
+		order.sort_by(|a, b| {
+			let detail = |s: &Streamed| s.screen_size / s.dimensions[0].max(s.dimensions[1]) as f32;
+			detail(b).partial_cmp(&detail(a)).unwrap()
+		});
+
+		// low levels are always resident, spend what's left in priority order
+		let mut used: u64 = order
+			.iter()
+			.map(|streamed| level_bytes(streamed, streamed.min_first_level))
+			.sum();
+		let mut targets = Vec::with_capacity(order.len());
+		for streamed in order.iter() {
+			let mut target = streamed.wanted_level();
+			let base = level_bytes(streamed, streamed.min_first_level);
+			while target < streamed.min_first_level
+				&& used + level_bytes(streamed, target) - base > self.budget
+			{
+				target += 1;
+			}
+			used += level_bytes(streamed, target) - base;
+			targets.push(target);
+		}
+
+		let mut uploaded = 0;
+		for (streamed, target) in order.into_iter().zip(targets) {
+			if target == streamed.first_level {
+				continue;
+			}
+			// dropping detail is a small upload and frees memory, always do it
+			let adding = target < streamed.first_level;
+			if adding
+				&& uploaded > 0
+				&& uploaded + level_bytes(streamed, target) > self.upload_budget
+			{
+				continue;
+			}
+			if adding {
+				uploaded += level_bytes(streamed, target);
+			}
+
+			let texture = upload(
+				assets,
+				&streamed.pixels,
+				streamed.dimensions,
+				streamed.format,
+				target,
+			);
+			assets.replace(&streamed.handle, texture);
+			streamed.first_level = target;
+		}
+	}
+}
+
+/// Pixels an object of `world_size` covers on screen at `distance` from a
+/// perspective camera with vertical field of view `fov_y` in radians.
+pub fn screen_size(world_size: f32, distance: f32, fov_y: f32, viewport_height: f32) -> f32 {
+	world_size / (2.0 * distance.max(1e-4) * (fov_y * 0.5).tan()) * viewport_height
+}
+
+// bytes of the image starting at `first`, with its full mip chain
+fn level_bytes(streamed: &Streamed, first: u32) -> u64 {
+	let [width, height] = level_dimensions(streamed.dimensions, first);
+	width as u64 * height as u64 * 4 * 4 / 3
+}
+
+fn level_dimensions(dimensions: [u32; 2], level: u32) -> [u32; 2] {
+	[
+		(dimensions[0] >> level).max(1),
+		(dimensions[1] >> level).max(1),
+	]
+}
+
+// creates the image from level `first` down, the gpu generates the rest
+fn upload(
+	assets: &mut Assets,
+	pixels: &[u8],
+	dimensions: [u32; 2],
+	format: Format,
+	first: u32,
+) -> Texture {
+	let [width, height] = level_dimensions(dimensions, first);
+	let pixels = downsample(pixels, dimensions, first);
+
+	let (image, future) = ImmutableImage::from_iter(
+		pixels.into_iter(),
+		ImageDimensions::Dim2d {
+			width,
+			height,
+			array_layers: 1,
+		},
+		MipmapsCount::Log2,
+		format,
+		assets.upload_queue().clone(),
+	)
+	.unwrap();
+	assets.upload(future);
+	ImageView::new(image).unwrap()
+}
+
+// box filters rgba8 pixels `levels` times
+fn downsample(pixels: &[u8], dimensions: [u32; 2], levels: u32) -> Vec<u8> {
+	let mut current = pixels.to_vec();
+	let [mut width, mut height] = dimensions;
+	for _ in 0..levels {
+		let [next_width, next_height] = [(width / 2).max(1), (height / 2).max(1)];
+		let mut next = vec![0; (next_width * next_height * 4) as usize];
+		for y in 0..next_height {
+			for x in 0..next_width {
+				for channel in 0..4 {
+					let mut sum = 0;
+					for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+						let sx = (x * 2 + dx).min(width - 1);
+						let sy = (y * 2 + dy).min(height - 1);
+						sum += current[((sy * width + sx) * 4 + channel) as usize] as u32;
+					}
+					next[((y * next_width + x) * 4 + channel) as usize] = (sum / 4) as u8;
+				}
+			}
+		}
+		current = next;
+		width = next_width;
+		height = next_height;
+	}
+	current
+}
@@ -0,0 +1,100 @@
+use super::{Asset, AssetError, AsyncAsset, AsyncContext, CompressedImage, HdrImage, LoadContext};
+
+use crate::render2d::{linear_texture_from_rgba, texture_from_rgba, Texture};
+
+use vulkano::pipeline::shader::ShaderModule;
+
+use std::sync::Arc;
+
+/// Compiled spir-v shader module.
+pub type Shader = Arc<ShaderModule>;
+
+/// Png and jpeg images, decoded and uploaded as rgba8, or dds and ktx2
+/// files uploaded in their own format with their mip levels, eg. bc1 to bc7.
+/// Basis files and uastc ktx2 files are transcoded to the best block format
+/// the device supports, see `TranscodeTarget`. Color is decoded from srgb
+/// unless the file name ends in `_normal` or `_linear`, eg.
+/// `brick_normal.png`. Ktx2 and dx10 dds headers say so themselves.
+///
+/// Radiance `.hdr` and `.exr` files become linear half float textures, see
+/// `EquirectToCube` for turning environment maps into cubemaps.
+impl AsyncAsset for Texture {
+	fn load_async(context: &mut AsyncContext) -> Result<Self, AssetError> {
+		let linear = context
+			.path()
+			.file_stem()
+			.and_then(|stem| stem.to_str())
+			.is_some_and(|stem| stem.ends_with("_normal") || stem.ends_with("_linear"));
+		let extension = context
+			.path()
+			.extension()
+			.and_then(|extension| extension.to_str())
+			.map(|extension| extension.to_ascii_lowercase());
+		let compressed = match extension.as_deref() {
+			Some("dds") => Some(CompressedImage::from_dds(&context.read()?, !linear)?),
+			Some("ktx2") => Some(CompressedImage::from_ktx2(
+				&context.read()?,
+				context.device(),
+			)?),
+			Some("basis") => Some(CompressedImage::from_basis(
+				&context.read()?,
+				!linear,
+				context.device(),
+			)?),
+			Some("hdr") => {
+				let image = HdrImage::from_radiance(&context.read()?)?;
+				let (texture, future) = image.upload(context.queue().clone());
+				context.upload(future);
+				return Ok(texture);
+			}
+			Some("exr") => {
+				let image = HdrImage::from_exr(context.file())?;
+				let (texture, future) = image.upload(context.queue().clone());
+				context.upload(future);
+				return Ok(texture);
+			}
+			_ => None,
+		};
+		if let Some(compressed) = compressed {
+			let (texture, future) = compressed.upload(context.queue().clone())?;
+			context.upload(future);
+			return Ok(texture);
+		}
+
+		let image = image::load_from_memory(&context.read()?)?.into_rgba8();
+		let (width, height) = image.dimensions();
+		let queue = context.queue().clone();
+		let pixels = image.into_raw();
+		let (texture, future) = if linear {
+			linear_texture_from_rgba(queue, [width, height], &pixels)
+		} else {
+			texture_from_rgba(queue, [width, height], &pixels)
+		};
+		context.upload(future);
+		Ok(texture)
+	}
+}
+
+impl Asset for Texture {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		context.load_blocking()
+	}
+}
+
+/// `.spv` files from `glslc` or `glslangValidator`.
+impl AsyncAsset for Shader {
+	fn load_async(context: &mut AsyncContext) -> Result<Self, AssetError> {
+		let bytes = context.read()?;
+		// spir-v is a stream of little endian words starting with a magic number
+		if bytes.len() % 4 != 0 || !bytes.starts_with(&[0x03, 0x02, 0x23, 0x07]) {
+			return Err(AssetError::Format("not a spir-v module"));
+		}
+		Ok(unsafe { ShaderModule::new(context.device().clone(), &bytes) }.unwrap())
+	}
+}
+
+impl Asset for Shader {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		context.load_blocking()
+	}
+}
@@ -0,0 +1,201 @@
+use super::{Asset, AssetError, AsyncAsset, AsyncContext, LoadContext};
+
+use crate::render2d::Texture;
+
+use vulkano::command_buffer::{AutoCommandBuffer, CommandBufferExecFuture};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{AcceptsPixels, Format};
+use vulkano::half::f16;
+use vulkano::image::view::ImageView;
+use vulkano::image::{
+	ImageCreateFlags, ImageDimensions, ImageUsage, ImmutableImage, MipmapsCount, StorageImage,
+};
+use vulkano::sync::NowFuture;
+
+use std::sync::Arc;
+
+/// A volume texture, sampled with `sampler3D` and normalized coordinates. For
+/// noise volumes, color luts and voxel lighting.
+///
+/// Loaded from `.cube` color lookup tables as half floats, red varies fastest
+/// along x like the file rows. Volumes written by compute passes start from
+/// `storage` instead:
+///
+/// ```glsl
+/// layout(set = 0, binding = 0, rgba16f) uniform writeonly image3D volume;
+///
+/// imageStore(volume, ivec3(gl_GlobalInvocationID), value);
+/// ```
+#[derive(Clone)]
+pub struct Texture3D {
+	pub texture: Texture,
+	dimensions: [u32; 3],
+	format: Format,
+}
+
+impl Texture3D {
+	/// Uploads tightly packed rgba8 voxels, x fastest then y then z.
+	pub fn from_rgba8(
+		queue: Arc<Queue>,
+		dimensions: [u32; 3],
+		voxels: &[u8],
+		srgb: bool,
+	) -> (Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>) {
+		assert_eq!(
+			voxels.len(),
+			(dimensions[0] * dimensions[1] * dimensions[2] * 4) as usize,
+			"voxel data doesn't match the texture size"
+		);
+		let format = if srgb {
+			Format::R8G8B8A8Srgb
+		} else {
+			Format::R8G8B8A8Unorm
+		};
+		Self::upload(queue, dimensions, voxels.iter().cloned(), format)
+	}
+
+	/// Uploads rgba float voxels as half floats, x fastest then y then z.
+	pub fn from_rgba_f32(
+		queue: Arc<Queue>,
+		dimensions: [u32; 3],
+		voxels: &[f32],
+	) -> (Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>) {
+		assert_eq!(
+			voxels.len(),
+			(dimensions[0] * dimensions[1] * dimensions[2] * 4) as usize,
+			"voxel data doesn't match the texture size"
+		);
+		Self::upload(
+			queue,
+			dimensions,
+			voxels.iter().map(|&value| f16::from_f32(value)),
+			Format::R16G16B16A16Sfloat,
+		)
+	}
+
+	/// An uninitialized volume compute passes can write to as an `image3D`
+	/// and shaders sample afterwards. Bind it with `Binding::StorageImage3d`
+	/// for a `ComputeKernel`.
+	pub fn storage(device: Arc<Device>, dimensions: [u32; 3], format: Format) -> Self {
+		let image = StorageImage::with_usage(
+			device.clone(),
+			ImageDimensions::Dim3d {
+				width: dimensions[0],
+				height: dimensions[1],
+				depth: dimensions[2],
+			},
+			format,
+			ImageUsage {
+				storage: true,
+				sampled: true,
+				transfer_destination: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			device.active_queue_families(),
+		)
+		.unwrap();
+
+		Texture3D {
+			texture: ImageView::new(image).unwrap(),
+			dimensions,
+			format,
+		}
+	}
+
+	pub fn dimensions(&self) -> [u32; 3] {
+		self.dimensions
+	}
+
+	pub fn format(&self) -> Format {
+		self.format
+	}
+
+	fn upload<P, I>(
+		queue: Arc<Queue>,
+		dimensions: [u32; 3],
+		voxels: I,
+		format: Format,
+	) -> (Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>)
+	where
+		P: Send + Sync + Clone + 'static,
+		I: ExactSizeIterator<Item = P>,
+		Format: AcceptsPixels<P>,
+	{
+		let (image, future) = ImmutableImage::from_iter(
+			voxels,
+			ImageDimensions::Dim3d {
+				width: dimensions[0],
+				height: dimensions[1],
+				depth: dimensions[2],
+			},
+			MipmapsCount::One,
+			format,
+			queue,
+		)
+		.unwrap();
+
+		let texture = Texture3D {
+			texture: ImageView::new(image).unwrap(),
+			dimensions,
+			format,
+		};
+		(texture, future)
+	}
+}
+
+/// Adobe `.cube` 3d luts. The domain is assumed to be 0 to 1.
+impl AsyncAsset for Texture3D {
+	fn load_async(context: &mut AsyncContext) -> Result<Self, AssetError> {
+		let source = String::from_utf8(context.read()?)
+			.map_err(|_| AssetError::Format("cube lut isn't utf-8"))?;
+
+		let mut size = None;
+		let mut voxels = Vec::new();
+		for line in source.lines().map(str::trim) {
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let mut words = line.split_whitespace();
+			match words.next() {
+				Some("LUT_3D_SIZE") => {
+					let n = words
+						.next()
+						.and_then(|n| n.parse::<u32>().ok())
+						.filter(|&n| n >= 2)
+						.ok_or(AssetError::Format("invalid cube lut size"))?;
+					size = Some(n);
+					voxels.reserve((n * n * n * 4) as usize);
+				}
+				Some("LUT_1D_SIZE") => {
+					return Err(AssetError::Format("1d cube luts aren't supported"));
+				}
+				Some("TITLE") | Some("DOMAIN_MIN") | Some("DOMAIN_MAX") => {}
+				Some(_) => {
+					let mut rgb = [0.0; 3];
+					for (channel, word) in rgb.iter_mut().zip(line.split_whitespace()) {
+						*channel = word
+							.parse()
+							.map_err(|_| AssetError::Format("invalid cube lut entry"))?;
+					}
+					voxels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 1.0]);
+				}
+				None => {}
+			}
+		}
+
+		let n = size.ok_or(AssetError::Format("cube lut has no LUT_3D_SIZE"))?;
+		if voxels.len() != (n * n * n * 4) as usize {
+			return Err(AssetError::Format("cube lut entries don't match its size"));
+		}
+		let (texture, future) = Texture3D::from_rgba_f32(context.queue().clone(), [n; 3], &voxels);
+		context.upload(future);
+		Ok(texture)
+	}
+}
+
+impl Asset for Texture3D {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		context.load_blocking()
+	}
+}
@@ -0,0 +1,145 @@
+use super::{Asset, AssetError, AsyncAsset, AsyncContext, LoadContext};
+
+use crate::render2d::Texture;
+
+use vulkano::command_buffer::{AutoCommandBuffer, CommandBufferExecFuture};
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::sync::NowFuture;
+
+use std::sync::Arc;
+
+/// Same size images in one `sampler2DArray`, picked by layer index in the
+/// shader. For terrain splats, decals and other sets of textures that would
+/// otherwise need a bind per texture:
+///
+/// ```glsl
+/// layout(set = 1, binding = 0) uniform sampler2DArray layers;
+///
+/// vec4 color = texture(layers, vec3(uv, layer));
+/// ```
+///
+/// Loaded from `.toml` files listing the images, paths are relative to the
+/// file. Color is srgb unless `linear` is set:
+///
+/// ```toml
+/// layers = ["grass.png", "rock.png", "snow.png"]
+/// linear = false
+/// ```
+#[derive(Clone)]
+pub struct Texture2DArray {
+	pub texture: Texture,
+	dimensions: [u32; 2],
+	layers: u32,
+}
+
+impl Texture2DArray {
+	/// Uploads tightly packed rgba8 pixels of each layer, all `dimensions`
+	/// in size, with mipmaps. The future has to be joined with the frame that
+	/// first samples the texture.
+	pub fn from_layers(
+		queue: Arc<Queue>,
+		dimensions: [u32; 2],
+		layers: &[&[u8]],
+		srgb: bool,
+	) -> (Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>) {
+		assert!(!layers.is_empty(), "texture array needs at least one layer");
+		let layer_size = (dimensions[0] * dimensions[1] * 4) as usize;
+		for (index, layer) in layers.iter().enumerate() {
+			assert_eq!(
+				layer.len(),
+				layer_size,
+				"layer {} doesn't match the texture array size",
+				index
+			);
+		}
+
+		let format = if srgb {
+			Format::R8G8B8A8Srgb
+		} else {
+			Format::R8G8B8A8Unorm
+		};
+		let pixels: Vec<u8> = layers.concat();
+		let (image, future) = ImmutableImage::from_iter(
+			pixels.into_iter(),
+			ImageDimensions::Dim2d {
+				width: dimensions[0],
+				height: dimensions[1],
+				array_layers: layers.len() as u32,
+			},
+			MipmapsCount::Log2,
+			format,
+			queue,
+		)
+		.unwrap();
+
+		let texture = Texture2DArray {
+			texture: ImageView::with_type(image, ImageViewType::Dim2dArray).unwrap(),
+			dimensions,
+			layers: layers.len() as u32,
+		};
+		(texture, future)
+	}
+
+	/// Size of each layer in pixels.
+	pub fn dimensions(&self) -> [u32; 2] {
+		self.dimensions
+	}
+
+	pub fn layers(&self) -> u32 {
+		self.layers
+	}
+}
+
+impl AsyncAsset for Texture2DArray {
+	fn load_async(context: &mut AsyncContext) -> Result<Self, AssetError> {
+		let source = String::from_utf8(context.read()?)
+			.map_err(|_| AssetError::Format("texture array isn't utf-8"))?;
+		let root: toml::Value = source.parse()?;
+		let paths = root
+			.get("layers")
+			.and_then(|layers| layers.as_array())
+			.filter(|layers| !layers.is_empty())
+			.ok_or(AssetError::Format("texture array needs a list of layers"))?;
+		let linear = match root.get("linear") {
+			Some(value) => value
+				.as_bool()
+				.ok_or(AssetError::Format("texture array linear should be a bool"))?,
+			None => false,
+		};
+
+		let directory = context.file().parent().unwrap().to_path_buf();
+		let mut dimensions = None;
+		let mut layers = Vec::with_capacity(paths.len());
+		for path in paths {
+			let path = path
+				.as_str()
+				.ok_or(AssetError::Format("texture array layer should be a path"))?;
+			let image =
+				image::load_from_memory(&std::fs::read(directory.join(path))?)?.into_rgba8();
+			let size = [image.dimensions().0, image.dimensions().1];
+			if *dimensions.get_or_insert(size) != size {
+				return Err(AssetError::Format("texture array layers differ in size"));
+			}
+			layers.push(image.into_raw());
+		}
+
+		let layers: Vec<&[u8]> = layers.iter().map(|layer| layer.as_slice()).collect();
+		let (texture, future) = Texture2DArray::from_layers(
+			context.queue().clone(),
+			dimensions.unwrap(),
+			&layers,
+			!linear,
+		);
+		context.upload(future);
+		Ok(texture)
+	}
+}
+
+impl Asset for Texture2DArray {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		context.load_blocking()
+	}
+}
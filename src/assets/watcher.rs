@@ -0,0 +1,68 @@
+// polls the modification time of loaded files on a background thread, no
+// platform file notification apis needed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+type Files = Arc<Mutex<HashMap<PathBuf, Option<SystemTime>>>>;
+
+pub(super) struct Watcher {
+	files: Files,
+	changed: Receiver<PathBuf>,
+}
+
+impl Watcher {
+	/// Checks every watched file each `interval`. The thread stops when the
+	/// watcher is dropped.
+	pub fn new(interval: Duration) -> Self {
+		let files = Files::default();
+		let (sender, changed) = mpsc::channel();
+
+		let weak = Arc::downgrade(&files);
+		thread::Builder::new()
+			.name("asset watcher".to_string())
+			.spawn(move || loop {
+				thread::sleep(interval);
+				let files = match weak.upgrade() {
+					Some(files) => files,
+					None => return,
+				};
+				for (file, modified) in files.lock().unwrap().iter_mut() {
+					let now = modified_time(file);
+					// deleting a file doesn't unload it, editors often delete
+					// and recreate on save
+					if now != *modified && now.is_some() && sender.send(file.clone()).is_err() {
+						return;
+					}
+					*modified = now;
+				}
+			})
+			.unwrap();
+
+		Watcher { files, changed }
+	}
+
+	pub fn watch(&self, file: &Path) {
+		self.files
+			.lock()
+			.unwrap()
+			.entry(file.to_path_buf())
+			.or_insert_with(|| modified_time(file));
+	}
+
+	/// Files modified since the last call.
+	pub fn changed(&self) -> Vec<PathBuf> {
+		let mut changed: Vec<PathBuf> = self.changed.try_iter().collect();
+		changed.sort();
+		changed.dedup();
+		changed
+	}
+}
+
+fn modified_time(file: &Path) -> Option<SystemTime> {
+	std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,164 @@
+// compute passes on a queue of their own, so particles, culling or the
+// exposure histogram run while the graphics queue draws shadows and the
+// g-buffer. built on `sync::Submissions`: graphics work only waits on the
+// compute queue's semaphore where it uses what the passes wrote.
+//
+// without a dedicated compute family the passes go to the graphics queue,
+// ordered before the graphics work, which is correct just not overlapped.
+
+use crate::sync::{Access, ResourceId, Submissions};
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::device::{Device, Queue};
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+/// Identifies an added compute pass, to remove it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ComputePassId(u64);
+
+type ComputeCallback = Box<dyn FnMut(&mut AutoCommandBufferBuilder)>;
+
+struct ComputePass {
+	id: ComputePassId,
+	accesses: Vec<(ResourceId, Access)>,
+	enabled: bool,
+	callback: ComputeCallback,
+}
+
+/// Compute passes submitted every frame on the async compute queue, and the
+/// graphics work that overlaps them:
+///
+/// ```text
+/// compute.dispatch();
+/// compute.submit_graphics(shadows_and_gbuffer, &[(ResourceId::image(&shadow_map), Access::DepthAttachment)]);
+/// // the rest of the frame, `Renderer::end_frame` waits for the passes
+/// ```
+///
+/// Resources shared with graphics have to be created for both queue
+/// families, eg. with `device.active_queue_families()`. Buffers the passes
+/// write are still read by the previous frame's graphics work until it
+/// finishes, give them a copy per frame in flight.
+pub struct AsyncCompute {
+	device: Arc<Device>,
+	graphics: Arc<Queue>,
+	queue: Arc<Queue>,
+	passes: Vec<ComputePass>,
+	next_id: u64,
+	submissions: Submissions,
+	pending: bool,
+}
+
+impl AsyncCompute {
+	/// Starts out on the graphics queue, see `set_queue`.
+	pub fn new(device: Arc<Device>, graphics: Arc<Queue>) -> Self {
+		AsyncCompute {
+			submissions: Submissions::new(device.clone()),
+			device,
+			queue: graphics.clone(),
+			graphics,
+			passes: Vec::new(),
+			next_id: 0,
+			pending: false,
+		}
+	}
+
+	/// Moves the passes to `queue`, which has to support compute.
+	pub fn set_queue(&mut self, queue: Arc<Queue>) {
+		assert!(queue.family().supports_compute());
+		self.queue = queue;
+	}
+
+	pub fn queue(&self) -> &Arc<Queue> {
+		&self.queue
+	}
+
+	/// Whether the passes run on a queue of their own, overlapping graphics.
+	pub fn is_async(&self) -> bool {
+		!self.queue.is_same(&self.graphics)
+	}
+
+	/// Adds a pass recorded by every `dispatch`. `accesses` are the
+	/// resources it reads and writes, what graphics work using them waits
+	/// for.
+	pub fn add_pass<F>(&mut self, accesses: &[(ResourceId, Access)], callback: F) -> ComputePassId
+	where
+		F: FnMut(&mut AutoCommandBufferBuilder) + 'static,
+	{
+		let id = ComputePassId(self.next_id);
+		self.next_id += 1;
+		self.passes.push(ComputePass {
+			id,
+			accesses: accesses.to_vec(),
+			enabled: true,
+			callback: Box::new(callback),
+		});
+		id
+	}
+
+	pub fn remove_pass(&mut self, id: ComputePassId) -> bool {
+		let count = self.passes.len();
+		self.passes.retain(|pass| pass.id != id);
+		self.passes.len() != count
+	}
+
+	pub fn set_enabled(&mut self, id: ComputePassId, enabled: bool) {
+		if let Some(pass) = self.passes.iter_mut().find(|pass| pass.id == id) {
+			pass.enabled = enabled;
+		}
+	}
+
+	/// Records the enabled passes into one command buffer and submits it on
+	/// the compute queue. Call early in the frame, before the graphics work
+	/// it should overlap.
+	pub fn dispatch(&mut self) {
+		let mut passes = self
+			.passes
+			.iter_mut()
+			.filter(|pass| pass.enabled)
+			.peekable();
+		if passes.peek().is_none() {
+			return;
+		}
+		let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+			self.device.clone(),
+			self.queue.family(),
+		)
+		.unwrap();
+		let mut accesses = Vec::new();
+		for pass in passes {
+			(pass.callback)(&mut builder);
+			accesses.extend_from_slice(&pass.accesses);
+		}
+		let command_buffer = builder.build().unwrap();
+		self.submissions
+			.submit(&self.queue, command_buffer, &accesses);
+		self.pending = true;
+	}
+
+	/// Queues graphics work ahead of the frame's last command buffer, eg.
+	/// shadows and the g-buffer. It only waits for the passes if `accesses`
+	/// includes what they write. Like the passes it reaches the gpu when the
+	/// frame is flushed.
+	pub fn submit_graphics<C>(&mut self, command_buffer: C, accesses: &[(ResourceId, Access)])
+	where
+		C: CommandBuffer + Send + Sync + 'static,
+	{
+		self.submissions
+			.submit(&self.graphics, command_buffer, accesses);
+		self.pending = true;
+	}
+
+	/// Whether anything was submitted since the last `finish`.
+	pub fn has_pending(&self) -> bool {
+		self.pending
+	}
+
+	/// Everything submitted this frame, for the frame's last submission to
+	/// wait on. `Renderer::end_frame` does this by itself.
+	pub fn finish(&mut self) -> Box<dyn GpuFuture> {
+		self.pending = false;
+		self.submissions.finish()
+	}
+}
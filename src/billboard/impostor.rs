@@ -0,0 +1,622 @@
+// octahedral impostors: a mesh rendered from a grid of directions laid out
+// on an octahedron into one atlas. far away the mesh is replaced by a quad
+// showing the frames baked closest to the current view direction.
+
+use crate::assets::{Mesh, MeshVertex};
+use crate::foliage::FoliageInstance;
+use crate::math::{cross, dot, length, normalize, sub, Mat4, Vec2, Vec3};
+use crate::particles::render::{BillboardCamera, Corner};
+use crate::render2d::Texture;
+
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use std::sync::Arc;
+
+const ALBEDO_FORMAT: Format = Format::R8G8B8A8Srgb;
+const NORMAL_FORMAT: Format = Format::R8G8B8A8Unorm;
+const DEPTH_FORMAT: Format = Format::D16Unorm;
+
+mod bake_vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec3 normal;
+			layout(location = 2) in vec4 tangent;
+			layout(location = 3) in vec2 uv;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec3 v_normal;
+
+			void main() {
+				gl_Position = push_constants.view_projection * vec4(position, 1.0);
+				v_uv = uv;
+				v_normal = normal;
+			}
+		"
+	}
+}
+
+mod bake_fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec3 v_normal;
+
+			layout(set = 0, binding = 0) uniform sampler2D albedo;
+
+			layout(location = 0) out vec4 f_albedo;
+			layout(location = 1) out vec4 f_normal;
+
+			void main() {
+				vec4 color = texture(albedo, v_uv);
+				if (color.a < 0.5) {
+					discard;
+				}
+				f_albedo = vec4(color.rgb, 1.0);
+				// object space, the impostor turns them with the instance
+				f_normal = vec4(normalize(v_normal) * 0.5 + 0.5, 1.0);
+			}
+		"
+	}
+}
+
+/// Per impostor data for the instanced draw.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ImpostorInstance {
+	pub position_scale: [f32; 4],
+	pub yaw: f32,
+}
+vulkano::impl_vertex!(ImpostorInstance, position_scale, yaw);
+
+mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 corner;
+			layout(location = 1) in vec4 position_scale;
+			layout(location = 2) in float yaw;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				// xyz eye, w frames along each side
+				vec4 eye;
+				// xyz center of the mesh, w bounding radius
+				vec4 bounds;
+				// 1 for a hemisphere
+				vec4 layout_;
+				// towards the sun
+				vec4 light;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) flat out vec2 v_frame;
+			layout(location = 2) flat out vec2 v_weights;
+			layout(location = 3) flat out vec2 v_yaw;
+
+			vec2 sign_not_zero(vec2 v) {
+				return vec2(v.x >= 0.0 ? 1.0 : -1.0, v.y >= 0.0 ? 1.0 : -1.0);
+			}
+
+			// direction to -1..1 on the atlas, the inverse of `frame_direction`
+			// in impostor.rs
+			vec2 encode(vec3 d) {
+				if (push_constants.layout_.x > 0.5) {
+					d.y = max(d.y, 0.0);
+					vec3 p = d / (abs(d.x) + abs(d.y) + abs(d.z));
+					return vec2(p.x + p.z, p.x - p.z);
+				}
+				vec2 p = d.xz / (abs(d.x) + abs(d.y) + abs(d.z));
+				if (d.y < 0.0) {
+					p = (1.0 - abs(p.yx)) * sign_not_zero(p);
+				}
+				return p;
+			}
+
+			void main() {
+				float scale = position_scale.w;
+				float c = cos(yaw);
+				float s = sin(yaw);
+				// object to world and back around y
+				mat3 rotation = mat3(c, 0.0, -s, 0.0, 1.0, 0.0, s, 0.0, c);
+				vec3 center = position_scale.xyz + rotation * push_constants.bounds.xyz * scale;
+
+				// the quad faces the eye with the same basis the frames were baked with
+				vec3 to_eye = normalize(transpose(rotation) * (push_constants.eye.xyz - center));
+				vec3 reference = abs(to_eye.y) > 0.99 ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 1.0, 0.0);
+				vec3 right = normalize(cross(-to_eye, reference));
+				vec3 up = cross(right, -to_eye);
+				vec3 offset = right * corner.x + up * corner.y;
+				vec3 world = center + rotation * offset * 2.0 * push_constants.bounds.w * scale;
+				gl_Position = push_constants.view_projection * vec4(world, 1.0);
+
+				float frames = push_constants.eye.w;
+				vec2 grid = (encode(to_eye) * 0.5 + 0.5) * frames - 0.5;
+				v_frame = floor(grid);
+				v_weights = grid - v_frame;
+				v_uv = vec2(corner.x + 0.5, 0.5 - corner.y);
+				v_yaw = vec2(c, s);
+			}
+		"
+	}
+}
+
+mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) flat in vec2 v_frame;
+			layout(location = 2) flat in vec2 v_weights;
+			layout(location = 3) flat in vec2 v_yaw;
+
+			layout(set = 0, binding = 0) uniform sampler2D albedo;
+			layout(set = 0, binding = 1) uniform sampler2D normals;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 eye;
+				vec4 bounds;
+				vec4 layout_;
+				vec4 light;
+			} push_constants;
+
+			layout(location = 0) out vec4 f_color;
+
+			vec2 atlas_uv(vec2 offset) {
+				float frames = push_constants.eye.w;
+				vec2 frame = clamp(v_frame + offset, vec2(0.0), vec2(frames - 1.0));
+				return (frame + v_uv) / frames;
+			}
+
+			void main() {
+				// blend the four frames around the view direction
+				vec2 offsets[4] = vec2[](vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0), vec2(1.0, 1.0));
+				float weights[4] = float[](
+					(1.0 - v_weights.x) * (1.0 - v_weights.y),
+					v_weights.x * (1.0 - v_weights.y),
+					(1.0 - v_weights.x) * v_weights.y,
+					v_weights.x * v_weights.y
+				);
+				vec4 color = vec4(0.0);
+				vec3 normal = vec3(0.0);
+				for (int i = 0; i < 4; i++) {
+					vec2 uv = atlas_uv(offsets[i]);
+					color += texture(albedo, uv) * weights[i];
+					normal += (texture(normals, uv).xyz * 2.0 - 1.0) * weights[i];
+				}
+				if (color.a < 0.5) {
+					discard;
+				}
+
+				float c = v_yaw.x;
+				float s = v_yaw.y;
+				normal = normalize(mat3(c, 0.0, -s, 0.0, 1.0, 0.0, s, 0.0, c) * normal);
+				float diffuse = max(dot(normal, push_constants.light.xyz), 0.0);
+				f_color = vec4(color.rgb / color.a * (0.3 + 0.7 * diffuse), 1.0);
+			}
+		"
+	}
+}
+
+/// How an impostor is baked.
+#[derive(Debug, Clone, Copy)]
+pub struct ImpostorDesc {
+	/// Views along each side of the atlas, `frames * frames` in total.
+	pub frames: u32,
+	/// Size of each view in pixels.
+	pub frame_resolution: u32,
+	/// Only bake views from above the horizon, for things never seen from
+	/// below like trees. Doubles the views where they matter.
+	pub hemisphere: bool,
+}
+
+impl Default for ImpostorDesc {
+	fn default() -> Self {
+		ImpostorDesc {
+			frames: 8,
+			frame_resolution: 128,
+			hemisphere: true,
+		}
+	}
+}
+
+/// A mesh baked from many directions, drawn with `ImpostorRenderer`.
+#[derive(Clone)]
+pub struct Impostor {
+	/// Srgb color, alpha is coverage.
+	pub albedo: Texture,
+	/// Object space normals packed as `n * 0.5 + 0.5`.
+	pub normals: Texture,
+	pub desc: ImpostorDesc,
+	/// Center and radius of the sphere around the mesh.
+	pub center: Vec3,
+	pub radius: f32,
+}
+
+/// Renders meshes into impostor atlases.
+pub struct ImpostorBaker {
+	device: Arc<Device>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	sampler: Arc<Sampler>,
+}
+
+impl ImpostorBaker {
+	pub fn new(device: Arc<Device>) -> Self {
+		let render_pass = Arc::new(
+			vulkano::single_pass_renderpass!(
+				device.clone(),
+				attachments: {
+					albedo: {
+						load: Clear,
+						store: Store,
+						format: ALBEDO_FORMAT,
+						samples: 1,
+					},
+					normal: {
+						load: Clear,
+						store: Store,
+						format: NORMAL_FORMAT,
+						samples: 1,
+					},
+					depth: {
+						load: Clear,
+						store: DontCare,
+						format: DEPTH_FORMAT,
+						samples: 1,
+					}
+				},
+				pass: {
+					color: [albedo, normal],
+					depth_stencil: {depth}
+				}
+			)
+			.unwrap(),
+		) as Arc<dyn RenderPassAbstract + Send + Sync>;
+
+		let vs = bake_vs::Shader::load(device.clone()).unwrap();
+		let fs = bake_fs::Shader::load(device.clone()).unwrap();
+		let pipeline = Arc::new(
+			GraphicsPipeline::start()
+				.vertex_input_single_buffer::<MeshVertex>()
+				.vertex_shader(vs.main_entry_point(), ())
+				.triangle_list()
+				.viewports_dynamic_scissors_irrelevant(1)
+				.fragment_shader(fs.main_entry_point(), ())
+				.depth_stencil_simple_depth()
+				.render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+				.build(device.clone())
+				.unwrap(),
+		) as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		ImpostorBaker {
+			sampler: Sampler::simple_repeat_linear(device.clone()),
+			device,
+			render_pass,
+			pipeline,
+		}
+	}
+
+	/// Records the views of `mesh` textured with `albedo` into a new atlas.
+	/// Must be recorded outside of a render pass, the impostor can be drawn
+	/// once the command buffer has run.
+	pub fn bake(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		mesh: &Mesh,
+		albedo: Texture,
+		desc: ImpostorDesc,
+	) -> Impostor {
+		assert!(desc.frames >= 2, "impostor needs at least 2x2 frames");
+		let (center, radius) = bounding_sphere(&mesh.data.positions);
+		let size = desc.frames * desc.frame_resolution;
+
+		let attachment = |format| {
+			AttachmentImage::with_usage(
+				self.device.clone(),
+				[size, size],
+				format,
+				ImageUsage {
+					sampled: true,
+					..ImageUsage::none()
+				},
+			)
+			.unwrap()
+		};
+		let albedo_atlas = ImageView::new(attachment(ALBEDO_FORMAT)).unwrap();
+		let normal_atlas = ImageView::new(attachment(NORMAL_FORMAT)).unwrap();
+		let depth = ImageView::new(
+			AttachmentImage::transient(self.device.clone(), [size, size], DEPTH_FORMAT).unwrap(),
+		)
+		.unwrap();
+		let framebuffer = Arc::new(
+			Framebuffer::start(self.render_pass.clone())
+				.add(albedo_atlas.clone())
+				.unwrap()
+				.add(normal_atlas.clone())
+				.unwrap()
+				.add(depth)
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_sampled_image(albedo, self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		// the views don't overlap, so one clear of the whole atlas does
+		builder
+			.begin_render_pass(
+				framebuffer,
+				SubpassContents::Inline,
+				vec![
+					[0.0, 0.0, 0.0, 0.0].into(),
+					[0.5, 0.5, 1.0, 0.0].into(),
+					1f32.into(),
+				],
+			)
+			.unwrap();
+		let resolution = desc.frame_resolution as f32;
+		for y in 0..desc.frames {
+			for x in 0..desc.frames {
+				let dynamic_state = DynamicState {
+					viewports: Some(vec![Viewport {
+						origin: [x as f32 * resolution, y as f32 * resolution],
+						dimensions: [resolution, resolution],
+						depth_range: 0.0..1.0,
+					}]),
+					..DynamicState::none()
+				};
+				let uv = [
+					(x as f32 + 0.5) / desc.frames as f32 * 2.0 - 1.0,
+					(y as f32 + 0.5) / desc.frames as f32 * 2.0 - 1.0,
+				];
+				let direction = frame_direction(uv, desc.hemisphere);
+				builder
+					.draw_indexed(
+						self.pipeline.clone(),
+						&dynamic_state,
+						vec![mesh.vertices.clone()],
+						mesh.indices.clone(),
+						set.clone(),
+						bake_vs::ty::PushConstants {
+							view_projection: frame_view_projection(direction, center, radius),
+						},
+						vec![],
+					)
+					.unwrap();
+			}
+		}
+		builder.end_render_pass().unwrap();
+
+		Impostor {
+			albedo: albedo_atlas,
+			normals: normal_atlas,
+			desc,
+			center,
+			radius,
+		}
+	}
+}
+
+/// Draws impostors in place of far away meshes, eg. the last lod of scattered
+/// trees. Alpha tested like the foliage it stands in for.
+pub struct ImpostorRenderer {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	quad: Arc<CpuAccessibleBuffer<[Corner]>>,
+	instances: CpuBufferPool<ImpostorInstance>,
+	sampler: Arc<Sampler>,
+	/// Direction the sun light comes from.
+	pub sun: Vec3,
+}
+
+impl ImpostorRenderer {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input(OneVertexOneInstanceDefinition::<Corner, ImpostorInstance>::new())
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_strip()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ());
+		if has_depth {
+			pipeline = pipeline.depth_stencil_simple_depth();
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		let quad = CpuAccessibleBuffer::from_iter(
+			device.clone(),
+			BufferUsage::vertex_buffer(),
+			false,
+			[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]]
+				.iter()
+				.map(|&corner| Corner { corner }),
+		)
+		.unwrap();
+
+		// frames next to each other hold different views, don't filter across them
+		let sampler = Sampler::new(
+			device.clone(),
+			Filter::Linear,
+			Filter::Linear,
+			MipmapMode::Nearest,
+			SamplerAddressMode::ClampToEdge,
+			SamplerAddressMode::ClampToEdge,
+			SamplerAddressMode::ClampToEdge,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		)
+		.unwrap();
+
+		ImpostorRenderer {
+			pipeline,
+			quad,
+			instances: CpuBufferPool::vertex_buffer(device),
+			sampler,
+			sun: normalize([0.4, 1.0, 0.3]),
+		}
+	}
+
+	/// Records one instanced draw of `impostor` at each of `instances`. Must be
+	/// called inside the subpass the renderer was created for.
+	pub fn draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		camera: &BillboardCamera,
+		impostor: &Impostor,
+		instances: &[FoliageInstance],
+	) {
+		if instances.is_empty() {
+			return;
+		}
+		let instances = self
+			.instances
+			.chunk(instances.iter().map(|instance| {
+				let [x, y, z] = instance.position;
+				ImpostorInstance {
+					position_scale: [x, y, z, instance.scale],
+					yaw: instance.yaw,
+				}
+			}))
+			.unwrap();
+
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_sampled_image(impostor.albedo.clone(), self.sampler.clone())
+				.unwrap()
+				.add_sampled_image(impostor.normals.clone(), self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let [cx, cy, cz] = impostor.center;
+		let sun = normalize(self.sun);
+		let push_constants = vs::ty::PushConstants {
+			view_projection: camera.view_projection,
+			eye: [
+				camera.eye[0],
+				camera.eye[1],
+				camera.eye[2],
+				impostor.desc.frames as f32,
+			],
+			bounds: [cx, cy, cz, impostor.radius],
+			layout_: [impostor.desc.hemisphere as u32 as f32, 0.0, 0.0, 0.0],
+			light: [sun[0], sun[1], sun[2], 0.0],
+		};
+		builder
+			.draw(
+				self.pipeline.clone(),
+				dynamic_state,
+				vec![
+					self.quad.clone() as Arc<dyn BufferAccess + Send + Sync>,
+					Arc::new(instances),
+				],
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
+
+// the direction towards the camera of the frame at `uv`, -1 to 1 across the
+// atlas
+fn frame_direction(uv: Vec2, hemisphere: bool) -> Vec3 {
+	let [u, v] = uv;
+	if hemisphere {
+		let (x, z) = ((u + v) * 0.5, (u - v) * 0.5);
+		return normalize([x, 1.0 - x.abs() - z.abs(), z]);
+	}
+	let y = 1.0 - u.abs() - v.abs();
+	if y >= 0.0 {
+		normalize([u, y, v])
+	} else {
+		let sign = |n: f32| if n >= 0.0 { 1.0 } else { -1.0 };
+		normalize([(1.0 - v.abs()) * sign(u), y, (1.0 - u.abs()) * sign(v)])
+	}
+}
+
+// orthographic view of the bounding sphere looking back along `direction`,
+// with the same basis the impostor's quad is built with
+fn frame_view_projection(direction: Vec3, center: Vec3, radius: f32) -> Mat4 {
+	let forward = [-direction[0], -direction[1], -direction[2]];
+	let reference = if direction[1].abs() > 0.99 {
+		[0.0, 0.0, 1.0]
+	} else {
+		[0.0, 1.0, 0.0]
+	};
+	let right = normalize(cross(forward, reference));
+	let up = cross(right, forward);
+
+	// x and y span the sphere, depth goes from its near side to its far side
+	let (r, d) = (radius.max(f32::EPSILON), direction);
+	[
+		[right[0] / r, -up[0] / r, -d[0] / (2.0 * r), 0.0],
+		[right[1] / r, -up[1] / r, -d[1] / (2.0 * r), 0.0],
+		[right[2] / r, -up[2] / r, -d[2] / (2.0 * r), 0.0],
+		[
+			-dot(center, right) / r,
+			dot(center, up) / r,
+			(r + dot(center, d)) / (2.0 * r),
+			1.0,
+		],
+	]
+}
+
+fn bounding_sphere(positions: &[Vec3]) -> (Vec3, f32) {
+	let mut min = [f32::MAX; 3];
+	let mut max = [f32::MIN; 3];
+	for p in positions {
+		for axis in 0..3 {
+			min[axis] = min[axis].min(p[axis]);
+			max[axis] = max[axis].max(p[axis]);
+		}
+	}
+	let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) * 0.5);
+	let radius = positions
+		.iter()
+		.map(|p| length(sub(*p, center)))
+		.fold(0.0, f32::max);
+	(center, radius)
+}
@@ -0,0 +1,246 @@
+// quads that turn towards the camera, the billboards of a scene and
+// impostors baked from meshes for things too far away to draw in full.
+
+pub mod impostor;
+
+use crate::assets::{Assets, Handle};
+use crate::math::{length, normalize};
+use crate::particles::render::{BillboardCamera, Corner};
+use crate::render2d::Texture;
+use crate::scene::{BillboardMode, Scene};
+
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Per billboard data for the instanced draw.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct BillboardInstance {
+	pub center: [f32; 3],
+	pub size: [f32; 2],
+	/// Axis an axis locked billboard turns around.
+	pub axis: [f32; 3],
+	pub color: [f32; 4],
+	/// 1 for `BillboardMode::Axis`.
+	pub locked: f32,
+}
+vulkano::impl_vertex!(BillboardInstance, center, size, axis, color, locked);
+
+mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 corner;
+			layout(location = 1) in vec3 center;
+			layout(location = 2) in vec2 size;
+			layout(location = 3) in vec3 axis;
+			layout(location = 4) in vec4 color;
+			layout(location = 5) in float locked;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 camera_right;
+				vec4 camera_up;
+				vec4 eye;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+
+			void main() {
+				vec3 right = push_constants.camera_right.xyz;
+				vec3 up = push_constants.camera_up.xyz;
+				vec2 offset = corner;
+				if (locked > 0.5) {
+					// turn around the axis to face the eye, standing on the center
+					vec3 side = cross(axis, push_constants.eye.xyz - center);
+					if (dot(side, side) > 1e-8) {
+						right = normalize(side);
+					}
+					up = axis;
+					offset.y += 0.5;
+				}
+
+				vec3 position = center + right * offset.x * size.x + up * offset.y * size.y;
+				gl_Position = push_constants.view_projection * vec4(position, 1.0);
+				v_uv = vec2(corner.x + 0.5, 0.5 - corner.y);
+				v_color = color;
+			}
+		"
+	}
+}
+
+mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+
+			layout(set = 0, binding = 0) uniform sampler2D billboard;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				vec4 color = texture(billboard, v_uv) * v_color;
+				if (color.a < 0.5) {
+					discard;
+				}
+				f_color = vec4(color.rgb, 1.0);
+			}
+		"
+	}
+}
+
+/// Draws the `Billboard`s of a scene, one instanced draw per texture.
+/// Billboards are alpha tested and write depth, so they need no sorting.
+pub struct BillboardRenderer {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	quad: Arc<CpuAccessibleBuffer<[Corner]>>,
+	instances: CpuBufferPool<BillboardInstance>,
+	sampler: Arc<Sampler>,
+}
+
+impl BillboardRenderer {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input(OneVertexOneInstanceDefinition::<Corner, BillboardInstance>::new())
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_strip()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ());
+		if has_depth {
+			pipeline = pipeline.depth_stencil_simple_depth();
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		let quad = CpuAccessibleBuffer::from_iter(
+			device.clone(),
+			BufferUsage::vertex_buffer(),
+			false,
+			[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]]
+				.iter()
+				.map(|&corner| Corner { corner }),
+		)
+		.unwrap();
+
+		BillboardRenderer {
+			pipeline,
+			quad,
+			instances: CpuBufferPool::vertex_buffer(device.clone()),
+			sampler: Sampler::simple_repeat_linear(device),
+		}
+	}
+
+	/// Records the draws of every billboard in `scene`. Must be called inside
+	/// the subpass the renderer was created for.
+	pub fn draw_scene(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		camera: &BillboardCamera,
+		scene: &Scene,
+		assets: &Assets,
+	) {
+		let mut batches: HashMap<Handle<Texture>, Vec<BillboardInstance>> = HashMap::new();
+		for (id, node) in scene.iter() {
+			let billboard = match &node.billboard {
+				Some(billboard) => billboard,
+				None => continue,
+			};
+			let world = scene.world_matrix(id);
+			let column = |i: usize| [world[i][0], world[i][1], world[i][2]];
+			let axis = column(1);
+			batches
+				.entry(billboard.texture.clone())
+				.or_default()
+				.push(BillboardInstance {
+					center: column(3),
+					size: [
+						billboard.size[0] * length(column(0)),
+						billboard.size[1] * length(axis),
+					],
+					axis: normalize(axis),
+					color: billboard.color,
+					locked: match billboard.mode {
+						BillboardMode::Full => 0.0,
+						BillboardMode::Axis => 1.0,
+					},
+				});
+		}
+
+		for (texture, instances) in batches {
+			self.draw(
+				builder,
+				dynamic_state,
+				camera,
+				assets.get(&texture).clone(),
+				instances,
+			);
+		}
+	}
+
+	/// Records one instanced draw of billboards sharing `texture`. Must be
+	/// called inside the subpass the renderer was created for.
+	pub fn draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		camera: &BillboardCamera,
+		texture: Texture,
+		instances: Vec<BillboardInstance>,
+	) {
+		if instances.is_empty() {
+			return;
+		}
+		let instances = self.instances.chunk(instances).unwrap();
+
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_sampled_image(texture, self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let push_constants = vs::ty::PushConstants {
+			view_projection: camera.view_projection,
+			camera_right: [camera.right[0], camera.right[1], camera.right[2], 0.0],
+			camera_up: [camera.up[0], camera.up[1], camera.up[2], 0.0],
+			eye: [camera.eye[0], camera.eye[1], camera.eye[2], 0.0],
+		};
+		builder
+			.draw(
+				self.pipeline.clone(),
+				dynamic_state,
+				vec![
+					self.quad.clone() as Arc<dyn BufferAccess + Send + Sync>,
+					Arc::new(instances),
+				],
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
@@ -0,0 +1,296 @@
+// gpu breadcrumbs, to find out what the gpu was working on when the device
+// was lost, eg. after a shader hung long enough for the driver to reset the
+// gpu. each marker is a `fill_buffer` writing the frame number into a slot of
+// host coherent memory, which the cpu can still read after the loss.
+//
+// vulkano 0.22 exposes neither VK_NV_device_diagnostic_checkpoints,
+// VK_AMD_buffer_marker nor VK_EXT_device_fault, so there is no pipeline stage
+// to write markers at and no fault address. a marker is written when the gpu
+// gets to it, the work before it may still be running, so the culprit is
+// around the first marker that wasn't reached.
+//
+// a lost device usually ends in a panic inside vulkano, waiting on the
+// frame's fence. `report_on_panic` prints the report when that happens.
+
+use vulkano::buffer::sys::{SparseLevel, UnsafeBuffer};
+use vulkano::buffer::{BufferAccess, BufferInner, BufferSlice, BufferUsage, TypedBufferAccess};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::{Device, DeviceOwned, Queue};
+use vulkano::image::ImageAccess;
+use vulkano::memory::{DeviceMemory, MappedDeviceMemory};
+use vulkano::sync::{AccessError, Sharing};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic;
+use std::sync::{Arc, Mutex};
+
+// frames still on the gpu when the cpu records the next one, and the one
+// being recorded
+const FRAMES_KEPT: usize = 3;
+
+/// Markers recorded between passes, and what the gpu got through of them:
+///
+/// ```text
+/// breadcrumbs.begin_frame();
+/// breadcrumbs.mark(&mut builder, "shadows");
+/// // draw shadows
+/// breadcrumbs.mark(&mut builder, "gbuffer");
+/// ```
+///
+/// Markers can't be recorded inside a render pass. `Renderer` keeps one
+/// when `EngineConfig::breadcrumbs` is set, see `Renderer::mark`.
+pub struct Breadcrumbs {
+	shared: Arc<Shared>,
+	capacity: usize,
+	frame: u32,
+	overflowed: bool,
+}
+
+struct Shared {
+	markers: Arc<MarkerBuffer>,
+	// labels of the frames that may still be on the gpu, oldest first
+	frames: Mutex<VecDeque<(u32, Vec<String>)>>,
+}
+
+impl Breadcrumbs {
+	/// Room for `capacity` markers a frame. `None` when the device has no
+	/// host coherent memory for them.
+	pub fn new(device: Arc<Device>, capacity: usize) -> Option<Self> {
+		let markers = MarkerBuffer::new(device, capacity)?;
+		Some(Breadcrumbs {
+			shared: Arc::new(Shared {
+				markers: Arc::new(markers),
+				frames: Mutex::new(VecDeque::new()),
+			}),
+			capacity,
+			frame: 0,
+			overflowed: false,
+		})
+	}
+
+	/// Starts a frame's markers, call before recording them.
+	pub fn begin_frame(&mut self) {
+		// 0 is what the slots start out with
+		self.frame = self.frame.wrapping_add(1).max(1);
+		let mut frames = self.shared.frames.lock().unwrap();
+		if frames.len() == FRAMES_KEPT {
+			frames.pop_front();
+		}
+		frames.push_back((self.frame, Vec::new()));
+	}
+
+	/// Records a marker the report lists as `label`. Only outside of render
+	/// passes, markers past the capacity are dropped.
+	pub fn mark(&mut self, builder: &mut AutoCommandBufferBuilder, label: impl Into<String>) {
+		let mut frames = self.shared.frames.lock().unwrap();
+		let labels = match frames.back_mut() {
+			Some((_, labels)) => labels,
+			None => return,
+		};
+		let slot = labels.len();
+		if slot == self.capacity {
+			if !self.overflowed {
+				log::warn!(
+					"More than {} breadcrumbs in a frame, dropping the rest",
+					self.capacity
+				);
+				self.overflowed = true;
+			}
+			return;
+		}
+		let marker = BufferSlice::from_typed_buffer_access(self.shared.markers.clone())
+			.slice(slot..slot + 1)
+			.unwrap();
+		builder.fill_buffer(marker, self.frame).unwrap();
+		labels.push(label.into());
+	}
+
+	/// How far the gpu got in the frames that may still be on it. Meant for
+	/// after the device is lost, before that the last frames are usually
+	/// just still running.
+	pub fn report(&self) -> BreadcrumbReport {
+		self.shared.report(&self.shared.frames.lock().unwrap())
+	}
+
+	/// Logs `report` when a panic mentions a lost device, on top of
+	/// whatever the panic hook did before. Stays installed after this is
+	/// dropped.
+	pub fn report_on_panic(&self) {
+		let shared = Arc::downgrade(&self.shared);
+		let previous = panic::take_hook();
+		panic::set_hook(Box::new(move |info| {
+			previous(info);
+			let message = info
+				.payload()
+				.downcast_ref::<String>()
+				.map(String::as_str)
+				.or_else(|| info.payload().downcast_ref::<&str>().copied())
+				.unwrap_or("");
+			if !message.contains("DeviceLost") {
+				return;
+			}
+			if let Some(shared) = shared.upgrade() {
+				// the panic may have happened while the labels were locked
+				if let Ok(frames) = shared.frames.try_lock() {
+					log::error!("{}", shared.report(&frames));
+				}
+			}
+		}));
+	}
+}
+
+impl Shared {
+	fn report(&self, frames: &VecDeque<(u32, Vec<String>)>) -> BreadcrumbReport {
+		let values = self.markers.read();
+		let frames = frames
+			.iter()
+			.map(|(frame, labels)| {
+				// frames are submitted in order, so later frames only raise a slot
+				let reached = labels
+					.iter()
+					.zip(&values)
+					.take_while(|(_, value)| **value >= *frame)
+					.count();
+				FrameProgress {
+					frame: *frame,
+					reached: labels[..reached].to_vec(),
+					not_reached: labels[reached..].to_vec(),
+				}
+			})
+			.collect();
+		BreadcrumbReport { frames }
+	}
+}
+
+/// What `Breadcrumbs::report` found, printing it lists every frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreadcrumbReport {
+	/// Oldest first.
+	pub frames: Vec<FrameProgress>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameProgress {
+	pub frame: u32,
+	/// Markers the gpu got to, in recording order.
+	pub reached: Vec<String>,
+	/// The rest, the gpu was stuck before the first one.
+	pub not_reached: Vec<String>,
+}
+
+impl fmt::Display for BreadcrumbReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Last known gpu progress:")?;
+		for progress in &self.frames {
+			write!(f, "  frame {}: ", progress.frame)?;
+			match progress.not_reached.first() {
+				None => writeln!(f, "finished all {} markers", progress.reached.len())?,
+				Some(stuck) if progress.reached.is_empty() => {
+					writeln!(f, "not started, first marker \"{}\"", stuck)?
+				}
+				Some(stuck) => writeln!(
+					f,
+					"stuck between \"{}\" and \"{}\"",
+					progress.reached.last().unwrap(),
+					stuck
+				)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+// a buffer bound to its own mapped memory, which unlike `CpuAccessibleBuffer`
+// can be read while frames using it are in flight
+struct MarkerBuffer {
+	buffer: UnsafeBuffer,
+	memory: MappedDeviceMemory,
+}
+
+impl MarkerBuffer {
+	fn new(device: Arc<Device>, capacity: usize) -> Option<Self> {
+		let size = capacity.max(1) * 4;
+		let usage = BufferUsage {
+			transfer_destination: true,
+			..BufferUsage::none()
+		};
+		let (buffer, requirements) = unsafe {
+			UnsafeBuffer::new(
+				device.clone(),
+				size,
+				usage,
+				Sharing::Exclusive::<std::iter::Empty<u32>>,
+				SparseLevel::none(),
+			)
+		}
+		.ok()?;
+		let memory_type = device
+			.physical_device()
+			.memory_types()
+			.find(|memory_type| {
+				requirements.memory_type_bits & (1 << memory_type.id()) != 0
+					&& memory_type.is_host_visible()
+					&& memory_type.is_host_coherent()
+			})?;
+		let memory =
+			DeviceMemory::alloc_and_map(device.clone(), memory_type, requirements.size).ok()?;
+		unsafe {
+			buffer.bind_memory(memory.as_ref(), 0).ok()?;
+			let mut slots = memory.read_write::<[u32]>(0..size);
+			slots.iter_mut().for_each(|slot| *slot = 0);
+		}
+		Some(MarkerBuffer { buffer, memory })
+	}
+
+	fn read(&self) -> Vec<u32> {
+		// the gpu may still be writing, a slot is either old or new which is
+		// all the report needs
+		unsafe { self.memory.read_write::<[u32]>(0..self.buffer.size()) }.to_vec()
+	}
+}
+
+unsafe impl BufferAccess for MarkerBuffer {
+	fn inner(&self) -> BufferInner<'_> {
+		BufferInner {
+			buffer: &self.buffer,
+			offset: 0,
+		}
+	}
+
+	fn size(&self) -> usize {
+		self.buffer.size()
+	}
+
+	fn conflicts_buffer(&self, other: &dyn BufferAccess) -> bool {
+		self.conflict_key() == other.conflict_key()
+	}
+
+	fn conflicts_image(&self, _: &dyn ImageAccess) -> bool {
+		false
+	}
+
+	fn conflict_key(&self) -> (u64, usize) {
+		(self.buffer.key(), 0)
+	}
+
+	// frames in flight write markers at the same time and the cpu reads them
+	// whenever, nothing to lock
+	fn try_gpu_lock(&self, _: bool, _: &Queue) -> Result<(), AccessError> {
+		Ok(())
+	}
+
+	unsafe fn increase_gpu_lock(&self) {}
+
+	unsafe fn unlock(&self) {}
+}
+
+unsafe impl TypedBufferAccess for MarkerBuffer {
+	type Content = [u32];
+}
+
+unsafe impl DeviceOwned for MarkerBuffer {
+	fn device(&self) -> &Arc<Device> {
+		self.buffer.device()
+	}
+}
@@ -0,0 +1,26 @@
+// optional gpu features that vulkano doesn't wrap yet, detected by extension
+// name so callers can pick a path up front and fall back cleanly.
+
+use vulkano::device::RawDeviceExtensions;
+use vulkano::instance::PhysicalDevice;
+
+/// Device extensions reporting when images actually reached the display and
+/// taking a desired present time, mostly on android.
+pub const DISPLAY_TIMING_EXTENSIONS: &[&str] = &["VK_GOOGLE_display_timing"];
+
+pub fn supports_extension(physical: PhysicalDevice, name: &str) -> bool {
+	supports_all(physical, &[name])
+}
+
+pub fn supports_all(physical: PhysicalDevice, names: &[&str]) -> bool {
+	let supported = RawDeviceExtensions::supported_by_device(physical);
+	names.iter().all(|name| {
+		supported
+			.iter()
+			.any(|extension| extension.to_bytes() == name.as_bytes())
+	})
+}
+
+pub fn supports_display_timing(physical: PhysicalDevice) -> bool {
+	supports_all(physical, DISPLAY_TIMING_EXTENSIONS)
+}
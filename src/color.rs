@@ -0,0 +1,232 @@
+// colors are stored linear, which is what lighting and blending expect.
+// anything picked by eye (hex codes, color pickers, 8 bit values) is srgb
+// and goes through `from_srgb` and friends on the way in.
+
+use crate::math::{Vec3, Vec4};
+
+use vulkano::format::ClearValue;
+
+/// Linear rgb color with straight (not premultiplied) alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Color {
+	pub r: f32,
+	pub g: f32,
+	pub b: f32,
+	pub a: f32,
+}
+
+impl Color {
+	pub const TRANSPARENT: Color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+	pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+	pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+	pub const GREY: Color = Color::rgb(0.214, 0.214, 0.214);
+	pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+	pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+	pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+	pub const YELLOW: Color = Color::rgb(1.0, 1.0, 0.0);
+	pub const CYAN: Color = Color::rgb(0.0, 1.0, 1.0);
+	pub const MAGENTA: Color = Color::rgb(1.0, 0.0, 1.0);
+	pub const ORANGE: Color = Color::rgb(1.0, 0.214, 0.0);
+	pub const CORNFLOWER_BLUE: Color = Color::rgb(0.127, 0.3, 0.847);
+
+	/// Linear color, opaque.
+	pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+		Color { r, g, b, a: 1.0 }
+	}
+
+	/// Linear color.
+	pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+		Color { r, g, b, a }
+	}
+
+	/// Converts srgb components in 0..1. Alpha is linear either way.
+	pub fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+		Color {
+			r: srgb_to_linear(r),
+			g: srgb_to_linear(g),
+			b: srgb_to_linear(b),
+			a,
+		}
+	}
+
+	/// Converts 8 bit srgb components like the ones in an image.
+	pub fn from_srgb8(r: u8, g: u8, b: u8, a: u8) -> Self {
+		Color::from_srgb(
+			r as f32 / 255.0,
+			g as f32 / 255.0,
+			b as f32 / 255.0,
+			a as f32 / 255.0,
+		)
+	}
+
+	/// Parses an srgb hex code, `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+	/// with or without the `#`.
+	pub fn from_hex(hex: &str) -> Option<Self> {
+		let hex = hex.strip_prefix('#').unwrap_or(hex);
+		if !hex.is_ascii() {
+			return None;
+		}
+		let digits: Option<Vec<u8>> = match hex.len() {
+			// each digit is doubled, f becomes ff
+			3 | 4 => hex
+				.chars()
+				.map(|c| c.to_digit(16).map(|d| d as u8 * 17))
+				.collect(),
+			6 | 8 => (0..hex.len())
+				.step_by(2)
+				.map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+				.collect(),
+			_ => None,
+		};
+		match *digits?.as_slice() {
+			[r, g, b] => Some(Color::from_srgb8(r, g, b, 255)),
+			[r, g, b, a] => Some(Color::from_srgb8(r, g, b, a)),
+			_ => None,
+		}
+	}
+
+	/// Hue in degrees, saturation and value in 0..1. Like a color picker the
+	/// result is treated as srgb.
+	pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+		let hue = hue.rem_euclid(360.0) / 60.0;
+		let chroma = value * saturation;
+		let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+		let (r, g, b) = match hue as u32 {
+			0 => (chroma, x, 0.0),
+			1 => (x, chroma, 0.0),
+			2 => (0.0, chroma, x),
+			3 => (0.0, x, chroma),
+			4 => (x, 0.0, chroma),
+			_ => (chroma, 0.0, x),
+		};
+		let m = value - chroma;
+		Color::from_srgb(r + m, g + m, b + m, 1.0)
+	}
+
+	/// Hue in degrees, saturation and value of the srgb color.
+	pub fn to_hsv(self) -> Vec3 {
+		let [r, g, b, _] = self.to_srgb();
+		let max = r.max(g).max(b);
+		let chroma = max - r.min(g).min(b);
+		let hue = if chroma == 0.0 {
+			0.0
+		} else if max == r {
+			60.0 * ((g - b) / chroma).rem_euclid(6.0)
+		} else if max == g {
+			60.0 * ((b - r) / chroma + 2.0)
+		} else {
+			60.0 * ((r - g) / chroma + 4.0)
+		};
+		let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+		[hue, saturation, max]
+	}
+
+	/// Srgb components, for writing into an srgb texture by hand or showing to people.
+	pub fn to_srgb(self) -> Vec4 {
+		[
+			linear_to_srgb(self.r),
+			linear_to_srgb(self.g),
+			linear_to_srgb(self.b),
+			self.a,
+		]
+	}
+
+	/// Srgb hex code, `#rrggbbaa` unless the color is opaque.
+	pub fn to_hex(self) -> String {
+		let [r, g, b, a] = self
+			.to_srgb()
+			.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+		if a == 255 {
+			format!("#{:02x}{:02x}{:02x}", r, g, b)
+		} else {
+			format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+		}
+	}
+
+	/// Linear components.
+	pub fn to_array(self) -> Vec4 {
+		[self.r, self.g, self.b, self.a]
+	}
+
+	/// Linear rgb, dropping alpha.
+	pub fn to_rgb(self) -> Vec3 {
+		[self.r, self.g, self.b]
+	}
+
+	pub fn with_alpha(self, a: f32) -> Self {
+		Color { a, ..self }
+	}
+
+	/// Color with rgb multiplied by alpha, for premultiplied blending.
+	pub fn premultiplied(self) -> Self {
+		Color::rgba(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+	}
+
+	/// Scales rgb, eg. by a light's intensity.
+	pub fn scale(self, s: f32) -> Self {
+		Color::rgba(self.r * s, self.g * s, self.b * s, self.a)
+	}
+
+	/// Linear interpolation, in linear space.
+	pub fn lerp(self, other: Color, t: f32) -> Self {
+		let mix = |a: f32, b: f32| a + (b - a) * t;
+		Color::rgba(
+			mix(self.r, other.r),
+			mix(self.g, other.g),
+			mix(self.b, other.b),
+			mix(self.a, other.a),
+		)
+	}
+}
+
+impl From<Color> for Vec4 {
+	fn from(color: Color) -> Self {
+		color.to_array()
+	}
+}
+
+impl From<Color> for Vec3 {
+	fn from(color: Color) -> Self {
+		color.to_rgb()
+	}
+}
+
+/// Linear components.
+impl From<Vec4> for Color {
+	fn from(c: Vec4) -> Self {
+		Color::rgba(c[0], c[1], c[2], c[3])
+	}
+}
+
+/// Linear components, opaque.
+impl From<Vec3> for Color {
+	fn from(c: Vec3) -> Self {
+		Color::rgb(c[0], c[1], c[2])
+	}
+}
+
+/// Clears with the linear color. Srgb attachments convert it on write like
+/// anything else rendered to them.
+impl From<Color> for ClearValue {
+	fn from(color: Color) -> Self {
+		ClearValue::Float(color.to_array())
+	}
+}
+
+/// Srgb to linear transfer function for a single component.
+pub fn srgb_to_linear(c: f32) -> f32 {
+	if c <= 0.04045 {
+		c / 12.92
+	} else {
+		((c + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Linear to srgb transfer function for a single component.
+pub fn linear_to_srgb(c: f32) -> f32 {
+	if c <= 0.003_130_8 {
+		c * 12.92
+	} else {
+		1.055 * c.powf(1.0 / 2.4) - 0.055
+	}
+}
@@ -0,0 +1,256 @@
+// the last stage of a frame: layers drawn over each other into the window
+// pass in order, eg. the world, a first person weapon camera, the ui and
+// debug overlays, each with its own blend mode. the scene target is just the
+// bottom layer, anything else drawn into the window goes in a layer of its own.
+//
+// texture layers hold linear scene color like the scene target and go through
+// the same output transform, so a camera drawn into a `RenderTarget` looks
+// right on sdr and hdr displays alike. their alpha is premultiplied coverage,
+// what a camera cleared to transparent black leaves.
+
+use crate::fullscreen::FullscreenPipeline;
+use crate::gpu::vulkan::VulkanEncoder;
+use crate::gpu::BlendMode;
+use crate::render2d::Texture;
+use crate::resolution::{composite_pipeline, DynamicResolution};
+
+use vulkano::command_buffer::DynamicState;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Order of the scene layer every compositor starts with.
+pub const SCENE_ORDER: i32 = 0;
+
+/// What a layer draws.
+pub enum LayerContent {
+	/// The scene target, or the texture given to `Compositor::set_scene`, eg.
+	/// a `PostChain`'s output. Upscaled and letterboxed by the
+	/// `DynamicResolution`, always opaque.
+	Scene,
+	/// Linear color stretched over the window, eg. a camera's
+	/// `RenderTarget`. `None` draws nothing.
+	Texture(Option<Texture>),
+	/// Drawn by a callback inside the window pass, with the window's viewport
+	/// set on the encoder, eg. ui or debug lines.
+	Draw(LayerCallback),
+}
+
+/// Draws a `LayerContent::Draw` layer.
+pub type LayerCallback = Box<dyn FnMut(&mut VulkanEncoder)>;
+
+/// One layer of a `Compositor`.
+pub struct CompositeLayer {
+	/// Identifies the layer in the compositor.
+	pub name: String,
+	/// Lower orders are drawn first, under the higher ones.
+	pub order: i32,
+	/// How texture layers combine with what's under them. Draw layers blend
+	/// however their pipelines do.
+	pub blend: BlendMode,
+	/// Multiplies texture layers.
+	pub opacity: f32,
+	/// Disabled layers are skipped.
+	pub enabled: bool,
+	pub content: LayerContent,
+}
+
+impl CompositeLayer {
+	/// An alpha blended texture layer, set its texture every frame with
+	/// `Compositor::set_texture`.
+	pub fn texture(name: &str, order: i32) -> Self {
+		CompositeLayer {
+			name: name.to_string(),
+			order,
+			blend: BlendMode::Alpha,
+			opacity: 1.0,
+			enabled: true,
+			content: LayerContent::Texture(None),
+		}
+	}
+
+	/// A layer drawn by `draw`.
+	pub fn draw<F>(name: &str, order: i32, draw: F) -> Self
+	where
+		F: FnMut(&mut VulkanEncoder) + 'static,
+	{
+		CompositeLayer {
+			name: name.to_string(),
+			order,
+			blend: BlendMode::Alpha,
+			opacity: 1.0,
+			enabled: true,
+			content: LayerContent::Draw(Box::new(draw)),
+		}
+	}
+}
+
+/// Layers composited into the window pass, by order:
+///
+/// ```text
+/// compositor.add(CompositeLayer::texture("weapon", 10));
+/// compositor.add(CompositeLayer::draw("ui", 20, move |encoder| ..));
+/// // every frame
+/// compositor.set_scene(post.run(encoder.builder(), resolution));
+/// compositor.set_texture("weapon", weapon_target.texture());
+/// compositor.upscale(&mut encoder, resolution);
+/// // window pass
+/// compositor.record(&mut encoder, resolution, window.dynamic_state());
+/// ```
+///
+/// A new compositor has one layer, "scene" at `SCENE_ORDER`.
+pub struct Compositor {
+	device: Arc<Device>,
+	window_subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	layers: Vec<CompositeLayer>,
+	// built the first time a texture layer uses the blend mode
+	pipelines: HashMap<BlendMode, Arc<FullscreenPipeline>>,
+	scene: Option<Texture>,
+}
+
+impl Compositor {
+	/// `window_subpass` is where the layers are drawn, the one the
+	/// `DynamicResolution` composites into.
+	pub fn new(
+		device: Arc<Device>,
+		window_subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let mut compositor = Compositor {
+			device,
+			window_subpass,
+			layers: Vec::new(),
+			pipelines: HashMap::new(),
+			scene: None,
+		};
+		compositor.add(CompositeLayer {
+			name: "scene".to_string(),
+			order: SCENE_ORDER,
+			blend: BlendMode::Replace,
+			opacity: 1.0,
+			enabled: true,
+			content: LayerContent::Scene,
+		});
+		compositor
+	}
+
+	/// Inserts `layer` by its `order`, after the layers with the same order.
+	pub fn add(&mut self, layer: CompositeLayer) {
+		let index = self
+			.layers
+			.iter()
+			.position(|other| other.order > layer.order)
+			.unwrap_or(self.layers.len());
+		self.layers.insert(index, layer);
+	}
+
+	pub fn remove(&mut self, name: &str) -> Option<CompositeLayer> {
+		let index = self.layers.iter().position(|layer| layer.name == name)?;
+		Some(self.layers.remove(index))
+	}
+
+	/// Changing a layer's `order` takes effect once it's added again.
+	pub fn get_mut(&mut self, name: &str) -> Option<&mut CompositeLayer> {
+		self.layers.iter_mut().find(|layer| layer.name == name)
+	}
+
+	/// Names of the layers, bottom first.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.layers.iter().map(|layer| layer.name.as_str())
+	}
+
+	pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+		match self.get_mut(name) {
+			Some(layer) => {
+				layer.enabled = enabled;
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// The texture of a texture layer for this frame. False when there is no
+	/// texture layer called `name`.
+	pub fn set_texture(&mut self, name: &str, texture: Texture) -> bool {
+		match self.get_mut(name).map(|layer| &mut layer.content) {
+			Some(LayerContent::Texture(current)) => {
+				*current = Some(texture);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// What scene layers draw instead of the scene target this frame, the
+	/// size of the scene target.
+	pub fn set_scene(&mut self, texture: Texture) {
+		self.scene = Some(texture);
+	}
+
+	/// The upscaling the scene layer needs, outside of a render pass and
+	/// before `record`. See `DynamicResolution::upscale_from`.
+	pub fn upscale(&self, encoder: &mut VulkanEncoder, resolution: &DynamicResolution) {
+		if self.has_scene() {
+			resolution.upscale_from(encoder.builder(), self.scene_source(resolution));
+		}
+	}
+
+	/// Draws the enabled layers bottom to top. Inside the window pass, with
+	/// the window's dynamic state. Forgets the scene given to `set_scene`.
+	pub fn record(
+		&mut self,
+		encoder: &mut VulkanEncoder,
+		resolution: &DynamicResolution,
+		dynamic_state: &DynamicState,
+	) {
+		encoder.set_dynamic_state(dynamic_state);
+		let scene = self.scene_source(resolution);
+		for index in 0..self.layers.len() {
+			if !self.layers[index].enabled {
+				continue;
+			}
+			let pipeline = match self.layers[index].content {
+				LayerContent::Texture(Some(_)) => Some(self.pipeline(self.layers[index].blend)),
+				_ => None,
+			};
+			let layer = &mut self.layers[index];
+			match (&mut layer.content, pipeline) {
+				(LayerContent::Scene, _) => {
+					resolution.composite_from(encoder.builder(), dynamic_state, scene.clone())
+				}
+				(LayerContent::Texture(Some(texture)), Some(pipeline)) => {
+					let texture = texture.clone();
+					resolution.composite_texture(
+						encoder.builder(),
+						dynamic_state,
+						pipeline,
+						texture,
+						layer.opacity,
+					);
+				}
+				(LayerContent::Draw(draw), _) => draw(encoder),
+				_ => {}
+			}
+		}
+		self.scene = None;
+	}
+
+	fn has_scene(&self) -> bool {
+		self.layers
+			.iter()
+			.any(|layer| layer.enabled && matches!(layer.content, LayerContent::Scene))
+	}
+
+	fn scene_source(&self, resolution: &DynamicResolution) -> Texture {
+		self.scene.clone().unwrap_or_else(|| resolution.texture())
+	}
+
+	fn pipeline(&mut self, blend: BlendMode) -> Arc<FullscreenPipeline> {
+		let (device, subpass) = (self.device.clone(), self.window_subpass.clone());
+		self.pipelines
+			.entry(blend)
+			.or_insert_with(|| composite_pipeline(device, subpass, blend))
+			.clone()
+	}
+}
@@ -0,0 +1,401 @@
+// general purpose compute on the same device and queue as rendering.
+//
+// shaders compiled into the engine should keep using `vulkano_shaders::shader!`
+// with `ComputePipeline::new`; this is for kernels that are only known at
+// runtime, loaded from glsl source or spir-v.
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DispatchError};
+use vulkano::descriptor::descriptor::{
+	DescriptorBufferDesc, DescriptorDesc, DescriptorDescTy, DescriptorImageDesc,
+	DescriptorImageDescArray, DescriptorImageDescDimensions, ShaderStages,
+};
+use vulkano::descriptor::descriptor_set::{
+	DescriptorSetsCollection, PersistentDescriptorSet, PersistentDescriptorSetBuilder,
+	UnsafeDescriptorSetLayout,
+};
+use vulkano::descriptor::pipeline_layout::{
+	PipelineLayout, PipelineLayoutDescPcRange, RuntimePipelineDesc, RuntimePipelineDescError,
+};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::format::Format;
+use vulkano::pipeline::shader::{ShaderModule, SpecializationConstants};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineCreationError};
+use vulkano::sync::{self, GpuFuture};
+use vulkano::OomError;
+
+use crate::shader::{
+	compile_glsl, Reflection, ShaderError, ShaderLayout, ShaderStage, SpecConstants,
+};
+
+use std::ffi::CStr;
+use std::fmt;
+use std::sync::Arc;
+
+/// What the kernel expects at each binding of descriptor set 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+	UniformBuffer,
+	StorageBuffer,
+	/// `image2D` with the given format.
+	StorageImage(Format),
+	/// `sampler2D`.
+	SampledImage,
+	/// `image3D` with the given format, eg. a storage `Texture3D`.
+	StorageImage3d(Format),
+	/// `sampler3D`.
+	SampledImage3d,
+}
+
+#[derive(Debug)]
+pub enum ComputeError {
+	/// The glsl failed to compile, holds the compiler output.
+	Compile(String),
+	ShaderModule(OomError),
+	Layout(RuntimePipelineDescError),
+	Pipeline(ComputePipelineCreationError),
+	/// Reflection failed, or the bindings don't match the shader.
+	Shader(ShaderError),
+}
+
+impl fmt::Display for ComputeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ComputeError::Compile(log) => write!(f, "failed to compile compute shader: {}", log),
+			ComputeError::ShaderModule(error) => {
+				write!(f, "failed to create shader module: {}", error)
+			}
+			ComputeError::Layout(error) => write!(f, "invalid binding layout: {}", error),
+			ComputeError::Pipeline(error) => {
+				write!(f, "failed to create compute pipeline: {}", error)
+			}
+			ComputeError::Shader(error) => error.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for ComputeError {}
+
+/// A compute pipeline created at runtime.
+///
+/// The bindings and push constant size are either described up front, and
+/// checked against the shader, or read from the shader with the `_reflected`
+/// constructors.
+pub struct ComputeKernel {
+	pipeline: Arc<ComputePipeline<PipelineLayout<RuntimePipelineDesc>>>,
+	local_size: Option<[u32; 3]>,
+}
+
+impl ComputeKernel {
+	/// Compiles a glsl compute shader with `main` as the entry point.
+	pub fn from_glsl(
+		device: Arc<Device>,
+		source: &str,
+		bindings: &[Binding],
+		push_constant_size: usize,
+	) -> Result<Self, ComputeError> {
+		let spirv = compile_kernel(source)?;
+		Self::from_spirv(device, &spirv, bindings, push_constant_size)
+	}
+
+	/// Compiles a glsl compute shader with the bindings and push constants
+	/// read from the shader itself.
+	pub fn from_glsl_reflected(device: Arc<Device>, source: &str) -> Result<Self, ComputeError> {
+		let spirv = compile_kernel(source)?;
+		Self::from_spirv_reflected(device, &spirv, None)
+	}
+
+	/// Creates the kernel from spir-v words, the entry point must be called `main`.
+	pub fn from_spirv(
+		device: Arc<Device>,
+		spirv: &[u32],
+		bindings: &[Binding],
+		push_constant_size: usize,
+	) -> Result<Self, ComputeError> {
+		let layout = manual_layout(spirv, bindings, push_constant_size)?;
+		Self::create(device, spirv, layout, &())
+	}
+
+	/// Like `from_spirv` with specialization constants, see `SpecConstants`.
+	pub fn from_spirv_specialized(
+		device: Arc<Device>,
+		spirv: &[u32],
+		bindings: &[Binding],
+		push_constant_size: usize,
+		constants: &SpecConstants,
+	) -> Result<Self, ComputeError> {
+		let layout = manual_layout(spirv, bindings, push_constant_size)?;
+		Self::create(device, spirv, layout, constants)
+	}
+
+	/// Creates the kernel from spir-v words with the bindings and push
+	/// constants read from the shader, the entry point must be called `main`.
+	pub fn from_spirv_reflected(
+		device: Arc<Device>,
+		spirv: &[u32],
+		constants: Option<&SpecConstants>,
+	) -> Result<Self, ComputeError> {
+		let reflection = Reflection::parse(spirv).map_err(ComputeError::Shader)?;
+		let layout = ShaderLayout::new(&[&reflection]).map_err(ComputeError::Shader)?;
+		let mut kernel = match constants {
+			Some(constants) => Self::create(device, spirv, layout.pipeline_desc(), constants)?,
+			None => Self::create(device, spirv, layout.pipeline_desc(), &())?,
+		};
+		kernel.local_size = reflection.local_size;
+		Ok(kernel)
+	}
+
+	fn create<S: SpecializationConstants>(
+		device: Arc<Device>,
+		spirv: &[u32],
+		layout: RuntimePipelineDesc,
+		constants: &S,
+	) -> Result<Self, ComputeError> {
+		// safe as long as the module is valid spir-v, which shaderc or the caller guarantees
+		let module = unsafe { ShaderModule::from_words(device.clone(), spirv) }
+			.map_err(ComputeError::ShaderModule)?;
+
+		let entry_point = unsafe {
+			module
+				.compute_entry_point::<S, _>(CStr::from_bytes_with_nul(b"main\0").unwrap(), layout)
+		};
+
+		let pipeline = ComputePipeline::new(device, &entry_point, constants, None)
+			.map_err(ComputeError::Pipeline)?;
+
+		Ok(ComputeKernel {
+			pipeline: Arc::new(pipeline),
+			local_size: None,
+		})
+	}
+
+	/// Work group size the shader declares. Only known for kernels created
+	/// with reflection.
+	pub fn local_size(&self) -> Option<[u32; 3]> {
+		self.local_size
+	}
+
+	/// Starts a descriptor set for the kernel's bindings, add them in binding order.
+	pub fn bind(&self) -> PersistentDescriptorSetBuilder<()> {
+		PersistentDescriptorSet::start(self.layout().clone())
+	}
+
+	pub fn layout(&self) -> &Arc<UnsafeDescriptorSetLayout> {
+		self.pipeline.descriptor_set_layout(0).unwrap()
+	}
+
+	/// Records a dispatch of `group_counts` work groups.
+	///
+	/// The command buffer builder inserts the barriers between this and any
+	/// earlier or later command using the same resources.
+	pub fn dispatch<S, Pc>(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		group_counts: [u32; 3],
+		sets: S,
+		push_constants: Pc,
+	) -> Result<(), DispatchError>
+	where
+		S: DescriptorSetsCollection,
+	{
+		builder.dispatch(
+			group_counts,
+			self.pipeline.clone(),
+			sets,
+			push_constants,
+			vec![],
+		)?;
+		Ok(())
+	}
+}
+
+/// Number of work groups needed to cover `size` invocations with the given local size.
+pub fn group_counts(size: [u32; 3], local_size: [u32; 3]) -> [u32; 3] {
+	[
+		size[0].div_ceil(local_size[0]),
+		size[1].div_ceil(local_size[1]),
+		size[2].div_ceil(local_size[2]),
+	]
+}
+
+fn compile_kernel(source: &str) -> Result<Vec<u32>, ComputeError> {
+	compile_glsl(source, ShaderStage::Compute, "kernel.comp").map_err(|error| match error {
+		ShaderError::Compile(log) => ComputeError::Compile(log),
+		error => ComputeError::Shader(error),
+	})
+}
+
+// the layout described by `bindings`, checked against the shader
+fn manual_layout(
+	spirv: &[u32],
+	bindings: &[Binding],
+	push_constant_size: usize,
+) -> Result<RuntimePipelineDesc, ComputeError> {
+	let descriptors: Vec<_> = bindings
+		.iter()
+		.map(|&binding| {
+			Some(DescriptorDesc {
+				ty: descriptor_type(binding),
+				array_count: 1,
+				stages: ShaderStages::compute(),
+				readonly: matches!(
+					binding,
+					Binding::UniformBuffer | Binding::SampledImage | Binding::SampledImage3d
+				),
+			})
+		})
+		.collect();
+
+	// skipped for shaders using something reflection doesn't understand,
+	// vulkan is the last line of defense there like before
+	if let Ok(reflection) = Reflection::parse(spirv) {
+		let reflected = ShaderLayout::new(&[&reflection]).map_err(ComputeError::Shader)?;
+		if reflected.set_count() > 1 {
+			return Err(ComputeError::Shader(ShaderError::Mismatch(
+				"kernels can only use descriptor set 0".to_string(),
+			)));
+		}
+		reflected
+			.check_set(0, &descriptors)
+			.and_then(|_| reflected.check_push_constants(push_constant_size))
+			.map_err(ComputeError::Shader)?;
+	}
+
+	let push_constants = if push_constant_size > 0 {
+		Some(PipelineLayoutDescPcRange {
+			offset: 0,
+			size: push_constant_size,
+			stages: ShaderStages::compute(),
+		})
+	} else {
+		None
+	};
+
+	RuntimePipelineDesc::new(std::iter::once(descriptors), push_constants)
+		.map_err(ComputeError::Layout)
+}
+
+fn descriptor_type(binding: Binding) -> DescriptorDescTy {
+	let image = |sampled, format, dimensions| DescriptorImageDesc {
+		sampled,
+		dimensions,
+		format,
+		multisampled: false,
+		array_layers: DescriptorImageDescArray::NonArrayed,
+	};
+
+	match binding {
+		Binding::UniformBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc {
+			dynamic: Some(false),
+			storage: false,
+		}),
+		Binding::StorageBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc {
+			dynamic: Some(false),
+			storage: true,
+		}),
+		Binding::StorageImage(format) => DescriptorDescTy::Image(image(
+			false,
+			Some(format),
+			DescriptorImageDescDimensions::TwoDimensional,
+		)),
+		Binding::SampledImage => DescriptorDescTy::CombinedImageSampler(image(
+			true,
+			None,
+			DescriptorImageDescDimensions::TwoDimensional,
+		)),
+		Binding::StorageImage3d(format) => DescriptorDescTy::Image(image(
+			false,
+			Some(format),
+			DescriptorImageDescDimensions::ThreeDimensional,
+		)),
+		Binding::SampledImage3d => DescriptorDescTy::CombinedImageSampler(image(
+			true,
+			None,
+			DescriptorImageDescDimensions::ThreeDimensional,
+		)),
+	}
+}
+
+/// Runs one-off compute work and reads results back to the cpu.
+///
+/// Every call submits and waits, so this is meant for tools and setup work
+/// rather than per frame use. Per frame dispatches should be recorded into the
+/// frame's command buffer instead.
+pub struct ComputeContext {
+	device: Arc<Device>,
+	queue: Arc<Queue>,
+}
+
+impl ComputeContext {
+	/// `queue` has to support compute, graphics queues always do.
+	pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+		assert!(queue.family().supports_compute());
+		ComputeContext { device, queue }
+	}
+
+	/// Host visible storage buffer, readable with `read()` after `run` returns.
+	pub fn storage_buffer<T, I>(&self, data: I) -> Arc<CpuAccessibleBuffer<[T]>>
+	where
+		T: Send + Sync + 'static,
+		I: ExactSizeIterator<Item = T>,
+	{
+		CpuAccessibleBuffer::from_iter(
+			self.device.clone(),
+			BufferUsage {
+				storage_buffer: true,
+				transfer_source: true,
+				transfer_destination: true,
+				..BufferUsage::none()
+			},
+			false,
+			data,
+		)
+		.unwrap()
+	}
+
+	/// Records commands with `record`, submits them and blocks until the gpu is done.
+	pub fn run<F>(&self, record: F)
+	where
+		F: FnOnce(&mut AutoCommandBufferBuilder),
+	{
+		let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+			self.device.clone(),
+			self.queue.family(),
+		)
+		.unwrap();
+		record(&mut builder);
+		let command_buffer = builder.build().unwrap();
+
+		sync::now(self.device.clone())
+			.then_execute(self.queue.clone(), command_buffer)
+			.unwrap()
+			.then_signal_fence_and_flush()
+			.unwrap()
+			.wait(None)
+			.unwrap();
+	}
+
+	/// Copies any buffer (device local ones included) back to the cpu.
+	pub fn read_buffer<T, B>(&self, buffer: B) -> Vec<T>
+	where
+		T: Copy + Default + Send + Sync + 'static,
+		B: TypedBufferAccess<Content = [T]> + Send + Sync + 'static,
+	{
+		let staging = CpuAccessibleBuffer::from_iter(
+			self.device.clone(),
+			BufferUsage::transfer_destination(),
+			true,
+			(0..buffer.len()).map(|_| T::default()),
+		)
+		.unwrap();
+
+		let destination = staging.clone();
+		self.run(move |builder| {
+			builder.copy_buffer(buffer, destination).unwrap();
+		});
+
+		let data = staging.read().unwrap();
+		data.to_vec()
+	}
+}
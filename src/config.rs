@@ -0,0 +1,511 @@
+// engine settings read from an `opal.toml`, so shipped apps can be
+// reconfigured without recompiling.
+//
+// [window]
+// title = "opal"
+// width = 1280
+// height = 720
+// fullscreen = false
+// vsync = true
+// headless = false
+// unfocused = "continue" # "pause" or a frame rate, eg. 10
+//
+// [gpu]
+// preference = "discrete" # "integrated", "any" or a device index
+//
+// [log]
+// level = "info"
+// validation = false
+// breadcrumbs = false
+// debug_labels = true
+// frame_budget = 16.6 # milliseconds, hitches are reported on exit
+//
+// [assets]
+// paths = ["assets"]
+// hot_reload = true
+
+use crate::replay::ReplayMode;
+use crate::requirements::Requirements;
+
+use vulkano::instance::{Instance, PhysicalDevice, PhysicalDeviceType};
+
+use log::LevelFilter;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Name of the config file `EngineConfig::find` looks for.
+pub const CONFIG_FILE: &str = "opal.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(std::io::Error),
+	Toml(toml::de::Error),
+	/// A key had a value of the wrong type or out of range.
+	Value {
+		key: &'static str,
+		expected: &'static str,
+	},
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io(error) => write!(f, "failed to read config: {}", error),
+			ConfigError::Toml(error) => write!(f, "invalid config: {}", error),
+			ConfigError::Value { key, expected } => {
+				write!(f, "invalid config: `{}` should be {}", key, expected)
+			}
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+	fn from(error: std::io::Error) -> Self {
+		ConfigError::Io(error)
+	}
+}
+
+impl From<toml::de::Error> for ConfigError {
+	fn from(error: toml::de::Error) -> Self {
+		ConfigError::Toml(error)
+	}
+}
+
+/// Which gpu the engine runs on when there's more than one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GpuPreference {
+	/// A discrete gpu if there is one, the fast choice on laptops.
+	#[default]
+	Discrete,
+	/// An integrated gpu if there is one, to save battery.
+	Integrated,
+	/// The first device, whatever it is.
+	Any,
+	/// The device at this index in `PhysicalDevice::enumerate`.
+	Index(usize),
+}
+
+impl GpuPreference {
+	/// Picks a device with a graphics queue, falling back to any such device
+	/// when none match the preference.
+	pub fn select(self, instance: &Arc<Instance>) -> Option<PhysicalDevice<'_>> {
+		let wanted = match self {
+			GpuPreference::Discrete => Some(PhysicalDeviceType::DiscreteGpu),
+			GpuPreference::Integrated => Some(PhysicalDeviceType::IntegratedGpu),
+			GpuPreference::Any | GpuPreference::Index(_) => None,
+		};
+		let usable = |physical: &PhysicalDevice| {
+			physical
+				.queue_families()
+				.any(|family| family.supports_graphics())
+		};
+
+		let preferred = match self {
+			GpuPreference::Index(index) => {
+				PhysicalDevice::from_index(instance, index).filter(|physical| usable(physical))
+			}
+			_ => PhysicalDevice::enumerate(instance)
+				.filter(|physical| usable(physical))
+				.find(|physical| Some(physical.ty()) == wanted),
+		};
+		preferred.or_else(|| PhysicalDevice::enumerate(instance).find(|physical| usable(physical)))
+	}
+
+	fn parse(value: &toml::Value) -> Option<GpuPreference> {
+		if let Some(index) = value.as_integer() {
+			return if index >= 0 {
+				Some(GpuPreference::Index(index as usize))
+			} else {
+				None
+			};
+		}
+		GpuPreference::from_name(value.as_str()?)
+	}
+
+	/// Parses `discrete`, `integrated`, `any` or a device index.
+	pub fn from_name(name: &str) -> Option<GpuPreference> {
+		match name {
+			"discrete" => Some(GpuPreference::Discrete),
+			"integrated" => Some(GpuPreference::Integrated),
+			"any" => Some(GpuPreference::Any),
+			_ => name.parse().ok().map(GpuPreference::Index),
+		}
+	}
+}
+
+/// What the main window does while it doesn't have focus.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FocusPolicy {
+	/// Keeps rendering as usual, eg. for games that run in the background.
+	#[default]
+	Continue,
+	/// Renders at most this many frames per second. Rates that aren't
+	/// positive pause instead.
+	Throttle(f64),
+	/// Stops rendering until the window has focus again, for tools.
+	Pause,
+}
+
+impl FocusPolicy {
+	/// Time to leave between frames without focus, `None` when paused.
+	pub fn interval(self) -> Option<Duration> {
+		match self {
+			FocusPolicy::Continue => Some(Duration::ZERO),
+			FocusPolicy::Pause => None,
+			// rates too small for a `Duration` pause as well
+			FocusPolicy::Throttle(rate) if rate > 0.0 => {
+				Duration::try_from_secs_f64(1.0 / rate).ok()
+			}
+			// zero, negative or nan
+			FocusPolicy::Throttle(_) => None,
+		}
+	}
+
+	fn parse(value: &toml::Value) -> Option<FocusPolicy> {
+		let rate = value
+			.as_float()
+			.or_else(|| value.as_integer().map(|n| n as f64));
+		if let Some(rate) = rate {
+			return if rate > 0.0 {
+				Some(FocusPolicy::Throttle(rate))
+			} else {
+				None
+			};
+		}
+		match value.as_str()? {
+			"continue" => Some(FocusPolicy::Continue),
+			"pause" => Some(FocusPolicy::Pause),
+			_ => None,
+		}
+	}
+}
+
+/// Everything `Engine::new` needs before it can open a window.
+///
+/// Starts from the defaults, then `opal.toml` when there is one, then
+/// whatever the app changes in code, so the app always gets the last word.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineConfig {
+	pub title: String,
+	/// Inner size of the window in logical pixels.
+	pub width: u32,
+	pub height: u32,
+	/// Borderless fullscreen on the current monitor.
+	pub fullscreen: bool,
+	pub vsync: bool,
+	/// Run without a window, eg. for servers, tests and offline rendering.
+	pub headless: bool,
+	/// Whether to keep rendering while the window is in the background.
+	pub unfocused: FocusPolicy,
+	pub gpu: GpuPreference,
+	/// Most verbose messages printed. The engine installs a logger printing
+	/// to stdout unless the app set up its own `log` logger first.
+	pub log_level: LevelFilter,
+	/// Enable the vulkan validation layer when it's installed and log what
+	/// it reports.
+	pub validation: bool,
+	/// Record gpu breadcrumbs and log how far the gpu got when the device
+	/// is lost, see `breadcrumbs::Breadcrumbs`.
+	pub breadcrumbs: bool,
+	/// Enable VK_EXT_debug_utils for the names and regions of `debug_labels`
+	/// when the driver has it, on by default in debug builds.
+	pub debug_labels: bool,
+	/// Report frames taking longer than this, see `watchdog::Watchdog`.
+	pub frame_budget: Option<Duration>,
+	/// Directories assets are looked up in, first match wins. Relative paths
+	/// are relative to the working directory.
+	pub asset_paths: Vec<PathBuf>,
+	/// Reload assets when their files change, on by default in debug builds.
+	pub hot_reload: bool,
+	/// What the gpu has to support, only settable in code.
+	pub requirements: Requirements,
+	/// Record or play back input, set in code or with `--record` and
+	/// `--replay`, see `replay::Replay`.
+	pub replay: ReplayMode,
+}
+
+impl Default for EngineConfig {
+	fn default() -> Self {
+		EngineConfig {
+			title: "opal".to_string(),
+			width: 1280,
+			height: 720,
+			fullscreen: false,
+			vsync: true,
+			headless: false,
+			unfocused: FocusPolicy::default(),
+			gpu: GpuPreference::default(),
+			log_level: LevelFilter::Info,
+			validation: false,
+			breadcrumbs: false,
+			debug_labels: cfg!(debug_assertions),
+			frame_budget: None,
+			asset_paths: vec![PathBuf::from("assets")],
+			hot_reload: cfg!(debug_assertions),
+			requirements: Requirements::default(),
+			replay: ReplayMode::Off,
+		}
+	}
+}
+
+impl EngineConfig {
+	/// The defaults overridden by the config file at `path`.
+	pub fn load(path: &Path) -> Result<EngineConfig, ConfigError> {
+		let mut config = EngineConfig::default();
+		config.merge_toml(&std::fs::read_to_string(path)?)?;
+		Ok(config)
+	}
+
+	/// Loads the file from `find`, or the defaults when there is none. A broken
+	/// file is reported and ignored so the app still starts.
+	pub fn load_or_default() -> EngineConfig {
+		let path = match EngineConfig::find() {
+			Some(path) => path,
+			None => return EngineConfig::default(),
+		};
+		EngineConfig::load(&path).unwrap_or_else(|error| {
+			// the file's log level is lost with it, warn at the default one
+			let config = EngineConfig::default();
+			crate::engine::init_logging(config.log_level);
+			log::warn!("Ignoring {}: {}", path.display(), error);
+			config
+		})
+	}
+
+	/// `opal.toml` in the working directory, or else next to the executable.
+	pub fn find() -> Option<PathBuf> {
+		let beside_exe = std::env::current_exe()
+			.ok()
+			.and_then(|exe| exe.parent().map(|dir| dir.join(CONFIG_FILE)));
+		std::iter::once(PathBuf::from(CONFIG_FILE))
+			.chain(beside_exe)
+			.find(|path| path.is_file())
+	}
+
+	/// Overrides the fields set in `source`, keys that are left out keep their
+	/// current value and unknown keys are ignored.
+	pub fn merge_toml(&mut self, source: &str) -> Result<(), ConfigError> {
+		let root: toml::Value = source.parse()?;
+
+		if let Some(window) = root.get("window") {
+			if let Some(value) = window.get("title") {
+				self.title = value
+					.as_str()
+					.ok_or(invalid("window.title", "a string"))?
+					.to_string();
+			}
+			if let Some(value) = window.get("width") {
+				self.width =
+					dimension(value).ok_or(invalid("window.width", "a positive integer"))?;
+			}
+			if let Some(value) = window.get("height") {
+				self.height =
+					dimension(value).ok_or(invalid("window.height", "a positive integer"))?;
+			}
+			if let Some(value) = window.get("fullscreen") {
+				self.fullscreen = value
+					.as_bool()
+					.ok_or(invalid("window.fullscreen", "true or false"))?;
+			}
+			if let Some(value) = window.get("vsync") {
+				self.vsync = value
+					.as_bool()
+					.ok_or(invalid("window.vsync", "true or false"))?;
+			}
+			if let Some(value) = window.get("headless") {
+				self.headless = value
+					.as_bool()
+					.ok_or(invalid("window.headless", "true or false"))?;
+			}
+			if let Some(value) = window.get("unfocused") {
+				self.unfocused = FocusPolicy::parse(value).ok_or(invalid(
+					"window.unfocused",
+					"\"continue\", \"pause\" or a frame rate",
+				))?;
+			}
+		}
+
+		if let Some(value) = root.get("gpu").and_then(|gpu| gpu.get("preference")) {
+			self.gpu = GpuPreference::parse(value).ok_or(invalid(
+				"gpu.preference",
+				"\"discrete\", \"integrated\", \"any\" or a device index",
+			))?;
+		}
+
+		if let Some(log) = root.get("log") {
+			if let Some(value) = log.get("level") {
+				self.log_level =
+					value
+						.as_str()
+						.and_then(|level| level.parse().ok())
+						.ok_or(invalid(
+							"log.level",
+							"one of off, error, warn, info, debug or trace",
+						))?;
+			}
+			if let Some(value) = log.get("validation") {
+				self.validation = value
+					.as_bool()
+					.ok_or(invalid("log.validation", "true or false"))?;
+			}
+			if let Some(value) = log.get("breadcrumbs") {
+				self.breadcrumbs = value
+					.as_bool()
+					.ok_or(invalid("log.breadcrumbs", "true or false"))?;
+			}
+			if let Some(value) = log.get("debug_labels") {
+				self.debug_labels = value
+					.as_bool()
+					.ok_or(invalid("log.debug_labels", "true or false"))?;
+			}
+			if let Some(value) = log.get("frame_budget") {
+				let millis = value
+					.as_float()
+					.or_else(|| value.as_integer().map(|n| n as f64))
+					.filter(|millis| *millis > 0.0)
+					.ok_or(invalid("log.frame_budget", "milliseconds above 0"))?;
+				self.frame_budget = Some(Duration::from_secs_f64(millis / 1000.0));
+			}
+		}
+
+		if let Some(assets) = root.get("assets") {
+			if let Some(value) = assets.get("paths") {
+				let paths = value.as_array().and_then(|paths| {
+					paths
+						.iter()
+						.map(|path| path.as_str().map(PathBuf::from))
+						.collect::<Option<_>>()
+				});
+				self.asset_paths = paths.ok_or(invalid("assets.paths", "an array of strings"))?;
+			}
+			if let Some(value) = assets.get("hot_reload") {
+				self.hot_reload = value
+					.as_bool()
+					.ok_or(invalid("assets.hot_reload", "true or false"))?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+fn invalid(key: &'static str, expected: &'static str) -> ConfigError {
+	ConfigError::Value { key, expected }
+}
+
+fn dimension(value: &toml::Value) -> Option<u32> {
+	value
+		.as_integer()
+		.filter(|&n| n > 0 && n <= u32::MAX as i64)
+		.map(|n| n as u32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn merged(source: &str) -> Result<EngineConfig, ConfigError> {
+		let mut config = EngineConfig::default();
+		config.merge_toml(source).map(|_| config)
+	}
+
+	fn rejected_key(source: &str) -> &'static str {
+		match merged(source) {
+			Err(ConfigError::Value { key, .. }) => key,
+			other => panic!("{:?} merged as {:?}", source, other),
+		}
+	}
+
+	#[test]
+	fn empty_file_keeps_the_defaults() {
+		assert_eq!(merged("").unwrap(), EngineConfig::default());
+	}
+
+	#[test]
+	fn every_section() {
+		let config = merged(
+			r#"
+			[window]
+			title = "editor"
+			width = 800
+			height = 600
+			fullscreen = true
+			vsync = false
+			unfocused = 10
+
+			[gpu]
+			preference = 1
+
+			[log]
+			level = "debug"
+			validation = true
+			frame_budget = 8
+
+			[assets]
+			paths = ["content", "/opt/shared"]
+			hot_reload = false
+			"#,
+		)
+		.unwrap();
+		assert_eq!(config.title, "editor");
+		assert_eq!((config.width, config.height), (800, 600));
+		assert!(config.fullscreen);
+		assert!(!config.vsync);
+		assert!(!config.headless);
+		assert_eq!(config.unfocused, FocusPolicy::Throttle(10.0));
+		assert_eq!(config.gpu, GpuPreference::Index(1));
+		assert_eq!(config.log_level, LevelFilter::Debug);
+		assert!(config.validation);
+		assert_eq!(config.frame_budget, Some(Duration::from_millis(8)));
+		assert_eq!(
+			config.asset_paths,
+			[PathBuf::from("content"), PathBuf::from("/opt/shared")]
+		);
+		assert!(!config.hot_reload);
+	}
+
+	#[test]
+	fn named_values() {
+		let config = merged(
+			r#"
+			window.unfocused = "pause"
+			gpu.preference = "integrated"
+			"#,
+		)
+		.unwrap();
+		assert_eq!(config.unfocused, FocusPolicy::Pause);
+		assert_eq!(config.gpu, GpuPreference::Integrated);
+	}
+
+	#[test]
+	fn unknown_keys_are_ignored() {
+		let config = merged("[window]\nicon = \"opal.png\"\n[audio]\nvolume = 1").unwrap();
+		assert_eq!(config, EngineConfig::default());
+	}
+
+	#[test]
+	fn wrong_values_name_their_key() {
+		assert_eq!(rejected_key("window.width = 0"), "window.width");
+		assert_eq!(rejected_key("window.height = 5000000000"), "window.height");
+		assert_eq!(rejected_key("window.vsync = \"yes\""), "window.vsync");
+		assert_eq!(rejected_key("window.unfocused = 0"), "window.unfocused");
+		assert_eq!(rejected_key("gpu.preference = -1"), "gpu.preference");
+		assert_eq!(
+			rejected_key("gpu.preference = \"fastest\""),
+			"gpu.preference"
+		);
+		assert_eq!(rejected_key("log.level = \"loud\""), "log.level");
+		assert_eq!(rejected_key("log.frame_budget = 0"), "log.frame_budget");
+		assert_eq!(rejected_key("assets.paths = [\"a\", 1]"), "assets.paths");
+	}
+
+	#[test]
+	fn broken_toml() {
+		assert!(matches!(merged("[window"), Err(ConfigError::Toml(_))));
+	}
+}
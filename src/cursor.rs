@@ -0,0 +1,137 @@
+// how the mouse cursor looks and behaves over a window. winit only offers
+// grabbing and hiding, locking is built from both plus recentering the
+// cursor on platforms where a grab only confines it.
+//
+// winit has no custom cursor images yet, so a `CustomCursor` hides the system
+// cursor and is drawn as a sprite with the ui instead.
+
+use crate::math::Vec2;
+use crate::render2d::{Sprite, Texture};
+
+use winit::dpi::PhysicalPosition;
+use winit::window::{CursorIcon, Window};
+
+/// What the cursor does while it's over the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorMode {
+	/// Free to move and visible.
+	#[default]
+	Normal,
+	/// Free to move but not drawn.
+	Hidden,
+	/// Visible, can't leave the window.
+	Confined,
+	/// Hidden and held in place, for mouse look. Read movement from
+	/// `DeviceEvent::MouseMotion`, cursor positions stop changing.
+	Locked,
+}
+
+impl CursorMode {
+	fn grabbed(self) -> bool {
+		matches!(self, CursorMode::Confined | CursorMode::Locked)
+	}
+
+	fn visible(self) -> bool {
+		matches!(self, CursorMode::Normal | CursorMode::Confined)
+	}
+}
+
+/// A cursor drawn from a texture.
+#[derive(Clone)]
+pub struct CustomCursor {
+	pub texture: Texture,
+	/// Size in logical pixels.
+	pub size: Vec2,
+	/// Point of the image at the cursor position, from `[0, 0]` (top left)
+	/// to `[1, 1]` (bottom right).
+	pub hotspot: Vec2,
+}
+
+impl CustomCursor {
+	/// An arrow like cursor pointing from the top left corner.
+	pub fn new(texture: Texture, size: Vec2) -> Self {
+		CustomCursor {
+			texture,
+			size,
+			hotspot: [0.0, 0.0],
+		}
+	}
+}
+
+/// Cursor settings of a window, kept so they can be restored when the window
+/// gets its focus back.
+pub(crate) struct CursorState {
+	pub mode: CursorMode,
+	pub icon: CursorIcon,
+	pub custom: Option<CustomCursor>,
+	// physical pixels, `None` while the cursor is outside of the window
+	pub position: Option<[f32; 2]>,
+	pub focused: bool,
+}
+
+impl Default for CursorState {
+	fn default() -> Self {
+		CursorState {
+			mode: CursorMode::Normal,
+			icon: CursorIcon::Default,
+			custom: None,
+			position: None,
+			focused: true,
+		}
+	}
+}
+
+impl CursorState {
+	/// Pushes the settings to the window. Without focus the cursor is always
+	/// released, so alt-tabbing out of mouse look gives it back.
+	pub fn apply(&self, window: &Window) {
+		let grab = self.focused && self.mode.grabbed();
+		if let Err(e) = window.set_cursor_grab(grab) {
+			if grab {
+				log::warn!("Failed to grab the cursor: {}", e);
+			}
+		}
+		let visible = !self.focused || (self.mode.visible() && self.custom.is_none());
+		window.set_cursor_visible(visible);
+		window.set_cursor_icon(self.icon);
+		if grab && self.mode == CursorMode::Locked {
+			self.recenter(window);
+		}
+	}
+
+	/// Called with every cursor position, moves a locked cursor back to the
+	/// middle so a grab that only confines can't run into the window's edge.
+	pub fn moved(&mut self, window: &Window, position: [f32; 2]) {
+		self.position = Some(position);
+		if self.focused && self.mode == CursorMode::Locked {
+			self.recenter(window);
+		}
+	}
+
+	fn recenter(&self, window: &Window) {
+		let size = window.inner_size();
+		let center = PhysicalPosition::new(size.width / 2, size.height / 2);
+		// unsupported on wayland, where the grab locks by itself
+		window.set_cursor_position(center).ok();
+	}
+
+	/// The custom cursor at the cursor position, with `scale_factor` physical
+	/// pixels per logical pixel, for a `Camera2d::new` camera.
+	pub fn sprite(&self, scale_factor: f32) -> Option<Sprite> {
+		let custom = self.custom.as_ref()?;
+		if !self.mode.visible() {
+			return None;
+		}
+		let position = self.position?;
+		Some(Sprite {
+			origin: custom.hotspot,
+			// above any ui
+			layer: i32::MAX,
+			..Sprite::new(
+				custom.texture.clone(),
+				position,
+				[custom.size[0] * scale_factor, custom.size[1] * scale_factor],
+			)
+		})
+	}
+}
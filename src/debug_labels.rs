@@ -0,0 +1,123 @@
+// names for buffers, images and pipelines, and labeled regions around the
+// commands of a pass, so renderdoc and nsight captures show "shadow map" and
+// "bloom" instead of handles. through VK_EXT_debug_utils, which the engine
+// enables with `EngineConfig::debug_labels`. without it everything here does
+// nothing, so calls can stay in release builds.
+
+use vulkano::buffer::BufferAccess;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::image::ImageAccess;
+use vulkano::pipeline::{ComputePipelineAbstract, GraphicsPipelineAbstract};
+use vulkano::{VulkanHandle, VulkanObject};
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::{Mutex, OnceLock};
+
+/// Color regions and labels get when none is given, a capture tool's default.
+pub const NO_COLOR: [f32; 4] = [0.0; 4];
+
+/// Whether the instance has VK_EXT_debug_utils, without it naming and
+/// labeling does nothing.
+pub fn enabled(device: &Device) -> bool {
+	device.instance().loaded_extensions().ext_debug_utils
+}
+
+/// Names any vulkano object, eg. a sampler or shader module.
+pub fn name<T>(object: &T, name: &str)
+where
+	T: VulkanObject + DeviceOwned,
+{
+	let device = object.device();
+	if enabled(device) {
+		device.set_object_name(object, &c_string(name)).ok();
+	}
+}
+
+/// Names the vulkan buffer behind `buffer`, slices name the whole buffer.
+pub fn name_buffer<B>(buffer: &B, name: &str)
+where
+	B: BufferAccess + ?Sized,
+{
+	self::name(buffer.inner().buffer, name);
+}
+
+/// Names the vulkan image behind `image`, eg. an `AttachmentImage`.
+pub fn name_image<I>(image: &I, name: &str)
+where
+	I: ImageAccess + ?Sized,
+{
+	let image = image.inner().image;
+	raw_name(image.device(), image, name);
+}
+
+pub fn name_graphics_pipeline(pipeline: &dyn GraphicsPipelineAbstract, name: &str) {
+	let inner = GraphicsPipelineAbstract::inner(pipeline);
+	raw_name(pipeline.device(), &inner, name);
+}
+
+pub fn name_compute_pipeline(pipeline: &dyn ComputePipelineAbstract, name: &str) {
+	let inner = pipeline.inner();
+	raw_name(pipeline.device(), &inner, name);
+}
+
+// for images and the `*Sys` wrappers pipelines hand out, which aren't
+// `DeviceOwned`
+fn raw_name<T: VulkanObject>(device: &Device, object: &T, name: &str) {
+	if enabled(device) {
+		// safe, the handle is from an object of `device`
+		unsafe {
+			device
+				.set_object_name_raw(T::TYPE, object.internal_object().value(), &c_string(name))
+				.ok();
+		}
+	}
+}
+
+/// Opens a labeled region in the command buffer, close it with `pop`.
+/// Regions nest, and can span render passes.
+pub fn push(builder: &mut AutoCommandBufferBuilder, label: &str, color: [f32; 4]) {
+	if enabled(builder.device()) {
+		// fails on compute only queues, which can't have labels
+		builder.debug_marker_begin(intern(label), color).ok();
+	}
+}
+
+pub fn pop(builder: &mut AutoCommandBufferBuilder) {
+	if enabled(builder.device()) {
+		builder.debug_marker_end().ok();
+	}
+}
+
+/// A single label between commands.
+pub fn insert(builder: &mut AutoCommandBufferBuilder, label: &str, color: [f32; 4]) {
+	if enabled(builder.device()) {
+		builder.debug_marker_insert(intern(label), color).ok();
+	}
+}
+
+/// Records `record` inside a region labeled `label`.
+pub fn region<F, R>(builder: &mut AutoCommandBufferBuilder, label: &str, record: F) -> R
+where
+	F: FnOnce(&mut AutoCommandBufferBuilder) -> R,
+{
+	push(builder, label, NO_COLOR);
+	let result = record(builder);
+	pop(builder);
+	result
+}
+
+fn c_string(name: &str) -> CString {
+	CString::new(name.replace('\0', " ")).unwrap()
+}
+
+// vulkano 0.22 wants region labels to live forever. labels are usually a
+// handful of pass names, so each distinct one is leaked once
+fn intern(label: &str) -> &'static CStr {
+	static LABELS: OnceLock<Mutex<HashMap<String, &'static CStr>>> = OnceLock::new();
+	let mut labels = LABELS.get_or_init(Default::default).lock().unwrap();
+	labels
+		.entry(label.to_string())
+		.or_insert_with(|| Box::leak(c_string(label).into_boxed_c_str()))
+}
@@ -0,0 +1,426 @@
+// deferred box projected decals. each decal is a box in the world, pixels
+// whose depth lands inside it are projected onto its texture and blended into
+// the albedo, normal and material images before lighting.
+
+use crate::assets::Texture2DArray;
+use crate::compute::group_counts;
+use crate::math::{dot, mat4_inverse, Frustum, Mat4};
+use crate::render2d::Texture;
+
+use vulkano::buffer::{BufferUsage, CpuBufferPool};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sampler::Sampler;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Format of the images decals are blended into.
+pub const DECAL_TARGET_FORMAT: Format = Format::R8G8B8A8Unorm;
+
+// bits of `info.w` in the shader
+const HAS_NORMALS: u32 = 1;
+const HAS_MATERIAL: u32 = 2;
+const HAS_NORMAL_LAYERS: u32 = 4;
+
+// matches `Decal` in the shader
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+struct GpuDecal {
+	world_to_decal: Mat4,
+	tangent: [f32; 4],
+	bitangent: [f32; 4],
+	axis: [f32; 4],
+	color: [f32; 4],
+}
+
+mod project {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+			struct Decal {
+				mat4 world_to_decal;
+				// world x axis of the box, w is the normal strength
+				vec4 tangent;
+				// world z axis, w is the roughness or negative to keep it
+				vec4 bitangent;
+				// world y axis the decal faces, w is the texture layer
+				vec4 axis;
+				vec4 color;
+			};
+
+			layout(set = 0, binding = 0) readonly buffer Decals {
+				Decal decals[];
+			};
+			layout(set = 0, binding = 1) uniform sampler2D depth;
+			layout(set = 0, binding = 2) uniform sampler2DArray albedo_layers;
+			layout(set = 0, binding = 3) uniform sampler2DArray normal_layers;
+			layout(set = 0, binding = 4, rgba8) uniform image2D albedo;
+			layout(set = 0, binding = 5, rgba8) uniform image2D normals;
+			layout(set = 0, binding = 6, rgba8) uniform image2D material;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 inverse_view_projection;
+				// width, height, decal count, which images are bound
+				uvec4 info;
+			} pc;
+
+			const uint HAS_NORMALS = 1u;
+			const uint HAS_MATERIAL = 2u;
+			const uint HAS_NORMAL_LAYERS = 4u;
+
+			vec3 world_position(vec2 pixel, float z) {
+				vec2 ndc = pixel / vec2(pc.info.xy) * 2.0 - 1.0;
+				vec4 world = pc.inverse_view_projection * vec4(ndc, z, 1.0);
+				return world.xyz / world.w;
+			}
+
+			void main() {
+				ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+				if (pixel.x >= int(pc.info.x) || pixel.y >= int(pc.info.y)) {
+					return;
+				}
+				float z = texelFetch(depth, pixel, 0).r;
+				if (z >= 1.0) {
+					return;
+				}
+
+				vec2 center = vec2(pixel) + 0.5;
+				vec4 world = vec4(world_position(center, z), 1.0);
+				// neighbours on the same depth give the texture gradients, compute
+				// shaders have no derivatives of their own
+				vec4 world_x = vec4(world_position(center + vec2(1.0, 0.0), z), 1.0);
+				vec4 world_y = vec4(world_position(center + vec2(0.0, 1.0), z), 1.0);
+
+				uint flags = pc.info.w;
+				vec4 color = imageLoad(albedo, pixel);
+				vec3 normal = vec3(0.0);
+				if ((flags & HAS_NORMALS) != 0u) {
+					normal = normalize(imageLoad(normals, pixel).xyz * 2.0 - 1.0);
+				}
+				vec4 surface = (flags & HAS_MATERIAL) != 0u ? imageLoad(material, pixel) : vec4(0.0);
+
+				bool touched = false;
+				for (uint i = 0u; i < pc.info.z; i++) {
+					Decal decal = decals[i];
+					vec3 local = (decal.world_to_decal * world).xyz;
+					if (any(greaterThan(abs(local), vec3(0.5)))) {
+						continue;
+					}
+					touched = true;
+
+					vec2 uv = local.xz + 0.5;
+					vec2 dx = (decal.world_to_decal * world_x).xz + 0.5 - uv;
+					vec2 dy = (decal.world_to_decal * world_y).xz + 0.5 - uv;
+					vec3 coords = vec3(uv, decal.axis.w);
+					vec4 texel = textureGrad(albedo_layers, coords, dx, dy) * decal.color;
+
+					// fade out towards the ends of the box and on surfaces facing away
+					float fade = 1.0 - smoothstep(0.35, 0.5, abs(local.y));
+					if ((flags & HAS_NORMALS) != 0u) {
+						fade *= smoothstep(0.1, 0.4, dot(normal, decal.axis.xyz));
+					}
+					float alpha = texel.a * fade;
+
+					color.rgb = mix(color.rgb, texel.rgb, alpha);
+					if ((flags & (HAS_NORMALS | HAS_NORMAL_LAYERS)) == (HAS_NORMALS | HAS_NORMAL_LAYERS)) {
+						vec2 bump = textureGrad(normal_layers, coords, dx, dy).xy * 2.0 - 1.0;
+						vec3 offset = decal.tangent.xyz * bump.x + decal.bitangent.xyz * bump.y;
+						normal = normalize(normal + offset * decal.tangent.w * alpha);
+					}
+					if (decal.bitangent.w >= 0.0) {
+						surface.g = mix(surface.g, decal.bitangent.w, alpha);
+					}
+				}
+				if (!touched) {
+					return;
+				}
+
+				imageStore(albedo, pixel, color);
+				if ((flags & HAS_NORMALS) != 0u) {
+					imageStore(normals, pixel, vec4(normal * 0.5 + 0.5, 1.0));
+				}
+				if ((flags & HAS_MATERIAL) != 0u) {
+					imageStore(material, pixel, surface);
+				}
+			}
+		"
+	}
+}
+
+/// A texture projected onto whatever is inside a box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decal {
+	/// Places the unit box, centered on the origin, that the decal projects
+	/// through. The texture is mapped across the box's x and z and projected
+	/// down its y axis, which should face out of the surface.
+	pub transform: Mat4,
+	/// Layer of `DecalTextures` to project.
+	pub layer: u32,
+	/// Tints the texture, alpha scales its opacity.
+	pub color: [f32; 4],
+	/// How much the normal layer bends the surface normal, 0 leaves it.
+	pub normal_strength: f32,
+	/// Roughness written where the decal covers, `None` keeps the surface's.
+	pub roughness: Option<f32>,
+	/// Decals with a higher order are drawn over lower ones, eg. blood over
+	/// road markings. Equal orders draw in the order they were given.
+	pub order: i32,
+}
+
+impl Decal {
+	pub fn new(transform: Mat4, layer: u32) -> Self {
+		Decal {
+			transform,
+			layer,
+			color: [1.0; 4],
+			normal_strength: 1.0,
+			roughness: None,
+			order: 0,
+		}
+	}
+
+	fn to_gpu(self) -> GpuDecal {
+		let axis = |column: usize| {
+			let [x, y, z, _] = self.transform[column];
+			let length = (x * x + y * y + z * z).sqrt().max(f32::EPSILON);
+			[x / length, y / length, z / length]
+		};
+		let ([tx, ty, tz], [bx, by, bz], [nx, ny, nz]) = (axis(0), axis(2), axis(1));
+		GpuDecal {
+			world_to_decal: mat4_inverse(self.transform)
+				.expect("decal transform can't be inverted"),
+			tangent: [tx, ty, tz, self.normal_strength],
+			bitangent: [bx, by, bz, self.roughness.unwrap_or(-1.0)],
+			axis: [nx, ny, nz, self.layer as f32],
+			color: self.color,
+		}
+	}
+
+	// the sphere around the box, for culling
+	fn bounds(&self) -> ([f32; 3], f32) {
+		let [x, y, z, _] = self.transform[3];
+		let radius_squared: f32 = (0..3)
+			.map(|column| {
+				let [x, y, z, _] = self.transform[column];
+				dot([x, y, z], [x, y, z])
+			})
+			.sum();
+		([x, y, z], radius_squared.sqrt() * 0.5)
+	}
+}
+
+/// Decals that come and go, like bullet holes. Once full the oldest decal
+/// makes room for the next.
+#[derive(Debug, Clone)]
+pub struct DecalPool {
+	decals: VecDeque<Decal>,
+	capacity: usize,
+}
+
+impl DecalPool {
+	pub fn new(capacity: usize) -> Self {
+		DecalPool {
+			decals: VecDeque::with_capacity(capacity),
+			capacity,
+		}
+	}
+
+	pub fn push(&mut self, decal: Decal) {
+		if self.capacity == 0 {
+			return;
+		}
+		if self.decals.len() == self.capacity {
+			self.decals.pop_front();
+		}
+		self.decals.push_back(decal);
+	}
+
+	pub fn clear(&mut self) {
+		self.decals.clear();
+	}
+
+	pub fn len(&self) -> usize {
+		self.decals.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.decals.is_empty()
+	}
+
+	/// Oldest first.
+	pub fn iter(&self) -> impl Iterator<Item = &Decal> {
+		self.decals.iter()
+	}
+}
+
+/// Layers decals project, `Decal::layer` picks one. Albedo alpha is the
+/// decal's coverage, normals are tangent space with x along the box's x axis.
+/// Both arrays need the same number of layers.
+#[derive(Clone)]
+pub struct DecalTextures {
+	pub albedo: Texture2DArray,
+	pub normals: Option<Texture2DArray>,
+}
+
+/// The images of the opaque pass decals are blended into, all the same size
+/// as the depth. They need storage usage in `DECAL_TARGET_FORMAT`.
+#[derive(Clone)]
+pub struct DecalTargets {
+	/// Single sampled scene depth.
+	pub depth: Texture,
+	/// Surface color, alpha is kept.
+	pub albedo: Texture,
+	/// World space normals packed as `n * 0.5 + 0.5`.
+	pub normals: Option<Texture>,
+	/// Occlusion, roughness and metallic in red, green and blue like gltf.
+	pub material: Option<Texture>,
+}
+
+/// Projects decals into the scene's surface images between the opaque pass
+/// and lighting.
+///
+/// Every pixel is tested against each visible decal, so keep the count in
+/// the hundreds.
+pub struct DecalRenderer {
+	pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	decals: CpuBufferPool<GpuDecal>,
+	sampler: Arc<Sampler>,
+	// bound in place of the targets that aren't given
+	placeholder: Texture,
+}
+
+impl DecalRenderer {
+	pub fn new(device: Arc<Device>) -> Self {
+		let shader = project::Shader::load(device.clone()).unwrap();
+		let pipeline = Arc::new(
+			ComputePipeline::new(device.clone(), &shader.main_entry_point(), &(), None).unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		let placeholder = StorageImage::with_usage(
+			device.clone(),
+			ImageDimensions::Dim2d {
+				width: 1,
+				height: 1,
+				array_layers: 1,
+			},
+			DECAL_TARGET_FORMAT,
+			ImageUsage {
+				storage: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			device.active_queue_families(),
+		)
+		.unwrap();
+
+		DecalRenderer {
+			pipeline,
+			decals: CpuBufferPool::new(
+				device.clone(),
+				BufferUsage {
+					storage_buffer: true,
+					..BufferUsage::none()
+				},
+			),
+			sampler: Sampler::simple_repeat_linear(device),
+			placeholder: ImageView::new(placeholder).unwrap(),
+		}
+	}
+
+	/// Records the projection of the `decals` seen from `view_projection`.
+	/// Must be recorded outside of a render pass, after the opaque geometry.
+	pub fn draw<'a>(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		view_projection: Mat4,
+		targets: &DecalTargets,
+		textures: &DecalTextures,
+		decals: impl IntoIterator<Item = &'a Decal>,
+	) {
+		let frustum = Frustum::from_view_projection(view_projection);
+		let mut visible: Vec<&Decal> = decals
+			.into_iter()
+			.filter(|decal| {
+				let (center, radius) = decal.bounds();
+				frustum.intersects_sphere(center, radius)
+			})
+			.collect();
+		if visible.is_empty() {
+			return;
+		}
+		visible.sort_by_key(|decal| decal.order);
+		let count = visible.len() as u32;
+		let decals = self
+			.decals
+			.chunk(visible.into_iter().map(|decal| decal.to_gpu()))
+			.unwrap();
+
+		let mut flags = 0;
+		if targets.normals.is_some() {
+			flags |= HAS_NORMALS;
+		}
+		if targets.material.is_some() {
+			flags |= HAS_MATERIAL;
+		}
+		if textures.normals.is_some() {
+			flags |= HAS_NORMAL_LAYERS;
+		}
+		let normal_layers = textures.normals.as_ref().unwrap_or(&textures.albedo);
+
+		let dimensions = targets.depth.image().dimensions().width_height();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_buffer(decals)
+				.unwrap()
+				.add_sampled_image(targets.depth.clone(), self.sampler.clone())
+				.unwrap()
+				.add_sampled_image(textures.albedo.texture.clone(), self.sampler.clone())
+				.unwrap()
+				.add_sampled_image(normal_layers.texture.clone(), self.sampler.clone())
+				.unwrap()
+				.add_image(targets.albedo.clone())
+				.unwrap()
+				.add_image(
+					targets
+						.normals
+						.clone()
+						.unwrap_or_else(|| self.placeholder.clone()),
+				)
+				.unwrap()
+				.add_image(
+					targets
+						.material
+						.clone()
+						.unwrap_or_else(|| self.placeholder.clone()),
+				)
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let push_constants = project::ty::PushConstants {
+			inverse_view_projection: mat4_inverse(view_projection)
+				.expect("camera view projection can't be inverted"),
+			info: [dimensions[0], dimensions[1], count, flags],
+		};
+		builder
+			.dispatch(
+				group_counts([dimensions[0], dimensions[1], 1], [8, 8, 1]),
+				self.pipeline.clone(),
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
@@ -0,0 +1,296 @@
+// what the swapchain images mean to the display, and the settings the final
+// output transform needs to map the linear hdr scene onto it. also the
+// connected monitors and the video modes they offer.
+
+use vulkano::format::Format;
+use vulkano::instance::InstanceExtensions;
+use vulkano::swapchain::{Capabilities, ColorSpace};
+
+use winit::event_loop::EventLoopWindowTarget;
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::Fullscreen;
+
+/// Color encoding of the swapchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayOutput {
+	/// Regular 0..1 output, highlights are tonemapped to white.
+	#[default]
+	Sdr,
+	/// Linear rec. 709 in a float format where 1 is 80 nits and values above
+	/// 1 are brighter than sdr white. Common on windows.
+	Scrgb,
+	/// Rec. 2020 primaries with the st 2084 (pq) curve in 10 bit.
+	Hdr10,
+}
+
+impl DisplayOutput {
+	/// Formats the output can use, in order of preference.
+	pub fn formats(self) -> &'static [Format] {
+		match self {
+			DisplayOutput::Sdr => &[
+				Format::B8G8R8A8Srgb,
+				Format::R8G8B8A8Srgb,
+				Format::A8B8G8R8SrgbPack32,
+			],
+			DisplayOutput::Scrgb => &[Format::R16G16B16A16Sfloat],
+			DisplayOutput::Hdr10 => &[
+				Format::A2B10G10R10UnormPack32,
+				Format::A2R10G10B10UnormPack32,
+			],
+		}
+	}
+
+	pub fn color_space(self) -> ColorSpace {
+		match self {
+			DisplayOutput::Sdr => ColorSpace::SrgbNonLinear,
+			DisplayOutput::Scrgb => ColorSpace::ExtendedSrgbLinear,
+			DisplayOutput::Hdr10 => ColorSpace::Hdr10St2084,
+		}
+	}
+
+	pub fn is_hdr(self) -> bool {
+		self != DisplayOutput::Sdr
+	}
+
+	/// Surface format for the output, `None` when the surface doesn't offer it.
+	///
+	/// Sdr prefers srgb formats, so the hardware encodes gamma on write. Without
+	/// one it takes whatever else the surface offers, and the final pass has to
+	/// encode gamma itself, see `needs_gamma`.
+	pub fn surface_format(self, caps: &Capabilities) -> Option<(Format, ColorSpace)> {
+		let preferred = self.formats().iter().find_map(|format| {
+			caps.supported_formats
+				.iter()
+				.find(|&&(f, color_space)| f == *format && color_space == self.color_space())
+				.copied()
+		});
+		if self != DisplayOutput::Sdr {
+			return preferred;
+		}
+		preferred
+			.or_else(|| {
+				caps.supported_formats
+					.iter()
+					.find(|(_, color_space)| *color_space == ColorSpace::SrgbNonLinear)
+					.copied()
+			})
+			.or_else(|| caps.supported_formats.first().copied())
+	}
+
+	/// Whether writing linear color to a swapchain of `format` needs the
+	/// shader to apply the srgb curve first.
+	pub fn needs_gamma(self, format: Format) -> bool {
+		self == DisplayOutput::Sdr && !is_srgb(format)
+	}
+
+	/// `self` when the surface supports it, otherwise sdr.
+	pub fn resolve(self, caps: &Capabilities) -> DisplayOutput {
+		if self.surface_format(caps).is_some() {
+			self
+		} else {
+			if self.is_hdr() {
+				log::warn!(
+					"{:?} output isn't supported by the display, using sdr",
+					self
+				);
+			}
+			DisplayOutput::Sdr
+		}
+	}
+
+	/// Value of the `output_mode` push constant in the composite shaders.
+	pub(crate) fn index(self) -> u32 {
+		match self {
+			DisplayOutput::Sdr => 0,
+			DisplayOutput::Scrgb => 1,
+			DisplayOutput::Hdr10 => 2,
+		}
+	}
+}
+
+/// Whether the hardware converts linear values to srgb when writing `format`.
+/// Only covers the color formats surfaces offer.
+pub fn is_srgb(format: Format) -> bool {
+	matches!(
+		format,
+		Format::B8G8R8A8Srgb
+			| Format::R8G8B8A8Srgb
+			| Format::A8B8G8R8SrgbPack32
+			| Format::B8G8R8Srgb
+			| Format::R8G8B8Srgb
+	)
+}
+
+/// Adds `VK_EXT_swapchain_colorspace` to `extensions` when the loader has it.
+/// Surfaces only report hdr color spaces to instances created with it.
+pub fn with_hdr_extensions(extensions: InstanceExtensions) -> InstanceExtensions {
+	let supported = InstanceExtensions::supported_by_core()
+		.map(|supported| supported.ext_swapchain_colorspace)
+		.unwrap_or(false);
+	InstanceExtensions {
+		ext_swapchain_colorspace: supported,
+		..extensions
+	}
+}
+
+/// Brightness of the display for hdr output, in nits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrSettings {
+	/// Brightness of a scene value of 1, ie. white paper and ui.
+	pub paper_white: f32,
+	/// Brightest the display can show, highlights roll off towards it.
+	pub peak_brightness: f32,
+}
+
+impl Default for HdrSettings {
+	fn default() -> Self {
+		HdrSettings {
+			paper_white: 200.0,
+			peak_brightness: 1000.0,
+		}
+	}
+}
+
+impl HdrSettings {
+	/// Brightest scene value the tonemapper outputs, relative to paper white.
+	pub fn max_output(&self, output: DisplayOutput) -> f32 {
+		if output.is_hdr() {
+			(self.peak_brightness / self.paper_white).max(1.0)
+		} else {
+			1.0
+		}
+	}
+}
+
+/// A connected monitor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+	pub name: Option<String>,
+	/// Top left corner on the desktop, in physical pixels.
+	pub position: [i32; 2],
+	/// Current resolution in physical pixels.
+	pub size: [u32; 2],
+	pub scale_factor: f64,
+	pub primary: bool,
+	handle: MonitorHandle,
+}
+
+impl Monitor {
+	pub fn from_handle(handle: MonitorHandle, primary: bool) -> Self {
+		let position = handle.position();
+		Monitor {
+			name: handle.name(),
+			position: [position.x, position.y],
+			size: handle.size().into(),
+			scale_factor: handle.scale_factor(),
+			primary,
+			handle,
+		}
+	}
+
+	pub fn handle(&self) -> &MonitorHandle {
+		&self.handle
+	}
+
+	/// Every mode the monitor supports, largest and fastest first.
+	pub fn video_modes(&self) -> Vec<DisplayMode> {
+		let mut modes: Vec<DisplayMode> = self.handle.video_modes().map(DisplayMode::new).collect();
+		modes.sort_by(|a, b| {
+			(b.size[0] * b.size[1], b.refresh_rate, b.bit_depth).cmp(&(
+				a.size[0] * a.size[1],
+				a.refresh_rate,
+				a.bit_depth,
+			))
+		});
+		modes.dedup_by(|a, b| {
+			a.size == b.size && a.refresh_rate == b.refresh_rate && a.bit_depth == b.bit_depth
+		});
+		modes
+	}
+
+	/// Distinct resolutions, largest first, eg. for a settings menu.
+	pub fn resolutions(&self) -> Vec<[u32; 2]> {
+		let mut sizes: Vec<[u32; 2]> = self.video_modes().iter().map(|mode| mode.size).collect();
+		sizes.dedup();
+		sizes
+	}
+
+	/// Refresh rates offered at `size`, fastest first.
+	pub fn refresh_rates(&self, size: [u32; 2]) -> Vec<u16> {
+		let mut rates: Vec<u16> = self
+			.video_modes()
+			.iter()
+			.filter(|mode| mode.size == size)
+			.map(|mode| mode.refresh_rate)
+			.collect();
+		rates.dedup();
+		rates
+	}
+
+	/// The mode with exactly `size`, at `refresh_rate` if given or the fastest
+	/// one otherwise, with the most bits per pixel.
+	pub fn find_mode(&self, size: [u32; 2], refresh_rate: Option<u16>) -> Option<DisplayMode> {
+		self.video_modes().into_iter().find(|mode| {
+			mode.size == size && refresh_rate.is_none_or(|rate| mode.refresh_rate == rate)
+		})
+	}
+
+	/// The mode matching the current resolution. Winit doesn't report the
+	/// current refresh rate, so it's the fastest one at that resolution.
+	pub fn current_mode(&self) -> Option<DisplayMode> {
+		self.find_mode(self.size, None)
+	}
+
+	/// Borderless fullscreen on this monitor at its current mode.
+	pub fn borderless(&self) -> Fullscreen {
+		Fullscreen::Borderless(Some(self.handle.clone()))
+	}
+}
+
+/// A resolution, refresh rate and bit depth a monitor can switch to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayMode {
+	/// Resolution in physical pixels.
+	pub size: [u32; 2],
+	/// In hertz.
+	pub refresh_rate: u16,
+	/// Bits per pixel.
+	pub bit_depth: u16,
+	mode: VideoMode,
+}
+
+impl DisplayMode {
+	pub fn new(mode: VideoMode) -> Self {
+		DisplayMode {
+			size: mode.size().into(),
+			refresh_rate: mode.refresh_rate(),
+			bit_depth: mode.bit_depth(),
+			mode,
+		}
+	}
+
+	pub fn video_mode(&self) -> &VideoMode {
+		&self.mode
+	}
+
+	/// Exclusive fullscreen switching the monitor to this mode, pass it to
+	/// `WindowTarget::set_fullscreen`.
+	pub fn exclusive(&self) -> Fullscreen {
+		Fullscreen::Exclusive(self.mode.clone())
+	}
+}
+
+/// The connected monitors, the primary one first when the platform knows
+/// which it is.
+pub fn monitors<T>(event_loop: &EventLoopWindowTarget<T>) -> Vec<Monitor> {
+	let primary = event_loop.primary_monitor();
+	let mut monitors: Vec<Monitor> = event_loop
+		.available_monitors()
+		.map(|handle| {
+			let is_primary = primary.as_ref() == Some(&handle);
+			Monitor::from_handle(handle, is_primary)
+		})
+		.collect();
+	monitors.sort_by_key(|monitor| !monitor.primary);
+	monitors
+}
@@ -0,0 +1,587 @@
+// the usual setup in one call: instance, gpu, device, window and renderer,
+// configured from `opal.toml` and the app.
+
+use crate::assets::Assets;
+use crate::config::EngineConfig;
+use crate::display::with_hdr_extensions;
+use crate::file_drop::{AssetKind, FileDrop};
+use crate::gpu::VulkanDevice;
+use crate::portability;
+use crate::renderer::Renderer;
+use crate::replay::Replay;
+use crate::requirements::{self, Fallback, Granted, Missing, RequirementsReport};
+use crate::secondary::SecondaryDevice;
+use crate::settings::GraphicsSettings;
+use crate::window::{SwapchainConfig, WindowTarget};
+
+use vulkano::device::{Device, DeviceExtensions, Features};
+use vulkano::instance::debug::DebugCallback;
+use vulkano::instance::{layers_list, InstanceExtensions};
+
+use winit::dpi::LogicalSize;
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopWindowTarget};
+use winit::window::{Fullscreen, WindowBuilder};
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Name of the layer `EngineConfig::validation` enables.
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+type FileDropCallback = Box<dyn FnMut(&FileDrop)>;
+type FocusCallback = Box<dyn FnMut(bool)>;
+
+/// Owns the vulkan instance, the device and the main window's renderer.
+pub struct Engine {
+	config: EngineConfig,
+	gpu: VulkanDevice,
+	// `None` when headless
+	renderer: Option<Renderer>,
+	assets: Assets,
+	file_drop_callbacks: Vec<FileDropCallback>,
+	load_dropped_files: bool,
+	focused: bool,
+	focus_callbacks: Vec<FocusCallback>,
+	// when `frame_due` last said yes
+	last_frame: Option<Instant>,
+	// created on first use
+	secondary: Option<SecondaryDevice>,
+	requirements: RequirementsReport,
+	granted: Granted,
+	replay: Replay,
+	// validation messages stop when this is dropped
+	_debug_callback: Option<DebugCallback>,
+}
+
+impl Engine {
+	/// Sets up the engine from `opal.toml` if there is one, see
+	/// `EngineConfig::load_or_default`. `overrides` gets to change the loaded
+	/// config before anything is created, eg. to force a window title or to
+	/// apply `EngineArgs`.
+	pub fn new<T, F>(event_loop: &EventLoopWindowTarget<T>, overrides: F) -> Self
+	where
+		F: FnOnce(&mut EngineConfig),
+	{
+		let mut config = EngineConfig::load_or_default();
+		overrides(&mut config);
+		Engine::with_config(event_loop, config)
+	}
+
+	/// Sets up the engine like `new`, with required and optional features
+	/// and extensions asked for on the returned builder.
+	pub fn builder() -> EngineBuilder {
+		EngineBuilder {
+			config: EngineConfig::load_or_default(),
+		}
+	}
+
+	/// Sets up the engine from `config` alone, without looking for a file.
+	/// Panics with the report when the gpu doesn't meet
+	/// `EngineConfig::requirements`, see `try_with_config`.
+	pub fn with_config<T>(event_loop: &EventLoopWindowTarget<T>, config: EngineConfig) -> Self {
+		Engine::try_with_config(event_loop, config).unwrap_or_else(|report| panic!("{}", report))
+	}
+
+	/// Like `new`, but returns what the gpu lacks instead of panicking, for
+	/// the app to show to the player.
+	pub fn try_new<T, F>(
+		event_loop: &EventLoopWindowTarget<T>,
+		overrides: F,
+	) -> Result<Self, RequirementsReport>
+	where
+		F: FnOnce(&mut EngineConfig),
+	{
+		let mut config = EngineConfig::load_or_default();
+		overrides(&mut config);
+		Engine::try_with_config(event_loop, config)
+	}
+
+	/// Like `with_config`, but returns what the gpu lacks instead of
+	/// panicking.
+	pub fn try_with_config<T>(
+		event_loop: &EventLoopWindowTarget<T>,
+		config: EngineConfig,
+	) -> Result<Self, RequirementsReport> {
+		init_logging(config.log_level);
+
+		let validation = config.validation && has_validation_layer();
+		if config.validation && !validation {
+			log::warn!(
+				"{} isn't installed, running without validation",
+				VALIDATION_LAYER
+			);
+		}
+		let debug_labels = config.debug_labels && has_debug_utils();
+		let extensions = InstanceExtensions {
+			ext_debug_utils: validation || debug_labels,
+			..if config.headless {
+				InstanceExtensions::none()
+			} else {
+				with_hdr_extensions(vulkano_win::required_extensions())
+			}
+		};
+		let layers = if validation {
+			Some(VALIDATION_LAYER)
+		} else {
+			None
+		};
+		let instance = portability::create_instance(extensions, layers).unwrap();
+		let debug_callback = if validation {
+			DebugCallback::errors_and_warnings(&instance, |message| {
+				log::warn!(
+					"{}: {}",
+					message.layer_prefix.unwrap_or("validation"),
+					message.description
+				);
+			})
+			.ok()
+		} else {
+			None
+		};
+
+		let physical = config
+			.gpu
+			.select(&instance)
+			.ok_or_else(RequirementsReport::no_gpu)?;
+		log::info!(
+			"Using device: {} (type: {:?})",
+			physical.name(),
+			physical.ty()
+		);
+
+		let mut requirements = config.requirements.clone();
+		requirements.extensions.khr_swapchain &= !config.headless;
+		let mut report = requirements.check(physical);
+		if config.validation && !validation {
+			report.fallbacks.push(Fallback {
+				missing: VALIDATION_LAYER,
+				instead: "running without validation",
+			});
+		}
+		if !report.is_supported() {
+			return Err(report);
+		}
+		if !report.fallbacks.is_empty() {
+			log::warn!("{}", report);
+		}
+		let granted = requirements.negotiate(physical);
+
+		// the window comes first so the queue family can be checked against
+		// its surface
+		let surface = if config.headless {
+			None
+		} else {
+			let mut builder = WindowBuilder::new()
+				.with_title(config.title.clone())
+				.with_inner_size(LogicalSize::new(config.width, config.height));
+			if config.fullscreen {
+				builder = builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+			}
+			let window = Arc::new(builder.build(event_loop).unwrap());
+			Some(vulkano_win::create_vk_surface(window, instance.clone()).unwrap())
+		};
+		let family = match physical.queue_families().find(|&q| {
+			q.supports_graphics()
+				&& match &surface {
+					Some(surface) => surface.is_supported(q).unwrap_or(false),
+					None => true,
+				}
+		}) {
+			Some(family) => family,
+			None => {
+				report.missing.push(Missing::Present);
+				return Err(report);
+			}
+		};
+		// a family without graphics has its own hardware queue for async compute
+		let compute_family = physical
+			.queue_families()
+			.find(|q| q.supports_compute() && !q.supports_graphics());
+		// a second queue for background uploads when the family has one
+		let mut queue_requests: Vec<_> = [(family, 1.0), (family, 0.5)]
+			.iter()
+			.take(family.queues_count().min(2))
+			.cloned()
+			.collect();
+		queue_requests.extend(compute_family.map(|compute| (compute, 1.0)));
+		let (device, mut queues) = Device::new(
+			physical,
+			&granted.features,
+			granted.raw_extensions(),
+			queue_requests,
+		)
+		.unwrap();
+		let queue = queues.next().unwrap();
+		let upload_queue = if family.queues_count() >= 2 {
+			queues.next()
+		} else {
+			None
+		};
+		let compute_queue = compute_family.and_then(|_| queues.next());
+		let gpu = VulkanDevice::new(device.clone(), queue.clone());
+
+		let renderer = surface.map(|surface| {
+			let swapchain = SwapchainConfig {
+				vsync: config.vsync,
+				..SwapchainConfig::default()
+			};
+			let window =
+				WindowTarget::from_surface(surface, device.clone(), queue.clone(), swapchain);
+
+			let settings = GraphicsSettings {
+				vsync: config.vsync,
+				..GraphicsSettings::default()
+			};
+			let mut renderer = Renderer::new(gpu.clone(), window, settings);
+			if config.breadcrumbs {
+				renderer.set_breadcrumbs(true);
+			}
+			renderer.set_frame_budget(config.frame_budget);
+			if let Some(compute_queue) = &compute_queue {
+				renderer
+					.async_compute_mut()
+					.set_queue(compute_queue.clone());
+			}
+			renderer
+		});
+
+		let mut assets = Assets::new(device.clone(), queue.clone(), config.asset_paths.clone());
+		if let Some(upload_queue) = upload_queue {
+			assets.set_upload_queue(upload_queue);
+		}
+		if config.hot_reload {
+			assets.watch(Duration::from_millis(500));
+		}
+
+		let replay = Replay::from_mode(&config.replay);
+
+		Ok(Engine {
+			config,
+			gpu,
+			renderer,
+			assets,
+			file_drop_callbacks: Vec::new(),
+			load_dropped_files: true,
+			focused: true,
+			focus_callbacks: Vec::new(),
+			last_frame: None,
+			secondary: None,
+			requirements: report,
+			granted,
+			replay,
+			_debug_callback: debug_callback,
+		})
+	}
+
+	/// What the gpu lacks and the fallbacks in use because of it.
+	pub fn requirements(&self) -> &RequirementsReport {
+		&self.requirements
+	}
+
+	/// The features and extensions the device was created with, to check
+	/// which of the optional ones the gpu had.
+	pub fn granted(&self) -> &Granted {
+		&self.granted
+	}
+
+	/// The config the engine was set up with.
+	pub fn config(&self) -> &EngineConfig {
+		&self.config
+	}
+
+	/// The device, for gpu work outside of the renderer.
+	pub fn gpu(&self) -> &VulkanDevice {
+		&self.gpu
+	}
+
+	/// A device for background gpu work, see `SecondaryDevice`. Created the
+	/// first time it's asked for, `None` when that failed.
+	pub fn secondary_device(&mut self) -> Option<&SecondaryDevice> {
+		if self.secondary.is_none() {
+			self.secondary = SecondaryDevice::new(self.gpu.device());
+		}
+		self.secondary.as_ref()
+	}
+
+	/// The main window's renderer, `None` when running headless.
+	pub fn renderer(&self) -> Option<&Renderer> {
+		self.renderer.as_ref()
+	}
+
+	pub fn renderer_mut(&mut self) -> Option<&mut Renderer> {
+		self.renderer.as_mut()
+	}
+
+	pub fn assets(&self) -> &Assets {
+		&self.assets
+	}
+
+	pub fn assets_mut(&mut self) -> &mut Assets {
+		&mut self.assets
+	}
+
+	/// Handles the main window's events the engine cares about: resizes,
+	/// scale factor changes, focus, dropped files and the app being suspended
+	/// and resumed. Call it with every event.
+	pub fn event<T>(&mut self, event: &Event<T>) {
+		self.replay.event(event);
+		let event = match (event, self.renderer.as_mut()) {
+			(Event::WindowEvent { window_id, event }, Some(renderer))
+				if *window_id == renderer.window().id() =>
+			{
+				event
+			}
+			(Event::Suspended, Some(renderer)) => return renderer.window_mut().suspend(),
+			(Event::Resumed, Some(renderer)) => return renderer.window_mut().resume(),
+			_ => return,
+		};
+		let renderer = self.renderer.as_mut().unwrap();
+		match event {
+			WindowEvent::Resized(_) => renderer.resized(),
+			WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+				renderer.scale_factor_changed(*scale_factor)
+			}
+			WindowEvent::Focused(focused) => {
+				renderer.window_mut().focused(*focused);
+				self.focused = *focused;
+				for callback in self.focus_callbacks.iter_mut() {
+					callback(*focused);
+				}
+			}
+			WindowEvent::HoveredFile(path) => self.file_drop(FileDrop::Hovered(path.clone())),
+			WindowEvent::HoveredFileCancelled => self.file_drop(FileDrop::Cancelled),
+			WindowEvent::DroppedFile(path) => {
+				let asset = match AssetKind::of(path) {
+					Some(kind) if self.load_dropped_files => {
+						Some(kind.load(&mut self.assets, path))
+					}
+					_ => None,
+				};
+				self.file_drop(FileDrop::Dropped {
+					path: path.clone(),
+					asset,
+				});
+			}
+			_ => {}
+		}
+	}
+
+	/// Input recording and playback from `EngineConfig::replay`, and the
+	/// frame's time step.
+	pub fn replay(&self) -> &Replay {
+		&self.replay
+	}
+
+	pub fn replay_mut(&mut self) -> &mut Replay {
+		&mut self.replay
+	}
+
+	/// Whether the main window has focus.
+	pub fn focused(&self) -> bool {
+		self.focused
+	}
+
+	/// Calls `callback` whenever the main window gains or loses focus.
+	pub fn on_focus_change<F>(&mut self, callback: F)
+	where
+		F: FnMut(bool) + 'static,
+	{
+		self.focus_callbacks.push(Box::new(callback));
+	}
+
+	/// Whether to draw a frame now under `EngineConfig::unfocused`, counting
+	/// it as drawn when it is. Always true while the window has focus.
+	///
+	/// ```text
+	/// Event::MainEventsCleared => {
+	///     if engine.frame_due() {
+	///         engine.renderer().unwrap().window().window().request_redraw();
+	///     }
+	///     *control_flow = engine.control_flow();
+	/// }
+	/// ```
+	pub fn frame_due(&mut self) -> bool {
+		let due = match (self.focused, self.config.unfocused.interval()) {
+			(true, _) => true,
+			(false, None) => false,
+			(false, Some(interval)) => self
+				.last_frame
+				.is_none_or(|last| last.elapsed() >= interval),
+		};
+		if due {
+			self.last_frame = Some(Instant::now());
+		}
+		due
+	}
+
+	/// How the event loop should wait for the next frame, so a paused or
+	/// throttled window sleeps instead of spinning.
+	pub fn control_flow(&self) -> ControlFlow {
+		match (self.focused, self.config.unfocused.interval()) {
+			(true, _) => ControlFlow::Poll,
+			(false, None) => ControlFlow::Wait,
+			(false, Some(interval)) if interval.is_zero() => ControlFlow::Poll,
+			(false, Some(interval)) => {
+				let last = self.last_frame.unwrap_or_else(Instant::now);
+				match last.checked_add(interval) {
+					Some(due) => ControlFlow::WaitUntil(due),
+					None => ControlFlow::Wait,
+				}
+			}
+		}
+	}
+
+	/// Calls `callback` for files dragged over and dropped onto the main
+	/// window, eg. to show a model dropped from the file manager.
+	pub fn on_file_drop<F>(&mut self, callback: F)
+	where
+		F: FnMut(&FileDrop) + 'static,
+	{
+		self.file_drop_callbacks.push(Box::new(callback));
+	}
+
+	/// Whether dropped meshes and textures are loaded before the callbacks
+	/// see them, on by default.
+	pub fn set_load_dropped_files(&mut self, load: bool) {
+		self.load_dropped_files = load;
+	}
+
+	fn file_drop(&mut self, drop: FileDrop) {
+		if let FileDrop::Dropped {
+			path,
+			asset: Some(Err(error)),
+		} = &drop
+		{
+			log::warn!("Failed to load {}: {}", path.display(), error);
+		}
+		for callback in self.file_drop_callbacks.iter_mut() {
+			callback(&drop);
+		}
+	}
+
+	/// `path` inside the first asset directory that has it.
+	pub fn find_asset(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
+		self.config
+			.asset_paths
+			.iter()
+			.map(|dir| dir.join(path.as_ref()))
+			.find(|candidate| candidate.exists())
+	}
+}
+
+/// Asks for device features and extensions before the engine is set up:
+///
+/// ```text
+/// let engine = Engine::builder()
+///     .require_features(Features { shader_float64: true, ..Features::none() })
+///     .request_extension_names(capabilities::DISPLAY_TIMING_EXTENSIONS)
+///     .build(&event_loop)?;
+/// let display_timing = engine.granted().has_all(capabilities::DISPLAY_TIMING_EXTENSIONS);
+/// ```
+///
+/// Required ones fail `build` when the gpu lacks them, requested ones are
+/// enabled when it has them.
+pub struct EngineBuilder {
+	config: EngineConfig,
+}
+
+impl EngineBuilder {
+	/// Changes the config loaded from `opal.toml`, like `Engine::new`.
+	pub fn config<F>(mut self, overrides: F) -> Self
+	where
+		F: FnOnce(&mut EngineConfig),
+	{
+		overrides(&mut self.config);
+		self
+	}
+
+	pub fn require_features(mut self, features: Features) -> Self {
+		let requirements = &mut self.config.requirements;
+		requirements.features = requirements::union_features(&requirements.features, &features);
+		self
+	}
+
+	pub fn request_features(mut self, features: Features) -> Self {
+		let requirements = &mut self.config.requirements;
+		requirements.optional_features =
+			requirements::union_features(&requirements.optional_features, &features);
+		self
+	}
+
+	pub fn require_extensions(mut self, extensions: DeviceExtensions) -> Self {
+		let requirements = &mut self.config.requirements;
+		requirements.extensions = requirements.extensions.union(&extensions);
+		self
+	}
+
+	pub fn request_extensions(mut self, extensions: DeviceExtensions) -> Self {
+		let requirements = &mut self.config.requirements;
+		requirements.optional_extensions = requirements.optional_extensions.union(&extensions);
+		self
+	}
+
+	/// Extensions by name, eg. `capabilities::DISPLAY_TIMING_EXTENSIONS`.
+	pub fn require_extension_names(mut self, names: &[&str]) -> Self {
+		let requirements = &mut self.config.requirements;
+		requirements
+			.extension_names
+			.extend(names.iter().map(|name| name.to_string()));
+		self
+	}
+
+	pub fn request_extension_names(mut self, names: &[&str]) -> Self {
+		let requirements = &mut self.config.requirements;
+		requirements
+			.optional_extension_names
+			.extend(names.iter().map(|name| name.to_string()));
+		self
+	}
+
+	/// Sets up the engine, or says what the gpu lacks.
+	pub fn build<T>(
+		self,
+		event_loop: &EventLoopWindowTarget<T>,
+	) -> Result<Engine, RequirementsReport> {
+		Engine::try_with_config(event_loop, self.config)
+	}
+}
+
+// prints records to stdout, every message of the engine goes through here
+struct StdoutLogger;
+
+impl log::Log for StdoutLogger {
+	fn enabled(&self, metadata: &log::Metadata) -> bool {
+		metadata.level() <= log::max_level()
+	}
+
+	fn log(&self, record: &log::Record) {
+		if self.enabled(record.metadata()) {
+			println!("[{}] {}", record.level(), record.args());
+		}
+	}
+
+	fn flush(&self) {}
+}
+
+static LOGGER: StdoutLogger = StdoutLogger;
+
+// installs `LOGGER` unless the app already set up a logger of its own, either
+// way `level` decides what gets through
+pub(crate) fn init_logging(level: log::LevelFilter) {
+	log::set_logger(&LOGGER).ok();
+	log::set_max_level(level);
+}
+
+fn has_debug_utils() -> bool {
+	InstanceExtensions::supported_by_core()
+		.map(|supported| supported.ext_debug_utils)
+		.unwrap_or(false)
+}
+
+fn has_validation_layer() -> bool {
+	layers_list()
+		.map(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER))
+		.unwrap_or(false)
+}
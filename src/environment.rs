@@ -0,0 +1,308 @@
+// time of day and weather in one place: the clock moves the sun along its
+// path for the latitude and day of the year, the weather sets the haze, fog
+// and ambient light, and `apply` and `apply_sun` hand both to the sky, the
+// fog and the sun's node every frame. a day/night cycle is `update` plus `apply`, nothing in
+// the renderer has to change for it.
+
+use crate::color::Color;
+use crate::fog::{FogLight, FogParams, VolumetricFog};
+use crate::math::{cross, dot, lerp, normalize, quat_normalize, Mat4, Quat, Vec3};
+use crate::render2d::Texture;
+use crate::scene::{Light, NodeId, Scene};
+use crate::sky::{Sky, SkyParams};
+
+use std::f32::consts::TAU;
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+/// Haze, fog and ambient light, see `Environment::transition_to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weather {
+	/// Haze in the air, see `SkyParams::turbidity`.
+	pub turbidity: f32,
+	pub fog: FogParams,
+	/// Light everything gets on top of the sky, keeps nights from going
+	/// completely black.
+	pub ambient: Color,
+}
+
+impl Default for Weather {
+	fn default() -> Self {
+		Weather::clear()
+	}
+}
+
+impl Weather {
+	pub fn clear() -> Self {
+		Weather {
+			turbidity: 2.5,
+			fog: FogParams {
+				density: 0.002,
+				..FogParams::default()
+			},
+			ambient: Color::rgb(0.01, 0.012, 0.02),
+		}
+	}
+
+	pub fn hazy() -> Self {
+		Weather {
+			turbidity: 6.0,
+			fog: FogParams {
+				color: [0.6, 0.62, 0.65],
+				density: 0.01,
+				..FogParams::default()
+			},
+			ambient: Color::rgb(0.02, 0.02, 0.025),
+		}
+	}
+
+	pub fn foggy() -> Self {
+		Weather {
+			turbidity: 10.0,
+			fog: FogParams {
+				color: [0.55, 0.57, 0.6],
+				density: 0.06,
+				height_falloff: 0.03,
+				..FogParams::default()
+			},
+			ambient: Color::rgb(0.03, 0.03, 0.035),
+		}
+	}
+
+	/// Linear interpolation of every parameter.
+	pub fn lerp(&self, other: &Weather, t: f32) -> Weather {
+		let mix = |a: f32, b: f32| a + (b - a) * t;
+		let (a, b) = (&self.fog, &other.fog);
+		Weather {
+			turbidity: mix(self.turbidity, other.turbidity),
+			fog: FogParams {
+				color: lerp(a.color, b.color, t),
+				density: mix(a.density, b.density),
+				base_height: mix(a.base_height, b.base_height),
+				height_falloff: mix(a.height_falloff, b.height_falloff),
+				start_distance: mix(a.start_distance, b.start_distance),
+				max_distance: mix(a.max_distance, b.max_distance),
+				anisotropy: mix(a.anisotropy, b.anisotropy),
+			},
+			ambient: self.ambient.lerp(other.ambient, t),
+		}
+	}
+}
+
+// a weather change in progress
+#[derive(Debug, Clone, Copy)]
+struct Transition {
+	from: Weather,
+	to: Weather,
+	elapsed: f32,
+	duration: f32,
+}
+
+/// The sun, sky, fog and ambient light of a level, changed at runtime:
+///
+/// ```text
+/// environment.time_scale = 24.0 / (20.0 * 60.0); // a day in 20 minutes
+/// environment.transition_to(Weather::foggy(), 30.0);
+/// // every frame
+/// environment.update(delta);
+/// environment.apply(&mut sky, &mut fog);
+/// environment.apply_sun(&mut scene, sun);
+/// if sky.update(&mut builder) {
+///     // prefilter the environment probes again
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Environment {
+	/// Hours since midnight, from 0 to 24. Noon has the sun highest.
+	pub time_of_day: f32,
+	/// Hours the clock advances per second of `update`, 0 stops it.
+	pub time_scale: f32,
+	/// 0 to 365, moves the sun's path north and south with the seasons.
+	pub day_of_year: f32,
+	/// Latitude in degrees, 0 at the equator.
+	pub latitude: f32,
+	/// Angle in radians from -z to north around +y. East is +x when 0.
+	pub north: f32,
+	/// Brightness of sunlight on a clear day, the intensity of the sun's
+	/// directional light.
+	pub sun_intensity: f32,
+	/// Everything but the sun direction and turbidity, those come from the
+	/// clock and the weather.
+	pub sky: SkyParams,
+	weather: Weather,
+	transition: Option<Transition>,
+}
+
+impl Default for Environment {
+	fn default() -> Self {
+		Environment {
+			time_of_day: 10.0,
+			time_scale: 0.0,
+			// an equinox, the sun rises in the east and sets in the west
+			day_of_year: 80.0,
+			latitude: 45.0,
+			north: 0.0,
+			sun_intensity: 3.0,
+			sky: SkyParams::default(),
+			weather: Weather::default(),
+			transition: None,
+		}
+	}
+}
+
+impl Environment {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Advances the clock and any weather transition by `delta` seconds.
+	pub fn update(&mut self, delta: f32) {
+		self.time_of_day = (self.time_of_day + delta * self.time_scale).rem_euclid(HOURS_PER_DAY);
+		if let Some(mut transition) = self.transition.take() {
+			transition.elapsed += delta;
+			let t = (transition.elapsed / transition.duration).min(1.0);
+			self.weather = transition.from.lerp(&transition.to, t);
+			if t < 1.0 {
+				self.transition = Some(transition);
+			}
+		}
+	}
+
+	/// The weather right now, partway through a transition.
+	pub fn weather(&self) -> &Weather {
+		&self.weather
+	}
+
+	/// Changes the weather at once, ending any transition.
+	pub fn set_weather(&mut self, weather: Weather) {
+		self.weather = weather;
+		self.transition = None;
+	}
+
+	/// Blends from the current weather to `weather` over `duration` seconds
+	/// of `update`.
+	pub fn transition_to(&mut self, weather: Weather, duration: f32) {
+		if duration <= 0.0 {
+			return self.set_weather(weather);
+		}
+		self.transition = Some(Transition {
+			from: self.weather,
+			to: weather,
+			elapsed: 0.0,
+			duration,
+		});
+	}
+
+	/// Direction towards the sun, +y is up. Below the horizon at night.
+	pub fn sun_direction(&self) -> Vec3 {
+		// declination after cooper, the hour angle is 0 at noon
+		let declination =
+			(-23.44f32).to_radians() * (TAU * (self.day_of_year + 10.0) / 365.0).cos();
+		let hour_angle = (self.time_of_day / HOURS_PER_DAY - 0.5) * TAU;
+		let latitude = self.latitude.to_radians();
+		let (sin_d, cos_d) = declination.sin_cos();
+		let (sin_l, cos_l) = latitude.sin_cos();
+		let meridian = cos_d * hour_angle.cos();
+		let east = -cos_d * hour_angle.sin();
+		let up = cos_l * meridian + sin_l * sin_d;
+		let north = -sin_l * meridian + cos_l * sin_d;
+		// east is +x and north -z, turned by `north`
+		let (sin_n, cos_n) = self.north.sin_cos();
+		normalize([
+			east * cos_n - north * sin_n,
+			up,
+			-east * sin_n - north * cos_n,
+		])
+	}
+
+	/// Whether the sun is above the horizon.
+	pub fn is_day(&self) -> bool {
+		self.sun_direction()[1] > 0.0
+	}
+
+	/// The sky for the current time and weather.
+	pub fn sky_params(&self) -> SkyParams {
+		SkyParams {
+			sun_direction: self.sun_direction(),
+			turbidity: self.weather.turbidity,
+			..self.sky
+		}
+	}
+
+	/// Color of direct sunlight times `sun_intensity`, black at night.
+	pub fn sun_light(&self) -> Vec3 {
+		self.sky_params()
+			.sun_color()
+			.map(|c| c * self.sun_intensity)
+	}
+
+	pub fn ambient(&self) -> Color {
+		self.weather.ambient
+	}
+
+	/// The weather's fog, its color darkening to the ambient light as the
+	/// sun sets.
+	pub fn fog_params(&self) -> FogParams {
+		let fog = self.weather.fog;
+		let sun = self.sun_direction()[1];
+		let daylight = ((sun + 0.1) / 0.3).clamp(0.0, 1.0);
+		FogParams {
+			color: lerp(self.weather.ambient.to_rgb(), fog.color, daylight),
+			..fog
+		}
+	}
+
+	/// The sun shining through the fog, with its shadow map if there is one.
+	pub fn fog_light(&self, shadow: Option<(Texture, Mat4)>) -> FogLight {
+		FogLight {
+			direction: self.sun_direction(),
+			color: self.sun_light(),
+			shadow,
+		}
+	}
+
+	/// Hands the sky and fog their parameters for this frame. The sky only
+	/// bakes again once the sun moved noticeably, see `Sky::update`.
+	pub fn apply(&self, sky: &mut Sky, fog: &mut VolumetricFog) {
+		sky.params = self.sky_params();
+		fog.params = self.fog_params();
+	}
+
+	/// Points the directional light of `sun` away from the sun and sets its
+	/// color and intensity. `sun` should be a root node, its rotation is
+	/// replaced.
+	pub fn apply_sun(&self, scene: &mut Scene, sun: NodeId) {
+		let direction = self.sun_direction();
+		let color = self.sky_params().sun_color();
+		let node = match scene.get_mut(sun) {
+			Some(node) => node,
+			None => return,
+		};
+		// directional lights shine along -z
+		node.transform.rotation = rotation_between(
+			[0.0, 0.0, -1.0],
+			[-direction[0], -direction[1], -direction[2]],
+		);
+		node.light = Some(Light::Directional {
+			color: Color::from(color),
+			intensity: self.sun_intensity,
+		});
+	}
+}
+
+// shortest rotation turning unit vector `from` onto `to`
+fn rotation_between(from: Vec3, to: Vec3) -> Quat {
+	let d = dot(from, to);
+	if d < -0.9999 {
+		// opposite, half a turn around any perpendicular axis
+		let axis = if from[0].abs() < 0.9 {
+			cross(from, [1.0, 0.0, 0.0])
+		} else {
+			cross(from, [0.0, 1.0, 0.0])
+		};
+		let [x, y, z] = normalize(axis);
+		return [x, y, z, 0.0];
+	}
+	let [x, y, z] = cross(from, to);
+	quat_normalize([x, y, z, 1.0 + d])
+}
@@ -0,0 +1,51 @@
+// per-instance fade values for screen-door dithering, see
+// `opal/dither.glsl`. one value carries both the lod crossfade and the fade
+// of things close to the camera, eg. a plant the camera passes through, which
+// would otherwise cut open at the near plane.
+//
+// the value goes to the shader with the instance, eg. in its push constants,
+// and the material's shader is compiled with `Feature::DitherFade`. 1 draws
+// the instance fully, 0 not at all.
+
+/// The lod drawn from `start` to `end` distance from the camera, crossfading
+/// with its neighbours over `width` around either end. Negative while it's
+/// fading in, so it dithers the pixels the lod fading out leaves out, and 0
+/// when it isn't drawn at all. Use a negative `start` for the first lod.
+pub fn lod_fade(distance: f32, start: f32, end: f32, width: f32) -> f32 {
+	let width = width.max(0.001);
+	let fade_in = ((distance - start) / width + 0.5).clamp(0.0, 1.0);
+	let fade_out = ((end - distance) / width + 0.5).clamp(0.0, 1.0);
+	if fade_in <= 0.0 || fade_out <= 0.0 {
+		0.0
+	} else if fade_in < 1.0 {
+		-fade_in
+	} else {
+		fade_out
+	}
+}
+
+/// Fades out an instance `distance` from the camera once it's closer than
+/// `near`, gone when the camera reaches its center. Meant for small things
+/// like plants and props, 0 turns it off.
+pub fn near_fade(distance: f32, near: f32) -> f32 {
+	if near <= 0.0 {
+		return 1.0;
+	}
+	(distance / near).clamp(0.0, 1.0)
+}
+
+/// The value to pass the shader, the lod fade keeps its sign.
+pub fn instance_fade(lod_fade: f32, near_fade: f32) -> f32 {
+	lod_fade * near_fade
+}
+
+/// Whether an instance with `fade` has to be drawn at all.
+pub fn is_visible(fade: f32) -> bool {
+	fade != 0.0
+}
+
+/// Whether an instance with `fade` can be drawn with a permutation without
+/// `Feature::DitherFade`, which is cheaper and keeps early depth testing.
+pub fn is_opaque(fade: f32) -> bool {
+	fade >= 1.0
+}
@@ -0,0 +1,60 @@
+// files dragged onto the window. `Engine::event` turns winit's drop events
+// into `FileDrop`s, loads the files it recognizes and hands them to the
+// callbacks from `Engine::on_file_drop`.
+
+use crate::assets::{AssetError, Assets, Handle, Mesh};
+use crate::render2d::Texture;
+
+use std::path::{Path, PathBuf};
+
+/// An asset loaded from a dropped file.
+pub enum DroppedAsset {
+	/// Gltf and glb files.
+	Mesh(Handle<Mesh>),
+	/// Images, including hdr, exr and compressed ones.
+	Texture(Handle<Texture>),
+}
+
+/// The kinds of files loaded when dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+	Mesh,
+	Texture,
+}
+
+impl AssetKind {
+	/// Guesses the kind from the extension, `None` for anything else.
+	pub fn of(path: &Path) -> Option<AssetKind> {
+		let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+		match extension.as_str() {
+			"gltf" | "glb" => Some(AssetKind::Mesh),
+			"png" | "jpg" | "jpeg" | "tga" | "bmp" | "gif" | "webp" | "dds" | "ktx2" | "basis"
+			| "hdr" | "exr" => Some(AssetKind::Texture),
+			_ => None,
+		}
+	}
+
+	/// Starts loading `path` on a loader thread, dropped files are usually
+	/// outside of the asset directories so the path should be absolute.
+	pub fn load(self, assets: &mut Assets, path: &Path) -> Result<DroppedAsset, AssetError> {
+		Ok(match self {
+			AssetKind::Mesh => DroppedAsset::Mesh(assets.load_async(path)?),
+			AssetKind::Texture => DroppedAsset::Texture(assets.load_async(path)?),
+		})
+	}
+}
+
+/// What happened to a file dragged over the window.
+pub enum FileDrop {
+	/// A file is being dragged over the window, one event per file.
+	Hovered(PathBuf),
+	/// The drag left the window or was cancelled.
+	Cancelled,
+	/// A file was dropped, one event per file. `asset` is set when the file
+	/// was recognized and automatic loading is on, and holds the error when
+	/// it couldn't be loaded.
+	Dropped {
+		path: PathBuf,
+		asset: Option<Result<DroppedAsset, AssetError>>,
+	},
+}
@@ -0,0 +1,528 @@
+// fog between the camera and the scene. the volumetric path lights a froxel
+// grid (frustum aligned voxels, exponentially deeper slices) and integrates
+// it front to back, the cheaper paths fog each pixel analytically.
+
+use crate::assets::Texture3D;
+use crate::compute::group_counts;
+use crate::math::{mat4_inverse, normalize, Mat4, Vec3, IDENTITY};
+use crate::render2d::Texture;
+use crate::resolution::SCENE_FORMAT;
+use crate::settings::FogQuality;
+
+use vulkano::buffer::CpuBufferPool;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use std::sync::Arc;
+
+mod inject {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 4, local_size_y = 4, local_size_z = 4) in;
+
+			layout(set = 0, binding = 0) uniform FogData {
+				mat4 inverse_view_projection;
+				mat4 shadow_view_projection;
+				// camera position, near plane
+				vec4 eye;
+				// ambient color, density
+				vec4 fog_color;
+				// base height, height falloff, start distance, max distance
+				vec4 height;
+				// towards the light, scattering anisotropy
+				vec4 light_direction;
+				// light color, 1 with a shadow map
+				vec4 light_color;
+				// fog quality, froxel volume size
+				uvec4 mode;
+			} fog;
+
+			float density_at(vec3 position) {
+				return fog.fog_color.w * exp(-max(position.y - fog.height.x, 0.0) * fog.height.y);
+			}
+
+			// world position of a uv on the screen at vulkan depth `depth`
+			vec3 unproject(vec2 uv, float depth) {
+				vec4 world = fog.inverse_view_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+				return world.xyz / world.w;
+			}
+
+			// distance from the eye `t` of the way through the volume
+			float slice_distance(float t) {
+				return fog.eye.w * pow(fog.height.w / fog.eye.w, t);
+			}
+
+			layout(set = 0, binding = 1) uniform sampler2D shadow_map;
+			layout(set = 0, binding = 2, rgba16f) uniform writeonly image3D scattering;
+
+			float phase(float cos_theta) {
+				// henyey-greenstein
+				float g = fog.light_direction.w;
+				float denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+				return (1.0 - g * g) / (12.566371 * denominator * sqrt(denominator));
+			}
+
+			float visibility(vec3 position) {
+				if (fog.light_color.w == 0.0) {
+					return 1.0;
+				}
+				vec4 clip = fog.shadow_view_projection * vec4(position, 1.0);
+				vec3 light = clip.xyz / clip.w;
+				vec2 uv = light.xy * 0.5 + 0.5;
+				if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || light.z > 1.0) {
+					return 1.0;
+				}
+				return light.z - 0.002 <= textureLod(shadow_map, uv, 0.0).r ? 1.0 : 0.0;
+			}
+
+			void main() {
+				uvec3 id = gl_GlobalInvocationID;
+				uvec3 size = fog.mode.yzw;
+				if (any(greaterThanEqual(id, size))) {
+					return;
+				}
+
+				vec2 uv = (vec2(id.xy) + 0.5) / vec2(size.xy);
+				vec3 ray = normalize(unproject(uv, 1.0) - fog.eye.xyz);
+				float distance = slice_distance((float(id.z) + 0.5) / float(size.z));
+				vec3 position = fog.eye.xyz + ray * distance;
+
+				float density = distance < fog.height.z ? 0.0 : density_at(position);
+				vec3 sun = fog.light_color.rgb * visibility(position)
+					* phase(dot(-ray, fog.light_direction.xyz));
+				imageStore(scattering, ivec3(id), vec4((fog.fog_color.rgb + sun) * density, density));
+			}
+		"
+	}
+}
+
+mod integrate {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8) in;
+
+			layout(set = 0, binding = 0) uniform FogData {
+				mat4 inverse_view_projection;
+				mat4 shadow_view_projection;
+				vec4 eye;
+				vec4 fog_color;
+				vec4 height;
+				vec4 light_direction;
+				vec4 light_color;
+				uvec4 mode;
+			} fog;
+
+			// distance from the eye `t` of the way through the volume
+			float slice_distance(float t) {
+				return fog.eye.w * pow(fog.height.w / fog.eye.w, t);
+			}
+
+			layout(set = 0, binding = 1, rgba16f) uniform readonly image3D scattering;
+			layout(set = 0, binding = 2, rgba16f) uniform writeonly image3D integrated;
+
+			void main() {
+				uvec2 id = gl_GlobalInvocationID.xy;
+				uvec3 size = fog.mode.yzw;
+				if (any(greaterThanEqual(id, size.xy))) {
+					return;
+				}
+
+				vec3 light = vec3(0.0);
+				float transmittance = 1.0;
+				float previous = fog.eye.w;
+				for (uint z = 0; z < size.z; z++) {
+					float next = slice_distance((float(z) + 1.0) / float(size.z));
+					vec4 slice = imageLoad(scattering, ivec3(id, z));
+					float extinction = max(slice.a, 1e-6);
+					float slice_transmittance = exp(-extinction * (next - previous));
+
+					// light scattered inside the slice, attenuated by the slice itself
+					light += transmittance * slice.rgb * (1.0 - slice_transmittance) / extinction;
+					transmittance *= slice_transmittance;
+					imageStore(integrated, ivec3(id, z), vec4(light, transmittance));
+					previous = next;
+				}
+			}
+		"
+	}
+}
+
+mod apply {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8) in;
+
+			layout(set = 0, binding = 0) uniform FogData {
+				mat4 inverse_view_projection;
+				mat4 shadow_view_projection;
+				vec4 eye;
+				vec4 fog_color;
+				vec4 height;
+				vec4 light_direction;
+				vec4 light_color;
+				uvec4 mode;
+			} fog;
+
+			float density_at(vec3 position) {
+				return fog.fog_color.w * exp(-max(position.y - fog.height.x, 0.0) * fog.height.y);
+			}
+
+			// world position of a uv on the screen at vulkan depth `depth`
+			vec3 unproject(vec2 uv, float depth) {
+				vec4 world = fog.inverse_view_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+				return world.xyz / world.w;
+			}
+
+			// distance from the eye `t` of the way through the volume
+			float slice_distance(float t) {
+				return fog.eye.w * pow(fog.height.w / fog.eye.w, t);
+			}
+
+			layout(set = 0, binding = 1) uniform sampler2D scene;
+			layout(set = 0, binding = 2) uniform sampler2D depth;
+			layout(set = 0, binding = 3) uniform sampler3D integrated;
+			layout(set = 0, binding = 4, rgba16f) uniform writeonly image2D fogged;
+
+			layout(push_constant) uniform PushConstants {
+				// rendered part of the scene in pixels
+				uvec2 dimensions;
+			} pc;
+
+			void main() {
+				ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+				if (any(greaterThanEqual(pixel, ivec2(pc.dimensions)))) {
+					return;
+				}
+
+				vec2 uv = (vec2(pixel) + 0.5) / vec2(pc.dimensions);
+				vec3 color = texelFetch(scene, pixel, 0).rgb;
+				vec3 position = unproject(uv, texelFetch(depth, pixel, 0).r);
+				vec3 ray = position - fog.eye.xyz;
+				float distance = min(length(ray), fog.height.w);
+				ray = normalize(ray);
+
+				if (fog.mode.x == 3u) {
+					// each froxel holds the fog up to its far side
+					float t = log(max(distance, fog.eye.w) / fog.eye.w) / log(fog.height.w / fog.eye.w);
+					t -= 0.5 / float(fog.mode.w);
+					vec4 fog_light = textureLod(integrated, vec3(uv, t), 0.0);
+					color = color * fog_light.a + fog_light.rgb;
+				} else {
+					float travelled = max(distance - fog.height.z, 0.0);
+					float optical_depth = fog.fog_color.w * travelled;
+					if (fog.mode.x == 2u) {
+						// density integrated along the ray through exponential height falloff
+						float falloff = max(fog.height.y, 1e-5);
+						vec3 start = fog.eye.xyz + ray * (distance - travelled);
+						float dy = ray.y * falloff * travelled;
+						float along = abs(dy) > 1e-4 ? (1.0 - exp(-dy)) / dy : 1.0;
+						optical_depth = density_at(start) * travelled * along;
+					}
+					color = mix(fog.fog_color.rgb, color, exp(-optical_depth));
+				}
+				imageStore(fogged, pixel, vec4(color, 1.0));
+			}
+		"
+	}
+}
+
+/// How thick the fog is and where.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogParams {
+	/// Ambient light the fog scatters, also the color distant things fade to.
+	pub color: Vec3,
+	/// Extinction per world unit at and below `base_height`.
+	pub density: f32,
+	pub base_height: f32,
+	/// How fast the fog thins out above `base_height`, 0 keeps it even.
+	pub height_falloff: f32,
+	/// No fog closer to the camera than this.
+	pub start_distance: f32,
+	/// Distance the froxel volume reaches, everything further is fogged as
+	/// if it was this far.
+	pub max_distance: f32,
+	/// Henyey-greenstein anisotropy, towards 1 makes bright shafts when
+	/// looking into the light.
+	pub anisotropy: f32,
+}
+
+impl Default for FogParams {
+	fn default() -> Self {
+		FogParams {
+			color: [0.5, 0.6, 0.7],
+			density: 0.02,
+			base_height: 0.0,
+			height_falloff: 0.1,
+			start_distance: 0.0,
+			max_distance: 200.0,
+			anisotropy: 0.6,
+		}
+	}
+}
+
+/// The directional light shining through the fog.
+#[derive(Clone)]
+pub struct FogLight {
+	/// Direction towards the light.
+	pub direction: Vec3,
+	pub color: Vec3,
+	/// Depth of the light's shadow map and the view projection it was
+	/// rendered with, occluded froxels make the light shafts.
+	pub shadow: Option<(Texture, Mat4)>,
+}
+
+/// Where the scene was drawn from.
+#[derive(Debug, Clone, Copy)]
+pub struct FogCamera {
+	pub view_projection: Mat4,
+	pub eye: Vec3,
+	pub near: f32,
+}
+
+/// Fogs the hdr scene before post processing, at the quality from
+/// `GraphicsSettings::fog`.
+///
+/// Reads the scene color and depth and writes a new image of the same size,
+/// post processing then reads `apply`'s result instead of the scene. The
+/// depth has to be single sampled.
+pub struct VolumetricFog {
+	device: Arc<Device>,
+	inject: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	integrate: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	apply: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	data: CpuBufferPool<inject::ty::FogData>,
+	sampler: Arc<Sampler>,
+	froxels: [u32; 3],
+	scattering: Texture3D,
+	integrated: Texture3D,
+	output: Option<([u32; 2], Texture)>,
+	pub quality: FogQuality,
+	pub params: FogParams,
+}
+
+impl VolumetricFog {
+	/// `froxels` is the size of the volume, eg. `[160, 90, 64]` for a 16:9
+	/// screen.
+	pub fn new(device: Arc<Device>, froxels: [u32; 3]) -> Self {
+		let inject = inject::Shader::load(device.clone()).unwrap();
+		let integrate = integrate::Shader::load(device.clone()).unwrap();
+		let apply = apply::Shader::load(device.clone()).unwrap();
+
+		let sampler = Sampler::new(
+			device.clone(),
+			Filter::Linear,
+			Filter::Linear,
+			MipmapMode::Nearest,
+			SamplerAddressMode::ClampToEdge,
+			SamplerAddressMode::ClampToEdge,
+			SamplerAddressMode::ClampToEdge,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		)
+		.unwrap();
+
+		VolumetricFog {
+			inject: Arc::new(
+				ComputePipeline::new(device.clone(), &inject.main_entry_point(), &(), None)
+					.unwrap(),
+			),
+			integrate: Arc::new(
+				ComputePipeline::new(device.clone(), &integrate.main_entry_point(), &(), None)
+					.unwrap(),
+			),
+			apply: Arc::new(
+				ComputePipeline::new(device.clone(), &apply.main_entry_point(), &(), None).unwrap(),
+			),
+			data: CpuBufferPool::uniform_buffer(device.clone()),
+			sampler,
+			froxels,
+			scattering: Texture3D::storage(device.clone(), froxels, SCENE_FORMAT),
+			integrated: Texture3D::storage(device.clone(), froxels, SCENE_FORMAT),
+			output: None,
+			device,
+			quality: FogQuality::default(),
+			params: FogParams::default(),
+		}
+	}
+
+	/// Records the fog passes over the `dimensions` drawn part of `scene`
+	/// and returns the fogged image, or `scene` itself with the fog off.
+	/// Must be recorded outside of a render pass.
+	pub fn apply(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		scene: Texture,
+		depth: Texture,
+		dimensions: [u32; 2],
+		camera: &FogCamera,
+		light: &FogLight,
+	) -> Texture {
+		let mode = match self.quality {
+			FogQuality::Off => return scene,
+			FogQuality::Distance => 1,
+			FogQuality::Height => 2,
+			FogQuality::Volumetric => 3,
+		};
+
+		let params = &self.params;
+		let direction = normalize(light.direction);
+		let data = self
+			.data
+			.next(inject::ty::FogData {
+				inverse_view_projection: mat4_inverse(camera.view_projection)
+					.expect("camera view projection can't be inverted"),
+				shadow_view_projection: light
+					.shadow
+					.as_ref()
+					.map_or(IDENTITY, |(_, matrix)| *matrix),
+				eye: [camera.eye[0], camera.eye[1], camera.eye[2], camera.near],
+				fog_color: [
+					params.color[0],
+					params.color[1],
+					params.color[2],
+					params.density,
+				],
+				height: [
+					params.base_height,
+					params.height_falloff,
+					params.start_distance,
+					params.max_distance.max(camera.near * 2.0),
+				],
+				light_direction: [direction[0], direction[1], direction[2], params.anisotropy],
+				light_color: [
+					light.color[0],
+					light.color[1],
+					light.color[2],
+					if light.shadow.is_some() { 1.0 } else { 0.0 },
+				],
+				mode: [mode, self.froxels[0], self.froxels[1], self.froxels[2]],
+			})
+			.unwrap();
+
+		if mode == 3 {
+			// without a shadow map any sampled image does, it isn't read
+			let shadow_map = match &light.shadow {
+				Some((shadow_map, _)) => shadow_map.clone(),
+				None => depth.clone(),
+			};
+			let set = Arc::new(
+				PersistentDescriptorSet::start(
+					self.inject.descriptor_set_layout(0).unwrap().clone(),
+				)
+				.add_buffer(data.clone())
+				.unwrap()
+				.add_sampled_image(shadow_map, self.sampler.clone())
+				.unwrap()
+				.add_image(self.scattering.texture.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+			);
+			builder
+				.dispatch(
+					group_counts(self.froxels, [4, 4, 4]),
+					self.inject.clone(),
+					set,
+					(),
+					vec![],
+				)
+				.unwrap();
+
+			let set = Arc::new(
+				PersistentDescriptorSet::start(
+					self.integrate.descriptor_set_layout(0).unwrap().clone(),
+				)
+				.add_buffer(data.clone())
+				.unwrap()
+				.add_image(self.scattering.texture.clone())
+				.unwrap()
+				.add_image(self.integrated.texture.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+			);
+			builder
+				.dispatch(
+					group_counts([self.froxels[0], self.froxels[1], 1], [8, 8, 1]),
+					self.integrate.clone(),
+					set,
+					(),
+					vec![],
+				)
+				.unwrap();
+		}
+
+		let output = self.output(scene.image().dimensions().width_height());
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.apply.descriptor_set_layout(0).unwrap().clone())
+				.add_buffer(data)
+				.unwrap()
+				.add_sampled_image(scene, self.sampler.clone())
+				.unwrap()
+				.add_sampled_image(depth, self.sampler.clone())
+				.unwrap()
+				.add_sampled_image(self.integrated.texture.clone(), self.sampler.clone())
+				.unwrap()
+				.add_image(output.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		builder
+			.dispatch(
+				group_counts([dimensions[0], dimensions[1], 1], [8, 8, 1]),
+				self.apply.clone(),
+				set,
+				apply::ty::PushConstants { dimensions },
+				vec![],
+			)
+			.unwrap();
+		output
+	}
+
+	// the image `apply` writes, recreated when the scene changes size
+	fn output(&mut self, dimensions: [u32; 2]) -> Texture {
+		if let Some((size, output)) = &self.output {
+			if *size == dimensions {
+				return output.clone();
+			}
+		}
+		let image = StorageImage::with_usage(
+			self.device.clone(),
+			ImageDimensions::Dim2d {
+				width: dimensions[0],
+				height: dimensions[1],
+				array_layers: 1,
+			},
+			SCENE_FORMAT,
+			ImageUsage {
+				storage: true,
+				sampled: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			self.device.active_queue_families(),
+		)
+		.unwrap();
+		let output: Texture = ImageView::new(image).unwrap();
+		self.output = Some((dimensions, output.clone()));
+		output
+	}
+}
@@ -0,0 +1,209 @@
+// vegetation scattered over the world in large numbers. instances are
+// painted or loaded into a `Scatter`, uploaded once and then culled and
+// sorted into lods on the gpu every frame.
+
+pub mod render;
+
+use crate::assets::{Asset, AssetError, LoadContext};
+use crate::math::{Vec2, Vec3};
+
+use serde_json::{Map, Value};
+
+use std::f32::consts::PI;
+
+/// One plant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoliageInstance {
+	pub position: Vec3,
+	/// Rotation around y in radians.
+	pub yaw: f32,
+	pub scale: f32,
+}
+
+/// Where the instances of one kind of plant are. Painted in an editor with
+/// `paint` and `erase`, or loaded from json:
+///
+/// ```json
+/// { "instances": [[12.5, 3.1, 40.2, 1.57, 0.9], [13.0, 3.0, 41.8, 0.2, 1.1]] }
+/// ```
+///
+/// where each instance is x, y, z, yaw and scale.
+#[derive(Debug, Clone, Default)]
+pub struct Scatter {
+	pub instances: Vec<FoliageInstance>,
+	rng: Rng,
+}
+
+/// How `Scatter::paint` places instances.
+#[derive(Debug, Clone, Copy)]
+pub struct Brush {
+	pub radius: f32,
+	/// Instances per square unit.
+	pub density: f32,
+	/// Smallest and largest random scale.
+	pub scale: Vec2,
+	/// No two instances closer than this, 0 allows any.
+	pub spacing: f32,
+}
+
+impl Default for Brush {
+	fn default() -> Self {
+		Brush {
+			radius: 4.0,
+			density: 1.0,
+			scale: [0.8, 1.2],
+			spacing: 0.5,
+		}
+	}
+}
+
+impl Scatter {
+	pub fn new(seed: u32) -> Self {
+		Scatter {
+			instances: Vec::new(),
+			rng: Rng::new(seed),
+		}
+	}
+
+	/// Scatters instances over the circle around `center` on x and z, placed
+	/// at the height `ground` returns there. `ground` returning `None` skips
+	/// the spot, eg. too steep or under water. Returns how many were added.
+	pub fn paint(
+		&mut self,
+		center: Vec2,
+		brush: &Brush,
+		ground: impl Fn(f32, f32) -> Option<f32>,
+	) -> usize {
+		let area = PI * brush.radius * brush.radius;
+		let count = self.rng.round(area * brush.density);
+		let spacing_squared = brush.spacing * brush.spacing;
+		let mut added = 0;
+		for _ in 0..count {
+			// uniform over the disk
+			let distance = brush.radius * self.rng.next().sqrt();
+			let angle = self.rng.next() * 2.0 * PI;
+			let x = center[0] + distance * angle.cos();
+			let z = center[1] + distance * angle.sin();
+
+			let crowded = spacing_squared > 0.0
+				&& self.instances.iter().any(|instance| {
+					let (dx, dz) = (instance.position[0] - x, instance.position[2] - z);
+					dx * dx + dz * dz < spacing_squared
+				});
+			if crowded {
+				continue;
+			}
+			let y = match ground(x, z) {
+				Some(y) => y,
+				None => continue,
+			};
+			let scale = brush.scale[0] + (brush.scale[1] - brush.scale[0]) * self.rng.next();
+			self.instances.push(FoliageInstance {
+				position: [x, y, z],
+				yaw: self.rng.next() * 2.0 * PI,
+				scale,
+			});
+			added += 1;
+		}
+		added
+	}
+
+	/// Removes the instances within `radius` of `center` on x and z, returns
+	/// how many.
+	pub fn erase(&mut self, center: Vec2, radius: f32) -> usize {
+		let before = self.instances.len();
+		self.instances.retain(|instance| {
+			let (dx, dz) = (
+				instance.position[0] - center[0],
+				instance.position[2] - center[1],
+			);
+			dx * dx + dz * dz > radius * radius
+		});
+		before - self.instances.len()
+	}
+
+	pub fn from_json(json: &str) -> Result<Self, AssetError> {
+		let root: Value = serde_json::from_str(json)?;
+		let instances = root["instances"]
+			.as_array()
+			.ok_or(AssetError::Format("scatter has no instances"))?;
+		let mut scatter = Scatter::new(instances.len() as u32);
+		for instance in instances {
+			let values: Option<Vec<f32>> = instance.as_array().and_then(|values| {
+				values
+					.iter()
+					.map(|value| value.as_f64().map(|value| value as f32))
+					.collect()
+			});
+			match values.as_deref() {
+				Some(&[x, y, z, yaw, scale]) => scatter.instances.push(FoliageInstance {
+					position: [x, y, z],
+					yaw,
+					scale,
+				}),
+				_ => {
+					return Err(AssetError::Format(
+						"scatter instance should be five numbers",
+					))
+				}
+			}
+		}
+		Ok(scatter)
+	}
+
+	/// Json `from_json` reads back.
+	pub fn to_json(&self) -> String {
+		let instances = self
+			.instances
+			.iter()
+			.map(|instance| {
+				let [x, y, z] = instance.position;
+				Value::from(vec![x, y, z, instance.yaw, instance.scale])
+			})
+			.collect();
+		let mut root = Map::new();
+		root.insert("instances".to_string(), Value::Array(instances));
+		serde_json::to_string(&Value::Object(root)).unwrap()
+	}
+}
+
+impl Asset for Scatter {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		let json = String::from_utf8(context.read()?)
+			.map_err(|_| AssetError::Format("scatter isn't utf-8"))?;
+		Scatter::from_json(&json)
+	}
+}
+
+// xorshift, painting only needs to look random and be repeatable
+#[derive(Debug, Clone)]
+struct Rng(u32);
+
+impl Default for Rng {
+	fn default() -> Self {
+		Rng::new(0)
+	}
+}
+
+impl Rng {
+	fn new(seed: u32) -> Self {
+		// zero would stay zero forever
+		Rng(seed.wrapping_mul(0x9e37_79b9) | 1)
+	}
+
+	// uniform in 0..1
+	fn next(&mut self) -> f32 {
+		let mut x = self.0;
+		x ^= x << 13;
+		x ^= x >> 17;
+		x ^= x << 5;
+		self.0 = x;
+		(x >> 8) as f32 / (1 << 24) as f32
+	}
+
+	// rounds up or down at random so fractional densities average out
+	fn round(&mut self, value: f32) -> usize {
+		let whole = value.floor();
+		whole as usize + (self.next() < value - whole) as usize
+	}
+}
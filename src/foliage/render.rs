@@ -0,0 +1,566 @@
+use super::Scatter;
+use crate::assets::{Mesh, MeshVertex};
+use crate::math::{length, normalize, Frustum, Mat4, Vec2, Vec3};
+use crate::render2d::Texture;
+
+use vulkano::buffer::{
+	BufferSlice, BufferUsage, CpuBufferPool, DeviceLocalBuffer, ImmutableBuffer,
+};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DrawIndexedIndirectCommand, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{
+	ComputePipeline, ComputePipelineAbstract, GraphicsPipeline, GraphicsPipelineAbstract,
+};
+use vulkano::sampler::Sampler;
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+/// Most lods a `Foliage` can have.
+pub const MAX_LODS: usize = 4;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+// matches `Instance` in the shaders
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+struct GpuInstance {
+	position_scale: [f32; 4],
+	// yaw, wind phase, lod fade
+	params: [f32; 4],
+}
+
+// sorts the instances into one list per lod. the last lod fades out with
+// distance, the others crossfade into the next over `fade_width`. the same
+// math as `fade::lod_fade` and `fade::near_fade`
+mod cull {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 64) in;
+
+			struct Instance {
+				vec4 position_scale;
+				// yaw, wind phase, lod fade
+				vec4 params;
+			};
+
+			struct DrawCommand {
+				uint index_count;
+				uint instance_count;
+				uint first_index;
+				int vertex_offset;
+				uint first_instance;
+			};
+
+			layout(set = 0, binding = 0) uniform Params {
+				vec4 planes[6];
+				// xyz eye, w fade width
+				vec4 eye;
+				// distance each lod ends at
+				vec4 lod_ends;
+				// instance count, lod count
+				uvec4 counts;
+				// bounding radius of an unscaled instance, near fade distance
+				vec4 bounds;
+			} params;
+
+			layout(set = 0, binding = 1) readonly buffer Instances {
+				Instance instances[];
+			};
+
+			layout(set = 0, binding = 2) writeonly buffer Visible {
+				Instance visible[];
+			};
+
+			layout(set = 0, binding = 3) buffer Commands {
+				DrawCommand commands[];
+			};
+
+			void emit(uint lod, Instance instance, float fade) {
+				uint slot = atomicAdd(commands[lod].instance_count, 1u);
+				instance.params.z = fade;
+				visible[lod * params.counts.x + slot] = instance;
+			}
+
+			void main() {
+				uint index = gl_GlobalInvocationID.x;
+				if (index >= params.counts.x) {
+					return;
+				}
+
+				Instance instance = instances[index];
+				vec3 center = instance.position_scale.xyz;
+				float radius = params.bounds.x * instance.position_scale.w;
+				for (int i = 0; i < 6; i++) {
+					if (dot(params.planes[i].xyz, center) + params.planes[i].w < -radius) {
+						return;
+					}
+				}
+
+				float distance = length(center - params.eye.xyz);
+				float width = max(params.eye.w, 0.001);
+				// the camera passing through fades plants out instead of cutting them open
+				float near = params.bounds.y > 0.0 ? clamp(distance / params.bounds.y, 0.0, 1.0) : 1.0;
+				if (near <= 0.0) {
+					return;
+				}
+				for (uint lod = 0u; lod < params.counts.y; lod++) {
+					float start = lod == 0u ? -width : params.lod_ends[lod - 1u];
+					float end = params.lod_ends[lod];
+					float fade_in = clamp((distance - start) / width + 0.5, 0.0, 1.0);
+					float fade_out = clamp((end - distance) / width + 0.5, 0.0, 1.0);
+					if (fade_in <= 0.0 || fade_out <= 0.0) {
+						continue;
+					}
+					// fading in is stored negative so it dithers the pixels the
+					// previous lod leaves out
+					emit(lod, instance, (fade_in < 1.0 ? -fade_in : fade_out) * near);
+				}
+			}
+		"
+	}
+}
+
+mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec3 normal;
+			layout(location = 2) in vec4 tangent;
+			layout(location = 3) in vec2 uv;
+
+			struct Instance {
+				vec4 position_scale;
+				vec4 params;
+			};
+
+			layout(set = 0, binding = 0) readonly buffer Visible {
+				Instance visible[];
+			};
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				// xy direction on the ground, strength, frequency
+				vec4 wind;
+				// time, height the sway is full at
+				vec4 time;
+				// towards the sun
+				vec4 light;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec3 v_normal;
+			layout(location = 2) flat out float v_fade;
+
+			void main() {
+				Instance instance = visible[gl_InstanceIndex];
+				float c = cos(instance.params.x);
+				float s = sin(instance.params.x);
+				mat3 rotation = mat3(c, 0.0, -s, 0.0, 1.0, 0.0, s, 0.0, c);
+				vec3 world = instance.position_scale.xyz + rotation * position * instance.position_scale.w;
+
+				// the base stays put and the top bends with slow gusts and a quick flutter
+				vec4 wind = push_constants.wind;
+				float time = push_constants.time.x * wind.w + instance.params.y;
+				float bend = clamp(position.y / push_constants.time.y, 0.0, 1.0);
+				float sway = 0.6 + 0.4 * sin(time) + 0.1 * sin(time * 3.7 + world.x * 0.5);
+				world.xz += wind.xy * wind.z * sway * bend * bend;
+
+				gl_Position = push_constants.view_projection * vec4(world, 1.0);
+				v_uv = uv;
+				v_normal = rotation * normal;
+				v_fade = instance.params.z;
+			}
+		"
+	}
+}
+
+mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec3 v_normal;
+			layout(location = 2) flat in float v_fade;
+
+			layout(set = 0, binding = 1) uniform sampler2D albedo;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 wind;
+				vec4 time;
+				vec4 light;
+			} push_constants;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				vec4 color = texture(albedo, v_uv);
+				if (color.a < 0.5) {
+					discard;
+				}
+
+				// interleaved gradient noise, lods fading in use the inverse
+				// pattern of the ones fading out so together they cover each pixel once
+				float dither = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+				if (v_fade < 0.0) {
+					dither = 1.0 - dither;
+				}
+				if (dither >= abs(v_fade)) {
+					discard;
+				}
+
+				// leaves are two sided
+				vec3 normal = normalize(gl_FrontFacing ? v_normal : -v_normal);
+				float diffuse = max(dot(normal, push_constants.light.xyz), 0.0);
+				f_color = vec4(color.rgb * (0.3 + 0.7 * diffuse), 1.0);
+			}
+		"
+	}
+}
+
+/// One level of detail of a plant.
+#[derive(Clone)]
+pub struct FoliageLod {
+	pub vertices: Arc<ImmutableBuffer<[MeshVertex]>>,
+	pub indices: Arc<ImmutableBuffer<[u32]>>,
+	index_count: u32,
+	/// Distance from the eye this lod is drawn up to.
+	pub end_distance: f32,
+	// around the mesh's origin, which is its base
+	radius: f32,
+	height: f32,
+}
+
+impl FoliageLod {
+	pub fn new(mesh: &Mesh, end_distance: f32) -> Self {
+		let positions = &mesh.data.positions;
+		FoliageLod {
+			vertices: mesh.vertices.clone(),
+			indices: mesh.indices.clone(),
+			index_count: mesh.data.indices.len() as u32,
+			end_distance,
+			radius: positions.iter().map(|&p| length(p)).fold(0.0, f32::max),
+			height: positions.iter().map(|p| p[1]).fold(0.0, f32::max),
+		}
+	}
+}
+
+/// The instances of one scatter uploaded with the meshes and texture they're
+/// drawn with.
+pub struct Foliage {
+	lods: Vec<FoliageLod>,
+	pub albedo: Texture,
+	/// Distance over which a lod crossfades into the next, and the last one
+	/// into nothing.
+	pub fade_width: f32,
+	/// Plants closer to the camera than this fade out, so walking through
+	/// them doesn't cut them open at the near plane. 0 turns it off.
+	pub near_fade: f32,
+	count: u32,
+	instances: Arc<ImmutableBuffer<[GpuInstance]>>,
+	visible: Arc<DeviceLocalBuffer<[GpuInstance]>>,
+	commands: Arc<DeviceLocalBuffer<[DrawIndexedIndirectCommand]>>,
+}
+
+impl Foliage {
+	/// `lods` go from the most detailed, with increasing `end_distance`.
+	/// `albedo` is alpha tested at 0.5. The future has to be joined with the
+	/// frame that first culls the foliage.
+	pub fn new(
+		queue: Arc<Queue>,
+		scatter: &Scatter,
+		lods: Vec<FoliageLod>,
+		albedo: Texture,
+	) -> (Self, impl GpuFuture) {
+		assert!(
+			!lods.is_empty() && lods.len() <= MAX_LODS,
+			"foliage needs 1 to {} lods",
+			MAX_LODS
+		);
+		assert!(
+			lods.windows(2)
+				.all(|pair| pair[0].end_distance < pair[1].end_distance),
+			"foliage lods should end further away one after another"
+		);
+		assert!(!scatter.instances.is_empty(), "scatter has no instances");
+
+		// the hash spreads the wind phase so neighbours don't sway in lockstep
+		let instances = scatter
+			.instances
+			.iter()
+			.enumerate()
+			.map(|(index, instance)| {
+				let [x, y, z] = instance.position;
+				let phase = (index as u32).wrapping_mul(0x9e37_79b9) as f32 / u32::MAX as f32;
+				GpuInstance {
+					position_scale: [x, y, z, instance.scale],
+					params: [instance.yaw, phase * std::f32::consts::TAU, 1.0, 0.0],
+				}
+			});
+		let device = queue.device().clone();
+		let (instances, future) = ImmutableBuffer::from_iter(
+			instances,
+			BufferUsage {
+				storage_buffer: true,
+				..BufferUsage::none()
+			},
+			queue,
+		)
+		.unwrap();
+
+		let count = scatter.instances.len() as u32;
+		let visible = DeviceLocalBuffer::array(
+			device.clone(),
+			count as usize * lods.len(),
+			BufferUsage {
+				storage_buffer: true,
+				..BufferUsage::none()
+			},
+			device.active_queue_families(),
+		)
+		.unwrap();
+		let commands = DeviceLocalBuffer::array(
+			device.clone(),
+			MAX_LODS,
+			BufferUsage {
+				storage_buffer: true,
+				indirect_buffer: true,
+				transfer_destination: true,
+				..BufferUsage::none()
+			},
+			device.active_queue_families(),
+		)
+		.unwrap();
+
+		let foliage = Foliage {
+			lods,
+			albedo,
+			fade_width: 4.0,
+			near_fade: 1.0,
+			count,
+			instances,
+			visible,
+			commands,
+		};
+		(foliage, future)
+	}
+
+	pub fn lods(&self) -> &[FoliageLod] {
+		&self.lods
+	}
+
+	pub fn len(&self) -> usize {
+		self.count as usize
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.count == 0
+	}
+
+	// draw commands with no instances, the culling counts them up
+	fn empty_commands(&self) -> Box<[DrawIndexedIndirectCommand]> {
+		(0..MAX_LODS)
+			.map(|lod| DrawIndexedIndirectCommand {
+				index_count: self.lods.get(lod).map_or(0, |lod| lod.index_count),
+				instance_count: 0,
+				first_index: 0,
+				vertex_offset: 0,
+				first_instance: lod as u32 * self.count,
+			})
+			.collect()
+	}
+}
+
+/// Wind the foliage sways in.
+#[derive(Debug, Clone, Copy)]
+pub struct Wind {
+	/// Direction on x and z.
+	pub direction: Vec2,
+	/// How far the top of a plant is pushed, in world units.
+	pub strength: f32,
+	/// Gusts per second, in radians.
+	pub frequency: f32,
+}
+
+impl Default for Wind {
+	fn default() -> Self {
+		Wind {
+			direction: [1.0, 0.0],
+			strength: 0.15,
+			frequency: 1.5,
+		}
+	}
+}
+
+/// Culls and draws `Foliage`. `cull` runs first each frame outside of the
+/// render pass, `draw` then draws whatever survived with one indirect draw
+/// per lod. The device needs `khr_storage_buffer_storage_class`.
+pub struct FoliageRenderer {
+	cull: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	params: CpuBufferPool<cull::ty::Params>,
+	sampler: Arc<Sampler>,
+	pub wind: Wind,
+	/// Seconds, drives the wind.
+	pub time: f32,
+	/// Direction the sun light comes from.
+	pub sun: Vec3,
+}
+
+impl FoliageRenderer {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let cull = cull::Shader::load(device.clone()).unwrap();
+		let cull = Arc::new(
+			ComputePipeline::new(device.clone(), &cull.main_entry_point(), &(), None).unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input_single_buffer::<MeshVertex>()
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ());
+		if has_depth {
+			pipeline = pipeline.depth_stencil_simple_depth();
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		FoliageRenderer {
+			cull,
+			pipeline,
+			params: CpuBufferPool::uniform_buffer(device.clone()),
+			sampler: Sampler::simple_repeat_linear(device),
+			wind: Wind::default(),
+			time: 0.0,
+			sun: normalize([0.4, 1.0, 0.3]),
+		}
+	}
+
+	/// Records the culling of `foliage` for this frame's camera. Must be
+	/// recorded outside of a render pass.
+	pub fn cull(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		foliage: &Foliage,
+		view_projection: Mat4,
+		eye: Vec3,
+	) {
+		builder
+			.update_buffer(foliage.commands.clone(), foliage.empty_commands())
+			.unwrap();
+
+		let mut lod_ends = [0.0; 4];
+		for (end, lod) in lod_ends.iter_mut().zip(&foliage.lods) {
+			*end = lod.end_distance;
+		}
+		// the swaying top can lean out of the mesh's own bounds
+		let radius = foliage.lods[0].radius + self.wind.strength * 1.2;
+		let params = self
+			.params
+			.next(cull::ty::Params {
+				planes: Frustum::from_view_projection(view_projection).planes,
+				eye: [eye[0], eye[1], eye[2], foliage.fade_width],
+				lod_ends,
+				counts: [foliage.count, foliage.lods.len() as u32, 0, 0],
+				bounds: [radius, foliage.near_fade, 0.0, 0.0],
+			})
+			.unwrap();
+
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.cull.descriptor_set_layout(0).unwrap().clone())
+				.add_buffer(params)
+				.unwrap()
+				.add_buffer(foliage.instances.clone())
+				.unwrap()
+				.add_buffer(foliage.visible.clone())
+				.unwrap()
+				.add_buffer(foliage.commands.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		builder
+			.dispatch(
+				[foliage.count.div_ceil(WORKGROUP_SIZE), 1, 1],
+				self.cull.clone(),
+				set,
+				(),
+				vec![],
+			)
+			.unwrap();
+	}
+
+	/// Records the draws of what `cull` left of `foliage`. Must be called
+	/// inside the subpass the renderer was created for.
+	pub fn draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		foliage: &Foliage,
+		view_projection: Mat4,
+	) {
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_buffer(foliage.visible.clone())
+				.unwrap()
+				.add_sampled_image(foliage.albedo.clone(), self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		let wind = self.wind.direction;
+		let wind_length = (wind[0] * wind[0] + wind[1] * wind[1])
+			.sqrt()
+			.max(f32::EPSILON);
+		let sun = normalize(self.sun);
+		let push_constants = vs::ty::PushConstants {
+			view_projection,
+			wind: [
+				wind[0] / wind_length,
+				wind[1] / wind_length,
+				self.wind.strength,
+				self.wind.frequency,
+			],
+			time: [self.time, foliage.lods[0].height.max(0.01), 0.0, 0.0],
+			light: [sun[0], sun[1], sun[2], 0.0],
+		};
+
+		for (index, lod) in foliage.lods.iter().enumerate() {
+			let command = BufferSlice::from_typed_buffer_access(foliage.commands.clone())
+				.slice(index..index + 1)
+				.unwrap();
+			builder
+				.draw_indexed_indirect(
+					self.pipeline.clone(),
+					dynamic_state,
+					vec![lod.vertices.clone()],
+					lod.indices.clone(),
+					command,
+					set.clone(),
+					push_constants,
+					vec![],
+				)
+				.unwrap();
+		}
+	}
+}
@@ -0,0 +1,268 @@
+// writes the intermediate targets of one frame to disk, eg. the g-buffer,
+// shadow maps, ssao and bloom, as a lightweight alternative to attaching
+// renderdoc. 8 bit targets become pngs, float and depth targets exrs, next to
+// a `manifest.json` listing them.
+//
+// only the first mip level and layer of each target is written, and only
+// targets that were created with transfer source usage and a single sample
+// can be copied at all.
+
+use crate::render2d::Texture;
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::DeviceOwned;
+use vulkano::format::Format;
+use vulkano::half::f16;
+use vulkano::image::ImageAccess;
+use vulkano::SafeDeref;
+
+use serde_json::{Map, Value};
+
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+	/// Asked for, recording starts with the next frame.
+	Requested,
+	Recording,
+	/// Waiting for the gpu to finish the copies.
+	InFlight,
+}
+
+struct Capture {
+	name: String,
+	size: [u32; 2],
+	format: Format,
+	buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+}
+
+/// A dump of one frame's targets into a directory, see
+/// `Renderer::dump_frame`.
+pub struct FrameDump {
+	dir: PathBuf,
+	state: State,
+	captures: Vec<Capture>,
+}
+
+impl FrameDump {
+	/// Dumps the frame after this one into `dir`, which is created if needed.
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		FrameDump {
+			dir: dir.into(),
+			state: State::Requested,
+			captures: Vec::new(),
+		}
+	}
+
+	/// Whether targets passed to `capture` are copied, only during the
+	/// dumped frame.
+	pub fn is_recording(&self) -> bool {
+		self.state == State::Recording
+	}
+
+	/// Call at the start of every frame.
+	pub fn begin_frame(&mut self) {
+		if self.state == State::Requested {
+			self.state = State::Recording;
+		}
+	}
+
+	/// Call once the frame's commands were submitted.
+	pub fn end_frame(&mut self) {
+		if self.state == State::Recording {
+			self.state = State::InFlight;
+		}
+	}
+
+	/// Copies level 0 of `texture` to be written as `name`. Only outside of
+	/// render passes, and while nothing else writes the target.
+	pub fn capture(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		name: &str,
+		texture: &Texture,
+	) {
+		if !self.is_recording() {
+			return;
+		}
+		let image = texture.image();
+		let format = image.format();
+		let size = image.dimensions().width_height();
+		let bytes = match format.size() {
+			Some(bytes) if image.samples() == 1 && decodable(format) => bytes,
+			_ => {
+				log::warn!("Can't dump {}, {:?} targets aren't supported", name, format);
+				return;
+			}
+		};
+		let length = size[0] as usize * size[1] as usize * bytes;
+		let buffer = CpuAccessibleBuffer::from_iter(
+			builder.device().clone(),
+			BufferUsage::transfer_destination(),
+			true,
+			(0..length).map(|_| 0u8),
+		)
+		.unwrap();
+		if let Err(e) = builder.copy_image_to_buffer(ViewImage(texture.clone()), buffer.clone()) {
+			log::warn!("Can't dump {}: {}", name, e);
+			return;
+		}
+		self.captures.push(Capture {
+			name: name.to_string(),
+			size,
+			format,
+			buffer,
+		});
+	}
+
+	/// Writes the captured targets once the gpu is done copying them.
+	/// `None` while it isn't yet, otherwise how many targets were written.
+	pub fn write(&self) -> Option<io::Result<usize>> {
+		if self.state != State::InFlight {
+			return None;
+		}
+		// the copies are done once nothing holds the buffers anymore
+		let pixels = self
+			.captures
+			.iter()
+			.map(|capture| capture.buffer.read().ok().map(|bytes| bytes.to_vec()))
+			.collect::<Option<Vec<_>>>()?;
+		Some(self.write_files(pixels))
+	}
+
+	fn write_files(&self, pixels: Vec<Vec<u8>>) -> io::Result<usize> {
+		fs::create_dir_all(&self.dir)?;
+		let mut targets = Vec::new();
+		for (index, (capture, bytes)) in self.captures.iter().zip(pixels).enumerate() {
+			let stem: String = capture
+				.name
+				.chars()
+				.map(|c| if c.is_alphanumeric() { c } else { '_' })
+				.collect();
+			// only decodable formats were captured
+			let pixels = decode(capture.format, &bytes).unwrap();
+			let extension = match pixels {
+				Pixels::Rgba8(_) => "png",
+				Pixels::Float(_) => "exr",
+			};
+			let file = format!("{:02}_{}.{}", index, stem, extension);
+			write_image(&self.dir.join(&file), capture.size, pixels)?;
+			let mut target = Map::new();
+			target.insert("name".to_string(), capture.name.clone().into());
+			target.insert("file".to_string(), file.into());
+			target.insert("width".to_string(), capture.size[0].into());
+			target.insert("height".to_string(), capture.size[1].into());
+			target.insert("format".to_string(), format!("{:?}", capture.format).into());
+			targets.push(Value::Object(target));
+		}
+		let mut manifest = Map::new();
+		manifest.insert("targets".to_string(), Value::Array(targets));
+		fs::write(
+			self.dir.join("manifest.json"),
+			serde_json::to_string_pretty(&manifest).unwrap(),
+		)?;
+		Ok(self.captures.len())
+	}
+}
+
+// lets a view's image be copied, `copy_image_to_buffer` wants it owned
+struct ViewImage(Texture);
+
+impl Deref for ViewImage {
+	type Target = dyn ImageAccess;
+
+	fn deref(&self) -> &Self::Target {
+		// views hold on to their image, so it lives as long as `self`
+		unsafe {
+			std::mem::transmute::<&dyn ImageAccess, &(dyn ImageAccess + 'static)>(self.0.image())
+		}
+	}
+}
+
+unsafe impl SafeDeref for ViewImage {}
+
+enum Pixels {
+	Rgba8(Vec<u8>),
+	/// Rgba.
+	Float(Vec<f32>),
+}
+
+fn decodable(format: Format) -> bool {
+	decode(format, &[]).is_some()
+}
+
+fn decode(format: Format, bytes: &[u8]) -> Option<Pixels> {
+	let rgba = |channels: &[f32]| match *channels {
+		[v] => [v, v, v, 1.0],
+		[r, g] => [r, g, 0.0, 1.0],
+		[r, g, b, a] => [r, g, b, a],
+		_ => unreachable!(),
+	};
+	let halves = |count: usize| {
+		let values: Vec<f32> = bytes
+			.chunks_exact(2)
+			.map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+			.collect();
+		Pixels::Float(values.chunks_exact(count).flat_map(rgba).collect())
+	};
+	let floats = |count: usize| {
+		let values: Vec<f32> = bytes
+			.chunks_exact(4)
+			.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+			.collect();
+		Pixels::Float(values.chunks_exact(count).flat_map(rgba).collect())
+	};
+	Some(match format {
+		Format::R8G8B8A8Unorm | Format::R8G8B8A8Srgb => Pixels::Rgba8(bytes.to_vec()),
+		Format::B8G8R8A8Unorm | Format::B8G8R8A8Srgb => Pixels::Rgba8(
+			bytes
+				.chunks_exact(4)
+				.flat_map(|p| [p[2], p[1], p[0], p[3]])
+				.collect(),
+		),
+		Format::R8Unorm => Pixels::Rgba8(bytes.iter().flat_map(|&v| [v, v, v, 255]).collect()),
+		Format::R8G8Unorm => Pixels::Rgba8(
+			bytes
+				.chunks_exact(2)
+				.flat_map(|p| [p[0], p[1], 0, 255])
+				.collect(),
+		),
+		Format::D16Unorm | Format::R16Unorm => Pixels::Float(
+			bytes
+				.chunks_exact(2)
+				.map(|b| u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0)
+				.flat_map(|v| rgba(&[v]))
+				.collect(),
+		),
+		Format::R16Sfloat => halves(1),
+		Format::R16G16Sfloat => halves(2),
+		Format::R16G16B16A16Sfloat => halves(4),
+		Format::R32Sfloat | Format::D32Sfloat => floats(1),
+		Format::R32G32Sfloat => floats(2),
+		Format::R32G32B32A32Sfloat => floats(4),
+		_ => return None,
+	})
+}
+
+fn write_image(path: &Path, size: [u32; 2], pixels: Pixels) -> io::Result<()> {
+	let other = |e: &dyn std::fmt::Display| io::Error::other(e.to_string());
+	match pixels {
+		Pixels::Rgba8(pixels) => {
+			image::save_buffer(path, &pixels, size[0], size[1], image::ColorType::Rgba8)
+				.map_err(|e| other(&e))
+		}
+		Pixels::Float(pixels) => {
+			let width = size[0] as usize;
+			exr::prelude::write_rgba_file(path, width, size[1] as usize, |x, y| {
+				let i = (y * width + x) * 4;
+				(pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3])
+			})
+			.map_err(|e| other(&e))
+		}
+	}
+}
@@ -0,0 +1,352 @@
+// fullscreen passes: one triangle that covers the whole viewport, drawn
+// without a vertex buffer. the vertex shader derives the uv from the vertex
+// index, 0 to 1 over the viewport with y down like the image.
+
+use crate::render2d::Texture;
+use crate::shader::{
+	compile_glsl, Reflection, ShaderError, ShaderInterface, ShaderLayout, ShaderStage,
+};
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents};
+use vulkano::descriptor::descriptor_set::{
+	DescriptorSetsCollection, PersistentDescriptorSet, UnsafeDescriptorSetLayout,
+};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, RenderPassAbstract, Subpass};
+use vulkano::pipeline::shader::{GraphicsShaderType, ShaderModule};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::Arc;
+
+/// A fullscreen triangle pipeline. Bufferless draws need the concrete
+/// pipeline type.
+pub type FullscreenPipeline = GraphicsPipeline<
+	BufferlessDefinition,
+	Box<dyn PipelineLayoutAbstract + Send + Sync>,
+	Arc<dyn RenderPassAbstract + Send + Sync>,
+>;
+
+// fullscreen triangle, uvs past 1 are clipped away
+pub(crate) mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) out vec2 v_uv;
+
+			void main() {
+				v_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+				gl_Position = vec4(v_uv * 2.0 - 1.0, 0.0, 1.0);
+			}
+		"
+	}
+}
+
+// samples a rect of the source
+mod copy {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+
+			layout(set = 0, binding = 0) uniform sampler2D source;
+
+			layout(push_constant) uniform PushConstants {
+				vec2 uv_offset;
+				vec2 uv_scale;
+			} pc;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				f_color = texture(source, pc.uv_offset + v_uv * pc.uv_scale);
+			}
+		"
+	}
+}
+
+/// Render pass with one color attachment of `format` whose old contents are
+/// discarded, what fullscreen passes covering the whole target draw in.
+pub fn render_pass(
+	device: Arc<Device>,
+	format: Format,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	Arc::new(
+		vulkano::single_pass_renderpass!(
+			device,
+			attachments: {
+				color: {
+					load: DontCare,
+					store: Store,
+					format: format,
+					samples: 1,
+				}
+			},
+			pass: {
+				color: [color],
+				depth_stencil: {}
+			}
+		)
+		.unwrap(),
+	)
+}
+
+/// A fullscreen triangle pipeline with a runtime fragment shader. The
+/// fragment shader gets the uv as `layout(location = 0) in vec2 v_uv;`, 0 to
+/// 1 over the whole target, and writes `layout(location = 0) out vec4`.
+pub struct FullscreenPass {
+	pipeline: Arc<FullscreenPipeline>,
+	layout: ShaderLayout,
+}
+
+impl FullscreenPass {
+	pub fn from_glsl(
+		device: Arc<Device>,
+		fragment: &str,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Result<Self, ShaderError> {
+		let spirv = compile_glsl(fragment, ShaderStage::Fragment, "post.frag")?;
+		Self::from_spirv(device, &spirv, subpass)
+	}
+
+	/// `from_glsl` against a pass with a single attachment of `format`, the
+	/// pass `blit` draws in.
+	pub fn for_format(
+		device: Arc<Device>,
+		fragment: &str,
+		format: Format,
+	) -> Result<Self, ShaderError> {
+		let subpass = Subpass::from(render_pass(device.clone(), format), 0).unwrap();
+		Self::from_glsl(device, fragment, subpass)
+	}
+
+	pub fn from_spirv(
+		device: Arc<Device>,
+		spirv: &[u32],
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Result<Self, ShaderError> {
+		let reflection = Reflection::parse(spirv)?;
+		let layout = ShaderLayout::new(&[&reflection])?;
+
+		// safe as long as the module is valid spir-v, which shaderc or the caller guarantees
+		let module = unsafe { ShaderModule::from_words(device.clone(), spirv) }.unwrap();
+		let fragment = unsafe {
+			module.graphics_entry_point::<(), _, _, _>(
+				CStr::from_bytes_with_nul(b"main\0").unwrap(),
+				ShaderInterface::inputs(&reflection),
+				ShaderInterface::outputs(&reflection),
+				layout.pipeline_desc(),
+				GraphicsShaderType::Fragment,
+			)
+		};
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let pipeline = GraphicsPipeline::start()
+			.vertex_input(BufferlessDefinition)
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fragment, ())
+			.render_pass(subpass)
+			.build(device)
+			.map_err(|error| ShaderError::Mismatch(error.to_string()))?;
+
+		Ok(FullscreenPass {
+			pipeline: Arc::new(pipeline),
+			layout,
+		})
+	}
+
+	pub fn pipeline(&self) -> &Arc<FullscreenPipeline> {
+		&self.pipeline
+	}
+
+	/// What the fragment shader declares, to check sets and push constants
+	/// against.
+	pub fn layout(&self) -> &ShaderLayout {
+		&self.layout
+	}
+
+	pub fn descriptor_set_layout(&self, set: usize) -> Arc<UnsafeDescriptorSetLayout> {
+		self.pipeline.descriptor_set_layout(set).unwrap().clone()
+	}
+
+	/// Runs the shader over all of `destination`, with `source` at set 0
+	/// binding 0 and `params` as push constants. Begins and ends its own
+	/// render pass, so only works for passes built by `for_format`, with
+	/// `destination` in that format.
+	pub fn blit<Pc>(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: Texture,
+		sampler: Arc<Sampler>,
+		destination: Texture,
+		params: Pc,
+	) {
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.descriptor_set_layout(0))
+				.add_sampled_image(source, sampler)
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		draw_into(builder, &self.pipeline, destination, set, params);
+	}
+
+	/// Draws the triangle, inside the pass the pipeline was built for.
+	pub fn draw<S, Pc>(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		sets: S,
+		push_constants: Pc,
+	) where
+		S: DescriptorSetsCollection,
+	{
+		builder
+			.draw(
+				self.pipeline.clone(),
+				dynamic_state,
+				BufferlessVertices {
+					vertices: 3,
+					instances: 1,
+				},
+				sets,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
+
+/// Copies and scales images with a fullscreen pass, for formats and sizes
+/// `blit_image` can't handle or inside a chain of fullscreen passes.
+pub struct Blitter {
+	device: Arc<Device>,
+	// one pipeline per destination format
+	pipelines: HashMap<Format, Arc<FullscreenPipeline>>,
+	pub sampler: Arc<Sampler>,
+}
+
+impl Blitter {
+	pub fn new(device: Arc<Device>) -> Self {
+		Blitter {
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+			device,
+			pipelines: HashMap::new(),
+		}
+	}
+
+	/// Stretches all of `source` over all of `destination`.
+	pub fn copy(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: Texture,
+		destination: Texture,
+	) {
+		self.copy_region(builder, source, [0.0, 0.0], [1.0, 1.0], destination);
+	}
+
+	/// Stretches the part of `source` starting at `uv_offset` and `uv_scale`
+	/// in size over all of `destination`.
+	pub fn copy_region(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: Texture,
+		uv_offset: [f32; 2],
+		uv_scale: [f32; 2],
+		destination: Texture,
+	) {
+		let pipeline = self.pipeline(destination.format());
+		let set = Arc::new(
+			PersistentDescriptorSet::start(pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_sampled_image(source, self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let params = copy::ty::PushConstants {
+			uv_offset,
+			uv_scale,
+		};
+		draw_into(builder, &pipeline, destination, set, params);
+	}
+
+	fn pipeline(&mut self, format: Format) -> Arc<FullscreenPipeline> {
+		let device = self.device.clone();
+		self.pipelines
+			.entry(format)
+			.or_insert_with(|| {
+				let vs = vs::Shader::load(device.clone()).unwrap();
+				let fs = copy::Shader::load(device.clone()).unwrap();
+				let subpass = Subpass::from(render_pass(device.clone(), format), 0).unwrap();
+				Arc::new(
+					GraphicsPipeline::start()
+						.vertex_input(BufferlessDefinition)
+						.vertex_shader(vs.main_entry_point(), ())
+						.triangle_list()
+						.viewports_dynamic_scissors_irrelevant(1)
+						.fragment_shader(fs.main_entry_point(), ())
+						.render_pass(subpass)
+						.build(device)
+						.unwrap(),
+				)
+			})
+			.clone()
+	}
+}
+
+// draws the triangle over all of `destination` in a pass of its own
+fn draw_into<S, Pc>(
+	builder: &mut AutoCommandBufferBuilder,
+	pipeline: &Arc<FullscreenPipeline>,
+	destination: Texture,
+	sets: S,
+	push_constants: Pc,
+) where
+	S: DescriptorSetsCollection,
+{
+	let dimensions = destination.image().dimensions();
+	let dynamic_state = DynamicState {
+		viewports: Some(vec![Viewport {
+			origin: [0.0, 0.0],
+			dimensions: [dimensions.width() as f32, dimensions.height() as f32],
+			depth_range: 0.0..1.0,
+		}]),
+		..DynamicState::none()
+	};
+	let framebuffer = Arc::new(
+		Framebuffer::start(pipeline.render_pass().clone())
+			.add(destination)
+			.unwrap()
+			.build()
+			.unwrap(),
+	);
+
+	builder
+		.begin_render_pass(framebuffer, SubpassContents::Inline, vec![ClearValue::None])
+		.unwrap();
+	builder
+		.draw(
+			pipeline.clone(),
+			&dynamic_state,
+			BufferlessVertices {
+				vertices: 3,
+				instances: 1,
+			},
+			sets,
+			push_constants,
+			vec![],
+		)
+		.unwrap();
+	builder.end_render_pass().unwrap();
+}
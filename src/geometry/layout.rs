@@ -0,0 +1,383 @@
+// vertex layouts: which attributes of a mesh end up interleaved in its
+// vertex buffer. a layout is either a struct fixed at compile time with
+// `vertex_layout!`, or a `VertexFormat` picked at runtime from the streams a
+// mesh actually has. either way attributes are bound to shader inputs by
+// name, so a shader only declares the ones it reads.
+
+use super::MeshData;
+use crate::math::{Vec2, Vec3, Vec4};
+
+use vulkano::buffer::BufferAccess;
+use vulkano::format::Format;
+use vulkano::pipeline::shader::ShaderInterfaceDef;
+use vulkano::pipeline::vertex::{
+	AttributeInfo, IncompatibleVertexDefinitionError, InputRate, Vertex, VertexDefinition,
+	VertexMemberTy, VertexSource,
+};
+
+use std::sync::Arc;
+use std::vec::IntoIter;
+
+/// One attribute of a vertex and the `MeshData` stream it comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexAttribute {
+	/// `vec3 position`
+	Position,
+	/// `vec3 normal`
+	Normal,
+	/// `vec4 tangent`
+	Tangent,
+	/// `vec2 uv`
+	Uv,
+	/// `vec2 uv2`, the first uv set when the mesh has no second one.
+	Uv2,
+	/// `vec4 color`, linear.
+	Color,
+	/// `uvec4 joints`
+	Joints,
+	/// `vec4 weights`
+	Weights,
+}
+
+impl VertexAttribute {
+	pub const ALL: [VertexAttribute; 8] = [
+		VertexAttribute::Position,
+		VertexAttribute::Normal,
+		VertexAttribute::Tangent,
+		VertexAttribute::Uv,
+		VertexAttribute::Uv2,
+		VertexAttribute::Color,
+		VertexAttribute::Joints,
+		VertexAttribute::Weights,
+	];
+
+	/// Name of the shader input it binds to.
+	pub fn name(self) -> &'static str {
+		match self {
+			VertexAttribute::Position => "position",
+			VertexAttribute::Normal => "normal",
+			VertexAttribute::Tangent => "tangent",
+			VertexAttribute::Uv => "uv",
+			VertexAttribute::Uv2 => "uv2",
+			VertexAttribute::Color => "color",
+			VertexAttribute::Joints => "joints",
+			VertexAttribute::Weights => "weights",
+		}
+	}
+
+	/// Number of 32 bit components.
+	pub fn components(self) -> usize {
+		match self {
+			VertexAttribute::Position | VertexAttribute::Normal => 3,
+			VertexAttribute::Uv | VertexAttribute::Uv2 => 2,
+			_ => 4,
+		}
+	}
+
+	pub fn format(self) -> Format {
+		match self {
+			VertexAttribute::Joints => Format::R32G32B32A32Uint,
+			_ => match self.components() {
+				2 => Format::R32G32Sfloat,
+				3 => Format::R32G32B32Sfloat,
+				_ => Format::R32G32B32A32Sfloat,
+			},
+		}
+	}
+
+	/// Whether `mesh` has its own data for the attribute. Missing ones can
+	/// still be interleaved, they're filled in with defaults.
+	pub fn present_in(self, mesh: &MeshData) -> bool {
+		let count = mesh.vertex_count();
+		match self {
+			VertexAttribute::Position => true,
+			VertexAttribute::Normal => mesh.normals.len() == count,
+			VertexAttribute::Tangent => mesh.tangents.len() == count,
+			VertexAttribute::Uv => mesh.uvs.len() == count,
+			VertexAttribute::Uv2 => mesh.uvs2.len() == count,
+			VertexAttribute::Color => mesh.colors.len() == count,
+			VertexAttribute::Joints => mesh.joints.len() == count,
+			VertexAttribute::Weights => mesh.weights.len() == count,
+		}
+	}
+
+	fn member_ty(self) -> VertexMemberTy {
+		match self {
+			VertexAttribute::Joints => VertexMemberTy::U32,
+			_ => VertexMemberTy::F32,
+		}
+	}
+
+	// the attribute of vertex `index` as raw 32 bit words
+	fn write(self, mesh: &MeshData, index: usize, out: &mut Vec<u32>) {
+		let floats = |values: &[f32], out: &mut Vec<u32>| {
+			out.extend(values.iter().map(|value| value.to_bits()))
+		};
+		match self {
+			VertexAttribute::Position => floats(&read::position(mesh, index), out),
+			VertexAttribute::Normal => floats(&read::normal(mesh, index), out),
+			VertexAttribute::Tangent => floats(&read::tangent(mesh, index), out),
+			VertexAttribute::Uv => floats(&read::uv(mesh, index), out),
+			VertexAttribute::Uv2 => floats(&read::uv2(mesh, index), out),
+			VertexAttribute::Color => floats(&read::color(mesh, index), out),
+			VertexAttribute::Joints => out.extend_from_slice(&read::joints(mesh, index)),
+			VertexAttribute::Weights => floats(&read::weights(mesh, index), out),
+		}
+	}
+}
+
+/// A vertex struct that can be filled in from `MeshData`. Implemented by
+/// `vertex_layout!`.
+pub trait VertexLayout: Vertex + Default + Copy {
+	const ATTRIBUTES: &'static [VertexAttribute];
+
+	fn from_mesh(mesh: &MeshData, index: usize) -> Self;
+}
+
+/// Declares a vertex struct with the attributes named, in that order, and
+/// implements `VertexLayout` and vulkano's `Vertex` for it. Field names are
+/// the snake case names of `VertexAttribute`s and decide the field types,
+/// eg. `vertex_layout!(pub struct UnlitVertex { position, uv, color })` and
+/// then `mesh.interleave::<UnlitVertex>()`.
+#[macro_export]
+macro_rules! vertex_layout {
+	(@type position) => { [f32; 3] };
+	(@type normal) => { [f32; 3] };
+	(@type tangent) => { [f32; 4] };
+	(@type uv) => { [f32; 2] };
+	(@type uv2) => { [f32; 2] };
+	(@type color) => { [f32; 4] };
+	(@type joints) => { [u32; 4] };
+	(@type weights) => { [f32; 4] };
+
+	(@attribute position) => { $crate::geometry::layout::VertexAttribute::Position };
+	(@attribute normal) => { $crate::geometry::layout::VertexAttribute::Normal };
+	(@attribute tangent) => { $crate::geometry::layout::VertexAttribute::Tangent };
+	(@attribute uv) => { $crate::geometry::layout::VertexAttribute::Uv };
+	(@attribute uv2) => { $crate::geometry::layout::VertexAttribute::Uv2 };
+	(@attribute color) => { $crate::geometry::layout::VertexAttribute::Color };
+	(@attribute joints) => { $crate::geometry::layout::VertexAttribute::Joints };
+	(@attribute weights) => { $crate::geometry::layout::VertexAttribute::Weights };
+
+	($(#[$meta:meta])* $vis:vis struct $name:ident { $($field:ident),+ $(,)? }) => {
+		$(#[$meta])*
+		#[derive(Default, Debug, Clone, Copy)]
+		$vis struct $name {
+			$(pub $field: $crate::vertex_layout!(@type $field),)+
+		}
+		vulkano::impl_vertex!($name, $($field),+);
+
+		impl $crate::geometry::layout::VertexLayout for $name {
+			const ATTRIBUTES: &'static [$crate::geometry::layout::VertexAttribute] =
+				&[$($crate::vertex_layout!(@attribute $field)),+];
+
+			fn from_mesh(mesh: &$crate::geometry::MeshData, index: usize) -> Self {
+				$name {
+					$($field: $crate::geometry::layout::read::$field(mesh, index),)+
+				}
+			}
+		}
+	};
+}
+
+/// Attributes of one vertex with the defaults used for missing streams.
+/// Used by `vertex_layout!`.
+#[doc(hidden)]
+pub mod read {
+	use super::*;
+
+	pub fn position(mesh: &MeshData, index: usize) -> Vec3 {
+		mesh.positions[index]
+	}
+
+	pub fn normal(mesh: &MeshData, index: usize) -> Vec3 {
+		mesh.normals.get(index).copied().unwrap_or([0.0, 1.0, 0.0])
+	}
+
+	pub fn tangent(mesh: &MeshData, index: usize) -> Vec4 {
+		mesh.tangents
+			.get(index)
+			.copied()
+			.unwrap_or([1.0, 0.0, 0.0, 1.0])
+	}
+
+	pub fn uv(mesh: &MeshData, index: usize) -> Vec2 {
+		mesh.uvs.get(index).copied().unwrap_or([0.0; 2])
+	}
+
+	pub fn uv2(mesh: &MeshData, index: usize) -> Vec2 {
+		mesh.uvs2
+			.get(index)
+			.copied()
+			.unwrap_or_else(|| uv(mesh, index))
+	}
+
+	pub fn color(mesh: &MeshData, index: usize) -> Vec4 {
+		mesh.colors.get(index).copied().unwrap_or([1.0; 4])
+	}
+
+	pub fn joints(mesh: &MeshData, index: usize) -> [u32; 4] {
+		mesh.joints
+			.get(index)
+			.map_or([0; 4], |joints| joints.map(|joint| joint as u32))
+	}
+
+	pub fn weights(mesh: &MeshData, index: usize) -> Vec4 {
+		mesh.weights
+			.get(index)
+			.copied()
+			.unwrap_or([1.0, 0.0, 0.0, 0.0])
+	}
+}
+
+/// Vertex layout chosen at runtime, usually `VertexFormat::of` a mesh so
+/// nothing is uploaded that the mesh doesn't have.
+///
+/// It's also the pipeline's vertex input, `.vertex_input(format.clone())`,
+/// and the buffer to draw with is `interleave`d into a `[u32]` buffer.
+/// Building the pipeline fails if the shader reads an attribute that isn't
+/// in the format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VertexFormat {
+	attributes: Vec<VertexAttribute>,
+}
+
+impl VertexFormat {
+	/// Format with `attributes` in order. Repeats are dropped.
+	pub fn new(attributes: impl IntoIterator<Item = VertexAttribute>) -> Self {
+		let mut format = VertexFormat {
+			attributes: Vec::new(),
+		};
+		for attribute in attributes {
+			if !format.contains(attribute) {
+				format.attributes.push(attribute);
+			}
+		}
+		format
+	}
+
+	/// Every attribute `mesh` has data for.
+	pub fn of(mesh: &MeshData) -> Self {
+		VertexFormat::new(
+			VertexAttribute::ALL
+				.iter()
+				.copied()
+				.filter(|attribute| attribute.present_in(mesh)),
+		)
+	}
+
+	/// Format of a `vertex_layout!` struct.
+	pub fn of_layout<V: VertexLayout>() -> Self {
+		VertexFormat::new(V::ATTRIBUTES.iter().copied())
+	}
+
+	pub fn attributes(&self) -> &[VertexAttribute] {
+		&self.attributes
+	}
+
+	pub fn contains(&self, attribute: VertexAttribute) -> bool {
+		self.attributes.contains(&attribute)
+	}
+
+	/// Size of one vertex in bytes.
+	pub fn stride(&self) -> usize {
+		self.attributes
+			.iter()
+			.map(|attribute| attribute.components() * 4)
+			.sum()
+	}
+
+	/// Bytes from the start of a vertex to `attribute`.
+	pub fn offset(&self, attribute: VertexAttribute) -> Option<usize> {
+		let index = self.attributes.iter().position(|&a| a == attribute)?;
+		Some(
+			self.attributes[..index]
+				.iter()
+				.map(|attribute| attribute.components() * 4)
+				.sum(),
+		)
+	}
+
+	/// Interleaves `mesh` as 32 bit words. Attributes the mesh is missing
+	/// get defaults.
+	pub fn interleave(&self, mesh: &MeshData) -> Vec<u32> {
+		let mut out = Vec::with_capacity(mesh.vertex_count() * self.stride() / 4);
+		for index in 0..mesh.vertex_count() {
+			for attribute in self.attributes.iter() {
+				attribute.write(mesh, index, &mut out);
+			}
+		}
+		out
+	}
+}
+
+unsafe impl<I> VertexDefinition<I> for VertexFormat
+where
+	I: ShaderInterfaceDef,
+{
+	type BuffersIter = IntoIter<(u32, usize, InputRate)>;
+	type AttribsIter = IntoIter<(u32, u32, AttributeInfo)>;
+
+	fn definition(
+		&self,
+		interface: &I,
+	) -> Result<(Self::BuffersIter, Self::AttribsIter), IncompatibleVertexDefinitionError> {
+		let mut attributes = Vec::new();
+		for element in interface.elements() {
+			let name = element.name.as_deref().unwrap_or_default();
+			let attribute = self
+				.attributes
+				.iter()
+				.copied()
+				.find(|attribute| attribute.name() == name)
+				.ok_or_else(|| IncompatibleVertexDefinitionError::MissingAttribute {
+					attribute: name.to_string(),
+				})?;
+
+			let locations = element.location.end - element.location.start;
+			if !attribute
+				.member_ty()
+				.matches(attribute.components(), element.format, locations)
+			{
+				return Err(IncompatibleVertexDefinitionError::FormatMismatch {
+					attribute: name.to_string(),
+					shader: (element.format, locations as usize),
+					definition: (attribute.member_ty(), attribute.components()),
+				});
+			}
+
+			let offset = self.offset(attribute).unwrap();
+			attributes.push((
+				element.location.start,
+				0,
+				AttributeInfo {
+					offset,
+					format: element.format,
+				},
+			));
+		}
+
+		let buffers = vec![(0, self.stride(), InputRate::Vertex)];
+		Ok((buffers.into_iter(), attributes.into_iter()))
+	}
+}
+
+unsafe impl VertexSource<Vec<Arc<dyn BufferAccess + Send + Sync>>> for VertexFormat {
+	fn decode(
+		&self,
+		mut source: Vec<Arc<dyn BufferAccess + Send + Sync>>,
+	) -> (Vec<Box<dyn BufferAccess + Send + Sync>>, usize, usize) {
+		assert_eq!(source.len(), 1, "a vertex format takes a single buffer");
+		let count = source[0].size() / self.stride();
+		(vec![Box::new(source.remove(0))], count, 1)
+	}
+}
+
+impl MeshData {
+	/// Interleaves the mesh into `V`s, see `vertex_layout!`.
+	pub fn interleave<V: VertexLayout>(&self) -> Vec<V> {
+		(0..self.vertex_count())
+			.map(|index| V::from_mesh(self, index))
+			.collect()
+	}
+}
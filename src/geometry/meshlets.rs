@@ -0,0 +1,257 @@
+// splits meshes into small clusters of triangles that can be culled on their
+// own on the cpu and drawn as ranges of a regular index buffer.
+//
+// clusters are grown greedily from a seed triangle, always taking the
+// neighbouring triangle that adds the fewest new vertices, so each one stays
+// compact and its bounds are tight.
+//
+// only the cpu path is here. the same clusters could feed task and mesh
+// shaders, but vulkano 0.22 can't enable `VK_EXT_mesh_shader` or build those
+// pipelines, so there is no gpu path to choose between yet.
+
+use super::MeshData;
+use crate::math::{add, cross, dot, length, normalize, scale, sub, Frustum, Vec3};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Small enough that a culled meshlet skips a useful amount of work.
+pub const MAX_MESHLET_VERTICES: usize = 64;
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+
+/// A cluster of up to `MAX_MESHLET_TRIANGLES` triangles.
+#[derive(Debug, Clone)]
+pub struct Meshlet {
+	/// Range of `Meshlets::vertices` holding the mesh vertex indices it uses.
+	pub vertices: Range<u32>,
+	/// Range of `Meshlets::triangles`, 3 local vertex indices per triangle.
+	pub triangles: Range<u32>,
+	pub center: Vec3,
+	pub radius: f32,
+	/// Average facing of the triangles. The whole meshlet faces away from the
+	/// camera when the view direction is within the cone around this axis.
+	pub cone_axis: Vec3,
+	/// Cosine of the angle between the axis and view directions that are
+	/// culled, 1 when the triangles face too many ways to cull anything.
+	pub cone_cutoff: f32,
+}
+
+impl Meshlet {
+	pub fn triangle_count(&self) -> u32 {
+		(self.triangles.end - self.triangles.start) / 3
+	}
+
+	/// False when every triangle faces away from `eye`.
+	pub fn is_front_facing(&self, eye: Vec3) -> bool {
+		if self.cone_cutoff >= 1.0 {
+			return true;
+		}
+		let to_center = sub(self.center, eye);
+		dot(to_center, self.cone_axis) < self.cone_cutoff * length(to_center) + self.radius
+	}
+
+	pub fn is_visible(&self, frustum: &Frustum, eye: Vec3) -> bool {
+		frustum.intersects_sphere(self.center, self.radius) && self.is_front_facing(eye)
+	}
+}
+
+/// A mesh split into meshlets.
+#[derive(Debug, Clone, Default)]
+pub struct Meshlets {
+	pub meshlets: Vec<Meshlet>,
+	/// Mesh vertex indices, referenced by `Meshlet::vertices`.
+	pub vertices: Vec<u32>,
+	/// Local vertex indices, referenced by `Meshlet::triangles`.
+	pub triangles: Vec<u8>,
+}
+
+impl Meshlets {
+	/// Index buffer for the regular vertex pipeline, with each meshlet's
+	/// triangles stored contiguously at `index_range`.
+	pub fn indices(&self) -> Vec<u32> {
+		self.meshlets
+			.iter()
+			.flat_map(|meshlet| {
+				let vertices = &self.vertices[meshlet.vertices.start as usize..];
+				self.triangles[meshlet.triangles.start as usize..meshlet.triangles.end as usize]
+					.iter()
+					.map(move |&local| vertices[local as usize])
+			})
+			.collect()
+	}
+
+	/// Where meshlet `index`'s triangles start and end in `indices`.
+	pub fn index_range(&self, index: usize) -> Range<u32> {
+		// triangles are stored in meshlet order, so the offsets line up
+		let meshlet = &self.meshlets[index];
+		meshlet.triangles.clone()
+	}
+
+	/// Culls meshlets against the frustum and by facing, returning the index
+	/// ranges left to draw, with neighbouring ranges merged. Draw each range
+	/// of the `indices` buffer with a regular pipeline.
+	pub fn visible_ranges(&self, frustum: &Frustum, eye: Vec3) -> Vec<Range<u32>> {
+		let mut ranges: Vec<Range<u32>> = Vec::new();
+		for (i, meshlet) in self.meshlets.iter().enumerate() {
+			if !meshlet.is_visible(frustum, eye) {
+				continue;
+			}
+			let range = self.index_range(i);
+			match ranges.last_mut() {
+				Some(last) if last.end == range.start => last.end = range.end,
+				_ => ranges.push(range),
+			}
+		}
+		ranges
+	}
+}
+
+impl MeshData {
+	/// Splits the mesh into meshlets, loaders call this at import time for
+	/// meshes that go through the meshlet path.
+	pub fn build_meshlets(&self) -> Meshlets {
+		let triangle_count = self.triangle_count();
+		let triangle = |t: usize| {
+			[
+				self.indices[t * 3],
+				self.indices[t * 3 + 1],
+				self.indices[t * 3 + 2],
+			]
+		};
+
+		let mut vertex_triangles: HashMap<u32, Vec<usize>> = HashMap::new();
+		for t in 0..triangle_count {
+			for index in triangle(t).iter() {
+				vertex_triangles.entry(*index).or_default().push(t);
+			}
+		}
+
+		let mut used = vec![false; triangle_count];
+		let mut next_seed = 0;
+		let mut result = Meshlets::default();
+
+		loop {
+			while next_seed < triangle_count && used[next_seed] {
+				next_seed += 1;
+			}
+			if next_seed == triangle_count {
+				break;
+			}
+
+			// local index of each mesh vertex in the meshlet being built
+			let mut local: HashMap<u32, u8> = HashMap::new();
+			let vertex_start = result.vertices.len();
+			let triangle_start = result.triangles.len();
+			let mut current = Some(next_seed);
+
+			while let Some(t) = current {
+				let indices = triangle(t);
+				let new_vertices = indices
+					.iter()
+					.filter(|index| !local.contains_key(index))
+					.count();
+				if local.len() + new_vertices > MAX_MESHLET_VERTICES
+					|| (result.triangles.len() - triangle_start) / 3 >= MAX_MESHLET_TRIANGLES
+				{
+					break;
+				}
+
+				used[t] = true;
+				for index in indices.iter() {
+					let next = local.len() as u8;
+					let slot = *local.entry(*index).or_insert_with(|| {
+						result.vertices.push(*index);
+						next
+					});
+					result.triangles.push(slot);
+				}
+
+				// neighbour that adds the fewest new vertices
+				current = local
+					.keys()
+					.flat_map(|index| vertex_triangles[index].iter().copied())
+					.filter(|&candidate| !used[candidate])
+					.min_by_key(|&candidate| {
+						let new_vertices = triangle(candidate)
+							.iter()
+							.filter(|index| !local.contains_key(index))
+							.count();
+						(new_vertices, candidate)
+					});
+			}
+
+			let vertices = vertex_start as u32..result.vertices.len() as u32;
+			let triangles = triangle_start as u32..result.triangles.len() as u32;
+			result
+				.meshlets
+				.push(self.meshlet_bounds(&result, vertices, triangles));
+		}
+
+		result
+	}
+
+	fn meshlet_bounds(
+		&self,
+		meshlets: &Meshlets,
+		vertices: Range<u32>,
+		triangles: Range<u32>,
+	) -> Meshlet {
+		let positions: Vec<Vec3> = meshlets.vertices
+			[vertices.start as usize..vertices.end as usize]
+			.iter()
+			.map(|&index| self.positions[index as usize])
+			.collect();
+
+		let mut min = positions[0];
+		let mut max = positions[0];
+		for p in positions.iter() {
+			for axis in 0..3 {
+				min[axis] = min[axis].min(p[axis]);
+				max[axis] = max[axis].max(p[axis]);
+			}
+		}
+		let center = scale(add(min, max), 0.5);
+		let radius = positions
+			.iter()
+			.map(|&p| length(sub(p, center)))
+			.fold(0.0, f32::max);
+
+		let normals: Vec<Vec3> = meshlets.triangles
+			[triangles.start as usize..triangles.end as usize]
+			.chunks_exact(3)
+			.map(|t| {
+				let [a, b, c] = [
+					positions[t[0] as usize],
+					positions[t[1] as usize],
+					positions[t[2] as usize],
+				];
+				cross(sub(b, a), sub(c, a))
+			})
+			.filter(|n| length(*n) > 0.0)
+			.map(normalize)
+			.collect();
+
+		let sum = normals.iter().fold([0.0; 3], |sum, &n| add(sum, n));
+		let (cone_axis, cone_cutoff) = if length(sum) > 0.0 {
+			let axis = normalize(sum);
+			let min_dot = normals.iter().map(|&n| dot(n, axis)).fold(1.0, f32::min);
+			// with normals spread over more than a hemisphere nothing can be culled
+			if min_dot <= 0.1 {
+				(axis, 1.0)
+			} else {
+				(axis, (1.0 - min_dot * min_dot).sqrt())
+			}
+		} else {
+			([0.0, 1.0, 0.0], 1.0)
+		};
+
+		Meshlet {
+			vertices,
+			triangles,
+			center,
+			radius,
+			cone_axis,
+			cone_cutoff,
+		}
+	}
+}
@@ -0,0 +1,151 @@
+pub mod layout;
+pub mod meshlets;
+pub mod shapes;
+pub mod splines;
+mod tangents;
+
+use crate::math::{Vec2, Vec3, Vec4};
+
+/// Cpu side mesh data.
+///
+/// Attributes are kept in separate streams so they can be packed into whatever
+/// vertex layout a pipeline needs. Every stream that is not empty has one entry
+/// per position.
+///
+/// Conventions: y is up, front faces wind counter-clockwise, uv (0, 0) is the
+/// top left of the texture and `tangent.w` is the sign to apply to
+/// `cross(normal, tangent.xyz)` to get the bitangent (pointing towards -v).
+///
+/// `uvs2` is an optional second uv channel, usually non-overlapping lightmap
+/// coordinates, and is left empty for meshes without one.
+///
+/// `colors` are optional linear vertex colors, left empty when the mesh has none.
+///
+/// `joints` and `weights` are only filled in for skinned meshes and hold up to
+/// four joint influences per vertex.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+	pub positions: Vec<Vec3>,
+	pub normals: Vec<Vec3>,
+	pub tangents: Vec<Vec4>,
+	pub uvs: Vec<Vec2>,
+	pub uvs2: Vec<Vec2>,
+	pub colors: Vec<Vec4>,
+	pub joints: Vec<[u16; 4]>,
+	pub weights: Vec<Vec4>,
+	pub indices: Vec<u32>,
+}
+
+impl MeshData {
+	pub fn vertex_count(&self) -> usize {
+		self.positions.len()
+	}
+
+	pub fn triangle_count(&self) -> usize {
+		self.indices.len() / 3
+	}
+
+	pub fn is_skinned(&self) -> bool {
+		!self.joints.is_empty()
+	}
+
+	/// Adds a vertex with all attributes and returns its index.
+	pub fn push_vertex(&mut self, position: Vec3, normal: Vec3, tangent: Vec4, uv: Vec2) -> u32 {
+		let index = self.positions.len() as u32;
+		self.positions.push(position);
+		self.normals.push(normal);
+		self.tangents.push(tangent);
+		self.uvs.push(uv);
+		index
+	}
+
+	/// Appends another mesh, offsetting its indices. Attributes only one of
+	/// the meshes has are padded with defaults so the streams stay lined up.
+	pub fn append(&mut self, other: &MeshData) {
+		let offset = self.positions.len() as u32;
+		self.positions.extend_from_slice(&other.positions);
+		let count = self.positions.len();
+		append_stream(
+			&mut self.normals,
+			&other.normals,
+			offset,
+			count,
+			[0.0, 1.0, 0.0],
+		);
+		append_stream(
+			&mut self.tangents,
+			&other.tangents,
+			offset,
+			count,
+			[1.0, 0.0, 0.0, 1.0],
+		);
+		append_stream(&mut self.uvs, &other.uvs, offset, count, [0.0; 2]);
+		append_stream(&mut self.uvs2, &other.uvs2, offset, count, [0.0; 2]);
+		append_stream(&mut self.colors, &other.colors, offset, count, [1.0; 4]);
+		append_stream(&mut self.joints, &other.joints, offset, count, [0; 4]);
+		// fully bound to the first joint
+		append_stream(
+			&mut self.weights,
+			&other.weights,
+			offset,
+			count,
+			[1.0, 0.0, 0.0, 0.0],
+		);
+		self.indices
+			.extend(other.indices.iter().map(|index| index + offset));
+	}
+}
+
+// appends `other` to one attribute stream, padding with `fill` for the
+// vertices of whichever mesh doesn't have it. streams neither mesh has stay empty
+fn append_stream<T: Copy>(stream: &mut Vec<T>, other: &[T], offset: u32, count: usize, fill: T) {
+	if stream.is_empty() && other.is_empty() {
+		return;
+	}
+	stream.resize(offset as usize, fill);
+	stream.extend_from_slice(other);
+	stream.resize(count, fill);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn append_pads_missing_streams() {
+		let mut mesh = shapes::plane([1.0, 1.0], [1, 1]);
+		let before = mesh.vertex_count();
+		assert!(mesh.uvs2.is_empty());
+
+		let mut other = shapes::plane([1.0, 1.0], [1, 1]);
+		other.uvs2 = vec![[0.5, 0.5]; other.vertex_count()];
+		mesh.append(&other);
+
+		let count = mesh.vertex_count();
+		assert_eq!(count, before + other.vertex_count());
+		assert_eq!(mesh.uvs2.len(), count);
+		assert!(mesh.uvs2[..before].iter().all(|uv| *uv == [0.0; 2]));
+		assert!(mesh.uvs2[before..].iter().all(|uv| *uv == [0.5, 0.5]));
+		assert_eq!(mesh.normals.len(), count);
+		assert_eq!(mesh.tangents.len(), count);
+		assert_eq!(mesh.uvs.len(), count);
+		assert!(mesh.colors.is_empty());
+		assert!(mesh.joints.is_empty());
+		assert!(mesh.weights.is_empty());
+		assert!(mesh.indices.iter().all(|index| (*index as usize) < count));
+	}
+
+	#[test]
+	fn append_pads_streams_the_other_mesh_lacks() {
+		let mut mesh = shapes::plane([1.0, 1.0], [1, 1]);
+		mesh.joints = vec![[1, 0, 0, 0]; mesh.vertex_count()];
+		mesh.weights = vec![[1.0, 0.0, 0.0, 0.0]; mesh.vertex_count()];
+		let before = mesh.vertex_count();
+
+		mesh.append(&shapes::plane([1.0, 1.0], [1, 1]));
+
+		assert_eq!(mesh.joints.len(), mesh.vertex_count());
+		assert_eq!(mesh.weights.len(), mesh.vertex_count());
+		assert_eq!(mesh.joints[before], [0; 4]);
+	}
+}
@@ -0,0 +1,403 @@
+// procedural primitive meshes so demos don't need any assets.
+//
+// everything is centered on the origin with y up. resolution arguments are
+// clamped to the smallest value that still produces a closed shape.
+
+use super::MeshData;
+use crate::math::{add, cross, normalize, scale, Vec2, Vec3};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// Flat plane in the xz plane facing +y.
+pub fn plane(size: [f32; 2], subdivisions: [u32; 2]) -> MeshData {
+	let mut mesh = MeshData::default();
+	face(
+		&mut mesh,
+		[-size[0] * 0.5, 0.0, -size[1] * 0.5],
+		[size[0], 0.0, 0.0],
+		[0.0, 0.0, size[1]],
+		subdivisions[0].max(1),
+		subdivisions[1].max(1),
+	);
+	mesh
+}
+
+/// Box with hard edges. Each face is split into `subdivisions` x `subdivisions` quads.
+pub fn cube(size: [f32; 3], subdivisions: u32) -> MeshData {
+	let [x, y, z] = [size[0] * 0.5, size[1] * 0.5, size[2] * 0.5];
+	let n = subdivisions.max(1);
+
+	// top left corner, u axis and v axis of each face: +x, -x, +y, -y, +z, -z
+	let faces = [
+		([x, y, z], [0.0, 0.0, -size[2]], [0.0, -size[1], 0.0]),
+		([-x, y, -z], [0.0, 0.0, size[2]], [0.0, -size[1], 0.0]),
+		([-x, y, -z], [size[0], 0.0, 0.0], [0.0, 0.0, size[2]]),
+		([-x, -y, z], [size[0], 0.0, 0.0], [0.0, 0.0, -size[2]]),
+		([-x, y, z], [size[0], 0.0, 0.0], [0.0, -size[1], 0.0]),
+		([x, y, -z], [-size[0], 0.0, 0.0], [0.0, -size[1], 0.0]),
+	];
+
+	let mut mesh = MeshData::default();
+	for &(origin, u_axis, v_axis) in faces.iter() {
+		face(&mut mesh, origin, u_axis, v_axis, n, n);
+	}
+	mesh
+}
+
+/// Latitude/longitude sphere. `sectors` runs around y and `stacks` from pole to pole.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+	let sectors = sectors.max(3);
+	let stacks = stacks.max(2);
+
+	let mut mesh = MeshData::default();
+	grid(&mut mesh, sectors, stacks, true, true, |i, j| {
+		let u = i as f32 / sectors as f32;
+		let v = j as f32 / stacks as f32;
+		let normal = sphere_normal(u * 2.0 * PI, v * PI);
+		(
+			scale(normal, radius),
+			normal,
+			around_y(u * 2.0 * PI),
+			[u, v],
+		)
+	});
+	mesh
+}
+
+/// Sphere made by subdividing an icosahedron, which spreads triangles evenly
+/// instead of bunching them at the poles.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+	let t = (1.0 + 5f32.sqrt()) * 0.5;
+	let mut points: Vec<Vec3> = [
+		[-1.0, t, 0.0],
+		[1.0, t, 0.0],
+		[-1.0, -t, 0.0],
+		[1.0, -t, 0.0],
+		[0.0, -1.0, t],
+		[0.0, 1.0, t],
+		[0.0, -1.0, -t],
+		[0.0, 1.0, -t],
+		[t, 0.0, -1.0],
+		[t, 0.0, 1.0],
+		[-t, 0.0, -1.0],
+		[-t, 0.0, 1.0],
+	]
+	.iter()
+	.map(|&p| normalize(p))
+	.collect();
+
+	let mut triangles: Vec<[u32; 3]> = vec![
+		[0, 11, 5],
+		[0, 5, 1],
+		[0, 1, 7],
+		[0, 7, 10],
+		[0, 10, 11],
+		[1, 5, 9],
+		[5, 11, 4],
+		[11, 10, 2],
+		[10, 7, 6],
+		[7, 1, 8],
+		[3, 9, 4],
+		[3, 4, 2],
+		[3, 2, 6],
+		[3, 6, 8],
+		[3, 8, 9],
+		[4, 9, 5],
+		[2, 4, 11],
+		[6, 2, 10],
+		[8, 6, 7],
+		[9, 8, 1],
+	];
+
+	for _ in 0..subdivisions {
+		let mut midpoints = HashMap::new();
+		let mut midpoint = |a: u32, b: u32, points: &mut Vec<Vec3>| -> u32 {
+			let key = (a.min(b), a.max(b));
+			*midpoints.entry(key).or_insert_with(|| {
+				let p = normalize(add(points[a as usize], points[b as usize]));
+				points.push(p);
+				points.len() as u32 - 1
+			})
+		};
+
+		let mut next = Vec::with_capacity(triangles.len() * 4);
+		for &[a, b, c] in triangles.iter() {
+			let ab = midpoint(a, b, &mut points);
+			let bc = midpoint(b, c, &mut points);
+			let ca = midpoint(c, a, &mut points);
+			next.push([a, ab, ca]);
+			next.push([b, bc, ab]);
+			next.push([c, ca, bc]);
+			next.push([ab, bc, ca]);
+		}
+		triangles = next;
+	}
+
+	let mut mesh = MeshData::default();
+	for &normal in points.iter() {
+		let phi = (-normal[2]).atan2(normal[0]);
+		let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+		let uv = [phi / (2.0 * PI), normal[1].clamp(-1.0, 1.0).acos() / PI];
+		push(&mut mesh, scale(normal, radius), normal, around_y(phi), uv);
+	}
+
+	// triangles that straddle the u seam get their low side duplicated with u + 1
+	// so the texture doesn't wrap backwards across the whole triangle.
+	let mut seam_copies = HashMap::new();
+	for triangle in triangles.iter_mut() {
+		let us = [
+			mesh.uvs[triangle[0] as usize][0],
+			mesh.uvs[triangle[1] as usize][0],
+			mesh.uvs[triangle[2] as usize][0],
+		];
+		let max = us[0].max(us[1]).max(us[2]);
+		let min = us[0].min(us[1]).min(us[2]);
+		if max - min < 0.5 {
+			continue;
+		}
+
+		for (index, &u) in triangle.iter_mut().zip(us.iter()) {
+			if u < 0.5 {
+				let original = *index as usize;
+				*index = *seam_copies.entry(original).or_insert_with(|| {
+					let uv = mesh.uvs[original];
+					mesh.push_vertex(
+						mesh.positions[original],
+						mesh.normals[original],
+						mesh.tangents[original],
+						[uv[0] + 1.0, uv[1]],
+					)
+				});
+			}
+		}
+	}
+
+	mesh.indices = triangles.iter().flatten().cloned().collect();
+	mesh
+}
+
+/// Capped cylinder along y.
+pub fn cylinder(radius: f32, height: f32, sectors: u32, stacks: u32) -> MeshData {
+	let sectors = sectors.max(3);
+	let stacks = stacks.max(1);
+	let top = height * 0.5;
+
+	let mut mesh = MeshData::default();
+	grid(&mut mesh, sectors, stacks, false, false, |i, j| {
+		let u = i as f32 / sectors as f32;
+		let v = j as f32 / stacks as f32;
+		let phi = u * 2.0 * PI;
+		let normal = [phi.cos(), 0.0, -phi.sin()];
+		(
+			[normal[0] * radius, top - v * height, normal[2] * radius],
+			normal,
+			around_y(phi),
+			[u, v],
+		)
+	});
+	disc(&mut mesh, top, radius, sectors, true);
+	disc(&mut mesh, -top, radius, sectors, false);
+	mesh
+}
+
+/// Cone along y with its tip at the top.
+pub fn cone(radius: f32, height: f32, sectors: u32) -> MeshData {
+	let sectors = sectors.max(3);
+	let top = height * 0.5;
+
+	let mut mesh = MeshData::default();
+	grid(&mut mesh, sectors, 1, true, false, |i, j| {
+		let u = i as f32 / sectors as f32;
+		// the tip is split per sector and takes the normal halfway across the
+		// triangle it belongs to so the shading stays smooth around the side.
+		let phi = if j == 0 {
+			(i as f32 - 0.5) / sectors as f32 * 2.0 * PI
+		} else {
+			u * 2.0 * PI
+		};
+		let normal = normalize([height * phi.cos(), radius, -height * phi.sin()]);
+		let position = if j == 0 {
+			[0.0, top, 0.0]
+		} else {
+			[radius * phi.cos(), -top, -radius * phi.sin()]
+		};
+		(position, normal, around_y(phi), [u, j as f32])
+	});
+	disc(&mut mesh, -top, radius, sectors, false);
+	mesh
+}
+
+/// Cylinder with hemispherical ends. `height` is the length of the straight section.
+pub fn capsule(radius: f32, height: f32, sectors: u32, rings: u32) -> MeshData {
+	let sectors = sectors.max(3);
+	let rings = rings.max(1);
+	let half = height * 0.5;
+
+	// v follows the arc length of the profile so the texture isn't squashed
+	let length = PI * radius + height;
+
+	let mut mesh = MeshData::default();
+	grid(&mut mesh, sectors, rings * 2 + 1, true, true, |i, j| {
+		let u = i as f32 / sectors as f32;
+		let (theta, offset, distance) = if j <= rings {
+			let theta = j as f32 / rings as f32 * PI * 0.5;
+			(theta, half, theta * radius)
+		} else {
+			let theta = (1.0 + (j - rings - 1) as f32 / rings as f32) * PI * 0.5;
+			(theta, -half, theta * radius + height)
+		};
+		let normal = sphere_normal(u * 2.0 * PI, theta);
+		(
+			add(scale(normal, radius), [0.0, offset, 0.0]),
+			normal,
+			around_y(u * 2.0 * PI),
+			[u, distance / length],
+		)
+	});
+	mesh
+}
+
+/// Torus around y. `major_radius` is the distance from the center to the middle of the tube.
+pub fn torus(
+	major_radius: f32,
+	minor_radius: f32,
+	major_segments: u32,
+	minor_segments: u32,
+) -> MeshData {
+	let major_segments = major_segments.max(3);
+	let minor_segments = minor_segments.max(3);
+
+	let mut mesh = MeshData::default();
+	grid(
+		&mut mesh,
+		major_segments,
+		minor_segments,
+		false,
+		false,
+		|i, j| {
+			let u = i as f32 / major_segments as f32;
+			let v = j as f32 / minor_segments as f32;
+			let phi = u * 2.0 * PI;
+			let psi = v * 2.0 * PI;
+			let center = [major_radius * phi.cos(), 0.0, -major_radius * phi.sin()];
+			// psi runs down the outside of the tube so v increases downwards
+			let normal = [psi.cos() * phi.cos(), -psi.sin(), -psi.cos() * phi.sin()];
+			(
+				add(center, scale(normal, minor_radius)),
+				normal,
+				around_y(phi),
+				[u, v],
+			)
+		},
+	);
+	mesh
+}
+
+// unit normal on a sphere where phi goes around y and theta is measured down from +y
+fn sphere_normal(phi: f32, theta: f32) -> Vec3 {
+	[
+		theta.sin() * phi.cos(),
+		theta.cos(),
+		-theta.sin() * phi.sin(),
+	]
+}
+
+// tangent of a circle around y, pointing in the direction of increasing phi
+fn around_y(phi: f32) -> Vec3 {
+	[-phi.sin(), 0.0, -phi.cos()]
+}
+
+fn push(mesh: &mut MeshData, position: Vec3, normal: Vec3, tangent: Vec3, uv: Vec2) -> u32 {
+	mesh.push_vertex(
+		position,
+		normal,
+		[tangent[0], tangent[1], tangent[2], 1.0],
+		uv,
+	)
+}
+
+// adds a (columns + 1) x (rows + 1) vertex grid with u along the columns and v
+// along the rows. the first or last row of triangles can be dropped when all of
+// that row's vertices meet at one point (poles, cone tips).
+fn grid<F>(
+	mesh: &mut MeshData,
+	columns: u32,
+	rows: u32,
+	collapse_first_row: bool,
+	collapse_last_row: bool,
+	vertex: F,
+) where
+	F: Fn(u32, u32) -> (Vec3, Vec3, Vec3, Vec2),
+{
+	let base = mesh.positions.len() as u32;
+	for j in 0..=rows {
+		for i in 0..=columns {
+			let (position, normal, tangent, uv) = vertex(i, j);
+			push(mesh, position, normal, tangent, uv);
+		}
+	}
+
+	let stride = columns + 1;
+	for j in 0..rows {
+		for i in 0..columns {
+			let a = base + j * stride + i;
+			let b = a + 1;
+			let c = a + stride;
+			let d = c + 1;
+			if !(collapse_first_row && j == 0) {
+				mesh.indices.extend_from_slice(&[a, c, b]);
+			}
+			if !(collapse_last_row && j == rows - 1) {
+				mesh.indices.extend_from_slice(&[b, c, d]);
+			}
+		}
+	}
+}
+
+// flat quad grid starting at the top left corner `origin`.
+// the face points along cross(v_axis, u_axis).
+fn face(mesh: &mut MeshData, origin: Vec3, u_axis: Vec3, v_axis: Vec3, columns: u32, rows: u32) {
+	let normal = normalize(cross(v_axis, u_axis));
+	let tangent = normalize(u_axis);
+	grid(mesh, columns, rows, false, false, |i, j| {
+		let u = i as f32 / columns as f32;
+		let v = j as f32 / rows as f32;
+		let position = add(origin, add(scale(u_axis, u), scale(v_axis, v)));
+		(position, normal, tangent, [u, v])
+	});
+}
+
+// cap for cylinders and cones with planar uvs
+fn disc(mesh: &mut MeshData, y: f32, radius: f32, sectors: u32, up: bool) {
+	let (normal, flip) = if up {
+		([0.0, 1.0, 0.0], 1.0)
+	} else {
+		([0.0, -1.0, 0.0], -1.0)
+	};
+	let tangent = [1.0, 0.0, 0.0];
+
+	let center = push(mesh, [0.0, y, 0.0], normal, tangent, [0.5, 0.5]);
+	for k in 0..sectors {
+		let phi = k as f32 / sectors as f32 * 2.0 * PI;
+		let x = phi.cos();
+		let z = -phi.sin();
+		push(
+			mesh,
+			[x * radius, y, z * radius],
+			normal,
+			tangent,
+			[x * 0.5 + 0.5, z * flip * 0.5 + 0.5],
+		);
+	}
+
+	for k in 0..sectors {
+		let a = center + 1 + k;
+		let b = center + 1 + (k + 1) % sectors;
+		if up {
+			mesh.indices.extend_from_slice(&[center, a, b]);
+		} else {
+			mesh.indices.extend_from_slice(&[center, b, a]);
+		}
+	}
+}
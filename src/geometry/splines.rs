@@ -0,0 +1,536 @@
+// smooth curves through 3d space and meshes swept along them.
+//
+// every curve is a chain of cubic bezier segments, catmull-rom splines are
+// converted to bezier control points per segment. `t` runs from 0 at the
+// start of the curve to 1 at the end, but isn't proportional to distance;
+// `ArcLength` maps distances back to `t` so extrusions and anything moving
+// along the curve go at an even speed.
+
+use super::MeshData;
+use crate::math::{add, cross, dot, length, lerp, normalize, scale, sub, Vec2, Vec3};
+
+/// A curve made of cubic bezier segments.
+pub trait Curve {
+	fn segment_count(&self) -> usize;
+
+	/// Bezier control points of one segment.
+	fn segment(&self, index: usize) -> [Vec3; 4];
+
+	/// Whether the end joins back up with the start.
+	fn closed(&self) -> bool {
+		false
+	}
+
+	/// Position at `t` in 0..1.
+	fn point(&self, t: f32) -> Vec3 {
+		let (index, t) = locate(self.segment_count(), t);
+		bezier_point(self.segment(index), t)
+	}
+
+	/// Derivative with respect to `t`, not normalized.
+	fn derivative(&self, t: f32) -> Vec3 {
+		let (index, t) = locate(self.segment_count(), t);
+		// scaled by the segment count so the derivative is relative to the whole curve
+		scale(
+			bezier_derivative(self.segment(index), t),
+			self.segment_count() as f32,
+		)
+	}
+
+	/// Unit direction of travel at `t`.
+	fn tangent(&self, t: f32) -> Vec3 {
+		normalize(self.derivative(t))
+	}
+}
+
+/// Chain of cubic bezier segments sharing end points.
+///
+/// `points` starts with the first end point and continues with two handles
+/// and an end point per segment, so it holds `3 * segments + 1` points.
+#[derive(Debug, Clone, Default)]
+pub struct Bezier {
+	pub points: Vec<Vec3>,
+}
+
+impl Bezier {
+	pub fn new(points: Vec<Vec3>) -> Self {
+		assert!(
+			points.len() >= 4 && (points.len() - 1).is_multiple_of(3),
+			"bezier needs 3 * segments + 1 points"
+		);
+		Bezier { points }
+	}
+
+	/// Single segment from `start` to `end`.
+	pub fn cubic(start: Vec3, handle_start: Vec3, handle_end: Vec3, end: Vec3) -> Self {
+		Bezier {
+			points: vec![start, handle_start, handle_end, end],
+		}
+	}
+}
+
+impl Curve for Bezier {
+	fn segment_count(&self) -> usize {
+		(self.points.len().max(1) - 1) / 3
+	}
+
+	fn segment(&self, index: usize) -> [Vec3; 4] {
+		let p = &self.points[index * 3..index * 3 + 4];
+		[p[0], p[1], p[2], p[3]]
+	}
+}
+
+/// Spline passing through every one of its points.
+///
+/// `alpha` picks how the points are spaced in `t`: 0 is uniform, 0.5 is
+/// centripetal and 1 is chordal. Centripetal never forms loops or cusps
+/// between the points and is the usual choice for paths.
+#[derive(Debug, Clone)]
+pub struct CatmullRom {
+	pub points: Vec<Vec3>,
+	pub closed: bool,
+	pub alpha: f32,
+}
+
+impl CatmullRom {
+	/// Centripetal spline through `points`.
+	pub fn new(points: Vec<Vec3>, closed: bool) -> Self {
+		assert!(points.len() >= 2, "catmull-rom needs at least two points");
+		CatmullRom {
+			points,
+			closed,
+			alpha: 0.5,
+		}
+	}
+
+	fn point_at(&self, index: isize) -> Vec3 {
+		let count = self.points.len() as isize;
+		if self.closed {
+			return self.points[index.rem_euclid(count) as usize];
+		}
+		// open ends are extended by mirroring the neighbouring point
+		if index < 0 {
+			sub(scale(self.points[0], 2.0), self.points[1])
+		} else if index >= count {
+			let last = count as usize - 1;
+			sub(scale(self.points[last], 2.0), self.points[last - 1])
+		} else {
+			self.points[index as usize]
+		}
+	}
+}
+
+impl Curve for CatmullRom {
+	fn segment_count(&self) -> usize {
+		if self.closed {
+			self.points.len()
+		} else {
+			self.points.len() - 1
+		}
+	}
+
+	fn segment(&self, index: usize) -> [Vec3; 4] {
+		let i = index as isize;
+		let [p0, p1, p2, p3] = [
+			self.point_at(i - 1),
+			self.point_at(i),
+			self.point_at(i + 1),
+			self.point_at(i + 2),
+		];
+
+		// knot intervals, kept away from zero for repeated points
+		let knot = |a: Vec3, b: Vec3| length(sub(b, a)).powf(self.alpha).max(1e-4);
+		let (t01, t12, t23) = (knot(p0, p1), knot(p1, p2), knot(p2, p3));
+
+		// hermite tangents of the non-uniform spline over the p1..p2 interval
+		let m1 = add(
+			sub(p2, p1),
+			scale(
+				sub(
+					scale(sub(p1, p0), 1.0 / t01),
+					scale(sub(p2, p0), 1.0 / (t01 + t12)),
+				),
+				t12,
+			),
+		);
+		let m2 = add(
+			sub(p2, p1),
+			scale(
+				sub(
+					scale(sub(p3, p2), 1.0 / t23),
+					scale(sub(p3, p1), 1.0 / (t12 + t23)),
+				),
+				t12,
+			),
+		);
+
+		[
+			p1,
+			add(p1, scale(m1, 1.0 / 3.0)),
+			sub(p2, scale(m2, 1.0 / 3.0)),
+			p2,
+		]
+	}
+
+	fn closed(&self) -> bool {
+		self.closed
+	}
+}
+
+/// Table mapping distance along a curve to `t`.
+#[derive(Debug, Clone)]
+pub struct ArcLength {
+	// distance from the start at evenly spaced t
+	distances: Vec<f32>,
+}
+
+impl ArcLength {
+	/// Measures `curve` with `samples_per_segment` straight pieces per
+	/// segment. 16 to 32 is plenty for gently bending curves.
+	pub fn new(curve: &impl Curve, samples_per_segment: usize) -> Self {
+		let count = (curve.segment_count() * samples_per_segment).max(1);
+		let mut distances = Vec::with_capacity(count + 1);
+		distances.push(0.0);
+		let mut previous = curve.point(0.0);
+		let mut total = 0.0;
+		for i in 1..=count {
+			let point = curve.point(i as f32 / count as f32);
+			total += length(sub(point, previous));
+			distances.push(total);
+			previous = point;
+		}
+		ArcLength { distances }
+	}
+
+	pub fn length(&self) -> f32 {
+		*self.distances.last().unwrap()
+	}
+
+	/// `t` at `distance` from the start, clamped to the ends of the curve.
+	pub fn t_at(&self, distance: f32) -> f32 {
+		let count = self.distances.len() - 1;
+		let distance = distance.max(0.0).min(self.length());
+		let upper = self
+			.distances
+			.partition_point(|&d| d < distance)
+			.max(1)
+			.min(count);
+		let (start, end) = (self.distances[upper - 1], self.distances[upper]);
+		let fraction = if end > start {
+			(distance - start) / (end - start)
+		} else {
+			0.0
+		};
+		(upper as f32 - 1.0 + fraction) / count as f32
+	}
+
+	/// Distance from the start at `t`.
+	pub fn distance_at(&self, t: f32) -> f32 {
+		let count = self.distances.len() - 1;
+		let position = t.clamp(0.0, 1.0) * count as f32;
+		let index = (position as usize).min(count - 1);
+		let fraction = position - index as f32;
+		self.distances[index] + (self.distances[index + 1] - self.distances[index]) * fraction
+	}
+
+	/// `t` of `count + 1` points spaced evenly along the curve, including both ends.
+	pub fn even_ts(&self, count: usize) -> Vec<f32> {
+		let count = count.max(1);
+		(0..=count)
+			.map(|i| self.t_at(self.length() * i as f32 / count as f32))
+			.collect()
+	}
+}
+
+/// Orientation at a point on a curve. `right` and `up` span the plane a
+/// profile is placed in, looking along `forward`.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+	pub position: Vec3,
+	pub forward: Vec3,
+	pub right: Vec3,
+	pub up: Vec3,
+}
+
+/// Frames at each of `ts`.
+///
+/// With an `up` direction every frame is turned to keep its up as close to
+/// it as possible, which is what roads and rails want. Without one the
+/// frames are rotation minimizing: they twist as little as possible along
+/// the curve, which suits pipes and cables that can loop over themselves.
+pub fn frames(curve: &impl Curve, ts: &[f32], up: Option<Vec3>) -> Vec<Frame> {
+	let mut frames: Vec<Frame> = Vec::with_capacity(ts.len());
+	for &t in ts {
+		let position = curve.point(t);
+		let mut forward = curve.tangent(t);
+		if dot(forward, forward) == 0.0 {
+			// coincident control points, keep going the way we were
+			forward = frames
+				.last()
+				.map_or([0.0, 0.0, -1.0], |frame| frame.forward);
+		}
+
+		let reference = match (up, frames.last()) {
+			(Some(up), _) => up,
+			(None, None) => [0.0, 1.0, 0.0],
+			(None, Some(previous)) => {
+				// double reflection (wang et al. 2008)
+				let reflect = |v: Vec3, axis: Vec3| {
+					let c = dot(axis, axis);
+					if c < 1e-12 {
+						v
+					} else {
+						sub(v, scale(axis, 2.0 * dot(axis, v) / c))
+					}
+				};
+				let v1 = sub(position, previous.position);
+				let up = reflect(previous.up, v1);
+				let forward_reflected = reflect(previous.forward, v1);
+				reflect(up, sub(forward, forward_reflected))
+			}
+		};
+
+		let mut right = normalize(cross(forward, reference));
+		if dot(right, right) == 0.0 {
+			// going straight along the reference
+			right = frames.last().map_or_else(
+				|| normalize(cross(forward, [1.0, 0.0, 0.0])),
+				|frame| frame.right,
+			);
+		}
+		frames.push(Frame {
+			position,
+			forward,
+			right,
+			up: cross(right, forward),
+		});
+	}
+
+	if up.is_none() && curve.closed() && frames.len() > 1 {
+		// rotation minimizing frames don't come back around to where they
+		// started, spread the leftover twist over the whole loop
+		let (first, last) = (frames[0], frames[frames.len() - 1]);
+		let twist = dot(cross(last.up, first.up), last.forward).atan2(dot(last.up, first.up));
+		let steps = (frames.len() - 1) as f32;
+		for (i, frame) in frames.iter_mut().enumerate() {
+			let angle = twist * i as f32 / steps;
+			frame.up = add(
+				scale(frame.up, angle.cos()),
+				scale(frame.right, angle.sin()),
+			);
+			frame.right = cross(frame.forward, frame.up);
+		}
+	}
+	frames
+}
+
+/// 2d cross section swept along a curve by `extrude`.
+///
+/// Points are in the frame's right (x) and up (y) plane and go counter
+/// clockwise, so the outside is on the right of the direction they're
+/// listed in. Open profiles are only visible from that side.
+#[derive(Debug, Clone)]
+pub struct Profile {
+	pub points: Vec<Vec2>,
+	/// Joins the last point back to the first.
+	pub closed: bool,
+	/// Shares normals between neighbouring edges instead of keeping them hard.
+	pub smooth: bool,
+	/// Distance around the profile the texture covers once.
+	pub texture_width: f32,
+	/// Distance around the profile where u is 0.
+	pub texture_offset: f32,
+}
+
+impl Profile {
+	/// Round tube for pipes and cables.
+	pub fn circle(radius: f32, sides: u32) -> Self {
+		let sides = sides.max(3);
+		let points = (0..sides)
+			.map(|i| {
+				let angle = i as f32 / sides as f32 * 2.0 * std::f32::consts::PI;
+				[radius * angle.cos(), radius * angle.sin()]
+			})
+			.collect();
+		let perimeter = 2.0 * sides as f32 * radius * (std::f32::consts::PI / sides as f32).sin();
+		Profile {
+			points,
+			closed: true,
+			smooth: true,
+			texture_width: perimeter,
+			texture_offset: 0.0,
+		}
+	}
+
+	/// Flat strip facing up, u going from right to left across it.
+	pub fn ribbon(width: f32) -> Self {
+		Profile {
+			points: vec![[width * 0.5, 0.0], [-width * 0.5, 0.0]],
+			closed: false,
+			smooth: false,
+			texture_width: width,
+			texture_offset: 0.0,
+		}
+	}
+
+	/// Flat top with sides running `depth` down so the edges don't show a gap
+	/// where the road meets the ground. The texture covers the top once.
+	pub fn road(width: f32, depth: f32) -> Self {
+		let half = width * 0.5;
+		Profile {
+			points: vec![[half, -depth], [half, 0.0], [-half, 0.0], [-half, -depth]],
+			closed: false,
+			smooth: false,
+			texture_width: width,
+			texture_offset: depth,
+		}
+	}
+
+	// vertices of one ring and the pairs of them that form edges
+	fn ring(&self) -> (Vec<RingVertex>, Vec<[usize; 2]>) {
+		let count = self.points.len();
+		let edge_count = if self.closed { count } else { count - 1 };
+		let edge_normal = |i: usize| {
+			let (a, b) = (self.points[i], self.points[(i + 1) % count]);
+			let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+			let len = (dx * dx + dy * dy).sqrt().max(1e-12);
+			[dy / len, -dx / len]
+		};
+		let u = |distance: f32| (distance - self.texture_offset) / self.texture_width;
+
+		let mut vertices = Vec::new();
+		let mut edges = Vec::new();
+		let mut distance = 0.0;
+		if self.smooth {
+			for i in 0..=edge_count {
+				let point = self.points[i % count];
+				if i > 0 {
+					let previous = self.points[i - 1];
+					let (dx, dy) = (point[0] - previous[0], point[1] - previous[1]);
+					distance += (dx * dx + dy * dy).sqrt();
+				}
+				// open ends only have the one edge to take a normal from
+				let normal = if !self.closed && i == 0 {
+					edge_normal(0)
+				} else if !self.closed && i == edge_count {
+					edge_normal(edge_count - 1)
+				} else {
+					let (a, b) = (edge_normal((i + count - 1) % count), edge_normal(i % count));
+					let sum = [a[0] + b[0], a[1] + b[1]];
+					let len = (sum[0] * sum[0] + sum[1] * sum[1]).sqrt().max(1e-12);
+					[sum[0] / len, sum[1] / len]
+				};
+				vertices.push((point, normal, u(distance)));
+				if i > 0 {
+					edges.push([i - 1, i]);
+				}
+			}
+		} else {
+			for i in 0..edge_count {
+				let (a, b) = (self.points[i], self.points[(i + 1) % count]);
+				let normal = edge_normal(i);
+				let edge_length = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+				vertices.push((a, normal, u(distance)));
+				distance += edge_length;
+				vertices.push((b, normal, u(distance)));
+				edges.push([i * 2, i * 2 + 1]);
+			}
+		}
+		(vertices, edges)
+	}
+}
+
+// position, normal and u of a profile point
+type RingVertex = (Vec2, Vec2, f32);
+
+/// How `extrude` sweeps a profile.
+#[derive(Debug, Clone, Copy)]
+pub struct Extrusion {
+	/// Distance between the rings of vertices along the curve.
+	pub spacing: f32,
+	/// Keeps the profile upright, see `frames`. `None` for rotation
+	/// minimizing frames.
+	pub up: Option<Vec3>,
+	/// Distance along the curve the texture covers once.
+	pub texture_length: f32,
+}
+
+impl Default for Extrusion {
+	fn default() -> Self {
+		Extrusion {
+			spacing: 0.5,
+			up: Some([0.0, 1.0, 0.0]),
+			texture_length: 1.0,
+		}
+	}
+}
+
+/// Sweeps `profile` along `curve` into a mesh with rings spaced evenly by
+/// distance. u goes around the profile and v along the curve. The ends are
+/// left open.
+pub fn extrude(curve: &impl Curve, profile: &Profile, extrusion: &Extrusion) -> MeshData {
+	let mut mesh = MeshData::default();
+	if profile.points.len() < 2 || curve.segment_count() == 0 {
+		return mesh;
+	}
+
+	let arc_length = ArcLength::new(curve, 32);
+	let total = arc_length.length();
+	let rings = ((total / extrusion.spacing.max(1e-4)).ceil() as usize).max(1);
+	let frames = frames(curve, &arc_length.even_ts(rings), extrusion.up);
+	let (ring, edges) = profile.ring();
+
+	for (i, frame) in frames.iter().enumerate() {
+		let v = total * i as f32 / rings as f32 / extrusion.texture_length;
+		for &(point, normal, u) in ring.iter() {
+			let across = |x: f32, y: f32| add(scale(frame.right, x), scale(frame.up, y));
+			mesh.push_vertex(
+				add(frame.position, across(point[0], point[1])),
+				across(normal[0], normal[1]),
+				// direction of increasing u, the bitangent then points back along the curve
+				{
+					let tangent = across(-normal[1], normal[0]);
+					[tangent[0], tangent[1], tangent[2], 1.0]
+				},
+				[u, v],
+			);
+		}
+	}
+
+	let stride = ring.len() as u32;
+	for i in 0..rings as u32 {
+		for &[a, b] in edges.iter() {
+			let (a, b) = (a as u32, b as u32);
+			let [a0, b0, a1, b1] = [
+				i * stride + a,
+				i * stride + b,
+				(i + 1) * stride + a,
+				(i + 1) * stride + b,
+			];
+			mesh.indices.extend_from_slice(&[a0, a1, b0, b0, a1, b1]);
+		}
+	}
+	mesh
+}
+
+// segment and local t for a curve wide t
+fn locate(segments: usize, t: f32) -> (usize, f32) {
+	let position = t.clamp(0.0, 1.0) * segments as f32;
+	let index = (position as usize).min(segments.max(1) - 1);
+	(index, position - index as f32)
+}
+
+fn bezier_point(p: [Vec3; 4], t: f32) -> Vec3 {
+	// de casteljau
+	let a = lerp(p[0], p[1], t);
+	let b = lerp(p[1], p[2], t);
+	let c = lerp(p[2], p[3], t);
+	lerp(lerp(a, b, t), lerp(b, c, t), t)
+}
+
+fn bezier_derivative(p: [Vec3; 4], t: f32) -> Vec3 {
+	let a = sub(p[1], p[0]);
+	let b = sub(p[2], p[1]);
+	let c = sub(p[3], p[2]);
+	scale(lerp(lerp(a, b, t), lerp(b, c, t), t), 3.0)
+}
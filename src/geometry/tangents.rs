@@ -0,0 +1,236 @@
+// normal and tangent generation for meshes that come in without them.
+//
+// tangents follow mikktspace: per corner tangents are projected onto the
+// vertex normal, weighted by the corner angle and only averaged between
+// corners that agree on handedness. uvs are treated as v-down like the rest of
+// the engine, which matches tangents exported to gltf by blender.
+
+use super::MeshData;
+use crate::math::{add, cross, dot, length, normalize, scale, sub, Vec3, Vec4};
+
+use std::collections::HashMap;
+
+impl MeshData {
+	/// Fills in normals and tangents if the mesh doesn't have them.
+	/// Loaders call this so every mesh ends up with a complete tangent frame.
+	/// Fails on indices past the last vertex, eg. from a broken file.
+	pub fn generate_missing_attributes(&mut self) -> Result<(), &'static str> {
+		if self.indices.is_empty() {
+			self.indices = (0..self.positions.len() as u32).collect();
+		}
+		if self
+			.indices
+			.iter()
+			.any(|&index| index as usize >= self.positions.len())
+		{
+			return Err("index past the last vertex");
+		}
+
+		if self.normals.len() != self.positions.len() {
+			self.compute_smooth_normals();
+		}
+
+		if self.tangents.len() != self.positions.len() && self.uvs.len() == self.positions.len() {
+			self.compute_tangents();
+		}
+		Ok(())
+	}
+
+	/// Replaces the normals with angle weighted averages of the face normals.
+	///
+	/// Vertices at (nearly) the same position are smoothed together even when
+	/// they were split for uv seams.
+	///
+	/// # Panics
+	///
+	/// When an index is past the last vertex. `generate_missing_attributes`
+	/// checks for that first.
+	pub fn compute_smooth_normals(&mut self) {
+		let mut sums: HashMap<[i64; 3], Vec3> = HashMap::new();
+
+		for triangle in self.indices.chunks_exact(3) {
+			let p = [
+				self.positions[triangle[0] as usize],
+				self.positions[triangle[1] as usize],
+				self.positions[triangle[2] as usize],
+			];
+			let face_normal = normalize(cross(sub(p[1], p[0]), sub(p[2], p[0])));
+
+			for corner in 0..3 {
+				let weight = corner_angle(p, corner);
+				let sum = sums.entry(position_key(p[corner])).or_insert([0.0; 3]);
+				*sum = add(*sum, scale(face_normal, weight));
+			}
+		}
+
+		self.normals = self
+			.positions
+			.iter()
+			.map(|&position| match sums.get(&position_key(position)) {
+				Some(&sum) if length(sum) > 0.0 => normalize(sum),
+				_ => [0.0, 1.0, 0.0],
+			})
+			.collect();
+	}
+
+	/// Computes tangents from the uvs.
+	///
+	/// Vertices whose triangles disagree on handedness (mirrored uvs) are
+	/// duplicated so each copy gets a consistent `tangent.w`.
+	///
+	/// # Panics
+	///
+	/// When there isn't a normal and uv for every position or an index is
+	/// past the last vertex. `generate_missing_attributes` only calls this
+	/// once both hold.
+	pub fn compute_tangents(&mut self) {
+		assert_eq!(self.normals.len(), self.positions.len());
+		assert_eq!(self.uvs.len(), self.positions.len());
+
+		// accumulated tangent per (vertex, handedness)
+		let mut sums: HashMap<(u32, bool), Vec3> = HashMap::new();
+		// handedness used by each corner so the index buffer can be patched afterwards
+		let mut corner_signs = Vec::with_capacity(self.indices.len());
+
+		for triangle in self.indices.chunks_exact(3) {
+			let i = [
+				triangle[0] as usize,
+				triangle[1] as usize,
+				triangle[2] as usize,
+			];
+			let p = [
+				self.positions[i[0]],
+				self.positions[i[1]],
+				self.positions[i[2]],
+			];
+			let uv = [self.uvs[i[0]], self.uvs[i[1]], self.uvs[i[2]]];
+
+			let e1 = sub(p[1], p[0]);
+			let e2 = sub(p[2], p[0]);
+			let du1 = uv[1][0] - uv[0][0];
+			let du2 = uv[2][0] - uv[0][0];
+			// flip v so the bitangent points up the texture
+			let dv1 = uv[0][1] - uv[1][1];
+			let dv2 = uv[0][1] - uv[2][1];
+
+			let det = du1 * dv2 - du2 * dv1;
+			let (tangent, bitangent) = if det.abs() > f32::EPSILON {
+				let r = 1.0 / det;
+				(
+					scale(sub(scale(e1, dv2), scale(e2, dv1)), r),
+					scale(sub(scale(e2, du1), scale(e1, du2)), r),
+				)
+			} else {
+				// no usable uvs, any tangent in the surface will do
+				(e1, cross(cross(e1, e2), e1))
+			};
+
+			for (corner, &vertex) in i.iter().enumerate() {
+				let normal = self.normals[vertex];
+				let positive = dot(cross(normal, tangent), bitangent) >= 0.0;
+				let projected = normalize(sub(tangent, scale(normal, dot(normal, tangent))));
+				let weight = corner_angle(p, corner);
+
+				let sum = sums.entry((i[corner] as u32, positive)).or_insert([0.0; 3]);
+				*sum = add(*sum, scale(projected, weight));
+				corner_signs.push(positive);
+			}
+		}
+
+		// the first handedness seen for a vertex keeps the original index,
+		// the other one gets a copy of the vertex.
+		let mut remap: HashMap<(u32, bool), u32> = HashMap::new();
+		let mut tangents: Vec<Vec4> = vec![[1.0, 0.0, 0.0, 1.0]; self.positions.len()];
+		let skinned = self.is_skinned();
+
+		for (corner, index) in self.indices.iter_mut().enumerate() {
+			let key = (*index, corner_signs[corner]);
+			let target = match remap.get(&key) {
+				Some(&target) => target,
+				None => {
+					let target = if remap.contains_key(&(*index, !key.1)) {
+						let i = *index as usize;
+						self.positions.push(self.positions[i]);
+						self.normals.push(self.normals[i]);
+						self.uvs.push(self.uvs[i]);
+						if !self.uvs2.is_empty() {
+							self.uvs2.push(self.uvs2[i]);
+						}
+						if !self.colors.is_empty() {
+							self.colors.push(self.colors[i]);
+						}
+						if skinned {
+							self.joints.push(self.joints[i]);
+							self.weights.push(self.weights[i]);
+						}
+						tangents.push([1.0, 0.0, 0.0, 1.0]);
+						self.positions.len() as u32 - 1
+					} else {
+						*index
+					};
+
+					let normal = self.normals[*index as usize];
+					let t = orthogonalize(sums[&key], normal);
+					let w = if key.1 { 1.0 } else { -1.0 };
+					tangents[target as usize] = [t[0], t[1], t[2], w];
+
+					remap.insert(key, target);
+					target
+				}
+			};
+			*index = target;
+		}
+
+		self.tangents = tangents;
+	}
+}
+
+// makes `tangent` perpendicular to `normal`, picking any perpendicular axis if it's degenerate
+fn orthogonalize(tangent: Vec3, normal: Vec3) -> Vec3 {
+	let t = sub(tangent, scale(normal, dot(normal, tangent)));
+	if length(t) > 1e-6 {
+		return normalize(t);
+	}
+
+	let axis = if normal[0].abs() < 0.9 {
+		[1.0, 0.0, 0.0]
+	} else {
+		[0.0, 1.0, 0.0]
+	};
+	normalize(cross(axis, normal))
+}
+
+fn corner_angle(p: [Vec3; 3], corner: usize) -> f32 {
+	let a = normalize(sub(p[(corner + 1) % 3], p[corner]));
+	let b = normalize(sub(p[(corner + 2) % 3], p[corner]));
+	dot(a, b).clamp(-1.0, 1.0).acos()
+}
+
+// positions closer than this are welded together when smoothing normals
+const WELD_DISTANCE: f32 = 1e-5;
+
+// i64 so keys of world space and terrain coordinates don't saturate
+fn position_key(p: Vec3) -> [i64; 3] {
+	[
+		(p[0] / WELD_DISTANCE).round() as i64,
+		(p[1] / WELD_DISTANCE).round() as i64,
+		(p[2] / WELD_DISTANCE).round() as i64,
+	]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn distant_positions_keep_their_own_key() {
+		assert_ne!(
+			position_key([30_000.0, 0.0, 0.0]),
+			position_key([40_000.0, 0.0, 0.0])
+		);
+		assert_eq!(
+			position_key([1.0, 2.0, 3.0]),
+			position_key([1.000_001, 2.0, 3.0])
+		);
+	}
+}
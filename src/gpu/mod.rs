@@ -0,0 +1,406 @@
+// a thin layer between the renderer and the graphics api. code on top of it
+// describes buffers, textures, pipelines and passes with the plain types
+// here, and a `GpuDevice` turns them into api objects and records commands
+// with its `GpuEncoder`. vulkan through vulkano is the only backend so far,
+// a wgpu one for the web and platforms without vulkan implements the same
+// two traits.
+//
+// `Renderer`, `Engine` and the passes added to a renderer only deal in these
+// types. most other modules still use vulkano directly and move over one at a
+// time, `vulkan::VulkanDevice` and the types it creates hand out the vulkano
+// objects behind them so both can be mixed in the meantime.
+
+pub mod queue;
+pub mod vulkan;
+
+pub use queue::{Draw, RenderQueue, SortKey};
+pub use vulkan::VulkanDevice;
+
+use crate::geometry::layout::VertexFormat;
+use crate::sampler::SamplerDesc;
+use crate::shader::ShaderError;
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// The api a device runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+	Vulkan,
+}
+
+/// What a buffer can be bound as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BufferUsage {
+	pub vertex: bool,
+	pub index: bool,
+	pub uniform: bool,
+	pub storage: bool,
+}
+
+impl BufferUsage {
+	pub const VERTEX: BufferUsage = BufferUsage {
+		vertex: true,
+		..BufferUsage::NONE
+	};
+	pub const INDEX: BufferUsage = BufferUsage {
+		index: true,
+		..BufferUsage::NONE
+	};
+	pub const UNIFORM: BufferUsage = BufferUsage {
+		uniform: true,
+		..BufferUsage::NONE
+	};
+	pub const STORAGE: BufferUsage = BufferUsage {
+		storage: true,
+		..BufferUsage::NONE
+	};
+	const NONE: BufferUsage = BufferUsage {
+		vertex: false,
+		index: false,
+		uniform: false,
+		storage: false,
+	};
+
+	pub fn union(self, other: BufferUsage) -> BufferUsage {
+		BufferUsage {
+			vertex: self.vertex || other.vertex,
+			index: self.index || other.index,
+			uniform: self.uniform || other.uniform,
+			storage: self.storage || other.storage,
+		}
+	}
+}
+
+/// Pixel formats every backend can create textures in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+	R8Unorm,
+	Rg8Unorm,
+	Rgba8Unorm,
+	Rgba8Srgb,
+	Bgra8Unorm,
+	Bgra8Srgb,
+	Rgba16Float,
+	Rgba32Float,
+	R32Float,
+	R32Uint,
+	Depth16,
+	Depth32Float,
+}
+
+impl TextureFormat {
+	pub fn is_depth(self) -> bool {
+		matches!(self, TextureFormat::Depth16 | TextureFormat::Depth32Float)
+	}
+
+	pub fn bytes_per_pixel(self) -> usize {
+		match self {
+			TextureFormat::R8Unorm => 1,
+			TextureFormat::Rg8Unorm | TextureFormat::Depth16 => 2,
+			TextureFormat::Rgba16Float => 8,
+			TextureFormat::Rgba32Float => 16,
+			_ => 4,
+		}
+	}
+}
+
+/// What a texture can be used for. Every texture can be written with
+/// `GpuEncoder::write_texture`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureUsage {
+	pub sampled: bool,
+	/// Color or depth attachment of a pass, depending on the format.
+	pub render_target: bool,
+	pub storage: bool,
+}
+
+impl Default for TextureUsage {
+	fn default() -> Self {
+		TextureUsage {
+			sampled: true,
+			render_target: false,
+			storage: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+	pub size: [u32; 2],
+	pub format: TextureFormat,
+	pub usage: TextureUsage,
+}
+
+impl TextureDesc {
+	/// A texture to sample from.
+	pub fn new(size: [u32; 2], format: TextureFormat) -> Self {
+		TextureDesc {
+			size,
+			format,
+			usage: TextureUsage::default(),
+		}
+	}
+
+	/// A texture to draw into and sample afterwards.
+	pub fn render_target(size: [u32; 2], format: TextureFormat) -> Self {
+		TextureDesc {
+			size,
+			format,
+			usage: TextureUsage {
+				render_target: true,
+				..TextureUsage::default()
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Topology {
+	#[default]
+	TriangleList,
+	TriangleStrip,
+	LineList,
+	LineStrip,
+	PointList,
+}
+
+/// Which triangles are dropped, counter clockwise ones face the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CullMode {
+	#[default]
+	None,
+	Back,
+	Front,
+}
+
+/// How the fragment shader's output is combined with the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+	/// The output overwrites the target.
+	#[default]
+	Replace,
+	/// Blended by the output's alpha.
+	Alpha,
+	/// Blended by the output's alpha, with the color already multiplied by it.
+	Premultiplied,
+	/// Added to the target.
+	Additive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompareOp {
+	Never,
+	Less,
+	Equal,
+	LessEqual,
+	Greater,
+	GreaterEqual,
+	Always,
+}
+
+/// Depth testing of a pipeline drawing into a pass with a depth target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthState {
+	pub compare: CompareOp,
+	pub write: bool,
+}
+
+impl Default for DepthState {
+	fn default() -> Self {
+		DepthState {
+			compare: CompareOp::Less,
+			write: true,
+		}
+	}
+}
+
+/// A graphics pipeline from spir-v, which every backend either consumes
+/// directly or translates. Vertex attributes are bound to the vertex
+/// shader's inputs by name.
+#[derive(Debug, Clone)]
+pub struct PipelineDesc<'a> {
+	pub vertex: &'a [u32],
+	pub fragment: &'a [u32],
+	/// `None` for shaders that make their vertices up from the vertex index,
+	/// drawn without a vertex buffer.
+	pub vertex_format: Option<VertexFormat>,
+	pub topology: Topology,
+	pub cull: CullMode,
+	/// Same for every color target.
+	pub blend: BlendMode,
+	/// Ignored without `depth_format`.
+	pub depth: DepthState,
+	/// Formats of the color targets of the passes it draws in, in order.
+	pub color_formats: Vec<TextureFormat>,
+	pub depth_format: Option<TextureFormat>,
+}
+
+impl<'a> PipelineDesc<'a> {
+	/// An opaque pipeline drawing triangles into a single color target.
+	pub fn new(vertex: &'a [u32], fragment: &'a [u32], color_format: TextureFormat) -> Self {
+		PipelineDesc {
+			vertex,
+			fragment,
+			vertex_format: None,
+			topology: Topology::default(),
+			cull: CullMode::default(),
+			blend: BlendMode::default(),
+			depth: DepthState::default(),
+			color_formats: vec![color_format],
+			depth_format: None,
+		}
+	}
+}
+
+/// What happens to a target's old contents when a pass begins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadOp<T> {
+	Load,
+	Clear(T),
+	/// Whatever was there, for passes that cover every pixel.
+	DontCare,
+}
+
+/// The targets of a pass. They all need the same size, and the formats the
+/// pipelines drawing in the pass were created with.
+pub struct PassDesc<'a, T> {
+	pub colors: Vec<(&'a T, LoadOp<[f32; 4]>)>,
+	pub depth: Option<(&'a T, LoadOp<f32>)>,
+}
+
+/// A resource at one binding of a bind group.
+pub enum Binding<'a, D: GpuDevice + ?Sized> {
+	/// A uniform or storage buffer, whichever the shader declares.
+	Buffer(&'a D::Buffer),
+	/// A combined image sampler.
+	Texture(&'a D::Texture, &'a D::Sampler),
+}
+
+#[derive(Debug)]
+pub enum GpuError {
+	OutOfMemory,
+	Shader(ShaderError),
+	/// Something the backend or device can't do.
+	Unsupported(String),
+	/// The call doesn't fit the state or the resources, eg. drawing without
+	/// a pipeline or writing past the end of a buffer.
+	Invalid(String),
+	/// Any other error from the api.
+	Backend(String),
+}
+
+impl fmt::Display for GpuError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			GpuError::OutOfMemory => write!(f, "out of gpu memory"),
+			GpuError::Shader(e) => write!(f, "{}", e),
+			GpuError::Unsupported(what) => write!(f, "unsupported: {}", what),
+			GpuError::Invalid(what) => write!(f, "invalid use: {}", what),
+			GpuError::Backend(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl Error for GpuError {}
+
+impl From<ShaderError> for GpuError {
+	fn from(e: ShaderError) -> Self {
+		GpuError::Shader(e)
+	}
+}
+
+/// Creates resources and submits recorded commands. Resources are cheap
+/// handles that can be cloned and dropped any time, the backend keeps them
+/// alive until the gpu is done with them.
+pub trait GpuDevice {
+	type Buffer: Clone;
+	type Texture: Clone;
+	type Sampler: Clone;
+	type Pipeline: Clone;
+	type BindGroup: Clone;
+	/// A pass begun outside of the layer that pipelines can be created for.
+	type Target: Clone;
+	type Encoder: GpuEncoder<Self>;
+
+	fn backend(&self) -> Backend;
+
+	/// A buffer holding `data`, whose length has to be a multiple of 4.
+	fn create_buffer(&self, usage: BufferUsage, data: &[u8]) -> Result<Self::Buffer, GpuError>;
+
+	/// Overwrites part of a buffer, `offset` and the length of `data` have to
+	/// be multiples of 4. Fails while the gpu still reads the buffer.
+	fn write_buffer(
+		&self,
+		buffer: &Self::Buffer,
+		offset: usize,
+		data: &[u8],
+	) -> Result<(), GpuError>;
+
+	/// A texture with undefined contents.
+	fn create_texture(&self, desc: &TextureDesc) -> Result<Self::Texture, GpuError>;
+
+	fn create_sampler(&self, desc: &SamplerDesc) -> Self::Sampler;
+
+	fn create_pipeline(&self, desc: &PipelineDesc) -> Result<Self::Pipeline, GpuError>;
+
+	/// A pipeline drawing into `target` instead of the attachments the desc
+	/// lists, eg. for `PassContext::target`.
+	fn create_pipeline_for(
+		&self,
+		desc: &PipelineDesc,
+		target: &Self::Target,
+	) -> Result<Self::Pipeline, GpuError>;
+
+	/// Resources for descriptor set `set` of `pipeline`, one per binding
+	/// starting at binding 0.
+	fn create_bind_group(
+		&self,
+		pipeline: &Self::Pipeline,
+		set: u32,
+		bindings: &[Binding<Self>],
+	) -> Result<Self::BindGroup, GpuError>;
+
+	fn create_encoder(&self) -> Result<Self::Encoder, GpuError>;
+
+	/// Runs the commands after everything submitted before, without waiting
+	/// for them to finish.
+	fn submit(&self, encoder: Self::Encoder) -> Result<(), GpuError>;
+
+	/// Blocks until everything submitted has finished.
+	fn wait_idle(&self) -> Result<(), GpuError>;
+}
+
+/// Records commands for `GpuDevice::submit`. Draws happen between
+/// `begin_pass` and `end_pass` and use the pipeline, bind groups, buffers and
+/// push constants set last.
+pub trait GpuEncoder<D: GpuDevice + ?Sized> {
+	/// Uploads all of a texture, `data` is tightly packed rows of pixels.
+	/// Only outside of a pass.
+	fn write_texture(&mut self, texture: &D::Texture, data: &[u8]) -> Result<(), GpuError>;
+
+	/// Begins drawing into the targets, with the viewport covering them.
+	fn begin_pass(&mut self, desc: &PassDesc<D::Texture>) -> Result<(), GpuError>;
+
+	/// Viewport in pixels from the top left corner of the targets.
+	fn set_viewport(&mut self, origin: [f32; 2], size: [f32; 2]);
+
+	fn set_pipeline(&mut self, pipeline: &D::Pipeline);
+
+	fn set_bind_group(&mut self, set: u32, group: &D::BindGroup);
+
+	fn set_vertex_buffer(&mut self, buffer: &D::Buffer);
+
+	/// Indices are 32 bit.
+	fn set_index_buffer(&mut self, buffer: &D::Buffer);
+
+	/// Up to 128 bytes, the most every device supports.
+	fn set_push_constants(&mut self, data: &[u8]);
+
+	fn draw(&mut self, vertices: Range<u32>) -> Result<(), GpuError>;
+
+	fn draw_indexed(&mut self, indices: Range<u32>) -> Result<(), GpuError>;
+
+	fn end_pass(&mut self) -> Result<(), GpuError>;
+}
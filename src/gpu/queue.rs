@@ -0,0 +1,185 @@
+// a queue of opaque draws sorted before they're recorded, so draws sharing a
+// pipeline and then a material end up next to each other and each of those is
+// bound once, and within a material near draws go first to cut overdraw.
+// everything is ordered by one 64 bit key:
+//
+// | pipeline, 16 bits | material, 24 bits | depth, 24 bits |
+//
+// vulkano skips binding the pipeline and descriptor sets a draw shares with
+// the one before it, so the fewer changes the fewer binds.
+
+use super::{GpuDevice, GpuEncoder, GpuError};
+
+use std::ops::Range;
+
+/// A pipeline added with `RenderQueue::add_pipeline`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PipelineId(u16);
+
+/// A material added with `RenderQueue::add_material`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialId(u32);
+
+// materials fit the key's 24 bits
+const MAX_MATERIALS: usize = 1 << 24;
+
+/// Orders draws, lowest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey(pub u64);
+
+impl SortKey {
+	/// By pipeline, then material, then front to back. `depth` is the
+	/// distance from the camera.
+	pub fn opaque(pipeline: PipelineId, material: MaterialId, depth: f32) -> SortKey {
+		// the bits of positive floats sort like the floats, the top 24 of them
+		// are plenty to tell draws apart
+		let depth = (depth.max(0.0).to_bits() >> 8) as u64;
+		SortKey((pipeline.0 as u64) << 48 | (material.0 as u64) << 24 | depth)
+	}
+}
+
+/// One draw of a mesh.
+pub struct Draw<D: GpuDevice> {
+	pub pipeline: PipelineId,
+	pub material: MaterialId,
+	/// Distance from the camera, eg. to the center of the mesh's bounds.
+	pub depth: f32,
+	/// `None` for pipelines without vertex input.
+	pub vertex_buffer: Option<D::Buffer>,
+	/// Draws `range` of the indices when set, otherwise of the vertices.
+	pub index_buffer: Option<D::Buffer>,
+	pub range: Range<u32>,
+	/// Per draw data, eg. the model matrix.
+	pub push_constants: Vec<u8>,
+}
+
+/// How many binds recording a queue took, to compare orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+	pub draws: usize,
+	pub pipeline_binds: usize,
+	pub material_binds: usize,
+}
+
+/// Opaque draws recorded sorted by `SortKey::opaque`. Pipelines and
+/// materials are added once, draws every frame:
+///
+/// ```text
+/// let pipeline = queue.add_pipeline(lit_pipeline);
+/// let brick = queue.add_material(&[(1, brick_bind_group)]);
+/// // every frame
+/// queue.push(Draw { pipeline, material: brick, depth, .. });
+/// encoder.begin_pass(&pass)?;
+/// let stats = queue.record(&mut encoder)?;
+/// encoder.end_pass()?;
+/// ```
+///
+/// `record_unsorted` records in submission order instead, its stats are what
+/// sorting saves.
+pub struct RenderQueue<D: GpuDevice> {
+	pipelines: Vec<D::Pipeline>,
+	// the bind groups and the sets they're bound at
+	materials: Vec<Vec<(u32, D::BindGroup)>>,
+	draws: Vec<(SortKey, Draw<D>)>,
+}
+
+impl<D: GpuDevice> RenderQueue<D> {
+	pub fn new() -> Self {
+		RenderQueue {
+			pipelines: Vec::new(),
+			materials: Vec::new(),
+			draws: Vec::new(),
+		}
+	}
+
+	/// Pipelines added first are drawn first.
+	pub fn add_pipeline(&mut self, pipeline: D::Pipeline) -> PipelineId {
+		assert!(
+			self.pipelines.len() <= u16::MAX as usize,
+			"too many pipelines"
+		);
+		let id = PipelineId(self.pipelines.len() as u16);
+		self.pipelines.push(pipeline);
+		id
+	}
+
+	/// Bind groups bound together for the draws of a material, eg. its
+	/// textures at set 1. Created for the pipelines the material is drawn
+	/// with.
+	pub fn add_material(&mut self, bind_groups: &[(u32, D::BindGroup)]) -> MaterialId {
+		assert!(self.materials.len() < MAX_MATERIALS, "too many materials");
+		let id = MaterialId(self.materials.len() as u32);
+		self.materials.push(bind_groups.to_vec());
+		id
+	}
+
+	/// Queues a draw for the next `record`.
+	pub fn push(&mut self, draw: Draw<D>) {
+		let key = SortKey::opaque(draw.pipeline, draw.material, draw.depth);
+		self.draws.push((key, draw));
+	}
+
+	pub fn len(&self) -> usize {
+		self.draws.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.draws.is_empty()
+	}
+
+	/// Records the queued draws sorted, inside a pass, and empties the queue.
+	pub fn record(&mut self, encoder: &mut D::Encoder) -> Result<QueueStats, GpuError> {
+		// stable, draws with equal keys keep their order
+		self.draws.sort_by_key(|(key, _)| *key);
+		self.record_unsorted(encoder)
+	}
+
+	/// Records the queued draws in the order they were pushed.
+	pub fn record_unsorted(&mut self, encoder: &mut D::Encoder) -> Result<QueueStats, GpuError> {
+		let mut stats = QueueStats::default();
+		let mut pipeline = None;
+		let mut material = None;
+		for (_, draw) in self.draws.drain(..) {
+			if pipeline != Some(draw.pipeline) {
+				encoder.set_pipeline(&self.pipelines[draw.pipeline.0 as usize]);
+				pipeline = Some(draw.pipeline);
+				// the material's sets were bound for the last pipeline
+				material = None;
+				stats.pipeline_binds += 1;
+			}
+			if material != Some(draw.material) {
+				for (set, group) in &self.materials[draw.material.0 as usize] {
+					encoder.set_bind_group(*set, group);
+				}
+				material = Some(draw.material);
+				stats.material_binds += 1;
+			}
+			if let Some(buffer) = &draw.vertex_buffer {
+				encoder.set_vertex_buffer(buffer);
+			}
+			if !draw.push_constants.is_empty() {
+				encoder.set_push_constants(&draw.push_constants);
+			}
+			match &draw.index_buffer {
+				Some(buffer) => {
+					encoder.set_index_buffer(buffer);
+					encoder.draw_indexed(draw.range)?;
+				}
+				None => encoder.draw(draw.range)?,
+			}
+			stats.draws += 1;
+		}
+		Ok(stats)
+	}
+
+	/// Drops the queued draws, keeping the pipelines and materials.
+	pub fn clear(&mut self) {
+		self.draws.clear();
+	}
+}
+
+impl<D: GpuDevice> Default for RenderQueue<D> {
+	fn default() -> Self {
+		RenderQueue::new()
+	}
+}
@@ -0,0 +1,1078 @@
+// the vulkano backend. pipelines and passes are built at runtime from the
+// descriptions, render passes are cached by their attachments since every
+// pass needs one and pipelines only need a compatible one.
+
+use super::{
+	Backend, Binding, BlendMode, BufferUsage, CompareOp, CullMode, GpuDevice, GpuEncoder, GpuError,
+	LoadOp, PassDesc, PipelineDesc, TextureDesc, TextureFormat, Topology,
+};
+use crate::geometry::layout::VertexFormat;
+use crate::render2d::Texture;
+use crate::sampler::SamplerDesc;
+use crate::shader::{Reflection, ShaderError, ShaderInterface, ShaderLayout};
+
+use vulkano::buffer::{BufferAccess, BufferSlice, CpuAccessibleBuffer, TypedBufferAccess};
+use vulkano::command_buffer::{
+	AutoCommandBuffer, AutoCommandBufferBuilder, CommandBufferExecFuture, DynamicState,
+	SubpassContents,
+};
+use vulkano::descriptor::descriptor::{DescriptorDesc, DescriptorType};
+use vulkano::descriptor::descriptor_set::{
+	DescriptorPool, DescriptorPoolAlloc, DescriptorSet, DescriptorSetDesc, DescriptorWrite,
+	StdDescriptorPoolAlloc, UnsafeDescriptorSet, UnsafeDescriptorSetLayout,
+};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, DeviceOwned, Queue};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{
+	AttachmentDescription, Framebuffer, FramebufferAbstract, FramebufferCreationError,
+	LoadOp as VkLoadOp, PassDependencyDescription, PassDescription, RenderPass, RenderPassAbstract,
+	RenderPassDesc, RenderPassDescClearValues, StoreOp, Subpass,
+};
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageLayout, ImageUsage, StorageImage};
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor, BlendOp};
+use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
+use vulkano::pipeline::input_assembly::PrimitiveTopology;
+use vulkano::pipeline::shader::{GraphicsShaderType, ShaderInterfaceDef, ShaderModule};
+use vulkano::pipeline::vertex::{
+	AttributeInfo, IncompatibleVertexDefinitionError, InputRate, VertexDefinition, VertexSource,
+};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+use vulkano::sync::{self, FenceSignalFuture, GpuFuture};
+use vulkano::OomError;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::vec::IntoIter;
+
+type RenderPasses = Arc<Mutex<HashMap<PassLayout, Arc<dyn RenderPassAbstract + Send + Sync>>>>;
+
+type Submission = FenceSignalFuture<CommandBufferExecFuture<Box<dyn GpuFuture>, AutoCommandBuffer>>;
+
+type Words = BufferSlice<[u32], Arc<CpuAccessibleBuffer<[u32]>>>;
+
+type Pipeline = GraphicsPipeline<
+	VertexInput,
+	Box<dyn PipelineLayoutAbstract + Send + Sync>,
+	Arc<dyn RenderPassAbstract + Send + Sync>,
+>;
+
+/// A vulkano device and the queue everything is submitted to. Clones share
+/// the render passes and submit after each other.
+#[derive(Clone)]
+pub struct VulkanDevice {
+	device: Arc<Device>,
+	queue: Arc<Queue>,
+	render_passes: RenderPasses,
+	previous: Rc<RefCell<Option<Submission>>>,
+}
+
+impl VulkanDevice {
+	pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+		VulkanDevice {
+			device,
+			queue,
+			render_passes: Arc::new(Mutex::new(HashMap::new())),
+			previous: Rc::new(RefCell::new(None)),
+		}
+	}
+
+	pub fn device(&self) -> &Arc<Device> {
+		&self.device
+	}
+
+	pub fn queue(&self) -> &Arc<Queue> {
+		&self.queue
+	}
+
+	/// Wraps a texture created outside of the layer, eg. by the asset loader,
+	/// to sample it. It can't be written or drawn into.
+	pub fn import_texture(&self, view: Texture, size: [u32; 2]) -> VulkanTexture {
+		VulkanTexture {
+			image: None,
+			view,
+			size,
+		}
+	}
+
+	/// Wraps a sampler created outside of the layer, eg. by `Samplers`.
+	pub fn import_sampler(&self, sampler: Arc<Sampler>) -> VulkanSampler {
+		VulkanSampler { sampler }
+	}
+
+	// a pipeline drawing in `subpass`, with depth testing when it has depth
+	fn pipeline(
+		&self,
+		desc: &PipelineDesc,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+		depth: bool,
+	) -> Result<VulkanPipeline, GpuError> {
+		let vertex = Reflection::parse(desc.vertex)?;
+		let fragment = Reflection::parse(desc.fragment)?;
+		let layout = ShaderLayout::new(&[&vertex, &fragment])?;
+		if layout.push_constant_size() > PUSH_CONSTANT_SIZE {
+			return Err(GpuError::Unsupported(format!(
+				"{} bytes of push constants",
+				layout.push_constant_size()
+			)));
+		}
+
+		// safe as long as the modules are valid spir-v, which shaderc or the
+		// caller guarantees
+		let vertex_module =
+			unsafe { ShaderModule::from_words(self.device.clone(), desc.vertex) }.map_err(oom)?;
+		let fragment_module =
+			unsafe { ShaderModule::from_words(self.device.clone(), desc.fragment) }.map_err(oom)?;
+		let main = CStr::from_bytes_with_nul(b"main\0").unwrap();
+		let vertex_entry = unsafe {
+			vertex_module.graphics_entry_point::<(), _, _, _>(
+				main,
+				ShaderInterface::inputs(&vertex),
+				ShaderInterface::outputs(&vertex),
+				layout.pipeline_desc(),
+				GraphicsShaderType::Vertex,
+			)
+		};
+		let fragment_entry = unsafe {
+			fragment_module.graphics_entry_point::<(), _, _, _>(
+				main,
+				ShaderInterface::inputs(&fragment),
+				ShaderInterface::outputs(&fragment),
+				layout.pipeline_desc(),
+				GraphicsShaderType::Fragment,
+			)
+		};
+
+		let mut builder = GraphicsPipeline::start()
+			.vertex_input(VertexInput(desc.vertex_format.clone()))
+			.vertex_shader(vertex_entry, ())
+			.primitive_topology(topology(desc.topology))
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fragment_entry, ())
+			.blend_collective(blend(desc.blend));
+		builder = match desc.cull {
+			CullMode::None => builder.cull_mode_disabled(),
+			CullMode::Back => builder.cull_mode_back(),
+			CullMode::Front => builder.cull_mode_front(),
+		};
+		if depth {
+			builder = builder.depth_stencil(DepthStencil {
+				depth_compare: compare(desc.depth.compare),
+				depth_write: desc.depth.write,
+				..DepthStencil::simple_depth_test()
+			});
+		}
+
+		let pipeline = builder
+			.render_pass(subpass)
+			.build(self.device.clone())
+			.map_err(|e| GpuError::Shader(ShaderError::Mismatch(e.to_string())))?;
+
+		Ok(VulkanPipeline {
+			pipeline: Arc::new(pipeline),
+			layout: Arc::new(layout),
+			stride: desc
+				.vertex_format
+				.as_ref()
+				.map(|format| format.stride() / 4)
+				.unwrap_or(0),
+		})
+	}
+}
+
+#[derive(Clone)]
+pub struct VulkanBuffer {
+	// 32 bit words, so the same buffer works as an index buffer
+	buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+}
+
+impl VulkanBuffer {
+	pub fn buffer(&self) -> &Arc<CpuAccessibleBuffer<[u32]>> {
+		&self.buffer
+	}
+
+	fn slice(&self, words: Range<usize>) -> Result<Arc<dyn BufferAccess + Send + Sync>, GpuError> {
+		Ok(Arc::new(self.words(words)?))
+	}
+
+	fn words(&self, words: Range<usize>) -> Result<Words, GpuError> {
+		BufferSlice::from_typed_buffer_access(self.buffer.clone())
+			.slice(words.clone())
+			.ok_or_else(|| {
+				GpuError::Invalid(format!(
+					"words {:?} of a buffer with {}",
+					words,
+					self.buffer.len()
+				))
+			})
+	}
+}
+
+#[derive(Clone)]
+pub struct VulkanTexture {
+	// `None` for imported textures
+	image: Option<Arc<StorageImage<Format>>>,
+	view: Texture,
+	size: [u32; 2],
+}
+
+impl VulkanTexture {
+	/// The view to use with the rest of the renderer.
+	pub fn view(&self) -> &Texture {
+		&self.view
+	}
+
+	pub fn size(&self) -> [u32; 2] {
+		self.size
+	}
+}
+
+#[derive(Clone)]
+pub struct VulkanSampler {
+	sampler: Arc<Sampler>,
+}
+
+impl VulkanSampler {
+	pub fn sampler(&self) -> &Arc<Sampler> {
+		&self.sampler
+	}
+}
+
+/// A subpass begun outside of the layer, eg. one of the renderer's stages,
+/// for `GpuDevice::create_pipeline_for`.
+#[derive(Clone)]
+pub struct VulkanTarget {
+	subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+}
+
+impl VulkanTarget {
+	pub fn new(subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>) -> Self {
+		VulkanTarget { subpass }
+	}
+
+	pub fn subpass(&self) -> &Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		&self.subpass
+	}
+}
+
+#[derive(Clone)]
+pub struct VulkanPipeline {
+	pipeline: Arc<Pipeline>,
+	layout: Arc<ShaderLayout>,
+	// words per vertex, 0 without a vertex buffer
+	stride: usize,
+}
+
+impl VulkanPipeline {
+	pub fn pipeline(&self) -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
+		self.pipeline.clone()
+	}
+}
+
+/// A descriptor set with any number of bindings, which
+/// `PersistentDescriptorSet`'s typed builder can't do at runtime.
+pub struct VulkanBindGroup {
+	set: StdDescriptorPoolAlloc,
+	layout: Arc<UnsafeDescriptorSetLayout>,
+	buffers: Vec<(Arc<dyn BufferAccess + Send + Sync>, u32)>,
+	images: Vec<(Texture, u32)>,
+	// kept alive for as long as the set
+	_samplers: Vec<Arc<Sampler>>,
+}
+
+unsafe impl DescriptorSet for VulkanBindGroup {
+	fn inner(&self) -> &UnsafeDescriptorSet {
+		self.set.inner()
+	}
+
+	fn num_buffers(&self) -> usize {
+		self.buffers.len()
+	}
+
+	fn buffer(&self, index: usize) -> Option<(&dyn BufferAccess, u32)> {
+		self.buffers
+			.get(index)
+			.map(|(buffer, binding)| (&**buffer as &dyn BufferAccess, *binding))
+	}
+
+	fn num_images(&self) -> usize {
+		self.images.len()
+	}
+
+	fn image(&self, index: usize) -> Option<(&dyn ImageViewAbstract, u32)> {
+		self.images
+			.get(index)
+			.map(|(image, binding)| (&**image as &dyn ImageViewAbstract, *binding))
+	}
+}
+
+unsafe impl DescriptorSetDesc for VulkanBindGroup {
+	fn num_bindings(&self) -> usize {
+		self.layout.num_bindings()
+	}
+
+	fn descriptor(&self, binding: usize) -> Option<DescriptorDesc> {
+		self.layout.descriptor(binding)
+	}
+}
+
+unsafe impl DeviceOwned for VulkanBindGroup {
+	fn device(&self) -> &Arc<Device> {
+		self.layout.device()
+	}
+}
+
+impl GpuDevice for VulkanDevice {
+	type Buffer = VulkanBuffer;
+	type Texture = VulkanTexture;
+	type Sampler = VulkanSampler;
+	type Pipeline = VulkanPipeline;
+	type BindGroup = Arc<VulkanBindGroup>;
+	type Target = VulkanTarget;
+	type Encoder = VulkanEncoder;
+
+	fn backend(&self) -> Backend {
+		Backend::Vulkan
+	}
+
+	fn create_buffer(&self, usage: BufferUsage, data: &[u8]) -> Result<VulkanBuffer, GpuError> {
+		let usage = vulkano::buffer::BufferUsage {
+			vertex_buffer: usage.vertex,
+			index_buffer: usage.index,
+			uniform_buffer: usage.uniform,
+			storage_buffer: usage.storage,
+			..vulkano::buffer::BufferUsage::none()
+		};
+		let buffer =
+			CpuAccessibleBuffer::from_iter(self.device.clone(), usage, false, words(data)?)
+				.map_err(|e| GpuError::Backend(e.to_string()))?;
+		Ok(VulkanBuffer { buffer })
+	}
+
+	fn write_buffer(
+		&self,
+		buffer: &VulkanBuffer,
+		offset: usize,
+		data: &[u8],
+	) -> Result<(), GpuError> {
+		if !offset.is_multiple_of(4) {
+			return Err(GpuError::Invalid(format!("unaligned offset {}", offset)));
+		}
+		let words: Vec<_> = words(data)?.collect();
+		let start = offset / 4;
+		if start + words.len() > buffer.buffer.len() {
+			return Err(GpuError::Invalid(format!(
+				"writing {} bytes at {} into a buffer of {}",
+				data.len(),
+				offset,
+				buffer.buffer.len() * 4
+			)));
+		}
+		let mut contents = buffer
+			.buffer
+			.write()
+			.map_err(|e| GpuError::Invalid(e.to_string()))?;
+		contents[start..start + words.len()].copy_from_slice(&words);
+		Ok(())
+	}
+
+	fn create_texture(&self, desc: &TextureDesc) -> Result<VulkanTexture, GpuError> {
+		let depth = desc.format.is_depth();
+		let image = StorageImage::with_usage(
+			self.device.clone(),
+			ImageDimensions::Dim2d {
+				width: desc.size[0],
+				height: desc.size[1],
+				array_layers: 1,
+			},
+			format(desc.format),
+			ImageUsage {
+				transfer_destination: true,
+				sampled: desc.usage.sampled,
+				storage: desc.usage.storage,
+				color_attachment: desc.usage.render_target && !depth,
+				depth_stencil_attachment: desc.usage.render_target && depth,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			self.device.active_queue_families(),
+		)
+		.map_err(|e| GpuError::Unsupported(format!("{:?} texture: {}", desc.format, e)))?;
+		let view = ImageView::new(image.clone()).map_err(|e| GpuError::Backend(e.to_string()))?;
+		Ok(VulkanTexture {
+			image: Some(image),
+			view,
+			size: desc.size,
+		})
+	}
+
+	fn create_sampler(&self, desc: &SamplerDesc) -> VulkanSampler {
+		VulkanSampler {
+			sampler: desc.create(self.device.clone(), desc.anisotropy.unwrap_or(1.0)),
+		}
+	}
+
+	fn create_pipeline(&self, desc: &PipelineDesc) -> Result<VulkanPipeline, GpuError> {
+		// load ops don't matter for compatibility
+		let pass = PassLayout {
+			colors: desc
+				.color_formats
+				.iter()
+				.map(|&color| (format(color), VkLoadOp::DontCare))
+				.collect(),
+			depth: desc
+				.depth_format
+				.map(|depth| (format(depth), VkLoadOp::DontCare)),
+		};
+		let render_pass = render_pass(&self.device, &self.render_passes, pass)?;
+		let subpass = Subpass::from(render_pass, 0).unwrap();
+		self.pipeline(desc, subpass, desc.depth_format.is_some())
+	}
+
+	fn create_pipeline_for(
+		&self,
+		desc: &PipelineDesc,
+		target: &VulkanTarget,
+	) -> Result<VulkanPipeline, GpuError> {
+		let depth = target.subpass.has_depth();
+		self.pipeline(desc, target.subpass.clone(), depth)
+	}
+
+	fn create_bind_group(
+		&self,
+		pipeline: &VulkanPipeline,
+		set: u32,
+		bindings: &[Binding<Self>],
+	) -> Result<Arc<VulkanBindGroup>, GpuError> {
+		let layout = pipeline
+			.pipeline
+			.descriptor_set_layout(set as usize)
+			.ok_or_else(|| GpuError::Invalid(format!("the pipeline has no set {}", set)))?
+			.clone();
+
+		let mut writes = Vec::new();
+		let mut buffers = Vec::new();
+		let mut images = Vec::new();
+		let mut samplers = Vec::new();
+		for (binding, resource) in (0..).zip(bindings) {
+			let ty = pipeline
+				.layout
+				.descriptor(set, binding)
+				.map(|descriptor| descriptor.ty.ty());
+			match (resource, ty) {
+				(Binding::Buffer(buffer), Some(DescriptorType::UniformBuffer)) => {
+					writes.push(unsafe {
+						DescriptorWrite::uniform_buffer(binding, 0, &buffer.buffer)
+					});
+					buffers.push((
+						buffer.buffer.clone() as Arc<dyn BufferAccess + Send + Sync>,
+						binding,
+					));
+				}
+				(Binding::Buffer(buffer), Some(DescriptorType::StorageBuffer)) => {
+					writes.push(unsafe {
+						DescriptorWrite::storage_buffer(binding, 0, &buffer.buffer)
+					});
+					buffers.push((
+						buffer.buffer.clone() as Arc<dyn BufferAccess + Send + Sync>,
+						binding,
+					));
+				}
+				(
+					Binding::Texture(texture, sampler),
+					Some(DescriptorType::CombinedImageSampler),
+				) => {
+					writes.push(DescriptorWrite::combined_image_sampler(
+						binding,
+						0,
+						&sampler.sampler,
+						&texture.view,
+					));
+					images.push((texture.view.clone(), binding));
+					samplers.push(sampler.sampler.clone());
+				}
+				(_, ty) => {
+					return Err(GpuError::Invalid(format!(
+						"set {} binding {} is {:?} in the shader",
+						set, binding, ty
+					)))
+				}
+			}
+		}
+
+		let mut alloc = Device::standard_descriptor_pool(&self.device)
+			.alloc(&layout)
+			.map_err(oom)?;
+		// safe because every write was checked against the layout above
+		unsafe {
+			alloc.inner_mut().write(&self.device, writes.into_iter());
+		}
+
+		Ok(Arc::new(VulkanBindGroup {
+			set: alloc,
+			layout,
+			buffers,
+			images,
+			_samplers: samplers,
+		}))
+	}
+
+	fn create_encoder(&self) -> Result<VulkanEncoder, GpuError> {
+		Ok(VulkanEncoder {
+			device: self.device.clone(),
+			render_passes: self.render_passes.clone(),
+			builder: AutoCommandBufferBuilder::primary_one_time_submit(
+				self.device.clone(),
+				self.queue.family(),
+			)
+			.map_err(oom)?,
+			in_pass: false,
+			dynamic_state: DynamicState::none(),
+			pipeline: None,
+			sets: Vec::new(),
+			vertex_buffer: None,
+			index_buffer: None,
+			push_constants: [0; PUSH_CONSTANT_SIZE],
+		})
+	}
+
+	fn submit(&self, encoder: VulkanEncoder) -> Result<(), GpuError> {
+		if encoder.in_pass {
+			return Err(GpuError::Invalid("submitted inside a pass".to_string()));
+		}
+		let command_buffer = encoder.finish()?;
+
+		let mut previous = self.previous.borrow_mut();
+		let mut future: Box<dyn GpuFuture> = match previous.take() {
+			Some(previous) => previous.boxed(),
+			None => sync::now(self.device.clone()).boxed(),
+		};
+		future.cleanup_finished();
+		let future = future
+			.then_execute(self.queue.clone(), command_buffer)
+			.map_err(|e| GpuError::Backend(e.to_string()))?
+			.then_signal_fence_and_flush()
+			.map_err(|e| GpuError::Backend(e.to_string()))?;
+		*previous = Some(future);
+		Ok(())
+	}
+
+	fn wait_idle(&self) -> Result<(), GpuError> {
+		match self.previous.borrow_mut().take() {
+			Some(previous) => previous
+				.wait(None)
+				.map_err(|e| GpuError::Backend(e.to_string())),
+			None => Ok(()),
+		}
+	}
+}
+
+const PUSH_CONSTANT_SIZE: usize = 128;
+
+// four colors and a depth target
+const MAX_ATTACHMENTS: usize = 5;
+
+pub struct VulkanEncoder {
+	device: Arc<Device>,
+	render_passes: RenderPasses,
+	builder: AutoCommandBufferBuilder,
+	in_pass: bool,
+	dynamic_state: DynamicState,
+	pipeline: Option<VulkanPipeline>,
+	sets: Vec<Option<Arc<VulkanBindGroup>>>,
+	vertex_buffer: Option<VulkanBuffer>,
+	index_buffer: Option<VulkanBuffer>,
+	push_constants: [u8; PUSH_CONSTANT_SIZE],
+}
+
+impl VulkanEncoder {
+	/// The command buffer, to record vulkano commands in between.
+	pub fn builder(&mut self) -> &mut AutoCommandBufferBuilder {
+		&mut self.builder
+	}
+
+	// for draws inside a pass begun with `builder`, eg. a renderer stage
+	pub(crate) fn set_dynamic_state(&mut self, dynamic_state: &DynamicState) {
+		self.dynamic_state = dynamic_state.clone();
+	}
+
+	pub(crate) fn finish(self) -> Result<AutoCommandBuffer, GpuError> {
+		self.builder
+			.build()
+			.map_err(|e| GpuError::Backend(e.to_string()))
+	}
+
+	fn pipeline(&self) -> Result<Arc<Pipeline>, GpuError> {
+		self.pipeline
+			.as_ref()
+			.map(|pipeline| pipeline.pipeline.clone())
+			.ok_or_else(|| GpuError::Invalid("drawing without a pipeline".to_string()))
+	}
+
+	fn sets(&self) -> Result<Vec<Arc<dyn DescriptorSet + Send + Sync>>, GpuError> {
+		(0..)
+			.zip(&self.sets)
+			.map(|(set, group)| {
+				group
+					.clone()
+					.map(|group| group as Arc<dyn DescriptorSet + Send + Sync>)
+					.ok_or_else(|| GpuError::Invalid(format!("no bind group for set {}", set)))
+			})
+			.collect()
+	}
+
+	// the bound vertex buffer from vertex `first` on, nothing for pipelines
+	// without vertex input
+	fn vertices(&self, first: usize, count: Option<usize>) -> Result<VertexBuffers, GpuError> {
+		let stride = self.pipeline.as_ref().map(|p| p.stride).unwrap_or(0);
+		if stride == 0 {
+			return Ok(VertexBuffers {
+				buffers: Vec::new(),
+				count: count.unwrap_or(0),
+			});
+		}
+		let buffer = self
+			.vertex_buffer
+			.as_ref()
+			.ok_or_else(|| GpuError::Invalid("drawing without a vertex buffer".to_string()))?;
+		let count = count.unwrap_or(buffer.buffer.len() / stride - first);
+		Ok(VertexBuffers {
+			buffers: vec![buffer.slice(first * stride..(first + count) * stride)?],
+			count,
+		})
+	}
+}
+
+impl GpuEncoder<VulkanDevice> for VulkanEncoder {
+	fn write_texture(&mut self, texture: &VulkanTexture, data: &[u8]) -> Result<(), GpuError> {
+		let image = texture
+			.image
+			.clone()
+			.ok_or_else(|| GpuError::Invalid("writing an imported texture".to_string()))?;
+		let source = CpuAccessibleBuffer::from_iter(
+			self.device.clone(),
+			vulkano::buffer::BufferUsage::transfer_source(),
+			false,
+			data.iter().copied(),
+		)
+		.map_err(|e| GpuError::Backend(e.to_string()))?;
+		self.builder
+			.copy_buffer_to_image(source, image)
+			.map_err(|e| GpuError::Invalid(e.to_string()))?;
+		Ok(())
+	}
+
+	fn begin_pass(&mut self, desc: &PassDesc<VulkanTexture>) -> Result<(), GpuError> {
+		let mut formats = Vec::new();
+		let mut clear_values = Vec::new();
+		let mut attachments = Vec::new();
+		for &(texture, load) in &desc.colors {
+			let (op, clear) = match load {
+				LoadOp::Load => (VkLoadOp::Load, ClearValue::None),
+				LoadOp::Clear(color) => (VkLoadOp::Clear, ClearValue::Float(color)),
+				LoadOp::DontCare => (VkLoadOp::DontCare, ClearValue::None),
+			};
+			formats.push((texture.view.format(), op));
+			clear_values.push(clear);
+			attachments.push(texture.view.clone());
+		}
+		let depth = match desc.depth {
+			Some((texture, load)) => {
+				let (op, clear) = match load {
+					LoadOp::Load => (VkLoadOp::Load, ClearValue::None),
+					LoadOp::Clear(depth) => (VkLoadOp::Clear, ClearValue::Depth(depth)),
+					LoadOp::DontCare => (VkLoadOp::DontCare, ClearValue::None),
+				};
+				clear_values.push(clear);
+				attachments.push(texture.view.clone());
+				Some((texture.view.format(), op))
+			}
+			None => None,
+		};
+		let size = desc
+			.colors
+			.first()
+			.map(|(texture, _)| *texture)
+			.or(desc.depth.map(|(texture, _)| texture))
+			.map(|texture| texture.size)
+			.ok_or_else(|| GpuError::Invalid("a pass without targets".to_string()))?;
+
+		let layout = PassLayout {
+			colors: formats,
+			depth,
+		};
+		let render_pass = render_pass(&self.device, &self.render_passes, layout)?;
+		let framebuffer =
+			framebuffer(render_pass, attachments).map_err(|e| GpuError::Invalid(e.to_string()))?;
+
+		self.builder
+			.begin_render_pass(framebuffer, SubpassContents::Inline, clear_values)
+			.map_err(|e| GpuError::Invalid(e.to_string()))?;
+		self.in_pass = true;
+		self.set_viewport([0.0, 0.0], [size[0] as f32, size[1] as f32]);
+		Ok(())
+	}
+
+	fn set_viewport(&mut self, origin: [f32; 2], size: [f32; 2]) {
+		self.dynamic_state.viewports = Some(vec![Viewport {
+			origin,
+			dimensions: size,
+			depth_range: 0.0..1.0,
+		}]);
+	}
+
+	fn set_pipeline(&mut self, pipeline: &VulkanPipeline) {
+		self.pipeline = Some(pipeline.clone());
+	}
+
+	fn set_bind_group(&mut self, set: u32, group: &Arc<VulkanBindGroup>) {
+		let set = set as usize;
+		if self.sets.len() <= set {
+			self.sets.resize(set + 1, None);
+		}
+		self.sets[set] = Some(group.clone());
+	}
+
+	fn set_vertex_buffer(&mut self, buffer: &VulkanBuffer) {
+		self.vertex_buffer = Some(buffer.clone());
+	}
+
+	fn set_index_buffer(&mut self, buffer: &VulkanBuffer) {
+		self.index_buffer = Some(buffer.clone());
+	}
+
+	fn set_push_constants(&mut self, data: &[u8]) {
+		let length = data.len().min(PUSH_CONSTANT_SIZE);
+		self.push_constants[..length].copy_from_slice(&data[..length]);
+	}
+
+	fn draw(&mut self, vertices: Range<u32>) -> Result<(), GpuError> {
+		let pipeline = self.pipeline()?;
+		let count = vertices.end.saturating_sub(vertices.start) as usize;
+		let vertex_buffers = self.vertices(vertices.start as usize, Some(count))?;
+		let sets = self.sets()?;
+		self.builder
+			.draw(
+				pipeline,
+				&self.dynamic_state,
+				vertex_buffers,
+				sets,
+				self.push_constants,
+				vec![],
+			)
+			.map_err(|e| GpuError::Invalid(e.to_string()))?;
+		Ok(())
+	}
+
+	fn draw_indexed(&mut self, indices: Range<u32>) -> Result<(), GpuError> {
+		let pipeline = self.pipeline()?;
+		let index_buffer = self
+			.index_buffer
+			.as_ref()
+			.ok_or_else(|| GpuError::Invalid("drawing without an index buffer".to_string()))?
+			.words(indices.start as usize..indices.end as usize)?;
+		let vertex_buffers = self.vertices(0, None)?;
+		let sets = self.sets()?;
+		self.builder
+			.draw_indexed(
+				pipeline,
+				&self.dynamic_state,
+				vertex_buffers,
+				index_buffer,
+				sets,
+				self.push_constants,
+				vec![],
+			)
+			.map_err(|e| GpuError::Invalid(e.to_string()))?;
+		Ok(())
+	}
+
+	fn end_pass(&mut self) -> Result<(), GpuError> {
+		self.builder
+			.end_render_pass()
+			.map_err(|e| GpuError::Invalid(e.to_string()))?;
+		self.in_pass = false;
+		Ok(())
+	}
+}
+
+/// Vertex input of a runtime pipeline, attributes matched by name like
+/// `VertexFormat` does, or none at all.
+pub struct VertexInput(Option<VertexFormat>);
+
+pub struct VertexBuffers {
+	buffers: Vec<Arc<dyn BufferAccess + Send + Sync>>,
+	count: usize,
+}
+
+unsafe impl<I> VertexDefinition<I> for VertexInput
+where
+	I: ShaderInterfaceDef,
+{
+	type BuffersIter = IntoIter<(u32, usize, InputRate)>;
+	type AttribsIter = IntoIter<(u32, u32, AttributeInfo)>;
+
+	fn definition(
+		&self,
+		interface: &I,
+	) -> Result<(Self::BuffersIter, Self::AttribsIter), IncompatibleVertexDefinitionError> {
+		match &self.0 {
+			Some(format) => format.definition(interface),
+			None => match interface.elements().next() {
+				Some(element) => Err(IncompatibleVertexDefinitionError::MissingAttribute {
+					attribute: element.name.as_deref().unwrap_or_default().to_string(),
+				}),
+				None => Ok((Vec::new().into_iter(), Vec::new().into_iter())),
+			},
+		}
+	}
+}
+
+unsafe impl VertexSource<Vec<Arc<dyn BufferAccess + Send + Sync>>> for VertexInput {
+	fn decode(
+		&self,
+		source: Vec<Arc<dyn BufferAccess + Send + Sync>>,
+	) -> (Vec<Box<dyn BufferAccess + Send + Sync>>, usize, usize) {
+		let count = match (&self.0, source.first()) {
+			(Some(format), Some(buffer)) => buffer.size() / format.stride(),
+			_ => 0,
+		};
+		let buffers = source
+			.into_iter()
+			.map(|buffer| Box::new(buffer) as Box<dyn BufferAccess + Send + Sync>)
+			.collect();
+		(buffers, count, 1)
+	}
+}
+
+unsafe impl VertexSource<VertexBuffers> for VertexInput {
+	fn decode(
+		&self,
+		source: VertexBuffers,
+	) -> (Vec<Box<dyn BufferAccess + Send + Sync>>, usize, usize) {
+		let buffers = source
+			.buffers
+			.into_iter()
+			.map(|buffer| Box::new(buffer) as Box<dyn BufferAccess + Send + Sync>)
+			.collect();
+		(buffers, source.count, 1)
+	}
+}
+
+// a single subpass drawing into every attachment, colors first
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PassLayout {
+	colors: Vec<(Format, VkLoadOp)>,
+	depth: Option<(Format, VkLoadOp)>,
+}
+
+unsafe impl RenderPassDesc for PassLayout {
+	fn num_attachments(&self) -> usize {
+		self.colors.len() + self.depth.is_some() as usize
+	}
+
+	fn attachment_desc(&self, num: usize) -> Option<AttachmentDescription> {
+		let (format, load, layout) = match self.colors.get(num) {
+			Some(&(format, load)) => (format, load, ImageLayout::ColorAttachmentOptimal),
+			None if num == self.colors.len() => {
+				let (format, load) = self.depth?;
+				(format, load, ImageLayout::DepthStencilAttachmentOptimal)
+			}
+			None => return None,
+		};
+		Some(AttachmentDescription {
+			format,
+			samples: 1,
+			load,
+			store: StoreOp::Store,
+			stencil_load: load,
+			stencil_store: StoreOp::Store,
+			initial_layout: layout,
+			final_layout: layout,
+		})
+	}
+
+	fn num_subpasses(&self) -> usize {
+		1
+	}
+
+	fn subpass_desc(&self, num: usize) -> Option<PassDescription> {
+		if num != 0 {
+			return None;
+		}
+		Some(PassDescription {
+			color_attachments: (0..self.colors.len())
+				.map(|index| (index, ImageLayout::ColorAttachmentOptimal))
+				.collect(),
+			depth_stencil: self.depth.map(|_| {
+				(
+					self.colors.len(),
+					ImageLayout::DepthStencilAttachmentOptimal,
+				)
+			}),
+			input_attachments: Vec::new(),
+			resolve_attachments: Vec::new(),
+			preserve_attachments: Vec::new(),
+		})
+	}
+
+	fn num_dependencies(&self) -> usize {
+		0
+	}
+
+	fn dependency_desc(&self, _: usize) -> Option<PassDependencyDescription> {
+		None
+	}
+}
+
+unsafe impl RenderPassDescClearValues<Vec<ClearValue>> for PassLayout {
+	fn convert_clear_values(
+		&self,
+		values: Vec<ClearValue>,
+	) -> Box<dyn Iterator<Item = ClearValue>> {
+		Box::new(values.into_iter())
+	}
+}
+
+fn render_pass(
+	device: &Arc<Device>,
+	cache: &RenderPasses,
+	layout: PassLayout,
+) -> Result<Arc<dyn RenderPassAbstract + Send + Sync>, GpuError> {
+	let mut cache = cache.lock().unwrap();
+	if let Some(render_pass) = cache.get(&layout) {
+		return Ok(render_pass.clone());
+	}
+	let render_pass = Arc::new(
+		RenderPass::new(device.clone(), layout.clone())
+			.map_err(|e| GpuError::Unsupported(e.to_string()))?,
+	) as Arc<dyn RenderPassAbstract + Send + Sync>;
+	cache.insert(layout, render_pass.clone());
+	Ok(render_pass)
+}
+
+// the attachments list is a type that grows with every attachment, so there
+// is one arm per count
+fn framebuffer(
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	views: Vec<Texture>,
+) -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, FramebufferCreationError> {
+	let start = Framebuffer::start(render_pass);
+	Ok(match &views[..] {
+		[a] => Arc::new(start.add(a.clone())?.build()?),
+		[a, b] => Arc::new(start.add(a.clone())?.add(b.clone())?.build()?),
+		[a, b, c] => Arc::new(
+			start
+				.add(a.clone())?
+				.add(b.clone())?
+				.add(c.clone())?
+				.build()?,
+		),
+		[a, b, c, d] => Arc::new(
+			start
+				.add(a.clone())?
+				.add(b.clone())?
+				.add(c.clone())?
+				.add(d.clone())?
+				.build()?,
+		),
+		[a, b, c, d, e] => Arc::new(
+			start
+				.add(a.clone())?
+				.add(b.clone())?
+				.add(c.clone())?
+				.add(d.clone())?
+				.add(e.clone())?
+				.build()?,
+		),
+		_ => {
+			return Err(FramebufferCreationError::AttachmentsCountMismatch {
+				expected: MAX_ATTACHMENTS,
+				obtained: views.len(),
+			})
+		}
+	})
+}
+
+fn words(data: &[u8]) -> Result<impl ExactSizeIterator<Item = u32> + '_, GpuError> {
+	if !data.len().is_multiple_of(4) {
+		return Err(GpuError::Invalid(format!(
+			"{} bytes, not a multiple of 4",
+			data.len()
+		)));
+	}
+	Ok(data
+		.chunks_exact(4)
+		.map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]])))
+}
+
+fn oom(e: OomError) -> GpuError {
+	match e {
+		OomError::OutOfHostMemory | OomError::OutOfDeviceMemory => GpuError::OutOfMemory,
+	}
+}
+
+fn format(format: TextureFormat) -> Format {
+	match format {
+		TextureFormat::R8Unorm => Format::R8Unorm,
+		TextureFormat::Rg8Unorm => Format::R8G8Unorm,
+		TextureFormat::Rgba8Unorm => Format::R8G8B8A8Unorm,
+		TextureFormat::Rgba8Srgb => Format::R8G8B8A8Srgb,
+		TextureFormat::Bgra8Unorm => Format::B8G8R8A8Unorm,
+		TextureFormat::Bgra8Srgb => Format::B8G8R8A8Srgb,
+		TextureFormat::Rgba16Float => Format::R16G16B16A16Sfloat,
+		TextureFormat::Rgba32Float => Format::R32G32B32A32Sfloat,
+		TextureFormat::R32Float => Format::R32Sfloat,
+		TextureFormat::R32Uint => Format::R32Uint,
+		TextureFormat::Depth16 => Format::D16Unorm,
+		TextureFormat::Depth32Float => Format::D32Sfloat,
+	}
+}
+
+fn topology(topology: Topology) -> PrimitiveTopology {
+	match topology {
+		Topology::TriangleList => PrimitiveTopology::TriangleList,
+		Topology::TriangleStrip => PrimitiveTopology::TriangleStrip,
+		Topology::LineList => PrimitiveTopology::LineList,
+		Topology::LineStrip => PrimitiveTopology::LineStrip,
+		Topology::PointList => PrimitiveTopology::PointList,
+	}
+}
+
+fn compare(op: CompareOp) -> Compare {
+	match op {
+		CompareOp::Never => Compare::Never,
+		CompareOp::Less => Compare::Less,
+		CompareOp::Equal => Compare::Equal,
+		CompareOp::LessEqual => Compare::LessOrEqual,
+		CompareOp::Greater => Compare::Greater,
+		CompareOp::GreaterEqual => Compare::GreaterOrEqual,
+		CompareOp::Always => Compare::Always,
+	}
+}
+
+pub(crate) fn blend(mode: BlendMode) -> AttachmentBlend {
+	let (source, destination) = match mode {
+		BlendMode::Replace => return AttachmentBlend::pass_through(),
+		BlendMode::Alpha => return AttachmentBlend::alpha_blending(),
+		BlendMode::Premultiplied => (BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+		BlendMode::Additive => (BlendFactor::One, BlendFactor::One),
+	};
+	AttachmentBlend {
+		enabled: true,
+		color_op: BlendOp::Add,
+		color_source: source,
+		color_destination: destination,
+		alpha_op: BlendOp::Add,
+		alpha_source: BlendFactor::One,
+		alpha_destination: BlendFactor::OneMinusSrcAlpha,
+		..AttachmentBlend::pass_through()
+	}
+}
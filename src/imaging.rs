@@ -0,0 +1,434 @@
+// image processing on the gpu as compute passes: downsampling into mip
+// chains, min/max reduction for hi-z and separable blurs. the passes read
+// with texelFetch and write storage images, so they work for formats that
+// can't be blitted or filtered, eg. r32f depth pyramids.
+//
+// vulkano's `StorageImage` only has one mip level, so each level of a chain
+// is an image of its own and shaders bind the level they want.
+
+use crate::compute::{group_counts, ComputeKernel};
+use crate::render2d::Texture;
+use crate::sampler::SamplerDesc;
+use crate::shader::{self, Defines, ShaderLibrary, ShaderStage};
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+use vulkano::sampler::Sampler;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const LOCAL_SIZE: [u32; 3] = [8, 8, 1];
+
+// each target texel reduces the source texels under it. the footprint is
+// rounded outwards, so odd sizes include the extra row and column, which is
+// what keeps a max reduced depth pyramid conservative.
+const REDUCE_SOURCE: &str = "
+	#version 450
+
+	layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+	layout(set = 0, binding = 0) uniform sampler2D source;
+	layout(set = 0, binding = 1, FORMAT) uniform writeonly image2D target;
+
+	layout(push_constant) uniform PushConstants {
+		ivec2 source_size;
+		ivec2 target_size;
+		// 0 average, 1 min, 2 max
+		uint mode;
+	} pc;
+
+	void main() {
+		ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+		if (id.x >= pc.target_size.x || id.y >= pc.target_size.y) {
+			return;
+		}
+
+		vec2 ratio = vec2(pc.source_size) / vec2(pc.target_size);
+		ivec2 start = ivec2(floor(vec2(id) * ratio));
+		ivec2 end = min(ivec2(ceil(vec2(id + 1) * ratio)), pc.source_size);
+		end = max(end, start + 1);
+
+		vec4 result = texelFetch(source, start, 0);
+		for (int y = start.y; y < end.y; y++) {
+			for (int x = start.x; x < end.x; x++) {
+				if (x == start.x && y == start.y) {
+					continue;
+				}
+				vec4 texel = texelFetch(source, ivec2(x, y), 0);
+				if (pc.mode == 1u) {
+					result = min(result, texel);
+				} else if (pc.mode == 2u) {
+					result = max(result, texel);
+				} else {
+					result += texel;
+				}
+			}
+		}
+		if (pc.mode == 0u) {
+			ivec2 count = end - start;
+			result /= float(count.x * count.y);
+		}
+		imageStore(target, id, result);
+	}
+";
+
+// one direction of a separable blur, sigma 0 is a box blur
+const BLUR_SOURCE: &str = "
+	#version 450
+
+	layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+	layout(set = 0, binding = 0) uniform sampler2D source;
+	layout(set = 0, binding = 1, FORMAT) uniform writeonly image2D target;
+
+	layout(push_constant) uniform PushConstants {
+		ivec2 size;
+		ivec2 direction;
+		int radius;
+		float sigma;
+	} pc;
+
+	void main() {
+		ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+		if (id.x >= pc.size.x || id.y >= pc.size.y) {
+			return;
+		}
+
+		vec4 sum = vec4(0.0);
+		float total = 0.0;
+		for (int i = -pc.radius; i <= pc.radius; i++) {
+			float weight = pc.sigma > 0.0 ? exp(-float(i * i) / (2.0 * pc.sigma * pc.sigma)) : 1.0;
+			ivec2 texel = clamp(id + pc.direction * i, ivec2(0), pc.size - 1);
+			sum += texelFetch(source, texel, 0) * weight;
+			total += weight;
+		}
+		imageStore(target, id, sum / total);
+	}
+";
+
+/// Formats the passes can write. The storage format is part of the shader,
+/// so each one gets its own pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterFormat {
+	Rgba8,
+	Rgba16f,
+	Rgba32f,
+	R16f,
+	R32f,
+	Rg16f,
+}
+
+impl FilterFormat {
+	pub fn format(self) -> Format {
+		match self {
+			FilterFormat::Rgba8 => Format::R8G8B8A8Unorm,
+			FilterFormat::Rgba16f => Format::R16G16B16A16Sfloat,
+			FilterFormat::Rgba32f => Format::R32G32B32A32Sfloat,
+			FilterFormat::R16f => Format::R16Sfloat,
+			FilterFormat::R32f => Format::R32Sfloat,
+			FilterFormat::Rg16f => Format::R16G16Sfloat,
+		}
+	}
+
+	// glsl image format qualifier
+	fn qualifier(self) -> &'static str {
+		match self {
+			FilterFormat::Rgba8 => "rgba8",
+			FilterFormat::Rgba16f => "rgba16f",
+			FilterFormat::Rgba32f => "rgba32f",
+			FilterFormat::R16f => "r16f",
+			FilterFormat::R32f => "r32f",
+			FilterFormat::Rg16f => "rg16f",
+		}
+	}
+}
+
+/// How a target texel combines the source texels it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduce {
+	/// Box filter, for color mips and bloom.
+	Average,
+	/// Nearest depth of the footprint.
+	Min,
+	/// Farthest depth of the footprint, for occlusion culling against a
+	/// hi-z pyramid.
+	Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blur {
+	/// Equal weights over `radius` texels each side.
+	Box { radius: u32 },
+	/// Weights fall off with `sigma` in texels, cut off at 3 sigma.
+	Gaussian { sigma: f32 },
+}
+
+impl Blur {
+	fn radius_and_sigma(self) -> (i32, f32) {
+		match self {
+			Blur::Box { radius } => (radius.min(64) as i32, 0.0),
+			Blur::Gaussian { sigma } => (
+				(sigma * 3.0).ceil().clamp(1.0, 64.0) as i32,
+				sigma.max(0.01),
+			),
+		}
+	}
+}
+
+/// Images halving in size each level, written by `ImageFilters::generate_chain`.
+pub struct MipChain {
+	format: FilterFormat,
+	levels: Vec<(Texture, [u32; 2])>,
+}
+
+impl MipChain {
+	/// `size` is the size of level 0. `levels` is capped at the number of
+	/// halvings down to 1x1, 0 goes all the way.
+	pub fn new(device: Arc<Device>, format: FilterFormat, size: [u32; 2], levels: u32) -> Self {
+		let full = 32 - size[0].max(size[1]).max(1).leading_zeros();
+		let count = if levels == 0 { full } else { levels.min(full) };
+
+		let levels = (0..count)
+			.map(|level| {
+				let level_size = [(size[0] >> level).max(1), (size[1] >> level).max(1)];
+				let image = StorageImage::with_usage(
+					device.clone(),
+					ImageDimensions::Dim2d {
+						width: level_size[0],
+						height: level_size[1],
+						array_layers: 1,
+					},
+					format.format(),
+					ImageUsage {
+						storage: true,
+						sampled: true,
+						..ImageUsage::none()
+					},
+					ImageCreateFlags::none(),
+					device.active_queue_families(),
+				)
+				.unwrap();
+				(ImageView::new(image).unwrap() as Texture, level_size)
+			})
+			.collect();
+
+		MipChain { format, levels }
+	}
+
+	pub fn format(&self) -> FilterFormat {
+		self.format
+	}
+
+	pub fn levels(&self) -> usize {
+		self.levels.len()
+	}
+
+	pub fn level(&self, level: usize) -> Texture {
+		self.levels[level].0.clone()
+	}
+
+	pub fn size(&self, level: usize) -> [u32; 2] {
+		self.levels[level].1
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Pass {
+	Reduce,
+	Blur,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ReducePushConstants {
+	source_size: [i32; 2],
+	target_size: [i32; 2],
+	mode: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BlurPushConstants {
+	size: [i32; 2],
+	direction: [i32; 2],
+	radius: i32,
+	sigma: f32,
+}
+
+/// The compute passes, with a pipeline per pass and format created the first
+/// time it's used. Everything here must be recorded outside of a render pass.
+pub struct ImageFilters {
+	device: Arc<Device>,
+	sampler: Arc<Sampler>,
+	kernels: HashMap<(Pass, FilterFormat), ComputeKernel>,
+}
+
+impl ImageFilters {
+	pub fn new(device: Arc<Device>) -> Self {
+		ImageFilters {
+			sampler: SamplerDesc::nearest().create(device.clone(), 1.0),
+			device,
+			kernels: HashMap::new(),
+		}
+	}
+
+	fn kernel(&mut self, pass: Pass, format: FilterFormat) -> &ComputeKernel {
+		let device = self.device.clone();
+		self.kernels.entry((pass, format)).or_insert_with(|| {
+			let (source, file_name) = match pass {
+				Pass::Reduce => (REDUCE_SOURCE, "reduce.comp"),
+				Pass::Blur => (BLUR_SOURCE, "blur.comp"),
+			};
+			let defines = Defines::new().with_value("FORMAT", format.qualifier());
+			let spirv = shader::compile(
+				source,
+				ShaderStage::Compute,
+				file_name,
+				&ShaderLibrary::default(),
+				&defines,
+			)
+			.unwrap();
+			ComputeKernel::from_spirv_reflected(device, &spirv, None).unwrap()
+		})
+	}
+
+	/// Resamples `source` into `target`, which is written as `format`. Works
+	/// for any ratio, each target texel reduces every source texel it covers.
+	#[allow(clippy::too_many_arguments)]
+	pub fn downsample(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: Texture,
+		source_size: [u32; 2],
+		target: Texture,
+		target_size: [u32; 2],
+		format: FilterFormat,
+		reduce: Reduce,
+	) {
+		let sampler = self.sampler.clone();
+		let kernel = self.kernel(Pass::Reduce, format);
+		let set = kernel
+			.bind()
+			.add_sampled_image(source, sampler)
+			.unwrap()
+			.add_image(target)
+			.unwrap()
+			.build()
+			.unwrap();
+		let push_constants = ReducePushConstants {
+			source_size: [source_size[0] as i32, source_size[1] as i32],
+			target_size: [target_size[0] as i32, target_size[1] as i32],
+			mode: match reduce {
+				Reduce::Average => 0,
+				Reduce::Min => 1,
+				Reduce::Max => 2,
+			},
+		};
+		kernel
+			.dispatch(
+				builder,
+				group_counts([target_size[0], target_size[1], 1], LOCAL_SIZE),
+				Arc::new(set),
+				push_constants,
+			)
+			.unwrap();
+	}
+
+	/// Fills every level of `chain`, level 0 from `source` and each level
+	/// after from the one before. With `Reduce::Max` and a depth buffer as
+	/// the source this builds a hi-z pyramid.
+	pub fn generate_chain(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: Texture,
+		source_size: [u32; 2],
+		chain: &MipChain,
+		reduce: Reduce,
+	) {
+		let mut previous = (source, source_size);
+		for level in 0..chain.levels() {
+			self.downsample(
+				builder,
+				previous.0,
+				previous.1,
+				chain.level(level),
+				chain.size(level),
+				chain.format(),
+				reduce,
+			);
+			previous = (chain.level(level), chain.size(level));
+		}
+	}
+
+	/// Blurs `source` into `target` in two passes through `temp`. All three
+	/// are `size`, `temp` and `target` have to be `format` storage images.
+	/// `source` and `target` can be the same image.
+	#[allow(clippy::too_many_arguments)]
+	pub fn blur(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: Texture,
+		temp: Texture,
+		target: Texture,
+		size: [u32; 2],
+		format: FilterFormat,
+		blur: Blur,
+	) {
+		let (radius, sigma) = blur.radius_and_sigma();
+		let sampler = self.sampler.clone();
+		let kernel = self.kernel(Pass::Blur, format);
+		let passes = [(source, temp.clone(), [1, 0]), (temp, target, [0, 1])];
+		for (from, to, direction) in passes {
+			let set = kernel
+				.bind()
+				.add_sampled_image(from, sampler.clone())
+				.unwrap()
+				.add_image(to)
+				.unwrap()
+				.build()
+				.unwrap();
+			let push_constants = BlurPushConstants {
+				size: [size[0] as i32, size[1] as i32],
+				direction,
+				radius,
+				sigma,
+			};
+			kernel
+				.dispatch(
+					builder,
+					group_counts([size[0], size[1], 1], LOCAL_SIZE),
+					Arc::new(set),
+					push_constants,
+				)
+				.unwrap();
+		}
+	}
+
+	/// Blurs every level of `chain` in place, through a second chain of the
+	/// same size. Blurring a downsampled chain like this is the usual way to
+	/// get wide blurs cheaply, eg. for bloom.
+	pub fn blur_chain(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		chain: &MipChain,
+		temp: &MipChain,
+		blur: Blur,
+	) {
+		assert_eq!(chain.levels(), temp.levels(), "chains have to match");
+		for level in 0..chain.levels() {
+			self.blur(
+				builder,
+				chain.level(level),
+				temp.level(level),
+				chain.level(level),
+				chain.size(level),
+				chain.format(),
+				blur,
+			);
+		}
+	}
+}
@@ -0,0 +1,234 @@
+// taps, drags, pinches and rotations recognized from the touches in `Input`.
+// one finger drags and taps, two fingers pan, pinch and rotate at once, so a
+// camera can follow all three from the same pair of fingers.
+
+use super::Input;
+use crate::math::Vec2;
+
+use winit::event::TouchPhase;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+/// Something the fingers did this frame. Positions are in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+	/// A finger touched and lifted without moving. `count` is 2 for the
+	/// second tap of a double tap, and so on.
+	Tap { position: Vec2, count: u32 },
+	/// A finger held still for `GestureRecognizer::long_press`. No tap
+	/// follows when it lifts.
+	LongPress { position: Vec2 },
+	/// One finger moved, or two fingers moved together, by `delta`.
+	Drag {
+		position: Vec2,
+		delta: Vec2,
+		fingers: u32,
+	},
+	/// Two fingers moved apart, `scale` above 1, or together, below 1, since
+	/// the last frame.
+	Pinch { center: Vec2, scale: f32 },
+	/// Two fingers turned by `angle` radians since the last frame, clockwise
+	/// on screen.
+	Rotate { center: Vec2, angle: f32 },
+}
+
+struct Tracked {
+	started: Instant,
+	previous: Vec2,
+	// moved too far for a tap, or another finger joined
+	moved: bool,
+	long_pressed: bool,
+}
+
+// the pair of fingers being pinched last frame
+struct Pair {
+	ids: (u64, u64),
+	center: Vec2,
+	distance: f32,
+	angle: f32,
+}
+
+/// Turns touches into gestures. Call `update` every frame before
+/// `Input::end_frame`:
+///
+/// ```text
+/// for gesture in gestures.update(&input) {
+///     match gesture {
+///         Gesture::Drag { delta, fingers: 1, .. } => orbit(delta),
+///         Gesture::Drag { delta, .. } => pan(delta),
+///         Gesture::Pinch { scale, .. } => distance /= scale,
+///         _ => {}
+///     }
+/// }
+/// ```
+pub struct GestureRecognizer {
+	/// How far a finger can move in physical pixels and still tap.
+	pub tap_distance: f32,
+	/// Longest touch that still counts as a tap.
+	pub tap_time: Duration,
+	/// Longest wait between the taps of a double tap.
+	pub double_tap_time: Duration,
+	pub long_press: Duration,
+	tracked: HashMap<u64, Tracked>,
+	pair: Option<Pair>,
+	// time, position and count of the last tap
+	last_tap: Option<(Instant, Vec2, u32)>,
+}
+
+impl Default for GestureRecognizer {
+	fn default() -> Self {
+		GestureRecognizer {
+			tap_distance: 20.0,
+			tap_time: Duration::from_millis(300),
+			double_tap_time: Duration::from_millis(300),
+			long_press: Duration::from_millis(500),
+			tracked: HashMap::new(),
+			pair: None,
+			last_tap: None,
+		}
+	}
+}
+
+impl GestureRecognizer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Gestures made since the last call.
+	pub fn update(&mut self, input: &Input) -> Vec<Gesture> {
+		let now = Instant::now();
+		let mut gestures = Vec::new();
+
+		let mut down: Vec<_> = input
+			.touches()
+			.filter(|touch| !matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled))
+			.collect();
+		down.sort_by_key(|touch| touch.id);
+
+		for touch in input.touches() {
+			let tracked = self.tracked.entry(touch.id).or_insert(Tracked {
+				started: now,
+				previous: touch.position,
+				moved: false,
+				long_pressed: false,
+			});
+			if distance(touch.position, touch.start) > self.tap_distance || down.len() > 1 {
+				tracked.moved = true;
+			}
+		}
+
+		// one finger
+		if let [touch] = down[..] {
+			let tracked = self.tracked.get_mut(&touch.id).unwrap();
+			if tracked.moved {
+				let delta = sub(touch.position, tracked.previous);
+				if delta != [0.0, 0.0] {
+					gestures.push(Gesture::Drag {
+						position: touch.position,
+						delta,
+						fingers: 1,
+					});
+				}
+			} else if !tracked.long_pressed && now - tracked.started >= self.long_press {
+				tracked.long_pressed = true;
+				gestures.push(Gesture::LongPress {
+					position: touch.position,
+				});
+			}
+		}
+
+		// two fingers, more are ignored until only two are left
+		let pair = match down[..] {
+			[a, b] => {
+				let center = [
+					(a.position[0] + b.position[0]) / 2.0,
+					(a.position[1] + b.position[1]) / 2.0,
+				];
+				let offset = sub(b.position, a.position);
+				Some(Pair {
+					ids: (a.id, b.id),
+					center,
+					distance: distance(a.position, b.position),
+					angle: offset[1].atan2(offset[0]),
+				})
+			}
+			_ => None,
+		};
+		if let (Some(pair), Some(previous)) = (&pair, &self.pair) {
+			if pair.ids == previous.ids {
+				let delta = sub(pair.center, previous.center);
+				if delta != [0.0, 0.0] {
+					gestures.push(Gesture::Drag {
+						position: pair.center,
+						delta,
+						fingers: 2,
+					});
+				}
+				if previous.distance > 0.0 && pair.distance != previous.distance {
+					gestures.push(Gesture::Pinch {
+						center: pair.center,
+						scale: pair.distance / previous.distance,
+					});
+				}
+				let mut angle = pair.angle - previous.angle;
+				if angle > PI {
+					angle -= 2.0 * PI;
+				} else if angle < -PI {
+					angle += 2.0 * PI;
+				}
+				if angle != 0.0 {
+					gestures.push(Gesture::Rotate {
+						center: pair.center,
+						angle,
+					});
+				}
+			}
+		}
+		self.pair = pair;
+
+		// lifted fingers
+		for touch in input.touches() {
+			let tracked = match touch.phase {
+				TouchPhase::Ended => self.tracked.remove(&touch.id).unwrap(),
+				TouchPhase::Cancelled => {
+					self.tracked.remove(&touch.id);
+					continue;
+				}
+				_ => {
+					self.tracked.get_mut(&touch.id).unwrap().previous = touch.position;
+					continue;
+				}
+			};
+			if tracked.moved || tracked.long_pressed || now - tracked.started > self.tap_time {
+				continue;
+			}
+			let count = match self.last_tap {
+				Some((time, position, count))
+					if now - time <= self.double_tap_time
+						&& distance(position, touch.position) <= self.tap_distance =>
+				{
+					count + 1
+				}
+				_ => 1,
+			};
+			self.last_tap = Some((now, touch.position, count));
+			gestures.push(Gesture::Tap {
+				position: touch.position,
+				count,
+			});
+		}
+
+		gestures
+	}
+}
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+	[a[0] - b[0], a[1] - b[1]]
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+	let d = sub(a, b);
+	(d[0] * d[0] + d[1] * d[1]).sqrt()
+}
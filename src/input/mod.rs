@@ -0,0 +1,182 @@
+// input gathered from winit events over a frame. feed every event to
+// `Input::event` and call `end_frame` once the frame has read what it needs.
+
+pub mod gestures;
+
+use crate::math::Vec2;
+
+use winit::event::{DeviceEvent, Event, TouchPhase, WindowEvent};
+
+use std::collections::HashMap;
+
+pub use gestures::{Gesture, GestureRecognizer};
+
+/// A finger on the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchPoint {
+	/// Stays the same from the finger touching down to it lifting.
+	pub id: u64,
+	/// Physical pixels.
+	pub position: Vec2,
+	/// Where the finger touched down.
+	pub start: Vec2,
+	/// Pressure from 0 to 1 where the device reports it.
+	pub force: Option<f32>,
+	/// `Started` the frame it touched down, `Ended` or `Cancelled` the frame
+	/// it lifted, `Moved` in between.
+	pub phase: TouchPhase,
+}
+
+/// Per frame input state.
+#[derive(Debug, Clone)]
+pub struct Input {
+	mouse_delta: Vec2,
+	cursor_position: Option<Vec2>,
+	cursor_delta: Vec2,
+	focused: bool,
+	text_input: bool,
+	text: String,
+	touches: HashMap<u64, TouchPoint>,
+}
+
+impl Default for Input {
+	fn default() -> Self {
+		Input {
+			mouse_delta: [0.0, 0.0],
+			cursor_position: None,
+			cursor_delta: [0.0, 0.0],
+			focused: true,
+			text_input: false,
+			text: String::new(),
+			touches: HashMap::new(),
+		}
+	}
+}
+
+impl Input {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Updates the state from a winit event.
+	pub fn event<T>(&mut self, event: &Event<T>) {
+		match event {
+			// device events arrive whether or not the window is focused
+			Event::DeviceEvent {
+				event: DeviceEvent::MouseMotion { delta },
+				..
+			} if self.focused => {
+				self.mouse_delta[0] += delta.0 as f32;
+				self.mouse_delta[1] += delta.1 as f32;
+			}
+			Event::WindowEvent { event, .. } => self.window_event(event),
+			_ => {}
+		}
+	}
+
+	/// Updates the state from an event of the window, for loops that
+	/// dispatch window events themselves.
+	pub fn window_event(&mut self, event: &WindowEvent) {
+		match event {
+			WindowEvent::CursorMoved { position, .. } => {
+				let position = [position.x as f32, position.y as f32];
+				if let Some(previous) = self.cursor_position {
+					self.cursor_delta[0] += position[0] - previous[0];
+					self.cursor_delta[1] += position[1] - previous[1];
+				}
+				self.cursor_position = Some(position);
+			}
+			WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+			// ime compositions arrive as characters once committed, backspace
+			// and the like are left to key events
+			WindowEvent::ReceivedCharacter(c) if self.text_input && !c.is_control() => {
+				self.text.push(*c);
+			}
+			WindowEvent::Touch(touch) => {
+				let position = [touch.location.x as f32, touch.location.y as f32];
+				let force = touch.force.map(|force| force.normalized() as f32);
+				let point = self.touches.entry(touch.id).or_insert(TouchPoint {
+					id: touch.id,
+					position,
+					start: position,
+					force,
+					phase: TouchPhase::Started,
+				});
+				point.position = position;
+				point.force = force;
+				// a touch that started this frame stays started until it's read
+				if point.phase != TouchPhase::Started || touch.phase != TouchPhase::Moved {
+					point.phase = touch.phase;
+				}
+			}
+			WindowEvent::Focused(focused) => {
+				self.focused = *focused;
+				if !focused {
+					self.mouse_delta = [0.0, 0.0];
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Relative mouse movement this frame, straight from the device without
+	/// the os acceleration where the platform reports raw input. Keeps coming
+	/// when the cursor is locked or stuck at the edge of the screen, use it
+	/// for mouse look. The units are the device's counts, not pixels.
+	pub fn mouse_delta(&self) -> Vec2 {
+		self.mouse_delta
+	}
+
+	/// How far the cursor moved over the window this frame in physical
+	/// pixels, accelerated and stopping at the window's edges.
+	pub fn cursor_delta(&self) -> Vec2 {
+		self.cursor_delta
+	}
+
+	/// Cursor position in physical pixels, `None` outside of the window.
+	pub fn cursor_position(&self) -> Option<Vec2> {
+		self.cursor_position
+	}
+
+	pub fn focused(&self) -> bool {
+		self.focused
+	}
+
+	/// Starts or stops collecting typed text, eg. while a text field has
+	/// focus. Move the ime candidate box along with
+	/// `WindowTarget::set_ime_position`.
+	pub fn set_text_input(&mut self, enabled: bool) {
+		self.text_input = enabled;
+		self.text.clear();
+	}
+
+	pub fn text_input(&self) -> bool {
+		self.text_input
+	}
+
+	/// Text typed this frame, including ime input, while text input is on.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+
+	/// Fingers on the screen, and those lifted this frame.
+	pub fn touches(&self) -> impl Iterator<Item = &TouchPoint> {
+		self.touches.values()
+	}
+
+	pub fn touch(&self, id: u64) -> Option<&TouchPoint> {
+		self.touches.get(&id)
+	}
+
+	/// Clears what was accumulated over the frame.
+	pub fn end_frame(&mut self) {
+		self.touches
+			.retain(|_, touch| !matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled));
+		for touch in self.touches.values_mut() {
+			touch.phase = TouchPhase::Moved;
+		}
+		self.mouse_delta = [0.0, 0.0];
+		self.cursor_delta = [0.0, 0.0];
+		self.text.clear();
+	}
+}
@@ -0,0 +1,63 @@
+pub mod animation;
+pub mod args;
+pub mod assets;
+pub mod async_compute;
+pub mod billboard;
+pub mod breadcrumbs;
+pub mod capabilities;
+pub mod color;
+pub mod compositor;
+pub mod compute;
+pub mod config;
+pub mod cursor;
+pub mod debug_labels;
+pub mod decals;
+pub mod display;
+pub mod engine;
+pub mod environment;
+pub mod fade;
+pub mod file_drop;
+pub mod fog;
+pub mod foliage;
+pub mod frame_dump;
+pub mod fullscreen;
+pub mod geometry;
+pub mod gpu;
+pub mod imaging;
+pub mod input;
+pub mod lightmap;
+pub mod lines;
+pub mod math;
+pub mod pacing;
+pub mod particles;
+pub mod passes;
+#[cfg(feature = "physics")]
+pub mod physics;
+pub mod picking;
+pub mod portability;
+pub mod post;
+pub mod prepass;
+pub mod probes;
+pub mod recovery;
+pub mod render2d;
+pub mod render_target;
+pub mod renderer;
+pub mod replay;
+pub mod requirements;
+pub mod resolution;
+pub mod sampler;
+pub mod scene;
+pub mod secondary;
+pub mod settings;
+pub mod shader;
+pub mod sky;
+pub mod sync;
+pub mod terrain;
+pub mod text;
+pub mod transforms;
+pub mod transient;
+pub mod viewport;
+pub mod watchdog;
+pub mod window;
+
+pub use color::Color;
@@ -0,0 +1,448 @@
+// baked lighting for static geometry, sampled through a second uv channel
+// instead of computing lights at runtime.
+
+use crate::animation::gltf::world_matrix;
+use crate::geometry::MeshData;
+use crate::math::{Mat4, Vec4};
+use crate::render2d::{linear_texture_from_rgba, Texture};
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBuffer, CommandBufferExecFuture};
+use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet, UnsafeDescriptorSetLayout};
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::half::f16;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::sampler::Sampler;
+use vulkano::sync::NowFuture;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum LightmapError {
+	Io(std::io::Error),
+	Gltf(::gltf::Error),
+	Json(serde_json::Error),
+	Exr(exr::error::Error),
+	Format(&'static str),
+}
+
+impl fmt::Display for LightmapError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			LightmapError::Io(error) => write!(f, "failed to read file: {}", error),
+			LightmapError::Gltf(error) => write!(f, "failed to load gltf: {}", error),
+			LightmapError::Json(error) => write!(f, "invalid gltf json: {}", error),
+			LightmapError::Exr(error) => write!(f, "failed to load exr: {}", error),
+			LightmapError::Format(what) => write!(f, "invalid lightmap: {}", what),
+		}
+	}
+}
+
+impl std::error::Error for LightmapError {}
+
+impl From<std::io::Error> for LightmapError {
+	fn from(error: std::io::Error) -> Self {
+		LightmapError::Io(error)
+	}
+}
+
+impl From<::gltf::Error> for LightmapError {
+	fn from(error: ::gltf::Error) -> Self {
+		LightmapError::Gltf(error)
+	}
+}
+
+impl From<serde_json::Error> for LightmapError {
+	fn from(error: serde_json::Error) -> Self {
+		LightmapError::Json(error)
+	}
+}
+
+impl From<exr::error::Error> for LightmapError {
+	fn from(error: exr::error::Error) -> Self {
+		LightmapError::Exr(error)
+	}
+}
+
+type UploadFuture = CommandBufferExecFuture<NowFuture, AutoCommandBuffer>;
+
+/// A baked lightmap texture and the part of it an object uses.
+#[derive(Clone)]
+pub struct Lightmap {
+	/// Linear light, either 8 bit or half float for hdr bakes.
+	pub texture: Texture,
+	/// `[scale u, scale v, offset u, offset v]` applied to the second uv
+	/// channel, for objects sharing one atlas.
+	pub scale_offset: Vec4,
+	pub intensity: f32,
+}
+
+impl Lightmap {
+	pub fn new(texture: Texture) -> Self {
+		Lightmap {
+			texture,
+			scale_offset: [1.0, 1.0, 0.0, 0.0],
+			intensity: 1.0,
+		}
+	}
+
+	/// Loads the first rgba layer of an exr file as a half float texture.
+	pub fn from_exr<P: AsRef<Path>>(
+		queue: Arc<Queue>,
+		path: P,
+	) -> Result<(Self, UploadFuture), LightmapError> {
+		use exr::prelude::*;
+
+		let image = read_first_rgba_layer_from_file(
+			path,
+			|size, _| {
+				(
+					size.width(),
+					vec![f16::from_f32(0.0); size.width() * size.height() * 4],
+				)
+			},
+			|(width, pixels), position, (r, g, b, a): (f32, f32, f32, f32)| {
+				let index = (position.y() * *width + position.x()) * 4;
+				pixels[index..index + 4].copy_from_slice(&[
+					f16::from_f32(r),
+					f16::from_f32(g),
+					f16::from_f32(b),
+					f16::from_f32(a),
+				]);
+			},
+		)?;
+
+		let size = image.layer_data.size;
+		let (_, pixels) = image.layer_data.channel_data.pixels;
+		let (image, future) = ImmutableImage::from_iter(
+			pixels.into_iter(),
+			ImageDimensions::Dim2d {
+				width: size.width() as u32,
+				height: size.height() as u32,
+				array_layers: 1,
+			},
+			MipmapsCount::One,
+			Format::R16G16B16A16Sfloat,
+			queue,
+		)
+		.unwrap();
+
+		Ok((Lightmap::new(ImageView::new(image).unwrap()), future))
+	}
+}
+
+crate::vertex_layout!(
+	/// Interleaved vertex for lightmapped meshes.
+	pub struct LightmappedVertex {
+		position,
+		normal,
+		tangent,
+		uv,
+		uv2,
+	}
+);
+
+/// Interleaves a mesh for the lightmapped vertex shader. Meshes without a
+/// second uv channel reuse the first one.
+pub fn vertices(mesh: &MeshData) -> Vec<LightmappedVertex> {
+	mesh.interleave()
+}
+
+// outputs match the static and skinned mesh paths, plus the lightmap uv
+pub mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec3 normal;
+			layout(location = 2) in vec4 tangent;
+			layout(location = 3) in vec2 uv;
+			layout(location = 4) in vec2 uv2;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				mat4 model;
+			} push_constants;
+
+			layout(location = 0) out vec3 v_normal;
+			layout(location = 1) out vec4 v_tangent;
+			layout(location = 2) out vec2 v_uv;
+			layout(location = 3) out vec2 v_uv2;
+
+			void main() {
+				mat3 normal_matrix = mat3(push_constants.model);
+
+				v_normal = normalize(normal_matrix * normal);
+				v_tangent = vec4(normalize(normal_matrix * tangent.xyz), tangent.w);
+				v_uv = uv;
+				v_uv2 = uv2;
+				gl_Position = push_constants.view_projection * push_constants.model * vec4(position, 1.0);
+			}
+		"
+	}
+}
+
+// base color lit only by the lightmap
+pub mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec3 v_normal;
+			layout(location = 1) in vec4 v_tangent;
+			layout(location = 2) in vec2 v_uv;
+			layout(location = 3) in vec2 v_uv2;
+
+			layout(set = 0, binding = 0) uniform Material {
+				vec4 color;
+				vec4 lightmap_scale_offset;
+				float lightmap_intensity;
+			} material;
+			layout(set = 0, binding = 1) uniform sampler2D base_color;
+			layout(set = 0, binding = 2) uniform sampler2D lightmap;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				vec4 albedo = texture(base_color, v_uv) * material.color;
+				vec2 lightmap_uv = v_uv2 * material.lightmap_scale_offset.xy + material.lightmap_scale_offset.zw;
+				vec3 light = texture(lightmap, lightmap_uv).rgb * material.lightmap_intensity;
+				f_color = vec4(albedo.rgb * light, albedo.a);
+			}
+		"
+	}
+}
+
+/// Descriptor set for set 0 of the lightmapped fragment shader.
+///
+/// `layout` comes from `pipeline.descriptor_set_layout(0)`. Build it once per
+/// material and object pair, objects sharing an atlas differ in `scale_offset`.
+pub fn material_set(
+	layout: &Arc<UnsafeDescriptorSetLayout>,
+	base_color: Texture,
+	color: Vec4,
+	lightmap: &Lightmap,
+	sampler: Arc<Sampler>,
+) -> Arc<dyn DescriptorSet + Send + Sync> {
+	let device = layout.device().clone();
+	let uniform = CpuAccessibleBuffer::from_data(
+		device,
+		BufferUsage::uniform_buffer(),
+		false,
+		fs::ty::Material {
+			color,
+			lightmap_scale_offset: lightmap.scale_offset,
+			lightmap_intensity: lightmap.intensity,
+		},
+	)
+	.unwrap();
+
+	Arc::new(
+		PersistentDescriptorSet::start(layout.clone())
+			.add_buffer(uniform)
+			.unwrap()
+			.add_sampled_image(base_color, sampler.clone())
+			.unwrap()
+			.add_sampled_image(lightmap.texture.clone(), sampler)
+			.unwrap()
+			.build()
+			.unwrap(),
+	)
+}
+
+/// A primitive from a lightmapped gltf file.
+pub struct LightmappedMesh {
+	/// `uvs2` holds the uv set the lightmap refers to.
+	pub mesh: MeshData,
+	/// The node's world matrix.
+	pub transform: Mat4,
+	/// Index into `LightmappedScene::lightmaps`.
+	pub lightmap: Option<usize>,
+}
+
+/// Lightmap image referenced by a gltf material, decoded to rgba8.
+pub struct ImportedLightmap {
+	pub dimensions: [u32; 2],
+	pub pixels: Vec<u8>,
+	pub intensity: f32,
+}
+
+impl ImportedLightmap {
+	pub fn upload(&self, queue: Arc<Queue>) -> (Lightmap, UploadFuture) {
+		let (texture, future) = linear_texture_from_rgba(queue, self.dimensions, &self.pixels);
+		let mut lightmap = Lightmap::new(texture);
+		lightmap.intensity = self.intensity;
+		(lightmap, future)
+	}
+}
+
+pub struct LightmappedScene {
+	pub meshes: Vec<LightmappedMesh>,
+	pub lightmaps: Vec<ImportedLightmap>,
+}
+
+/// Loads every mesh in a gltf file along with the lightmaps its materials
+/// reference through the `MOZ_lightmap` extension.
+///
+/// The gltf crate doesn't expose unknown extensions, so they are read from
+/// the file's json directly.
+pub fn import_gltf<P: AsRef<Path>>(path: P) -> Result<LightmappedScene, LightmapError> {
+	let path = path.as_ref();
+	let (document, buffers, images) = ::gltf::import(path)?;
+	let buffer_data = |buffer: ::gltf::Buffer| Some(&*buffers[buffer.index()]);
+	let json = read_json(path)?;
+
+	let mut parents = HashMap::new();
+	for node in document.nodes() {
+		for child in node.children() {
+			parents.insert(child.index(), node.index());
+		}
+	}
+
+	// lightmap of each material, sharing images between materials
+	let mut lightmaps = Vec::new();
+	let mut by_material: HashMap<usize, (usize, u32)> = HashMap::new();
+	let mut by_image: HashMap<usize, usize> = HashMap::new();
+	let materials = json["materials"].as_array().cloned().unwrap_or_default();
+	for (material, value) in materials.iter().enumerate() {
+		let extension = &value["extensions"]["MOZ_lightmap"];
+		let texture = match extension["index"].as_u64() {
+			Some(texture) => texture as usize,
+			None => continue,
+		};
+		let image = document
+			.textures()
+			.nth(texture)
+			.ok_or(LightmapError::Format("lightmap texture out of range"))?
+			.source()
+			.index();
+		let index = match by_image.get(&image) {
+			Some(&index) => index,
+			None => {
+				let data = &images[image];
+				lightmaps.push(ImportedLightmap {
+					dimensions: [data.width, data.height],
+					pixels: to_rgba8(data)?,
+					intensity: extension["intensity"].as_f64().unwrap_or(1.0) as f32,
+				});
+				by_image.insert(image, lightmaps.len() - 1);
+				lightmaps.len() - 1
+			}
+		};
+		let tex_coord = extension["texCoord"].as_u64().unwrap_or(1) as u32;
+		by_material.insert(material, (index, tex_coord));
+	}
+
+	let mut meshes = Vec::new();
+	for node in document.nodes() {
+		let node_mesh = match node.mesh() {
+			Some(node_mesh) => node_mesh,
+			None => continue,
+		};
+		let transform = world_matrix(&document, &parents, node.index());
+
+		for primitive in node_mesh.primitives() {
+			if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+				continue;
+			}
+
+			let reader = primitive.reader(buffer_data);
+			let mut mesh = MeshData {
+				positions: match reader.read_positions() {
+					Some(positions) => positions.collect(),
+					None => continue,
+				},
+				..Default::default()
+			};
+			let count = mesh.positions.len();
+
+			let lightmap = primitive
+				.material()
+				.index()
+				.and_then(|material| by_material.get(&material).copied());
+			let lightmap_set = lightmap.map_or(1, |(_, tex_coord)| tex_coord);
+
+			mesh.normals = reader.read_normals().map_or_else(Vec::new, |n| n.collect());
+			mesh.tangents = reader
+				.read_tangents()
+				.map_or_else(Vec::new, |t| t.collect());
+			mesh.uvs = reader
+				.read_tex_coords(0)
+				.map_or_else(|| vec![[0.0; 2]; count], |uvs| uvs.into_f32().collect());
+			mesh.uvs2 = reader
+				.read_tex_coords(lightmap_set)
+				.map_or_else(Vec::new, |uvs| uvs.into_f32().collect());
+			mesh.indices = reader
+				.read_indices()
+				.map_or_else(|| (0..count as u32).collect(), |i| i.into_u32().collect());
+
+			mesh.generate_missing_attributes()
+				.map_err(LightmapError::Format)?;
+			meshes.push(LightmappedMesh {
+				mesh,
+				transform,
+				lightmap: lightmap.map(|(index, _)| index),
+			});
+		}
+	}
+
+	Ok(LightmappedScene { meshes, lightmaps })
+}
+
+// the json chunk of a .glb, or the whole file for .gltf
+fn read_json(path: &Path) -> Result<serde_json::Value, LightmapError> {
+	let bytes = std::fs::read(path)?;
+	if !bytes.starts_with(b"glTF") {
+		return Ok(serde_json::from_slice(&bytes)?);
+	}
+
+	let word = |offset: usize| {
+		bytes
+			.get(offset..offset + 4)
+			.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+			.ok_or(LightmapError::Format("truncated glb header"))
+	};
+	// 12 byte header, then the json chunk's length and type
+	let length = word(12)?;
+	if word(16)? != 0x4e4f_534a {
+		return Err(LightmapError::Format("glb doesn't start with a json chunk"));
+	}
+	let chunk = bytes
+		.get(20..20 + length)
+		.ok_or(LightmapError::Format("truncated glb json chunk"))?;
+	Ok(serde_json::from_slice(chunk)?)
+}
+
+fn to_rgba8(data: &::gltf::image::Data) -> Result<Vec<u8>, LightmapError> {
+	use ::gltf::image::Format;
+
+	// 16 bit channels keep their high byte
+	let high = |pixel: &[u8], channel: usize| {
+		(u16::from_ne_bytes([pixel[channel * 2], pixel[channel * 2 + 1]]) >> 8) as u8
+	};
+	let convert = |stride: usize, texel: &dyn Fn(&[u8]) -> [u8; 4]| {
+		data.pixels.chunks_exact(stride).flat_map(texel).collect()
+	};
+
+	Ok(match data.format {
+		Format::R8 => convert(1, &|p| [p[0], p[0], p[0], 255]),
+		Format::R8G8 => convert(2, &|p| [p[0], p[1], 0, 255]),
+		Format::R8G8B8 => convert(3, &|p| [p[0], p[1], p[2], 255]),
+		Format::R8G8B8A8 => data.pixels.clone(),
+		Format::B8G8R8 => convert(3, &|p| [p[2], p[1], p[0], 255]),
+		Format::B8G8R8A8 => convert(4, &|p| [p[2], p[1], p[0], p[3]]),
+		Format::R16 => convert(2, &|p| [high(p, 0), high(p, 0), high(p, 0), 255]),
+		Format::R16G16 => convert(4, &|p| [high(p, 0), high(p, 1), 0, 255]),
+		Format::R16G16B16 => convert(6, &|p| [high(p, 0), high(p, 1), high(p, 2), 255]),
+		Format::R16G16B16A16 => convert(8, &|p| [high(p, 0), high(p, 1), high(p, 2), high(p, 3)]),
+	})
+}
@@ -0,0 +1,539 @@
+// thick lines. polylines are projected on the cpu and expanded into
+// triangles in screen space, so joins and caps come out the same at any
+// width, with a pixel of fringe on each side for antialiasing.
+
+use crate::math::{Mat4, Vec2, Vec3, Vec4};
+
+use vulkano::buffer::{BufferAccess, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+
+// round joins and caps get a triangle per this many radians
+const ROUND_STEP: f32 = PI / 8.0;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct LineVertex {
+	/// Clip space, the expansion already happened on the cpu.
+	pub position: [f32; 4],
+	pub color: [f32; 4],
+	/// Signed distance from the middle of the line and half its width, in pixels.
+	pub edge: [f32; 2],
+}
+vulkano::impl_vertex!(LineVertex, position, color, edge);
+
+mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec4 position;
+			layout(location = 1) in vec4 color;
+			layout(location = 2) in vec2 edge;
+
+			layout(location = 0) out vec4 v_color;
+			layout(location = 1) out vec2 v_edge;
+
+			void main() {
+				gl_Position = position;
+				v_color = color;
+				v_edge = edge;
+			}
+		"
+	}
+}
+
+mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec4 v_color;
+			layout(location = 1) in vec2 v_edge;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				float coverage = clamp(v_edge.y + 0.5 - abs(v_edge.x), 0.0, 1.0);
+				f_color = vec4(v_color.rgb, v_color.a * coverage);
+			}
+		"
+	}
+}
+
+/// How wide a line is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineWidth {
+	/// The same on screen at any distance, for guides and gizmos.
+	Pixels(f32),
+	/// World units, thinner further away like any other geometry.
+	World(f32),
+}
+
+/// How the corners between segments are filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+	/// Sharp corners, beveled past `LineStyle::miter_limit`.
+	Miter,
+	Bevel,
+	Round,
+}
+
+/// How the ends of an open polyline look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+	/// Ends exactly at the end points.
+	Butt,
+	/// Goes half the width past the end points.
+	Square,
+	Round,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+	pub width: LineWidth,
+	pub color: Vec4,
+	pub join: LineJoin,
+	pub cap: LineCap,
+	/// Longest a miter can get relative to the line's width before it's beveled.
+	pub miter_limit: f32,
+	/// Draws over everything instead of being depth tested, for editor guides.
+	pub on_top: bool,
+}
+
+impl Default for LineStyle {
+	fn default() -> Self {
+		LineStyle {
+			width: LineWidth::Pixels(2.0),
+			color: [1.0; 4],
+			join: LineJoin::Miter,
+			cap: LineCap::Butt,
+			miter_limit: 4.0,
+			on_top: false,
+		}
+	}
+}
+
+struct QueuedLine {
+	points: Vec<Vec3>,
+	style: LineStyle,
+	closed: bool,
+}
+
+// a point of a polyline after projection
+#[derive(Debug, Clone, Copy)]
+struct ScreenPoint {
+	pixel: Vec2,
+	z: f32,
+	w: f32,
+	// half the width in pixels
+	half_width: f32,
+}
+
+/// Draws polylines of any width in the 3d world, or in 2d with a `Camera2d`
+/// projection. Lines are alpha blended and, when the subpass has a depth
+/// attachment, depth tested without writing depth.
+pub struct LineRenderer {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	overlay: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	vertices: CpuBufferPool<LineVertex>,
+	queued: Vec<QueuedLine>,
+}
+
+impl LineRenderer {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let build = |depth_test: bool| {
+			let mut pipeline = GraphicsPipeline::start()
+				.vertex_input_single_buffer::<LineVertex>()
+				.vertex_shader(vs.main_entry_point(), ())
+				.triangle_list()
+				.viewports_dynamic_scissors_irrelevant(1)
+				.fragment_shader(fs.main_entry_point(), ())
+				.blend_alpha_blending();
+			if depth_test {
+				pipeline = pipeline.depth_stencil_simple_depth().depth_write(false);
+			}
+			Arc::new(
+				pipeline
+					.render_pass(subpass.clone())
+					.build(device.clone())
+					.unwrap(),
+			) as Arc<dyn GraphicsPipelineAbstract + Send + Sync>
+		};
+
+		LineRenderer {
+			pipeline: build(subpass.has_depth()),
+			overlay: build(false),
+			vertices: CpuBufferPool::vertex_buffer(device.clone()),
+			queued: Vec::new(),
+		}
+	}
+
+	/// Queues an open polyline through `points` for the next `draw`.
+	pub fn queue(&mut self, points: &[Vec3], style: &LineStyle) {
+		self.queue_line(points, style, false);
+	}
+
+	/// Queues a polyline that joins its last point back to the first.
+	pub fn queue_loop(&mut self, points: &[Vec3], style: &LineStyle) {
+		self.queue_line(points, style, true);
+	}
+
+	fn queue_line(&mut self, points: &[Vec3], style: &LineStyle, closed: bool) {
+		if points.len() < 2 {
+			return;
+		}
+		self.queued.push(QueuedLine {
+			points: points.to_vec(),
+			style: *style,
+			closed,
+		});
+	}
+
+	/// Expands the queued lines for the viewport in `dynamic_state`, records
+	/// them and clears the queue. Must be called inside the subpass the
+	/// renderer was created for.
+	///
+	/// Returns the number of draw calls recorded.
+	pub fn draw(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		view_projection: Mat4,
+	) -> usize {
+		if self.queued.is_empty() {
+			return 0;
+		}
+		let viewport = dynamic_state
+			.viewports
+			.as_ref()
+			.and_then(|viewports| viewports.first())
+			.expect("lines need the viewport in the dynamic state")
+			.dimensions;
+
+		// lines on top go last so nothing is blended over them
+		self.queued.sort_by_key(|line| line.style.on_top);
+		let mut vertices = Vec::new();
+		let mut depth_tested = 0..0;
+		// the projection's y scale is the length of the view projection's y
+		// row, the view only rotates it
+		let y_scale = (0..3)
+			.map(|column| view_projection[column][1].powi(2))
+			.sum::<f32>()
+			.sqrt();
+		for line in self.queued.drain(..) {
+			let mut expander = Expander {
+				vertices: &mut vertices,
+				viewport,
+				y_scale,
+				style: line.style,
+			};
+			expander.line(&line.points, line.closed, view_projection);
+			if !line.style.on_top {
+				depth_tested.end = vertices.len();
+			}
+		}
+		if vertices.is_empty() {
+			return 0;
+		}
+
+		let count = vertices.len();
+		let buffer = Arc::new(self.vertices.chunk(vertices).unwrap());
+		let mut draws = 0;
+		let ranges: [(Range<usize>, _); 2] = [
+			(depth_tested.clone(), &self.pipeline),
+			(depth_tested.end..count, &self.overlay),
+		];
+		for (range, pipeline) in ranges {
+			if range.is_empty() {
+				continue;
+			}
+			let range = buffer.clone().into_buffer_slice().slice(range).unwrap();
+			builder
+				.draw(
+					pipeline.clone(),
+					dynamic_state,
+					vec![Arc::new(range) as Arc<dyn BufferAccess + Send + Sync>],
+					(),
+					(),
+					vec![],
+				)
+				.unwrap();
+			draws += 1;
+		}
+		draws
+	}
+}
+
+// turns one polyline into triangles
+struct Expander<'a> {
+	vertices: &'a mut Vec<LineVertex>,
+	viewport: Vec2,
+	// projection's y scale, world units to ndc at a clip w of 1
+	y_scale: f32,
+	style: LineStyle,
+}
+
+impl Expander<'_> {
+	fn line(&mut self, points: &[Vec3], closed: bool, view_projection: Mat4) {
+		let clip: Vec<Vec4> = points
+			.iter()
+			.map(|&p| transform(view_projection, p))
+			.collect();
+		let mut segments: Vec<(Vec4, Vec4)> =
+			clip.windows(2).map(|pair| (pair[0], pair[1])).collect();
+		if closed {
+			segments.push((clip[clip.len() - 1], clip[0]));
+		}
+
+		// cut the segments at the near plane, every cut starts a new run
+		let mut runs: Vec<Vec<ScreenPoint>> = Vec::new();
+		let mut cut = false;
+		let mut connected = false;
+		for (a, b) in segments {
+			let (a, b, a_cut, b_cut) = match clip_near(a, b) {
+				Some(clipped) => clipped,
+				None => {
+					cut = true;
+					connected = false;
+					continue;
+				}
+			};
+			if !connected || a_cut {
+				runs.push(vec![self.screen(a)]);
+			}
+			runs.last_mut().unwrap().push(self.screen(b));
+			connected = !b_cut;
+			cut |= a_cut || b_cut;
+		}
+
+		for mut run in runs {
+			run.dedup_by(|b, a| distance(a.pixel, b.pixel) < 1e-3);
+			if run.len() < 2 {
+				continue;
+			}
+			// a loop only stays closed when none of it was cut away
+			let closed = closed && !cut;
+			if closed && distance(run[0].pixel, run[run.len() - 1].pixel) < 1e-3 {
+				run.pop();
+			}
+			self.run(&run, closed);
+		}
+	}
+
+	fn screen(&self, clip: Vec4) -> ScreenPoint {
+		let w = clip[3];
+		let half_width = match self.style.width {
+			LineWidth::Pixels(pixels) => pixels * 0.5,
+			LineWidth::World(width) => width * 0.5 * self.y_scale / w * self.viewport[1] * 0.5,
+		};
+		ScreenPoint {
+			pixel: [
+				(clip[0] / w * 0.5 + 0.5) * self.viewport[0],
+				(clip[1] / w * 0.5 + 0.5) * self.viewport[1],
+			],
+			z: clip[2],
+			w,
+			half_width,
+		}
+	}
+
+	fn vertex(&mut self, point: &ScreenPoint, offset: Vec2, edge: f32) {
+		let pixel = [point.pixel[0] + offset[0], point.pixel[1] + offset[1]];
+		self.vertices.push(LineVertex {
+			position: [
+				(pixel[0] / self.viewport[0] * 2.0 - 1.0) * point.w,
+				(pixel[1] / self.viewport[1] * 2.0 - 1.0) * point.w,
+				point.z,
+				point.w,
+			],
+			color: self.style.color,
+			edge: [edge, point.half_width],
+		});
+	}
+
+	fn run(&mut self, points: &[ScreenPoint], closed: bool) {
+		let count = points.len();
+		let segments = if closed { count } else { count - 1 };
+		for i in 0..segments {
+			let (mut a, mut b) = (points[i], points[(i + 1) % count]);
+			let direction = direction(a.pixel, b.pixel);
+			if !closed && self.style.cap == LineCap::Square {
+				if i == 0 {
+					a.pixel = add(a.pixel, scale(direction, -a.half_width));
+				}
+				if i == segments - 1 {
+					b.pixel = add(b.pixel, scale(direction, b.half_width));
+				}
+			}
+			self.segment(&a, &b, normal(direction));
+		}
+
+		let joins = if closed { 0..count } else { 1..count - 1 };
+		for i in joins {
+			let previous = points[(i + count - 1) % count].pixel;
+			let next = points[(i + 1) % count].pixel;
+			self.join(
+				&points[i],
+				direction(previous, points[i].pixel),
+				direction(points[i].pixel, next),
+			);
+		}
+
+		if !closed && self.style.cap == LineCap::Round {
+			let start = direction(points[0].pixel, points[1].pixel);
+			let end = direction(points[count - 2].pixel, points[count - 1].pixel);
+			self.fan(&points[0], normal(start), scale(normal(start), -1.0), PI);
+			self.fan(
+				&points[count - 1],
+				scale(normal(end), -1.0),
+				normal(end),
+				PI,
+			);
+		}
+	}
+
+	fn segment(&mut self, a: &ScreenPoint, b: &ScreenPoint, normal: Vec2) {
+		let (ra, rb) = (a.half_width + 1.0, b.half_width + 1.0);
+		let corners = [
+			(a, scale(normal, ra), ra),
+			(a, scale(normal, -ra), -ra),
+			(b, scale(normal, rb), rb),
+			(b, scale(normal, -rb), -rb),
+		];
+		for &index in &[0, 1, 2, 2, 1, 3] {
+			let (point, offset, edge) = corners[index];
+			self.vertex(point, offset, edge);
+		}
+	}
+
+	// fills the gap on the outer side of a corner
+	fn join(&mut self, point: &ScreenPoint, incoming: Vec2, outgoing: Vec2) {
+		let (n0, n1) = (normal(incoming), normal(outgoing));
+		// turning towards a normal puts the gap on the other side
+		let side = if dot(outgoing, n0) > 0.0 { -1.0 } else { 1.0 };
+		let turn = cross(incoming, outgoing).abs();
+		if turn < 1e-4 && dot(incoming, outgoing) > 0.0 {
+			return;
+		}
+		let (outer0, outer1) = (scale(n0, side), scale(n1, side));
+		let radius = point.half_width + 1.0;
+
+		match self.style.join {
+			LineJoin::Round => {
+				let angle = dot(outer0, outer1).clamp(-1.0, 1.0).acos();
+				self.fan(point, outer0, outer1, angle);
+			}
+			LineJoin::Miter | LineJoin::Bevel => {
+				let miter = normalize(add(outer0, outer1));
+				let length = radius / dot(miter, outer0).max(1e-4);
+				let bevel =
+					self.style.join == LineJoin::Bevel || length > self.style.miter_limit * radius;
+				if bevel {
+					self.vertex(point, [0.0, 0.0], 0.0);
+					self.vertex(point, scale(outer0, radius), radius);
+					self.vertex(point, scale(outer1, radius), radius);
+				} else {
+					let tip = scale(miter, length);
+					for outer in [outer0, outer1] {
+						self.vertex(point, [0.0, 0.0], 0.0);
+						self.vertex(point, scale(outer, radius), radius);
+						self.vertex(point, tip, radius);
+					}
+				}
+			}
+		}
+	}
+
+	// triangles around `point` sweeping `angle` from `from` towards `to`
+	fn fan(&mut self, point: &ScreenPoint, from: Vec2, to: Vec2, angle: f32) {
+		let radius = point.half_width + 1.0;
+		let steps = (angle / ROUND_STEP).ceil().max(1.0) as usize;
+		// sweep the short way to `to`, half circles go counterclockwise which
+		// is the outside for both caps
+		let sign = if cross(from, to) >= 0.0 { 1.0 } else { -1.0 };
+		let rotate = |v: Vec2, a: f32| {
+			let (s, c) = (a * sign).sin_cos();
+			[v[0] * c - v[1] * s, v[0] * s + v[1] * c]
+		};
+		for step in 0..steps {
+			let a0 = angle * step as f32 / steps as f32;
+			let a1 = angle * (step + 1) as f32 / steps as f32;
+			self.vertex(point, [0.0, 0.0], 0.0);
+			self.vertex(point, scale(rotate(from, a0), radius), radius);
+			self.vertex(point, scale(rotate(from, a1), radius), radius);
+		}
+	}
+}
+
+fn transform(m: Mat4, p: Vec3) -> Vec4 {
+	let mut out = [0.0; 4];
+	for (row, out) in out.iter_mut().enumerate() {
+		*out = m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row];
+	}
+	out
+}
+
+// the part of a clip space segment in front of the near plane, with whether
+// each end was moved
+fn clip_near(a: Vec4, b: Vec4) -> Option<(Vec4, Vec4, bool, bool)> {
+	let inside = |p: Vec4| p[2] >= 0.0 && p[3] > 1e-6;
+	let lerp = |a: Vec4, b: Vec4, t: f32| [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t);
+	match (inside(a), inside(b)) {
+		(true, true) => Some((a, b, false, false)),
+		(false, false) => None,
+		(true, false) => Some((a, lerp(a, b, a[2] / (a[2] - b[2])), false, true)),
+		(false, true) => Some((lerp(a, b, a[2] / (a[2] - b[2])), b, true, false)),
+	}
+}
+
+fn add(a: Vec2, b: Vec2) -> Vec2 {
+	[a[0] + b[0], a[1] + b[1]]
+}
+
+fn scale(a: Vec2, s: f32) -> Vec2 {
+	[a[0] * s, a[1] * s]
+}
+
+fn dot(a: Vec2, b: Vec2) -> f32 {
+	a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+	a[0] * b[1] - a[1] * b[0]
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+	let d = [b[0] - a[0], b[1] - a[1]];
+	dot(d, d).sqrt()
+}
+
+fn normalize(a: Vec2) -> Vec2 {
+	let length = dot(a, a).sqrt();
+	if length > 0.0 {
+		scale(a, 1.0 / length)
+	} else {
+		[1.0, 0.0]
+	}
+}
+
+fn direction(from: Vec2, to: Vec2) -> Vec2 {
+	normalize([to[0] - from[0], to[1] - from[1]])
+}
+
+fn normal(direction: Vec2) -> Vec2 {
+	[-direction[1], direction[0]]
+}
@@ -1,25 +1,31 @@
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents};
 use vulkano::device::{Device, DeviceExtensions};
 use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
 use vulkano::image::view::ImageView;
 use vulkano::image::{ImageUsage, SwapchainImage};
-use vulkano::instance::{Instance, PhysicalDevice};
+use vulkano::instance::PhysicalDevice;
 use vulkano::pipeline::viewport::Viewport;
 use vulkano::pipeline::GraphicsPipeline;
 use vulkano::swapchain;
 use vulkano::swapchain::{
-	AcquireError, ColorSpace, FullscreenExclusive, PresentMode, SurfaceTransform, Swapchain,
+	AcquireError, FullscreenExclusive, PresentMode, SurfaceTransform, Swapchain,
 	SwapchainCreationError,
 };
 use vulkano::sync;
 use vulkano::sync::{FlushError, GpuFuture};
 
 use vulkano_win::VkSurfaceBuild;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, MouseButton, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
 
+use opal::display::DisplayOutput;
+use opal::geometry::layout::VertexFormat;
+use opal::geometry::MeshData;
+use opal::picking::PickingTarget;
+use opal::Color;
+
 use std::sync::Arc;
 
 fn main() {
@@ -27,8 +33,9 @@ fn main() {
 	// We start with the extensions required by vulkano_win to create a window.
 	let vk_required_extensions = vulkano_win::required_extensions();
 
-	// create instance of vulkano
-	let vk_instance = Instance::new(None, &vk_required_extensions, None).unwrap();
+	// create instance of vulkano, through moltenvk directly on macos when the
+	// loader doesn't list it
+	let vk_instance = opal::portability::create_instance(vk_required_extensions, None).unwrap();
 
 	// todo pick the best device here
 
@@ -61,8 +68,10 @@ fn main() {
 		.unwrap();
 
 	// vulkan device extension requirements
+	// storage buffers are needed by compute kernels and gpu particles
 	let vk_device_ext = DeviceExtensions {
 		khr_swapchain: true,
+		khr_storage_buffer_storage_class: true,
 		..DeviceExtensions::none()
 	};
 
@@ -70,7 +79,8 @@ fn main() {
 	let (vk_device, mut vk_queues) = Device::new(
 		vk_physical_device,
 		vk_physical_device.supported_features(),
-		&vk_device_ext,
+		// portability devices like moltenvk need their subset extension enabled
+		&opal::portability::device_extensions(vk_physical_device, vk_device_ext),
 		[(vk_queue_family, 0.5)].iter().cloned(),
 	)
 	.unwrap();
@@ -84,7 +94,8 @@ fn main() {
 
 		let alpha = caps.supported_composite_alpha.iter().next().unwrap();
 
-		let format = caps.supported_formats[0].0;
+		// prefer an srgb format so the output isn't washed out
+		let (format, color_space) = DisplayOutput::Sdr.surface_format(&caps).unwrap();
 
 		let dimensions: [u32; 2] = surface.window().inner_size().into();
 
@@ -102,39 +113,29 @@ fn main() {
 			PresentMode::Fifo,
 			FullscreenExclusive::Default,
 			true,
-			ColorSpace::SrgbNonLinear,
+			color_space,
 		)
 		.unwrap()
 	};
 
-	// buffer for storing the vertices of the triangle
-	let vertex_buffer = {
-		#[derive(Default, Debug, Clone)]
-		struct Vertex {
-			position: [f32; 2],
-		}
-		vulkano::impl_vertex!(Vertex, position);
-
-		CpuAccessibleBuffer::from_iter(
-			vk_device.clone(),
-			BufferUsage::all(),
-			false,
-			[
-				Vertex {
-					position: [-0.5, -0.25],
-				},
-				Vertex {
-					position: [0.0, 0.5],
-				},
-				Vertex {
-					position: [0.25, -0.1],
-				},
-			]
-			.iter()
-			.cloned(),
-		)
-		.unwrap();
+	// the triangle as a mesh, uploaded with only the attributes it has
+	let triangle = MeshData {
+		positions: vec![[-0.5, -0.25, 0.0], [0.0, 0.5, 0.0], [0.25, -0.1, 0.0]],
+		colors: vec![
+			Color::RED.into(),
+			Color::GREEN.into(),
+			Color::from_hex("#3366ff").unwrap().into(),
+		],
+		..Default::default()
 	};
+	let vertex_format = VertexFormat::of(&triangle);
+	let vertex_buffer = CpuAccessibleBuffer::from_iter(
+		vk_device.clone(),
+		BufferUsage::all(),
+		false,
+		vertex_format.interleave(&triangle).into_iter(),
+	)
+	.unwrap() as Arc<dyn BufferAccess + Send + Sync>;
 
 	mod vs {
 		vulkano_shaders::shader! {
@@ -142,10 +143,14 @@ fn main() {
 			src: "
 				#version 450
 
-				layout(location = 0) in vec2 position;
+				layout(location = 0) in vec3 position;
+				layout(location = 1) in vec4 color;
+
+				layout(location = 0) out vec4 v_color;
 
 				void main() {
-					gl_Position = vec4(position, 0.0, 1.0);
+					gl_Position = vec4(position, 1.0);
+					v_color = color;
 				}
 			"
 		}
@@ -157,10 +162,12 @@ fn main() {
 			src: "
 				#version 450
 
+				layout(location = 0) in vec4 v_color;
+
 				layout(location = 0) out vec4 f_color;
 
 				void main() {
-					f_color = vec4(1.0, 0.0, 0.0, 1.0);
+					f_color = v_color;
 				}
 			"
 		}
@@ -168,6 +175,7 @@ fn main() {
 
 	let vs = vs::Shader::load(vk_device.clone()).unwrap();
 	let fs = fs::Shader::load(vk_device.clone()).unwrap();
+	let pick_fs = opal::picking::fs::Shader::load(vk_device.clone()).unwrap();
 
 	let render_pass = Arc::new(
 		vulkano::single_pass_renderpass!(
@@ -190,7 +198,7 @@ fn main() {
 
 	let pipeline = Arc::new(
 		GraphicsPipeline::start()
-			.vertex_input_single_buffer()
+			.vertex_input(vertex_format.clone())
 			.vertex_shader(vs.main_entry_point(), ())
 			.triangle_list()
 			.viewports_dynamic_scissors_irrelevant(1)
@@ -200,6 +208,22 @@ fn main() {
 			.unwrap(),
 	);
 
+	// object id pass used for picking what is under the cursor
+	let mut picking = PickingTarget::new(vk_device.clone(), vk_images[0].dimensions());
+
+	let pick_pipeline = Arc::new(
+		GraphicsPipeline::start()
+			.vertex_input(vertex_format.clone())
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(pick_fs.main_entry_point(), ())
+			.depth_stencil_simple_depth()
+			.render_pass(Subpass::from(picking.render_pass(), 0).unwrap())
+			.build(vk_device.clone())
+			.unwrap(),
+	);
+
 	let mut dynamic_state = DynamicState {
 		line_width: None,
 		viewports: None,
@@ -214,6 +238,8 @@ fn main() {
 
 	let mut recreate_swapchain = false;
 
+	let mut cursor_position = [0u32; 2];
+
 	let mut previous_frame_end = Some(sync::now(vk_device.clone()).boxed());
 
 	event_loop.run(move |event, _, control_flow| match event {
@@ -229,9 +255,30 @@ fn main() {
 		} => {
 			recreate_swapchain = true;
 		}
+		Event::WindowEvent {
+			event: WindowEvent::CursorMoved { position, .. },
+			..
+		} => {
+			cursor_position = [position.x.max(0.0) as u32, position.y.max(0.0) as u32];
+		}
+		Event::WindowEvent {
+			event:
+				WindowEvent::MouseInput {
+					state: ElementState::Pressed,
+					button: MouseButton::Left,
+					..
+				},
+			..
+		} => {
+			picking.request(cursor_position);
+		}
 		Event::RedrawEventsCleared => {
 			previous_frame_end.as_mut().unwrap().cleanup_finished();
 
+			if let Some(object_id) = picking.poll() {
+				println!("Picked object: {}", object_id);
+			}
+
 			if recreate_swapchain {
 				let dimensions: [u32; 2] = surface.window().inner_size().into();
 				let (new_swapchain, new_images) =
@@ -246,6 +293,7 @@ fn main() {
 					render_pass.clone(),
 					&mut dynamic_state,
 				);
+				picking.resize(dimensions);
 				recreate_swapchain = false;
 			}
 
@@ -263,7 +311,7 @@ fn main() {
 				recreate_swapchain = true;
 			}
 
-			let clear_values = vec![[0.0, 0.0, 1.0, 1.0].into()];
+			let clear_values = vec![Color::BLUE.into()];
 
 			let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
 				vk_device.clone(),
@@ -271,6 +319,21 @@ fn main() {
 			)
 			.unwrap();
 
+			if picking.is_requested() {
+				picking.begin(&mut builder);
+				builder
+					.draw(
+						pick_pipeline.clone(),
+						&dynamic_state,
+						vec![vertex_buffer.clone()],
+						(),
+						opal::picking::fs::ty::PushConstants { object_id: 1 },
+						vec![],
+					)
+					.unwrap();
+				picking.end(&mut builder);
+			}
+
 			builder
 				.begin_render_pass(
 					framebuffers[image_num].clone(),
@@ -281,7 +344,7 @@ fn main() {
 				.draw(
 					pipeline.clone(),
 					&dynamic_state,
-					vertex_buffer.clone(),
+					vec![vertex_buffer.clone()],
 					(),
 					(),
 					vec![],
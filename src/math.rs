@@ -0,0 +1,644 @@
+// small vector helpers over plain arrays.
+// the rest of the engine passes [f32; N] around so these keep that style
+// instead of pulling in a math crate. the `glam` feature adds conversions
+// for apps that use glam themselves.
+
+#[cfg(feature = "glam")]
+pub use glam;
+
+pub type Vec2 = [f32; 2];
+pub type Vec3 = [f32; 3];
+pub type Vec4 = [f32; 4];
+
+pub fn add(a: Vec3, b: Vec3) -> Vec3 {
+	[a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+	[a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(a: Vec3, s: f32) -> Vec3 {
+	[a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> f32 {
+	a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+	[
+		a[1] * b[2] - a[2] * b[1],
+		a[2] * b[0] - a[0] * b[2],
+		a[0] * b[1] - a[1] * b[0],
+	]
+}
+
+pub fn length(a: Vec3) -> f32 {
+	dot(a, a).sqrt()
+}
+
+/// Returns the unit vector in the direction of `a`, or zero if `a` has no length.
+pub fn normalize(a: Vec3) -> Vec3 {
+	let len = length(a);
+	if len > 0.0 {
+		scale(a, 1.0 / len)
+	} else {
+		[0.0; 3]
+	}
+}
+
+pub fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+	add(a, scale(sub(b, a), t))
+}
+
+/// Quaternion stored as (x, y, z, w), same layout as gltf.
+pub type Quat = [f32; 4];
+
+/// Column-major 4x4 matrix, the same layout glsl and gltf use.
+pub type Mat4 = [[f32; 4]; 4];
+
+pub const IDENTITY: Mat4 = [
+	[1.0, 0.0, 0.0, 0.0],
+	[0.0, 1.0, 0.0, 0.0],
+	[0.0, 0.0, 1.0, 0.0],
+	[0.0, 0.0, 0.0, 1.0],
+];
+
+pub fn mat4_mul(a: Mat4, b: Mat4) -> Mat4 {
+	let mut out = [[0.0; 4]; 4];
+	for (column, b_column) in out.iter_mut().zip(b.iter()) {
+		for (row, value) in column.iter_mut().enumerate() {
+			*value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+		}
+	}
+	out
+}
+
+/// Inverse of the matrix, `None` when it can't be inverted.
+pub fn mat4_inverse(m: Mat4) -> Option<Mat4> {
+	// gauss-jordan elimination with partial pivoting, rows are the matrix
+	// rows next to the identity
+	let mut rows = [[0.0f32; 8]; 4];
+	for (r, row) in rows.iter_mut().enumerate() {
+		for c in 0..4 {
+			row[c] = m[c][r];
+		}
+		row[4 + r] = 1.0;
+	}
+	for c in 0..4 {
+		let pivot = (c..4)
+			.max_by(|&a, &b| rows[a][c].abs().total_cmp(&rows[b][c].abs()))
+			.unwrap();
+		// nan sorts above everything, so it ends up as the pivot
+		if rows[pivot][c].abs() < 1e-12 || rows[pivot][c].is_nan() {
+			return None;
+		}
+		rows.swap(c, pivot);
+		let scale = 1.0 / rows[c][c];
+		for value in rows[c].iter_mut() {
+			*value *= scale;
+		}
+		for r in 0..4 {
+			if r != c {
+				let factor = rows[r][c];
+				let pivot_row = rows[c];
+				for (value, pivot) in rows[r].iter_mut().zip(pivot_row.iter()) {
+					*value -= factor * pivot;
+				}
+			}
+		}
+	}
+
+	let mut out = [[0.0; 4]; 4];
+	for (r, row) in rows.iter().enumerate() {
+		for (c, column) in out.iter_mut().enumerate() {
+			column[r] = row[4 + c];
+		}
+	}
+	Some(out)
+}
+
+/// Transforms a point, ignoring any projection.
+pub fn mat4_transform_point(m: Mat4, p: Vec3) -> Vec3 {
+	[
+		m[0][0] * p[0] + m[1][0] * p[1] + m[2][0] * p[2] + m[3][0],
+		m[0][1] * p[0] + m[1][1] * p[1] + m[2][1] * p[2] + m[3][1],
+		m[0][2] * p[0] + m[1][2] * p[1] + m[2][2] * p[2] + m[3][2],
+	]
+}
+
+/// Builds translation * rotation * scale.
+pub fn mat4_from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Mat4 {
+	let [x, y, z, w] = rotation;
+	[
+		[
+			(1.0 - 2.0 * (y * y + z * z)) * scale[0],
+			(2.0 * (x * y + z * w)) * scale[0],
+			(2.0 * (x * z - y * w)) * scale[0],
+			0.0,
+		],
+		[
+			(2.0 * (x * y - z * w)) * scale[1],
+			(1.0 - 2.0 * (x * x + z * z)) * scale[1],
+			(2.0 * (y * z + x * w)) * scale[1],
+			0.0,
+		],
+		[
+			(2.0 * (x * z + y * w)) * scale[2],
+			(2.0 * (y * z - x * w)) * scale[2],
+			(1.0 - 2.0 * (x * x + y * y)) * scale[2],
+			0.0,
+		],
+		[translation[0], translation[1], translation[2], 1.0],
+	]
+}
+
+/// The six planes of a view frustum as `[a, b, c, d]` with normals pointing
+/// inwards, so a point is inside when `a * x + b * y + c * z + d >= 0` for all of them.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+	pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+	/// Extracts the planes of a vulkan (0 to 1 depth) view projection matrix.
+	pub fn from_view_projection(m: Mat4) -> Self {
+		let row = |i: usize| [m[0][i], m[1][i], m[2][i], m[3][i]];
+		let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+		let plane = |a: Vec4, b: Vec4, sign: f32| {
+			let p = [
+				a[0] + b[0] * sign,
+				a[1] + b[1] * sign,
+				a[2] + b[2] * sign,
+				a[3] + b[3] * sign,
+			];
+			let len = length([p[0], p[1], p[2]]);
+			[p[0] / len, p[1] / len, p[2] / len, p[3] / len]
+		};
+
+		Frustum {
+			planes: [
+				plane(w, x, 1.0),
+				plane(w, x, -1.0),
+				plane(w, y, 1.0),
+				plane(w, y, -1.0),
+				plane(z, z, 0.0),
+				plane(w, z, -1.0),
+			],
+		}
+	}
+
+	/// False when the sphere is entirely outside of the frustum.
+	pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+		self.planes
+			.iter()
+			.all(|p| p[0] * center[0] + p[1] * center[1] + p[2] * center[2] + p[3] >= -radius)
+	}
+
+	/// False when the box is entirely outside of the frustum. Boxes near a
+	/// corner of the frustum can pass without touching it.
+	pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+		let (center, extent) = (aabb.center(), aabb.extent());
+		self.planes.iter().all(|p| {
+			// how far the box reaches towards the plane's normal
+			let reach = extent[0] * p[0].abs() + extent[1] * p[1].abs() + extent[2] * p[2].abs();
+			p[0] * center[0] + p[1] * center[1] + p[2] * center[2] + p[3] >= -reach
+		})
+	}
+
+	pub fn contains_point(&self, point: Vec3) -> bool {
+		self.intersects_sphere(point, 0.0)
+	}
+
+	/// One of the planes, in the order left, right, bottom, top, near, far.
+	pub fn plane(&self, index: usize) -> Plane {
+		let [a, b, c, d] = self.planes[index];
+		Plane {
+			normal: [a, b, c],
+			d,
+		}
+	}
+}
+
+/// Axis aligned bounding box. `Aabb::empty()` holds nothing and grows to fit
+/// whatever is added to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+	pub min: Vec3,
+	pub max: Vec3,
+}
+
+impl Aabb {
+	pub fn new(min: Vec3, max: Vec3) -> Self {
+		Aabb { min, max }
+	}
+
+	pub fn empty() -> Self {
+		Aabb {
+			min: [f32::INFINITY; 3],
+			max: [f32::NEG_INFINITY; 3],
+		}
+	}
+
+	/// Box reaching `extent` from `center` along each axis.
+	pub fn from_center_extent(center: Vec3, extent: Vec3) -> Self {
+		Aabb {
+			min: sub(center, extent),
+			max: add(center, extent),
+		}
+	}
+
+	pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+		let mut aabb = Aabb::empty();
+		for point in points {
+			aabb.grow(point);
+		}
+		aabb
+	}
+
+	pub fn is_empty(&self) -> bool {
+		(0..3).any(|i| self.min[i] > self.max[i])
+	}
+
+	pub fn center(&self) -> Vec3 {
+		scale(add(self.min, self.max), 0.5)
+	}
+
+	/// Half the size.
+	pub fn extent(&self) -> Vec3 {
+		scale(sub(self.max, self.min), 0.5)
+	}
+
+	pub fn size(&self) -> Vec3 {
+		sub(self.max, self.min)
+	}
+
+	pub fn grow(&mut self, point: Vec3) {
+		for (i, &value) in point.iter().enumerate() {
+			self.min[i] = self.min[i].min(value);
+			self.max[i] = self.max[i].max(value);
+		}
+	}
+
+	pub fn union(&self, other: &Aabb) -> Aabb {
+		let mut aabb = *self;
+		aabb.grow(other.min);
+		aabb.grow(other.max);
+		aabb
+	}
+
+	pub fn contains_point(&self, point: Vec3) -> bool {
+		(0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+	}
+
+	pub fn intersects(&self, other: &Aabb) -> bool {
+		(0..3).all(|i| self.min[i] <= other.max[i] && self.max[i] >= other.min[i])
+	}
+
+	/// Distance from `point` to the closest point of the box, 0 inside it.
+	pub fn distance(&self, point: Vec3) -> f32 {
+		let outside: Vec3 = [0, 1, 2].map(|i| {
+			(self.min[i] - point[i])
+				.max(point[i] - self.max[i])
+				.max(0.0)
+		});
+		length(outside)
+	}
+
+	/// Box around this one after transforming it by `m`.
+	pub fn transform(&self, m: Mat4) -> Aabb {
+		let (center, extent) = (self.center(), self.extent());
+		// each axis of the new extent sums the absolute reach of the old one
+		let extent = [0, 1, 2].map(|row| {
+			(0..3)
+				.map(|column| m[column][row].abs() * extent[column])
+				.sum()
+		});
+		Aabb::from_center_extent(mat4_transform_point(m, center), extent)
+	}
+
+	/// Sphere around the box.
+	pub fn bounding_sphere(&self) -> Sphere {
+		Sphere {
+			center: self.center(),
+			radius: length(self.extent()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+	pub center: Vec3,
+	pub radius: f32,
+}
+
+impl Sphere {
+	pub fn new(center: Vec3, radius: f32) -> Self {
+		Sphere { center, radius }
+	}
+
+	pub fn contains_point(&self, point: Vec3) -> bool {
+		let offset = sub(point, self.center);
+		dot(offset, offset) <= self.radius * self.radius
+	}
+
+	pub fn intersects(&self, other: &Sphere) -> bool {
+		let offset = sub(other.center, self.center);
+		let reach = self.radius + other.radius;
+		dot(offset, offset) <= reach * reach
+	}
+
+	pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+		aabb.distance(self.center) <= self.radius
+	}
+}
+
+/// Plane of the points where `dot(normal, point) + d` is 0. Points on the
+/// side the normal faces are in front of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+	pub normal: Vec3,
+	pub d: f32,
+}
+
+impl Plane {
+	/// Plane through `point` facing `normal`, which is normalized here.
+	pub fn from_point_normal(point: Vec3, normal: Vec3) -> Self {
+		let normal = normalize(normal);
+		Plane {
+			normal,
+			d: -dot(normal, point),
+		}
+	}
+
+	/// Plane through a triangle, facing the side it winds counter-clockwise on.
+	pub fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Self {
+		Plane::from_point_normal(a, cross(sub(b, a), sub(c, a)))
+	}
+
+	/// Positive in front of the plane, negative behind it.
+	pub fn signed_distance(&self, point: Vec3) -> f32 {
+		dot(self.normal, point) + self.d
+	}
+}
+
+/// Half line from `origin`. `direction` is kept unit length so the `t`
+/// intersections return are distances.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+	pub origin: Vec3,
+	pub direction: Vec3,
+}
+
+impl Ray {
+	pub fn new(origin: Vec3, direction: Vec3) -> Self {
+		Ray {
+			origin,
+			direction: normalize(direction),
+		}
+	}
+
+	/// Ray from the near plane through a point in normalized device
+	/// coordinates (-1 to 1, y down), for picking with the mouse. `None` when
+	/// the view projection can't be inverted.
+	pub fn from_ndc(ndc: Vec2, view_projection: Mat4) -> Option<Self> {
+		let inverse = mat4_inverse(view_projection)?;
+		let unproject = |depth: f32| {
+			let p = [ndc[0], ndc[1], depth, 1.0];
+			let mut out = [0.0; 4];
+			for (row, value) in out.iter_mut().enumerate() {
+				*value = (0..4).map(|column| inverse[column][row] * p[column]).sum();
+			}
+			[out[0] / out[3], out[1] / out[3], out[2] / out[3]]
+		};
+		let near = unproject(0.0);
+		Some(Ray::new(near, sub(unproject(1.0), near)))
+	}
+
+	pub fn at(&self, t: f32) -> Vec3 {
+		add(self.origin, scale(self.direction, t))
+	}
+
+	/// Distance to where the ray crosses the plane from either side.
+	pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+		let facing = dot(plane.normal, self.direction);
+		if facing.abs() < 1e-8 {
+			return None;
+		}
+		let t = -plane.signed_distance(self.origin) / facing;
+		if t >= 0.0 {
+			Some(t)
+		} else {
+			None
+		}
+	}
+
+	/// Distance to the first hit, 0 when starting inside the sphere.
+	pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+		let offset = sub(self.origin, sphere.center);
+		let b = dot(offset, self.direction);
+		let c = dot(offset, offset) - sphere.radius * sphere.radius;
+		if c <= 0.0 {
+			return Some(0.0);
+		}
+		let discriminant = b * b - c;
+		if b > 0.0 || discriminant < 0.0 {
+			return None;
+		}
+		Some(-b - discriminant.sqrt())
+	}
+
+	/// Distance to the first hit, 0 when starting inside the box.
+	pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+		// slab test, dividing by zero gives infinities that sort themselves out
+		let (mut near, mut far) = (0.0f32, f32::INFINITY);
+		for i in 0..3 {
+			let inverse = 1.0 / self.direction[i];
+			let a = (aabb.min[i] - self.origin[i]) * inverse;
+			let b = (aabb.max[i] - self.origin[i]) * inverse;
+			near = near.max(a.min(b));
+			far = far.min(a.max(b));
+		}
+		if near <= far {
+			Some(near)
+		} else {
+			None
+		}
+	}
+
+	/// Distance to a hit on either side of the triangle (möller-trumbore).
+	pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+		let (ab, ac) = (sub(b, a), sub(c, a));
+		let p = cross(self.direction, ac);
+		let determinant = dot(ab, p);
+		if determinant.abs() < 1e-8 {
+			return None;
+		}
+		let inverse = 1.0 / determinant;
+		let offset = sub(self.origin, a);
+		let u = dot(offset, p) * inverse;
+		if !(0.0..=1.0).contains(&u) {
+			return None;
+		}
+		let q = cross(offset, ab);
+		let v = dot(self.direction, q) * inverse;
+		if v < 0.0 || u + v > 1.0 {
+			return None;
+		}
+		let t = dot(ac, q) * inverse;
+		if t >= 0.0 {
+			Some(t)
+		} else {
+			None
+		}
+	}
+}
+
+pub fn quat_normalize(q: Quat) -> Quat {
+	let len = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+	if len > 0.0 {
+		[q[0] / len, q[1] / len, q[2] / len, q[3] / len]
+	} else {
+		[0.0, 0.0, 0.0, 1.0]
+	}
+}
+
+/// Spherical interpolation along the shortest path.
+pub fn quat_slerp(a: Quat, b: Quat, t: f32) -> Quat {
+	let mut cos = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+	let mut b = b;
+	if cos < 0.0 {
+		cos = -cos;
+		b = [-b[0], -b[1], -b[2], -b[3]];
+	}
+
+	// close enough that a normalized lerp is indistinguishable and avoids dividing by ~0
+	let (wa, wb) = if cos > 0.9995 {
+		(1.0 - t, t)
+	} else {
+		let angle = cos.acos();
+		let sin = angle.sin();
+		(((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+	};
+
+	quat_normalize([
+		a[0] * wa + b[0] * wb,
+		a[1] * wa + b[1] * wb,
+		a[2] * wa + b[2] * wb,
+		a[3] * wa + b[3] * wb,
+	])
+}
+
+// glam's vectors already convert from and into arrays with `From`, matrices
+// and quaternions need these
+#[cfg(feature = "glam")]
+pub fn mat4_to_glam(m: Mat4) -> glam::Mat4 {
+	glam::Mat4::from_cols_array_2d(&m)
+}
+
+#[cfg(feature = "glam")]
+pub fn mat4_from_glam(m: glam::Mat4) -> Mat4 {
+	m.to_cols_array_2d()
+}
+
+#[cfg(feature = "glam")]
+pub fn quat_to_glam(q: Quat) -> glam::Quat {
+	glam::Quat::from_array(q)
+}
+
+#[cfg(feature = "glam")]
+pub fn quat_from_glam(q: glam::Quat) -> Quat {
+	q.to_array()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn close(a: f32, b: f32) -> bool {
+		(a - b).abs() < 1e-4
+	}
+
+	// looks down -z, x and y from -1 to 1, depth 0 at z = 0 to 1 at z = -10
+	const ORTHOGRAPHIC: Mat4 = [
+		[1.0, 0.0, 0.0, 0.0],
+		[0.0, 1.0, 0.0, 0.0],
+		[0.0, 0.0, -0.1, 0.0],
+		[0.0, 0.0, 0.0, 1.0],
+	];
+
+	#[test]
+	fn inverse_round_trips() {
+		let rotation = quat_normalize([0.3, -0.5, 0.1, 0.8]);
+		let m = mat4_from_trs([1.0, -2.0, 3.0], rotation, [2.0, 0.5, 4.0]);
+		let product = mat4_mul(m, mat4_inverse(m).unwrap());
+		for (column, identity) in product.iter().zip(IDENTITY.iter()) {
+			for (value, expected) in column.iter().zip(identity.iter()) {
+				assert!(close(*value, *expected), "{:?}", product);
+			}
+		}
+	}
+
+	#[test]
+	fn inverse_rejects_singular_and_nan() {
+		assert!(mat4_inverse([[0.0; 4]; 4]).is_none());
+		let mut m = IDENTITY;
+		m[1][2] = f32::NAN;
+		assert!(mat4_inverse(m).is_none());
+		assert!(Ray::from_ndc([0.0, 0.0], [[0.0; 4]; 4]).is_none());
+	}
+
+	#[test]
+	fn frustum_culls_aabbs() {
+		let frustum = Frustum::from_view_projection(ORTHOGRAPHIC);
+		let inside = Aabb::new([-0.5, -0.5, -6.0], [0.5, 0.5, -4.0]);
+		let straddling = Aabb::new([0.5, -0.5, -6.0], [2.0, 0.5, -4.0]);
+		let behind = Aabb::new([-0.5, -0.5, 1.0], [0.5, 0.5, 2.0]);
+		let beyond = Aabb::new([-0.5, -0.5, -12.0], [0.5, 0.5, -11.0]);
+		let aside = Aabb::new([2.0, -0.5, -6.0], [3.0, 0.5, -4.0]);
+		assert!(frustum.intersects_aabb(&inside));
+		assert!(frustum.intersects_aabb(&straddling));
+		assert!(!frustum.intersects_aabb(&behind));
+		assert!(!frustum.intersects_aabb(&beyond));
+		assert!(!frustum.intersects_aabb(&aside));
+	}
+
+	#[test]
+	fn ray_from_ndc_goes_through_the_point() {
+		let ray = Ray::from_ndc([0.5, -0.25], ORTHOGRAPHIC).unwrap();
+		assert!(close(ray.origin[0], 0.5) && close(ray.origin[1], -0.25));
+		assert!(close(ray.origin[2], 0.0));
+		assert!(close(ray.direction[2], -1.0));
+	}
+
+	#[test]
+	fn slerp_interpolates_along_the_shortest_path() {
+		let a = [0.0, 0.0, 0.0, 1.0];
+		// half a turn around y
+		let b = [0.0, 1.0, 0.0, 0.0];
+		assert_eq!(quat_slerp(a, b, 0.0), a);
+		let end = quat_slerp(a, b, 1.0);
+		assert!(close(end[1], 1.0) && close(end[3], 0.0));
+
+		// a quarter turn
+		let half = quat_slerp(a, b, 0.5);
+		let expected = std::f32::consts::FRAC_1_SQRT_2;
+		assert!(
+			close(half[1], expected) && close(half[3], expected),
+			"{:?}",
+			half
+		);
+
+		// -q is the same rotation as q, halfway to a negated quarter turn is
+		// still an eighth of a turn and not the long way around
+		let quarter = [0.0, -expected, 0.0, -expected];
+		let eighth = quat_slerp(a, quarter, 0.5);
+		let (sin, cos) = (std::f32::consts::PI / 8.0).sin_cos();
+		assert!(
+			close(eighth[1], sin) && close(eighth[3], cos),
+			"{:?}",
+			eighth
+		);
+	}
+}
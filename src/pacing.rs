@@ -0,0 +1,316 @@
+// frame pacing: start every frame a fixed interval after the previous one
+// instead of as soon as the gpu lets us, so motion looks even.
+//
+// VK_GOOGLE_display_timing isn't wrapped by vulkano 0.22, its two queries are
+// loaded from the device by hand. they tell the pacer how long a refresh
+// cycle really is and when presents reached the display.
+
+use crate::capabilities::supports_display_timing;
+use crate::window::WindowTarget;
+
+use vulkano::device::Device;
+use vulkano::VulkanObject;
+
+use std::ffi::c_char;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// presents reaching the display this much before they had to are on time
+// with headroom, more and frames could have started later
+const TARGET_MARGIN: Duration = Duration::from_millis(2);
+
+/// How the pacer finds out when to start the next frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PacingMethod {
+	/// Sleeps on the cpu until the next frame is due, works everywhere.
+	#[default]
+	Sleep,
+	/// Sleeps too, corrected by `VK_GOOGLE_display_timing`: the interval
+	/// snaps to whole refresh cycles, missed vblanks restart the schedule and
+	/// frames start later when presents keep reaching the display early.
+	DisplayTiming,
+}
+
+impl PacingMethod {
+	/// The method that will actually be used on `device`. `DisplayTiming`
+	/// needs `capabilities::DISPLAY_TIMING_EXTENSIONS` enabled, see
+	/// `EngineBuilder::request_extension_names`.
+	pub fn resolve(self, device: &Arc<Device>) -> PacingMethod {
+		let available = supports_display_timing(device.physical_device())
+			&& DisplayTiming::load(device.clone()).is_some();
+		if self == PacingMethod::DisplayTiming && !available {
+			log::warn!("display timing isn't enabled on the device, pacing frames by sleeping");
+			return PacingMethod::Sleep;
+		}
+		self
+	}
+}
+
+// how vulkano's bindings declare vkGetDeviceProcAddr, as if it never returned null
+type RawGetDeviceProcAddr = extern "system" fn(usize, *const c_char) -> extern "system" fn();
+type GetDeviceProcAddr = unsafe extern "system" fn(usize, *const c_char) -> Option<VoidFunction>;
+type VoidFunction = unsafe extern "system" fn();
+type GetRefreshCycleDuration =
+	unsafe extern "system" fn(usize, u64, *mut RefreshCycleDuration) -> i32;
+type GetPastPresentationTiming =
+	unsafe extern "system" fn(usize, u64, *mut u32, *mut PastPresentationTiming) -> i32;
+
+// VkRefreshCycleDurationGOOGLE
+#[repr(C)]
+struct RefreshCycleDuration {
+	refresh_duration: u64,
+}
+
+// VkPastPresentationTimingGOOGLE
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PastPresentationTiming {
+	present_id: u32,
+	desired_present_time: u64,
+	actual_present_time: u64,
+	earliest_present_time: u64,
+	present_margin: u64,
+}
+
+/// When a present reached the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PastPresent {
+	/// On the display's clock, only differences between presents mean anything.
+	pub actual: Duration,
+	/// How long before it had to be the present was ready.
+	pub margin: Duration,
+}
+
+/// The queries of `VK_GOOGLE_display_timing`. Call them from the thread that
+/// presents, they access the swapchain.
+#[derive(Debug, Clone)]
+pub struct DisplayTiming {
+	device: Arc<Device>,
+	refresh_cycle_duration: GetRefreshCycleDuration,
+	past_presentation_timing: GetPastPresentationTiming,
+}
+
+impl DisplayTiming {
+	/// `None` unless `device` was created with
+	/// `capabilities::DISPLAY_TIMING_EXTENSIONS`.
+	pub fn load(device: Arc<Device>) -> Option<Self> {
+		// safe, vkGetDeviceProcAddr returns null for commands the device
+		// doesn't have, which the `Option` keeps
+		unsafe {
+			let get_proc_addr = mem::transmute::<RawGetDeviceProcAddr, GetDeviceProcAddr>(
+				device.instance().pointers().GetDeviceProcAddr,
+			);
+			let handle = device.internal_object();
+			let load = |name: &[u8]| get_proc_addr(handle, name.as_ptr() as *const c_char);
+			let refresh = load(b"vkGetRefreshCycleDurationGOOGLE\0")?;
+			let past = load(b"vkGetPastPresentationTimingGOOGLE\0")?;
+			Some(DisplayTiming {
+				refresh_cycle_duration: mem::transmute::<VoidFunction, GetRefreshCycleDuration>(
+					refresh,
+				),
+				past_presentation_timing: mem::transmute::<VoidFunction, GetPastPresentationTiming>(
+					past,
+				),
+				device,
+			})
+		}
+	}
+
+	/// Time between two vblanks of the display `window` is on.
+	pub fn refresh_duration(&self, window: &WindowTarget) -> Option<Duration> {
+		let swapchain = window.swapchain_handle()?;
+		let mut duration = RefreshCycleDuration {
+			refresh_duration: 0,
+		};
+		let result = unsafe {
+			(self.refresh_cycle_duration)(self.device.internal_object(), swapchain, &mut duration)
+		};
+		if result < 0 || duration.refresh_duration == 0 {
+			return None;
+		}
+		Some(Duration::from_nanos(duration.refresh_duration))
+	}
+
+	/// The presents of `window` that reached the display since the last call,
+	/// oldest first.
+	pub fn past_presents(&self, window: &WindowTarget) -> Vec<PastPresent> {
+		let swapchain = match window.swapchain_handle() {
+			Some(swapchain) => swapchain,
+			None => return Vec::new(),
+		};
+		let device = self.device.internal_object();
+		let mut count = 0;
+		let mut timings = Vec::new();
+		unsafe {
+			let query = self.past_presentation_timing;
+			if query(device, swapchain, &mut count, ptr::null_mut()) < 0 {
+				return Vec::new();
+			}
+			timings.resize(count as usize, PastPresentationTiming::default());
+			// VK_INCOMPLETE when more arrived in between, those come next time
+			if query(device, swapchain, &mut count, timings.as_mut_ptr()) < 0 {
+				return Vec::new();
+			}
+		}
+		timings.truncate(count as usize);
+		timings.sort_by_key(|timing| timing.actual_present_time);
+		timings
+			.iter()
+			.map(|timing| PastPresent {
+				actual: Duration::from_nanos(timing.actual_present_time),
+				margin: Duration::from_nanos(timing.present_margin),
+			})
+			.collect()
+	}
+}
+
+/// Paces frames to a fixed interval. Call `wait` once per frame right
+/// before acquiring the swapchain image.
+///
+/// `thread::sleep` tends to oversleep by up to a couple of milliseconds, so
+/// the pacer measures how much and sleeps that much less, spinning for the
+/// rest of the time.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+	interval: Duration,
+	next: Option<Instant>,
+	last: Option<Instant>,
+	// how late sleeps have been waking up recently
+	oversleep: Duration,
+	frame_time: Duration,
+	display_timing: Option<DisplayTiming>,
+	last_present: Option<Duration>,
+}
+
+impl FramePacer {
+	pub fn new(interval: Duration) -> Self {
+		FramePacer {
+			interval,
+			next: None,
+			last: None,
+			oversleep: Duration::from_millis(1),
+			frame_time: interval,
+			display_timing: None,
+			last_present: None,
+		}
+	}
+
+	/// Pacer for a target frame rate, eg. the display's refresh rate or an
+	/// integer fraction of it. `None` unless the rate is positive and finite.
+	pub fn with_rate(frames_per_second: f64) -> Option<Self> {
+		if !(frames_per_second > 0.0 && frames_per_second.is_finite()) {
+			return None;
+		}
+		Some(FramePacer::new(Duration::from_secs_f64(
+			1.0 / frames_per_second,
+		)))
+	}
+
+	/// Switches to `method`, falling back to `Sleep` when `device` can't use
+	/// it. Returns the method in use.
+	pub fn set_method(&mut self, method: PacingMethod, device: Arc<Device>) -> PacingMethod {
+		self.display_timing = match method.resolve(&device) {
+			PacingMethod::DisplayTiming => DisplayTiming::load(device),
+			PacingMethod::Sleep => None,
+		};
+		self.last_present = None;
+		match self.display_timing {
+			Some(_) => PacingMethod::DisplayTiming,
+			None => PacingMethod::Sleep,
+		}
+	}
+
+	pub fn interval(&self) -> Duration {
+		self.interval
+	}
+
+	pub fn set_interval(&mut self, interval: Duration) {
+		self.interval = interval;
+		self.next = None;
+	}
+
+	/// Time between the last two calls to `wait`.
+	pub fn frame_time(&self) -> Duration {
+		self.frame_time
+	}
+
+	/// Blocks until the next frame is due. When a frame ran long the schedule
+	/// starts over from now instead of rushing the following frames.
+	pub fn wait(&mut self) {
+		let now = Instant::now();
+		let deadline = match self.next {
+			Some(next) if next > now => next,
+			// a little late, keep the schedule
+			Some(next) if now - next < self.interval => next,
+			// first frame, or a whole interval behind
+			_ => now,
+		};
+
+		if deadline > now {
+			let remaining = deadline - now;
+			if remaining > self.oversleep {
+				let sleep = remaining - self.oversleep;
+				let before = Instant::now();
+				thread::sleep(sleep);
+				let overshoot = before.elapsed().saturating_sub(sleep);
+				// rise quickly when sleeps get worse, settle slowly
+				self.oversleep = if overshoot > self.oversleep {
+					overshoot
+				} else {
+					(self.oversleep * 15 + overshoot) / 16
+				};
+			}
+			while Instant::now() < deadline {
+				thread::yield_now();
+			}
+		}
+
+		let start = Instant::now();
+		if let Some(last) = self.last {
+			self.frame_time = start - last;
+		}
+		self.last = Some(start);
+		self.next = Some(deadline + self.interval);
+	}
+
+	/// Corrects the schedule with when `window`'s presents actually reached
+	/// the display. Call after presenting, does nothing with the `Sleep`
+	/// method.
+	pub fn presented(&mut self, window: &WindowTarget) {
+		let timing = match &self.display_timing {
+			Some(timing) => timing,
+			None => return,
+		};
+
+		// a whole number of refresh cycles, frames in between get judder
+		if let Some(refresh) = timing.refresh_duration(window) {
+			let cycles = (self.interval.as_secs_f64() / refresh.as_secs_f64())
+				.round()
+				.max(1.0);
+			self.interval = refresh.mul_f64(cycles);
+		}
+
+		let presents = timing.past_presents(window);
+		for present in &presents {
+			if let Some(last) = self.last_present {
+				// missed a vblank, start over instead of rushing to catch up
+				if present.actual.saturating_sub(last) > self.interval + self.interval / 2 {
+					self.next = None;
+				}
+			}
+			self.last_present = Some(present.actual);
+		}
+
+		// frames finishing well ahead of their vblank could start later for
+		// less latency. a quarter at a time, the reports lag a few frames
+		let margin = presents.last().map(|present| present.margin);
+		if let (Some(margin), Some(next)) = (margin, &mut self.next) {
+			if margin > TARGET_MARGIN {
+				*next += (margin - TARGET_MARGIN) / 4;
+			}
+		}
+	}
+}
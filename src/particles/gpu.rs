@@ -0,0 +1,480 @@
+use super::render::{fs, BillboardCamera};
+use super::EmitterDesc;
+use crate::math::Vec3;
+
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuBufferPool, DeviceLocalBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DrawIndirectCommand, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::{
+	ComputePipeline, ComputePipelineAbstract, GraphicsPipeline, GraphicsPipelineAbstract,
+};
+use vulkano::sampler::Sampler;
+
+use std::sync::Arc;
+
+// number of evenly spaced samples the color and size curves are baked into
+const CURVE_SAMPLES: usize = 8;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+// matches `Particle` in the shaders
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+struct GpuParticle {
+	position_size: [f32; 4],
+	velocity_age: [f32; 4],
+	color: [f32; 4],
+	lifetime: [f32; 4],
+}
+
+// spawns, integrates and compacts the particles in one pass.
+// threads below the live count simulate, the ones after it spawn. survivors
+// and new particles are appended to the other buffer, which also builds the
+// indirect draw arguments.
+mod simulate {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 64) in;
+
+			struct Particle {
+				vec4 position_size;
+				vec4 velocity_age;
+				vec4 color;
+				vec4 lifetime;
+			};
+
+			layout(set = 0, binding = 0) uniform Params {
+				// xyz emitter position, w spawn radius
+				vec4 origin_radius;
+				// xyz velocity, w random spread
+				vec4 velocity_spread;
+				// xyz gravity, w drag
+				vec4 gravity_drag;
+				// min lifetime, max lifetime, delta time
+				vec4 lifetime_delta;
+				vec4 color_samples[8];
+				vec4 size_samples[2];
+				// spawn count, capacity, random seed
+				uvec4 counts;
+			} params;
+
+			layout(set = 0, binding = 1) readonly buffer Source {
+				Particle particles[];
+			} src;
+
+			layout(set = 0, binding = 2) readonly buffer SourceArgs {
+				uint vertex_count;
+				uint instance_count;
+				uint first_vertex;
+				uint first_instance;
+			} src_args;
+
+			layout(set = 0, binding = 3) writeonly buffer Destination {
+				Particle particles[];
+			} dst;
+
+			layout(set = 0, binding = 4) buffer DestinationArgs {
+				uint vertex_count;
+				uint instance_count;
+				uint first_vertex;
+				uint first_instance;
+			} dst_args;
+
+			uint hash(uint x) {
+				x ^= x >> 16;
+				x *= 0x7feb352du;
+				x ^= x >> 15;
+				x *= 0x846ca68bu;
+				x ^= x >> 16;
+				return x;
+			}
+
+			float random(inout uint state) {
+				state = hash(state);
+				return float(state >> 8) / 16777216.0;
+			}
+
+			vec3 random_direction(inout uint state) {
+				float z = random(state) * 2.0 - 1.0;
+				float angle = random(state) * 6.2831853;
+				float r = sqrt(1.0 - z * z);
+				return vec3(r * cos(angle), r * sin(angle), z);
+			}
+
+			vec4 sample_color(float age) {
+				float x = clamp(age, 0.0, 1.0) * 7.0;
+				int i = min(int(x), 6);
+				return mix(params.color_samples[i], params.color_samples[i + 1], x - float(i));
+			}
+
+			float size_sample(int i) {
+				return params.size_samples[i / 4][i % 4];
+			}
+
+			float sample_size(float age) {
+				float x = clamp(age, 0.0, 1.0) * 7.0;
+				int i = min(int(x), 6);
+				return mix(size_sample(i), size_sample(i + 1), x - float(i));
+			}
+
+			void append(Particle particle) {
+				uint index = atomicAdd(dst_args.instance_count, 1);
+				dst.particles[index] = particle;
+			}
+
+			void main() {
+				uint i = gl_GlobalInvocationID.x;
+				uint alive = src_args.instance_count;
+				float delta = params.lifetime_delta.z;
+
+				if (i < alive) {
+					Particle particle = src.particles[i];
+					float age = particle.velocity_age.w + delta;
+					if (age >= particle.lifetime.x) {
+						return;
+					}
+
+					float damping = max(1.0 - params.gravity_drag.w * delta, 0.0);
+					vec3 velocity = (particle.velocity_age.xyz + params.gravity_drag.xyz * delta) * damping;
+					vec3 position = particle.position_size.xyz + velocity * delta;
+					float t = age / particle.lifetime.x;
+
+					particle.position_size = vec4(position, sample_size(t));
+					particle.velocity_age = vec4(velocity, age);
+					particle.color = sample_color(t);
+					append(particle);
+					return;
+				}
+
+				// survivors never outnumber the live particles so this keeps the total under capacity
+				uint spawn = i - alive;
+				if (spawn >= params.counts.x || i >= params.counts.y) {
+					return;
+				}
+
+				uint state = hash(params.counts.z ^ hash(spawn));
+				vec3 offset = random_direction(state) * params.origin_radius.w * random(state);
+				vec3 spread = random_direction(state) * params.velocity_spread.w * random(state);
+				float lifetime = mix(params.lifetime_delta.x, params.lifetime_delta.y, random(state));
+
+				Particle particle;
+				particle.position_size = vec4(params.origin_radius.xyz + offset, sample_size(0.0));
+				particle.velocity_age = vec4(params.velocity_spread.xyz + spread, 0.0);
+				particle.color = sample_color(0.0);
+				particle.lifetime = vec4(max(lifetime, 0.0001), 0.0, 0.0, 0.0);
+				append(particle);
+			}
+		"
+	}
+}
+
+// billboard vertex shader that pulls particles straight from the simulation buffer.
+// outputs match `render::vs` so the same fragment shader is used.
+mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			struct Particle {
+				vec4 position_size;
+				vec4 velocity_age;
+				vec4 color;
+				vec4 lifetime;
+			};
+
+			layout(set = 1, binding = 0) readonly buffer Particles {
+				Particle particles[];
+			} live;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 camera_right;
+				vec4 camera_up;
+				vec4 depth_params;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+			layout(location = 2) out float v_depth;
+
+			const vec2 corners[4] = vec2[](
+				vec2(-0.5, -0.5),
+				vec2(0.5, -0.5),
+				vec2(-0.5, 0.5),
+				vec2(0.5, 0.5)
+			);
+
+			void main() {
+				Particle particle = live.particles[gl_InstanceIndex];
+				vec2 corner = corners[gl_VertexIndex];
+				vec3 offset = push_constants.camera_right.xyz * corner.x
+					+ push_constants.camera_up.xyz * corner.y;
+
+				gl_Position = push_constants.view_projection
+					* vec4(particle.position_size.xyz + offset * particle.position_size.w, 1.0);
+				v_uv = corner + 0.5;
+				v_color = particle.color;
+				v_depth = gl_Position.w;
+			}
+		"
+	}
+}
+
+/// Emitter simulated entirely on the gpu, meant for hundreds of thousands of particles.
+///
+/// Uses the same `EmitterDesc` as the cpu `Emitter`. Particles live in two
+/// buffers that are swapped every update; the simulation writes the survivors
+/// compacted into the other buffer along with the indirect draw arguments, so
+/// the cpu never needs to know how many are alive.
+///
+/// Particles are not sorted, use additive looking colors or accept some
+/// blending order artifacts. The device needs `khr_storage_buffer_storage_class`.
+pub struct GpuEmitter {
+	pub desc: EmitterDesc,
+	pub position: Vec3,
+	/// Distance over which particles fade out near opaque geometry, 0 disables it.
+	pub softness: f32,
+	simulate: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	particles: [Arc<DeviceLocalBuffer<[GpuParticle]>>; 2],
+	args: [Arc<DeviceLocalBuffer<[DrawIndirectCommand]>>; 2],
+	params: CpuBufferPool<simulate::ty::Params>,
+	sampler: Arc<Sampler>,
+	// index of the buffer holding the live particles
+	current: usize,
+	// false until the draw arguments have been written once
+	initialized: bool,
+	time: f32,
+	spawn_accumulator: f32,
+	frame: u32,
+}
+
+impl GpuEmitter {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+		desc: EmitterDesc,
+		position: Vec3,
+	) -> Self {
+		let simulate_shader = simulate::Shader::load(device.clone()).unwrap();
+		let simulate = Arc::new(
+			ComputePipeline::new(
+				device.clone(),
+				&simulate_shader.main_entry_point(),
+				&(),
+				None,
+			)
+			.unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input(BufferlessDefinition)
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_strip()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ())
+			.blend_alpha_blending();
+		if has_depth {
+			pipeline = pipeline.depth_stencil_simple_depth().depth_write(false);
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		let particle_buffer = || {
+			DeviceLocalBuffer::array(
+				device.clone(),
+				desc.max_particles as usize,
+				BufferUsage {
+					storage_buffer: true,
+					..BufferUsage::none()
+				},
+				device.active_queue_families(),
+			)
+			.unwrap()
+		};
+		let args_buffer = || {
+			DeviceLocalBuffer::array(
+				device.clone(),
+				1,
+				BufferUsage {
+					storage_buffer: true,
+					indirect_buffer: true,
+					transfer_destination: true,
+					..BufferUsage::none()
+				},
+				device.active_queue_families(),
+			)
+			.unwrap()
+		};
+
+		GpuEmitter {
+			particles: [particle_buffer(), particle_buffer()],
+			args: [args_buffer(), args_buffer()],
+			params: CpuBufferPool::uniform_buffer(device.clone()),
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device),
+			desc,
+			position,
+			softness: 0.5,
+			simulate,
+			pipeline,
+			current: 0,
+			initialized: false,
+			time: 0.0,
+			spawn_accumulator: 0.0,
+			frame: 0,
+		}
+	}
+
+	/// Records the simulation step. Must be recorded outside of a render pass.
+	pub fn update(&mut self, builder: &mut AutoCommandBufferBuilder, delta: f32) {
+		let (src, dst) = (self.current, 1 - self.current);
+
+		if !self.initialized {
+			builder
+				.update_buffer(self.args[src].clone(), empty_args())
+				.unwrap();
+			self.initialized = true;
+		}
+		builder
+			.update_buffer(self.args[dst].clone(), empty_args())
+			.unwrap();
+
+		let spawn = self
+			.desc
+			.spawn_count(self.time, delta, &mut self.spawn_accumulator);
+		self.time += delta;
+		self.frame = self.frame.wrapping_add(1);
+
+		let desc = &self.desc;
+		let mut color_samples = [[0.0; 4]; CURVE_SAMPLES];
+		let mut size_samples = [[0.0; 4]; CURVE_SAMPLES / 4];
+		for i in 0..CURVE_SAMPLES {
+			let age = i as f32 / (CURVE_SAMPLES - 1) as f32;
+			color_samples[i] = desc.color.sample(age);
+			size_samples[i / 4][i % 4] = desc.size.sample(age);
+		}
+
+		let params = self
+			.params
+			.next(simulate::ty::Params {
+				origin_radius: [
+					self.position[0],
+					self.position[1],
+					self.position[2],
+					desc.spawn_radius,
+				],
+				velocity_spread: [
+					desc.velocity[0],
+					desc.velocity[1],
+					desc.velocity[2],
+					desc.velocity_spread,
+				],
+				gravity_drag: [desc.gravity[0], desc.gravity[1], desc.gravity[2], desc.drag],
+				lifetime_delta: [desc.lifetime[0], desc.lifetime[1], delta, 0.0],
+				color_samples,
+				size_samples,
+				counts: [
+					spawn,
+					desc.max_particles,
+					self.frame.wrapping_mul(0x9e37_79b9),
+					0,
+				],
+			})
+			.unwrap();
+
+		let layout = self.simulate.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_buffer(params)
+				.unwrap()
+				.add_buffer(self.particles[src].clone())
+				.unwrap()
+				.add_buffer(self.args[src].clone())
+				.unwrap()
+				.add_buffer(self.particles[dst].clone())
+				.unwrap()
+				.add_buffer(self.args[dst].clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		// enough threads for every live particle plus the new ones, capped by capacity
+		let groups = desc.max_particles.div_ceil(WORKGROUP_SIZE);
+		builder
+			.dispatch([groups, 1, 1], self.simulate.clone(), set, (), vec![])
+			.unwrap();
+
+		self.current = dst;
+	}
+
+	/// Records the indirect draw of the live particles. Must be called inside
+	/// the subpass the emitter was created for.
+	pub fn draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		camera: &BillboardCamera,
+		scene_depth: Arc<dyn ImageViewAbstract + Send + Sync>,
+	) {
+		if !self.initialized {
+			return;
+		}
+
+		let depth_set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).unwrap().clone())
+				.add_sampled_image(scene_depth, self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let particle_set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(1).unwrap().clone())
+				.add_buffer(self.particles[self.current].clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		let push_constants = vs::ty::PushConstants {
+			view_projection: camera.view_projection,
+			camera_right: [camera.right[0], camera.right[1], camera.right[2], 0.0],
+			camera_up: [camera.up[0], camera.up[1], camera.up[2], 0.0],
+			depth_params: [camera.near, camera.far, self.softness, 0.0],
+		};
+
+		builder
+			.draw_indirect(
+				self.pipeline.clone(),
+				dynamic_state,
+				Vec::<Arc<dyn BufferAccess + Send + Sync>>::new(),
+				self.args[self.current].clone(),
+				(depth_set, particle_set),
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
+
+fn empty_args() -> Box<[DrawIndirectCommand]> {
+	Box::new([DrawIndirectCommand {
+		vertex_count: 4,
+		instance_count: 0,
+		first_vertex: 0,
+		first_instance: 0,
+	}])
+}
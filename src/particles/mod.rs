@@ -0,0 +1,272 @@
+pub mod gpu;
+pub mod render;
+
+use crate::math::{add, normalize, scale, Vec3, Vec4};
+
+/// Piecewise linear curve over a particle's normalized age (0 = born, 1 = dead).
+#[derive(Debug, Clone)]
+pub struct Curve<T> {
+	/// `(age, value)` pairs sorted by age.
+	pub keys: Vec<(f32, T)>,
+}
+
+impl<T: Copy + Lerp> Curve<T> {
+	pub fn constant(value: T) -> Self {
+		Curve {
+			keys: vec![(0.0, value)],
+		}
+	}
+
+	pub fn linear(start: T, end: T) -> Self {
+		Curve {
+			keys: vec![(0.0, start), (1.0, end)],
+		}
+	}
+
+	/// Value at `age`, held flat before the first and after the last key.
+	/// A curve without keys samples as `T::default()`.
+	pub fn sample(&self, age: f32) -> T
+	where
+		T: Default,
+	{
+		let last = match self.keys.len() {
+			0 => return T::default(),
+			len => len - 1,
+		};
+		if age <= self.keys[0].0 {
+			return self.keys[0].1;
+		}
+		if age >= self.keys[last].0 {
+			return self.keys[last].1;
+		}
+
+		let next = self.keys.partition_point(|key| key.0 <= age);
+		let (a, b) = (self.keys[next - 1], self.keys[next]);
+		a.1.lerp(b.1, (age - a.0) / (b.0 - a.0))
+	}
+}
+
+pub trait Lerp {
+	fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+	fn lerp(self, other: Self, t: f32) -> Self {
+		self + (other - self) * t
+	}
+}
+
+impl Lerp for Vec4 {
+	fn lerp(self, other: Self, t: f32) -> Self {
+		[
+			self[0].lerp(other[0], t),
+			self[1].lerp(other[1], t),
+			self[2].lerp(other[2], t),
+			self[3].lerp(other[3], t),
+		]
+	}
+}
+
+/// Spawns a fixed number of particles at `time` seconds after the emitter starts.
+#[derive(Debug, Clone, Copy)]
+pub struct Burst {
+	pub time: f32,
+	pub count: u32,
+}
+
+/// Everything that describes how an emitter behaves.
+///
+/// Kept free of any simulation state so the same description can drive either
+/// particle backend.
+#[derive(Debug, Clone)]
+pub struct EmitterDesc {
+	/// Particles per second.
+	pub spawn_rate: f32,
+	pub bursts: Vec<Burst>,
+	/// Emission stops after this many seconds, `None` runs forever.
+	pub duration: Option<f32>,
+	pub max_particles: u32,
+	/// Lifetime in seconds, picked uniformly between the two values.
+	pub lifetime: [f32; 2],
+	/// Particles start somewhere inside a sphere of this radius around the emitter.
+	pub spawn_radius: f32,
+	pub velocity: Vec3,
+	/// Random extra velocity in any direction, up to this length.
+	pub velocity_spread: f32,
+	pub gravity: Vec3,
+	/// Fraction of velocity lost per second.
+	pub drag: f32,
+	pub color: Curve<Vec4>,
+	pub size: Curve<f32>,
+}
+
+impl Default for EmitterDesc {
+	fn default() -> Self {
+		EmitterDesc {
+			spawn_rate: 10.0,
+			bursts: Vec::new(),
+			duration: None,
+			max_particles: 1000,
+			lifetime: [1.0, 1.0],
+			spawn_radius: 0.0,
+			velocity: [0.0, 1.0, 0.0],
+			velocity_spread: 0.0,
+			gravity: [0.0, -9.81, 0.0],
+			drag: 0.0,
+			color: Curve::constant([1.0; 4]),
+			size: Curve::constant(0.1),
+		}
+	}
+}
+
+impl EmitterDesc {
+	/// Number of particles to spawn for the step from `time` to `time + delta`.
+	///
+	/// `accumulator` carries the fractional particles of the spawn rate over to
+	/// the next step.
+	pub fn spawn_count(&self, time: f32, delta: f32, accumulator: &mut f32) -> u32 {
+		let end = time + delta;
+
+		let mut count = 0;
+		if !matches!(self.duration, Some(d) if time >= d) {
+			*accumulator += self.spawn_rate * delta;
+			count = *accumulator as u32;
+			*accumulator -= count as f32;
+		}
+
+		count
+			+ self
+				.bursts
+				.iter()
+				.filter(|burst| burst.time >= time && burst.time < end)
+				.map(|burst| burst.count)
+				.sum::<u32>()
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+	pub position: Vec3,
+	pub velocity: Vec3,
+	pub age: f32,
+	pub lifetime: f32,
+}
+
+impl Particle {
+	/// Age from 0 to 1 over the particle's lifetime.
+	pub fn normalized_age(&self) -> f32 {
+		self.age / self.lifetime
+	}
+}
+
+/// Cpu simulated emitter.
+pub struct Emitter {
+	pub desc: EmitterDesc,
+	/// World space position new particles are spawned around.
+	pub position: Vec3,
+	pub particles: Vec<Particle>,
+	time: f32,
+	// fractional particles carried over between frames
+	spawn_accumulator: f32,
+	rng: Rng,
+}
+
+impl Emitter {
+	pub fn new(desc: EmitterDesc, position: Vec3) -> Self {
+		Emitter {
+			particles: Vec::with_capacity(desc.max_particles as usize),
+			desc,
+			position,
+			time: 0.0,
+			spawn_accumulator: 0.0,
+			rng: Rng::new(0x9e37_79b9),
+		}
+	}
+
+	/// True once emission has stopped and every particle has died.
+	pub fn is_finished(&self) -> bool {
+		self.particles.is_empty() && matches!(self.desc.duration, Some(d) if self.time >= d)
+	}
+
+	/// Restarts emission, keeping the particles that are still alive.
+	pub fn restart(&mut self) {
+		self.time = 0.0;
+		self.spawn_accumulator = 0.0;
+	}
+
+	pub fn update(&mut self, delta: f32) {
+		let start = self.time;
+		self.time += delta;
+
+		// age and integrate, dropping dead particles
+		let desc = &self.desc;
+		let damping = (1.0 - desc.drag * delta).max(0.0);
+		self.particles.retain_mut(|particle| {
+			particle.age += delta;
+			particle.velocity = scale(add(particle.velocity, scale(desc.gravity, delta)), damping);
+			particle.position = add(particle.position, scale(particle.velocity, delta));
+			particle.age < particle.lifetime
+		});
+
+		let count = self
+			.desc
+			.spawn_count(start, delta, &mut self.spawn_accumulator);
+		for _ in 0..count {
+			self.spawn();
+		}
+	}
+
+	/// Spawns a single particle, ignored when the emitter is full.
+	pub fn spawn(&mut self) {
+		if self.particles.len() >= self.desc.max_particles as usize {
+			return;
+		}
+
+		let desc = &self.desc;
+		let offset = scale(self.rng.unit_vector(), desc.spawn_radius * self.rng.next());
+		let spread = scale(
+			self.rng.unit_vector(),
+			desc.velocity_spread * self.rng.next(),
+		);
+		let lifetime = desc.lifetime[0] + (desc.lifetime[1] - desc.lifetime[0]) * self.rng.next();
+
+		self.particles.push(Particle {
+			position: add(self.position, offset),
+			velocity: add(desc.velocity, spread),
+			age: 0.0,
+			lifetime: lifetime.max(f32::EPSILON),
+		});
+	}
+}
+
+// small xorshift generator, particles only need cheap noise
+#[derive(Debug, Clone)]
+struct Rng(u32);
+
+impl Rng {
+	fn new(seed: u32) -> Self {
+		Rng(seed.max(1))
+	}
+
+	// uniform in [0, 1)
+	fn next(&mut self) -> f32 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 17;
+		self.0 ^= self.0 << 5;
+		(self.0 >> 8) as f32 / (1u32 << 24) as f32
+	}
+
+	fn unit_vector(&mut self) -> Vec3 {
+		loop {
+			let v = [
+				self.next() * 2.0 - 1.0,
+				self.next() * 2.0 - 1.0,
+				self.next() * 2.0 - 1.0,
+			];
+			let length_squared = v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+			if length_squared > 1e-4 && length_squared <= 1.0 {
+				return normalize(v);
+			}
+		}
+	}
+}
@@ -0,0 +1,276 @@
+use super::Emitter;
+use crate::math::{mat4_mul, Mat4, Vec3};
+
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+
+use std::sync::Arc;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Corner {
+	pub corner: [f32; 2],
+}
+vulkano::impl_vertex!(Corner, corner);
+
+/// Per particle data for the instanced billboard draw.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ParticleInstance {
+	pub center: [f32; 3],
+	pub size: f32,
+	pub color: [f32; 4],
+}
+vulkano::impl_vertex!(ParticleInstance, center, size, color);
+
+/// Camera data the billboards need to face the viewer.
+#[derive(Debug, Clone, Copy)]
+pub struct BillboardCamera {
+	pub view_projection: Mat4,
+	/// World space right and up axes of the camera.
+	pub right: Vec3,
+	pub up: Vec3,
+	/// Camera position in world space.
+	pub eye: Vec3,
+	pub near: f32,
+	pub far: f32,
+}
+
+impl BillboardCamera {
+	pub fn new(view: Mat4, projection: Mat4, near: f32, far: f32) -> Self {
+		BillboardCamera {
+			view_projection: mat4_mul(projection, view),
+			// the rows of the view rotation are the camera axes in world space
+			right: [view[0][0], view[1][0], view[2][0]],
+			up: [view[0][1], view[1][1], view[2][1]],
+			// the translation undone by the transposed rotation
+			eye: [0, 1, 2].map(|axis| {
+				-(view[axis][0] * view[3][0]
+					+ view[axis][1] * view[3][1]
+					+ view[axis][2] * view[3][2])
+			}),
+			near,
+			far,
+		}
+	}
+
+	// distance in front of the camera, clip space w for a perspective projection
+	fn depth(&self, p: Vec3) -> f32 {
+		let m = &self.view_projection;
+		m[0][3] * p[0] + m[1][3] * p[1] + m[2][3] * p[2] + m[3][3]
+	}
+}
+
+pub mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 corner;
+			layout(location = 1) in vec3 center;
+			layout(location = 2) in float size;
+			layout(location = 3) in vec4 color;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 camera_right;
+				vec4 camera_up;
+				// near, far, soft particle fade distance
+				vec4 depth_params;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+			layout(location = 2) out float v_depth;
+
+			void main() {
+				vec3 offset = push_constants.camera_right.xyz * corner.x
+					+ push_constants.camera_up.xyz * corner.y;
+
+				gl_Position = push_constants.view_projection * vec4(center + offset * size, 1.0);
+				v_uv = corner + 0.5;
+				v_color = color;
+				v_depth = gl_Position.w;
+			}
+		"
+	}
+}
+
+pub mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+			layout(location = 2) in float v_depth;
+
+			layout(set = 0, binding = 0) uniform sampler2D scene_depth;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 camera_right;
+				vec4 camera_up;
+				vec4 depth_params;
+			} push_constants;
+
+			layout(location = 0) out vec4 f_color;
+
+			float linear_depth(float depth) {
+				float near = push_constants.depth_params.x;
+				float far = push_constants.depth_params.y;
+				return near * far / (far - depth * (far - near));
+			}
+
+			void main() {
+				// soft round dot
+				float shape = 1.0 - smoothstep(0.3, 0.5, length(v_uv - 0.5));
+
+				// fade out where the billboard cuts into opaque geometry
+				float fade = 1.0;
+				float softness = push_constants.depth_params.z;
+				if (softness > 0.0) {
+					vec2 screen_uv = gl_FragCoord.xy / vec2(textureSize(scene_depth, 0));
+					float scene = linear_depth(texture(scene_depth, screen_uv).r);
+					fade = clamp((scene - v_depth) / softness, 0.0, 1.0);
+				}
+
+				f_color = vec4(v_color.rgb, v_color.a * shape * fade);
+			}
+		"
+	}
+}
+
+/// Draws every particle of a set of emitters as camera facing quads in one instanced draw.
+///
+/// Soft particles sample the depth of the opaque geometry, so `scene_depth` has
+/// to be a copy (or the result of an earlier pass) rather than the depth
+/// attachment bound while drawing the particles.
+pub struct ParticleRenderer {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	quad: Arc<CpuAccessibleBuffer<[Corner]>>,
+	instances: CpuBufferPool<ParticleInstance>,
+	sampler: Arc<Sampler>,
+	/// Distance over which particles fade out near opaque geometry, 0 disables it.
+	pub softness: f32,
+}
+
+impl ParticleRenderer {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input(OneVertexOneInstanceDefinition::<Corner, ParticleInstance>::new())
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_strip()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ())
+			.blend_alpha_blending();
+		if has_depth {
+			// test against the scene but don't occlude other particles
+			pipeline = pipeline.depth_stencil_simple_depth().depth_write(false);
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		let quad = CpuAccessibleBuffer::from_iter(
+			device.clone(),
+			BufferUsage::vertex_buffer(),
+			false,
+			[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]]
+				.iter()
+				.map(|&corner| Corner { corner }),
+		)
+		.unwrap();
+
+		ParticleRenderer {
+			instances: CpuBufferPool::vertex_buffer(device.clone()),
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device),
+			pipeline,
+			quad,
+			softness: 0.5,
+		}
+	}
+
+	/// Records the draw. Must be called inside the subpass the renderer was created for.
+	pub fn draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		camera: &BillboardCamera,
+		emitters: &[&Emitter],
+		scene_depth: Arc<dyn ImageViewAbstract + Send + Sync>,
+	) {
+		let mut instances: Vec<(f32, ParticleInstance)> = emitters
+			.iter()
+			.flat_map(|emitter| {
+				emitter.particles.iter().map(move |particle| {
+					let age = particle.normalized_age();
+					(
+						camera.depth(particle.position),
+						ParticleInstance {
+							center: particle.position,
+							size: emitter.desc.size.sample(age),
+							color: emitter.desc.color.sample(age),
+						},
+					)
+				})
+			})
+			.collect();
+
+		if instances.is_empty() {
+			return;
+		}
+
+		// back to front for alpha blending
+		instances.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+		let instance_buffer = self
+			.instances
+			.chunk(instances.into_iter().map(|(_, instance)| instance))
+			.unwrap();
+
+		let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(scene_depth, self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		let push_constants = vs::ty::PushConstants {
+			view_projection: camera.view_projection,
+			camera_right: [camera.right[0], camera.right[1], camera.right[2], 0.0],
+			camera_up: [camera.up[0], camera.up[1], camera.up[2], 0.0],
+			depth_params: [camera.near, camera.far, self.softness, 0.0],
+		};
+
+		builder
+			.draw(
+				self.pipeline.clone(),
+				dynamic_state,
+				vec![
+					self.quad.clone() as Arc<dyn BufferAccess + Send + Sync>,
+					Arc::new(instance_buffer),
+				],
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
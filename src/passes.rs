@@ -0,0 +1,140 @@
+// user passes recorded at fixed points of the frame, so custom draws and
+// dispatches can be added without forking the renderer. the frame loop calls
+// `Renderer::run_passes` at each stage with the targets and camera of the
+// moment.
+
+use crate::gpu::vulkan::{VulkanEncoder, VulkanTarget, VulkanTexture};
+use crate::viewport::View;
+
+/// Where in the frame a pass is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PassStage {
+	/// Inside the depth pre-pass, only recorded while
+	/// `GraphicsSettings::depth_prepass` is on. Draws opaque geometry with
+	/// depth only pipelines, see `prepass`.
+	DepthPrepass,
+	/// Inside the scene pass before any opaque geometry, eg. a custom sky.
+	BeforeOpaque,
+	/// Inside the scene pass after the opaque geometry, with depth filled in.
+	AfterOpaque,
+	/// After the scene pass ended and before upscaling, outside of any render
+	/// pass, so compute dispatches and copies of the scene are allowed.
+	BeforePost,
+	/// Inside the window pass after the ui, the last thing drawn.
+	AfterUi,
+}
+
+impl PassStage {
+	/// Whether the stage is recorded inside a render pass, where only draws
+	/// are allowed.
+	pub fn in_render_pass(self) -> bool {
+		!matches!(self, PassStage::BeforePost)
+	}
+}
+
+/// What a pass can draw with. The encoder it gets has the stage's viewport
+/// set already.
+pub struct PassContext<'a> {
+	pub stage: PassStage,
+	/// What pipelines drawing in this stage have to be created for with
+	/// `GpuDevice::create_pipeline_for`, `None` outside of a render pass.
+	pub target: Option<VulkanTarget>,
+	/// The camera being drawn, if the frame loop passed one.
+	pub view: Option<&'a View>,
+	/// The scene color, not readable while the scene pass is recorded.
+	pub scene_color: VulkanTexture,
+	/// The scene depth, not readable while the scene pass is recorded.
+	pub scene_depth: VulkanTexture,
+	pub render_dimensions: [u32; 2],
+	pub window_dimensions: [u32; 2],
+}
+
+/// Identifies an added pass, to remove it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassId(u64);
+
+type PassCallback = Box<dyn FnMut(&mut VulkanEncoder, &PassContext)>;
+
+struct Pass {
+	id: PassId,
+	stage: PassStage,
+	order: i32,
+	enabled: bool,
+	callback: PassCallback,
+}
+
+/// The passes added to a renderer, by stage.
+#[derive(Default)]
+pub struct RenderPasses {
+	passes: Vec<Pass>,
+	next_id: u64,
+}
+
+impl RenderPasses {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a pass run at `stage` after the passes already there.
+	pub fn add<F>(&mut self, stage: PassStage, callback: F) -> PassId
+	where
+		F: FnMut(&mut VulkanEncoder, &PassContext) + 'static,
+	{
+		self.add_ordered(stage, 0, callback)
+	}
+
+	/// Adds a pass run at `stage`, passes with a lower `order` go first and
+	/// equal orders in the order they were added.
+	pub fn add_ordered<F>(&mut self, stage: PassStage, order: i32, callback: F) -> PassId
+	where
+		F: FnMut(&mut VulkanEncoder, &PassContext) + 'static,
+	{
+		let id = PassId(self.next_id);
+		self.next_id += 1;
+		let index = self
+			.passes
+			.iter()
+			.position(|pass| (pass.stage, pass.order) > (stage, order))
+			.unwrap_or(self.passes.len());
+		self.passes.insert(
+			index,
+			Pass {
+				id,
+				stage,
+				order,
+				enabled: true,
+				callback: Box::new(callback),
+			},
+		);
+		id
+	}
+
+	pub fn remove(&mut self, id: PassId) -> bool {
+		let count = self.passes.len();
+		self.passes.retain(|pass| pass.id != id);
+		self.passes.len() != count
+	}
+
+	/// Skips a pass without removing it.
+	pub fn set_enabled(&mut self, id: PassId, enabled: bool) {
+		if let Some(pass) = self.passes.iter_mut().find(|pass| pass.id == id) {
+			pass.enabled = enabled;
+		}
+	}
+
+	/// Whether any enabled pass runs at `stage`.
+	pub fn has(&self, stage: PassStage) -> bool {
+		self.passes
+			.iter()
+			.any(|pass| pass.stage == stage && pass.enabled)
+	}
+
+	/// Records the enabled passes of `context.stage`.
+	pub fn run(&mut self, encoder: &mut VulkanEncoder, context: &PassContext) {
+		for pass in self.passes.iter_mut() {
+			if pass.stage == context.stage && pass.enabled {
+				(pass.callback)(encoder, context);
+			}
+		}
+	}
+}
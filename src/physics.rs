@@ -0,0 +1,343 @@
+// rigid body physics through rapier. bodies are linked to scene nodes: the
+// simulation moves the nodes of dynamic bodies and kinematic bodies follow
+// their nodes, so a demo only has to build the bodies and call `update`
+// once a frame.
+//
+// only built with the `physics` feature.
+
+use crate::lines::{LineRenderer, LineStyle, LineWidth};
+use crate::math::{Quat, Vec3, Vec4};
+use crate::scene::{NodeId, Scene};
+
+use rapier3d::na::{Point3, Quaternion, Translation3, UnitQuaternion};
+use rapier3d::prelude::*;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// a slow frame runs at most this many steps and drops the rest of the time
+// rather than falling further behind every frame
+const MAX_STEPS: u32 = 8;
+
+/// A rapier world plus the scene nodes its bodies drive.
+///
+/// The bodies, colliders and joints are public so anything rapier can do
+/// is available; `add_body` and `remove_body` just keep the links in step.
+pub struct Physics {
+	pub gravity: Vec3,
+	pub bodies: RigidBodySet,
+	pub colliders: ColliderSet,
+	pub joints: JointSet,
+	/// `dt` is the fixed step, 1/60 s by default.
+	pub integration_parameters: IntegrationParameters,
+	pipeline: PhysicsPipeline,
+	islands: IslandManager,
+	broad_phase: BroadPhase,
+	narrow_phase: NarrowPhase,
+	ccd_solver: CCDSolver,
+	links: HashMap<RigidBodyHandle, NodeId>,
+	accumulator: f32,
+}
+
+impl Default for Physics {
+	fn default() -> Self {
+		Physics::new()
+	}
+}
+
+impl Physics {
+	pub fn new() -> Self {
+		Physics {
+			gravity: [0.0, -9.81, 0.0],
+			bodies: RigidBodySet::new(),
+			colliders: ColliderSet::new(),
+			joints: JointSet::new(),
+			integration_parameters: IntegrationParameters::default(),
+			pipeline: PhysicsPipeline::new(),
+			islands: IslandManager::new(),
+			broad_phase: BroadPhase::new(),
+			narrow_phase: NarrowPhase::new(),
+			ccd_solver: CCDSolver::new(),
+			links: HashMap::new(),
+			accumulator: 0.0,
+		}
+	}
+
+	/// Adds a body with its colliders. With a `node` the body starts where the
+	/// node is and stays linked to it.
+	pub fn add_body(
+		&mut self,
+		scene: &Scene,
+		mut body: RigidBody,
+		colliders: Vec<Collider>,
+		node: Option<NodeId>,
+	) -> RigidBodyHandle {
+		if let Some(node) = node.and_then(|id| scene.get(id)) {
+			body.set_position(
+				isometry(node.transform.translation, node.transform.rotation),
+				true,
+			);
+		}
+		let handle = self.bodies.insert(body);
+		for collider in colliders {
+			self.colliders
+				.insert_with_parent(collider, handle, &mut self.bodies);
+		}
+		if let Some(node) = node {
+			self.links.insert(handle, node);
+		}
+		handle
+	}
+
+	/// Removes a body and its colliders. The node it was linked to stays.
+	pub fn remove_body(&mut self, handle: RigidBodyHandle) {
+		self.links.remove(&handle);
+		self.bodies.remove(
+			handle,
+			&mut self.islands,
+			&mut self.colliders,
+			&mut self.joints,
+		);
+	}
+
+	/// Links an existing body to a node, replacing any node it had.
+	pub fn link(&mut self, handle: RigidBodyHandle, node: NodeId) {
+		self.links.insert(handle, node);
+	}
+
+	pub fn unlink(&mut self, handle: RigidBodyHandle) {
+		self.links.remove(&handle);
+	}
+
+	pub fn linked_node(&self, handle: RigidBodyHandle) -> Option<NodeId> {
+		self.links.get(&handle).copied()
+	}
+
+	/// Advances the simulation by `dt` seconds in fixed steps and syncs the
+	/// linked nodes.
+	///
+	/// Kinematic bodies are moved to their node's transform first, then the
+	/// transforms of dynamic bodies are written back to their nodes. Bodies
+	/// drive the node's local transform, so linked nodes should be roots or
+	/// children of nodes that don't move. Links to removed nodes are dropped.
+	pub fn update(&mut self, scene: &mut Scene, dt: f32) {
+		self.links.retain(|_, node| scene.get(*node).is_some());
+
+		for (&handle, &node) in self.links.iter() {
+			let body = match self.bodies.get_mut(handle) {
+				Some(body) => body,
+				None => continue,
+			};
+			if body.is_kinematic() {
+				let transform = &scene.get(node).unwrap().transform;
+				body.set_next_kinematic_position(isometry(
+					transform.translation,
+					transform.rotation,
+				));
+			}
+		}
+
+		let step = self.integration_parameters.dt;
+		self.accumulator += dt;
+		let mut steps = 0;
+		while self.accumulator >= step {
+			if steps == MAX_STEPS {
+				self.accumulator = 0.0;
+				break;
+			}
+			self.step();
+			self.accumulator -= step;
+			steps += 1;
+		}
+
+		for (&handle, &node) in self.links.iter() {
+			let body = match self.bodies.get(handle) {
+				Some(body) => body,
+				None => continue,
+			};
+			if !body.is_dynamic() {
+				continue;
+			}
+			let (translation, rotation) = from_isometry(body.position());
+			let transform = &mut scene.get_mut(node).unwrap().transform;
+			transform.translation = translation;
+			transform.rotation = rotation;
+		}
+	}
+
+	/// Runs one fixed step without touching the scene.
+	pub fn step(&mut self) {
+		let gravity = vector![self.gravity[0], self.gravity[1], self.gravity[2]];
+		self.pipeline.step(
+			&gravity,
+			&self.integration_parameters,
+			&mut self.islands,
+			&mut self.broad_phase,
+			&mut self.narrow_phase,
+			&mut self.bodies,
+			&mut self.colliders,
+			&mut self.joints,
+			&mut self.ccd_solver,
+			&(),
+			&(),
+		);
+	}
+
+	/// Queues wireframes of every collider. Awake dynamic bodies are green,
+	/// sleeping ones grey and static or kinematic ones blue. Shapes without a
+	/// wireframe of their own are drawn as their bounding box in orange.
+	pub fn debug_draw(&self, lines: &mut LineRenderer) {
+		for (_, collider) in self.colliders.iter() {
+			let color = match collider.parent().and_then(|parent| self.bodies.get(parent)) {
+				Some(body) if body.is_dynamic() && body.is_sleeping() => [0.5, 0.5, 0.5, 1.0],
+				Some(body) if body.is_dynamic() => [0.2, 1.0, 0.3, 1.0],
+				_ => [0.3, 0.6, 1.0, 1.0],
+			};
+			let style = debug_style(color);
+			let position = collider.position();
+			let shape = collider.shape();
+			let at = |point: Vec3| {
+				let point = position * Point3::new(point[0], point[1], point[2]);
+				[point.x, point.y, point.z]
+			};
+
+			if let Some(ball) = shape.as_ball() {
+				for axis in 0..3 {
+					lines.queue_loop(&circle(ball.radius, 0.0, axis, &at), &style);
+				}
+			} else if let Some(cuboid) = shape.as_cuboid() {
+				let h = cuboid.half_extents;
+				wire_box(lines, [-h.x, -h.y, -h.z], [h.x, h.y, h.z], &at, &style);
+			} else if let Some(cylinder) = shape.as_cylinder() {
+				let (r, h) = (cylinder.radius, cylinder.half_height);
+				for y in [-h, h] {
+					lines.queue_loop(&circle(r, y, 1, &at), &style);
+				}
+				for [x, z] in [[r, 0.0], [-r, 0.0], [0.0, r], [0.0, -r]] {
+					lines.queue(&[at([x, -h, z]), at([x, h, z])], &style);
+				}
+			} else if let Some(capsule) = shape.as_capsule() {
+				// drawn along y in its own frame, then placed along the segment
+				let (a, b) = (capsule.segment.a, capsule.segment.b);
+				let r = capsule.radius;
+				let axis = b - a;
+				let h = axis.norm() * 0.5;
+				let rotation = UnitQuaternion::rotation_between(&Vector::y(), &axis)
+					.unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector::x_axis(), PI));
+				let center = Translation3::from((a.coords + b.coords) * 0.5);
+				let local = Isometry::from_parts(center, rotation);
+				let at = |point: Vec3| {
+					at(from_point(
+						local * Point3::new(point[0], point[1], point[2]),
+					))
+				};
+				for y in [-h, h] {
+					lines.queue_loop(&circle(r, y, 1, &at), &style);
+				}
+				for [x, z] in [[r, 0.0], [-r, 0.0], [0.0, r], [0.0, -r]] {
+					lines.queue(&[at([x, -h, z]), at([x, h, z])], &style);
+				}
+				for (sign, y) in [(1.0, h), (-1.0, -h)] {
+					for plane in 0..2 {
+						let arc: Vec<Vec3> = (0..=8)
+							.map(|i| {
+								let angle = i as f32 / 8.0 * PI;
+								let (across, up) = (r * angle.cos(), sign * r * angle.sin());
+								if plane == 0 {
+									at([across, y + up, 0.0])
+								} else {
+									at([0.0, y + up, across])
+								}
+							})
+							.collect();
+						lines.queue(&arc, &style);
+					}
+				}
+			} else {
+				let aabb = collider.compute_aabb();
+				wire_box(
+					lines,
+					from_point(aabb.mins),
+					from_point(aabb.maxs),
+					&|point| point,
+					&debug_style([1.0, 0.6, 0.2, 1.0]),
+				);
+			}
+		}
+	}
+}
+
+fn debug_style(color: Vec4) -> LineStyle {
+	LineStyle {
+		width: LineWidth::Pixels(1.5),
+		color,
+		..LineStyle::default()
+	}
+}
+
+// circle of `radius` around `axis` (0 x, 1 y, 2 z), `offset` along it
+fn circle(radius: f32, offset: f32, axis: usize, at: &impl Fn(Vec3) -> Vec3) -> Vec<Vec3> {
+	(0..24)
+		.map(|i| {
+			let angle = i as f32 / 24.0 * 2.0 * PI;
+			let (a, b) = (radius * angle.cos(), radius * angle.sin());
+			at(match axis {
+				0 => [offset, a, b],
+				1 => [a, offset, b],
+				_ => [a, b, offset],
+			})
+		})
+		.collect()
+}
+
+fn wire_box(
+	lines: &mut LineRenderer,
+	min: Vec3,
+	max: Vec3,
+	at: &impl Fn(Vec3) -> Vec3,
+	style: &LineStyle,
+) {
+	let corner = |x: bool, y: bool, z: bool| {
+		at([
+			if x { max[0] } else { min[0] },
+			if y { max[1] } else { min[1] },
+			if z { max[2] } else { min[2] },
+		])
+	};
+	for y in [false, true] {
+		lines.queue_loop(
+			&[
+				corner(false, y, false),
+				corner(true, y, false),
+				corner(true, y, true),
+				corner(false, y, true),
+			],
+			style,
+		);
+	}
+	for (x, z) in [(false, false), (true, false), (true, true), (false, true)] {
+		lines.queue(&[corner(x, false, z), corner(x, true, z)], style);
+	}
+}
+
+fn isometry(translation: Vec3, rotation: Quat) -> Isometry<Real> {
+	Isometry::from_parts(
+		Translation3::new(translation[0], translation[1], translation[2]),
+		UnitQuaternion::from_quaternion(Quaternion::new(
+			rotation[3],
+			rotation[0],
+			rotation[1],
+			rotation[2],
+		)),
+	)
+}
+
+fn from_isometry(isometry: &Isometry<Real>) -> (Vec3, Quat) {
+	let t = isometry.translation.vector;
+	let q = isometry.rotation.into_inner().coords;
+	([t.x, t.y, t.z], [q.x, q.y, q.z, q.w])
+}
+
+fn from_point(point: Point3<Real>) -> Vec3 {
+	[point.x, point.y, point.z]
+}
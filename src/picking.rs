@@ -0,0 +1,216 @@
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SubpassContents};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract};
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageUsage};
+
+use std::sync::Arc;
+
+/// Object id written for pixels that are not covered by any draw.
+pub const NO_OBJECT: u32 = 0;
+
+// fragment shader that writes the object id from a push constant.
+// pair this with the vertex shader of whatever you are drawing so skinned
+// geometry lines up, or swap in your own that discards for alpha testing.
+pub mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(push_constant) uniform PushConstants {
+				uint object_id;
+			} push_constants;
+
+			layout(location = 0) out uint f_object_id;
+
+			void main() {
+				f_object_id = push_constants.object_id;
+			}
+		"
+	}
+}
+
+/// Offscreen object-id target used for pixel-accurate picking.
+///
+/// The id pass only needs to be recorded on frames where a pick was requested.
+/// The result is read back asynchronously: `poll` returns `None` until the gpu
+/// has finished the frame that wrote it.
+pub struct PickingTarget {
+	device: Arc<Device>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	ids: Arc<AttachmentImage>,
+	framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+	readback: Arc<CpuAccessibleBuffer<[u32]>>,
+	// pixel we want to read on the next recorded id pass
+	requested: Option<[u32; 2]>,
+	// true once the copy into the readback buffer has been recorded
+	in_flight: bool,
+}
+
+impl PickingTarget {
+	pub fn new(device: Arc<Device>, dimensions: [u32; 2]) -> Self {
+		let render_pass = Arc::new(
+			vulkano::single_pass_renderpass!(
+				device.clone(),
+				attachments: {
+					object_id: {
+						load: Clear,
+						store: Store,
+						format: Format::R32Uint,
+						samples: 1,
+					},
+					depth: {
+						load: Clear,
+						store: DontCare,
+						format: Format::D16Unorm,
+						samples: 1,
+					}
+				},
+				pass: {
+					color: [object_id],
+					depth_stencil: {depth}
+				}
+			)
+			.unwrap(),
+		) as Arc<dyn RenderPassAbstract + Send + Sync>;
+
+		let (ids, framebuffer) =
+			create_framebuffer(device.clone(), render_pass.clone(), dimensions);
+
+		let readback = CpuAccessibleBuffer::from_iter(
+			device.clone(),
+			BufferUsage::transfer_destination(),
+			true,
+			std::iter::once(NO_OBJECT),
+		)
+		.unwrap();
+
+		PickingTarget {
+			device,
+			render_pass,
+			ids,
+			framebuffer,
+			readback,
+			requested: None,
+			in_flight: false,
+		}
+	}
+
+	/// Render pass that id pipelines have to be built against.
+	pub fn render_pass(&self) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+		self.render_pass.clone()
+	}
+
+	/// Recreates the target to match the new swapchain size. Drops a pending
+	/// request, its pixel may be outside the new target.
+	pub fn resize(&mut self, dimensions: [u32; 2]) {
+		let (ids, framebuffer) =
+			create_framebuffer(self.device.clone(), self.render_pass.clone(), dimensions);
+		self.ids = ids;
+		self.framebuffer = framebuffer;
+		self.requested = None;
+	}
+
+	/// Asks for the object id under the given pixel (in physical window coordinates).
+	pub fn request(&mut self, position: [u32; 2]) {
+		let dimensions = self.ids.dimensions();
+		if dimensions[0] == 0 || dimensions[1] == 0 {
+			return;
+		}
+		self.requested = Some([
+			position[0].min(dimensions[0] - 1),
+			position[1].min(dimensions[1] - 1),
+		]);
+	}
+
+	/// True when the id pass should be recorded this frame.
+	pub fn is_requested(&self) -> bool {
+		self.requested.is_some()
+	}
+
+	/// Begins the id pass. Draw every pickable object with an id pipeline after this.
+	pub fn begin(&self, builder: &mut AutoCommandBufferBuilder) {
+		builder
+			.begin_render_pass(
+				self.framebuffer.clone(),
+				SubpassContents::Inline,
+				vec![[NO_OBJECT; 4].into(), 1f32.into()],
+			)
+			.unwrap();
+	}
+
+	/// Ends the id pass and copies the requested pixel into the readback buffer.
+	pub fn end(&mut self, builder: &mut AutoCommandBufferBuilder) {
+		builder.end_render_pass().unwrap();
+
+		if let Some([x, y]) = self.requested.take() {
+			builder
+				.copy_image_to_buffer_dimensions(
+					self.ids.clone(),
+					self.readback.clone(),
+					[x, y, 0],
+					[1, 1, 1],
+					0,
+					1,
+					0,
+				)
+				.unwrap();
+			self.in_flight = true;
+		}
+	}
+
+	/// Returns the picked object id once the gpu is done with it.
+	///
+	/// The buffer stays locked by the gpu until the frame's future has been cleaned up,
+	/// so this never blocks.
+	pub fn poll(&mut self) -> Option<u32> {
+		if !self.in_flight {
+			return None;
+		}
+
+		match self.readback.read() {
+			Ok(data) => {
+				self.in_flight = false;
+				Some(data[0])
+			}
+			Err(_) => None,
+		}
+	}
+}
+
+fn create_framebuffer(
+	device: Arc<Device>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	dimensions: [u32; 2],
+) -> (
+	Arc<AttachmentImage>,
+	Arc<dyn FramebufferAbstract + Send + Sync>,
+) {
+	let ids = AttachmentImage::with_usage(
+		device.clone(),
+		dimensions,
+		Format::R32Uint,
+		ImageUsage {
+			transfer_source: true,
+			..ImageUsage::none()
+		},
+	)
+	.unwrap();
+
+	let depth = AttachmentImage::transient(device, dimensions, Format::D16Unorm).unwrap();
+
+	let framebuffer = Arc::new(
+		Framebuffer::start(render_pass)
+			.add(ImageView::new(ids.clone()).unwrap())
+			.unwrap()
+			.add(ImageView::new(depth).unwrap())
+			.unwrap()
+			.build()
+			.unwrap(),
+	) as Arc<dyn FramebufferAbstract + Send + Sync>;
+
+	(ids, framebuffer)
+}
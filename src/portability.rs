@@ -0,0 +1,105 @@
+// devices running on top of another api through a portability layer, eg.
+// moltenvk on macos and ios. they expose `VK_KHR_portability_subset`, which
+// has to be enabled whenever it's there and needs
+// `VK_KHR_get_physical_device_properties2` on the instance.
+//
+// vulkan loaders from 1.3.216 on only list portability drivers to instances
+// created with `VK_KHR_portability_enumeration` and its create flag. vulkano
+// 0.22 can't pass instance create flags, so when the loader lists no device
+// `create_instance` loads moltenvk directly instead of going through it.
+
+use crate::capabilities::supports_extension;
+
+use vulkano::device::DeviceExtensions;
+use vulkano::instance::loader::{DynamicLibraryLoader, FunctionPointers};
+use vulkano::instance::{Instance, InstanceCreationError, InstanceExtensions, PhysicalDevice};
+
+use std::sync::Arc;
+
+/// Device extension portability devices have.
+pub const PORTABILITY_SUBSET: &str = "VK_KHR_portability_subset";
+
+/// Where `create_instance` looks for moltenvk, relative ones next to the
+/// executable and inside an app bundle's frameworks.
+pub const MOLTENVK_PATHS: &[&str] = &[
+	"libMoltenVK.dylib",
+	"../Frameworks/libMoltenVK.dylib",
+	"/usr/local/lib/libMoltenVK.dylib",
+	"/opt/homebrew/lib/libMoltenVK.dylib",
+];
+
+/// Whether the device only implements part of vulkan on top of another api.
+/// Triangle fans, point polygon mode, events and a few sampler and format
+/// features may be missing on it.
+pub fn is_portability(physical: PhysicalDevice) -> bool {
+	supports_extension(physical, PORTABILITY_SUBSET)
+}
+
+/// `extensions` plus what portability devices need from the instance, when
+/// the loader has it.
+pub fn instance_extensions(extensions: InstanceExtensions) -> InstanceExtensions {
+	let supported = InstanceExtensions::supported_by_core()
+		.map(|supported| supported.khr_get_physical_device_properties2)
+		.unwrap_or(false);
+	InstanceExtensions {
+		khr_get_physical_device_properties2: extensions.khr_get_physical_device_properties2
+			|| supported,
+		..extensions
+	}
+}
+
+/// `extensions` plus `VK_KHR_portability_subset` on devices that have it,
+/// creating a device without it there isn't allowed.
+pub fn device_extensions(
+	physical: PhysicalDevice,
+	extensions: DeviceExtensions,
+) -> DeviceExtensions {
+	extensions.union(&DeviceExtensions::required_extensions(physical))
+}
+
+/// Creates the instance through the vulkan loader, or straight from moltenvk
+/// on apple platforms when the loader doesn't list any device.
+pub fn create_instance(
+	extensions: InstanceExtensions,
+	layers: Option<&str>,
+) -> Result<Arc<Instance>, InstanceCreationError> {
+	let extensions = instance_extensions(extensions);
+	let instance = Instance::new(None, &extensions, layers);
+	if let Ok(instance) = &instance {
+		if PhysicalDevice::enumerate(instance).next().is_some() {
+			return Ok(instance.clone());
+		}
+	}
+	if cfg!(any(target_os = "macos", target_os = "ios")) {
+		if let Some(moltenvk) = moltenvk_instance(extensions, layers) {
+			return Ok(moltenvk);
+		}
+	}
+	instance
+}
+
+fn moltenvk_instance(
+	extensions: InstanceExtensions,
+	layers: Option<&str>,
+) -> Option<Arc<Instance>> {
+	let exe_dir = std::env::current_exe()
+		.ok()
+		.and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))?;
+	MOLTENVK_PATHS.iter().find_map(|path| {
+		let path = exe_dir.join(path);
+		if !path.is_file() {
+			return None;
+		}
+		// the library stays loaded for as long as the instance lives
+		let loader = unsafe { DynamicLibraryLoader::new(&path) }.ok()?;
+		let instance = Instance::with_loader(
+			FunctionPointers::new(Box::new(loader)),
+			None,
+			&extensions,
+			layers,
+		)
+		.ok()?;
+		log::info!("Using {} directly", path.display());
+		Some(instance)
+	})
+}
@@ -0,0 +1,341 @@
+// screen space effects run on the scene between the scene pass and
+// upscaling. each effect draws a fullscreen triangle into its own target and
+// the next one reads it, targets come from `TransientTargets` so the chain
+// only ever holds two images per format.
+//
+// cameras drawing into textures run the chain over their own target, with a
+// pool of transient targets each, so their output survives the chain running
+// for the next camera.
+
+use crate::fullscreen;
+use crate::render2d::Texture;
+use crate::render_target::RenderTarget;
+use crate::resolution::{DynamicResolution, SCENE_FORMAT};
+use crate::scene::{Camera, CameraTarget};
+use crate::transient::{TransientDesc, TransientTargets};
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageView;
+use vulkano::sampler::Sampler;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Extra inputs an effect reads besides the previous effect's color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PostInputs {
+	/// The scene depth. Multisampled when the scene is.
+	pub depth: bool,
+}
+
+/// What an effect records its pass with.
+pub struct PostContext<'a> {
+	/// Output of the previous effect, or the scene for the first one.
+	pub input: Texture,
+	/// Only there when the effect's `inputs` ask for it.
+	pub depth: Option<Texture>,
+	/// Linear without mipmaps.
+	pub sampler: Arc<Sampler>,
+	/// Viewport covering the rendered part of the targets.
+	pub dynamic_state: &'a DynamicState,
+	/// Only the top left `render_dimensions` of the targets hold the frame,
+	/// see `DynamicResolution`.
+	pub render_dimensions: [u32; 2],
+	pub target_dimensions: [u32; 2],
+	/// Multiplies the scene, the camera's own or `PostChain::exposure`.
+	/// Effects that tonemap apply it.
+	pub exposure: f32,
+}
+
+impl PostContext<'_> {
+	/// Multiply the fullscreen triangle's uvs by this to sample the rendered
+	/// part of `input`.
+	pub fn uv_scale(&self) -> [f32; 2] {
+		[
+			self.render_dimensions[0] as f32 / self.target_dimensions[0] as f32,
+			self.render_dimensions[1] as f32 / self.target_dimensions[1] as f32,
+		]
+	}
+}
+
+/// A screen space effect in a `PostChain`.
+///
+/// The chain builds the effect against a subpass with one color attachment
+/// of `output_format`, then every frame records it inside that pass with the
+/// previous output as `PostContext::input`. `fullscreen::FullscreenPass`
+/// covers the pipeline for most effects.
+pub trait PostEffect {
+	/// Identifies the effect in the chain.
+	fn name(&self) -> &str;
+
+	/// Effects with a lower order run first, equal orders in the order they
+	/// were added.
+	fn order(&self) -> i32 {
+		0
+	}
+
+	/// Disabled effects are skipped and their input passed on.
+	fn enabled(&self) -> bool {
+		true
+	}
+
+	fn inputs(&self) -> PostInputs {
+		PostInputs::default()
+	}
+
+	fn output_format(&self) -> Format {
+		SCENE_FORMAT
+	}
+
+	/// Builds the effect's pipelines, called once when the effect is added.
+	fn build(
+		&mut self,
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	);
+
+	/// Draws the effect, usually a single fullscreen triangle.
+	fn record(&mut self, builder: &mut AutoCommandBufferBuilder, context: &PostContext);
+}
+
+/// Post effects run in order over the scene.
+///
+/// Record `run` between `DynamicResolution::end` and `upscale_from`, and
+/// composite its result with `composite_from`. Cameras with their own post
+/// settings use `run_for`, or `run_target` when they draw into a texture.
+pub struct PostChain {
+	device: Arc<Device>,
+	effects: Vec<Box<dyn PostEffect>>,
+	// one single attachment pass per output format
+	render_passes: HashMap<Format, Arc<dyn RenderPassAbstract + Send + Sync>>,
+	targets: TransientTargets,
+	// by the name of the camera target
+	camera_targets: HashMap<String, TransientTargets>,
+	sampler: Arc<Sampler>,
+	exposure: f32,
+}
+
+// what the chain runs over
+struct PostSource<'a> {
+	color: Texture,
+	depth: Texture,
+	dynamic_state: &'a DynamicState,
+	render_dimensions: [u32; 2],
+	target_dimensions: [u32; 2],
+}
+
+impl PostChain {
+	pub fn new(device: Arc<Device>) -> Self {
+		PostChain {
+			targets: TransientTargets::new(device.clone()),
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+			device,
+			effects: Vec::new(),
+			render_passes: HashMap::new(),
+			camera_targets: HashMap::new(),
+			exposure: 1.0,
+		}
+	}
+
+	pub fn exposure(&self) -> f32 {
+		self.exposure
+	}
+
+	/// Exposure of cameras without their own, 1 by default.
+	pub fn set_exposure(&mut self, exposure: f32) {
+		self.exposure = exposure;
+	}
+
+	/// Builds `effect` and inserts it by its `order`.
+	pub fn add(&mut self, mut effect: Box<dyn PostEffect>) {
+		let render_pass = self.render_pass(effect.output_format());
+		effect.build(self.device.clone(), Subpass::from(render_pass, 0).unwrap());
+		let order = effect.order();
+		let index = self
+			.effects
+			.iter()
+			.position(|other| other.order() > order)
+			.unwrap_or(self.effects.len());
+		self.effects.insert(index, effect);
+	}
+
+	pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+		let index = self
+			.effects
+			.iter()
+			.position(|effect| effect.name() == name)?;
+		Some(self.effects.remove(index))
+	}
+
+	pub fn get_mut(&mut self, name: &str) -> Option<&mut (dyn PostEffect + 'static)> {
+		self.effects
+			.iter_mut()
+			.find(|effect| effect.name() == name)
+			.map(|effect| effect.as_mut())
+	}
+
+	/// Names of the effects in the order they run.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.effects.iter().map(|effect| effect.name())
+	}
+
+	pub fn len(&self) -> usize {
+		self.effects.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.effects.is_empty()
+	}
+
+	/// Runs the enabled effects over the scene and returns the last output,
+	/// the scene itself when none are enabled. Must be recorded outside of a
+	/// render pass.
+	pub fn run(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		resolution: &DynamicResolution,
+	) -> Texture {
+		self.run_for(builder, resolution, &Camera::default())
+	}
+
+	/// Like `run`, with the exposure and effects of `camera`.
+	pub fn run_for(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		resolution: &DynamicResolution,
+		camera: &Camera,
+	) -> Texture {
+		let source = PostSource {
+			color: resolution.texture(),
+			depth: resolution.depth_texture(),
+			dynamic_state: resolution.dynamic_state(),
+			render_dimensions: resolution.render_dimensions(),
+			target_dimensions: resolution.target_dimensions(),
+		};
+		self.record(builder, source, None, camera)
+	}
+
+	/// Runs the effects `camera` allows over the offscreen `target` it drew
+	/// into, see `CameraTargets`. The output is kept until the chain runs for
+	/// the same camera target again.
+	pub fn run_target(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		target: &RenderTarget,
+		camera: &Camera,
+	) -> Texture {
+		let pool = match &camera.target {
+			CameraTarget::Texture { name, .. } => Some(name.as_str()),
+			CameraTarget::Screen => None,
+		};
+		let source = PostSource {
+			color: target.texture(),
+			depth: target.depth_texture(),
+			dynamic_state: target.dynamic_state(),
+			render_dimensions: target.dimensions(),
+			target_dimensions: target.dimensions(),
+		};
+		self.record(builder, source, pool, camera)
+	}
+
+	fn record(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		source: PostSource,
+		pool: Option<&str>,
+		camera: &Camera,
+	) -> Texture {
+		let target_dimensions = source.target_dimensions;
+		let enabled: Vec<usize> = (0..self.effects.len())
+			.filter(|&index| {
+				let effect = &self.effects[index];
+				effect.enabled() && camera.post.allows(effect.name())
+			})
+			.collect();
+		let formats: Vec<Format> = enabled
+			.iter()
+			.map(|&index| self.effects[index].output_format())
+			.collect();
+
+		let targets = match pool {
+			Some(name) => {
+				let device = self.device.clone();
+				self.camera_targets
+					.entry(name.to_string())
+					.or_insert_with(|| TransientTargets::new(device))
+			}
+			None => &mut self.targets,
+		};
+		// each output lives from its own pass to the next effect's
+		targets.begin_frame();
+		let outputs: Vec<_> = formats
+			.iter()
+			.enumerate()
+			.map(|(pass, &format)| {
+				targets.declare(
+					TransientDesc::new(target_dimensions, format),
+					pass..=pass + 1,
+				)
+			})
+			.collect();
+		targets.allocate();
+
+		let mut input = source.color;
+		for ((&index, &output), &format) in enabled.iter().zip(&outputs).zip(&formats) {
+			let render_pass = render_pass(&mut self.render_passes, &self.device, format);
+			let framebuffer = Arc::new(
+				Framebuffer::start(render_pass)
+					.add(ImageView::new(targets.image(output)).unwrap())
+					.unwrap()
+					.build()
+					.unwrap(),
+			);
+			let effect = &mut self.effects[index];
+			let context = PostContext {
+				input: input.clone(),
+				depth: match effect.inputs().depth {
+					true => Some(source.depth.clone()),
+					false => None,
+				},
+				sampler: self.sampler.clone(),
+				dynamic_state: source.dynamic_state,
+				render_dimensions: source.render_dimensions,
+				target_dimensions,
+				exposure: camera.exposure.unwrap_or(self.exposure),
+			};
+
+			builder
+				.begin_render_pass(framebuffer, SubpassContents::Inline, vec![ClearValue::None])
+				.unwrap();
+			effect.record(builder, &context);
+			builder.end_render_pass().unwrap();
+
+			input = targets.texture(output);
+		}
+		input
+	}
+
+	/// Drops the targets kept for the camera target `name`, eg. once the
+	/// camera is gone.
+	pub fn forget_camera_target(&mut self, name: &str) {
+		self.camera_targets.remove(name);
+	}
+
+	fn render_pass(&mut self, format: Format) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+		render_pass(&mut self.render_passes, &self.device, format)
+	}
+}
+
+fn render_pass(
+	render_passes: &mut HashMap<Format, Arc<dyn RenderPassAbstract + Send + Sync>>,
+	device: &Arc<Device>,
+	format: Format,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	render_passes
+		.entry(format)
+		.or_insert_with(|| fullscreen::render_pass(device.clone(), format))
+		.clone()
+}
@@ -0,0 +1,52 @@
+// the depth pre-pass: opaque geometry drawn depth only before the scene pass,
+// which then tests against that depth without writing it, so every pixel runs
+// the expensive lit fragment shader once at most. worth it in scenes with a
+// lot of overdraw, otherwise it just draws the geometry twice.
+//
+// the pre-pass draws with the shadow permutation of each material's shader
+// and into a depth only pass like shadow maps, so on single sampled targets
+// the pipelines shadow maps are drawn with work as they are. with msaa they
+// have to be built again against `DynamicResolution::depth_prepass_subpass`.
+
+use crate::gpu::{CompareOp, DepthState};
+use crate::shader::{Feature, ShaderFeatures};
+
+use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
+
+/// The permutation to draw a material with `features` in the pre-pass, the
+/// one shadow maps use. Only what changes the depth is kept, skinning and
+/// alpha testing.
+pub fn features(features: ShaderFeatures) -> ShaderFeatures {
+	let mut depth_only = ShaderFeatures::new().with(Feature::ShadowPass);
+	for feature in [Feature::Skinned, Feature::AlphaTest] {
+		depth_only.set(feature, features.contains(feature));
+	}
+	depth_only
+}
+
+/// Depth test of opaque pipelines drawing in the scene pass. After a
+/// pre-pass the depth is already final, so it isn't written again and only
+/// the nearest surface passes.
+pub fn opaque_depth(prepass: bool) -> DepthStencil {
+	if prepass {
+		DepthStencil {
+			depth_compare: Compare::LessOrEqual,
+			depth_write: false,
+			..DepthStencil::simple_depth_test()
+		}
+	} else {
+		DepthStencil::simple_depth_test()
+	}
+}
+
+/// `opaque_depth` for pipelines created through `gpu::GpuDevice`.
+pub fn opaque_depth_state(prepass: bool) -> DepthState {
+	if prepass {
+		DepthState {
+			compare: CompareOp::LessEqual,
+			write: false,
+		}
+	} else {
+		DepthState::default()
+	}
+}
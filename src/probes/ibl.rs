@@ -0,0 +1,501 @@
+// image based lighting preprocessing: the ggx prefilter and irradiance
+// convolution of an environment cubemap and the brdf lut of the split sum
+// approximation. reflection probes use the same passes on their captures.
+//
+// the inputs can be any cubemap, eg. one from `EquirectToCube` or a baked
+// `Sky`, so environments can be baked at load time or ahead of time.
+
+use super::PROBE_FORMAT;
+use crate::compute::group_counts;
+use crate::render2d::Texture;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use std::sync::Arc;
+
+/// Format of the brdf lut, scale in red and bias in green.
+pub const BRDF_LUT_FORMAT: Format = Format::R16G16Sfloat;
+
+// convolves the environment with the ggx lobe of one roughness into one
+// level of the prefiltered cube array.
+mod prefilter {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+			layout(set = 0, binding = 0) uniform samplerCube environment;
+			layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray level;
+
+			layout(push_constant) uniform PushConstants {
+				float roughness;
+				uint size;
+				uint sample_count;
+			} pc;
+
+			// direction through the texel, same face layout as vulkan cube sampling
+			vec3 cube_direction(uint face, vec2 uv) {
+				switch (face) {
+					case 0u: return vec3(1.0, -uv.y, -uv.x);
+					case 1u: return vec3(-1.0, -uv.y, uv.x);
+					case 2u: return vec3(uv.x, 1.0, uv.y);
+					case 3u: return vec3(uv.x, -1.0, -uv.y);
+					case 4u: return vec3(uv.x, -uv.y, 1.0);
+					default: return vec3(-uv.x, -uv.y, -1.0);
+				}
+			}
+
+			vec2 hammersley(uint i, uint count) {
+				uint bits = bitfieldReverse(i);
+				return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+			}
+
+			vec3 importance_sample_ggx(vec2 xi, vec3 n, float alpha) {
+				float phi = 6.2831853 * xi.x;
+				float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+				float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+				vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+				vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+				vec3 tangent = normalize(cross(up, n));
+				vec3 bitangent = cross(n, tangent);
+				return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+			}
+
+			void main() {
+				uvec3 id = gl_GlobalInvocationID;
+				if (id.x >= pc.size || id.y >= pc.size) {
+					return;
+				}
+
+				vec2 uv = (vec2(id.xy) + 0.5) / float(pc.size) * 2.0 - 1.0;
+				vec3 n = normalize(cube_direction(id.z, uv));
+
+				if (pc.roughness == 0.0) {
+					imageStore(level, ivec3(id), vec4(textureLod(environment, n, 0.0).rgb, 1.0));
+					return;
+				}
+
+				// assumes the view direction is the normal, like most split sum prefilters
+				float alpha = pc.roughness * pc.roughness;
+				vec3 color = vec3(0.0);
+				float weight = 0.0;
+				for (uint i = 0; i < pc.sample_count; i++) {
+					vec3 h = importance_sample_ggx(hammersley(i, pc.sample_count), n, alpha);
+					vec3 l = normalize(2.0 * dot(n, h) * h - n);
+					float n_dot_l = dot(n, l);
+					if (n_dot_l > 0.0) {
+						color += textureLod(environment, l, 0.0).rgb * n_dot_l;
+						weight += n_dot_l;
+					}
+				}
+
+				imageStore(level, ivec3(id), vec4(color / max(weight, 0.0001), 1.0));
+			}
+		"
+	}
+}
+
+// cosine weighted average of the environment over the hemisphere around
+// each texel's direction, the radiance a white lambertian surface reflects.
+mod irradiance {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+			layout(set = 0, binding = 0) uniform samplerCube environment;
+			layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray faces;
+
+			layout(push_constant) uniform PushConstants {
+				uint size;
+				uint sample_count;
+			} pc;
+
+			vec3 cube_direction(uint face, vec2 uv) {
+				switch (face) {
+					case 0u: return vec3(1.0, -uv.y, -uv.x);
+					case 1u: return vec3(-1.0, -uv.y, uv.x);
+					case 2u: return vec3(uv.x, 1.0, uv.y);
+					case 3u: return vec3(uv.x, -1.0, -uv.y);
+					case 4u: return vec3(uv.x, -uv.y, 1.0);
+					default: return vec3(-uv.x, -uv.y, -1.0);
+				}
+			}
+
+			vec2 hammersley(uint i, uint count) {
+				uint bits = bitfieldReverse(i);
+				return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+			}
+
+			void main() {
+				uvec3 id = gl_GlobalInvocationID;
+				if (id.x >= pc.size || id.y >= pc.size) {
+					return;
+				}
+
+				vec2 uv = (vec2(id.xy) + 0.5) / float(pc.size) * 2.0 - 1.0;
+				vec3 n = normalize(cube_direction(id.z, uv));
+				vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+				vec3 tangent = normalize(cross(up, n));
+				vec3 bitangent = cross(n, tangent);
+
+				// cosine distributed samples, the pdf cancels the cosine term
+				vec3 color = vec3(0.0);
+				for (uint i = 0; i < pc.sample_count; i++) {
+					vec2 xi = hammersley(i, pc.sample_count);
+					float phi = 6.2831853 * xi.x;
+					float sin_theta = sqrt(xi.y);
+					vec3 l = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, sqrt(1.0 - xi.y));
+					l = tangent * l.x + bitangent * l.y + n * l.z;
+					color += textureLod(environment, l, 0.0).rgb;
+				}
+
+				imageStore(faces, ivec3(id), vec4(color / float(pc.sample_count), 1.0));
+			}
+		"
+	}
+}
+
+// scale and bias applied to f0 by the specular term of the split sum, x is
+// n dot v and y is roughness.
+mod brdf_lut {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+			layout(set = 0, binding = 0, rg16f) uniform writeonly image2D lut;
+
+			layout(push_constant) uniform PushConstants {
+				uint size;
+				uint sample_count;
+			} pc;
+
+			vec2 hammersley(uint i, uint count) {
+				uint bits = bitfieldReverse(i);
+				return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+			}
+
+			// smith ggx with the k used for image based lighting
+			float geometry_smith(float n_dot_v, float n_dot_l, float alpha) {
+				float k = alpha / 2.0;
+				float v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+				float l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+				return v * l;
+			}
+
+			void main() {
+				uvec2 id = gl_GlobalInvocationID.xy;
+				if (id.x >= pc.size || id.y >= pc.size) {
+					return;
+				}
+
+				float n_dot_v = max((float(id.x) + 0.5) / float(pc.size), 0.001);
+				float roughness = (float(id.y) + 0.5) / float(pc.size);
+				float alpha = roughness * roughness;
+				vec3 v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+				vec2 result = vec2(0.0);
+				for (uint i = 0; i < pc.sample_count; i++) {
+					vec2 xi = hammersley(i, pc.sample_count);
+					float phi = 6.2831853 * xi.x;
+					float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+					float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+					vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+					vec3 l = normalize(2.0 * dot(v, h) * h - v);
+
+					float n_dot_l = max(l.z, 0.0);
+					float n_dot_h = max(h.z, 0.0);
+					float v_dot_h = max(dot(v, h), 0.0);
+					if (n_dot_l > 0.0) {
+						float g = geometry_smith(n_dot_v, n_dot_l, alpha);
+						float visibility = g * v_dot_h / (n_dot_h * n_dot_v);
+						float fresnel = pow(1.0 - v_dot_h, 5.0);
+						result += vec2((1.0 - fresnel) * visibility, fresnel * visibility);
+					}
+				}
+
+				imageStore(lut, ivec2(id), vec4(result / float(pc.sample_count), 0.0, 0.0));
+			}
+		"
+	}
+}
+
+/// A prefiltered environment, one cube per roughness level in a cube array.
+pub struct PrefilteredEnvironment {
+	/// The levels as a `samplerCubeArray`, layer 0 is the mirror reflection
+	/// and the last layer fully rough.
+	pub texture: Texture,
+	/// Each level's six faces as an `image2DArray`, the prefilter's targets.
+	pub levels: Vec<Texture>,
+	pub size: u32,
+}
+
+impl PrefilteredEnvironment {
+	/// Allocates the cube array without filling it. `levels` is at least 2.
+	pub fn new(device: Arc<Device>, size: u32, levels: u32) -> Self {
+		assert!(
+			levels >= 2,
+			"a prefiltered environment needs at least 2 levels"
+		);
+		let image = cube_image(device, size, levels);
+		let level_views = (0..levels)
+			.map(|level| {
+				ImageView::with_type_ranges(
+					image.clone(),
+					ImageViewType::Dim2dArray,
+					0..1,
+					level * 6..level * 6 + 6,
+				)
+				.unwrap() as Texture
+			})
+			.collect();
+
+		PrefilteredEnvironment {
+			texture: ImageView::with_type(image, ImageViewType::CubemapArray).unwrap(),
+			levels: level_views,
+			size,
+		}
+	}
+
+	/// Roughness the level is prefiltered for, levels are spread evenly.
+	pub fn roughness(&self, level: usize) -> f32 {
+		level as f32 / (self.levels.len() - 1) as f32
+	}
+}
+
+/// The image based lighting passes. Everything here must be recorded
+/// outside of a render pass, environments are any view usable as a
+/// `samplerCube`. Results are written in `PROBE_FORMAT`.
+///
+/// Shading with the results follows the split sum approximation:
+///
+/// ```glsl
+/// vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+/// vec3 specular = prefiltered * (f0 * brdf.x + brdf.y);
+/// vec3 diffuse = irradiance * base_color * (1.0 - metallic);
+/// ```
+pub struct IblBaker {
+	device: Arc<Device>,
+	prefilter: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	irradiance: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	brdf_lut: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	sampler: Arc<Sampler>,
+	/// Importance samples per texel for the rough prefilter levels.
+	pub prefilter_samples: u32,
+	/// Samples per texel of the irradiance convolution.
+	pub irradiance_samples: u32,
+	/// Samples per texel of the brdf lut.
+	pub brdf_samples: u32,
+}
+
+impl IblBaker {
+	pub fn new(device: Arc<Device>) -> Self {
+		let prefilter = prefilter::Shader::load(device.clone()).unwrap();
+		let irradiance = irradiance::Shader::load(device.clone()).unwrap();
+		let brdf_lut = brdf_lut::Shader::load(device.clone()).unwrap();
+
+		IblBaker {
+			prefilter: Arc::new(
+				ComputePipeline::new(device.clone(), &prefilter.main_entry_point(), &(), None)
+					.unwrap(),
+			),
+			irradiance: Arc::new(
+				ComputePipeline::new(device.clone(), &irradiance.main_entry_point(), &(), None)
+					.unwrap(),
+			),
+			brdf_lut: Arc::new(
+				ComputePipeline::new(device.clone(), &brdf_lut.main_entry_point(), &(), None)
+					.unwrap(),
+			),
+			sampler: Sampler::new(
+				device.clone(),
+				Filter::Linear,
+				Filter::Linear,
+				MipmapMode::Nearest,
+				SamplerAddressMode::ClampToEdge,
+				SamplerAddressMode::ClampToEdge,
+				SamplerAddressMode::ClampToEdge,
+				0.0,
+				1.0,
+				0.0,
+				0.0,
+			)
+			.unwrap(),
+			device,
+			prefilter_samples: 128,
+			irradiance_samples: 512,
+			brdf_samples: 512,
+		}
+	}
+
+	/// Prefilters `environment` into a new cube array with `size` pixel faces
+	/// and `levels` roughness steps.
+	pub fn prefilter(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		environment: Texture,
+		size: u32,
+		levels: u32,
+	) -> PrefilteredEnvironment {
+		let prefiltered = PrefilteredEnvironment::new(self.device.clone(), size, levels);
+		self.prefilter_into(builder, environment, &prefiltered, self.prefilter_samples);
+		prefiltered
+	}
+
+	/// Prefilters every level of an existing `PrefilteredEnvironment`.
+	pub fn prefilter_into(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		environment: Texture,
+		prefiltered: &PrefilteredEnvironment,
+		sample_count: u32,
+	) {
+		let layout = self.prefilter.descriptor_set_layout(0).unwrap();
+		for (level, target) in prefiltered.levels.iter().enumerate() {
+			let set = Arc::new(
+				PersistentDescriptorSet::start(layout.clone())
+					.add_sampled_image(environment.clone(), self.sampler.clone())
+					.unwrap()
+					.add_image(target.clone())
+					.unwrap()
+					.build()
+					.unwrap(),
+			);
+			let push_constants = prefilter::ty::PushConstants {
+				roughness: prefiltered.roughness(level),
+				size: prefiltered.size,
+				sample_count,
+			};
+			builder
+				.dispatch(
+					group_counts([prefiltered.size, prefiltered.size, 6], [8, 8, 1]),
+					self.prefilter.clone(),
+					set,
+					push_constants,
+					vec![],
+				)
+				.unwrap();
+		}
+	}
+
+	/// Convolves `environment` into a new `samplerCube` of diffuse light with
+	/// `size` pixel faces. Irradiance is smooth, 16 to 32 is plenty.
+	pub fn irradiance(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		environment: Texture,
+		size: u32,
+	) -> Texture {
+		let image = cube_image(self.device.clone(), size, 1);
+		let layout = self.irradiance.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(environment, self.sampler.clone())
+				.unwrap()
+				.add_image(ImageView::with_type(image.clone(), ImageViewType::Dim2dArray).unwrap())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		builder
+			.dispatch(
+				group_counts([size, size, 6], [8, 8, 1]),
+				self.irradiance.clone(),
+				set,
+				irradiance::ty::PushConstants {
+					size,
+					sample_count: self.irradiance_samples,
+				},
+				vec![],
+			)
+			.unwrap();
+		ImageView::with_type(image, ImageViewType::Cubemap).unwrap()
+	}
+
+	/// Integrates the brdf lut into a new `size` square texture. It only
+	/// depends on the brdf, so one is enough for every environment.
+	pub fn brdf_lut(&self, builder: &mut AutoCommandBufferBuilder, size: u32) -> Texture {
+		let image = StorageImage::with_usage(
+			self.device.clone(),
+			ImageDimensions::Dim2d {
+				width: size,
+				height: size,
+				array_layers: 1,
+			},
+			BRDF_LUT_FORMAT,
+			ImageUsage {
+				storage: true,
+				sampled: true,
+				transfer_source: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			self.device.active_queue_families(),
+		)
+		.unwrap();
+		let view = ImageView::new(image).unwrap() as Texture;
+
+		let layout = self.brdf_lut.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_image(view.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		builder
+			.dispatch(
+				group_counts([size, size, 1], [8, 8, 1]),
+				self.brdf_lut.clone(),
+				set,
+				brdf_lut::ty::PushConstants {
+					size,
+					sample_count: self.brdf_samples,
+				},
+				vec![],
+			)
+			.unwrap();
+		view
+	}
+}
+
+// cube compatible storage image with `cubes` cubes in its layers
+fn cube_image(device: Arc<Device>, size: u32, cubes: u32) -> Arc<StorageImage<Format>> {
+	StorageImage::with_usage(
+		device.clone(),
+		ImageDimensions::Dim2d {
+			width: size,
+			height: size,
+			array_layers: 6 * cubes,
+		},
+		PROBE_FORMAT,
+		ImageUsage {
+			storage: true,
+			sampled: true,
+			transfer_source: true,
+			..ImageUsage::none()
+		},
+		ImageCreateFlags {
+			cube_compatible: true,
+			..ImageCreateFlags::none()
+		},
+		device.active_queue_families(),
+	)
+	.unwrap()
+}
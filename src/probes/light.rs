@@ -0,0 +1,329 @@
+use super::{CubeCapture, Sh9};
+use crate::math::Vec3;
+use crate::scene::RenderLayers;
+use crate::viewport::View;
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::ClearValue;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sampler::Sampler;
+
+use std::sync::Arc;
+
+// matches `Result` in the projection shader
+type ShCoefficients = [[f32; 4]; 9];
+
+// projects the captured cube onto the 9 sh basis functions in one work group,
+// every texel weighted by the solid angle it covers.
+mod project {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 64) in;
+
+			layout(set = 0, binding = 0) uniform samplerCube environment;
+			layout(set = 0, binding = 1) writeonly buffer Result {
+				vec4 coefficients[9];
+			} result;
+
+			layout(push_constant) uniform PushConstants {
+				uint size;
+			} pc;
+
+			shared vec3 partial[64][9];
+			shared float partial_weight[64];
+
+			vec3 cube_direction(uint face, vec2 uv) {
+				switch (face) {
+					case 0u: return vec3(1.0, -uv.y, -uv.x);
+					case 1u: return vec3(-1.0, -uv.y, uv.x);
+					case 2u: return vec3(uv.x, 1.0, uv.y);
+					case 3u: return vec3(uv.x, -1.0, -uv.y);
+					case 4u: return vec3(uv.x, -uv.y, 1.0);
+					default: return vec3(-uv.x, -uv.y, -1.0);
+				}
+			}
+
+			void main() {
+				uint thread = gl_LocalInvocationIndex;
+				uint face_texels = pc.size * pc.size;
+
+				vec3 sums[9];
+				for (int k = 0; k < 9; k++) {
+					sums[k] = vec3(0.0);
+				}
+				float weight_sum = 0.0;
+
+				for (uint i = thread; i < face_texels * 6u; i += 64u) {
+					uint face = i / face_texels;
+					uint texel = i % face_texels;
+					vec2 uv = (vec2(texel % pc.size, texel / pc.size) + 0.5) / float(pc.size) * 2.0 - 1.0;
+					vec3 d = cube_direction(face, uv);
+					// texels near the face corners cover less of the sphere
+					float weight = 1.0 / pow(dot(d, d), 1.5);
+					vec3 n = normalize(d);
+					vec3 color = textureLod(environment, n, 0.0).rgb * weight;
+
+					sums[0] += color * 0.282095;
+					sums[1] += color * 0.488603 * n.y;
+					sums[2] += color * 0.488603 * n.z;
+					sums[3] += color * 0.488603 * n.x;
+					sums[4] += color * 1.092548 * n.x * n.y;
+					sums[5] += color * 1.092548 * n.y * n.z;
+					sums[6] += color * 0.315392 * (3.0 * n.z * n.z - 1.0);
+					sums[7] += color * 1.092548 * n.x * n.z;
+					sums[8] += color * 0.546274 * (n.x * n.x - n.y * n.y);
+					weight_sum += weight;
+				}
+
+				for (int k = 0; k < 9; k++) {
+					partial[thread][k] = sums[k];
+				}
+				partial_weight[thread] = weight_sum;
+				barrier();
+
+				if (thread == 0u) {
+					float total_weight = 0.0;
+					for (int t = 0; t < 64; t++) {
+						total_weight += partial_weight[t];
+					}
+					float normalization = 4.0 * 3.14159265 / total_weight;
+					for (int k = 0; k < 9; k++) {
+						vec3 total = vec3(0.0);
+						for (int t = 0; t < 64; t++) {
+							total += partial[t][k];
+						}
+						result.coefficients[k] = vec4(total * normalization, 0.0);
+					}
+				}
+			}
+		"
+	}
+}
+
+/// Light probes laid out on a regular grid, each storing the light arriving
+/// at its position as spherical harmonics.
+///
+/// Dynamic objects sample the grid at their center and pass the interpolated
+/// `Sh9::irradiance_coefficients` to their shader for ambient diffuse
+/// lighting, a cheap stand-in for global illumination.
+///
+/// Captures run on the gpu and are read back once the command buffer has
+/// finished, so call `receive` every frame after waiting on earlier frames.
+pub struct LightProbeGrid {
+	/// World position of the first probe.
+	pub origin: Vec3,
+	/// Distance between neighbouring probes along each axis.
+	pub spacing: Vec3,
+	pub near: f32,
+	pub far: f32,
+	/// Layers drawn into the captures, eg. to leave out dynamic objects.
+	pub layers: RenderLayers,
+	pub sampler: Arc<Sampler>,
+	device: Arc<Device>,
+	counts: [u32; 3],
+	probes: Vec<Sh9>,
+	capture: CubeCapture,
+	project: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	// probe index and the buffer its coefficients are written to
+	pending: Vec<(usize, Arc<CpuAccessibleBuffer<ShCoefficients>>)>,
+}
+
+impl LightProbeGrid {
+	/// Grid of `counts` probes, each captured at `resolution` per cube face.
+	/// Diffuse lighting is low frequency, so 16 or 32 is plenty.
+	pub fn new(
+		device: Arc<Device>,
+		origin: Vec3,
+		spacing: Vec3,
+		counts: [u32; 3],
+		resolution: u32,
+	) -> Self {
+		assert!(
+			counts.iter().all(|&count| count > 0),
+			"light probe grid needs at least one probe on each axis"
+		);
+
+		let shader = project::Shader::load(device.clone()).unwrap();
+		let project = Arc::new(
+			ComputePipeline::new(device.clone(), &shader.main_entry_point(), &(), None).unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		LightProbeGrid {
+			origin,
+			spacing,
+			near: 0.05,
+			far: 1000.0,
+			layers: RenderLayers::ALL,
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+			capture: CubeCapture::new(device.clone(), resolution),
+			device,
+			counts,
+			probes: vec![Sh9::ZERO; (counts[0] * counts[1] * counts[2]) as usize],
+			project,
+			pending: Vec::new(),
+		}
+	}
+
+	/// Subpass that pipelines drawing the scene into probes have to be built against.
+	pub fn subpass(&self) -> Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		self.capture.subpass()
+	}
+
+	pub fn counts(&self) -> [u32; 3] {
+		self.counts
+	}
+
+	pub fn len(&self) -> usize {
+		self.probes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.probes.is_empty()
+	}
+
+	pub fn index(&self, cell: [u32; 3]) -> usize {
+		((cell[2] * self.counts[1] + cell[1]) * self.counts[0] + cell[0]) as usize
+	}
+
+	pub fn position(&self, cell: [u32; 3]) -> Vec3 {
+		[
+			self.origin[0] + cell[0] as f32 * self.spacing[0],
+			self.origin[1] + cell[1] as f32 * self.spacing[1],
+			self.origin[2] + cell[2] as f32 * self.spacing[2],
+		]
+	}
+
+	pub fn probe(&self, cell: [u32; 3]) -> &Sh9 {
+		&self.probes[self.index(cell)]
+	}
+
+	/// Overrides a probe, eg. with baked coefficients loaded from disk.
+	pub fn set_probe(&mut self, cell: [u32; 3], sh: Sh9) {
+		let index = self.index(cell);
+		self.probes[index] = sh;
+	}
+
+	/// Records the capture and projection of one probe. Must be recorded
+	/// outside of a render pass, `draw` records the scene for one face.
+	pub fn capture<F>(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		cell: [u32; 3],
+		clear_color: ClearValue,
+		draw: F,
+	) where
+		F: FnMut(&mut AutoCommandBufferBuilder, &DynamicState, &View),
+	{
+		let position = self.position(cell);
+		self.capture.record(
+			builder,
+			position,
+			self.near,
+			self.far,
+			self.layers,
+			clear_color,
+			draw,
+		);
+
+		let result = CpuAccessibleBuffer::from_data(
+			self.device.clone(),
+			BufferUsage {
+				storage_buffer: true,
+				..BufferUsage::none()
+			},
+			true,
+			[[0.0; 4]; 9],
+		)
+		.unwrap();
+
+		let layout = self.project.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(self.capture.texture(), self.sampler.clone())
+				.unwrap()
+				.add_buffer(result.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let push_constants = project::ty::PushConstants {
+			size: self.capture.resolution(),
+		};
+		builder
+			.dispatch([1, 1, 1], self.project.clone(), set, push_constants, vec![])
+			.unwrap();
+
+		self.pending.push((self.index(cell), result));
+	}
+
+	/// Records the capture of every probe in the grid.
+	pub fn capture_all<F>(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		clear_color: ClearValue,
+		mut draw: F,
+	) where
+		F: FnMut(&mut AutoCommandBufferBuilder, &DynamicState, &View),
+	{
+		for z in 0..self.counts[2] {
+			for y in 0..self.counts[1] {
+				for x in 0..self.counts[0] {
+					self.capture(builder, [x, y, z], clear_color, &mut draw);
+				}
+			}
+		}
+	}
+
+	/// Stores the coefficients of captures the gpu has finished, returns how
+	/// many probes were updated.
+	pub fn receive(&mut self) -> usize {
+		let probes = &mut self.probes;
+		let before = self.pending.len();
+		self.pending.retain(|(index, result)| match result.read() {
+			Ok(coefficients) => {
+				probes[*index] = Sh9 {
+					coefficients: (*coefficients).map(|c| [c[0], c[1], c[2]]),
+				};
+				false
+			}
+			// still in use by the gpu
+			Err(_) => true,
+		});
+		before - self.pending.len()
+	}
+
+	/// Trilinear blend of the eight probes around `position`, clamped to the
+	/// grid's bounds.
+	pub fn sample(&self, position: Vec3) -> Sh9 {
+		let mut cell = [0; 3];
+		let mut fraction = [0.0; 3];
+		for axis in 0..3 {
+			let last = self.counts[axis] - 1;
+			let local =
+				((position[axis] - self.origin[axis]) / self.spacing[axis]).clamp(0.0, last as f32);
+			cell[axis] = (local.floor() as u32).min(last.saturating_sub(1));
+			fraction[axis] = local - cell[axis] as f32;
+		}
+
+		let corner = |dx: u32, dy: u32, dz: u32| {
+			let at = [
+				(cell[0] + dx).min(self.counts[0] - 1),
+				(cell[1] + dy).min(self.counts[1] - 1),
+				(cell[2] + dz).min(self.counts[2] - 1),
+			];
+			self.probes[self.index(at)]
+		};
+
+		let along_x = |dy, dz| corner(0, dy, dz).lerp(&corner(1, dy, dz), fraction[0]);
+		let along_y = |dz| along_x(0, dz).lerp(&along_x(1, dz), fraction[1]);
+		along_y(0).lerp(&along_y(1), fraction[2])
+	}
+}
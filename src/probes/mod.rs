@@ -0,0 +1,233 @@
+// probes capture the scene around a point into a cubemap, so objects nearby
+// can be lit by their surroundings instead of a single global environment.
+
+pub mod ibl;
+pub mod light;
+pub mod reflection;
+pub mod sh;
+
+pub use ibl::{IblBaker, PrefilteredEnvironment, BRDF_LUT_FORMAT};
+pub use light::LightProbeGrid;
+pub use reflection::{ReflectionProbe, ReflectionProbes};
+pub use sh::Sh9;
+
+use crate::math::{dot, Mat4, Vec3};
+use crate::render2d::Texture;
+use crate::render_target::DEPTH_FORMAT;
+use crate::scene::RenderLayers;
+use crate::viewport::{View, ViewportRect};
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::image::{
+	AttachmentImage, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage,
+};
+
+use std::sync::Arc;
+
+/// Format the cube faces are captured in, with enough range for bright lights.
+pub const PROBE_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+/// When a probe recaptures its surroundings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeUpdate {
+	/// Captured the first time it is updated, for static scenery.
+	Once,
+	/// Captured whenever `request_update` was called since the last capture.
+	OnDemand,
+	/// Captured every n frames, spread probes out so they don't all capture
+	/// on the same frame.
+	EveryNFrames(u32),
+}
+
+/// View matrix looking out of `position` through cube face `face`, in vulkan
+/// face order (+x, -x, +y, -y, +z, -z).
+///
+/// Rows are picked so the image lines up with how cubemaps are sampled, pair
+/// it with `cube_face_projection`. The faces come out mirrored compared to a
+/// regular camera, so pipelines that cull back faces see flipped winding.
+pub fn cube_face_view(face: usize, position: Vec3) -> Mat4 {
+	// right, down and forward of each face
+	let (right, down, forward): (Vec3, Vec3, Vec3) = match face {
+		0 => ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+		1 => ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0], [-1.0, 0.0, 0.0]),
+		2 => ([1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+		3 => ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+		4 => ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+		5 => ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+		_ => panic!("cube face {} out of range", face),
+	};
+	let back = [-forward[0], -forward[1], -forward[2]];
+
+	[
+		[right[0], down[0], back[0], 0.0],
+		[right[1], down[1], back[1], 0.0],
+		[right[2], down[2], back[2], 0.0],
+		[
+			-dot(right, position),
+			-dot(down, position),
+			-dot(back, position),
+			1.0,
+		],
+	]
+}
+
+/// 90 degree square projection with vulkan's 0..1 depth, for `cube_face_view`.
+pub fn cube_face_projection(near: f32, far: f32) -> Mat4 {
+	[
+		[1.0, 0.0, 0.0, 0.0],
+		[0.0, 1.0, 0.0, 0.0],
+		[0.0, 0.0, far / (near - far), -1.0],
+		[0.0, 0.0, near * far / (near - far), 0.0],
+	]
+}
+
+/// A cube image and render pass the scene is drawn into, one face at a time.
+///
+/// Probes only keep what they derive from the capture, so one of these is
+/// reused for every probe recorded into a command buffer.
+pub struct CubeCapture {
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	faces: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+	texture: Texture,
+	resolution: u32,
+}
+
+impl CubeCapture {
+	pub fn new(device: Arc<Device>, resolution: u32) -> Self {
+		let render_pass = Arc::new(
+			vulkano::single_pass_renderpass!(
+				device.clone(),
+				attachments: {
+					color: {
+						load: Clear,
+						store: Store,
+						format: PROBE_FORMAT,
+						samples: 1,
+					},
+					depth: {
+						load: Clear,
+						store: DontCare,
+						format: DEPTH_FORMAT,
+						samples: 1,
+					}
+				},
+				pass: {
+					color: [color],
+					depth_stencil: {depth}
+				}
+			)
+			.unwrap(),
+		) as Arc<dyn RenderPassAbstract + Send + Sync>;
+
+		let image = StorageImage::with_usage(
+			device.clone(),
+			ImageDimensions::Dim2d {
+				width: resolution,
+				height: resolution,
+				array_layers: 6,
+			},
+			PROBE_FORMAT,
+			ImageUsage {
+				color_attachment: true,
+				sampled: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags {
+				cube_compatible: true,
+				..ImageCreateFlags::none()
+			},
+			device.active_queue_families(),
+		)
+		.unwrap();
+
+		// faces are drawn one after another, so they can share a depth buffer
+		let depth = ImageView::new(
+			AttachmentImage::transient(device, [resolution, resolution], DEPTH_FORMAT).unwrap(),
+		)
+		.unwrap();
+
+		let faces = (0..6)
+			.map(|face| {
+				let view = ImageView::with_type_ranges(
+					image.clone(),
+					ImageViewType::Dim2d,
+					0..1,
+					face..face + 1,
+				)
+				.unwrap();
+				Arc::new(
+					Framebuffer::start(render_pass.clone())
+						.add(view)
+						.unwrap()
+						.add(depth.clone())
+						.unwrap()
+						.build()
+						.unwrap(),
+				) as Arc<dyn FramebufferAbstract + Send + Sync>
+			})
+			.collect();
+
+		CubeCapture {
+			render_pass,
+			faces,
+			texture: ImageView::with_type(image, ImageViewType::Cubemap).unwrap(),
+			resolution,
+		}
+	}
+
+	/// Subpass that pipelines drawing the scene into the capture have to be built against.
+	pub fn subpass(&self) -> Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		Subpass::from(self.render_pass.clone(), 0).unwrap()
+	}
+
+	pub fn resolution(&self) -> u32 {
+		self.resolution
+	}
+
+	/// The last capture as a `samplerCube`.
+	pub fn texture(&self) -> Texture {
+		self.texture.clone()
+	}
+
+	/// Draws the six faces around `position`. Must be recorded outside of a
+	/// render pass, `draw` records the scene for one face with the face's
+	/// viewport and camera.
+	#[allow(clippy::too_many_arguments)]
+	pub fn record<F>(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		position: Vec3,
+		near: f32,
+		far: f32,
+		layers: RenderLayers,
+		clear_color: ClearValue,
+		mut draw: F,
+	) where
+		F: FnMut(&mut AutoCommandBufferBuilder, &DynamicState, &View),
+	{
+		let dimensions = [self.resolution, self.resolution];
+		for (face, framebuffer) in self.faces.iter().enumerate() {
+			let view = View {
+				rect: ViewportRect::FULL,
+				view: cube_face_view(face, position),
+				projection: cube_face_projection(near, far),
+				position,
+				layers,
+			};
+
+			builder
+				.begin_render_pass(
+					framebuffer.clone(),
+					SubpassContents::Inline,
+					vec![clear_color, 1f32.into()],
+				)
+				.unwrap();
+			draw(builder, &view.dynamic_state(dimensions), &view);
+			builder.end_render_pass().unwrap();
+		}
+	}
+}
@@ -0,0 +1,216 @@
+use super::ibl::{IblBaker, PrefilteredEnvironment};
+use super::{CubeCapture, ProbeUpdate};
+use crate::math::{sub, Vec3};
+use crate::render2d::Texture;
+use crate::scene::RenderLayers;
+use crate::viewport::View;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::device::Device;
+use vulkano::format::ClearValue;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+
+use std::sync::Arc;
+
+/// Scene captured around a point and prefiltered for a range of roughness.
+pub struct ReflectionProbe {
+	pub position: Vec3,
+	/// Objects within this distance prefer the probe over the ones further away.
+	pub radius: f32,
+	pub near: f32,
+	pub far: f32,
+	/// Layers drawn into the capture, eg. to leave out dynamic objects.
+	pub layers: RenderLayers,
+	pub update: ProbeUpdate,
+	prefiltered: PrefilteredEnvironment,
+	requested: bool,
+	last_capture: Option<u64>,
+}
+
+impl ReflectionProbe {
+	/// Recaptures an `OnDemand` probe on the next update.
+	pub fn request_update(&mut self) {
+		self.requested = true;
+	}
+
+	pub fn needs_update(&self, frame: u64) -> bool {
+		match (self.update, self.last_capture) {
+			(_, None) => true,
+			(ProbeUpdate::Once, Some(_)) => false,
+			(ProbeUpdate::OnDemand, Some(_)) => self.requested,
+			(ProbeUpdate::EveryNFrames(n), Some(last)) => frame >= last + n.max(1) as u64,
+		}
+	}
+
+	/// The prefiltered levels as a `samplerCubeArray`, layer 0 is the mirror
+	/// reflection and the last layer fully rough.
+	pub fn texture(&self) -> Texture {
+		self.prefiltered.texture.clone()
+	}
+}
+
+/// Reflection probes sharing one capture resolution and prefilter pipeline.
+///
+/// The scene is drawn into each face of a probe by a caller supplied closure,
+/// then convolved into one cube per roughness level. Shaders pick the level
+/// from the surface roughness, blending between the two closest:
+///
+/// ```glsl
+/// layout(set = 1, binding = 0) uniform samplerCubeArray probe;
+///
+/// vec3 probe_specular(vec3 r, float roughness, float levels) {
+///     float level = roughness * (levels - 1.0);
+///     float lower = floor(level);
+///     vec3 a = texture(probe, vec4(r, lower)).rgb;
+///     vec3 b = texture(probe, vec4(r, min(lower + 1.0, levels - 1.0))).rgb;
+///     return mix(a, b, level - lower);
+/// }
+/// ```
+///
+/// Bind the `nearest` probe's `texture` per object in place of the global
+/// environment's specular term. Sampling a cube array needs the device's
+/// `image_cube_array` feature.
+pub struct ReflectionProbes {
+	device: Arc<Device>,
+	capture: CubeCapture,
+	ibl: IblBaker,
+	probes: Vec<ReflectionProbe>,
+	levels: u32,
+	/// Importance samples per texel for the rough levels.
+	pub sample_count: u32,
+}
+
+impl ReflectionProbes {
+	/// `resolution` is the size of each cube face and `levels` the number of
+	/// roughness steps, at least 2.
+	pub fn new(device: Arc<Device>, resolution: u32, levels: u32) -> Self {
+		assert!(levels >= 2, "reflection probes need at least 2 levels");
+
+		ReflectionProbes {
+			capture: CubeCapture::new(device.clone(), resolution),
+			ibl: IblBaker::new(device.clone()),
+			device,
+			probes: Vec::new(),
+			levels,
+			sample_count: 128,
+		}
+	}
+
+	/// Subpass that pipelines drawing the scene into probes have to be built against.
+	pub fn subpass(&self) -> Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		self.capture.subpass()
+	}
+
+	pub fn levels(&self) -> u32 {
+		self.levels
+	}
+
+	/// Adds a probe, it is captured on the next `update`.
+	pub fn add(&mut self, position: Vec3, radius: f32, update: ProbeUpdate) -> usize {
+		let prefiltered = PrefilteredEnvironment::new(
+			self.device.clone(),
+			self.capture.resolution(),
+			self.levels,
+		);
+		self.probes.push(ReflectionProbe {
+			position,
+			radius,
+			near: 0.05,
+			far: 1000.0,
+			layers: RenderLayers::ALL,
+			update,
+			prefiltered,
+			requested: true,
+			last_capture: None,
+		});
+		self.probes.len() - 1
+	}
+
+	pub fn probe(&self, index: usize) -> &ReflectionProbe {
+		&self.probes[index]
+	}
+
+	pub fn probe_mut(&mut self, index: usize) -> &mut ReflectionProbe {
+		&mut self.probes[index]
+	}
+
+	pub fn len(&self) -> usize {
+		self.probes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.probes.is_empty()
+	}
+
+	/// Captures and prefilters every probe due on `frame`, returns how many
+	/// were captured. Must be recorded outside of a render pass.
+	///
+	/// `draw` records the scene for one face, with the face's viewport and
+	/// camera, into a pass built from `subpass`.
+	pub fn update<F>(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		frame: u64,
+		clear_color: ClearValue,
+		mut draw: F,
+	) -> usize
+	where
+		F: FnMut(&mut AutoCommandBufferBuilder, &DynamicState, &View),
+	{
+		let mut captured = 0;
+		for index in 0..self.probes.len() {
+			if self.probes[index].needs_update(frame) {
+				self.capture(builder, index, clear_color, &mut draw);
+				let probe = &mut self.probes[index];
+				probe.requested = false;
+				probe.last_capture = Some(frame);
+				captured += 1;
+			}
+		}
+		captured
+	}
+
+	/// Captures and prefilters one probe right away, whatever its update mode.
+	pub fn capture<F>(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		index: usize,
+		clear_color: ClearValue,
+		draw: F,
+	) where
+		F: FnMut(&mut AutoCommandBufferBuilder, &DynamicState, &View),
+	{
+		let probe = &self.probes[index];
+		self.capture.record(
+			builder,
+			probe.position,
+			probe.near,
+			probe.far,
+			probe.layers,
+			clear_color,
+			draw,
+		);
+
+		self.ibl.prefilter_into(
+			builder,
+			self.capture.texture(),
+			&probe.prefiltered,
+			self.sample_count,
+		);
+	}
+
+	/// Probe whose radius contains `position`, preferring the closest center,
+	/// or the closest probe overall when none of them contain it.
+	pub fn nearest(&self, position: Vec3) -> Option<&ReflectionProbe> {
+		self.probes
+			.iter()
+			.map(|probe| {
+				let d = sub(position, probe.position);
+				let distance_squared = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+				let outside = distance_squared > probe.radius * probe.radius;
+				((outside, distance_squared), probe)
+			})
+			.min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+			.map(|(_, probe)| probe)
+	}
+}
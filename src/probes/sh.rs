@@ -0,0 +1,128 @@
+use crate::math::{add, lerp, scale, Vec3};
+
+use std::f32::consts::PI;
+
+// band 2 cosine lobe convolution factors, divided by pi for lambertian radiance
+const BAND_FACTORS: [f32; 9] = [
+	1.0,
+	2.0 / 3.0,
+	2.0 / 3.0,
+	2.0 / 3.0,
+	0.25,
+	0.25,
+	0.25,
+	0.25,
+	0.25,
+];
+
+/// Second order spherical harmonics of incoming radiance, 9 rgb coefficients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sh9 {
+	pub coefficients: [Vec3; 9],
+}
+
+impl Default for Sh9 {
+	fn default() -> Self {
+		Sh9::ZERO
+	}
+}
+
+impl Sh9 {
+	pub const ZERO: Sh9 = Sh9 {
+		coefficients: [[0.0; 3]; 9],
+	};
+
+	/// Light of `color` coming equally from every direction.
+	pub fn uniform(color: Vec3) -> Self {
+		let mut sh = Sh9::ZERO;
+		sh.coefficients[0] = scale(color, 2.0 * PI.sqrt());
+		sh
+	}
+
+	/// The 9 real basis functions for a normalized direction.
+	pub fn basis(direction: Vec3) -> [f32; 9] {
+		let [x, y, z] = direction;
+		[
+			0.282_095,
+			0.488_603 * y,
+			0.488_603 * z,
+			0.488_603 * x,
+			1.092_548 * x * y,
+			1.092_548 * y * z,
+			0.315_392 * (3.0 * z * z - 1.0),
+			1.092_548 * x * z,
+			0.546_274 * (x * x - y * y),
+		]
+	}
+
+	/// Adds radiance arriving from `direction`, weighted by the solid angle it covers.
+	pub fn add_sample(&mut self, direction: Vec3, color: Vec3, weight: f32) {
+		for (coefficient, basis) in self.coefficients.iter_mut().zip(Sh9::basis(direction)) {
+			*coefficient = add(*coefficient, scale(color, basis * weight));
+		}
+	}
+
+	pub fn scale(&self, s: f32) -> Self {
+		Sh9 {
+			coefficients: self.coefficients.map(|c| scale(c, s)),
+		}
+	}
+
+	pub fn add(&self, other: &Sh9) -> Self {
+		let mut sum = *self;
+		for (a, b) in sum.coefficients.iter_mut().zip(other.coefficients.iter()) {
+			*a = add(*a, *b);
+		}
+		sum
+	}
+
+	pub fn lerp(&self, other: &Sh9, t: f32) -> Self {
+		let mut result = *self;
+		for (a, b) in result
+			.coefficients
+			.iter_mut()
+			.zip(other.coefficients.iter())
+		{
+			*a = lerp(*a, *b, t);
+		}
+		result
+	}
+
+	/// Coefficients convolved with a cosine lobe, ready for `sh_irradiance`.
+	///
+	/// ```glsl
+	/// vec3 sh_irradiance(vec4 sh[9], vec3 n) {
+	///     vec3 result = sh[0].rgb * 0.282095
+	///         + sh[1].rgb * 0.488603 * n.y
+	///         + sh[2].rgb * 0.488603 * n.z
+	///         + sh[3].rgb * 0.488603 * n.x
+	///         + sh[4].rgb * 1.092548 * n.x * n.y
+	///         + sh[5].rgb * 1.092548 * n.y * n.z
+	///         + sh[6].rgb * 0.315392 * (3.0 * n.z * n.z - 1.0)
+	///         + sh[7].rgb * 1.092548 * n.x * n.z
+	///         + sh[8].rgb * 0.546274 * (n.x * n.x - n.y * n.y);
+	///     return max(result, vec3(0.0));
+	/// }
+	/// ```
+	///
+	/// Multiply the result by the surface's diffuse albedo.
+	pub fn irradiance_coefficients(&self) -> [[f32; 4]; 9] {
+		let mut result = [[0.0; 4]; 9];
+		for (i, coefficient) in self.coefficients.iter().enumerate() {
+			let c = scale(*coefficient, BAND_FACTORS[i]);
+			result[i] = [c[0], c[1], c[2], 0.0];
+		}
+		result
+	}
+
+	/// Diffuse light reaching a surface facing `normal`, the cpu version of
+	/// `sh_irradiance`.
+	pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+		let basis = Sh9::basis(normal);
+		let mut result = [0.0; 3];
+		for (i, coefficient) in self.coefficients.iter().enumerate() {
+			result = add(result, scale(*coefficient, basis[i] * BAND_FACTORS[i]));
+		}
+		result.map(|c| c.max(0.0))
+	}
+}
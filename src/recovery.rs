@@ -0,0 +1,74 @@
+// rebuilding everything after the device is lost. vulkan can't bring a lost
+// device back, every object made from it has to be made again from scratch.
+
+use vulkano::device::{Device, Queue};
+use vulkano::instance::PhysicalDevice;
+
+use std::sync::Arc;
+
+type RecreateHook = Box<dyn FnMut(&Arc<Device>, &Arc<Queue>)>;
+
+/// Makes a replacement device after a loss and lets everything holding gpu
+/// resources recreate them on it.
+///
+/// Register a hook for each system that owns buffers, images or pipelines,
+/// re-uploading from cpu side copies. Windows are rebuilt separately with
+/// `WindowTarget::recreate_device`.
+#[derive(Default)]
+pub struct DeviceRecovery {
+	hooks: Vec<RecreateHook>,
+}
+
+impl DeviceRecovery {
+	pub fn new() -> Self {
+		DeviceRecovery::default()
+	}
+
+	/// Adds a hook called with the new device and queue, in registration order.
+	pub fn on_recreate<F>(&mut self, hook: F)
+	where
+		F: FnMut(&Arc<Device>, &Arc<Queue>) + 'static,
+	{
+		self.hooks.push(Box::new(hook));
+	}
+
+	/// Creates a device with the same features and extensions as `lost` and
+	/// one queue from the same family, then runs the hooks.
+	///
+	/// Uses the same physical device when it's still there, otherwise the
+	/// first one with a graphics queue, eg. after a laptop switched gpus.
+	/// Returns `None` when no device could be created, try again later.
+	pub fn recreate(&mut self, lost: &Device, queue: &Queue) -> Option<(Arc<Device>, Arc<Queue>)> {
+		let instance = lost.instance();
+		let physical = PhysicalDevice::from_index(instance, lost.physical_device().index())
+			.filter(|physical| physical.name() == lost.physical_device().name())
+			.or_else(|| {
+				PhysicalDevice::enumerate(instance)
+					.find(|physical| physical.queue_families().any(|q| q.supports_graphics()))
+			})?;
+
+		let family = physical
+			.queue_family_by_id(queue.family().id())
+			.filter(|family| family.supports_graphics())
+			.or_else(|| physical.queue_families().find(|q| q.supports_graphics()))?;
+
+		let (device, mut queues) = match Device::new(
+			physical,
+			lost.enabled_features(),
+			lost.loaded_extensions(),
+			[(family, 0.5)].iter().cloned(),
+		) {
+			Ok(r) => r,
+			Err(e) => {
+				log::error!("Failed to recreate the device: {:?}", e);
+				return None;
+			}
+		};
+		let queue = queues.next().unwrap();
+
+		for hook in self.hooks.iter_mut() {
+			hook(&device, &queue);
+		}
+		Some((device, queue))
+	}
+}
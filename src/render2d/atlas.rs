@@ -0,0 +1,336 @@
+use super::{linear_texture_from_rgba, texture_from_rgba, texture_size, Sprite, Texture};
+use crate::math::Vec2;
+
+use vulkano::command_buffer::{AutoCommandBuffer, CommandBufferExecFuture};
+use vulkano::device::Queue;
+use vulkano::sync::NowFuture;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum AtlasError {
+	/// The images don't fit in a texture of the maximum size.
+	TooLarge,
+	Json(serde_json::Error),
+	/// The sprite sheet metadata is valid json but not in a format we know.
+	Format(&'static str),
+}
+
+impl fmt::Display for AtlasError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			AtlasError::TooLarge => write!(f, "images don't fit in the atlas"),
+			AtlasError::Json(error) => write!(f, "failed to parse sprite sheet: {}", error),
+			AtlasError::Format(reason) => write!(f, "invalid sprite sheet: {}", reason),
+		}
+	}
+}
+
+impl std::error::Error for AtlasError {}
+
+impl From<serde_json::Error> for AtlasError {
+	fn from(error: serde_json::Error) -> Self {
+		AtlasError::Json(error)
+	}
+}
+
+/// Named regions of a single texture.
+///
+/// Drawing everything from one atlas lets the sprite batch put all of it in a
+/// single draw call. Regions are addressed by index, in the order they were
+/// added, packed or listed in the sheet.
+pub struct TextureAtlas {
+	pub texture: Texture,
+	regions: Vec<[f32; 4]>,
+	names: HashMap<String, usize>,
+}
+
+impl TextureAtlas {
+	/// Splits a sprite sheet into equally sized cells, row by row from the top left.
+	pub fn grid(texture: Texture, cell_size: [u32; 2]) -> Self {
+		let [width, height] = texture_size(&texture);
+		let columns = width / cell_size[0];
+		let rows = height / cell_size[1];
+
+		let regions = (0..rows)
+			.flat_map(|row| (0..columns).map(move |column| (column, row)))
+			.map(|(column, row)| {
+				normalized(
+					[column * cell_size[0], row * cell_size[1]],
+					cell_size,
+					[width, height],
+				)
+			})
+			.collect();
+
+		TextureAtlas {
+			texture,
+			regions,
+			names: HashMap::new(),
+		}
+	}
+
+	/// Reads the regions from json sprite sheet metadata.
+	///
+	/// Takes the common format written by TexturePacker and Aseprite, with
+	/// `frames` either an object keyed by name or an array of entries with a
+	/// `filename`. Only the `frame` rectangle of each entry is used, trimmed or
+	/// rotated frames aren't supported.
+	pub fn from_json(texture: Texture, json: &str) -> Result<Self, AtlasError> {
+		let sheet: serde_json::Value = serde_json::from_str(json)?;
+		let size = texture_size(&texture);
+
+		let frames: Vec<(&str, &serde_json::Value)> = match &sheet["frames"] {
+			serde_json::Value::Object(frames) => frames
+				.iter()
+				.map(|(name, frame)| (name.as_str(), frame))
+				.collect(),
+			serde_json::Value::Array(frames) => frames
+				.iter()
+				.map(|frame| {
+					frame["filename"]
+						.as_str()
+						.map(|name| (name, frame))
+						.ok_or(AtlasError::Format("frame without a filename"))
+				})
+				.collect::<Result<_, _>>()?,
+			_ => return Err(AtlasError::Format("missing frames")),
+		};
+
+		let mut atlas = TextureAtlas {
+			texture,
+			regions: Vec::with_capacity(frames.len()),
+			names: HashMap::with_capacity(frames.len()),
+		};
+
+		for (name, frame) in frames {
+			let rect = &frame["frame"];
+			let field = |key: &str| {
+				rect[key]
+					.as_u64()
+					.map(|value| value as u32)
+					.ok_or(AtlasError::Format(
+						"frame rectangle must have x, y, w and h",
+					))
+			};
+			let region = normalized([field("x")?, field("y")?], [field("w")?, field("h")?], size);
+			atlas.names.insert(name.to_owned(), atlas.regions.len());
+			atlas.regions.push(region);
+		}
+
+		Ok(atlas)
+	}
+
+	pub fn len(&self) -> usize {
+		self.regions.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.regions.is_empty()
+	}
+
+	/// Index of a named region.
+	pub fn find(&self, name: &str) -> Option<usize> {
+		self.names.get(name).copied()
+	}
+
+	/// Normalized `[u, v, width, height]` of a region, ready for `Sprite::region`.
+	pub fn region(&self, index: usize) -> [f32; 4] {
+		self.regions[index]
+	}
+
+	/// Sprite showing one region of the atlas.
+	pub fn sprite(&self, index: usize, position: Vec2, size: Vec2) -> Sprite {
+		Sprite {
+			region: self.regions[index],
+			..Sprite::new(self.texture.clone(), position, size)
+		}
+	}
+}
+
+/// Packs many small images into one atlas texture at runtime.
+pub struct AtlasBuilder {
+	images: Vec<Image>,
+	names: HashMap<String, usize>,
+	/// Space around every image, filled by stretching its edge pixels so
+	/// linear filtering doesn't bleed neighbours in.
+	pub padding: u32,
+	/// Largest width and height the atlas is allowed to grow to.
+	pub max_size: u32,
+	/// Whether the pixels are srgb colors, turn off for data like distance fields.
+	pub srgb: bool,
+}
+
+struct Image {
+	dimensions: [u32; 2],
+	pixels: Vec<u8>,
+}
+
+impl Default for AtlasBuilder {
+	fn default() -> Self {
+		AtlasBuilder {
+			images: Vec::new(),
+			names: HashMap::new(),
+			padding: 1,
+			max_size: 4096,
+			srgb: true,
+		}
+	}
+}
+
+impl AtlasBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds tightly packed rgba8 pixels and returns the index of their region
+	/// in the built atlas.
+	pub fn add(&mut self, name: &str, dimensions: [u32; 2], pixels: Vec<u8>) -> usize {
+		assert_eq!(
+			pixels.len(),
+			(dimensions[0] * dimensions[1] * 4) as usize,
+			"pixel data doesn't match the image size"
+		);
+
+		let index = self.images.len();
+		self.images.push(Image { dimensions, pixels });
+		self.names.insert(name.to_owned(), index);
+		index
+	}
+
+	/// Packs the images and uploads the atlas.
+	///
+	/// Like `texture_from_rgba`, the returned future has to be joined with the
+	/// frame that first draws from the atlas.
+	pub fn build(
+		self,
+		queue: Arc<Queue>,
+	) -> Result<
+		(
+			TextureAtlas,
+			CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+		),
+		AtlasError,
+	> {
+		let sizes: Vec<[u32; 2]> = self.images.iter().map(|image| image.dimensions).collect();
+		let (size, positions) =
+			pack(&sizes, self.padding, self.max_size).ok_or(AtlasError::TooLarge)?;
+
+		let mut pixels = vec![0u8; (size[0] * size[1] * 4) as usize];
+		for (image, &position) in self.images.iter().zip(positions.iter()) {
+			blit(&mut pixels, size[0], image, position, self.padding);
+		}
+
+		let (texture, future) = if self.srgb {
+			texture_from_rgba(queue, size, &pixels)
+		} else {
+			linear_texture_from_rgba(queue, size, &pixels)
+		};
+
+		let regions = self
+			.images
+			.iter()
+			.zip(positions)
+			.map(|(image, position)| normalized(position, image.dimensions, size))
+			.collect();
+
+		Ok((
+			TextureAtlas {
+				texture,
+				regions,
+				names: self.names,
+			},
+			future,
+		))
+	}
+}
+
+fn normalized(position: [u32; 2], size: [u32; 2], texture_size: [u32; 2]) -> [f32; 4] {
+	let (width, height) = (texture_size[0] as f32, texture_size[1] as f32);
+	[
+		position[0] as f32 / width,
+		position[1] as f32 / height,
+		size[0] as f32 / width,
+		size[1] as f32 / height,
+	]
+}
+
+// shelf packing, tallest images first. starts small and doubles the atlas
+// until everything fits, returns the atlas size and the top left corner of
+// every image (inside its padding).
+fn pack(sizes: &[[u32; 2]], padding: u32, max_size: u32) -> Option<([u32; 2], Vec<[u32; 2]>)> {
+	let mut order: Vec<usize> = (0..sizes.len()).collect();
+	order.sort_by_key(|&i| std::cmp::Reverse(sizes[i][1]));
+
+	let mut size = [64, 64];
+	loop {
+		if let Some(positions) = pack_shelves(sizes, &order, padding, size) {
+			return Some((size, positions));
+		}
+
+		// grow the shorter side so the atlas stays close to square
+		let side = if size[0] <= size[1] { 0 } else { 1 };
+		if size[side] * 2 > max_size {
+			return None;
+		}
+		size[side] *= 2;
+	}
+}
+
+fn pack_shelves(
+	sizes: &[[u32; 2]],
+	order: &[usize],
+	padding: u32,
+	size: [u32; 2],
+) -> Option<Vec<[u32; 2]>> {
+	let mut positions = vec![[0, 0]; sizes.len()];
+	let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+	for &i in order {
+		let width = sizes[i][0] + padding * 2;
+		let height = sizes[i][1] + padding * 2;
+		if width > size[0] {
+			return None;
+		}
+
+		// start a new shelf when the current one is full
+		if x + width > size[0] {
+			x = 0;
+			y += shelf_height;
+			shelf_height = 0;
+		}
+		if y + height > size[1] {
+			return None;
+		}
+
+		positions[i] = [x + padding, y + padding];
+		x += width;
+		shelf_height = shelf_height.max(height);
+	}
+
+	Some(positions)
+}
+
+// copies the image into the atlas, extruding its edges into the padding
+fn blit(atlas: &mut [u8], atlas_width: u32, image: &Image, position: [u32; 2], padding: u32) {
+	let [width, height] = image.dimensions;
+	if width == 0 || height == 0 {
+		return;
+	}
+
+	let padding = padding as i64;
+	for dy in -padding..height as i64 + padding {
+		let source_y = dy.clamp(0, height as i64 - 1) as u32;
+		let target_y = (position[1] as i64 + dy) as u32;
+		for dx in -padding..width as i64 + padding {
+			let source_x = dx.clamp(0, width as i64 - 1) as u32;
+			let target_x = (position[0] as i64 + dx) as u32;
+
+			let source = ((source_y * width + source_x) * 4) as usize;
+			let target = ((target_y * atlas_width + target_x) * 4) as usize;
+			atlas[target..target + 4].copy_from_slice(&image.pixels[source..source + 4]);
+		}
+	}
+}
@@ -0,0 +1,219 @@
+use super::{Camera2d, Sprite, Texture};
+
+use vulkano::buffer::{BufferAccess, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+
+use std::sync::Arc;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SpriteVertex {
+	pub position: [f32; 2],
+	pub uv: [f32; 2],
+	pub color: [f32; 4],
+}
+vulkano::impl_vertex!(SpriteVertex, position, uv, color);
+
+pub mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 position;
+			layout(location = 1) in vec2 uv;
+			layout(location = 2) in vec4 color;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+
+			void main() {
+				gl_Position = push_constants.view_projection * vec4(position, 0.0, 1.0);
+				v_uv = uv;
+				v_color = color;
+			}
+		"
+	}
+}
+
+pub mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+
+			layout(set = 0, binding = 0) uniform sampler2D sprite_texture;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				f_color = texture(sprite_texture, v_uv) * v_color;
+			}
+		"
+	}
+}
+
+/// Collects sprites over a frame and draws them with one draw per texture change.
+///
+/// Sprites are sorted by layer and then by texture, so sprites on the same
+/// layer don't keep their submission order unless they share a texture.
+/// Depth testing is disabled, layers are the only thing deciding what ends
+/// up on top.
+pub struct SpriteBatch {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	vertices: CpuBufferPool<SpriteVertex>,
+	sprites: Vec<Sprite>,
+	/// Linear filtering by default, use a nearest sampler for pixel art.
+	pub sampler: Arc<Sampler>,
+}
+
+impl SpriteBatch {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let pipeline = Arc::new(
+			GraphicsPipeline::start()
+				.vertex_input_single_buffer::<SpriteVertex>()
+				.vertex_shader(vs.main_entry_point(), ())
+				.triangle_list()
+				.viewports_dynamic_scissors_irrelevant(1)
+				.fragment_shader(fs.main_entry_point(), ())
+				.blend_alpha_blending()
+				.render_pass(subpass)
+				.build(device.clone())
+				.unwrap(),
+		) as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		SpriteBatch {
+			pipeline,
+			vertices: CpuBufferPool::vertex_buffer(device.clone()),
+			sprites: Vec::new(),
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device),
+		}
+	}
+
+	/// Queues a sprite for the next `flush`.
+	pub fn draw(&mut self, sprite: Sprite) {
+		self.sprites.push(sprite);
+	}
+
+	pub fn len(&self) -> usize {
+		self.sprites.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.sprites.is_empty()
+	}
+
+	/// Records the queued sprites and clears the queue. Must be called inside
+	/// the subpass the batch was created for.
+	///
+	/// Returns the number of draw calls recorded.
+	pub fn flush(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		camera: &Camera2d,
+	) -> usize {
+		if self.sprites.is_empty() {
+			return 0;
+		}
+
+		// stable, so sprites sharing a layer and texture keep their order
+		self.sprites
+			.sort_by_key(|sprite| (sprite.layer, texture_key(&sprite.texture)));
+
+		let vertices: Vec<SpriteVertex> = self.sprites.iter().flat_map(sprite_vertices).collect();
+		let vertices = Arc::new(self.vertices.chunk(vertices).unwrap());
+
+		let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+		let push_constants = vs::ty::PushConstants {
+			view_projection: camera.view_projection(),
+		};
+
+		let mut draws = 0;
+		let mut start = 0;
+		while start < self.sprites.len() {
+			let texture = &self.sprites[start].texture;
+			let key = texture_key(texture);
+			let end = start
+				+ self.sprites[start..]
+					.iter()
+					.take_while(|sprite| texture_key(&sprite.texture) == key)
+					.count();
+
+			let set = Arc::new(
+				PersistentDescriptorSet::start(layout.clone())
+					.add_sampled_image(texture.clone(), self.sampler.clone())
+					.unwrap()
+					.build()
+					.unwrap(),
+			);
+
+			let range = vertices
+				.clone()
+				.into_buffer_slice()
+				.slice(start * 6..end * 6)
+				.unwrap();
+
+			builder
+				.draw(
+					self.pipeline.clone(),
+					dynamic_state,
+					vec![Arc::new(range) as Arc<dyn BufferAccess + Send + Sync>],
+					set,
+					push_constants,
+					vec![],
+				)
+				.unwrap();
+
+			draws += 1;
+			start = end;
+		}
+
+		self.sprites.clear();
+		draws
+	}
+}
+
+// sprites are batched by image view identity
+fn texture_key(texture: &Texture) -> usize {
+	Arc::as_ptr(texture) as *const () as usize
+}
+
+// two triangles per sprite, there is no index buffer
+fn sprite_vertices(sprite: &Sprite) -> [SpriteVertex; 6] {
+	let corners = sprite.corners();
+	let [u, v, w, h] = sprite.region;
+	let uvs = [[u, v], [u + w, v], [u, v + h], [u + w, v + h]];
+
+	let vertex = |i: usize| SpriteVertex {
+		position: corners[i],
+		uv: uvs[i],
+		color: sprite.color,
+	};
+	[
+		vertex(0),
+		vertex(1),
+		vertex(2),
+		vertex(2),
+		vertex(1),
+		vertex(3),
+	]
+}
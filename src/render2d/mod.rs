@@ -0,0 +1,311 @@
+pub mod atlas;
+pub mod batch;
+pub mod nine_slice;
+
+use crate::math::{Mat4, Vec2, Vec4};
+
+use vulkano::command_buffer::{AutoCommandBuffer, CommandBufferExecFuture};
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::sync::NowFuture;
+
+use std::sync::Arc;
+
+pub use atlas::{AtlasBuilder, TextureAtlas};
+pub use batch::SpriteBatch;
+pub use nine_slice::NineSlice;
+
+/// Any sampled image view can be drawn as a sprite.
+pub type Texture = Arc<dyn ImageViewAbstract + Send + Sync>;
+
+/// Uploads tightly packed rgba8 pixels as an srgb texture.
+///
+/// The texture can be used right away, the returned future just has to be
+/// joined with the frame that first draws it.
+pub fn texture_from_rgba(
+	queue: Arc<Queue>,
+	dimensions: [u32; 2],
+	pixels: &[u8],
+) -> (
+	Texture,
+	CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+) {
+	upload_rgba(queue, dimensions, pixels, Format::R8G8B8A8Srgb)
+}
+
+/// Like `texture_from_rgba` but without the srgb decode, for data such as
+/// distance fields that has to be sampled as is.
+pub fn linear_texture_from_rgba(
+	queue: Arc<Queue>,
+	dimensions: [u32; 2],
+	pixels: &[u8],
+) -> (
+	Texture,
+	CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+) {
+	upload_rgba(queue, dimensions, pixels, Format::R8G8B8A8Unorm)
+}
+
+/// Width and height of a texture in pixels.
+pub fn texture_size(texture: &Texture) -> [u32; 2] {
+	let dimensions = texture.image().dimensions();
+	[dimensions.width(), dimensions.height()]
+}
+
+fn upload_rgba(
+	queue: Arc<Queue>,
+	dimensions: [u32; 2],
+	pixels: &[u8],
+	format: Format,
+) -> (
+	Texture,
+	CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+) {
+	assert_eq!(
+		pixels.len(),
+		(dimensions[0] * dimensions[1] * 4) as usize,
+		"pixel data doesn't match the texture size"
+	);
+
+	let (image, future) = ImmutableImage::from_iter(
+		pixels.iter().cloned(),
+		ImageDimensions::Dim2d {
+			width: dimensions[0],
+			height: dimensions[1],
+			array_layers: 1,
+		},
+		MipmapsCount::One,
+		format,
+		queue,
+	)
+	.unwrap();
+
+	(ImageView::new(image).unwrap(), future)
+}
+
+/// Which point of the viewport `Camera2d::position` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraOrigin {
+	/// The top left corner, so world space maps straight onto window pixels.
+	#[default]
+	TopLeft,
+	/// The center, eg. for a camera following a player.
+	Center,
+}
+
+/// Orthographic camera where one world unit is one pixel at a zoom of 1.
+///
+/// Y points down and `position` is the world point shown at the `origin` of
+/// the viewport, the top left corner by default, so the default camera maps
+/// world space straight onto window pixels.
+///
+/// At arbitrary positions and zooms texels land between pixels and sprites
+/// show seams and shimmer as they move. `pixel_perfect` turns on the options
+/// that keep them on the pixel grid.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera2d {
+	/// Size of the viewport in pixels.
+	pub viewport: Vec2,
+	pub position: Vec2,
+	pub zoom: f32,
+	pub origin: CameraOrigin,
+	/// Rounds the view to whole screen pixels, so things at whole world units
+	/// start on a pixel edge however the camera moves.
+	pub snap: bool,
+	/// Only zooms by whole numbers, `zoom` rounded down but at least 1, so
+	/// every texel covers the same number of pixels.
+	pub integer_zoom: bool,
+	/// Moves the view by half a pixel, so whole world units land on pixel
+	/// centers instead of edges. For one pixel lines and points.
+	pub half_texel: bool,
+}
+
+impl Camera2d {
+	pub fn new(viewport: Vec2) -> Self {
+		Camera2d {
+			viewport,
+			position: [0.0, 0.0],
+			zoom: 1.0,
+			origin: CameraOrigin::TopLeft,
+			snap: false,
+			integer_zoom: false,
+			half_texel: false,
+		}
+	}
+
+	/// Camera where one world unit is one logical pixel, `viewport` being
+	/// the physical size of the target and `scale_factor` the window's. Ui
+	/// laid out with it keeps its size on hidpi displays, and `screen_to_world`
+	/// still takes physical positions like winit's cursor.
+	pub fn scaled(viewport: Vec2, scale_factor: f32) -> Self {
+		Camera2d {
+			zoom: scale_factor,
+			..Camera2d::new(viewport)
+		}
+	}
+
+	/// Camera for pixel art, snapped to whole pixels at whole zooms with
+	/// `position` at `origin`. `viewport` is in physical pixels.
+	pub fn pixel_perfect(viewport: Vec2, origin: CameraOrigin) -> Self {
+		Camera2d {
+			origin,
+			snap: true,
+			integer_zoom: true,
+			..Camera2d::new(viewport)
+		}
+	}
+
+	/// The zoom the view uses, see `integer_zoom`.
+	pub fn effective_zoom(&self) -> f32 {
+		if self.integer_zoom {
+			self.zoom.floor().max(1.0)
+		} else {
+			self.zoom
+		}
+	}
+
+	// screen position of the origin, a whole pixel when snapping so an odd
+	// viewport doesn't center on a pixel edge
+	fn origin_offset(&self) -> Vec2 {
+		match self.origin {
+			CameraOrigin::TopLeft => [0.0, 0.0],
+			CameraOrigin::Center if self.snap => [
+				(self.viewport[0] * 0.5).floor(),
+				(self.viewport[1] * 0.5).floor(),
+			],
+			CameraOrigin::Center => [self.viewport[0] * 0.5, self.viewport[1] * 0.5],
+		}
+	}
+
+	// world point in the top left corner of the viewport
+	fn corner(&self) -> Vec2 {
+		let zoom = self.effective_zoom();
+		let offset = self.origin_offset();
+		let mut corner = [
+			self.position[0] - offset[0] / zoom,
+			self.position[1] - offset[1] / zoom,
+		];
+		if self.snap {
+			corner = corner.map(|c| (c * zoom).round() / zoom);
+		}
+		if self.half_texel {
+			corner = corner.map(|c| c - 0.5 / zoom);
+		}
+		corner
+	}
+
+	pub fn view_projection(&self) -> Mat4 {
+		let zoom = self.effective_zoom();
+		let corner = self.corner();
+		let sx = 2.0 * zoom / self.viewport[0];
+		let sy = 2.0 * zoom / self.viewport[1];
+		[
+			[sx, 0.0, 0.0, 0.0],
+			[0.0, sy, 0.0, 0.0],
+			[0.0, 0.0, 1.0, 0.0],
+			[-corner[0] * sx - 1.0, -corner[1] * sy - 1.0, 0.0, 1.0],
+		]
+	}
+
+	/// World position under a pixel of the viewport, eg. the cursor.
+	pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+		let zoom = self.effective_zoom();
+		let corner = self.corner();
+		[corner[0] + screen[0] / zoom, corner[1] + screen[1] / zoom]
+	}
+
+	/// Moves the view along with a drag of `delta` screen pixels, eg. from
+	/// `Gesture::Drag`.
+	pub fn pan(&mut self, delta: Vec2) {
+		let zoom = self.effective_zoom();
+		self.position[0] -= delta[0] / zoom;
+		self.position[1] -= delta[1] / zoom;
+	}
+
+	/// Zooms by `factor` keeping the world point under `screen` in place, eg.
+	/// the center and scale of a `Gesture::Pinch`. With `integer_zoom` the
+	/// view only changes once `zoom` passes a whole number.
+	pub fn zoom_at(&mut self, screen: Vec2, factor: f32) {
+		// relative to the origin before snapping, so repeated zooms don't drift
+		let offset = self.origin_offset();
+		let from_origin = [screen[0] - offset[0], screen[1] - offset[1]];
+		let zoom = self.effective_zoom();
+		let anchor = [
+			self.position[0] + from_origin[0] / zoom,
+			self.position[1] + from_origin[1] / zoom,
+		];
+		self.zoom *= factor;
+		let zoom = self.effective_zoom();
+		self.position = [
+			anchor[0] - from_origin[0] / zoom,
+			anchor[1] - from_origin[1] / zoom,
+		];
+	}
+
+	pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+		let zoom = self.effective_zoom();
+		let corner = self.corner();
+		[(world[0] - corner[0]) * zoom, (world[1] - corner[1]) * zoom]
+	}
+}
+
+/// A textured quad.
+#[derive(Clone)]
+pub struct Sprite {
+	pub texture: Texture,
+	/// Part of the texture to show as `[u, v, width, height]` in normalized
+	/// coordinates. A negative width or height flips the sprite.
+	pub region: [f32; 4],
+	/// Where the sprite's origin ends up in world space.
+	pub position: Vec2,
+	/// Size in world units.
+	pub size: Vec2,
+	/// Point the sprite is positioned and rotated around, from `[0, 0]` (top
+	/// left) to `[1, 1]` (bottom right).
+	pub origin: Vec2,
+	/// Clockwise rotation in radians.
+	pub rotation: f32,
+	/// Multiplied with the texture color.
+	pub color: Vec4,
+	/// Sprites on lower layers are drawn first.
+	pub layer: i32,
+}
+
+impl Sprite {
+	/// Whole texture at `position` with its top left corner as the origin.
+	pub fn new(texture: Texture, position: Vec2, size: Vec2) -> Self {
+		Sprite {
+			texture,
+			region: [0.0, 0.0, 1.0, 1.0],
+			position,
+			size,
+			origin: [0.0, 0.0],
+			rotation: 0.0,
+			color: [1.0; 4],
+			layer: 0,
+		}
+	}
+
+	/// World space corners in top left, top right, bottom left, bottom right order.
+	pub fn corners(&self) -> [Vec2; 4] {
+		let (sin, cos) = self.rotation.sin_cos();
+		let corner = |x: f32, y: f32| {
+			let local = [
+				(x - self.origin[0]) * self.size[0],
+				(y - self.origin[1]) * self.size[1],
+			];
+			[
+				self.position[0] + local[0] * cos - local[1] * sin,
+				self.position[1] + local[0] * sin + local[1] * cos,
+			]
+		};
+		[
+			corner(0.0, 0.0),
+			corner(1.0, 0.0),
+			corner(0.0, 1.0),
+			corner(1.0, 1.0),
+		]
+	}
+}
@@ -0,0 +1,115 @@
+use super::{texture_size, Sprite, SpriteBatch, Texture};
+use crate::math::{Vec2, Vec4};
+
+/// A panel image whose corners keep their size while the edges and center
+/// stretch, for ui panels and buttons of any size.
+#[derive(Clone)]
+pub struct NineSlice {
+	pub texture: Texture,
+	/// Part of the texture holding the panel, normalized like `Sprite::region`.
+	pub region: [f32; 4],
+	/// Border widths as `[left, top, right, bottom]` in texture pixels.
+	pub insets: [f32; 4],
+	/// World units per texture pixel of the borders.
+	pub border_scale: f32,
+	pub color: Vec4,
+	pub layer: i32,
+}
+
+impl NineSlice {
+	/// Whole texture as the panel.
+	pub fn new(texture: Texture, insets: [f32; 4]) -> Self {
+		NineSlice {
+			texture,
+			region: [0.0, 0.0, 1.0, 1.0],
+			insets,
+			border_scale: 1.0,
+			color: [1.0; 4],
+			layer: 0,
+		}
+	}
+
+	/// The sprites covering a panel with its top left corner at `position`.
+	///
+	/// Borders are shrunk evenly when `size` is smaller than the borders
+	/// together. Pieces that end up empty are left out.
+	pub fn sprites(&self, position: Vec2, size: Vec2) -> Vec<Sprite> {
+		let [texture_width, texture_height] = texture_size(&self.texture);
+		let [left, top, right, bottom] = self.insets;
+
+		// border sizes in uv and in world units
+		let uv_x = [left / texture_width as f32, right / texture_width as f32];
+		let uv_y = [top / texture_height as f32, bottom / texture_height as f32];
+		let world_x = fit([left, right], self.border_scale, size[0]);
+		let world_y = fit([top, bottom], self.border_scale, size[1]);
+
+		// start and size of the three columns and rows
+		let [u, v, width, height] = self.region;
+		let columns = [
+			(position[0], world_x[0], u, uv_x[0]),
+			(
+				position[0] + world_x[0],
+				size[0] - world_x[0] - world_x[1],
+				u + uv_x[0],
+				width - uv_x[0] - uv_x[1],
+			),
+			(
+				position[0] + size[0] - world_x[1],
+				world_x[1],
+				u + width - uv_x[1],
+				uv_x[1],
+			),
+		];
+		let rows = [
+			(position[1], world_y[0], v, uv_y[0]),
+			(
+				position[1] + world_y[0],
+				size[1] - world_y[0] - world_y[1],
+				v + uv_y[0],
+				height - uv_y[0] - uv_y[1],
+			),
+			(
+				position[1] + size[1] - world_y[1],
+				world_y[1],
+				v + height - uv_y[1],
+				uv_y[1],
+			),
+		];
+
+		let mut sprites = Vec::with_capacity(9);
+		for &(y, row_height, row_v, row_uv_height) in &rows {
+			for &(x, column_width, column_u, column_uv_width) in &columns {
+				if column_width <= 0.0 || row_height <= 0.0 {
+					continue;
+				}
+				sprites.push(Sprite {
+					region: [column_u, row_v, column_uv_width, row_uv_height],
+					color: self.color,
+					layer: self.layer,
+					..Sprite::new(self.texture.clone(), [x, y], [column_width, row_height])
+				});
+			}
+		}
+		sprites
+	}
+}
+
+impl SpriteBatch {
+	/// Queues a nine-slice panel for the next `flush`.
+	pub fn draw_nine_slice(&mut self, panel: &NineSlice, position: Vec2, size: Vec2) {
+		for sprite in panel.sprites(position, size) {
+			self.draw(sprite);
+		}
+	}
+}
+
+// scales a pair of borders to world units, shrinking them to fit in `available`
+fn fit(borders: [f32; 2], scale: f32, available: f32) -> [f32; 2] {
+	let total = (borders[0] + borders[1]) * scale;
+	let shrink = if total > available && total > 0.0 {
+		available.max(0.0) / total
+	} else {
+		1.0
+	};
+	[borders[0] * scale * shrink, borders[1] * scale * shrink]
+}
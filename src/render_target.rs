@@ -0,0 +1,493 @@
+use crate::render2d::Texture;
+use crate::scene::{Camera, CameraTarget};
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::pipeline::viewport::Viewport;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Depth format of every render target, the one format vulkan guarantees can
+/// be both a depth attachment and sampled.
+pub const DEPTH_FORMAT: Format = Format::D16Unorm;
+
+/// Offscreen color and depth images to render a camera into, and then sample
+/// like any other texture. Mirrors, in-game screens, portals and minimaps are
+/// all drawn this way.
+///
+/// Pipelines drawing into the target have to be built against its `subpass`.
+/// With more than one sample the color is resolved at the end of the pass,
+/// so `texture` is always single sampled.
+/// Record the target's pass before the pass that samples it, the command
+/// buffer builder takes care of the barrier in between.
+pub struct RenderTarget {
+	device: Arc<Device>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	format: Format,
+	samples: u32,
+	color: Texture,
+	depth: Texture,
+	framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+	// the depth only pass and its framebuffer when the pre-pass is on
+	prepass: Option<(
+		Arc<dyn RenderPassAbstract + Send + Sync>,
+		Arc<dyn FramebufferAbstract + Send + Sync>,
+	)>,
+	dynamic_state: DynamicState,
+	dimensions: [u32; 2],
+}
+
+impl RenderTarget {
+	pub fn new(device: Arc<Device>, dimensions: [u32; 2], format: Format) -> Self {
+		RenderTarget::with_samples(device, dimensions, format, 1)
+	}
+
+	/// Like `new`, drawn with `samples` samples per pixel for msaa.
+	pub fn with_samples(
+		device: Arc<Device>,
+		dimensions: [u32; 2],
+		format: Format,
+		samples: u32,
+	) -> Self {
+		let render_pass = scene_render_pass(device.clone(), format, samples, false);
+
+		let (color, depth, framebuffer) = create_framebuffer(
+			device.clone(),
+			render_pass.clone(),
+			dimensions,
+			format,
+			samples,
+		);
+
+		RenderTarget {
+			device,
+			render_pass,
+			format,
+			samples,
+			color,
+			depth,
+			framebuffer,
+			prepass: None,
+			dynamic_state: dynamic_state(dimensions),
+			dimensions,
+		}
+	}
+
+	/// Draws depth in a pass of its own before the target's pass, which then
+	/// keeps that depth instead of clearing it. Recreates the render pass and
+	/// the images like `resize`, pipelines built against the old `subpass`
+	/// stay compatible.
+	pub fn set_depth_prepass(&mut self, enabled: bool) {
+		if enabled == self.prepass.is_some() {
+			return;
+		}
+		self.render_pass =
+			scene_render_pass(self.device.clone(), self.format, self.samples, enabled);
+		self.prepass = None;
+		self.resize(self.dimensions);
+		if enabled {
+			self.prepass = Some(prepass(
+				self.device.clone(),
+				self.depth.clone(),
+				self.samples,
+			));
+		}
+	}
+
+	pub fn depth_prepass(&self) -> bool {
+		self.prepass.is_some()
+	}
+
+	/// Subpass depth pre-pass pipelines are built against, `None` while the
+	/// pre-pass is off. Compatible with shadow map pipelines, see
+	/// `depth_only_render_pass`, when the target has a single sample.
+	pub fn depth_prepass_subpass(
+		&self,
+	) -> Option<Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>> {
+		let (render_pass, _) = self.prepass.as_ref()?;
+		Some(Subpass::from(render_pass.clone(), 0).unwrap())
+	}
+
+	pub fn render_pass(&self) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+		self.render_pass.clone()
+	}
+
+	/// Subpass that pipelines drawing into the target have to be built against.
+	pub fn subpass(&self) -> Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		Subpass::from(self.render_pass.clone(), 0).unwrap()
+	}
+
+	pub fn dimensions(&self) -> [u32; 2] {
+		self.dimensions
+	}
+
+	pub fn samples(&self) -> u32 {
+		self.samples
+	}
+
+	/// Aspect ratio for the projection of the camera drawn into the target.
+	pub fn aspect_ratio(&self) -> f32 {
+		self.dimensions[0] as f32 / self.dimensions[1] as f32
+	}
+
+	/// Viewport covering the whole target, pass this to draws inside the pass.
+	pub fn dynamic_state(&self) -> &DynamicState {
+		&self.dynamic_state
+	}
+
+	/// The rendered color, bind it with a sampler to show the result.
+	pub fn texture(&self) -> Texture {
+		self.color.clone()
+	}
+
+	/// The rendered depth, eg. for soft particles drawn on top of the target.
+	/// Multisampled when the target is, it isn't resolved.
+	pub fn depth_texture(&self) -> Texture {
+		self.depth.clone()
+	}
+
+	/// Recreates the images at a new size. Descriptor sets and sprites holding
+	/// the old `texture` keep showing the old images and have to be recreated.
+	pub fn resize(&mut self, dimensions: [u32; 2]) {
+		let (color, depth, framebuffer) = create_framebuffer(
+			self.device.clone(),
+			self.render_pass.clone(),
+			dimensions,
+			self.format,
+			self.samples,
+		);
+		if self.prepass.is_some() {
+			self.prepass = Some(prepass(self.device.clone(), depth.clone(), self.samples));
+		}
+		self.color = color;
+		self.depth = depth;
+		self.framebuffer = framebuffer;
+		self.dynamic_state = dynamic_state(dimensions);
+		self.dimensions = dimensions;
+	}
+
+	/// Begins the target's render pass, clearing color to `clear_color` and
+	/// depth to 1, or keeping the depth of the pre-pass.
+	pub fn begin(&self, builder: &mut AutoCommandBufferBuilder, clear_color: ClearValue) {
+		let depth = if self.prepass.is_some() {
+			ClearValue::None
+		} else {
+			1f32.into()
+		};
+		builder
+			.begin_render_pass(
+				self.framebuffer.clone(),
+				SubpassContents::Inline,
+				if self.samples > 1 {
+					vec![clear_color, depth, ClearValue::None]
+				} else {
+					vec![clear_color, depth]
+				},
+			)
+			.unwrap();
+	}
+
+	/// Begins the depth pre-pass, clearing depth to 1. End it with `end`
+	/// before `begin`. Panics when the pre-pass is off.
+	pub fn begin_depth_prepass(&self, builder: &mut AutoCommandBufferBuilder) {
+		let (_, framebuffer) = self.prepass.as_ref().expect("depth pre-pass is off");
+		builder
+			.begin_render_pass(
+				framebuffer.clone(),
+				SubpassContents::Inline,
+				vec![1f32.into()],
+			)
+			.unwrap();
+	}
+
+	pub fn end(&self, builder: &mut AutoCommandBufferBuilder) {
+		builder.end_render_pass().unwrap();
+	}
+}
+
+/// The offscreen targets of cameras drawing into a texture, by the name in
+/// their `CameraTarget`:
+///
+/// ```text
+/// if let Some(target) = camera_targets.get_or_create(&camera) {
+///     target.begin(&mut builder, camera.clear_color_or(sky).into());
+///     // draw the scene as the camera sees it
+///     target.end(&mut builder);
+///     let minimap = post.run_target(&mut builder, target, &camera);
+/// }
+/// ```
+pub struct CameraTargets {
+	device: Arc<Device>,
+	format: Format,
+	samples: u32,
+	targets: HashMap<String, RenderTarget>,
+}
+
+impl CameraTargets {
+	/// Targets are created with `format` and `samples`, eg. `SCENE_FORMAT`
+	/// to run post effects on them.
+	pub fn new(device: Arc<Device>, format: Format, samples: u32) -> Self {
+		CameraTargets {
+			device,
+			format,
+			samples,
+			targets: HashMap::new(),
+		}
+	}
+
+	/// The target `camera` draws into, created or resized to the camera's
+	/// size. `None` for cameras drawing to the screen.
+	pub fn get_or_create(&mut self, camera: &Camera) -> Option<&mut RenderTarget> {
+		let (name, size) = match &camera.target {
+			CameraTarget::Texture { name, size } => (name, [size[0].max(1), size[1].max(1)]),
+			CameraTarget::Screen => return None,
+		};
+		let (device, format, samples) = (self.device.clone(), self.format, self.samples);
+		let target = self
+			.targets
+			.entry(name.clone())
+			.or_insert_with(|| RenderTarget::with_samples(device, size, format, samples));
+		if target.dimensions() != size {
+			target.resize(size);
+		}
+		Some(target)
+	}
+
+	pub fn get(&self, name: &str) -> Option<&RenderTarget> {
+		self.targets.get(name)
+	}
+
+	/// The rendered color of the target `name`, eg. to show a minimap.
+	pub fn texture(&self, name: &str) -> Option<Texture> {
+		self.targets.get(name).map(|target| target.texture())
+	}
+
+	pub fn remove(&mut self, name: &str) -> Option<RenderTarget> {
+		self.targets.remove(name)
+	}
+}
+
+fn dynamic_state(dimensions: [u32; 2]) -> DynamicState {
+	DynamicState {
+		viewports: Some(vec![Viewport {
+			origin: [0.0, 0.0],
+			dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+			depth_range: 0.0..1.0,
+		}]),
+		..DynamicState::none()
+	}
+}
+
+fn single_sampled_render_pass(
+	device: Arc<Device>,
+	format: Format,
+	depth_prepass: bool,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	macro_rules! render_pass {
+		($depth_load:ident) => {
+			Arc::new(
+				vulkano::single_pass_renderpass!(
+					device,
+					attachments: {
+						color: {
+							load: Clear,
+							store: Store,
+							format: format,
+							samples: 1,
+						},
+						depth: {
+							load: $depth_load,
+							store: Store,
+							format: DEPTH_FORMAT,
+							samples: 1,
+						}
+					},
+					pass: {
+						color: [color],
+						depth_stencil: {depth}
+					}
+				)
+				.unwrap(),
+			)
+		};
+	}
+	// the depth pre-pass already filled in the depth
+	if depth_prepass {
+		render_pass!(Load)
+	} else {
+		render_pass!(Clear)
+	}
+}
+
+fn multisampled_render_pass(
+	device: Arc<Device>,
+	format: Format,
+	samples: u32,
+	depth_prepass: bool,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	macro_rules! render_pass {
+		($depth_load:ident) => {
+			Arc::new(
+				vulkano::single_pass_renderpass!(
+					device,
+					attachments: {
+						color: {
+							load: Clear,
+							store: DontCare,
+							format: format,
+							samples: samples,
+						},
+						depth: {
+							load: $depth_load,
+							store: Store,
+							format: DEPTH_FORMAT,
+							samples: samples,
+						},
+						resolved: {
+							load: DontCare,
+							store: Store,
+							format: format,
+							samples: 1,
+						}
+					},
+					pass: {
+						color: [color],
+						depth_stencil: {depth},
+						resolve: [resolved]
+					}
+				)
+				.unwrap(),
+			)
+		};
+	}
+	if depth_prepass {
+		render_pass!(Load)
+	} else {
+		render_pass!(Clear)
+	}
+}
+
+/// A pass drawing only depth, `DEPTH_FORMAT` with `samples` samples and
+/// cleared to 1. Shadow maps are drawn in one, and so is a target's depth
+/// pre-pass, so single sampled targets can draw it with the shadow
+/// pipelines.
+pub fn depth_only_render_pass(
+	device: Arc<Device>,
+	samples: u32,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	Arc::new(
+		vulkano::single_pass_renderpass!(
+			device,
+			attachments: {
+				depth: {
+					load: Clear,
+					store: Store,
+					format: DEPTH_FORMAT,
+					samples: samples,
+				}
+			},
+			pass: {
+				color: [],
+				depth_stencil: {depth}
+			}
+		)
+		.unwrap(),
+	)
+}
+
+fn scene_render_pass(
+	device: Arc<Device>,
+	format: Format,
+	samples: u32,
+	depth_prepass: bool,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	if samples > 1 {
+		multisampled_render_pass(device, format, samples, depth_prepass)
+	} else {
+		single_sampled_render_pass(device, format, depth_prepass)
+	}
+}
+
+fn prepass(
+	device: Arc<Device>,
+	depth: Texture,
+	samples: u32,
+) -> (
+	Arc<dyn RenderPassAbstract + Send + Sync>,
+	Arc<dyn FramebufferAbstract + Send + Sync>,
+) {
+	let render_pass = depth_only_render_pass(device, samples);
+	let framebuffer = Arc::new(
+		Framebuffer::start(render_pass.clone())
+			.add(depth)
+			.unwrap()
+			.build()
+			.unwrap(),
+	) as Arc<dyn FramebufferAbstract + Send + Sync>;
+	(render_pass, framebuffer)
+}
+
+fn create_framebuffer(
+	device: Arc<Device>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	dimensions: [u32; 2],
+	format: Format,
+	samples: u32,
+) -> (Texture, Texture, Arc<dyn FramebufferAbstract + Send + Sync>) {
+	let usage = ImageUsage {
+		sampled: true,
+		transfer_source: true,
+		..ImageUsage::none()
+	};
+	let color = ImageView::new(
+		AttachmentImage::with_usage(device.clone(), dimensions, format, usage).unwrap(),
+	)
+	.unwrap();
+
+	if samples > 1 {
+		let multisampled = ImageView::new(
+			AttachmentImage::transient_multisampled(device.clone(), dimensions, samples, format)
+				.unwrap(),
+		)
+		.unwrap();
+		let depth = ImageView::new(
+			AttachmentImage::sampled_multisampled(device, dimensions, samples, DEPTH_FORMAT)
+				.unwrap(),
+		)
+		.unwrap();
+		let framebuffer = Arc::new(
+			Framebuffer::start(render_pass)
+				.add(multisampled)
+				.unwrap()
+				.add(depth.clone())
+				.unwrap()
+				.add(color.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		) as Arc<dyn FramebufferAbstract + Send + Sync>;
+		return (color, depth, framebuffer);
+	}
+
+	let depth = ImageView::new(
+		AttachmentImage::with_usage(device, dimensions, DEPTH_FORMAT, usage).unwrap(),
+	)
+	.unwrap();
+
+	let framebuffer = Arc::new(
+		Framebuffer::start(render_pass)
+			.add(color.clone())
+			.unwrap()
+			.add(depth.clone())
+			.unwrap()
+			.build()
+			.unwrap(),
+	) as Arc<dyn FramebufferAbstract + Send + Sync>;
+
+	(color, depth, framebuffer)
+}
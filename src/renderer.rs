@@ -0,0 +1,524 @@
+// ties the window, the scene target and the graphics settings together and
+// drives the frame: acquire, draw the scene, composite, present.
+
+use crate::async_compute::AsyncCompute;
+use crate::breadcrumbs::Breadcrumbs;
+use crate::compositor::Compositor;
+use crate::debug_labels;
+use crate::frame_dump::FrameDump;
+use crate::gpu::vulkan::{VulkanEncoder, VulkanSampler, VulkanTarget, VulkanTexture};
+use crate::gpu::{GpuDevice, GpuError, VulkanDevice};
+use crate::passes::{PassContext, PassId, PassStage, RenderPasses};
+use crate::render2d::Texture;
+use crate::resolution::{DynamicResolution, ScaleMode, Upscaler};
+use crate::sampler::{SamplerDesc, Samplers};
+use crate::settings::{GraphicsSettings, SettingsChanges};
+use crate::viewport::View;
+use crate::watchdog::Watchdog;
+use crate::window::{Frame, WindowTarget};
+
+use vulkano::device::Device;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Renders the scene into the window at the resolution and quality the
+/// current `GraphicsSettings` ask for.
+///
+/// A frame is `begin_frame`, drawing the scene into `resolution` and
+/// compositing it onto the window with an `encoder`, then `end_frame`. Passes added with
+/// `add_pass` are recorded where the frame calls `run_passes`:
+///
+/// ```text
+/// resolution.begin_depth_prepass(..)  run_passes(DepthPrepass)  resolution.end(..)
+/// resolution.begin(..)
+///     run_passes(BeforeOpaque)  opaque  run_passes(AfterOpaque)  transparent
+/// resolution.end(..)
+/// run_passes(BeforePost)
+/// compositor().upscale(..)
+/// window pass: composite  run_passes(AfterUi)
+/// ```
+///
+/// `composite` draws the layers of `compositor_mut`, the scene and whatever
+/// else was added, eg. other cameras' targets and the ui.
+///
+/// The depth pre-pass is only there with `GraphicsSettings::depth_prepass`.
+///
+/// Compute passes on the async compute queue are dispatched with
+/// `async_compute_mut`, `end_frame` waits for them.
+pub struct Renderer {
+	gpu: VulkanDevice,
+	window: WindowTarget,
+	resolution: DynamicResolution,
+	settings: GraphicsSettings,
+	pending: Option<GraphicsSettings>,
+	changes: SettingsChanges,
+	samplers: Samplers,
+	// set_default_sampler was called since the last frame
+	sampler_changed: bool,
+	// scale_factor_changed was called since the last frame
+	scale_factor_changed: bool,
+	last_frame: Option<Instant>,
+	passes: RenderPasses,
+	compute: AsyncCompute,
+	breadcrumbs: Option<Breadcrumbs>,
+	dump: Option<FrameDump>,
+	watchdog: Option<Watchdog>,
+	compositor: Compositor,
+}
+
+// markers a frame can record with `Renderer::mark`
+const BREADCRUMBS: usize = 64;
+
+// hitches `Renderer::watchdog` keeps
+const HITCHES: usize = 64;
+
+impl Renderer {
+	pub fn new(gpu: VulkanDevice, window: WindowTarget, settings: GraphicsSettings) -> Self {
+		let (device, queue) = (gpu.device().clone(), gpu.queue().clone());
+		let resolution =
+			DynamicResolution::new(device.clone(), window.subpass(), window.dimensions());
+		let compositor = Compositor::new(device.clone(), window.subpass());
+		// what the window and target were created with, `apply` changes the rest
+		let current = GraphicsSettings {
+			vsync: window.config().vsync,
+			anisotropy: settings.anisotropy,
+			..GraphicsSettings::default()
+		};
+		let mut renderer = Renderer {
+			compute: AsyncCompute::new(device.clone(), queue.clone()),
+			samplers: Samplers::new(device, settings.anisotropy),
+			gpu,
+			window,
+			resolution,
+			settings: current,
+			pending: None,
+			changes: SettingsChanges::default(),
+			sampler_changed: false,
+			scale_factor_changed: false,
+			last_frame: None,
+			passes: RenderPasses::new(),
+			breadcrumbs: None,
+			dump: None,
+			watchdog: None,
+			compositor,
+		};
+		renderer.apply(settings);
+		renderer.changes = SettingsChanges::default();
+		renderer
+	}
+
+	pub fn gpu(&self) -> &VulkanDevice {
+		&self.gpu
+	}
+
+	/// Records a frame's commands, hand it to `end_frame` once done.
+	pub fn encoder(&self) -> Result<VulkanEncoder, GpuError> {
+		self.gpu.create_encoder()
+	}
+
+	pub fn window(&self) -> &WindowTarget {
+		&self.window
+	}
+
+	pub fn window_mut(&mut self) -> &mut WindowTarget {
+		&mut self.window
+	}
+
+	/// The scene target, draw the scene between its `begin` and `end`.
+	pub fn resolution(&self) -> &DynamicResolution {
+		&self.resolution
+	}
+
+	pub fn resolution_mut(&mut self) -> &mut DynamicResolution {
+		&mut self.resolution
+	}
+
+	/// The settings in effect, pending ones show up here after the next `begin_frame`.
+	pub fn settings(&self) -> &GraphicsSettings {
+		&self.settings
+	}
+
+	/// Sampler for material textures, `set_default_sampler` with the
+	/// anisotropy from the settings.
+	pub fn sampler(&self) -> VulkanSampler {
+		self.gpu.import_sampler(self.samplers.default_sampler())
+	}
+
+	/// Sampler for textures that want something other than the default, eg.
+	/// a material's `sampler`. Created once per distinct description.
+	pub fn sampler_for(&mut self, desc: &SamplerDesc) -> VulkanSampler {
+		self.gpu.import_sampler(self.samplers.get(desc))
+	}
+
+	/// Changes what `sampler` gives, takes effect right away and flags
+	/// `SettingsChanges::sampler` on the next frame.
+	pub fn set_default_sampler(&mut self, desc: SamplerDesc) {
+		if desc != self.samplers.default_desc() {
+			self.samplers.set_default(desc);
+			self.sampler_changed = true;
+		}
+	}
+
+	/// What the settings applied by the last `begin_frame` invalidated.
+	pub fn changes(&self) -> SettingsChanges {
+		self.changes
+	}
+
+	/// Queues new settings. They are applied all at once at the start of the
+	/// next frame, while nothing is in flight.
+	pub fn apply_settings(&mut self, settings: GraphicsSettings) {
+		self.pending = Some(settings);
+	}
+
+	/// Call on `WindowEvent::Resized`.
+	pub fn resized(&mut self) {
+		self.window.resized();
+	}
+
+	/// Call on `WindowEvent::ScaleFactorChanged`, flags
+	/// `SettingsChanges::scale_factor` on the next frame.
+	pub fn scale_factor_changed(&mut self, scale_factor: f64) {
+		if scale_factor != self.window.scale_factor() {
+			self.window.scale_factor_changed(scale_factor);
+			self.scale_factor_changed = true;
+		}
+	}
+
+	/// See `WindowTarget::scale_factor`.
+	pub fn scale_factor(&self) -> f64 {
+		self.window.scale_factor()
+	}
+
+	/// Applies pending settings and acquires the next window image, see
+	/// `WindowTarget::acquire`. Check `changes` after this returns.
+	pub fn begin_frame(&mut self) -> Option<Frame> {
+		self.changes = SettingsChanges {
+			sampler: std::mem::take(&mut self.sampler_changed),
+			scale_factor: std::mem::take(&mut self.scale_factor_changed),
+			..SettingsChanges::default()
+		};
+		if let Some(settings) = self.pending.take() {
+			self.apply(settings);
+		}
+
+		if let Some(watchdog) = &mut self.watchdog {
+			watchdog.begin_frame();
+		}
+		self.begin_span("acquire");
+		let frame = self.window.acquire();
+		self.end_span();
+		let frame = frame?;
+		if self.window.dimensions() != self.resolution.window_dimensions() {
+			self.resolution.window_resized(self.window.dimensions());
+		}
+
+		// frame to frame time stands in for gpu time, which vulkano can't
+		// measure. with vsync it's just the refresh interval
+		let now = Instant::now();
+		if let Some(last) = self.last_frame {
+			if !self.settings.vsync {
+				self.resolution.report_frame_time(now - last);
+			}
+		}
+		self.last_frame = Some(now);
+
+		if let Some(breadcrumbs) = &mut self.breadcrumbs {
+			breadcrumbs.begin_frame();
+		}
+		// a dumped frame is written once the gpu is done with it
+		let written = self.dump.as_mut().and_then(|dump| {
+			dump.begin_frame();
+			dump.write()
+		});
+		if let Some(written) = written {
+			match written {
+				Ok(targets) => log::info!("Dumped {} targets of a frame", targets),
+				Err(e) => log::warn!("Couldn't dump the frame: {}", e),
+			}
+			self.dump = None;
+		}
+
+		Some(frame)
+	}
+
+	pub fn compositor(&self) -> &Compositor {
+		&self.compositor
+	}
+
+	/// The layers drawn into the window, add a layer for anything drawn
+	/// there besides the scene.
+	pub fn compositor_mut(&mut self) -> &mut Compositor {
+		&mut self.compositor
+	}
+
+	/// Upscales the compositor's scene layer, outside of a render pass and
+	/// before the window pass.
+	pub fn upscale(&mut self, encoder: &mut VulkanEncoder) {
+		self.compositor.upscale(encoder, &self.resolution);
+	}
+
+	/// Draws the compositor's layers, first thing in the window pass.
+	pub fn composite(&mut self, encoder: &mut VulkanEncoder) {
+		self.begin_span("composite");
+		let dynamic_state = self.window.dynamic_state().clone();
+		self.compositor
+			.record(encoder, &self.resolution, &dynamic_state);
+		self.end_span();
+	}
+
+	/// Adds a pass recorded at `stage` of every frame, see `PassStage`.
+	pub fn add_pass<F>(&mut self, stage: PassStage, callback: F) -> PassId
+	where
+		F: FnMut(&mut VulkanEncoder, &PassContext) + 'static,
+	{
+		self.passes.add(stage, callback)
+	}
+
+	pub fn remove_pass(&mut self, id: PassId) -> bool {
+		self.passes.remove(id)
+	}
+
+	/// The added passes, to order or disable them.
+	pub fn passes_mut(&mut self) -> &mut RenderPasses {
+		&mut self.passes
+	}
+
+	/// Records the passes added for `stage`. `view` is the camera being
+	/// drawn, passed on to the passes.
+	pub fn run_passes(
+		&mut self,
+		stage: PassStage,
+		encoder: &mut VulkanEncoder,
+		view: Option<&View>,
+	) {
+		let scene_color = self.scene_texture(self.resolution.texture());
+		let scene_depth = self.scene_texture(self.resolution.depth_texture());
+		if stage == PassStage::BeforePost {
+			self.mark(encoder, "before post");
+			self.capture(encoder, "scene color", &scene_color);
+			self.capture(encoder, "scene depth", &scene_depth);
+		}
+		if !self.passes.has(stage) {
+			return;
+		}
+		let (subpass, dynamic_state) = match stage {
+			PassStage::DepthPrepass => match self.resolution.depth_prepass_subpass() {
+				Some(subpass) => (Some(subpass), self.resolution.dynamic_state()),
+				None => return,
+			},
+			PassStage::BeforeOpaque | PassStage::AfterOpaque => (
+				Some(self.resolution.subpass()),
+				self.resolution.dynamic_state(),
+			),
+			PassStage::BeforePost => (None, self.resolution.dynamic_state()),
+			PassStage::AfterUi => (Some(self.window.subpass()), self.window.dynamic_state()),
+		};
+		encoder.set_dynamic_state(dynamic_state);
+		let context = PassContext {
+			stage,
+			target: subpass.map(VulkanTarget::new),
+			view,
+			scene_color,
+			scene_depth,
+			render_dimensions: self.resolution.render_dimensions(),
+			window_dimensions: self.window.dimensions(),
+		};
+		let label = format!("{:?} passes", stage);
+		if let Some(watchdog) = &mut self.watchdog {
+			watchdog.begin_span(&label);
+		}
+		debug_labels::push(encoder.builder(), &label, debug_labels::NO_COLOR);
+		self.passes.run(encoder, &context);
+		debug_labels::pop(encoder.builder());
+		if let Some(watchdog) = &mut self.watchdog {
+			watchdog.end_span();
+		}
+	}
+
+	pub fn async_compute(&self) -> &AsyncCompute {
+		&self.compute
+	}
+
+	pub fn async_compute_mut(&mut self) -> &mut AsyncCompute {
+		&mut self.compute
+	}
+
+	/// Records gpu breadcrumbs with `mark` and logs how far the gpu got
+	/// when the device is lost. Off by default, see `EngineConfig::breadcrumbs`.
+	pub fn set_breadcrumbs(&mut self, enabled: bool) {
+		if !enabled {
+			self.breadcrumbs = None;
+		} else if self.breadcrumbs.is_none() {
+			self.breadcrumbs = Breadcrumbs::new(self.gpu.device().clone(), BREADCRUMBS);
+			match &self.breadcrumbs {
+				Some(breadcrumbs) => breadcrumbs.report_on_panic(),
+				None => log::warn!("No host coherent memory for gpu breadcrumbs"),
+			}
+		}
+	}
+
+	pub fn breadcrumbs(&self) -> Option<&Breadcrumbs> {
+		self.breadcrumbs.as_ref()
+	}
+
+	/// Records a breadcrumb marker outside of a render pass, eg. between the
+	/// shadow and scene passes. Does nothing unless breadcrumbs are enabled.
+	pub fn mark(&mut self, encoder: &mut VulkanEncoder, label: &str) {
+		if let Some(breadcrumbs) = &mut self.breadcrumbs {
+			breadcrumbs.mark(encoder.builder(), label);
+		}
+	}
+
+	/// Writes every target of the next frame passed to `capture` into `dir`,
+	/// as images next to a `manifest.json`, a lightweight alternative to
+	/// attaching renderdoc. The scene color and depth are captured by the
+	/// renderer itself. The files are written a frame or two later.
+	pub fn dump_frame(&mut self, dir: impl Into<PathBuf>) {
+		self.dump = Some(FrameDump::new(dir));
+	}
+
+	/// Whether this frame is being dumped, to skip work only done for
+	/// `capture`.
+	pub fn is_dumping(&self) -> bool {
+		self.dump.as_ref().is_some_and(FrameDump::is_recording)
+	}
+
+	/// Adds an intermediate target, eg. a g-buffer layer, shadow map or bloom
+	/// mip, to the frame being dumped. Record it outside of render passes
+	/// once the target is written. Does nothing unless `dump_frame` was
+	/// called.
+	pub fn capture(&mut self, encoder: &mut VulkanEncoder, name: &str, texture: &VulkanTexture) {
+		if let Some(dump) = &mut self.dump {
+			dump.capture(encoder.builder(), name, texture.view());
+		}
+	}
+
+	/// Flags frames taking longer than `budget` and keeps what they spent
+	/// their time on, see `watchdog::Watchdog`. `None` turns it off, see
+	/// `EngineConfig::frame_budget`.
+	pub fn set_frame_budget(&mut self, budget: Option<Duration>) {
+		match (budget, &mut self.watchdog) {
+			(None, _) => self.watchdog = None,
+			(Some(budget), Some(watchdog)) => watchdog.set_budget(budget),
+			(Some(budget), None) => self.watchdog = Some(Watchdog::new(budget, HITCHES)),
+		}
+	}
+
+	/// The hitches caught since `set_frame_budget`.
+	pub fn watchdog(&self) -> Option<&Watchdog> {
+		self.watchdog.as_ref()
+	}
+
+	pub fn watchdog_mut(&mut self) -> Option<&mut Watchdog> {
+		self.watchdog.as_mut()
+	}
+
+	/// Times the work until `end_span` for the watchdog, eg. updating
+	/// particles. Does nothing unless a frame budget is set.
+	pub fn begin_span(&mut self, name: &str) {
+		if let Some(watchdog) = &mut self.watchdog {
+			watchdog.begin_span(name);
+		}
+	}
+
+	pub fn end_span(&mut self) {
+		if let Some(watchdog) = &mut self.watchdog {
+			watchdog.end_span();
+		}
+	}
+
+	/// Submits the frame's commands after everything submitted through
+	/// `async_compute_mut` this frame, and presents.
+	pub fn end_frame(&mut self, frame: Frame, encoder: VulkanEncoder) {
+		let command_buffer = encoder.finish().unwrap();
+		if self.compute.has_pending() {
+			self.window.join(self.compute.finish());
+		}
+		let lost = self.window.is_device_lost();
+		self.begin_span("present");
+		self.window.present(frame, command_buffer);
+		self.end_span();
+		if let Some(dump) = &mut self.dump {
+			dump.end_frame();
+		}
+		if !lost && self.window.is_device_lost() {
+			log::error!("Device lost");
+			if let Some(breadcrumbs) = &self.breadcrumbs {
+				log::error!("{}", breadcrumbs.report());
+			}
+		}
+	}
+
+	fn scene_texture(&self, view: Texture) -> VulkanTexture {
+		self.gpu
+			.import_texture(view, self.resolution.target_dimensions())
+	}
+
+	fn apply(&mut self, settings: GraphicsSettings) {
+		let old = std::mem::replace(&mut self.settings, settings.clone());
+
+		if settings.vsync != old.vsync {
+			let render_pass = self.window.render_pass();
+			let mut config = self.window.config();
+			config.vsync = settings.vsync;
+			self.window.set_config(config);
+			self.changes.window_render_pass =
+				!Arc::ptr_eq(&render_pass, &self.window.render_pass());
+		}
+
+		let samples = supported_samples(self.gpu.device(), settings.msaa);
+		if samples != self.resolution.samples() {
+			self.gpu.queue().wait().unwrap();
+			self.resolution.set_samples(samples);
+			self.changes.msaa = true;
+		}
+		self.settings.msaa = samples;
+
+		self.resolution.mode = match settings.target_frame_time {
+			Some(target) => {
+				if settings.vsync {
+					log::warn!("automatic render scale needs vsync off, keeping the scale fixed");
+				}
+				ScaleMode::Automatic { target }
+			}
+			None => ScaleMode::Fixed,
+		};
+		self.resolution.set_scale(settings.render_scale);
+		self.resolution.upscaler = if settings.post.upscaling {
+			Upscaler::Fsr {
+				sharpness: settings.post.sharpness,
+			}
+		} else {
+			Upscaler::Bilinear
+		};
+
+		self.changes.shadows = settings.shadow_quality != old.shadow_quality;
+
+		if settings.depth_prepass != old.depth_prepass {
+			self.resolution.set_depth_prepass(settings.depth_prepass);
+			self.changes.depth_prepass = true;
+		}
+
+		if settings.anisotropy != old.anisotropy {
+			self.samplers.set_anisotropy(settings.anisotropy);
+			self.changes.sampler = true;
+		}
+	}
+}
+
+// largest sample count the device supports for color and depth up to `requested`
+fn supported_samples(device: &Device, requested: u32) -> u32 {
+	let limits = device.physical_device().limits();
+	let supported =
+		limits.framebuffer_color_sample_counts() & limits.framebuffer_depth_sample_counts();
+	let mut samples = requested.max(1).next_power_of_two();
+	if samples > requested.max(1) {
+		samples /= 2;
+	}
+	while samples > 1 && supported & samples == 0 {
+		samples /= 2;
+	}
+	samples
+}
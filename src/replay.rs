@@ -0,0 +1,553 @@
+// recording input and frame times to a file and playing them back frame for
+// frame, so a performance regression or a rendering bug can be reproduced
+// exactly. a replayed frame gets the events and the time step its recording
+// got, whatever the wall clock says, so the app ends up in the same state as
+// long as its own simulation only depends on those.
+//
+// the file is text, one record per line, floats written so they read back
+// exactly:
+//
+// frame 0.016667              a frame and how many seconds it advanced
+// key 30 17 1                 scancode, virtual keycode or -, pressed
+// modifiers 4                 `ModifiersState` bits
+// button 0 1                  0 left, 1 right, 2 middle, 3+n other n
+// wheel line 0 -1 | wheel pixel 0 -12
+// cursor 640.5 360            physical pixels
+// cursor_left
+// motion 1.5 -2               raw mouse motion
+// char 97                     typed character as a code point
+// touch 3 started 100 200 0.5 id, phase, position and force or -
+// focus 1
+//
+// the window size isn't recorded, play back at the size it was recorded at.
+
+use winit::dpi::PhysicalPosition;
+use winit::event::{
+	DeviceEvent, DeviceId, ElementState, Event, Force, KeyboardInput, ModifiersState, MouseButton,
+	MouseScrollDelta, Touch, TouchPhase, VirtualKeyCode, WindowEvent,
+};
+use winit::window::WindowId;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Whether the engine records or plays back, see `Replay`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ReplayMode {
+	#[default]
+	Off,
+	/// Records to the file, replacing it.
+	Record(PathBuf),
+	Play(PathBuf),
+}
+
+/// An input event in a form that can be written out and read back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayEvent {
+	Key {
+		scancode: u32,
+		keycode: Option<VirtualKeyCode>,
+		pressed: bool,
+	},
+	Modifiers(ModifiersState),
+	MouseButton {
+		button: MouseButton,
+		pressed: bool,
+	},
+	Wheel(MouseScrollDelta),
+	CursorMoved([f64; 2]),
+	CursorLeft,
+	MouseMotion([f64; 2]),
+	Character(char),
+	Touch {
+		id: u64,
+		phase: TouchPhase,
+		position: [f64; 2],
+		/// Normalized, from 0 to 1.
+		force: Option<f64>,
+	},
+	Focused(bool),
+}
+
+impl ReplayEvent {
+	/// The recordable part of `event`, `None` for everything else.
+	pub fn from_event<T>(event: &Event<T>) -> Option<ReplayEvent> {
+		let event = match event {
+			Event::DeviceEvent {
+				event: DeviceEvent::MouseMotion { delta },
+				..
+			} => return Some(ReplayEvent::MouseMotion([delta.0, delta.1])),
+			Event::WindowEvent { event, .. } => event,
+			_ => return None,
+		};
+		Some(match event {
+			WindowEvent::KeyboardInput { input, .. } => ReplayEvent::Key {
+				scancode: input.scancode,
+				keycode: input.virtual_keycode,
+				pressed: input.state == ElementState::Pressed,
+			},
+			WindowEvent::ModifiersChanged(modifiers) => ReplayEvent::Modifiers(*modifiers),
+			WindowEvent::MouseInput { state, button, .. } => ReplayEvent::MouseButton {
+				button: *button,
+				pressed: *state == ElementState::Pressed,
+			},
+			WindowEvent::MouseWheel { delta, .. } => ReplayEvent::Wheel(*delta),
+			WindowEvent::CursorMoved { position, .. } => {
+				ReplayEvent::CursorMoved([position.x, position.y])
+			}
+			WindowEvent::CursorLeft { .. } => ReplayEvent::CursorLeft,
+			WindowEvent::ReceivedCharacter(c) => ReplayEvent::Character(*c),
+			WindowEvent::Touch(touch) => ReplayEvent::Touch {
+				id: touch.id,
+				phase: touch.phase,
+				position: [touch.location.x, touch.location.y],
+				force: touch.force.map(|force| force.normalized()),
+			},
+			WindowEvent::Focused(focused) => ReplayEvent::Focused(*focused),
+			_ => return None,
+		})
+	}
+
+	/// The event again, as if it came from the window `window_id`.
+	#[allow(deprecated)]
+	pub fn to_event<T>(&self, window_id: WindowId) -> Event<'static, T> {
+		// there is no real device behind a replayed event
+		let device_id = unsafe { DeviceId::dummy() };
+		let modifiers = ModifiersState::empty();
+		let state = |pressed| {
+			if pressed {
+				ElementState::Pressed
+			} else {
+				ElementState::Released
+			}
+		};
+		let event = match *self {
+			ReplayEvent::MouseMotion(delta) => {
+				return Event::DeviceEvent {
+					device_id,
+					event: DeviceEvent::MouseMotion {
+						delta: (delta[0], delta[1]),
+					},
+				};
+			}
+			ReplayEvent::Key {
+				scancode,
+				keycode,
+				pressed,
+			} => WindowEvent::KeyboardInput {
+				device_id,
+				input: KeyboardInput {
+					scancode,
+					state: state(pressed),
+					virtual_keycode: keycode,
+					modifiers,
+				},
+				is_synthetic: false,
+			},
+			ReplayEvent::Modifiers(modifiers) => WindowEvent::ModifiersChanged(modifiers),
+			ReplayEvent::MouseButton { button, pressed } => WindowEvent::MouseInput {
+				device_id,
+				state: state(pressed),
+				button,
+				modifiers,
+			},
+			ReplayEvent::Wheel(delta) => WindowEvent::MouseWheel {
+				device_id,
+				delta,
+				phase: TouchPhase::Moved,
+				modifiers,
+			},
+			ReplayEvent::CursorMoved(position) => WindowEvent::CursorMoved {
+				device_id,
+				position: PhysicalPosition::new(position[0], position[1]),
+				modifiers,
+			},
+			ReplayEvent::CursorLeft => WindowEvent::CursorLeft { device_id },
+			ReplayEvent::Character(c) => WindowEvent::ReceivedCharacter(c),
+			ReplayEvent::Touch {
+				id,
+				phase,
+				position,
+				force,
+			} => WindowEvent::Touch(Touch {
+				device_id,
+				phase,
+				location: PhysicalPosition::new(position[0], position[1]),
+				force: force.map(Force::Normalized),
+				id,
+			}),
+			ReplayEvent::Focused(focused) => WindowEvent::Focused(focused),
+		};
+		Event::WindowEvent { window_id, event }
+	}
+
+	/// Reads a line written by `Display`, `None` when it isn't an event.
+	pub fn parse(line: &str) -> Option<ReplayEvent> {
+		let mut words = line.split_whitespace();
+		let kind = words.next()?;
+		let mut next = || words.next();
+		let event = match kind {
+			"key" => ReplayEvent::Key {
+				scancode: next()?.parse().ok()?,
+				keycode: match next()? {
+					"-" => None,
+					code => Some(keycode(code.parse().ok()?)?),
+				},
+				pressed: flag(next()?)?,
+			},
+			"modifiers" => {
+				ReplayEvent::Modifiers(ModifiersState::from_bits_truncate(next()?.parse().ok()?))
+			}
+			"button" => ReplayEvent::MouseButton {
+				button: match next()?.parse::<u16>().ok()? {
+					0 => MouseButton::Left,
+					1 => MouseButton::Right,
+					2 => MouseButton::Middle,
+					n => MouseButton::Other(n - 3),
+				},
+				pressed: flag(next()?)?,
+			},
+			"wheel" => {
+				let unit = next()?;
+				let x: f64 = next()?.parse().ok()?;
+				let y: f64 = next()?.parse().ok()?;
+				ReplayEvent::Wheel(match unit {
+					"line" => MouseScrollDelta::LineDelta(x as f32, y as f32),
+					"pixel" => MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y)),
+					_ => return None,
+				})
+			}
+			"cursor" => ReplayEvent::CursorMoved([next()?.parse().ok()?, next()?.parse().ok()?]),
+			"cursor_left" => ReplayEvent::CursorLeft,
+			"motion" => ReplayEvent::MouseMotion([next()?.parse().ok()?, next()?.parse().ok()?]),
+			"char" => ReplayEvent::Character(char::from_u32(next()?.parse().ok()?)?),
+			"touch" => ReplayEvent::Touch {
+				id: next()?.parse().ok()?,
+				phase: match next()? {
+					"started" => TouchPhase::Started,
+					"moved" => TouchPhase::Moved,
+					"ended" => TouchPhase::Ended,
+					"cancelled" => TouchPhase::Cancelled,
+					_ => return None,
+				},
+				position: [next()?.parse().ok()?, next()?.parse().ok()?],
+				force: match next()? {
+					"-" => None,
+					force => Some(force.parse().ok()?),
+				},
+			},
+			"focus" => ReplayEvent::Focused(flag(next()?)?),
+			_ => return None,
+		};
+		// trailing words mean a newer or broken file
+		match next() {
+			Some(_) => None,
+			None => Some(event),
+		}
+	}
+}
+
+impl fmt::Display for ReplayEvent {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ReplayEvent::Key {
+				scancode,
+				keycode,
+				pressed,
+			} => match keycode {
+				Some(keycode) => {
+					write!(f, "key {} {} {}", scancode, *keycode as u32, *pressed as u8)
+				}
+				None => write!(f, "key {} - {}", scancode, *pressed as u8),
+			},
+			ReplayEvent::Modifiers(modifiers) => write!(f, "modifiers {}", modifiers.bits()),
+			ReplayEvent::MouseButton { button, pressed } => {
+				let button = match button {
+					MouseButton::Left => 0,
+					MouseButton::Right => 1,
+					MouseButton::Middle => 2,
+					MouseButton::Other(n) => *n as u32 + 3,
+				};
+				write!(f, "button {} {}", button, *pressed as u8)
+			}
+			ReplayEvent::Wheel(MouseScrollDelta::LineDelta(x, y)) => {
+				write!(f, "wheel line {} {}", x, y)
+			}
+			ReplayEvent::Wheel(MouseScrollDelta::PixelDelta(delta)) => {
+				write!(f, "wheel pixel {} {}", delta.x, delta.y)
+			}
+			ReplayEvent::CursorMoved([x, y]) => write!(f, "cursor {} {}", x, y),
+			ReplayEvent::CursorLeft => write!(f, "cursor_left"),
+			ReplayEvent::MouseMotion([x, y]) => write!(f, "motion {} {}", x, y),
+			ReplayEvent::Character(c) => write!(f, "char {}", *c as u32),
+			ReplayEvent::Touch {
+				id,
+				phase,
+				position,
+				force,
+			} => {
+				let phase = match phase {
+					TouchPhase::Started => "started",
+					TouchPhase::Moved => "moved",
+					TouchPhase::Ended => "ended",
+					TouchPhase::Cancelled => "cancelled",
+				};
+				write!(f, "touch {} {} {} {} ", id, phase, position[0], position[1])?;
+				match force {
+					Some(force) => write!(f, "{}", force),
+					None => write!(f, "-"),
+				}
+			}
+			ReplayEvent::Focused(focused) => write!(f, "focus {}", *focused as u8),
+		}
+	}
+}
+
+fn flag(word: &str) -> Option<bool> {
+	match word {
+		"0" => Some(false),
+		"1" => Some(true),
+		_ => None,
+	}
+}
+
+fn keycode(code: u32) -> Option<VirtualKeyCode> {
+	// `VirtualKeyCode` is `repr(u32)` with variants numbered from 0 to `Cut`
+	if code <= VirtualKeyCode::Cut as u32 {
+		Some(unsafe { std::mem::transmute::<u32, VirtualKeyCode>(code) })
+	} else {
+		None
+	}
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+	Io(io::Error),
+	/// A line that isn't a frame or an event, counting from 1.
+	Parse(usize),
+}
+
+impl fmt::Display for ReplayError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ReplayError::Io(e) => write!(f, "{}", e),
+			ReplayError::Parse(line) => write!(f, "invalid replay at line {}", line),
+		}
+	}
+}
+
+impl Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+	fn from(error: io::Error) -> Self {
+		ReplayError::Io(error)
+	}
+}
+
+/// What a frame should advance by and the input it gets.
+pub struct ReplayFrame<T: 'static> {
+	pub delta: Duration,
+	/// Replayed events, to handle like live ones before updating.
+	pub events: Vec<Event<'static, T>>,
+}
+
+struct RecordedFrame {
+	delta: Duration,
+	events: Vec<ReplayEvent>,
+}
+
+enum State {
+	Off,
+	Recording {
+		out: BufWriter<File>,
+		events: Vec<ReplayEvent>,
+	},
+	Playing {
+		frames: VecDeque<RecordedFrame>,
+		played: u64,
+	},
+}
+
+/// Records or plays back input, or just measures frame times when neither.
+/// Pass it every event, and call `begin_frame` before updating:
+///
+/// ```text
+/// Event::RedrawRequested(_) => {
+///     let frame = engine.replay_mut().begin_frame(window_id);
+///     for event in &frame.events {
+///         input.event(event);
+///     }
+///     update(frame.delta);
+/// }
+/// event => {
+///     engine.event(&event);
+///     if !engine.replay().is_playing() {
+///         input.event(&event);
+///     }
+/// }
+/// ```
+pub struct Replay {
+	state: State,
+	timestep: Option<Duration>,
+	last_frame: Option<Instant>,
+}
+
+impl Default for Replay {
+	fn default() -> Self {
+		Replay {
+			state: State::Off,
+			timestep: None,
+			last_frame: None,
+		}
+	}
+}
+
+impl Replay {
+	/// Neither records nor plays back.
+	pub fn off() -> Self {
+		Replay::default()
+	}
+
+	/// Records to `path`, replacing it. Each frame is written as it begins,
+	/// so a recording survives a crash.
+	pub fn record(path: impl AsRef<Path>) -> io::Result<Self> {
+		Ok(Replay {
+			state: State::Recording {
+				out: BufWriter::new(File::create(path)?),
+				events: Vec::new(),
+			},
+			..Replay::default()
+		})
+	}
+
+	/// Plays back a recording from `path`. When it runs out the app goes on
+	/// with live input.
+	pub fn play(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+		let mut frames = VecDeque::new();
+		for (index, line) in fs::read_to_string(path)?.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() {
+				continue;
+			}
+			if let Some(delta) = line.strip_prefix("frame ") {
+				let delta = delta.parse().map_err(|_| ReplayError::Parse(index + 1))?;
+				frames.push_back(RecordedFrame {
+					delta: Duration::from_secs_f64(delta),
+					events: Vec::new(),
+				});
+				continue;
+			}
+			let event = ReplayEvent::parse(line).ok_or(ReplayError::Parse(index + 1))?;
+			frames
+				.back_mut()
+				.ok_or(ReplayError::Parse(index + 1))?
+				.events
+				.push(event);
+		}
+		Ok(Replay {
+			state: State::Playing { frames, played: 0 },
+			..Replay::default()
+		})
+	}
+
+	/// Sets up what `mode` asks for, `Off` when the file can't be used.
+	pub fn from_mode(mode: &ReplayMode) -> Self {
+		let replay = match mode {
+			ReplayMode::Off => return Replay::off(),
+			ReplayMode::Record(path) => Replay::record(path).map_err(ReplayError::from),
+			ReplayMode::Play(path) => Replay::play(path),
+		};
+		replay.unwrap_or_else(|e| {
+			log::error!("Failed to set up replay: {}", e);
+			Replay::off()
+		})
+	}
+
+	pub fn is_recording(&self) -> bool {
+		matches!(self.state, State::Recording { .. })
+	}
+
+	/// True until the recording runs out. Live input should be ignored
+	/// meanwhile.
+	pub fn is_playing(&self) -> bool {
+		matches!(self.state, State::Playing { .. })
+	}
+
+	/// Advances every frame by `timestep` instead of the time it took, while
+	/// not playing back. Recording with one makes frames line up with a
+	/// fixed simulation step.
+	pub fn set_timestep(&mut self, timestep: Option<Duration>) {
+		self.timestep = timestep;
+	}
+
+	/// Collects input events while recording, others are ignored.
+	pub fn event<T>(&mut self, event: &Event<T>) {
+		if let State::Recording { events, .. } = &mut self.state {
+			events.extend(ReplayEvent::from_event(event));
+		}
+	}
+
+	/// Starts a frame. While recording it's written with the events since the
+	/// last one, while playing back the next recorded frame comes out.
+	/// Replayed events get `window_id`, the window they should look like they
+	/// came from.
+	pub fn begin_frame<T>(&mut self, window_id: WindowId) -> ReplayFrame<T> {
+		let now = Instant::now();
+		let elapsed = self
+			.last_frame
+			.map_or(Duration::from_secs(0), |last| now - last);
+		self.last_frame = Some(now);
+		let delta = self.timestep.unwrap_or(elapsed);
+
+		match &mut self.state {
+			State::Off => ReplayFrame {
+				delta,
+				events: Vec::new(),
+			},
+			State::Recording { out, events } => {
+				let written = writeln!(out, "frame {}", delta.as_secs_f64())
+					.and_then(|_| {
+						events
+							.iter()
+							.try_for_each(|event| writeln!(out, "{}", event))
+					})
+					.and_then(|_| out.flush());
+				events.clear();
+				if let Err(e) = written {
+					log::error!("Failed to write replay, stopped recording: {}", e);
+					self.state = State::Off;
+				}
+				ReplayFrame {
+					delta,
+					events: Vec::new(),
+				}
+			}
+			State::Playing { frames, played } => match frames.pop_front() {
+				Some(frame) => {
+					*played += 1;
+					ReplayFrame {
+						delta: frame.delta,
+						events: frame
+							.events
+							.iter()
+							.map(|event| event.to_event(window_id))
+							.collect(),
+					}
+				}
+				None => {
+					log::info!("Replay finished after {} frames", played);
+					self.state = State::Off;
+					ReplayFrame {
+						delta,
+						events: Vec::new(),
+					}
+				}
+			},
+		}
+	}
+}
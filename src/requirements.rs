@@ -0,0 +1,400 @@
+// what the gpu needs for the engine and the app to run, checked against the
+// chosen device at startup. a shipped app can show the player what their gpu
+// lacks, instead of panicking somewhere deep inside vulkano.
+//
+// on top of that, features and extensions can be asked for optionally. the
+// device is created with what was required plus whatever of the optional
+// ones it supports, `Granted` says which.
+
+use crate::capabilities;
+use crate::portability;
+
+use vulkano::device::{DeviceExtensions, Features, RawDeviceExtensions};
+use vulkano::instance::{PhysicalDevice, Version};
+
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+
+/// The least a device has to support, set on `EngineConfig::requirements`.
+/// The defaults are what the engine itself uses, apps add to them:
+///
+/// ```text
+/// config.requirements.features.geometry_shader = true;
+/// config.requirements.limits.max_image_dimension_2d = 8192;
+/// ```
+///
+/// Everything required is enabled on the device, and the optional features
+/// and extensions it supports. `Engine::builder` has shorthands for these.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requirements {
+	pub api_version: Version,
+	pub features: Features,
+	/// `khr_swapchain` is only required with a window.
+	pub extensions: DeviceExtensions,
+	/// Extensions by name, for the ones vulkano has no field for, eg.
+	/// `capabilities::DISPLAY_TIMING_EXTENSIONS`.
+	pub extension_names: Vec<String>,
+	pub limits: RequiredLimits,
+	/// Enabled when supported. By default what the engine makes use of.
+	pub optional_features: Features,
+	pub optional_extensions: DeviceExtensions,
+	pub optional_extension_names: Vec<String>,
+}
+
+impl Default for Requirements {
+	fn default() -> Self {
+		Requirements {
+			api_version: Version {
+				major: 1,
+				minor: 0,
+				patch: 0,
+			},
+			features: Features::none(),
+			// storage buffers are needed by compute kernels and gpu particles
+			extensions: DeviceExtensions {
+				khr_swapchain: true,
+				khr_storage_buffer_storage_class: true,
+				..DeviceExtensions::none()
+			},
+			extension_names: Vec::new(),
+			limits: RequiredLimits::default(),
+			optional_features: Features {
+				sampler_anisotropy: true,
+				texture_compression_bc: true,
+				texture_compression_etc2: true,
+				texture_compression_astc_ldr: true,
+				// foliage draws its lods with instance offsets
+				draw_indirect_first_instance: true,
+				multi_draw_indirect: true,
+				fill_mode_non_solid: true,
+				wide_lines: true,
+				shader_storage_image_extended_formats: true,
+				..Features::none()
+			},
+			optional_extensions: DeviceExtensions::none(),
+			optional_extension_names: Vec::new(),
+		}
+	}
+}
+
+/// Lower bounds for device limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequiredLimits {
+	pub max_image_dimension_2d: u32,
+	/// Bytes.
+	pub max_push_constants_size: u32,
+	pub max_bound_descriptor_sets: u32,
+	pub max_color_attachments: u32,
+}
+
+impl Default for RequiredLimits {
+	fn default() -> Self {
+		RequiredLimits {
+			max_image_dimension_2d: 4096,
+			max_push_constants_size: 128,
+			max_bound_descriptor_sets: 4,
+			max_color_attachments: 4,
+		}
+	}
+}
+
+impl Requirements {
+	/// Compares the requirements with what `physical` supports, and notes the
+	/// fallbacks the engine will use on it.
+	pub fn check(&self, physical: PhysicalDevice) -> RequirementsReport {
+		let mut missing = Vec::new();
+
+		if physical.api_version() < self.api_version {
+			missing.push(Missing::ApiVersion {
+				required: self.api_version,
+				supported: physical.api_version(),
+			});
+		}
+
+		let features = self.features.difference(physical.supported_features());
+		missing.extend(names(&format!("{:?}", features)).map(Missing::Feature));
+
+		let extensions = self
+			.extensions
+			.difference(&DeviceExtensions::supported_by_device(physical));
+		missing.extend(names(&format!("{:?}", extensions)).map(Missing::Extension));
+		missing.extend(
+			self.extension_names
+				.iter()
+				.filter(|name| !capabilities::supports_extension(physical, name))
+				.cloned()
+				.map(Missing::Extension),
+		);
+
+		let limits = physical.limits();
+		let supported = [
+			(
+				"max_image_dimension_2d",
+				self.limits.max_image_dimension_2d,
+				limits.max_image_dimension_2d(),
+			),
+			(
+				"max_push_constants_size",
+				self.limits.max_push_constants_size,
+				limits.max_push_constants_size(),
+			),
+			(
+				"max_bound_descriptor_sets",
+				self.limits.max_bound_descriptor_sets,
+				limits.max_bound_descriptor_sets(),
+			),
+			(
+				"max_color_attachments",
+				self.limits.max_color_attachments,
+				limits.max_color_attachments(),
+			),
+		];
+		for (name, required, supported) in supported.iter().copied() {
+			if supported < required {
+				missing.push(Missing::Limit {
+					name,
+					required,
+					supported,
+				});
+			}
+		}
+
+		let mut fallbacks = Vec::new();
+		let graphics = physical.queue_families().find(|q| q.supports_graphics());
+		if graphics.is_none() {
+			missing.push(Missing::Graphics);
+		}
+		if graphics.is_some_and(|family| family.queues_count() < 2) {
+			fallbacks.push(Fallback {
+				missing: "a second graphics queue",
+				instead: "assets upload on the rendering queue",
+			});
+		}
+		if !physical
+			.queue_families()
+			.any(|q| q.supports_compute() && !q.supports_graphics())
+		{
+			fallbacks.push(Fallback {
+				missing: "a dedicated compute queue",
+				instead: "async compute passes run on the graphics queue",
+			});
+		}
+		if !physical.supported_features().sampler_anisotropy {
+			fallbacks.push(Fallback {
+				missing: "anisotropic filtering",
+				instead: "textures are sampled without it",
+			});
+		}
+
+		RequirementsReport {
+			device: Some(physical.name().to_string()),
+			missing,
+			fallbacks,
+		}
+	}
+
+	/// What a device on `physical` gets created with, assuming `check`
+	/// found nothing missing.
+	pub fn negotiate(&self, physical: PhysicalDevice) -> Granted {
+		let supported = physical.supported_features();
+		let features = union_features(
+			&self.features,
+			&self.optional_features.intersection(supported),
+		);
+
+		let extensions = self.extensions.union(
+			&self
+				.optional_extensions
+				.intersection(&DeviceExtensions::supported_by_device(physical)),
+		);
+		let extensions = portability::device_extensions(physical, extensions);
+		let mut extension_names: Vec<String> = RawDeviceExtensions::from(&extensions)
+			.iter()
+			.map(|name| name.to_string_lossy().into_owned())
+			.collect();
+		let optional = self
+			.optional_extension_names
+			.iter()
+			.filter(|name| capabilities::supports_extension(physical, name));
+		for name in self.extension_names.iter().chain(optional) {
+			if !extension_names.contains(name) {
+				extension_names.push(name.clone());
+			}
+		}
+		extension_names.sort();
+
+		Granted {
+			features,
+			// picks up named extensions vulkano knows too
+			extensions: DeviceExtensions::from(&raw(&extension_names)),
+			extension_names,
+		}
+	}
+}
+
+// `Features` has no union in vulkano 0.22, so through the complements
+pub(crate) fn union_features(a: &Features, b: &Features) -> Features {
+	let all = Features::all();
+	all.difference(&all.difference(a).intersection(&all.difference(b)))
+}
+
+fn raw(names: &[String]) -> RawDeviceExtensions {
+	RawDeviceExtensions::new(
+		names
+			.iter()
+			.map(|name| CString::new(name.as_str()).unwrap()),
+	)
+}
+
+/// The features and extensions a device was created with, see
+/// `Engine::granted`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Granted {
+	pub features: Features,
+	pub extensions: DeviceExtensions,
+	/// Every enabled extension by name, sorted, including the ones vulkano
+	/// has no field for.
+	pub extension_names: Vec<String>,
+}
+
+impl Granted {
+	pub fn has_extension(&self, name: &str) -> bool {
+		self.extension_names.iter().any(|enabled| enabled == name)
+	}
+
+	/// Whether all of `names` are enabled, eg.
+	/// `capabilities::DISPLAY_TIMING_EXTENSIONS`.
+	pub fn has_all(&self, names: &[&str]) -> bool {
+		names.iter().all(|name| self.has_extension(name))
+	}
+
+	/// The extensions to create the device with.
+	pub fn raw_extensions(&self) -> RawDeviceExtensions {
+		raw(&self.extension_names)
+	}
+}
+
+// the names set to true in the debug output of `Features` ("Features { a:
+// true, b: false }") or `DeviceExtensions` ("[a, b]"), vulkano has no other
+// way to list them
+fn names(debug: &str) -> impl Iterator<Item = String> + '_ {
+	debug
+		.trim_start_matches("Features")
+		.trim_matches(|c| c == ' ' || c == '{' || c == '}' || c == '[' || c == ']')
+		.split(", ")
+		.filter_map(|entry| match entry.split_once(": ") {
+			Some((name, value)) => (value == "true").then(|| name.to_string()),
+			None => (!entry.is_empty()).then(|| entry.to_string()),
+		})
+}
+
+/// Something the device lacks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Missing {
+	/// No gpu with graphics support was found at all.
+	Gpu,
+	/// The device has no queue that can draw.
+	Graphics,
+	/// None of the device's graphics queues can present to the window.
+	Present,
+	ApiVersion {
+		required: Version,
+		supported: Version,
+	},
+	/// A `Features` field.
+	Feature(String),
+	/// A device extension, eg. `VK_KHR_swapchain`.
+	Extension(String),
+	Limit {
+		name: &'static str,
+		required: u32,
+		supported: u32,
+	},
+}
+
+impl fmt::Display for Missing {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Missing::Gpu => write!(f, "a gpu with vulkan support"),
+			Missing::Graphics => write!(f, "a queue that supports graphics"),
+			Missing::Present => write!(f, "a graphics queue that can present to the window"),
+			Missing::ApiVersion {
+				required,
+				supported,
+			} => write!(
+				f,
+				"vulkan {} (the driver supports {}, try updating it)",
+				required, supported
+			),
+			Missing::Feature(name) => write!(f, "the {} feature", name),
+			Missing::Extension(name) => write!(f, "the {} extension", name),
+			Missing::Limit {
+				name,
+				required,
+				supported,
+			} => write!(
+				f,
+				"{} of at least {} (it has {})",
+				name, required, supported
+			),
+		}
+	}
+}
+
+/// A capability the device lacks that the engine works around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fallback {
+	pub missing: &'static str,
+	/// What happens instead.
+	pub instead: &'static str,
+}
+
+/// What `Requirements::check` found on the chosen device. Printing it gives
+/// a message meant for players, see `Engine::requirements`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequirementsReport {
+	/// `None` when there was no gpu to check.
+	pub device: Option<String>,
+	/// Empty when the device can run the app.
+	pub missing: Vec<Missing>,
+	pub fallbacks: Vec<Fallback>,
+}
+
+impl RequirementsReport {
+	/// The report when not even a gpu was found.
+	pub fn no_gpu() -> Self {
+		RequirementsReport {
+			device: None,
+			missing: vec![Missing::Gpu],
+			fallbacks: Vec::new(),
+		}
+	}
+
+	pub fn is_supported(&self) -> bool {
+		self.missing.is_empty()
+	}
+}
+
+impl fmt::Display for RequirementsReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let device = self.device.as_deref().unwrap_or("your computer");
+		if self.is_supported() {
+			write!(f, "{} meets the requirements", device)?;
+		} else {
+			write!(f, "{} can't run this app, it lacks:", device)?;
+			for missing in &self.missing {
+				write!(f, "\n  - {}", missing)?;
+			}
+		}
+		if !self.fallbacks.is_empty() {
+			write!(f, "\nfallbacks in use:")?;
+			for fallback in &self.fallbacks {
+				write!(f, "\n  - no {}, {}", fallback.missing, fallback.instead)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Error for RequirementsReport {}
@@ -0,0 +1,391 @@
+// fsr1 style upscaling in two passes: easu reconstructs the window sized
+// image with a kernel stretched along edges, rcas sharpens it while
+// compositing onto the window.
+
+use super::{OutputTransform, SCENE_FORMAT};
+use crate::compute::group_counts;
+use crate::fullscreen::{vs, FullscreenPipeline};
+use crate::render2d::Texture;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract, GraphicsPipeline};
+use vulkano::sampler::Sampler;
+
+use std::sync::Arc;
+
+mod easu {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8) in;
+
+			layout(set = 0, binding = 0) uniform sampler2D scene;
+			layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D upscaled;
+
+			layout(push_constant) uniform PushConstants {
+				// rendered part of the scene target in pixels
+				vec2 input_size;
+				vec2 output_size;
+			} pc;
+
+			vec3 fetch(ivec2 pixel) {
+				return texelFetch(scene, clamp(pixel, ivec2(0), ivec2(pc.input_size) - 1), 0).rgb;
+			}
+
+			float luma(vec3 c) {
+				return c.g + 0.5 * (c.r + c.b);
+			}
+
+			// edge direction and strength around one texel of the center quad,
+			// weighted by its bilinear weight
+			void edge(inout vec2 dir, inout float len, float w,
+				float up, float left, float center, float right, float down) {
+				float dx = right - left;
+				float lx = max(abs(right - center), abs(center - left));
+				lx = clamp(abs(dx) / max(lx, 1e-5), 0.0, 1.0);
+				float dy = down - up;
+				float ly = max(abs(down - center), abs(center - up));
+				ly = clamp(abs(dy) / max(ly, 1e-5), 0.0, 1.0);
+				dir += vec2(dx, dy) * w;
+				len += (lx * lx + ly * ly) * w;
+			}
+
+			// windowed lanczos2 approximation from fsr, d2 is the squared distance
+			void tap(inout vec3 color, inout float weight, vec2 offset, vec2 dir,
+				vec2 len2, float lob, float clp, vec3 c) {
+				vec2 v = vec2(dot(offset, dir), dot(offset, vec2(-dir.y, dir.x))) * len2;
+				float d2 = min(dot(v, v), clp);
+				float wb = 2.0 / 5.0 * d2 - 1.0;
+				float wa = lob * d2 - 1.0;
+				wb *= wb;
+				wa *= wa;
+				wb = 25.0 / 16.0 * wb - (25.0 / 16.0 - 1.0);
+				float w = wb * wa;
+				color += c * w;
+				weight += w;
+			}
+
+			void main() {
+				ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+				if (any(greaterThanEqual(pixel, ivec2(pc.output_size)))) {
+					return;
+				}
+
+				vec2 pp = (vec2(pixel) + 0.5) * pc.input_size / pc.output_size - 0.5;
+				ivec2 fp = ivec2(floor(pp));
+				pp -= floor(pp);
+
+				//    b c
+				//  e f g h
+				//  i j k l
+				//    n o
+				vec3 b = fetch(fp + ivec2(0, -1));
+				vec3 c = fetch(fp + ivec2(1, -1));
+				vec3 e = fetch(fp + ivec2(-1, 0));
+				vec3 f = fetch(fp);
+				vec3 g = fetch(fp + ivec2(1, 0));
+				vec3 h = fetch(fp + ivec2(2, 0));
+				vec3 i = fetch(fp + ivec2(-1, 1));
+				vec3 j = fetch(fp + ivec2(0, 1));
+				vec3 k = fetch(fp + ivec2(1, 1));
+				vec3 l = fetch(fp + ivec2(2, 1));
+				vec3 n = fetch(fp + ivec2(0, 2));
+				vec3 o = fetch(fp + ivec2(1, 2));
+
+				float lb = luma(b), lc = luma(c), le = luma(e), lf = luma(f);
+				float lg = luma(g), lh = luma(h), li = luma(i), lj = luma(j);
+				float lk = luma(k), ll = luma(l), ln = luma(n), lo = luma(o);
+
+				vec2 dir = vec2(0.0);
+				float len = 0.0;
+				edge(dir, len, (1.0 - pp.x) * (1.0 - pp.y), lb, le, lf, lg, lj);
+				edge(dir, len, pp.x * (1.0 - pp.y), lc, lf, lg, lh, lk);
+				edge(dir, len, (1.0 - pp.x) * pp.y, lf, li, lj, lk, ln);
+				edge(dir, len, pp.x * pp.y, lg, lj, lk, ll, lo);
+
+				float dir2 = dot(dir, dir);
+				dir = dir2 < 1.0 / 32768.0 ? vec2(1.0, 0.0) : dir * inversesqrt(dir2);
+				len = len * 0.5;
+				len *= len;
+
+				// stretch the kernel along the edge and shrink it across
+				float stretch = 1.0 / max(abs(dir.x), abs(dir.y));
+				vec2 len2 = vec2(1.0 + (stretch - 1.0) * len, 1.0 - 0.5 * len);
+				float lob = 0.5 + (1.0 / 4.0 - 0.04 - 0.5) * len;
+				float clp = 1.0 / lob;
+
+				vec3 color = vec3(0.0);
+				float weight = 0.0;
+				tap(color, weight, vec2(0.0, -1.0) - pp, dir, len2, lob, clp, b);
+				tap(color, weight, vec2(1.0, -1.0) - pp, dir, len2, lob, clp, c);
+				tap(color, weight, vec2(-1.0, 0.0) - pp, dir, len2, lob, clp, e);
+				tap(color, weight, vec2(0.0, 0.0) - pp, dir, len2, lob, clp, f);
+				tap(color, weight, vec2(1.0, 0.0) - pp, dir, len2, lob, clp, g);
+				tap(color, weight, vec2(2.0, 0.0) - pp, dir, len2, lob, clp, h);
+				tap(color, weight, vec2(-1.0, 1.0) - pp, dir, len2, lob, clp, i);
+				tap(color, weight, vec2(0.0, 1.0) - pp, dir, len2, lob, clp, j);
+				tap(color, weight, vec2(1.0, 1.0) - pp, dir, len2, lob, clp, k);
+				tap(color, weight, vec2(2.0, 1.0) - pp, dir, len2, lob, clp, l);
+				tap(color, weight, vec2(0.0, 2.0) - pp, dir, len2, lob, clp, n);
+				tap(color, weight, vec2(1.0, 2.0) - pp, dir, len2, lob, clp, o);
+
+				// no ringing past the nearest 4 texels
+				vec3 lo4 = min(min(f, g), min(j, k));
+				vec3 hi4 = max(max(f, g), max(j, k));
+				color = clamp(color / weight, lo4, hi4);
+
+				imageStore(upscaled, pixel, vec4(color, 1.0));
+			}
+		"
+	}
+}
+
+mod rcas {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+
+			layout(set = 0, binding = 0) uniform sampler2D upscaled;
+
+			layout(push_constant) uniform PushConstants {
+				// 1 is the strongest, 0 disables sharpening
+				float sharpness;
+				uint output_mode;
+				// brightest output relative to paper white
+				float max_output;
+				// nits
+				float paper_white;
+				// swapchain format isn't srgb
+				uint encode_gamma;
+			} pc;
+
+			layout(location = 0) out vec4 f_color;
+
+			// tonemaps and encodes for the display, see `display::DisplayOutput`
+			vec3 output_transform(vec3 c) {
+				c = max(c, vec3(0.0));
+				// linear up to the knee, then rolls off towards max_output
+				float peak = max(c.r, max(c.g, c.b));
+				float knee = 0.5 * pc.max_output;
+				if (peak > knee) {
+					float range = pc.max_output - knee;
+					c *= (knee + range * (1.0 - exp((knee - peak) / range))) / peak;
+				}
+
+				if (pc.output_mode == 1u) {
+					// scrgb, 1 is 80 nits
+					return c * pc.paper_white / 80.0;
+				}
+				if (pc.output_mode == 2u) {
+					// hdr10, rec. 2020 primaries and the pq curve
+					vec3 rec2020 = vec3(
+						dot(vec3(0.6274, 0.3293, 0.0433), c),
+						dot(vec3(0.0691, 0.9195, 0.0114), c),
+						dot(vec3(0.0164, 0.0880, 0.8956), c)
+					);
+					vec3 l = pow(rec2020 * pc.paper_white / 10000.0, vec3(0.1593017578125));
+					return pow((0.8359375 + 18.8515625 * l) / (1.0 + 18.6875 * l), vec3(78.84375));
+				}
+				if (pc.encode_gamma != 0u) {
+					return mix(
+						c * 12.92,
+						1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055,
+						greaterThan(c, vec3(0.0031308))
+					);
+				}
+				return c;
+			}
+
+			float max3(vec3 c) {
+				return max(c.r, max(c.g, c.b));
+			}
+
+			// rcas expects 0..1, so work in a reversible tonemapped space
+			vec3 fetch(ivec2 pixel) {
+				ivec2 size = textureSize(upscaled, 0);
+				vec3 c = texelFetch(upscaled, clamp(pixel, ivec2(0), size - 1), 0).rgb;
+				return c / (1.0 + max3(c));
+			}
+
+			void main() {
+				ivec2 pixel = ivec2(gl_FragCoord.xy);
+				//   b
+				// d e f
+				//   h
+				vec3 b = fetch(pixel + ivec2(0, -1));
+				vec3 d = fetch(pixel + ivec2(-1, 0));
+				vec3 e = fetch(pixel);
+				vec3 f = fetch(pixel + ivec2(1, 0));
+				vec3 h = fetch(pixel + ivec2(0, 1));
+
+				// the strongest negative lobe that can't push any channel out of 0..1
+				vec3 lo4 = min(min(b, d), min(f, h));
+				vec3 hi4 = max(max(b, d), max(f, h));
+				vec3 hit_min = lo4 / (4.0 * hi4 + 1e-5);
+				vec3 hit_max = (1.0 - hi4) / (4.0 * lo4 - 4.0);
+				vec3 lobe_rgb = max(-hit_min, hit_max);
+				float lobe = max(-(0.25 - 1.0 / 16.0), min(max3(lobe_rgb), 0.0)) * pc.sharpness;
+
+				vec3 color = (lobe * (b + d + f + h) + e) / (4.0 * lobe + 1.0);
+				f_color = vec4(output_transform(color / max(1.0 - max3(color), 1e-5)), 1.0);
+			}
+		"
+	}
+}
+
+/// The two fsr passes and the window sized image between them.
+pub(super) struct Fsr {
+	device: Arc<Device>,
+	easu: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	rcas: Arc<FullscreenPipeline>,
+	sampler: Arc<Sampler>,
+	upscaled: Texture,
+	dimensions: [u32; 2],
+}
+
+impl Fsr {
+	pub fn new(
+		device: Arc<Device>,
+		window_subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+		window_dimensions: [u32; 2],
+	) -> Self {
+		let easu_shader = easu::Shader::load(device.clone()).unwrap();
+		let easu = Arc::new(
+			ComputePipeline::new(device.clone(), &easu_shader.main_entry_point(), &(), None)
+				.unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = rcas::Shader::load(device.clone()).unwrap();
+		let rcas = Arc::new(
+			GraphicsPipeline::start()
+				.vertex_input(BufferlessDefinition)
+				.vertex_shader(vs.main_entry_point(), ())
+				.triangle_list()
+				.viewports_dynamic_scissors_irrelevant(1)
+				.fragment_shader(fs.main_entry_point(), ())
+				.render_pass(window_subpass)
+				.build(device.clone())
+				.unwrap(),
+		);
+
+		Fsr {
+			upscaled: create_image(device.clone(), window_dimensions),
+			// easu and rcas fetch texels directly, this only satisfies the binding
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+			device,
+			easu,
+			rcas,
+			dimensions: window_dimensions,
+		}
+	}
+
+	pub fn resize(&mut self, window_dimensions: [u32; 2]) {
+		self.upscaled = create_image(self.device.clone(), window_dimensions);
+		self.dimensions = window_dimensions;
+	}
+
+	/// Upscales the top left `input_size` pixels of `scene` to the window size.
+	pub fn easu(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		scene: Texture,
+		input_size: [u32; 2],
+	) {
+		let layout = self.easu.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(scene, self.sampler.clone())
+				.unwrap()
+				.add_image(self.upscaled.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		let push_constants = easu::ty::PushConstants {
+			input_size: [input_size[0] as f32, input_size[1] as f32],
+			output_size: [self.dimensions[0] as f32, self.dimensions[1] as f32],
+		};
+
+		builder
+			.dispatch(
+				group_counts([self.dimensions[0], self.dimensions[1], 1], [8, 8, 1]),
+				self.easu.clone(),
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+
+	/// Sharpens the upscaled image onto the window.
+	pub fn rcas(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		sharpness: f32,
+		transform: OutputTransform,
+	) {
+		let layout = self.rcas.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(self.upscaled.clone(), self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		builder
+			.draw(
+				self.rcas.clone(),
+				dynamic_state,
+				BufferlessVertices {
+					vertices: 3,
+					instances: 1,
+				},
+				set,
+				rcas::ty::PushConstants {
+					sharpness: sharpness.clamp(0.0, 1.0),
+					output_mode: transform.output_mode,
+					max_output: transform.max_output,
+					paper_white: transform.paper_white,
+					encode_gamma: transform.encode_gamma,
+				},
+				vec![],
+			)
+			.unwrap();
+	}
+}
+
+fn create_image(device: Arc<Device>, dimensions: [u32; 2]) -> Texture {
+	let image = StorageImage::with_usage(
+		device.clone(),
+		ImageDimensions::Dim2d {
+			width: dimensions[0],
+			height: dimensions[1],
+			array_layers: 1,
+		},
+		SCENE_FORMAT,
+		ImageUsage {
+			storage: true,
+			sampled: true,
+			..ImageUsage::none()
+		},
+		ImageCreateFlags::none(),
+		device.active_queue_families(),
+	)
+	.unwrap();
+	ImageView::new(image).unwrap()
+}
@@ -0,0 +1,688 @@
+// dynamic resolution: the scene is drawn into part of an hdr target at a
+// fraction of the window size and stretched back over the window at the end.
+//
+// the target is allocated at `max_scale` once per window size, changing the
+// scale only changes the viewport, so adjusting it every few frames is free.
+//
+// with a fixed presentation the scene keeps one resolution whatever the
+// window size and is letterboxed into the window instead.
+
+mod fsr;
+
+use crate::display::{DisplayOutput, HdrSettings};
+use crate::fullscreen::{vs, FullscreenPipeline};
+use crate::gpu::BlendMode;
+use crate::render2d::Texture;
+use crate::render_target::RenderTarget;
+use crate::sampler::SamplerDesc;
+
+use fsr::Fsr;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Format of the scene target, linear hdr color.
+pub const SCENE_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+// scale changes smaller than this are ignored so the image doesn't shimmer
+// from constant tiny resolution changes
+const SCALE_STEP: f32 = 0.05;
+
+// push constants of the output transform shared by both composite shaders
+#[derive(Debug, Clone, Copy)]
+struct OutputTransform {
+	output_mode: u32,
+	max_output: f32,
+	paper_white: f32,
+	encode_gamma: u32,
+}
+
+/// How the render scale is chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+	/// Stays at whatever `set_scale` was given.
+	Fixed,
+	/// Lowers the scale when frames take longer than `target` and raises it
+	/// again when there is headroom. Needs vsync off, see `report_frame_time`.
+	Automatic { target: Duration },
+}
+
+/// How the scene is fitted into the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Presentation {
+	/// Drawn at the render scale times the window resolution and stretched
+	/// over the whole window.
+	Window,
+	/// Drawn at `dimensions` whatever the window size, scaled to fit the
+	/// window without distorting it. The bars on the sides or at the top and
+	/// bottom keep the window pass's clear color. `integer` only scales by
+	/// whole multiples with nearest filtering, for pixel art.
+	Fixed { dimensions: [u32; 2], integer: bool },
+}
+
+/// How the scene is stretched over the window when drawn below its resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Upscaler {
+	/// A single bilinear sample, cheap but soft.
+	Bilinear,
+	/// Edge adaptive upscaling followed by contrast adaptive sharpening, in
+	/// the style of fsr 1. `sharpness` goes from 0 (none) to 1.
+	Fsr { sharpness: f32 },
+}
+
+mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+
+			layout(set = 0, binding = 0) uniform sampler2D scene;
+
+			layout(push_constant) uniform PushConstants {
+				// rendered part of the target in uv space
+				vec2 uv_scale;
+				// last uv the bilinear filter can use without reading outside it
+				vec2 uv_max;
+				uint output_mode;
+				// brightest output relative to paper white
+				float max_output;
+				// nits
+				float paper_white;
+				// swapchain format isn't srgb
+				uint encode_gamma;
+				// compositor layers, the scene is opaque
+				float opacity;
+				uint source_alpha;
+			} pc;
+
+			layout(location = 0) out vec4 f_color;
+
+			// tonemaps and encodes for the display, see `display::DisplayOutput`
+			vec3 output_transform(vec3 c) {
+				c = max(c, vec3(0.0));
+				// linear up to the knee, then rolls off towards max_output
+				float peak = max(c.r, max(c.g, c.b));
+				float knee = 0.5 * pc.max_output;
+				if (peak > knee) {
+					float range = pc.max_output - knee;
+					c *= (knee + range * (1.0 - exp((knee - peak) / range))) / peak;
+				}
+
+				if (pc.output_mode == 1u) {
+					// scrgb, 1 is 80 nits
+					return c * pc.paper_white / 80.0;
+				}
+				if (pc.output_mode == 2u) {
+					// hdr10, rec. 2020 primaries and the pq curve
+					vec3 rec2020 = vec3(
+						dot(vec3(0.6274, 0.3293, 0.0433), c),
+						dot(vec3(0.0691, 0.9195, 0.0114), c),
+						dot(vec3(0.0164, 0.0880, 0.8956), c)
+					);
+					vec3 l = pow(rec2020 * pc.paper_white / 10000.0, vec3(0.1593017578125));
+					return pow((0.8359375 + 18.8515625 * l) / (1.0 + 18.6875 * l), vec3(78.84375));
+				}
+				if (pc.encode_gamma != 0u) {
+					return mix(
+						c * 12.92,
+						1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055,
+						greaterThan(c, vec3(0.0031308))
+					);
+				}
+				return c;
+			}
+
+			void main() {
+				vec2 uv = min(v_uv * pc.uv_scale, pc.uv_max);
+				vec4 texel = texture(scene, uv);
+				// premultiplied, so undone around the transform
+				float alpha = pc.source_alpha != 0u ? texel.a : 1.0;
+				vec3 color = alpha > 0.0 ? output_transform(texel.rgb / alpha) * alpha : vec3(0.0);
+				f_color = vec4(color, alpha) * pc.opacity;
+			}
+		"
+	}
+}
+
+/// The hdr scene target, drawn at `scale` times the window resolution and
+/// upscaled onto the window by `composite`.
+///
+/// Draw the scene between `begin` and `end` with `dynamic_state`, which holds
+/// the scaled viewport, then call `composite` inside the window's render pass.
+pub struct DynamicResolution {
+	device: Arc<Device>,
+	target: RenderTarget,
+	pipeline: Arc<FullscreenPipeline>,
+	sampler: Arc<Sampler>,
+	nearest_sampler: Arc<Sampler>,
+	window_dimensions: [u32; 2],
+	presentation: Presentation,
+	scale: f32,
+	min_scale: f32,
+	max_scale: f32,
+	pub mode: ScaleMode,
+	pub upscaler: Upscaler,
+	/// How the window's swapchain is encoded, see `WindowTarget::output`.
+	pub output: DisplayOutput,
+	pub hdr: HdrSettings,
+	/// Apply the srgb curve when compositing, see `WindowTarget::needs_gamma`.
+	pub encode_gamma: bool,
+	fsr: Fsr,
+	dynamic_state: DynamicState,
+	// smoothed frame time in seconds for the automatic mode
+	frame_time: Option<f32>,
+}
+
+impl DynamicResolution {
+	/// `window_subpass` is where `composite` draws, usually `WindowTarget::subpass`.
+	pub fn new(
+		device: Arc<Device>,
+		window_subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+		window_dimensions: [u32; 2],
+	) -> Self {
+		let pipeline =
+			composite_pipeline(device.clone(), window_subpass.clone(), BlendMode::Replace);
+
+		let target = RenderTarget::new(device.clone(), window_dimensions, SCENE_FORMAT);
+
+		let fsr = Fsr::new(device.clone(), window_subpass, window_dimensions);
+
+		let mut resolution = DynamicResolution {
+			device: device.clone(),
+			target,
+			pipeline,
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+			nearest_sampler: SamplerDesc::nearest().create(device, 1.0),
+			window_dimensions,
+			presentation: Presentation::Window,
+			scale: 1.0,
+			min_scale: 0.5,
+			max_scale: 1.0,
+			mode: ScaleMode::Fixed,
+			upscaler: Upscaler::Fsr { sharpness: 0.8 },
+			output: DisplayOutput::Sdr,
+			hdr: HdrSettings::default(),
+			encode_gamma: false,
+			fsr,
+			dynamic_state: DynamicState::none(),
+			frame_time: None,
+		};
+		resolution.update_viewport();
+		resolution
+	}
+
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+
+	/// Sets the fraction of the window resolution the scene is drawn at,
+	/// clamped to the scale range.
+	pub fn set_scale(&mut self, scale: f32) {
+		self.scale = scale.clamp(self.min_scale, self.max_scale);
+		self.update_viewport();
+	}
+
+	pub fn scale_range(&self) -> (f32, f32) {
+		(self.min_scale, self.max_scale)
+	}
+
+	/// Limits the scale, also for the automatic mode. A `max` above 1
+	/// supersamples the scene.
+	pub fn set_scale_range(&mut self, min: f32, max: f32) {
+		assert!(0.0 < min && min <= max, "invalid render scale range");
+		self.min_scale = min;
+		self.max_scale = max;
+		let size = self.target_size();
+		if size != self.target.dimensions() {
+			self.target.resize(size);
+		}
+		self.set_scale(self.scale);
+	}
+
+	pub fn presentation(&self) -> Presentation {
+		self.presentation
+	}
+
+	/// Switches between following the window and a fixed resolution,
+	/// recreating the target if its size changes.
+	pub fn set_presentation(&mut self, presentation: Presentation) {
+		self.presentation = presentation;
+		let size = self.target_size();
+		if size != self.target.dimensions() {
+			self.target.resize(size);
+		}
+		self.update_viewport();
+	}
+
+	/// Window pixels the scene is composited into, origin and size. The
+	/// whole window unless the presentation is fixed.
+	pub fn output_rect(&self) -> ([u32; 2], [u32; 2]) {
+		let window = self.window_dimensions;
+		let (dimensions, integer) = match self.presentation {
+			Presentation::Window => return ([0, 0], window),
+			Presentation::Fixed {
+				dimensions,
+				integer,
+			} => (dimensions, integer),
+		};
+
+		let fit =
+			(window[0] as f32 / dimensions[0] as f32).min(window[1] as f32 / dimensions[1] as f32);
+		// below 1x integer scaling would show nothing, shrink smoothly instead
+		let fit = if integer && fit >= 1.0 {
+			fit.floor()
+		} else {
+			fit
+		};
+		let size = [
+			((dimensions[0] as f32 * fit).round() as u32).clamp(1, window[0].max(1)),
+			((dimensions[1] as f32 * fit).round() as u32).clamp(1, window[1].max(1)),
+		];
+		let origin = [
+			(window[0] - size[0].min(window[0])) / 2,
+			(window[1] - size[1].min(window[1])) / 2,
+		];
+		(origin, size)
+	}
+
+	/// Maps a window position in pixels, eg. the cursor, to scene pixels.
+	/// `None` over the letterbox bars.
+	pub fn window_to_scene(&self, position: [f32; 2]) -> Option<[f32; 2]> {
+		let (origin, size) = self.output_rect();
+		let render = self.render_dimensions();
+		let x = (position[0] - origin[0] as f32) / size[0] as f32;
+		let y = (position[1] - origin[1] as f32) / size[1] as f32;
+		if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+			return None;
+		}
+		Some([x * render[0] as f32, y * render[1] as f32])
+	}
+
+	pub fn samples(&self) -> u32 {
+		self.target.samples()
+	}
+
+	/// Recreates the scene target with `samples` samples per pixel, the
+	/// color is resolved before upscaling. Scene pipelines have to be rebuilt
+	/// against the new `subpass`.
+	pub fn set_samples(&mut self, samples: u32) {
+		if samples == self.target.samples() {
+			return;
+		}
+		let depth_prepass = self.target.depth_prepass();
+		self.target = RenderTarget::with_samples(
+			self.device.clone(),
+			self.target.dimensions(),
+			SCENE_FORMAT,
+			samples,
+		);
+		self.target.set_depth_prepass(depth_prepass);
+	}
+
+	/// See `RenderTarget::set_depth_prepass`.
+	pub fn set_depth_prepass(&mut self, enabled: bool) {
+		self.target.set_depth_prepass(enabled);
+	}
+
+	pub fn depth_prepass(&self) -> bool {
+		self.target.depth_prepass()
+	}
+
+	/// Subpass depth pre-pass pipelines have to be built against, `None`
+	/// while the pre-pass is off.
+	pub fn depth_prepass_subpass(
+		&self,
+	) -> Option<Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>> {
+		self.target.depth_prepass_subpass()
+	}
+
+	/// Call when the window is resized, recreates the scene target.
+	pub fn window_resized(&mut self, window_dimensions: [u32; 2]) {
+		self.window_dimensions = window_dimensions;
+		let size = self.target_size();
+		if size != self.target.dimensions() {
+			self.target.resize(size);
+		}
+		self.fsr.resize(window_dimensions);
+		self.update_viewport();
+	}
+
+	pub fn window_dimensions(&self) -> [u32; 2] {
+		self.window_dimensions
+	}
+
+	/// Size the scene is currently drawn at.
+	pub fn render_dimensions(&self) -> [u32; 2] {
+		if let Presentation::Fixed { dimensions, .. } = self.presentation {
+			return dimensions;
+		}
+		let target = self.target.dimensions();
+		let render = scaled(self.window_dimensions, self.scale);
+		[render[0].min(target[0]), render[1].min(target[1])]
+	}
+
+	/// Size of the scene target, which is bigger than `render_dimensions`
+	/// below the maximum scale.
+	pub fn target_dimensions(&self) -> [u32; 2] {
+		self.target.dimensions()
+	}
+
+	/// Aspect ratio for the scene camera, that of the window or of the fixed
+	/// resolution.
+	pub fn aspect_ratio(&self) -> f32 {
+		let dimensions = match self.presentation {
+			Presentation::Window => self.window_dimensions,
+			Presentation::Fixed { dimensions, .. } => dimensions,
+		};
+		dimensions[0] as f32 / dimensions[1] as f32
+	}
+
+	/// Subpass that scene pipelines have to be built against.
+	pub fn subpass(&self) -> Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		self.target.subpass()
+	}
+
+	/// Viewport covering the scaled part of the target, pass this to scene draws.
+	pub fn dynamic_state(&self) -> &DynamicState {
+		&self.dynamic_state
+	}
+
+	/// The whole scene target, only the top left `render_dimensions` of it
+	/// hold the current frame.
+	pub fn texture(&self) -> Texture {
+		self.target.texture()
+	}
+
+	pub fn depth_texture(&self) -> Texture {
+		self.target.depth_texture()
+	}
+
+	/// Begins the depth pre-pass, see `RenderTarget::begin_depth_prepass`.
+	/// Draw with `dynamic_state` in it too, and `end` it before `begin`.
+	pub fn begin_depth_prepass(&self, builder: &mut AutoCommandBufferBuilder) {
+		self.target.begin_depth_prepass(builder);
+	}
+
+	/// Begins the scene pass, see `RenderTarget::begin`.
+	pub fn begin(&self, builder: &mut AutoCommandBufferBuilder, clear_color: ClearValue) {
+		self.target.begin(builder, clear_color);
+	}
+
+	pub fn end(&self, builder: &mut AutoCommandBufferBuilder) {
+		self.target.end(builder);
+	}
+
+	/// Feeds the automatic mode with how long the last frame took.
+	///
+	/// vulkano 0.22 can't record timestamp queries, so `Renderer` passes the
+	/// cpu time from one frame to the next. That only follows the gpu while
+	/// the gpu is the bottleneck and presents don't wait for vblank, with vsync
+	/// on every frame takes the refresh interval and the scale never settles.
+	/// `Renderer` doesn't report anything while vsync is on.
+	pub fn report_frame_time(&mut self, frame_time: Duration) {
+		let target = match (self.mode, self.presentation) {
+			(ScaleMode::Automatic { target }, Presentation::Window) => target.as_secs_f32(),
+			_ => return,
+		};
+
+		let time = frame_time.as_secs_f32();
+		let smoothed = match self.frame_time {
+			Some(previous) => previous + (time - previous) * 0.1,
+			None => time,
+		};
+		self.frame_time = Some(smoothed);
+
+		// gpu time grows with the pixel count, so with the area
+		let ideal = (self.scale * (target / smoothed).sqrt()).clamp(self.min_scale, self.max_scale);
+		// leave some headroom before going up again
+		let ideal = if ideal > self.scale {
+			self.scale + (ideal - self.scale) * 0.5
+		} else {
+			ideal
+		};
+		if (ideal - self.scale).abs() >= SCALE_STEP {
+			self.set_scale((ideal / SCALE_STEP).round() * SCALE_STEP);
+			// the average was measured at the old scale
+			self.frame_time = None;
+		}
+	}
+
+	/// Records the upscaling work that has to happen outside of a render pass,
+	/// between `end` and beginning the window's render pass. Does nothing
+	/// unless `Upscaler::Fsr` is used below full resolution.
+	pub fn upscale(&self, builder: &mut AutoCommandBufferBuilder) {
+		self.upscale_from(builder, self.texture());
+	}
+
+	/// `upscale` of `source` instead of the scene, eg. the output of a
+	/// `PostChain`. `source` has to be the size of the scene target.
+	pub fn upscale_from(&self, builder: &mut AutoCommandBufferBuilder, source: Texture) {
+		if self.uses_fsr() {
+			self.fsr.easu(builder, source, self.render_dimensions());
+		}
+	}
+
+	/// Stretches the scene over the window. Must be called inside the
+	/// subpass given to `new`, with the window's dynamic state, after `upscale`.
+	pub fn composite(&self, builder: &mut AutoCommandBufferBuilder, dynamic_state: &DynamicState) {
+		self.composite_from(builder, dynamic_state, self.texture());
+	}
+
+	/// `composite` of `source` instead of the scene, after `upscale_from` with
+	/// the same `source`.
+	pub fn composite_from(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		source: Texture,
+	) {
+		if let Upscaler::Fsr { sharpness } = self.upscaler {
+			if self.uses_fsr() {
+				self.fsr
+					.rcas(builder, dynamic_state, sharpness, self.output_transform());
+				return;
+			}
+		}
+
+		let sampler = match self.presentation {
+			Presentation::Fixed { integer: true, .. } => self.nearest_sampler.clone(),
+			_ => self.sampler.clone(),
+		};
+		let target = self.target.dimensions();
+		let render = self.render_dimensions();
+
+		// letterboxed, only the output rect is drawn over
+		let (origin, size) = self.output_rect();
+		let mut dynamic_state = dynamic_state.clone();
+		if let Presentation::Fixed { .. } = self.presentation {
+			dynamic_state.viewports = Some(vec![Viewport {
+				origin: [origin[0] as f32, origin[1] as f32],
+				dimensions: [size[0] as f32, size[1] as f32],
+				depth_range: 0.0..1.0,
+			}]);
+		}
+
+		let layer = OutputLayer {
+			uv_scale: [
+				render[0] as f32 / target[0] as f32,
+				render[1] as f32 / target[1] as f32,
+			],
+			uv_max: [
+				(render[0] as f32 - 0.5) / target[0] as f32,
+				(render[1] as f32 - 0.5) / target[1] as f32,
+			],
+			opacity: 1.0,
+			source_alpha: false,
+		};
+		self.draw_output(
+			builder,
+			&dynamic_state,
+			self.pipeline.clone(),
+			source,
+			sampler,
+			layer,
+		);
+	}
+
+	/// Draws all of `source`, linear scene color like the scene target, over
+	/// the window through the output transform, blended by `pipeline` from
+	/// `composite_pipeline`. Its alpha is taken as premultiplied coverage.
+	/// Inside the window's pass, like `composite`.
+	pub fn composite_texture(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		pipeline: Arc<FullscreenPipeline>,
+		source: Texture,
+		opacity: f32,
+	) {
+		let dimensions = source.image().dimensions().width_height();
+		let layer = OutputLayer {
+			uv_scale: [1.0, 1.0],
+			uv_max: [
+				(dimensions[0] as f32 - 0.5) / dimensions[0] as f32,
+				(dimensions[1] as f32 - 0.5) / dimensions[1] as f32,
+			],
+			opacity,
+			source_alpha: true,
+		};
+		let sampler = self.sampler.clone();
+		self.draw_output(builder, dynamic_state, pipeline, source, sampler, layer);
+	}
+
+	fn draw_output(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		pipeline: Arc<FullscreenPipeline>,
+		source: Texture,
+		sampler: Arc<Sampler>,
+		layer: OutputLayer,
+	) {
+		let layout = pipeline.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(source, sampler)
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		let transform = self.output_transform();
+		let push_constants = fs::ty::PushConstants {
+			uv_scale: layer.uv_scale,
+			uv_max: layer.uv_max,
+			output_mode: transform.output_mode,
+			max_output: transform.max_output,
+			paper_white: transform.paper_white,
+			encode_gamma: transform.encode_gamma,
+			opacity: layer.opacity,
+			source_alpha: layer.source_alpha as u32,
+		};
+
+		builder
+			.draw(
+				pipeline,
+				dynamic_state,
+				BufferlessVertices {
+					vertices: 3,
+					instances: 1,
+				},
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+
+	fn output_transform(&self) -> OutputTransform {
+		OutputTransform {
+			output_mode: self.output.index(),
+			max_output: self.hdr.max_output(self.output),
+			paper_white: self.hdr.paper_white,
+			encode_gamma: (self.encode_gamma && !self.output.is_hdr()) as u32,
+		}
+	}
+
+	// at or above the window resolution there is nothing to reconstruct
+	fn uses_fsr(&self) -> bool {
+		let render = self.render_dimensions();
+		// fsr scales to the whole window, not the letterboxed rect
+		matches!(self.upscaler, Upscaler::Fsr { .. })
+			&& self.presentation == Presentation::Window
+			&& (render[0] < self.window_dimensions[0] || render[1] < self.window_dimensions[1])
+	}
+
+	// size the target is allocated at
+	fn target_size(&self) -> [u32; 2] {
+		match self.presentation {
+			Presentation::Window => scaled(self.window_dimensions, self.max_scale),
+			Presentation::Fixed { dimensions, .. } => dimensions,
+		}
+	}
+
+	fn update_viewport(&mut self) {
+		let render = self.render_dimensions();
+		self.dynamic_state.viewports = Some(vec![Viewport {
+			origin: [0.0, 0.0],
+			dimensions: [render[0] as f32, render[1] as f32],
+			depth_range: 0.0..1.0,
+		}]);
+	}
+}
+
+// where and how a source is drawn by the output transform
+struct OutputLayer {
+	uv_scale: [f32; 2],
+	uv_max: [f32; 2],
+	opacity: f32,
+	source_alpha: bool,
+}
+
+/// A pipeline drawing the output transform into `window_subpass` with
+/// `blend`, for `DynamicResolution::composite_texture`. Layers are
+/// premultiplied, so `BlendMode::Alpha` blends like `Premultiplied`.
+pub fn composite_pipeline(
+	device: Arc<Device>,
+	window_subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	blend: BlendMode,
+) -> Arc<FullscreenPipeline> {
+	let blend = match blend {
+		BlendMode::Alpha => BlendMode::Premultiplied,
+		blend => blend,
+	};
+	let vs = vs::Shader::load(device.clone()).unwrap();
+	let fs = fs::Shader::load(device.clone()).unwrap();
+	Arc::new(
+		GraphicsPipeline::start()
+			.vertex_input(BufferlessDefinition)
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ())
+			.blend_collective(crate::gpu::vulkan::blend(blend))
+			.render_pass(window_subpass)
+			.build(device)
+			.unwrap(),
+	)
+}
+
+fn scaled(dimensions: [u32; 2], scale: f32) -> [u32; 2] {
+	[
+		((dimensions[0] as f32 * scale).round() as u32).max(1),
+		((dimensions[1] as f32 * scale).round() as u32).max(1),
+	]
+}
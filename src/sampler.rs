@@ -0,0 +1,245 @@
+// how textures are filtered and wrapped, described by value so materials can
+// ask for a sampler and share it with everything that asks for the same one.
+
+use vulkano::device::Device;
+use vulkano::sampler::{BorderColor, Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use std::sync::Arc;
+
+/// What happens outside of the 0 to 1 texture coordinate range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+	#[default]
+	Repeat,
+	Mirror,
+	Clamp,
+	/// The sampler's `border` color.
+	Border,
+}
+
+/// Everything a sampler is created from. Descriptions that compare equal
+/// share one sampler, see `Samplers`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerDesc {
+	pub mag_filter: Filter,
+	pub min_filter: Filter,
+	pub mipmap_mode: MipmapMode,
+	/// Wrapping along u, v and w.
+	pub wrap: [Wrap; 3],
+	/// Color of `Wrap::Border`, the float colors are for regular textures.
+	pub border: BorderColor,
+	/// Added to the mip level, negative is sharper. Clamped to the device limit.
+	pub mip_bias: f32,
+	/// Maximum anisotropic filtering, 1 disables it and `None` follows
+	/// `GraphicsSettings::anisotropy`. Clamped to the device limit.
+	pub anisotropy: Option<f32>,
+	pub min_lod: f32,
+	pub max_lod: f32,
+}
+
+impl Default for SamplerDesc {
+	fn default() -> Self {
+		SamplerDesc {
+			mag_filter: Filter::Linear,
+			min_filter: Filter::Linear,
+			mipmap_mode: MipmapMode::Linear,
+			wrap: [Wrap::Repeat; 3],
+			border: BorderColor::FloatTransparentBlack,
+			mip_bias: 0.0,
+			anisotropy: None,
+			min_lod: 0.0,
+			max_lod: 1000.0,
+		}
+	}
+}
+
+impl SamplerDesc {
+	/// Trilinear with anisotropy from the settings, the default.
+	pub fn linear() -> Self {
+		SamplerDesc::default()
+	}
+
+	/// Nearest texel and mip level without anisotropy, for pixel art.
+	pub fn nearest() -> Self {
+		SamplerDesc {
+			mag_filter: Filter::Nearest,
+			min_filter: Filter::Nearest,
+			mipmap_mode: MipmapMode::Nearest,
+			anisotropy: Some(1.0),
+			..SamplerDesc::default()
+		}
+	}
+
+	/// The same wrapping on every axis.
+	pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+		self.wrap = [wrap; 3];
+		self
+	}
+
+	/// Clamps to `border` on every axis.
+	pub fn with_border(mut self, border: BorderColor) -> Self {
+		self.wrap = [Wrap::Border; 3];
+		self.border = border;
+		self
+	}
+
+	/// Reads a `[sampler]` table, keys that are missing keep their defaults:
+	///
+	/// ```toml
+	/// [sampler]
+	/// filter = "nearest"      # or "linear", both min and mag
+	/// mipmap = "nearest"      # or "linear"
+	/// wrap = ["repeat", "clamp"] # or one of repeat, mirror, clamp, border for all axes
+	/// border = "white"        # or "black", "transparent"
+	/// mip_bias = -0.5
+	/// anisotropy = 4
+	/// ```
+	pub fn from_toml(table: &toml::Value) -> Result<Self, &'static str> {
+		let mut desc = SamplerDesc::default();
+		let number = |key| match table.get(key) {
+			Some(value) => value
+				.as_float()
+				.or_else(|| value.as_integer().map(|n| n as f64))
+				.map(|n| Some(n as f32))
+				.ok_or("sampler value should be a number"),
+			None => Ok(None),
+		};
+		let wrap = |value: &toml::Value| match value.as_str() {
+			Some("repeat") => Ok(Wrap::Repeat),
+			Some("mirror") => Ok(Wrap::Mirror),
+			Some("clamp") => Ok(Wrap::Clamp),
+			Some("border") => Ok(Wrap::Border),
+			_ => Err("sampler wrap should be repeat, mirror, clamp or border"),
+		};
+
+		match table.get("filter").map(|value| value.as_str()) {
+			Some(Some("linear")) | None => {}
+			Some(Some("nearest")) => {
+				desc.mag_filter = Filter::Nearest;
+				desc.min_filter = Filter::Nearest;
+			}
+			_ => return Err("sampler filter should be linear or nearest"),
+		}
+		match table.get("mipmap").map(|value| value.as_str()) {
+			Some(Some("linear")) | None => {}
+			Some(Some("nearest")) => desc.mipmap_mode = MipmapMode::Nearest,
+			_ => return Err("sampler mipmap should be linear or nearest"),
+		}
+		match table.get("wrap") {
+			Some(toml::Value::Array(values)) if (1..=3).contains(&values.len()) => {
+				for (axis, value) in values.iter().enumerate() {
+					desc.wrap[axis] = wrap(value)?;
+				}
+			}
+			Some(value @ toml::Value::String(_)) => desc.wrap = [wrap(value)?; 3],
+			Some(_) => return Err("sampler wrap should be a mode or a list of modes"),
+			None => {}
+		}
+		match table.get("border").map(|value| value.as_str()) {
+			Some(Some("transparent")) | None => {}
+			Some(Some("black")) => desc.border = BorderColor::FloatOpaqueBlack,
+			Some(Some("white")) => desc.border = BorderColor::FloatOpaqueWhite,
+			_ => return Err("sampler border should be transparent, black or white"),
+		}
+		desc.mip_bias = number("mip_bias")?.unwrap_or(desc.mip_bias);
+		desc.anisotropy = number("anisotropy")?.or(desc.anisotropy);
+		desc.min_lod = number("min_lod")?.unwrap_or(desc.min_lod);
+		desc.max_lod = number("max_lod")?.unwrap_or(desc.max_lod);
+		Ok(desc)
+	}
+
+	/// Creates the sampler, `anisotropy` is used when the description doesn't
+	/// pick one.
+	pub fn create(&self, device: Arc<Device>, anisotropy: f32) -> Arc<Sampler> {
+		let limits = device.physical_device().limits();
+		let anisotropy = if device.enabled_features().sampler_anisotropy {
+			self.anisotropy
+				.unwrap_or(anisotropy)
+				.clamp(1.0, limits.max_sampler_anisotropy())
+		} else {
+			1.0
+		};
+		let max_bias = limits.max_sampler_lod_bias();
+		let address = |wrap| match wrap {
+			Wrap::Repeat => SamplerAddressMode::Repeat,
+			Wrap::Mirror => SamplerAddressMode::MirroredRepeat,
+			Wrap::Clamp => SamplerAddressMode::ClampToEdge,
+			Wrap::Border => SamplerAddressMode::ClampToBorder(self.border),
+		};
+
+		Sampler::new(
+			device,
+			self.mag_filter,
+			self.min_filter,
+			self.mipmap_mode,
+			address(self.wrap[0]),
+			address(self.wrap[1]),
+			address(self.wrap[2]),
+			self.mip_bias.clamp(-max_bias, max_bias),
+			anisotropy,
+			self.min_lod,
+			self.max_lod.max(self.min_lod),
+		)
+		.unwrap()
+	}
+}
+
+/// Samplers created from descriptions, each distinct one is created once.
+/// Descriptions without their own anisotropy use the global setting.
+pub struct Samplers {
+	device: Arc<Device>,
+	anisotropy: f32,
+	default: SamplerDesc,
+	default_sampler: Arc<Sampler>,
+	created: Vec<(SamplerDesc, Arc<Sampler>)>,
+}
+
+impl Samplers {
+	pub fn new(device: Arc<Device>, anisotropy: f32) -> Self {
+		let default = SamplerDesc::default();
+		Samplers {
+			default_sampler: default.create(device.clone(), anisotropy),
+			device,
+			anisotropy,
+			default,
+			created: Vec::new(),
+		}
+	}
+
+	/// Sampler for textures that don't ask for anything else.
+	pub fn default_sampler(&self) -> Arc<Sampler> {
+		self.default_sampler.clone()
+	}
+
+	pub fn default_desc(&self) -> SamplerDesc {
+		self.default
+	}
+
+	pub fn set_default(&mut self, desc: SamplerDesc) {
+		self.default = desc;
+		self.default_sampler = self.get(&desc);
+	}
+
+	pub fn anisotropy(&self) -> f32 {
+		self.anisotropy
+	}
+
+	/// Recreates every sampler, the old ones stay valid as long as they are
+	/// used.
+	pub fn set_anisotropy(&mut self, anisotropy: f32) {
+		self.anisotropy = anisotropy;
+		self.created.clear();
+		let default = self.default;
+		self.default_sampler = self.get(&default);
+	}
+
+	/// The sampler for `desc`, created the first time it's asked for.
+	pub fn get(&mut self, desc: &SamplerDesc) -> Arc<Sampler> {
+		if let Some((_, sampler)) = self.created.iter().find(|(created, _)| created == desc) {
+			return sampler.clone();
+		}
+		let sampler = desc.create(self.device.clone(), self.anisotropy);
+		self.created.push((*desc, sampler.clone()));
+		sampler
+	}
+}
@@ -0,0 +1,174 @@
+// merges small static meshes sharing a material into one mesh, in world
+// space, so a level full of props draws in a handful of draws instead of one
+// per prop. done once after the scene is built: the merged nodes keep their
+// place in the hierarchy, lights and cameras, only their mesh moves into a
+// batch node.
+
+use super::{Node, NodeId, RenderLayers, Scene};
+
+use crate::assets::{Assets, Handle, Material, Mesh};
+use crate::geometry::MeshData;
+use crate::math::{mat4_inverse, mat4_transform_point, normalize, Mat4, Vec3};
+
+use std::collections::HashMap;
+
+/// Which meshes `Scene::batch_static` merges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOptions {
+	/// Meshes with more vertices are drawn on their own, merging them saves
+	/// little and copies a lot.
+	pub max_mesh_vertices: usize,
+	/// A batch is split once it has this many vertices, so it can still be
+	/// culled in parts.
+	pub max_batch_vertices: usize,
+	/// Fewer meshes sharing a material are left alone.
+	pub min_meshes: usize,
+}
+
+impl Default for BatchOptions {
+	fn default() -> Self {
+		BatchOptions {
+			max_mesh_vertices: 4096,
+			max_batch_vertices: 1 << 16,
+			min_meshes: 2,
+		}
+	}
+}
+
+/// What `Scene::batch_static` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchReport {
+	/// Meshes moved into batches.
+	pub merged: usize,
+	/// Batch nodes added, the draws the merged meshes take now.
+	pub batches: usize,
+}
+
+// meshes only merge when everything a draw depends on matches
+#[derive(PartialEq, Eq, Hash)]
+struct BatchKey {
+	material: Option<Handle<Material>>,
+	layers: RenderLayers,
+	// meshes with and without a second uv channel would misalign it
+	has_uvs2: bool,
+}
+
+impl Scene {
+	/// Merges the meshes of `Node::is_static` nodes into batch nodes, one or
+	/// more per material. Meshes that are skinned, too big or still loading
+	/// are skipped. Call it once the scene is built, moving a merged node
+	/// afterwards leaves its mesh behind.
+	pub fn batch_static(&mut self, assets: &mut Assets, options: &BatchOptions) -> BatchReport {
+		let mut groups: HashMap<BatchKey, Vec<(NodeId, Mat4)>> = HashMap::new();
+		// in hierarchy order, so batches come out the same every time
+		for id in self.depth_first() {
+			let node = self.node(id);
+			let mesh = match (&node.mesh, node.is_static) {
+				(Some(mesh), true) => mesh,
+				_ => continue,
+			};
+			let data = match assets.try_get(mesh) {
+				Some(mesh) => &mesh.data,
+				None => continue,
+			};
+			if data.is_skinned() || data.vertex_count() > options.max_mesh_vertices {
+				continue;
+			}
+			let key = BatchKey {
+				material: node.material.clone(),
+				layers: node.layers,
+				has_uvs2: !data.uvs2.is_empty(),
+			};
+			groups
+				.entry(key)
+				.or_default()
+				.push((id, self.world_matrix(id)));
+		}
+
+		let mut groups: Vec<_> = groups
+			.into_iter()
+			.filter(|(_, meshes)| meshes.len() >= options.min_meshes.max(1))
+			.collect();
+		groups.sort_by_key(|(_, meshes)| meshes[0].0);
+
+		let mut report = BatchReport::default();
+		for (key, meshes) in groups {
+			let mut batch = MeshData::default();
+			for (id, world) in meshes {
+				let mesh = self.node_mut(id).mesh.take().unwrap();
+				let data = to_world(&assets.get(&mesh).data, world);
+				if batch.vertex_count() > 0
+					&& batch.vertex_count() + data.vertex_count() > options.max_batch_vertices
+				{
+					let full = std::mem::take(&mut batch);
+					self.add_batch(assets, full, &key, report.batches);
+					report.batches += 1;
+				}
+				batch.append(&data);
+				self.node_mut(id).material = None;
+				report.merged += 1;
+			}
+			self.add_batch(assets, batch, &key, report.batches);
+			report.batches += 1;
+		}
+		report
+	}
+
+	fn add_batch(&mut self, assets: &mut Assets, data: MeshData, key: &BatchKey, index: usize) {
+		let (mesh, future) = Mesh::upload(data, assets.upload_queue().clone());
+		assets.upload(future);
+		let node = Node {
+			mesh: Some(assets.add(mesh)),
+			material: key.material.clone(),
+			layers: key.layers,
+			is_static: true,
+			..Node::new(format!("static batch {}", index))
+		};
+		self.add(node, None);
+	}
+}
+
+// the mesh moved into world space by `world`
+fn to_world(data: &MeshData, world: Mat4) -> MeshData {
+	// normals go through the inverse transpose, which stays correct under
+	// non-uniform scale
+	let inverse = mat4_inverse(world).unwrap_or(world);
+	let normal = |n: Vec3| {
+		normalize([
+			inverse[0][0] * n[0] + inverse[0][1] * n[1] + inverse[0][2] * n[2],
+			inverse[1][0] * n[0] + inverse[1][1] * n[1] + inverse[1][2] * n[2],
+			inverse[2][0] * n[0] + inverse[2][1] * n[1] + inverse[2][2] * n[2],
+		])
+	};
+	let direction = |d: Vec3| {
+		normalize([
+			world[0][0] * d[0] + world[1][0] * d[1] + world[2][0] * d[2],
+			world[0][1] * d[0] + world[1][1] * d[1] + world[2][1] * d[2],
+			world[0][2] * d[0] + world[1][2] * d[1] + world[2][2] * d[2],
+		])
+	};
+	// a mirroring transform turns the triangles inside out
+	let determinant = world[0][0] * (world[1][1] * world[2][2] - world[2][1] * world[1][2])
+		- world[1][0] * (world[0][1] * world[2][2] - world[2][1] * world[0][2])
+		+ world[2][0] * (world[0][1] * world[1][2] - world[1][1] * world[0][2]);
+	let mirrored = determinant < 0.0;
+
+	let mut out = data.clone();
+	for position in out.positions.iter_mut() {
+		*position = mat4_transform_point(world, *position);
+	}
+	for n in out.normals.iter_mut() {
+		*n = normal(*n);
+	}
+	for t in out.tangents.iter_mut() {
+		let [x, y, z] = direction([t[0], t[1], t[2]]);
+		let w = if mirrored { -t[3] } else { t[3] };
+		*t = [x, y, z, w];
+	}
+	if mirrored {
+		for triangle in out.indices.chunks_exact_mut(3) {
+			triangle.swap(1, 2);
+		}
+	}
+	out
+}
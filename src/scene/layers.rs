@@ -0,0 +1,97 @@
+// render layers filter what a camera draws and which objects a light's
+// shadows come from, eg. a first person weapon only the player camera sees
+// or editor gizmos hidden from the game view.
+
+use std::fmt;
+
+/// A set of the 32 render layers, as a bit mask.
+///
+/// Objects are on one or more layers and cameras and lights have a mask of
+/// the layers they see. An object is drawn when its layers and the mask share
+/// at least one layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderLayers(pub u32);
+
+impl Default for RenderLayers {
+	/// Just layer 0, where everything starts out.
+	fn default() -> Self {
+		RenderLayers::DEFAULT
+	}
+}
+
+impl RenderLayers {
+	pub const NONE: RenderLayers = RenderLayers(0);
+	pub const ALL: RenderLayers = RenderLayers(u32::MAX);
+	pub const DEFAULT: RenderLayers = RenderLayers(1);
+
+	/// Only `layer`, which is 0 to 31.
+	pub const fn layer(layer: u32) -> Self {
+		assert!(layer < 32, "render layers go from 0 to 31");
+		RenderLayers(1 << layer)
+	}
+
+	/// Every layer in `layers`.
+	pub fn from_layers(layers: &[u32]) -> Self {
+		layers
+			.iter()
+			.fold(RenderLayers::NONE, |mask, &layer| mask.with(layer))
+	}
+
+	pub fn with(self, layer: u32) -> Self {
+		RenderLayers(self.0 | RenderLayers::layer(layer).0)
+	}
+
+	pub fn without(self, layer: u32) -> Self {
+		RenderLayers(self.0 & !RenderLayers::layer(layer).0)
+	}
+
+	pub fn contains(self, layer: u32) -> bool {
+		layer < 32 && self.0 & (1 << layer) != 0
+	}
+
+	/// Whether the two share a layer, ie. an object on `self` passes the
+	/// mask `other`.
+	pub fn intersects(self, other: RenderLayers) -> bool {
+		self.0 & other.0 != 0
+	}
+
+	pub fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+
+	/// The layers in the set, lowest first.
+	pub fn iter(self) -> impl Iterator<Item = u32> {
+		(0..32).filter(move |&layer| self.contains(layer))
+	}
+}
+
+impl std::ops::BitOr for RenderLayers {
+	type Output = RenderLayers;
+
+	fn bitor(self, other: RenderLayers) -> RenderLayers {
+		RenderLayers(self.0 | other.0)
+	}
+}
+
+impl std::ops::BitAnd for RenderLayers {
+	type Output = RenderLayers;
+
+	fn bitand(self, other: RenderLayers) -> RenderLayers {
+		RenderLayers(self.0 & other.0)
+	}
+}
+
+impl std::ops::Not for RenderLayers {
+	type Output = RenderLayers;
+
+	fn not(self) -> RenderLayers {
+		RenderLayers(!self.0)
+	}
+}
+
+/// Like `0b101`, for logs.
+impl fmt::Binary for RenderLayers {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Binary::fmt(&self.0, f)
+	}
+}
@@ -0,0 +1,404 @@
+// a hierarchy of nodes placing meshes, lights, cameras and billboards in the world, the
+// part of a level that gets saved and loaded.
+
+mod batching;
+mod layers;
+mod prefab;
+mod serialize;
+
+pub use batching::{BatchOptions, BatchReport};
+pub use layers::RenderLayers;
+pub use prefab::Prefab;
+pub use serialize::SceneError;
+
+use crate::animation::Transform;
+use crate::assets::{Handle, Material, Mesh};
+use crate::color::Color;
+use crate::math::{mat4_mul, Mat4, Vec2, Vec4};
+use crate::render2d::Texture;
+
+/// Index of a node in its `Scene`. Ids of removed nodes are reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+	pub fn index(self) -> usize {
+		self.0
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+	/// Shines along the node's -z axis from infinitely far away.
+	Directional { color: Color, intensity: f32 },
+	Point {
+		color: Color,
+		intensity: f32,
+		/// Distance the light fades out at.
+		range: f32,
+	},
+	/// Cone along the node's -z axis.
+	Spot {
+		color: Color,
+		intensity: f32,
+		range: f32,
+		/// Angles from the axis in radians, full intensity inside `inner_angle`.
+		inner_angle: f32,
+		outer_angle: f32,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+	/// `fov_y` is the vertical field of view in radians.
+	Perspective { fov_y: f32, near: f32, far: f32 },
+	/// `height` is the world space height of the view.
+	Orthographic { height: f32, near: f32, far: f32 },
+}
+
+/// Which post effects a camera's frame goes through, see `PostChain::run_for`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PostOverride {
+	/// Every enabled effect of the chain, like the main camera.
+	#[default]
+	Inherit,
+	/// None at all, eg. for a minimap.
+	Disabled,
+	/// Only the enabled effects with these names.
+	Only(Vec<String>),
+}
+
+impl PostOverride {
+	/// Whether the effect called `name` runs.
+	pub fn allows(&self, name: &str) -> bool {
+		match self {
+			PostOverride::Inherit => true,
+			PostOverride::Disabled => false,
+			PostOverride::Only(names) => names.iter().any(|n| n == name),
+		}
+	}
+}
+
+/// Where a camera draws.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CameraTarget {
+	/// The scene target shown in the window.
+	#[default]
+	Screen,
+	/// An offscreen target of `size` pixels, kept by `CameraTargets` under
+	/// `name` to be sampled like a texture, eg. by a minimap or portrait.
+	Texture { name: String, size: [u32; 2] },
+}
+
+/// Looks along the node's -z axis with +y up.
+///
+/// Everything but the projection and culling mask is up to whoever draws
+/// the camera, left at their defaults the camera looks like the main one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+	pub projection: Projection,
+	/// Layers of the nodes the camera draws, everything by default.
+	pub culling_mask: RenderLayers,
+	/// Color the camera's target is cleared to, `None` for the default.
+	pub clear_color: Option<Color>,
+	/// Multiplies the scene going into post, `None` keeps
+	/// `PostChain::exposure`.
+	pub exposure: Option<f32>,
+	pub post: PostOverride,
+	pub target: CameraTarget,
+}
+
+impl Default for Camera {
+	fn default() -> Self {
+		Camera {
+			projection: Projection::Perspective {
+				fov_y: 60f32.to_radians(),
+				near: 0.1,
+				far: 1000.0,
+			},
+			culling_mask: RenderLayers::ALL,
+			clear_color: None,
+			exposure: None,
+			post: PostOverride::Inherit,
+			target: CameraTarget::Screen,
+		}
+	}
+}
+
+impl Camera {
+	/// The color to clear the camera's target to, `default` unless it has
+	/// its own.
+	pub fn clear_color_or(&self, default: Color) -> Color {
+		self.clear_color.unwrap_or(default)
+	}
+
+	/// Projection matrix with vulkan's y down clip space and 0..1 depth.
+	pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+		match self.projection {
+			Projection::Perspective { fov_y, near, far } => {
+				let f = 1.0 / (fov_y * 0.5).tan();
+				[
+					[f / aspect_ratio, 0.0, 0.0, 0.0],
+					[0.0, -f, 0.0, 0.0],
+					[0.0, 0.0, far / (near - far), -1.0],
+					[0.0, 0.0, near * far / (near - far), 0.0],
+				]
+			}
+			Projection::Orthographic { height, near, far } => {
+				let width = height * aspect_ratio;
+				[
+					[2.0 / width, 0.0, 0.0, 0.0],
+					[0.0, -2.0 / height, 0.0, 0.0],
+					[0.0, 0.0, 1.0 / (near - far), 0.0],
+					[0.0, 0.0, near / (near - far), 1.0],
+				]
+			}
+		}
+	}
+}
+
+/// How a billboard turns towards the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BillboardMode {
+	/// Faces the camera completely, like a sprite. Centered on the node.
+	Full,
+	/// Only turns around the node's y axis, like a distant tree. Stands on
+	/// the node.
+	Axis,
+}
+
+/// A textured quad that turns towards the camera, drawn with
+/// `BillboardRenderer`. Alpha below 0.5 is cut out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Billboard {
+	pub texture: Handle<Texture>,
+	/// World size before the node's scale.
+	pub size: Vec2,
+	/// Multiplies the texture.
+	pub color: Vec4,
+	pub mode: BillboardMode,
+}
+
+impl Billboard {
+	pub fn new(texture: Handle<Texture>, size: Vec2, mode: BillboardMode) -> Self {
+		Billboard {
+			texture,
+			size,
+			color: [1.0; 4],
+			mode,
+		}
+	}
+}
+
+/// Something placed in the scene, relative to its parent.
+#[derive(Debug, Clone)]
+pub struct Node {
+	pub name: String,
+	pub transform: Transform,
+	pub mesh: Option<Handle<Mesh>>,
+	pub material: Option<Handle<Material>>,
+	pub light: Option<Light>,
+	pub camera: Option<Camera>,
+	pub billboard: Option<Billboard>,
+	/// Layers the node's mesh and billboard are on. Not inherited by children.
+	pub layers: RenderLayers,
+	/// Layers of the nodes that cast shadows from the node's light.
+	pub shadow_mask: RenderLayers,
+	/// Never moves once the scene is built, so `Scene::batch_static` may
+	/// merge its mesh with others.
+	pub is_static: bool,
+	parent: Option<NodeId>,
+	children: Vec<NodeId>,
+}
+
+impl Default for Node {
+	fn default() -> Self {
+		Node {
+			name: String::new(),
+			transform: Transform::default(),
+			mesh: None,
+			material: None,
+			light: None,
+			camera: None,
+			billboard: None,
+			layers: RenderLayers::DEFAULT,
+			shadow_mask: RenderLayers::ALL,
+			is_static: false,
+			parent: None,
+			children: Vec::new(),
+		}
+	}
+}
+
+impl Node {
+	pub fn new(name: impl Into<String>) -> Self {
+		Node {
+			name: name.into(),
+			..Node::default()
+		}
+	}
+
+	/// Whether a camera or light with `mask` sees the node.
+	pub fn visible_to(&self, mask: RenderLayers) -> bool {
+		self.layers.intersects(mask)
+	}
+
+	pub fn parent(&self) -> Option<NodeId> {
+		self.parent
+	}
+
+	pub fn children(&self) -> &[NodeId] {
+		&self.children
+	}
+}
+
+/// Nodes in a hierarchy. Saved and loaded as json with `save` and `load`,
+/// referencing meshes and materials by their asset paths.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+	nodes: Vec<Option<Node>>,
+	free: Vec<usize>,
+	roots: Vec<NodeId>,
+}
+
+impl Scene {
+	pub fn new() -> Self {
+		Scene::default()
+	}
+
+	/// Adds `node` under `parent`, or as a root. The node's own parent and
+	/// children are ignored.
+	pub fn add(&mut self, mut node: Node, parent: Option<NodeId>) -> NodeId {
+		node.parent = None;
+		node.children.clear();
+		let id = match self.free.pop() {
+			Some(index) => {
+				self.nodes[index] = Some(node);
+				NodeId(index)
+			}
+			None => {
+				self.nodes.push(Some(node));
+				NodeId(self.nodes.len() - 1)
+			}
+		};
+		self.set_parent(id, parent);
+		id
+	}
+
+	/// Removes the node and everything below it.
+	pub fn remove(&mut self, id: NodeId) {
+		self.set_parent(id, None);
+		self.roots.retain(|&root| root != id);
+		let mut stack = vec![id];
+		while let Some(id) = stack.pop() {
+			if let Some(node) = self.nodes[id.0].take() {
+				stack.extend(node.children);
+				self.free.push(id.0);
+			}
+		}
+	}
+
+	/// Moves the node under `parent`, or to the roots. Panics if that would
+	/// make the node its own ancestor.
+	pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+		let mut ancestor = parent;
+		while let Some(current) = ancestor {
+			assert_ne!(current, id, "node can't be its own ancestor");
+			ancestor = self.node(current).parent;
+		}
+
+		match self.node(id).parent {
+			Some(old) => self.node_mut(old).children.retain(|&child| child != id),
+			None => self.roots.retain(|&root| root != id),
+		}
+		match parent {
+			Some(parent) => self.node_mut(parent).children.push(id),
+			None => self.roots.push(id),
+		}
+		self.node_mut(id).parent = parent;
+	}
+
+	pub fn get(&self, id: NodeId) -> Option<&Node> {
+		self.nodes.get(id.0)?.as_ref()
+	}
+
+	pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+		self.nodes.get_mut(id.0)?.as_mut()
+	}
+
+	/// Nodes without a parent, in the order they were added.
+	pub fn roots(&self) -> &[NodeId] {
+		&self.roots
+	}
+
+	/// First node called `name`.
+	pub fn find(&self, name: &str) -> Option<NodeId> {
+		self.iter()
+			.find(|(_, node)| node.name == name)
+			.map(|(id, _)| id)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+		self.nodes
+			.iter()
+			.enumerate()
+			.filter_map(|(index, node)| Some((NodeId(index), node.as_ref()?)))
+	}
+
+	/// Nodes on a layer of `mask`, eg. a camera's `culling_mask`.
+	pub fn visible(&self, mask: RenderLayers) -> impl Iterator<Item = (NodeId, &Node)> {
+		self.iter().filter(move |(_, node)| node.visible_to(mask))
+	}
+
+	/// Nodes with a mesh that cast shadows from the light on `light`,
+	/// nothing if the node has no light.
+	pub fn shadow_casters(&self, light: NodeId) -> impl Iterator<Item = (NodeId, &Node)> {
+		let node = self.node(light);
+		let mask = match node.light {
+			Some(_) => node.shadow_mask,
+			None => RenderLayers::NONE,
+		};
+		self.visible(mask).filter(|(_, node)| node.mesh.is_some())
+	}
+
+	pub fn count(&self) -> usize {
+		self.nodes.len() - self.free.len()
+	}
+
+	/// Every node with parents before children, the order transforms resolve in.
+	pub fn depth_first(&self) -> Vec<NodeId> {
+		self.walk(self.roots.iter().rev().copied().collect())
+	}
+
+	/// The node and everything below it, parents before children.
+	pub fn subtree(&self, id: NodeId) -> Vec<NodeId> {
+		self.walk(vec![id])
+	}
+
+	/// Transform of the node relative to the world.
+	pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+		let node = self.node(id);
+		let local = node.transform.matrix();
+		match node.parent {
+			Some(parent) => mat4_mul(self.world_matrix(parent), local),
+			None => local,
+		}
+	}
+
+	fn walk(&self, mut stack: Vec<NodeId>) -> Vec<NodeId> {
+		let mut order = Vec::new();
+		while let Some(id) = stack.pop() {
+			order.push(id);
+			stack.extend(self.node(id).children.iter().rev());
+		}
+		order
+	}
+
+	fn node(&self, id: NodeId) -> &Node {
+		self.get(id).expect("node was removed")
+	}
+
+	fn node_mut(&mut self, id: NodeId) -> &mut Node {
+		self.get_mut(id).expect("node was removed")
+	}
+}
@@ -0,0 +1,243 @@
+// reusable pieces of a scene, a subtree of nodes saved on its own with named
+// parameters that instances can override.
+
+use super::{NodeId, Scene, SceneError};
+
+use crate::assets::{Asset, AssetError, Assets, Handle, LoadContext};
+
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+// prefabs containing prefabs deeper than this are assumed to contain themselves
+const MAX_NESTING: usize = 16;
+
+/// A subtree of nodes that can be instantiated into a scene any number of
+/// times, see `Scene::instantiate`. Loaded from json in the scene format with
+/// the first node as the root, and parameters naming a node and a key path
+/// into its json:
+///
+/// ```json
+/// {
+///     "parameters": {
+///         "color": { "node": "bulb", "key": "light.color" },
+///         "shade": { "node": "shade", "key": "overrides.tint" }
+///     },
+///     "nodes": [
+///         { "name": "lamp", "mesh": "props/lamp.gltf" },
+///         { "name": "bulb", "parent": 0, "light": { "type": "point", "intensity": 4, "range": 8 } },
+///         { "name": "shade", "parent": 0, "prefab": "props/shade.json", "overrides": { "tint": [1, 0.8, 0.6] } }
+///     ]
+/// }
+/// ```
+///
+/// Nodes with a `prefab` path get that prefab instantiated below them with
+/// their `overrides`. Paths are relative to the asset directories like in
+/// scenes. Instances are plain nodes, reloading the prefab doesn't change
+/// them.
+#[derive(Debug, Clone)]
+pub struct Prefab {
+	nodes: Vec<Value>,
+	parameters: HashMap<String, Parameter>,
+}
+
+#[derive(Debug, Clone)]
+struct Parameter {
+	node: usize,
+	key: Vec<String>,
+}
+
+impl Prefab {
+	/// Captures the node and everything below it. Meshes and materials that
+	/// weren't loaded from a file are left out with a warning.
+	pub fn from_scene(scene: &Scene, root: NodeId, assets: &Assets) -> Self {
+		Prefab {
+			nodes: scene.nodes_to_json(&scene.subtree(root), assets),
+			parameters: HashMap::new(),
+		}
+	}
+
+	pub fn from_json(json: &str) -> Result<Self, AssetError> {
+		let root: Value = serde_json::from_str(json)?;
+		let nodes = root["nodes"]
+			.as_array()
+			.filter(|nodes| !nodes.is_empty())
+			.ok_or(AssetError::Format("prefab has no nodes"))?;
+		for (index, node) in nodes.iter().enumerate() {
+			if !node.is_object() {
+				return Err(AssetError::Format("prefab node should be an object"));
+			}
+			let parent = node.get("parent").map(|parent| parent.as_u64());
+			match (index, parent) {
+				(0, None) => {}
+				(0, Some(_)) => return Err(AssetError::Format("prefab root can't have a parent")),
+				(_, Some(Some(parent))) if (parent as usize) < index => {}
+				_ => return Err(AssetError::Format("prefab node needs an earlier parent")),
+			}
+		}
+
+		let mut prefab = Prefab {
+			nodes: nodes.clone(),
+			parameters: HashMap::new(),
+		};
+		if let Some(parameters) = root.get("parameters") {
+			let parameters = parameters
+				.as_object()
+				.ok_or(AssetError::Format("prefab parameters should be an object"))?;
+			for (name, parameter) in parameters {
+				let (node, key) = match (parameter["node"].as_str(), parameter["key"].as_str()) {
+					(Some(node), Some(key)) => (node, key),
+					_ => return Err(AssetError::Format("prefab parameter needs a node and key")),
+				};
+				prefab = prefab.with_parameter(name, node, key)?;
+			}
+		}
+		Ok(prefab)
+	}
+
+	/// Json `from_json` reads back.
+	pub fn to_json(&self) -> String {
+		let parameters: Map<String, Value> = self
+			.parameters
+			.iter()
+			.map(|(name, parameter)| {
+				let mut object = Map::new();
+				object.insert(
+					"node".to_string(),
+					self.nodes[parameter.node]["name"].clone(),
+				);
+				object.insert("key".to_string(), Value::from(parameter.key.join(".")));
+				(name.clone(), Value::Object(object))
+			})
+			.collect();
+
+		let mut root = Map::new();
+		root.insert("parameters".to_string(), Value::Object(parameters));
+		root.insert("nodes".to_string(), Value::Array(self.nodes.clone()));
+		serde_json::to_string_pretty(&Value::Object(root)).unwrap()
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+		std::fs::write(path, self.to_json())?;
+		Ok(())
+	}
+
+	/// Adds a parameter `name` setting `key` of the first node called `node`.
+	/// Keys are dot separated paths into the node's json, eg. `light.color`.
+	pub fn with_parameter(mut self, name: &str, node: &str, key: &str) -> Result<Self, AssetError> {
+		let node = self
+			.nodes
+			.iter()
+			.position(|value| value["name"].as_str() == Some(node))
+			.ok_or(AssetError::Format("prefab parameter names a missing node"))?;
+		if key.is_empty() || key.split('.').any(str::is_empty) {
+			return Err(AssetError::Format("prefab parameter key is empty"));
+		}
+		self.parameters.insert(
+			name.to_string(),
+			Parameter {
+				node,
+				key: key.split('.').map(str::to_string).collect(),
+			},
+		);
+		Ok(self)
+	}
+
+	pub fn parameters(&self) -> impl Iterator<Item = &str> {
+		self.parameters.keys().map(String::as_str)
+	}
+
+	/// Number of nodes, not counting nested prefabs.
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+}
+
+impl Parameter {
+	fn apply(&self, nodes: &mut [Value], value: Value) -> Result<(), SceneError> {
+		let (last, path) = self.key.split_last().unwrap();
+		let mut target = &mut nodes[self.node];
+		for part in path {
+			target = target
+				.as_object_mut()
+				.ok_or(SceneError::Format(
+					"prefab parameter key goes through a value",
+				))?
+				.entry(part.clone())
+				.or_insert_with(|| Value::Object(Map::new()));
+		}
+		target
+			.as_object_mut()
+			.ok_or(SceneError::Format(
+				"prefab parameter key goes through a value",
+			))?
+			.insert(last.clone(), value);
+		Ok(())
+	}
+}
+
+impl Asset for Prefab {
+	fn load(context: &mut LoadContext) -> Result<Self, AssetError> {
+		let json = String::from_utf8(context.read()?)
+			.map_err(|_| AssetError::Format("prefab isn't utf-8"))?;
+		Prefab::from_json(&json)
+	}
+}
+
+impl Scene {
+	/// Adds the prefab's nodes below `parent`, or as a root, and returns the
+	/// prefab's root. `overrides` set parameters by name, eg.
+	/// `("color", Value::from(vec![1.0, 0.5, 0.2]))`. Nothing is added when a
+	/// node or nested prefab fails to load.
+	pub fn instantiate(
+		&mut self,
+		prefab: &Handle<Prefab>,
+		parent: Option<NodeId>,
+		overrides: &[(&str, Value)],
+		assets: &mut Assets,
+	) -> Result<NodeId, SceneError> {
+		let overrides: Map<String, Value> = overrides
+			.iter()
+			.map(|(name, value)| (name.to_string(), value.clone()))
+			.collect();
+		let prefab = assets.get(prefab).clone();
+		self.instantiate_prefab(&prefab, parent, &overrides, assets, 0)
+	}
+
+	pub(super) fn instantiate_prefab(
+		&mut self,
+		prefab: &Prefab,
+		parent: Option<NodeId>,
+		overrides: &Map<String, Value>,
+		assets: &mut Assets,
+		depth: usize,
+	) -> Result<NodeId, SceneError> {
+		if depth > MAX_NESTING {
+			return Err(SceneError::Format("prefabs nested too deep"));
+		}
+		let mut nodes = prefab.nodes.clone();
+		for (name, value) in overrides {
+			let parameter = prefab.parameters.get(name).ok_or_else(|| {
+				log::warn!("Prefab has no parameter {}", name);
+				SceneError::Format("unknown prefab parameter")
+			})?;
+			parameter.apply(&mut nodes, value.clone())?;
+		}
+
+		let mut ids = Vec::with_capacity(nodes.len());
+		match self.add_json_nodes(&nodes, parent, assets, depth, &mut ids) {
+			Ok(()) => Ok(ids[0]),
+			Err(error) => {
+				if let Some(&root) = ids.first() {
+					self.remove(root);
+				}
+				Err(error)
+			}
+		}
+	}
+}
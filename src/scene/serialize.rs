@@ -0,0 +1,519 @@
+// scenes as json: nodes are written parents first with the index of their
+// parent, meshes, materials and textures by the path they were loaded from.
+
+use super::{
+	Billboard, BillboardMode, Camera, CameraTarget, Light, Node, NodeId, PostOverride, Prefab,
+	Projection, RenderLayers, Scene,
+};
+
+use crate::animation::Transform;
+use crate::assets::{AssetError, Assets};
+use crate::color::Color;
+
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum SceneError {
+	Io(std::io::Error),
+	Json(serde_json::Error),
+	Asset(AssetError),
+	Format(&'static str),
+}
+
+impl fmt::Display for SceneError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SceneError::Io(error) => write!(f, "failed to access scene file: {}", error),
+			SceneError::Json(error) => write!(f, "invalid scene json: {}", error),
+			SceneError::Asset(error) => write!(f, "failed to load scene asset: {}", error),
+			SceneError::Format(what) => write!(f, "invalid scene: {}", what),
+		}
+	}
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+	fn from(error: std::io::Error) -> Self {
+		SceneError::Io(error)
+	}
+}
+
+impl From<serde_json::Error> for SceneError {
+	fn from(error: serde_json::Error) -> Self {
+		SceneError::Json(error)
+	}
+}
+
+impl From<AssetError> for SceneError {
+	fn from(error: AssetError) -> Self {
+		SceneError::Asset(error)
+	}
+}
+
+impl Scene {
+	/// Writes the scene as json, see `to_json`.
+	pub fn save(&self, path: impl AsRef<Path>, assets: &Assets) -> Result<(), SceneError> {
+		std::fs::write(path, self.to_json(assets))?;
+		Ok(())
+	}
+
+	/// Reads a scene written by `save`, loading its meshes in the background.
+	pub fn load(path: impl AsRef<Path>, assets: &mut Assets) -> Result<Scene, SceneError> {
+		Scene::from_json(&std::fs::read_to_string(path)?, assets)
+	}
+
+	/// The scene as pretty printed json, meshes and materials referenced by
+	/// their asset paths. Assets that weren't loaded from a file are left out
+	/// with a warning.
+	pub fn to_json(&self, assets: &Assets) -> String {
+		let mut root = Map::new();
+		root.insert(
+			"nodes".to_string(),
+			Value::Array(self.nodes_to_json(&self.depth_first(), assets)),
+		);
+		serde_json::to_string_pretty(&Value::Object(root)).unwrap()
+	}
+
+	// `order` has to have parents before children, parents that aren't in
+	// it are left out
+	pub(super) fn nodes_to_json(&self, order: &[NodeId], assets: &Assets) -> Vec<Value> {
+		let indices: HashMap<NodeId, usize> = order
+			.iter()
+			.enumerate()
+			.map(|(index, &id)| (id, index))
+			.collect();
+
+		order
+			.iter()
+			.map(|&id| {
+				let node = self.node(id);
+				let mut object = Map::new();
+				object.insert("name".to_string(), Value::from(node.name.as_str()));
+				if let Some(index) = node.parent.and_then(|parent| indices.get(&parent)) {
+					object.insert("parent".to_string(), Value::from(*index));
+				}
+				object.insert(
+					"translation".to_string(),
+					numbers(&node.transform.translation),
+				);
+				object.insert("rotation".to_string(), numbers(&node.transform.rotation));
+				object.insert("scale".to_string(), numbers(&node.transform.scale));
+
+				let mut reference = |key: &str, path: Option<&Path>, present: bool| match path
+					.and_then(|path| path.to_str())
+				{
+					Some(path) => {
+						object.insert(key.to_string(), Value::from(path));
+					}
+					None if present => {
+						log::warn!(
+							"Not saving the {} of node {}, it has no asset path",
+							key,
+							node.name
+						);
+					}
+					None => {}
+				};
+				reference(
+					"mesh",
+					node.mesh.as_ref().and_then(|mesh| assets.path(mesh)),
+					node.mesh.is_some(),
+				);
+				reference(
+					"material",
+					node.material
+						.as_ref()
+						.and_then(|material| assets.path(material)),
+					node.material.is_some(),
+				);
+
+				// masks are only written when they differ from the default
+				if node.layers != RenderLayers::DEFAULT {
+					object.insert("layers".to_string(), Value::from(node.layers.0));
+				}
+				if let Some(light) = &node.light {
+					object.insert("light".to_string(), light_to_json(light));
+					if node.shadow_mask != RenderLayers::ALL {
+						object.insert("shadow_mask".to_string(), Value::from(node.shadow_mask.0));
+					}
+				}
+				if node.is_static {
+					object.insert("static".to_string(), Value::from(true));
+				}
+				if let Some(camera) = &node.camera {
+					object.insert("camera".to_string(), camera_to_json(camera));
+				}
+				if let Some(billboard) = &node.billboard {
+					match assets
+						.path(&billboard.texture)
+						.and_then(|path| path.to_str())
+					{
+						Some(path) => {
+							object.insert(
+								"billboard".to_string(),
+								billboard_to_json(billboard, path),
+							);
+						}
+						None => log::warn!(
+							"Not saving the billboard of node {}, its texture has no asset path",
+							node.name
+						),
+					}
+				}
+				Value::Object(object)
+			})
+			.collect()
+	}
+
+	/// Parses json written by `to_json`. Meshes load with
+	/// `Assets::load_async`, materials with `Assets::load`. Nodes with a
+	/// `prefab` path get that prefab instantiated below them, see `Prefab`.
+	pub fn from_json(json: &str, assets: &mut Assets) -> Result<Scene, SceneError> {
+		let root: Value = serde_json::from_str(json)?;
+		let nodes = root["nodes"]
+			.as_array()
+			.ok_or(SceneError::Format("scene has no node list"))?;
+
+		let mut scene = Scene::new();
+		scene.add_json_nodes(nodes, None, assets, 0, &mut Vec::new())?;
+		Ok(scene)
+	}
+
+	// adds json nodes below `parent` and instantiates the prefabs they
+	// reference, `ids` gets every node as it's added so failures can be undone
+	pub(super) fn add_json_nodes(
+		&mut self,
+		nodes: &[Value],
+		parent: Option<NodeId>,
+		assets: &mut Assets,
+		depth: usize,
+		ids: &mut Vec<NodeId>,
+	) -> Result<(), SceneError> {
+		for value in nodes {
+			let node_parent = parent_from_json(value, ids)?.or(parent);
+			let id = self.add(node_from_json(value, assets)?, node_parent);
+			ids.push(id);
+
+			if let Some(path) = value.get("prefab") {
+				let path = path
+					.as_str()
+					.ok_or(SceneError::Format("prefab should be a path"))?;
+				let overrides = match value.get("overrides") {
+					Some(overrides) => overrides
+						.as_object()
+						.ok_or(SceneError::Format("prefab overrides should be an object"))?
+						.clone(),
+					None => Map::new(),
+				};
+				let prefab = assets.load::<Prefab>(path)?;
+				let prefab = assets.get(&prefab).clone();
+				self.instantiate_prefab(&prefab, Some(id), &overrides, assets, depth + 1)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+// the already added node a json node's `parent` index points at
+fn parent_from_json(value: &Value, ids: &[NodeId]) -> Result<Option<NodeId>, SceneError> {
+	match value.get("parent") {
+		Some(parent) => Ok(Some(
+			parent
+				.as_u64()
+				.and_then(|index| ids.get(index as usize).copied())
+				.ok_or(SceneError::Format("node parent has to come before it"))?,
+		)),
+		None => Ok(None),
+	}
+}
+
+// everything of a json node except its parent
+fn node_from_json(value: &Value, assets: &mut Assets) -> Result<Node, SceneError> {
+	let mut node = Node::new(value["name"].as_str().unwrap_or_default());
+	let default = Transform::default();
+	node.transform = Transform {
+		translation: read_numbers(value.get("translation"), default.translation)?,
+		rotation: read_numbers(value.get("rotation"), default.rotation)?,
+		scale: read_numbers(value.get("scale"), default.scale)?,
+	};
+	if let Some(path) = value.get("mesh") {
+		let path = path
+			.as_str()
+			.ok_or(SceneError::Format("mesh should be a path"))?;
+		node.mesh = Some(assets.load_async(path)?);
+	}
+	if let Some(path) = value.get("material") {
+		let path = path
+			.as_str()
+			.ok_or(SceneError::Format("material should be a path"))?;
+		node.material = Some(assets.load(path)?);
+	}
+	if let Some(layers) = value.get("layers") {
+		node.layers = layers_from_json(layers)?;
+	}
+	if let Some(light) = value.get("light") {
+		node.light = Some(light_from_json(light)?);
+	}
+	if let Some(mask) = value.get("shadow_mask") {
+		node.shadow_mask = layers_from_json(mask)?;
+	}
+	if let Some(value) = value.get("static") {
+		node.is_static = value
+			.as_bool()
+			.ok_or(SceneError::Format("static should be true or false"))?;
+	}
+	if let Some(camera) = value.get("camera") {
+		node.camera = Some(camera_from_json(camera)?);
+	}
+	if let Some(billboard) = value.get("billboard") {
+		node.billboard = Some(billboard_from_json(billboard, assets)?);
+	}
+	Ok(node)
+}
+
+fn numbers(values: &[f32]) -> Value {
+	Value::Array(values.iter().map(|&n| Value::from(n)).collect())
+}
+
+fn read_numbers<const N: usize>(
+	value: Option<&Value>,
+	default: [f32; N],
+) -> Result<[f32; N], SceneError> {
+	let values = match value {
+		Some(value) => value
+			.as_array()
+			.filter(|values| values.len() == N)
+			.ok_or(SceneError::Format("vector has the wrong length"))?,
+		None => return Ok(default),
+	};
+	let mut out = default;
+	for (out, value) in out.iter_mut().zip(values) {
+		*out = value
+			.as_f64()
+			.ok_or(SceneError::Format("vector should be numbers"))? as f32;
+	}
+	Ok(out)
+}
+
+fn number(value: &Value, key: &str) -> Result<f32, SceneError> {
+	value[key]
+		.as_f64()
+		.map(|n| n as f32)
+		.ok_or(SceneError::Format("missing or invalid number"))
+}
+
+fn layers_from_json(value: &Value) -> Result<RenderLayers, SceneError> {
+	value
+		.as_u64()
+		.filter(|&mask| mask <= u32::MAX as u64)
+		.map(|mask| RenderLayers(mask as u32))
+		.ok_or(SceneError::Format("layers should be a 32 bit mask"))
+}
+
+fn light_to_json(light: &Light) -> Value {
+	let mut object = Map::new();
+	let mut insert = |key: &str, value: Value| {
+		object.insert(key.to_string(), value);
+	};
+	match *light {
+		Light::Directional { color, intensity } => {
+			insert("type", Value::from("directional"));
+			insert("color", numbers(&color.to_rgb()));
+			insert("intensity", Value::from(intensity));
+		}
+		Light::Point {
+			color,
+			intensity,
+			range,
+		} => {
+			insert("type", Value::from("point"));
+			insert("color", numbers(&color.to_rgb()));
+			insert("intensity", Value::from(intensity));
+			insert("range", Value::from(range));
+		}
+		Light::Spot {
+			color,
+			intensity,
+			range,
+			inner_angle,
+			outer_angle,
+		} => {
+			insert("type", Value::from("spot"));
+			insert("color", numbers(&color.to_rgb()));
+			insert("intensity", Value::from(intensity));
+			insert("range", Value::from(range));
+			insert("inner_angle", Value::from(inner_angle));
+			insert("outer_angle", Value::from(outer_angle));
+		}
+	}
+	Value::Object(object)
+}
+
+fn light_from_json(value: &Value) -> Result<Light, SceneError> {
+	let color = Color::from(read_numbers(value.get("color"), [1.0; 3])?);
+	let intensity = number(value, "intensity")?;
+	Ok(match value["type"].as_str() {
+		Some("directional") => Light::Directional { color, intensity },
+		Some("point") => Light::Point {
+			color,
+			intensity,
+			range: number(value, "range")?,
+		},
+		Some("spot") => Light::Spot {
+			color,
+			intensity,
+			range: number(value, "range")?,
+			inner_angle: number(value, "inner_angle")?,
+			outer_angle: number(value, "outer_angle")?,
+		},
+		_ => return Err(SceneError::Format("unknown light type")),
+	})
+}
+
+fn camera_to_json(camera: &Camera) -> Value {
+	let mut object = Map::new();
+	let (kind, size_key, size, near, far) = match camera.projection {
+		Projection::Perspective { fov_y, near, far } => ("perspective", "fov_y", fov_y, near, far),
+		Projection::Orthographic { height, near, far } => {
+			("orthographic", "height", height, near, far)
+		}
+	};
+	object.insert("type".to_string(), Value::from(kind));
+	object.insert(size_key.to_string(), Value::from(size));
+	object.insert("near".to_string(), Value::from(near));
+	object.insert("far".to_string(), Value::from(far));
+	if camera.culling_mask != RenderLayers::ALL {
+		object.insert(
+			"culling_mask".to_string(),
+			Value::from(camera.culling_mask.0),
+		);
+	}
+	if let Some(color) = camera.clear_color {
+		object.insert("clear_color".to_string(), numbers(&color.to_array()));
+	}
+	if let Some(exposure) = camera.exposure {
+		object.insert("exposure".to_string(), Value::from(exposure));
+	}
+	match &camera.post {
+		PostOverride::Inherit => {}
+		PostOverride::Disabled => {
+			object.insert("post".to_string(), Value::from(false));
+		}
+		PostOverride::Only(names) => {
+			let names = names
+				.iter()
+				.map(|name| Value::from(name.as_str()))
+				.collect();
+			object.insert("post".to_string(), Value::Array(names));
+		}
+	}
+	if let CameraTarget::Texture { name, size } = &camera.target {
+		let mut target = Map::new();
+		target.insert("name".to_string(), Value::from(name.as_str()));
+		target.insert("size".to_string(), Value::from(size.to_vec()));
+		object.insert("target".to_string(), Value::Object(target));
+	}
+	Value::Object(object)
+}
+
+fn camera_from_json(value: &Value) -> Result<Camera, SceneError> {
+	let near = number(value, "near")?;
+	let far = number(value, "far")?;
+	let projection = match value["type"].as_str() {
+		Some("perspective") => Projection::Perspective {
+			fov_y: number(value, "fov_y")?,
+			near,
+			far,
+		},
+		Some("orthographic") => Projection::Orthographic {
+			height: number(value, "height")?,
+			near,
+			far,
+		},
+		_ => return Err(SceneError::Format("unknown camera type")),
+	};
+	let culling_mask = match value.get("culling_mask") {
+		Some(mask) => layers_from_json(mask)?,
+		None => RenderLayers::ALL,
+	};
+	let clear_color = match value.get("clear_color") {
+		Some(color) => Some(Color::from(read_numbers(Some(color), [0.0; 4])?)),
+		None => None,
+	};
+	let exposure = match value.get("exposure") {
+		Some(_) => Some(number(value, "exposure")?),
+		None => None,
+	};
+	let post = match value.get("post") {
+		None => PostOverride::Inherit,
+		Some(Value::Bool(true)) => PostOverride::Inherit,
+		Some(Value::Bool(false)) => PostOverride::Disabled,
+		Some(Value::Array(names)) => PostOverride::Only(
+			names
+				.iter()
+				.map(|name| name.as_str().map(|name| name.to_string()))
+				.collect::<Option<_>>()
+				.ok_or(SceneError::Format("post effects should be names"))?,
+		),
+		Some(_) => return Err(SceneError::Format("post should be a bool or names")),
+	};
+	let target = match value.get("target") {
+		Some(target) => {
+			let size = target
+				.get("size")
+				.ok_or(SceneError::Format("camera target needs a size"))?;
+			let size = read_numbers(Some(size), [0.0; 2])?;
+			CameraTarget::Texture {
+				name: target["name"]
+					.as_str()
+					.ok_or(SceneError::Format("camera target needs a name"))?
+					.to_string(),
+				size: [size[0] as u32, size[1] as u32],
+			}
+		}
+		None => CameraTarget::Screen,
+	};
+	Ok(Camera {
+		projection,
+		culling_mask,
+		clear_color,
+		exposure,
+		post,
+		target,
+	})
+}
+
+fn billboard_to_json(billboard: &Billboard, texture: &str) -> Value {
+	let mut object = Map::new();
+	object.insert("texture".to_string(), Value::from(texture));
+	object.insert("size".to_string(), numbers(&billboard.size));
+	object.insert("color".to_string(), numbers(&billboard.color));
+	let mode = match billboard.mode {
+		BillboardMode::Full => "full",
+		BillboardMode::Axis => "axis",
+	};
+	object.insert("mode".to_string(), Value::from(mode));
+	Value::Object(object)
+}
+
+fn billboard_from_json(value: &Value, assets: &mut Assets) -> Result<Billboard, SceneError> {
+	let texture = value["texture"]
+		.as_str()
+		.ok_or(SceneError::Format("billboard texture should be a path"))?;
+	let mode = match value["mode"].as_str() {
+		Some("full") | None => BillboardMode::Full,
+		Some("axis") => BillboardMode::Axis,
+		_ => return Err(SceneError::Format("unknown billboard mode")),
+	};
+	Ok(Billboard {
+		texture: assets.load(texture)?,
+		size: read_numbers(value.get("size"), [1.0; 2])?,
+		color: read_numbers(value.get("color"), [1.0; 4])?,
+		mode,
+	})
+}
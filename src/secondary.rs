@@ -0,0 +1,201 @@
+// a second logical device for heavy offline work like baking assets,
+// prefiltering ibl probes or encoding video, so it doesn't queue up behind
+// the frame. on machines with two gpus it runs on the one not presenting,
+// otherwise on a device of its own on the same gpu, which at least keeps its
+// submissions and their fences apart from the frame's.
+//
+// vulkan objects can't be shared between devices, so results come back
+// through host memory: `read_image` downloads them on the secondary device
+// and `HostImage::upload` uploads them to the presenting one.
+
+use crate::portability;
+use crate::render2d::Texture;
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::device::{Device, DeviceExtensions, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageAccess, ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::instance::PhysicalDevice;
+use vulkano::sync::GpuFuture;
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+/// A device for background gpu work next to the one presenting.
+pub struct SecondaryDevice {
+	device: Arc<Device>,
+	queue: Arc<Queue>,
+	separate: bool,
+}
+
+impl SecondaryDevice {
+	/// Creates a device on another gpu than `primary`'s when there is one,
+	/// otherwise on the same gpu. `None` when no device could be created.
+	pub fn new(primary: &Device) -> Option<Self> {
+		let instance = primary.instance();
+		let primary_index = primary.physical_device().index();
+		let other = PhysicalDevice::enumerate(instance)
+			.filter(|physical| physical.index() != primary_index)
+			.find(|physical| family(*physical).is_some());
+		let physical = other.unwrap_or_else(|| primary.physical_device());
+
+		let family = family(physical)?;
+		let extensions = DeviceExtensions {
+			khr_storage_buffer_storage_class: true,
+			..DeviceExtensions::none()
+		};
+		let (device, mut queues) = match Device::new(
+			physical,
+			physical.supported_features(),
+			&portability::device_extensions(physical, extensions),
+			// below the frame's queue where the gpu is shared
+			[(family, 0.25)].iter().cloned(),
+		) {
+			Ok(r) => r,
+			Err(e) => {
+				log::warn!("Failed to create a secondary device: {:?}", e);
+				return None;
+			}
+		};
+		log::info!("Using secondary device: {}", physical.name());
+
+		Some(SecondaryDevice {
+			device,
+			queue: queues.next().unwrap(),
+			separate: other.is_some(),
+		})
+	}
+
+	pub fn device(&self) -> &Arc<Device> {
+		&self.device
+	}
+
+	pub fn queue(&self) -> &Arc<Queue> {
+		&self.queue
+	}
+
+	/// True when the device is on another gpu than the presenting one.
+	pub fn is_separate_gpu(&self) -> bool {
+		self.separate
+	}
+
+	/// Runs `job` on a thread of its own with the secondary device and queue.
+	/// The job can block on its fences, it only holds up itself. Bring
+	/// images back with `read_image`.
+	pub fn spawn<T, F>(&self, job: F) -> SecondaryJob<T>
+	where
+		F: FnOnce(&Arc<Device>, &Arc<Queue>) -> T + Send + 'static,
+		T: Send + 'static,
+	{
+		let device = self.device.clone();
+		let queue = self.queue.clone();
+		let (sender, receiver) = mpsc::channel();
+		thread::Builder::new()
+			.name("secondary gpu job".to_string())
+			.spawn(move || {
+				// the receiver may be gone if nobody waits for the result
+				sender.send(job(&device, &queue)).ok();
+			})
+			.unwrap();
+		SecondaryJob { receiver }
+	}
+}
+
+// graphics for baking with render passes, compute only as a fallback
+fn family(physical: PhysicalDevice) -> Option<vulkano::instance::QueueFamily> {
+	physical
+		.queue_families()
+		.find(|q| q.supports_graphics())
+		.or_else(|| physical.queue_families().find(|q| q.supports_compute()))
+}
+
+/// The result of a job on the secondary device.
+pub struct SecondaryJob<T> {
+	receiver: Receiver<T>,
+}
+
+impl<T> SecondaryJob<T> {
+	/// The result once the job has finished, without blocking. Only returns
+	/// it once.
+	pub fn poll(&self) -> Option<T> {
+		self.receiver.try_recv().ok()
+	}
+
+	/// Blocks until the job has finished. Panics if the job did.
+	pub fn wait(self) -> T {
+		self.receiver.recv().expect("secondary gpu job panicked")
+	}
+}
+
+/// Pixels of a single level 2d image in host memory, on their way from one
+/// device to another.
+#[derive(Debug, Clone)]
+pub struct HostImage {
+	pub size: [u32; 2],
+	pub format: Format,
+	/// Tightly packed rows.
+	pub pixels: Vec<u8>,
+}
+
+impl HostImage {
+	/// Creates the image on `queue`'s device, usually the presenting one.
+	/// Join the future into the frame, eg. with `Assets::upload`.
+	pub fn upload(&self, queue: Arc<Queue>) -> (Texture, impl GpuFuture) {
+		let (image, future) = ImmutableImage::from_iter(
+			self.pixels.iter().copied(),
+			ImageDimensions::Dim2d {
+				width: self.size[0],
+				height: self.size[1],
+				array_layers: 1,
+			},
+			MipmapsCount::One,
+			self.format,
+			queue,
+		)
+		.unwrap();
+		(ImageView::new(image).unwrap(), future)
+	}
+}
+
+/// Copies the first level and layer of `image` into host memory, blocking
+/// until the gpu is done. Meant for the threads of `SecondaryDevice::spawn`,
+/// with everything that writes the image already submitted on `queue`.
+pub fn read_image<I>(queue: &Arc<Queue>, image: I) -> HostImage
+where
+	I: ImageAccess + Send + Sync + 'static,
+{
+	let device = queue.device().clone();
+	let format = image.format();
+	let [width, height] = image.dimensions().width_height();
+	let length = width as usize * height as usize * format.size().expect("compressed format");
+	let buffer = CpuAccessibleBuffer::from_iter(
+		device.clone(),
+		BufferUsage::transfer_destination(),
+		false,
+		(0..length).map(|_| 0u8),
+	)
+	.unwrap();
+
+	let mut builder =
+		AutoCommandBufferBuilder::primary_one_time_submit(device, queue.family()).unwrap();
+	builder.copy_image_to_buffer(image, buffer.clone()).unwrap();
+	builder
+		.build()
+		.unwrap()
+		.execute(queue.clone())
+		.unwrap()
+		.then_signal_fence_and_flush()
+		.unwrap()
+		.wait(None)
+		.unwrap();
+
+	let pixels = buffer.read().unwrap().to_vec();
+	HostImage {
+		size: [width, height],
+		format,
+		pixels,
+	}
+}
@@ -0,0 +1,133 @@
+// user facing graphics options, the kind shown in a settings menu.
+
+use std::time::Duration;
+
+/// Resolution of shadow maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadowQuality {
+	Off,
+	Low,
+	#[default]
+	Medium,
+	High,
+	Ultra,
+}
+
+impl ShadowQuality {
+	/// Size of each shadow map, `None` when shadows are off.
+	pub fn map_size(self) -> Option<u32> {
+		match self {
+			ShadowQuality::Off => None,
+			ShadowQuality::Low => Some(512),
+			ShadowQuality::Medium => Some(1024),
+			ShadowQuality::High => Some(2048),
+			ShadowQuality::Ultra => Some(4096),
+		}
+	}
+}
+
+/// How fog is drawn, see `fog::VolumetricFog`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FogQuality {
+	Off,
+	/// Fades to the fog color with distance.
+	Distance,
+	/// Distance fog that thins out with height.
+	Height,
+	/// Lit fog in a froxel volume, with light shafts from the shadow map.
+	#[default]
+	Volumetric,
+}
+
+/// Post processing toggles, read by the passes that implement them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostEffects {
+	/// Fsr style upscaling when drawing below window resolution, bilinear without.
+	pub upscaling: bool,
+	/// Sharpening after upscaling, 0 to 1.
+	pub sharpness: f32,
+	pub bloom: bool,
+	pub ambient_occlusion: bool,
+}
+
+impl Default for PostEffects {
+	fn default() -> Self {
+		PostEffects {
+			upscaling: true,
+			sharpness: 0.8,
+			bloom: true,
+			ambient_occlusion: true,
+		}
+	}
+}
+
+/// Everything that can be changed from a graphics settings menu, hand it to
+/// `Renderer::apply_settings`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphicsSettings {
+	/// Fraction of the window resolution the scene is drawn at.
+	pub render_scale: f32,
+	/// Frame time the render scale adapts to, `None` keeps `render_scale`.
+	/// Only with `vsync` off, frames take the refresh interval otherwise.
+	pub target_frame_time: Option<Duration>,
+	/// Samples per pixel of the scene, 1 disables msaa. Rounded down to what
+	/// the device supports.
+	pub msaa: u32,
+	pub shadow_quality: ShadowQuality,
+	pub fog: FogQuality,
+	/// Draw the opaque depth in a pass of its own first, so expensive
+	/// fragment shaders only run for visible pixels. Pays off with a lot of
+	/// overdraw, see `PassStage::DepthPrepass`.
+	pub depth_prepass: bool,
+	pub vsync: bool,
+	/// Maximum anisotropic filtering of material textures, 1 disables it.
+	pub anisotropy: f32,
+	pub post: PostEffects,
+}
+
+impl Default for GraphicsSettings {
+	fn default() -> Self {
+		GraphicsSettings {
+			render_scale: 1.0,
+			target_frame_time: None,
+			msaa: 1,
+			shadow_quality: ShadowQuality::default(),
+			fog: FogQuality::default(),
+			depth_prepass: false,
+			vsync: true,
+			anisotropy: 8.0,
+			post: PostEffects::default(),
+		}
+	}
+}
+
+/// What applying new settings invalidated, so systems outside the renderer
+/// know what to rebuild. See `Renderer::changes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SettingsChanges {
+	/// The window's render pass was recreated, rebuild pipelines drawing into it.
+	pub window_render_pass: bool,
+	/// Msaa changed, rebuild scene targets and the pipelines drawing into them.
+	pub msaa: bool,
+	/// Shadow maps have to be reallocated.
+	pub shadows: bool,
+	/// The depth pre-pass was turned on or off, rebuild the opaque pipelines
+	/// for the depth test that goes with it, see `prepass::opaque_depth`.
+	pub depth_prepass: bool,
+	/// `Renderer::sampler` was recreated, rebuild descriptor sets using it.
+	pub sampler: bool,
+	/// The window's scale factor changed, lay out ui and rasterize text for
+	/// the new one.
+	pub scale_factor: bool,
+}
+
+impl SettingsChanges {
+	pub fn any(&self) -> bool {
+		self.window_render_pass
+			|| self.msaa
+			|| self.shadows
+			|| self.depth_prepass
+			|| self.sampler
+			|| self.scale_factor
+	}
+}
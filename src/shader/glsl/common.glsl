@@ -0,0 +1,26 @@
+#ifndef OPAL_COMMON_GLSL
+#define OPAL_COMMON_GLSL
+
+#define PI 3.14159265359
+#define TAU 6.28318530718
+
+float saturate(float x) {
+	return clamp(x, 0.0, 1.0);
+}
+
+vec3 saturate(vec3 x) {
+	return clamp(x, vec3(0.0), vec3(1.0));
+}
+
+// rec. 709 luminance of a linear color
+float luminance(vec3 c) {
+	return dot(c, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// low discrepancy point i of count in 0..1
+vec2 hammersley(uint i, uint count) {
+	uint bits = bitfieldReverse(i);
+	return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+#endif
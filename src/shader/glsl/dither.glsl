@@ -0,0 +1,35 @@
+#ifndef OPAL_DITHER_GLSL
+#define OPAL_DITHER_GLSL
+
+// screen-door fading: instead of blending, a fading object discards a share of
+// its pixels in a fixed pattern, so it stays opaque and keeps writing depth.
+// the fade comes per instance from `fade::instance_fade`, 1 is fully drawn, 0
+// gone, and negative while a lod fades in. for fragment shaders only.
+
+// interleaved gradient noise, a pattern in 0..1 that dithers well at any
+// coverage
+float interleaved_gradient_noise(vec2 pixel) {
+	return fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
+}
+
+// whether the pixel is left out at `fade`. lods fading in use the inverse
+// pattern of the ones fading out, so together they cover each pixel once
+bool dither_discarded(vec2 pixel, float fade) {
+	float dither = interleaved_gradient_noise(pixel);
+	if (fade < 0.0) {
+		dither = 1.0 - dither;
+	}
+	return dither >= abs(fade);
+}
+
+// discards the fragment when it's left out at `fade`, a no-op unless the
+// permutation has `DITHER_FADE`
+void dither_fade(float fade) {
+#ifdef DITHER_FADE
+	if (dither_discarded(gl_FragCoord.xy, fade)) {
+		discard;
+	}
+#endif
+}
+
+#endif
@@ -0,0 +1,74 @@
+#ifndef OPAL_LIGHTING_GLSL
+#define OPAL_LIGHTING_GLSL
+
+#include "common.glsl"
+
+// metallic roughness pbr, matching the gltf material model
+
+// ggx normal distribution, alpha is roughness squared
+float distribution_ggx(float n_dot_h, float alpha) {
+	float a2 = alpha * alpha;
+	float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+	return a2 / (PI * d * d);
+}
+
+// height correlated smith visibility, includes the 1 / (4 n.l n.v) term
+float visibility_smith_ggx(float n_dot_v, float n_dot_l, float alpha) {
+	float a2 = alpha * alpha;
+	float v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - a2) + a2);
+	float l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - a2) + a2);
+	return 0.5 / max(v + l, 1e-5);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+	return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// direction around n for a ggx lobe, xi from `hammersley`
+vec3 importance_sample_ggx(vec2 xi, vec3 n, float alpha) {
+	float phi = TAU * xi.x;
+	float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+	float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+	vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+	vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+	vec3 tangent = normalize(cross(up, n));
+	vec3 bitangent = cross(n, tangent);
+	return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+// light reflected towards v from a light in direction l with the given
+// radiance. n, v and l are normalized and point away from the surface.
+vec3 shade_light(
+	vec3 n,
+	vec3 v,
+	vec3 l,
+	vec3 radiance,
+	vec3 base_color,
+	float metallic,
+	float roughness
+) {
+	float n_dot_l = saturate(dot(n, l));
+	if (n_dot_l <= 0.0) {
+		return vec3(0.0);
+	}
+	vec3 h = normalize(v + l);
+	float n_dot_v = max(dot(n, v), 1e-4);
+	float n_dot_h = saturate(dot(n, h));
+	float alpha = max(roughness * roughness, 0.002);
+
+	vec3 f0 = mix(vec3(0.04), base_color, metallic);
+	vec3 f = fresnel_schlick(saturate(dot(v, h)), f0);
+	vec3 specular = f * distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx(n_dot_v, n_dot_l, alpha);
+	vec3 diffuse = (1.0 - f) * (1.0 - metallic) * base_color / PI;
+	return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// inverse square falloff that reaches zero at range
+float attenuation(float distance, float range) {
+	float ratio = distance / range;
+	float window = saturate(1.0 - ratio * ratio * ratio * ratio);
+	return window * window / max(distance * distance, 1e-4);
+}
+
+#endif
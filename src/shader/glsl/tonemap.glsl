@@ -0,0 +1,50 @@
+#ifndef OPAL_TONEMAP_GLSL
+#define OPAL_TONEMAP_GLSL
+
+// linear up to the knee, then rolls off towards max_output. the same curve
+// the final output transform uses, see `display::HdrSettings::max_output`
+vec3 tonemap_rolloff(vec3 c, float max_output) {
+	c = max(c, vec3(0.0));
+	float peak = max(c.r, max(c.g, c.b));
+	float knee = 0.5 * max_output;
+	if (peak > knee) {
+		float range = max_output - knee;
+		c *= (knee + range * (1.0 - exp((knee - peak) / range))) / peak;
+	}
+	return c;
+}
+
+vec3 tonemap_reinhard(vec3 c) {
+	return c / (1.0 + c);
+}
+
+// narkowicz's fit of the aces filmic curve
+vec3 tonemap_aces(vec3 c) {
+	c *= 0.6;
+	return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 linear_to_srgb(vec3 c) {
+	return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, greaterThan(c, vec3(0.0031308)));
+}
+
+vec3 srgb_to_linear(vec3 c) {
+	return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), greaterThan(c, vec3(0.04045)));
+}
+
+// rec. 709 to rec. 2020 primaries, both linear
+vec3 rec709_to_rec2020(vec3 c) {
+	return vec3(
+		dot(vec3(0.6274, 0.3293, 0.0433), c),
+		dot(vec3(0.0691, 0.9195, 0.0114), c),
+		dot(vec3(0.0164, 0.0880, 0.8956), c)
+	);
+}
+
+// st 2084 (pq) curve, nits is absolute luminance
+vec3 encode_pq(vec3 nits) {
+	vec3 l = pow(max(nits, vec3(0.0)) / 10000.0, vec3(0.1593017578125));
+	return pow((0.8359375 + 18.8515625 * l) / (1.0 + 18.6875 * l), vec3(78.84375));
+}
+
+#endif
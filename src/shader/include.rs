@@ -0,0 +1,219 @@
+// `#include` resolution for runtime shaders. includes are looked up in a
+// virtual file system first, which holds the engine's shared chunks under
+// `opal/`, then in any directories added to it.
+
+use shaderc::{IncludeType, ResolvedInclude};
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+
+// deeper than this is almost certainly an include cycle without a guard
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Chunks every library starts out with.
+pub const BUILTIN_CHUNKS: &[(&str, &str)] = &[
+	("opal/common.glsl", include_str!("glsl/common.glsl")),
+	("opal/dither.glsl", include_str!("glsl/dither.glsl")),
+	("opal/lighting.glsl", include_str!("glsl/lighting.glsl")),
+	("opal/tonemap.glsl", include_str!("glsl/tonemap.glsl")),
+];
+
+/// Files `#include` can pull in, by path. Paths always use `/`.
+///
+/// `#include "file.glsl"` looks next to the including file first and then
+/// from the root, `#include <file.glsl>` only from the root. Built-in chunks
+/// are included with eg. `#include <opal/lighting.glsl>`.
+#[derive(Debug, Clone)]
+pub struct ShaderLibrary {
+	files: HashMap<String, Cow<'static, str>>,
+	directories: Vec<PathBuf>,
+}
+
+impl Default for ShaderLibrary {
+	fn default() -> Self {
+		let mut library = ShaderLibrary::empty();
+		for &(path, source) in BUILTIN_CHUNKS {
+			library.add(path, source);
+		}
+		library
+	}
+}
+
+impl ShaderLibrary {
+	/// Library with the built-in chunks.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Library without any files, not even the built-in ones.
+	pub fn empty() -> Self {
+		ShaderLibrary {
+			files: HashMap::new(),
+			directories: Vec::new(),
+		}
+	}
+
+	/// Adds or replaces a file. Replacing a built-in chunk changes it for
+	/// every shader compiled with this library.
+	pub fn add(&mut self, path: &str, source: impl Into<Cow<'static, str>>) {
+		self.files.insert(normalize(path), source.into());
+	}
+
+	pub fn remove(&mut self, path: &str) -> Option<Cow<'static, str>> {
+		self.files.remove(&normalize(path))
+	}
+
+	pub fn get(&self, path: &str) -> Option<&str> {
+		self.files
+			.get(&normalize(path))
+			.map(|source| source.as_ref())
+	}
+
+	/// Includes missing from the virtual files are looked up under `directory`
+	/// on disk. Directories are searched in the order they were added.
+	pub fn add_directory(&mut self, directory: impl Into<PathBuf>) {
+		self.directories.push(directory.into());
+	}
+
+	/// Paths of the virtual files.
+	pub fn paths(&self) -> impl Iterator<Item = &str> {
+		self.files.keys().map(|path| path.as_str())
+	}
+
+	fn find(&self, path: &str) -> Option<ResolvedInclude> {
+		if let Some(source) = self.files.get(path) {
+			return Some(ResolvedInclude {
+				resolved_name: path.to_string(),
+				content: source.to_string(),
+			});
+		}
+		self.directories.iter().find_map(|directory| {
+			let content = std::fs::read_to_string(directory.join(path)).ok()?;
+			Some(ResolvedInclude {
+				resolved_name: path.to_string(),
+				content,
+			})
+		})
+	}
+
+	// called by shaderc for every `#include`
+	pub(super) fn resolve(
+		&self,
+		requested: &str,
+		include_type: IncludeType,
+		requesting: &str,
+		depth: usize,
+	) -> Result<ResolvedInclude, String> {
+		if depth > MAX_INCLUDE_DEPTH {
+			return Err(format!(
+				"includes nested deeper than {}, is an include guard missing?",
+				MAX_INCLUDE_DEPTH
+			));
+		}
+
+		let relative = match include_type {
+			IncludeType::Relative => {
+				let directory = match requesting.rfind('/') {
+					Some(end) => &requesting[..end],
+					None => "",
+				};
+				self.find(&normalize(&format!("{}/{}", directory, requested)))
+			}
+			IncludeType::Standard => None,
+		};
+		relative
+			.or_else(|| self.find(&normalize(requested)))
+			.ok_or_else(|| format!("no shader file named {}", requested))
+	}
+}
+
+// collapses `.`, `..` and repeated slashes
+fn normalize(path: &str) -> String {
+	let mut parts: Vec<&str> = Vec::new();
+	for part in path.split(['/', '\\']) {
+		match part {
+			"" | "." => {}
+			".." => {
+				parts.pop();
+			}
+			part => parts.push(part),
+		}
+	}
+	parts.join("/")
+}
+
+/// Preprocessor macros a shader is compiled with, eg. to pick a permutation
+/// with `#ifdef`. Kept sorted so equal sets compare and hash the same.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Defines(BTreeMap<String, Option<String>>);
+
+impl Defines {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Defines `name` without a value, like `#define name`.
+	pub fn define(&mut self, name: &str) -> &mut Self {
+		self.0.insert(name.to_string(), None);
+		self
+	}
+
+	/// Defines `name` as `value`, like `#define name value`.
+	pub fn set(&mut self, name: &str, value: impl ToString) -> &mut Self {
+		self.0.insert(name.to_string(), Some(value.to_string()));
+		self
+	}
+
+	/// `define` that takes and returns the defines, for building them inline.
+	pub fn with(mut self, name: &str) -> Self {
+		self.define(name);
+		self
+	}
+
+	/// `set` that takes and returns the defines, for building them inline.
+	pub fn with_value(mut self, name: &str, value: impl ToString) -> Self {
+		self.set(name, value);
+		self
+	}
+
+	pub fn remove(&mut self, name: &str) -> &mut Self {
+		self.0.remove(name);
+		self
+	}
+
+	pub fn contains(&self, name: &str) -> bool {
+		self.0.contains_key(name)
+	}
+
+	pub fn get(&self, name: &str) -> Option<&str> {
+		self.0.get(name).and_then(|value| value.as_deref())
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+		self.0
+			.iter()
+			.map(|(name, value)| (name.as_str(), value.as_deref()))
+	}
+}
+
+/// `A B=2` style, for logs and error messages.
+impl fmt::Display for Defines {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (i, (name, value)) in self.iter().enumerate() {
+			if i > 0 {
+				write!(f, " ")?;
+			}
+			match value {
+				Some(value) => write!(f, "{}={}", name, value)?,
+				None => write!(f, "{}", name)?,
+			}
+		}
+		Ok(())
+	}
+}
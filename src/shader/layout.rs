@@ -0,0 +1,209 @@
+// pipeline layouts and shader interfaces built from reflection, and checks
+// that what the rust side binds matches what the shaders declare.
+
+use super::{InterfaceVariable, Reflection, ShaderError};
+
+use crate::geometry::layout::VertexFormat;
+
+use vulkano::descriptor::descriptor::{DescriptorDesc, DescriptorDescTy, ShaderStages};
+use vulkano::descriptor::pipeline_layout::{PipelineLayoutDescPcRange, RuntimePipelineDesc};
+use vulkano::pipeline::shader::{ShaderInterfaceDef, ShaderInterfaceDefEntry};
+use vulkano::pipeline::vertex::VertexDefinition;
+
+use std::borrow::Cow;
+use std::vec::IntoIter;
+
+/// Pipeline layout of the stages of one pipeline, merged from their
+/// reflections.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderLayout {
+	// indexed by set and binding
+	sets: Vec<Vec<Option<DescriptorDesc>>>,
+	push_constants: Option<PipelineLayoutDescPcRange>,
+}
+
+impl ShaderLayout {
+	/// Merges the stages' descriptors. A binding used by several stages
+	/// has to be declared the same way in each.
+	pub fn new(stages: &[&Reflection]) -> Result<Self, ShaderError> {
+		let mut layout = ShaderLayout::default();
+		for reflection in stages.iter() {
+			let stage = match reflection.stage {
+				Some(stage) => stage.shader_stages(),
+				None => ShaderStages::none(),
+			};
+
+			for descriptor in reflection.descriptors.iter() {
+				let set = descriptor.set as usize;
+				let binding = descriptor.binding as usize;
+				if layout.sets.len() <= set {
+					layout.sets.resize(set + 1, Vec::new());
+				}
+				let bindings = &mut layout.sets[set];
+				if bindings.len() <= binding {
+					bindings.resize(binding + 1, None);
+				}
+				bindings[binding] = match bindings[binding].take() {
+					Some(existing) => Some(existing.union(&descriptor.desc).ok_or_else(|| {
+						ShaderError::Mismatch(format!(
+							"set {} binding {} ({}) has a different type in each stage",
+							set, binding, descriptor.name
+						))
+					})?),
+					None => Some(descriptor.desc.clone()),
+				};
+			}
+
+			// one range every stage can see, like a single push constant
+			// block shared between stages
+			if reflection.push_constant_size > 0 {
+				let range = layout
+					.push_constants
+					.get_or_insert(PipelineLayoutDescPcRange {
+						offset: 0,
+						size: 0,
+						stages: ShaderStages::none(),
+					});
+				range.size = range.size.max(reflection.push_constant_size);
+				range.stages = range.stages | stage;
+			}
+		}
+		Ok(layout)
+	}
+
+	pub fn set_count(&self) -> usize {
+		self.sets.len()
+	}
+
+	pub fn descriptor(&self, set: u32, binding: u32) -> Option<&DescriptorDesc> {
+		self.sets.get(set as usize)?.get(binding as usize)?.as_ref()
+	}
+
+	/// Bindings of a set, `None` for gaps.
+	pub fn bindings(&self, set: u32) -> &[Option<DescriptorDesc>] {
+		self.sets
+			.get(set as usize)
+			.map(|bindings| bindings.as_slice())
+			.unwrap_or_default()
+	}
+
+	/// Makes a buffer binding dynamic, so draws pick the part of the buffer
+	/// they use with a dynamic offset, see `ObjectUniforms`.
+	pub fn set_dynamic(&mut self, set: u32, binding: u32) -> Result<(), ShaderError> {
+		let desc = self
+			.sets
+			.get_mut(set as usize)
+			.and_then(|bindings| bindings.get_mut(binding as usize)?.as_mut());
+		match desc.map(|desc| &mut desc.ty) {
+			Some(DescriptorDescTy::Buffer(buffer)) => {
+				buffer.dynamic = Some(true);
+				Ok(())
+			}
+			_ => Err(ShaderError::Mismatch(format!(
+				"set {} binding {} is not a buffer and can't be dynamic",
+				set, binding
+			))),
+		}
+	}
+
+	/// Size of the push constant block, 0 without one.
+	pub fn push_constant_size(&self) -> usize {
+		self.push_constants.map(|range| range.size).unwrap_or(0)
+	}
+
+	/// The layout for `ShaderModule::*_entry_point`.
+	pub fn pipeline_desc(&self) -> RuntimePipelineDesc {
+		// a single push constant range can't conflict with anything
+		RuntimePipelineDesc::new(self.sets.iter().cloned(), self.push_constants).unwrap()
+	}
+
+	/// Checks a descriptor set's bindings, in binding order, against what the
+	/// shaders declare. The bindings have to cover every declared one, extra
+	/// bindings are fine.
+	pub fn check_set(
+		&self,
+		set: u32,
+		bindings: &[Option<DescriptorDesc>],
+	) -> Result<(), ShaderError> {
+		for (binding, declared) in self.bindings(set).iter().enumerate() {
+			let declared = match declared {
+				Some(declared) => declared,
+				None => continue,
+			};
+			let provided = bindings
+				.get(binding)
+				.and_then(|provided| provided.as_ref())
+				.ok_or_else(|| {
+					ShaderError::Mismatch(format!(
+						"set {} binding {} is used by the shader but not provided",
+						set, binding
+					))
+				})?;
+			provided.is_superset_of(declared).map_err(|error| {
+				ShaderError::Mismatch(format!("set {} binding {}: {}", set, binding, error))
+			})?;
+		}
+		Ok(())
+	}
+
+	/// Checks that the push constants passed are big enough for the block
+	/// the shaders declare.
+	pub fn check_push_constants(&self, size: usize) -> Result<(), ShaderError> {
+		if size < self.push_constant_size() {
+			return Err(ShaderError::Mismatch(format!(
+				"push constants are {} bytes but the shader reads {}",
+				size,
+				self.push_constant_size()
+			)));
+		}
+		Ok(())
+	}
+}
+
+/// Inputs or outputs of a stage as vulkano's graphics entry points want
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderInterface(Vec<InterfaceVariable>);
+
+impl ShaderInterface {
+	pub fn inputs(reflection: &Reflection) -> Self {
+		ShaderInterface(reflection.inputs.clone())
+	}
+
+	pub fn outputs(reflection: &Reflection) -> Self {
+		ShaderInterface(reflection.outputs.clone())
+	}
+
+	pub fn variables(&self) -> &[InterfaceVariable] {
+		&self.0
+	}
+}
+
+// safe because the entries come straight from the spir-v
+unsafe impl ShaderInterfaceDef for ShaderInterface {
+	type Iter = IntoIter<ShaderInterfaceDefEntry>;
+
+	fn elements(&self) -> Self::Iter {
+		self.0
+			.iter()
+			.map(|variable| ShaderInterfaceDefEntry {
+				location: variable.location..variable.location + variable.locations,
+				format: variable.format,
+				name: Some(Cow::Owned(variable.name.clone())),
+			})
+			.collect::<Vec<_>>()
+			.into_iter()
+	}
+}
+
+/// Checks that `format` has every attribute a vertex shader reads, with the
+/// right number of components. Vertex inputs are matched by name, like
+/// `VertexFormat` does when the pipeline is built.
+pub fn check_vertex_input(
+	reflection: &Reflection,
+	format: &VertexFormat,
+) -> Result<(), ShaderError> {
+	VertexDefinition::definition(format, &ShaderInterface::inputs(reflection))
+		.map(|_| ())
+		.map_err(|error| ShaderError::Mismatch(format!("vertex input: {}", error)))
+}
@@ -0,0 +1,133 @@
+// shaders only known at runtime, glsl compiled with shaderc or spir-v loaded
+// from disk. shaders built into the engine keep using
+// `vulkano_shaders::shader!`, which does the same work at compile time.
+
+mod include;
+mod layout;
+mod reflect;
+mod specialization;
+mod variants;
+
+pub use include::{Defines, ShaderLibrary, BUILTIN_CHUNKS};
+pub use layout::{check_vertex_input, ShaderInterface, ShaderLayout};
+pub use reflect::{DescriptorBinding, InterfaceVariable, Reflection};
+pub use specialization::{SpecConstant, SpecConstants, SpecValue, MAX_SPEC_CONSTANTS};
+pub use variants::{
+	Feature, ShaderFeatures, ShaderSource, ShaderVariants, Variant, VariantStage, LIGHT_BUCKETS,
+};
+
+use vulkano::descriptor::descriptor::ShaderStages;
+
+use std::fmt;
+
+/// Pipeline stage a shader is compiled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+	Vertex,
+	TessellationControl,
+	TessellationEvaluation,
+	Geometry,
+	Fragment,
+	Compute,
+}
+
+impl ShaderStage {
+	fn kind(self) -> shaderc::ShaderKind {
+		match self {
+			ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
+			ShaderStage::TessellationControl => shaderc::ShaderKind::TessControl,
+			ShaderStage::TessellationEvaluation => shaderc::ShaderKind::TessEvaluation,
+			ShaderStage::Geometry => shaderc::ShaderKind::Geometry,
+			ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
+			ShaderStage::Compute => shaderc::ShaderKind::Compute,
+		}
+	}
+
+	pub fn shader_stages(self) -> ShaderStages {
+		ShaderStages {
+			vertex: self == ShaderStage::Vertex,
+			tessellation_control: self == ShaderStage::TessellationControl,
+			tessellation_evaluation: self == ShaderStage::TessellationEvaluation,
+			geometry: self == ShaderStage::Geometry,
+			fragment: self == ShaderStage::Fragment,
+			compute: self == ShaderStage::Compute,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+	/// The glsl failed to compile, holds the compiler output.
+	Compile(String),
+	/// The spir-v is malformed or uses something reflection doesn't handle.
+	Reflect(&'static str),
+	/// No specialization constant has this name.
+	UnknownConstant(String),
+	/// The value doesn't fit the constant's type.
+	ConstantType {
+		name: String,
+		expected: &'static str,
+	},
+	/// What the rust side binds doesn't match what the shader declares.
+	Mismatch(String),
+}
+
+impl fmt::Display for ShaderError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ShaderError::Compile(log) => write!(f, "failed to compile shader: {}", log),
+			ShaderError::Reflect(reason) => write!(f, "failed to reflect shader: {}", reason),
+			ShaderError::UnknownConstant(name) => {
+				write!(f, "no specialization constant named {}", name)
+			}
+			ShaderError::ConstantType { name, expected } => {
+				write!(f, "specialization constant {} needs a {}", name, expected)
+			}
+			ShaderError::Mismatch(reason) => write!(f, "shader mismatch: {}", reason),
+		}
+	}
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Compiles glsl to spir-v with `main` as the entry point. Only the built-in
+/// chunks can be included. `file_name` shows up in error messages.
+pub fn compile_glsl(
+	source: &str,
+	stage: ShaderStage,
+	file_name: &str,
+) -> Result<Vec<u32>, ShaderError> {
+	compile(
+		source,
+		stage,
+		file_name,
+		&ShaderLibrary::default(),
+		&Defines::new(),
+	)
+}
+
+/// Compiles glsl to spir-v with `main` as the entry point, resolving
+/// `#include`s from `library` and with `defines` set before the first line.
+/// `file_name` is where relative includes start from, eg. a shader at
+/// `water/surface.frag` can `#include "waves.glsl"` from `water/`.
+pub fn compile(
+	source: &str,
+	stage: ShaderStage,
+	file_name: &str,
+	library: &ShaderLibrary,
+	defines: &Defines,
+) -> Result<Vec<u32>, ShaderError> {
+	let mut options = shaderc::CompileOptions::new().unwrap();
+	options.set_include_callback(|requested, include_type, requesting, depth| {
+		library.resolve(requested, include_type, requesting, depth)
+	});
+	for (name, value) in defines.iter() {
+		options.add_macro_definition(name, value);
+	}
+
+	let mut compiler = shaderc::Compiler::new().unwrap();
+	let artifact = compiler
+		.compile_into_spirv(source, stage.kind(), file_name, "main", Some(&options))
+		.map_err(|error| ShaderError::Compile(error.to_string()))?;
+	Ok(artifact.as_binary().to_vec())
+}
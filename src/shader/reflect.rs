@@ -0,0 +1,799 @@
+// reads what a spir-v module declares straight from its words, so runtime
+// shaders can be configured by name without keeping a rust side copy of the
+// shader's interface in sync by hand.
+
+use super::{ShaderError, ShaderStage, SpecConstant, SpecValue};
+
+use vulkano::descriptor::descriptor::{
+	DescriptorBufferDesc, DescriptorDesc, DescriptorDescTy, DescriptorImageDesc,
+	DescriptorImageDescArray, DescriptorImageDescDimensions, ShaderStages,
+};
+use vulkano::format::Format;
+use vulkano::pipeline::shader::{GeometryShaderExecutionMode, GraphicsShaderType};
+
+use std::collections::{HashMap, HashSet};
+
+const MAGIC: u32 = 0x0723_0203;
+
+// opcodes
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// decorations
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_NON_WRITABLE: u32 = 24;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+// execution modes
+const MODE_INPUT_POINTS: u32 = 19;
+const MODE_INPUT_LINES: u32 = 20;
+const MODE_INPUT_LINES_ADJACENCY: u32 = 21;
+const MODE_TRIANGLES: u32 = 22;
+const MODE_INPUT_TRIANGLES_ADJACENCY: u32 = 23;
+const MODE_LOCAL_SIZE: u32 = 17;
+
+// image dims
+const DIM_1D: u32 = 0;
+const DIM_2D: u32 = 1;
+const DIM_3D: u32 = 2;
+const DIM_CUBE: u32 = 3;
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// What a spir-v module declares.
+#[derive(Debug, Clone, Default)]
+pub struct Reflection {
+	/// Stage of the `main` entry point.
+	pub stage: Option<ShaderStage>,
+	/// Work group size of a compute shader.
+	pub local_size: Option<[u32; 3]>,
+	/// Primitive a geometry shader takes.
+	pub geometry_input: Option<GeometryShaderExecutionMode>,
+	/// Sorted by set and binding.
+	pub descriptors: Vec<DescriptorBinding>,
+	/// Size of the push constant block, 0 without one.
+	pub push_constant_size: usize,
+	/// Vertex attributes for a vertex shader. Sorted by location.
+	pub inputs: Vec<InterfaceVariable>,
+	/// Color attachments for a fragment shader. Sorted by location.
+	pub outputs: Vec<InterfaceVariable>,
+	/// Sorted by constant id.
+	pub spec_constants: Vec<SpecConstant>,
+}
+
+/// A resource the shader reads or writes through a descriptor set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorBinding {
+	pub set: u32,
+	pub binding: u32,
+	/// Name of the variable, or of the block type for buffers declared
+	/// without an instance name. Empty when the spir-v was stripped.
+	pub name: String,
+	/// Accessed from the reflected stage only.
+	pub desc: DescriptorDesc,
+}
+
+/// A `layout(location = N) in` or `out` variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceVariable {
+	pub location: u32,
+	/// Locations taken up, more than one for matrices and arrays.
+	pub locations: u32,
+	/// Format of each location.
+	pub format: Format,
+	pub name: String,
+}
+
+impl Reflection {
+	pub fn parse(spirv: &[u32]) -> Result<Self, ShaderError> {
+		let module = Module::parse(spirv)?;
+		let mut reflection = Reflection::default();
+		let mut main = None;
+
+		for instruction in module.instructions.iter() {
+			let operands = instruction.operands;
+			if instruction.opcode == OP_ENTRY_POINT
+				&& operands.get(2..).map(string).as_deref() == Some("main")
+			{
+				reflection.stage = Some(match operand(operands, 0)? {
+					0 => ShaderStage::Vertex,
+					1 => ShaderStage::TessellationControl,
+					2 => ShaderStage::TessellationEvaluation,
+					3 => ShaderStage::Geometry,
+					4 => ShaderStage::Fragment,
+					5 => ShaderStage::Compute,
+					_ => return Err(ShaderError::Reflect("unsupported execution model")),
+				});
+				main = Some(operand(operands, 1)?);
+			}
+		}
+		let stages = match reflection.stage {
+			Some(stage) => stage.shader_stages(),
+			None => return Err(ShaderError::Reflect("no entry point named main")),
+		};
+
+		for instruction in module.instructions.iter() {
+			let operands = instruction.operands;
+			match instruction.opcode {
+				OP_EXECUTION_MODE if Some(operand(operands, 0)?) == main => {
+					match operand(operands, 1)? {
+						MODE_LOCAL_SIZE => {
+							reflection.local_size = Some([
+								operand(operands, 2)?,
+								operand(operands, 3)?,
+								operand(operands, 4)?,
+							]);
+						}
+						MODE_INPUT_POINTS => {
+							reflection.geometry_input = Some(GeometryShaderExecutionMode::Points)
+						}
+						MODE_INPUT_LINES => {
+							reflection.geometry_input = Some(GeometryShaderExecutionMode::Lines)
+						}
+						MODE_INPUT_LINES_ADJACENCY => {
+							reflection.geometry_input =
+								Some(GeometryShaderExecutionMode::LinesWithAdjacency)
+						}
+						// also used by tessellation, where it doesn't matter
+						MODE_TRIANGLES => {
+							reflection.geometry_input = Some(GeometryShaderExecutionMode::Triangles)
+						}
+						MODE_INPUT_TRIANGLES_ADJACENCY => {
+							reflection.geometry_input =
+								Some(GeometryShaderExecutionMode::TrianglesWithAdjacency)
+						}
+						_ => {}
+					}
+				}
+				OP_VARIABLE => {
+					let variable = operand(operands, 1)?;
+					let storage = operand(operands, 2)?;
+					let pointee = match module.types.get(&operand(operands, 0)?) {
+						Some(&Type::Pointer(pointee)) => pointee,
+						_ => return Err(ShaderError::Reflect("variable isn't a pointer")),
+					};
+					match storage {
+						STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+							reflection.descriptors.push(DescriptorBinding {
+								set: module
+									.decoration(variable, DECORATION_DESCRIPTOR_SET)
+									.unwrap_or(0),
+								binding: module
+									.decoration(variable, DECORATION_BINDING)
+									.ok_or(ShaderError::Reflect("descriptor without a binding"))?,
+								name: module.name(variable, pointee),
+								desc: module.descriptor(variable, pointee, storage, stages)?,
+							});
+						}
+						STORAGE_PUSH_CONSTANT => {
+							reflection.push_constant_size = module.size(pointee, None)?;
+						}
+						STORAGE_INPUT | STORAGE_OUTPUT => {
+							// builtins like gl_Position don't have a location
+							let location = match module.decoration(variable, DECORATION_LOCATION) {
+								Some(location) => location,
+								None => continue,
+							};
+							let (format, locations) = module.interface_format(pointee)?;
+							let variable = InterfaceVariable {
+								location,
+								locations,
+								format,
+								name: module.names.get(&variable).cloned().unwrap_or_default(),
+							};
+							if storage == STORAGE_INPUT {
+								reflection.inputs.push(variable);
+							} else {
+								reflection.outputs.push(variable);
+							}
+						}
+						_ => {}
+					}
+				}
+				_ => {}
+			}
+		}
+		reflection
+			.descriptors
+			.sort_by_key(|descriptor| (descriptor.set, descriptor.binding));
+		reflection.inputs.sort_by_key(|input| input.location);
+		reflection.outputs.sort_by_key(|output| output.location);
+
+		for instruction in module.instructions.iter() {
+			let operands = instruction.operands;
+			let (result, default) = match instruction.opcode {
+				OP_SPEC_CONSTANT_TRUE => (operand(operands, 1)?, SpecValue::Bool(true)),
+				OP_SPEC_CONSTANT_FALSE => (operand(operands, 1)?, SpecValue::Bool(false)),
+				OP_SPEC_CONSTANT => {
+					let bits = operand(operands, 2)?;
+					let value = match module.types.get(&operand(operands, 0)?) {
+						Some(Type::Scalar(Scalar::Int {
+							signed: true,
+							width: 32,
+						})) => SpecValue::Int(bits as i32),
+						Some(Type::Scalar(Scalar::Int {
+							signed: false,
+							width: 32,
+						})) => SpecValue::Uint(bits),
+						Some(Type::Scalar(Scalar::Float { width: 32 })) => {
+							SpecValue::Float(f32::from_bits(bits))
+						}
+						_ => {
+							return Err(ShaderError::Reflect(
+								"specialization constants have to be 32 bit scalars",
+							))
+						}
+					};
+					(operand(operands, 1)?, value)
+				}
+				_ => continue,
+			};
+
+			// constants without a SpecId are only used to build other constants
+			let constant_id = match module.decoration(result, DECORATION_SPEC_ID) {
+				Some(id) => id,
+				None => continue,
+			};
+			reflection.spec_constants.push(SpecConstant {
+				constant_id,
+				name: module.names.get(&result).cloned().unwrap_or_default(),
+				default,
+			});
+		}
+		reflection
+			.spec_constants
+			.sort_by_key(|constant| constant.constant_id);
+
+		Ok(reflection)
+	}
+
+	/// The stage as vulkano's graphics entry points want it, `None` for compute.
+	pub fn graphics_shader_type(&self) -> Option<GraphicsShaderType> {
+		match self.stage? {
+			ShaderStage::Vertex => Some(GraphicsShaderType::Vertex),
+			ShaderStage::TessellationControl => Some(GraphicsShaderType::TessellationControl),
+			ShaderStage::TessellationEvaluation => Some(GraphicsShaderType::TessellationEvaluation),
+			ShaderStage::Geometry => Some(GraphicsShaderType::Geometry(
+				self.geometry_input
+					.unwrap_or(GeometryShaderExecutionMode::Triangles),
+			)),
+			ShaderStage::Fragment => Some(GraphicsShaderType::Fragment),
+			ShaderStage::Compute => None,
+		}
+	}
+
+	pub fn descriptor(&self, set: u32, binding: u32) -> Option<&DescriptorBinding> {
+		self.descriptors
+			.iter()
+			.find(|descriptor| descriptor.set == set && descriptor.binding == binding)
+	}
+}
+
+pub(super) struct Instruction<'a> {
+	pub opcode: u32,
+	pub operands: &'a [u32],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Scalar {
+	Bool,
+	Int { signed: bool, width: u32 },
+	Float { width: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Type {
+	Scalar(Scalar),
+	Vector {
+		component: Scalar,
+		count: u32,
+	},
+	Matrix {
+		column: u32,
+		count: u32,
+	},
+	/// `length` is `None` for runtime arrays.
+	Array {
+		element: u32,
+		length: Option<u32>,
+	},
+	Struct(Vec<u32>),
+	Image(ImageType),
+	Sampler,
+	SampledImage(u32),
+	Pointer(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct ImageType {
+	dim: u32,
+	arrayed: bool,
+	multisampled: bool,
+	// 1 is sampled, 2 is storage
+	sampled: u32,
+	format: Option<Format>,
+}
+
+// the instructions and the debug info, decorations and types the rest of
+// reflection needs
+pub(super) struct Module<'a> {
+	pub instructions: Vec<Instruction<'a>>,
+	pub names: HashMap<u32, String>,
+	pub decorations: HashMap<(u32, u32), u32>,
+	pub flags: HashSet<(u32, u32)>,
+	pub member_decorations: HashMap<(u32, u32, u32), u32>,
+	pub member_flags: HashSet<(u32, u32, u32)>,
+	pub types: HashMap<u32, Type>,
+	pub constants: HashMap<u32, u32>,
+}
+
+impl<'a> Module<'a> {
+	pub fn parse(spirv: &'a [u32]) -> Result<Self, ShaderError> {
+		if spirv.len() < 5 || spirv[0] != MAGIC {
+			return Err(ShaderError::Reflect("not spir-v"));
+		}
+
+		let mut instructions = Vec::new();
+		let mut words = &spirv[5..];
+		while !words.is_empty() {
+			let count = (words[0] >> 16) as usize;
+			if count == 0 || count > words.len() {
+				return Err(ShaderError::Reflect("truncated instruction"));
+			}
+			instructions.push(Instruction {
+				opcode: words[0] & 0xffff,
+				operands: &words[1..count],
+			});
+			words = &words[count..];
+		}
+
+		let mut module = Module {
+			instructions,
+			names: HashMap::new(),
+			decorations: HashMap::new(),
+			flags: HashSet::new(),
+			member_decorations: HashMap::new(),
+			member_flags: HashSet::new(),
+			types: HashMap::new(),
+			constants: HashMap::new(),
+		};
+		for instruction in module.instructions.iter() {
+			let operands = instruction.operands;
+			match instruction.opcode {
+				OP_NAME => {
+					module
+						.names
+						.insert(operand(operands, 0)?, string(&operands[1..]));
+				}
+				OP_DECORATE => {
+					let key = (operand(operands, 0)?, operand(operands, 1)?);
+					if let Some(&value) = operands.get(2) {
+						module.decorations.insert(key, value);
+					} else {
+						module.flags.insert(key);
+					}
+				}
+				OP_MEMBER_DECORATE => {
+					let key = (
+						operand(operands, 0)?,
+						operand(operands, 1)?,
+						operand(operands, 2)?,
+					);
+					if let Some(&value) = operands.get(3) {
+						module.member_decorations.insert(key, value);
+					} else {
+						module.member_flags.insert(key);
+					}
+				}
+				OP_TYPE_BOOL => {
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Scalar(Scalar::Bool));
+				}
+				OP_TYPE_INT => {
+					let scalar = Scalar::Int {
+						width: operand(operands, 1)?,
+						signed: operand(operands, 2)? == 1,
+					};
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Scalar(scalar));
+				}
+				OP_TYPE_FLOAT => {
+					let scalar = Scalar::Float {
+						width: operand(operands, 1)?,
+					};
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Scalar(scalar));
+				}
+				OP_TYPE_VECTOR => {
+					let component = match module.types.get(&operand(operands, 1)?) {
+						Some(&Type::Scalar(scalar)) => scalar,
+						_ => return Err(ShaderError::Reflect("vector of a non scalar type")),
+					};
+					let count = operand(operands, 2)?;
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Vector { component, count });
+				}
+				OP_TYPE_MATRIX => {
+					let ty = Type::Matrix {
+						column: operand(operands, 1)?,
+						count: operand(operands, 2)?,
+					};
+					module.types.insert(operand(operands, 0)?, ty);
+				}
+				OP_TYPE_IMAGE => {
+					let image = ImageType {
+						dim: operand(operands, 2)?,
+						arrayed: operand(operands, 4)? == 1,
+						multisampled: operand(operands, 5)? == 1,
+						sampled: operand(operands, 6)?,
+						format: image_format(operand(operands, 7)?),
+					};
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Image(image));
+				}
+				OP_TYPE_SAMPLER => {
+					module.types.insert(operand(operands, 0)?, Type::Sampler);
+				}
+				OP_TYPE_SAMPLED_IMAGE => {
+					module.types.insert(
+						operand(operands, 0)?,
+						Type::SampledImage(operand(operands, 1)?),
+					);
+				}
+				OP_TYPE_ARRAY => {
+					// lengths are always declared before the array
+					let length = *module
+						.constants
+						.get(&operand(operands, 2)?)
+						.ok_or(ShaderError::Reflect("array length isn't a constant"))?;
+					let ty = Type::Array {
+						element: operand(operands, 1)?,
+						length: Some(length),
+					};
+					module.types.insert(operand(operands, 0)?, ty);
+				}
+				OP_TYPE_RUNTIME_ARRAY => {
+					let ty = Type::Array {
+						element: operand(operands, 1)?,
+						length: None,
+					};
+					module.types.insert(operand(operands, 0)?, ty);
+				}
+				OP_TYPE_STRUCT => {
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Struct(operands[1..].to_vec()));
+				}
+				OP_TYPE_POINTER => {
+					module
+						.types
+						.insert(operand(operands, 0)?, Type::Pointer(operand(operands, 2)?));
+				}
+				// arrays sized by a specialization constant use its default
+				OP_CONSTANT | OP_SPEC_CONSTANT => {
+					module
+						.constants
+						.insert(operand(operands, 1)?, operand(operands, 2)?);
+				}
+				_ => {}
+			}
+		}
+		Ok(module)
+	}
+
+	pub fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+		self.decorations.get(&(id, decoration)).copied()
+	}
+
+	fn has_flag(&self, id: u32, decoration: u32) -> bool {
+		self.flags.contains(&(id, decoration))
+	}
+
+	fn ty(&self, id: u32) -> Result<&Type, ShaderError> {
+		self.types
+			.get(&id)
+			.ok_or(ShaderError::Reflect("reference to an unknown type"))
+	}
+
+	// blocks declared without an instance name have an empty variable name
+	fn name(&self, variable: u32, pointee: u32) -> String {
+		let mut ty = pointee;
+		while let Some(&Type::Array { element, .. }) = self.types.get(&ty) {
+			ty = element;
+		}
+		match self.names.get(&variable) {
+			Some(name) if !name.is_empty() => name.clone(),
+			_ => self.names.get(&ty).cloned().unwrap_or_default(),
+		}
+	}
+
+	fn descriptor(
+		&self,
+		variable: u32,
+		pointee: u32,
+		storage: u32,
+		stages: ShaderStages,
+	) -> Result<DescriptorDesc, ShaderError> {
+		// arrays of descriptors
+		let mut ty = pointee;
+		let mut array_count = 1;
+		while let Type::Array { element, length } = *self.ty(ty)? {
+			array_count *= length.ok_or(ShaderError::Reflect(
+				"runtime sized descriptor arrays aren't supported",
+			))?;
+			ty = element;
+		}
+
+		let non_writable = self.has_flag(variable, DECORATION_NON_WRITABLE);
+		let (ty, readonly) = match *self.ty(ty)? {
+			Type::Struct(ref members) => {
+				let storage =
+					storage == STORAGE_STORAGE_BUFFER || self.has_flag(ty, DECORATION_BUFFER_BLOCK);
+				// glslang marks `readonly buffer` members rather than the variable
+				let readonly = !storage
+					|| non_writable || (0..members.len() as u32).all(|member| {
+					self.member_flags
+						.contains(&(ty, member, DECORATION_NON_WRITABLE))
+				});
+				let buffer = DescriptorBufferDesc {
+					dynamic: Some(false),
+					storage,
+				};
+				(DescriptorDescTy::Buffer(buffer), readonly)
+			}
+			Type::Sampler => (DescriptorDescTy::Sampler, true),
+			Type::SampledImage(image) => match *self.ty(image)? {
+				Type::Image(image) if image.dim == DIM_BUFFER => (
+					DescriptorDescTy::TexelBuffer {
+						storage: false,
+						format: image.format,
+					},
+					true,
+				),
+				Type::Image(image) => (
+					DescriptorDescTy::CombinedImageSampler(image_desc(image)?),
+					true,
+				),
+				_ => return Err(ShaderError::Reflect("sampled image of a non image type")),
+			},
+			Type::Image(image) if image.dim == DIM_SUBPASS_DATA => (
+				DescriptorDescTy::InputAttachment {
+					multisampled: image.multisampled,
+					array_layers: DescriptorImageDescArray::NonArrayed,
+				},
+				true,
+			),
+			Type::Image(image) if image.dim == DIM_BUFFER => (
+				DescriptorDescTy::TexelBuffer {
+					storage: image.sampled == 2,
+					format: image.format,
+				},
+				image.sampled != 2 || non_writable,
+			),
+			Type::Image(image) => (
+				DescriptorDescTy::Image(image_desc(image)?),
+				image.sampled != 2 || non_writable,
+			),
+			_ => return Err(ShaderError::Reflect("unsupported descriptor type")),
+		};
+
+		Ok(DescriptorDesc {
+			ty,
+			array_count,
+			stages,
+			readonly,
+		})
+	}
+
+	// size in bytes using the explicit layout decorations, `matrix_stride`
+	// comes from the struct member holding a matrix
+	pub fn size(&self, id: u32, matrix_stride: Option<u32>) -> Result<usize, ShaderError> {
+		Ok(match *self.ty(id)? {
+			Type::Scalar(scalar) => scalar_size(scalar),
+			Type::Vector { component, count } => scalar_size(component) * count as usize,
+			Type::Matrix { column, count } => match matrix_stride {
+				Some(stride) => stride as usize * count as usize,
+				None => self.size(column, None)? * count as usize,
+			},
+			Type::Array { element, length } => {
+				let stride = match self.decoration(id, DECORATION_ARRAY_STRIDE) {
+					Some(stride) => stride as usize,
+					None => self.size(element, matrix_stride)?,
+				};
+				// a runtime array adds nothing to the minimum size
+				stride * length.unwrap_or(0) as usize
+			}
+			Type::Struct(ref members) => {
+				let mut size = 0;
+				for (i, &member) in members.iter().enumerate() {
+					let key = |decoration| (id, i as u32, decoration);
+					let offset = self
+						.member_decorations
+						.get(&key(DECORATION_OFFSET))
+						.copied()
+						.unwrap_or(0) as usize;
+					let stride = self
+						.member_decorations
+						.get(&key(DECORATION_MATRIX_STRIDE))
+						.copied();
+					size = size.max(offset + self.size(member, stride)?);
+				}
+				size
+			}
+			_ => return Err(ShaderError::Reflect("type has no size")),
+		})
+	}
+
+	// format of each location and the number of locations
+	fn interface_format(&self, id: u32) -> Result<(Format, u32), ShaderError> {
+		Ok(match *self.ty(id)? {
+			Type::Scalar(scalar) => (vertex_format(scalar, 1)?, 1),
+			Type::Vector { component, count } => (vertex_format(component, count)?, 1),
+			Type::Matrix { column, count } => (self.interface_format(column)?.0, count),
+			Type::Array {
+				element,
+				length: Some(length),
+			} => {
+				let (format, locations) = self.interface_format(element)?;
+				(format, locations * length)
+			}
+			_ => return Err(ShaderError::Reflect("unsupported interface variable type")),
+		})
+	}
+}
+
+fn scalar_size(scalar: Scalar) -> usize {
+	match scalar {
+		// bools can't be in externally visible blocks, but they are 4 bytes
+		// wherever they end up
+		Scalar::Bool => 4,
+		Scalar::Int { width, .. } | Scalar::Float { width } => width as usize / 8,
+	}
+}
+
+fn vertex_format(scalar: Scalar, count: u32) -> Result<Format, ShaderError> {
+	let formats = match scalar {
+		Scalar::Float { width: 32 } => [
+			Format::R32Sfloat,
+			Format::R32G32Sfloat,
+			Format::R32G32B32Sfloat,
+			Format::R32G32B32A32Sfloat,
+		],
+		Scalar::Float { width: 64 } => [
+			Format::R64Sfloat,
+			Format::R64G64Sfloat,
+			Format::R64G64B64Sfloat,
+			Format::R64G64B64A64Sfloat,
+		],
+		Scalar::Int {
+			signed: true,
+			width: 32,
+		} => [
+			Format::R32Sint,
+			Format::R32G32Sint,
+			Format::R32G32B32Sint,
+			Format::R32G32B32A32Sint,
+		],
+		Scalar::Int {
+			signed: false,
+			width: 32,
+		} => [
+			Format::R32Uint,
+			Format::R32G32Uint,
+			Format::R32G32B32Uint,
+			Format::R32G32B32A32Uint,
+		],
+		_ => return Err(ShaderError::Reflect("unsupported interface variable type")),
+	};
+	formats
+		.get(count as usize - 1)
+		.copied()
+		.ok_or(ShaderError::Reflect("vector with more than 4 components"))
+}
+
+fn image_desc(image: ImageType) -> Result<DescriptorImageDesc, ShaderError> {
+	let dimensions = match image.dim {
+		DIM_1D => DescriptorImageDescDimensions::OneDimensional,
+		DIM_2D => DescriptorImageDescDimensions::TwoDimensional,
+		DIM_3D => DescriptorImageDescDimensions::ThreeDimensional,
+		DIM_CUBE => DescriptorImageDescDimensions::Cube,
+		_ => return Err(ShaderError::Reflect("unsupported image dimensions")),
+	};
+	Ok(DescriptorImageDesc {
+		sampled: image.sampled != 2,
+		dimensions,
+		format: image.format,
+		multisampled: image.multisampled,
+		array_layers: if image.arrayed {
+			DescriptorImageDescArray::Arrayed { max_layers: None }
+		} else {
+			DescriptorImageDescArray::NonArrayed
+		},
+	})
+}
+
+// the spir-v image formats storage images can be declared with
+fn image_format(format: u32) -> Option<Format> {
+	Some(match format {
+		1 => Format::R32G32B32A32Sfloat,
+		2 => Format::R16G16B16A16Sfloat,
+		3 => Format::R32Sfloat,
+		4 => Format::R8G8B8A8Unorm,
+		5 => Format::R8G8B8A8Snorm,
+		6 => Format::R32G32Sfloat,
+		7 => Format::R16G16Sfloat,
+		8 => Format::B10G11R11UfloatPack32,
+		9 => Format::R16Sfloat,
+		10 => Format::R16G16B16A16Unorm,
+		11 => Format::A2B10G10R10UnormPack32,
+		12 => Format::R16G16Unorm,
+		13 => Format::R8G8Unorm,
+		14 => Format::R16Unorm,
+		15 => Format::R8Unorm,
+		21 => Format::R32G32B32A32Sint,
+		22 => Format::R16G16B16A16Sint,
+		23 => Format::R8G8B8A8Sint,
+		24 => Format::R32Sint,
+		30 => Format::R32G32B32A32Uint,
+		31 => Format::R16G16B16A16Uint,
+		32 => Format::R8G8B8A8Uint,
+		33 => Format::R32Uint,
+		_ => return None,
+	})
+}
+
+pub(super) fn operand(operands: &[u32], index: usize) -> Result<u32, ShaderError> {
+	operands
+		.get(index)
+		.copied()
+		.ok_or(ShaderError::Reflect("instruction is missing an operand"))
+}
+
+// nul terminated utf-8 packed into little endian words
+pub(super) fn string(words: &[u32]) -> String {
+	let bytes: Vec<u8> = words
+		.iter()
+		.flat_map(|word| word.to_le_bytes())
+		.take_while(|&byte| byte != 0)
+		.collect();
+	String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,203 @@
+use super::{Reflection, ShaderError};
+
+use vulkano::pipeline::shader::{SpecializationConstants, SpecializationMapEntry};
+
+use std::sync::Arc;
+
+/// Highest constant id + 1 a runtime shader can use.
+pub const MAX_SPEC_CONSTANTS: usize = 32;
+
+/// Value of a specialization constant. Only 32 bit scalars are supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecValue {
+	Bool(bool),
+	Int(i32),
+	Uint(u32),
+	Float(f32),
+}
+
+impl SpecValue {
+	fn type_name(self) -> &'static str {
+		match self {
+			SpecValue::Bool(_) => "bool",
+			SpecValue::Int(_) => "int",
+			SpecValue::Uint(_) => "uint",
+			SpecValue::Float(_) => "float",
+		}
+	}
+
+	fn bits(self) -> u32 {
+		match self {
+			SpecValue::Bool(value) => value as u32,
+			SpecValue::Int(value) => value as u32,
+			SpecValue::Uint(value) => value,
+			SpecValue::Float(value) => value.to_bits(),
+		}
+	}
+
+	// `self` as the type of `like`, when it fits without losing anything
+	fn convert(self, like: SpecValue) -> Option<SpecValue> {
+		match (self, like) {
+			(SpecValue::Bool(value), SpecValue::Bool(_)) => Some(SpecValue::Bool(value)),
+			(SpecValue::Int(value), SpecValue::Int(_)) => Some(SpecValue::Int(value)),
+			(SpecValue::Int(value), SpecValue::Uint(_)) if value >= 0 => {
+				Some(SpecValue::Uint(value as u32))
+			}
+			(SpecValue::Uint(value), SpecValue::Uint(_)) => Some(SpecValue::Uint(value)),
+			(SpecValue::Uint(value), SpecValue::Int(_)) if value <= i32::MAX as u32 => {
+				Some(SpecValue::Int(value as i32))
+			}
+			(SpecValue::Float(value), SpecValue::Float(_)) => Some(SpecValue::Float(value)),
+			(SpecValue::Int(value), SpecValue::Float(_)) => Some(SpecValue::Float(value as f32)),
+			(SpecValue::Uint(value), SpecValue::Float(_)) => Some(SpecValue::Float(value as f32)),
+			_ => None,
+		}
+	}
+}
+
+impl From<bool> for SpecValue {
+	fn from(value: bool) -> Self {
+		SpecValue::Bool(value)
+	}
+}
+
+impl From<i32> for SpecValue {
+	fn from(value: i32) -> Self {
+		SpecValue::Int(value)
+	}
+}
+
+impl From<u32> for SpecValue {
+	fn from(value: u32) -> Self {
+		SpecValue::Uint(value)
+	}
+}
+
+impl From<f32> for SpecValue {
+	fn from(value: f32) -> Self {
+		SpecValue::Float(value)
+	}
+}
+
+/// A `layout(constant_id = N) const` declared by a shader.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecConstant {
+	pub constant_id: u32,
+	/// Empty when the spir-v was stripped of debug names.
+	pub name: String,
+	/// Value in the shader source, used unless it's overridden.
+	pub default: SpecValue,
+}
+
+/// Specialization constants of one shader stage, set by name and handed to
+/// the pipeline builder in place of the struct `vulkano_shaders::shader!`
+/// generates:
+///
+/// ```text
+/// let mut constants = SpecConstants::new(&Reflection::parse(&spirv)?)?;
+/// constants.set("cascade_count", 4)?;
+/// GraphicsPipeline::start().fragment_shader(entry_point, constants)
+/// ```
+///
+/// Every constant starts out with its default from the shader.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct SpecConstants {
+	// indexed by constant id, the pipeline reads these words directly
+	values: [u32; MAX_SPEC_CONSTANTS],
+	constants: Arc<[SpecConstant]>,
+	current: Vec<SpecValue>,
+}
+
+impl SpecConstants {
+	pub fn new(reflection: &Reflection) -> Result<Self, ShaderError> {
+		let mut values = [0; MAX_SPEC_CONSTANTS];
+		for constant in reflection.spec_constants.iter() {
+			let slot =
+				values
+					.get_mut(constant.constant_id as usize)
+					.ok_or(ShaderError::Reflect(
+						"specialization constant id is too large",
+					))?;
+			*slot = constant.default.bits();
+		}
+		Ok(SpecConstants {
+			values,
+			constants: reflection.spec_constants.clone().into(),
+			current: reflection
+				.spec_constants
+				.iter()
+				.map(|constant| constant.default)
+				.collect(),
+		})
+	}
+
+	/// Overrides a constant. Integers are converted to the constant's type
+	/// when they fit, floats and bools have to match.
+	pub fn set(&mut self, name: &str, value: impl Into<SpecValue>) -> Result<(), ShaderError> {
+		let index = self
+			.constants
+			.iter()
+			.position(|constant| constant.name == name)
+			.ok_or_else(|| ShaderError::UnknownConstant(name.to_string()))?;
+		let constant = &self.constants[index];
+		let value =
+			value
+				.into()
+				.convert(constant.default)
+				.ok_or_else(|| ShaderError::ConstantType {
+					name: name.to_string(),
+					expected: constant.default.type_name(),
+				})?;
+		self.values[constant.constant_id as usize] = value.bits();
+		self.current[index] = value;
+		Ok(())
+	}
+
+	pub fn get(&self, name: &str) -> Option<SpecValue> {
+		self.constants
+			.iter()
+			.position(|constant| constant.name == name)
+			.map(|index| self.current[index])
+	}
+
+	/// Puts every constant back to its default.
+	pub fn reset(&mut self) {
+		for (index, constant) in self.constants.iter().enumerate() {
+			self.values[constant.constant_id as usize] = constant.default.bits();
+			self.current[index] = constant.default;
+		}
+	}
+
+	/// The constants the shader declares.
+	pub fn constants(&self) -> &[SpecConstant] {
+		&self.constants
+	}
+}
+
+// every id gets an entry, ids the shader doesn't use are ignored by vulkan
+static ENTRIES: [SpecializationMapEntry; MAX_SPEC_CONSTANTS] = entries();
+
+const fn entries() -> [SpecializationMapEntry; MAX_SPEC_CONSTANTS] {
+	const EMPTY: SpecializationMapEntry = SpecializationMapEntry {
+		constant_id: 0,
+		offset: 0,
+		size: 4,
+	};
+	let mut entries = [EMPTY; MAX_SPEC_CONSTANTS];
+	let mut i = 0;
+	while i < MAX_SPEC_CONSTANTS {
+		entries[i].constant_id = i as u32;
+		entries[i].offset = i as u32 * 4;
+		i += 1;
+	}
+	entries
+}
+
+// safe because `values` is at the start of the repr(c) struct and holds a 4
+// byte word for every entry
+unsafe impl SpecializationConstants for SpecConstants {
+	fn descriptors() -> &'static [SpecializationMapEntry] {
+		&ENTRIES
+	}
+}
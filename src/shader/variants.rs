@@ -0,0 +1,327 @@
+// permutations of one shader program, picked by feature flags that turn into
+// `#define`s. each permutation is compiled and its pipeline built the first
+// time it's asked for, or ahead of time with `warm_up`.
+
+use super::{compile, Defines, Reflection, ShaderError, ShaderLayout, ShaderLibrary, ShaderStage};
+
+use vulkano::device::Device;
+use vulkano::pipeline::shader::ShaderModule;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Light counts shaders are compiled for. A permutation is compiled for the
+/// smallest bucket that fits, the shader skips the unused lights.
+pub const LIGHT_BUCKETS: [u32; 6] = [0, 1, 2, 4, 8, 16];
+
+/// A feature flag, defined as `SKINNED` etc. in the shader when it's on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+	Skinned,
+	AlphaTest,
+	/// Depth only rendering into a shadow map.
+	ShadowPass,
+	VertexColors,
+	Lightmapped,
+	NormalMap,
+	/// Screen-door fading by a per-instance fade, see `opal/dither.glsl`.
+	DitherFade,
+}
+
+impl Feature {
+	pub const ALL: [Feature; 7] = [
+		Feature::Skinned,
+		Feature::AlphaTest,
+		Feature::ShadowPass,
+		Feature::VertexColors,
+		Feature::Lightmapped,
+		Feature::NormalMap,
+		Feature::DitherFade,
+	];
+
+	/// Name of the define.
+	pub fn define(self) -> &'static str {
+		match self {
+			Feature::Skinned => "SKINNED",
+			Feature::AlphaTest => "ALPHA_TEST",
+			Feature::ShadowPass => "SHADOW_PASS",
+			Feature::VertexColors => "VERTEX_COLORS",
+			Feature::Lightmapped => "LIGHTMAPPED",
+			Feature::NormalMap => "NORMAL_MAP",
+			Feature::DitherFade => "DITHER_FADE",
+		}
+	}
+
+	fn bit(self) -> u32 {
+		1 << self as u32
+	}
+}
+
+/// Which permutation to use. Also defines `NUM_LIGHTS` as the light bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ShaderFeatures {
+	flags: u32,
+	lights: u32,
+}
+
+impl ShaderFeatures {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with(mut self, feature: Feature) -> Self {
+		self.set(feature, true);
+		self
+	}
+
+	pub fn set(&mut self, feature: Feature, on: bool) {
+		if on {
+			self.flags |= feature.bit();
+		} else {
+			self.flags &= !feature.bit();
+		}
+	}
+
+	pub fn contains(&self, feature: Feature) -> bool {
+		self.flags & feature.bit() != 0
+	}
+
+	/// Rounds `count` up to a light bucket, counts past the last bucket use
+	/// the last one.
+	pub fn with_lights(mut self, count: u32) -> Self {
+		self.lights = LIGHT_BUCKETS
+			.iter()
+			.copied()
+			.find(|&bucket| bucket >= count)
+			.unwrap_or(LIGHT_BUCKETS[LIGHT_BUCKETS.len() - 1]);
+		self
+	}
+
+	/// The light bucket, what `NUM_LIGHTS` is defined as.
+	pub fn lights(&self) -> u32 {
+		self.lights
+	}
+
+	pub fn features(&self) -> impl Iterator<Item = Feature> + '_ {
+		Feature::ALL
+			.iter()
+			.copied()
+			.filter(move |&feature| self.contains(feature))
+	}
+
+	pub fn defines(&self) -> Defines {
+		let mut defines = Defines::new();
+		for feature in self.features() {
+			defines.define(feature.define());
+		}
+		defines.set("NUM_LIGHTS", self.lights);
+		defines
+	}
+
+	/// Every combination of `features` being on or off, for each of the
+	/// light counts. Handy for warming up the set a scene is expected to use.
+	pub fn permutations(features: &[Feature], light_counts: &[u32]) -> Vec<ShaderFeatures> {
+		let mut permutations = Vec::new();
+		for &lights in light_counts {
+			for mask in 0..1u32 << features.len() {
+				let mut permutation = ShaderFeatures::new().with_lights(lights);
+				for (i, &feature) in features.iter().enumerate() {
+					permutation.set(feature, mask & (1 << i) != 0);
+				}
+				if !permutations.contains(&permutation) {
+					permutations.push(permutation);
+				}
+			}
+		}
+		permutations
+	}
+}
+
+/// `SKINNED ALPHA_TEST NUM_LIGHTS=4` style, for logs.
+impl fmt::Display for ShaderFeatures {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.defines().fmt(f)
+	}
+}
+
+/// Glsl for one stage of a program.
+#[derive(Debug, Clone)]
+pub struct ShaderSource {
+	pub stage: ShaderStage,
+	/// Where relative includes start from, see `compile`.
+	pub file_name: String,
+	pub source: String,
+}
+
+impl ShaderSource {
+	pub fn new(stage: ShaderStage, file_name: &str, source: &str) -> Self {
+		ShaderSource {
+			stage,
+			file_name: file_name.to_string(),
+			source: source.to_string(),
+		}
+	}
+}
+
+/// One compiled permutation of a program.
+pub struct Variant {
+	pub features: ShaderFeatures,
+	/// In the order the program's sources were given.
+	pub stages: Vec<VariantStage>,
+	/// Merged from every stage.
+	pub layout: ShaderLayout,
+}
+
+impl Variant {
+	pub fn stage(&self, stage: ShaderStage) -> Option<&VariantStage> {
+		self.stages.iter().find(|compiled| compiled.stage == stage)
+	}
+}
+
+pub struct VariantStage {
+	pub stage: ShaderStage,
+	pub module: Arc<ShaderModule>,
+	pub reflection: Reflection,
+}
+
+type BuildPipeline<P> = Box<dyn Fn(&Variant) -> P>;
+
+/// The permutations of a program and their pipelines.
+///
+/// `build` turns a compiled variant into a pipeline, usually by creating
+/// entry points from its modules with `ShaderInterface` and
+/// `ShaderLayout::pipeline_desc`. Pipelines are meant to be cheap to clone,
+/// like an `Arc`.
+pub struct ShaderVariants<P> {
+	device: Arc<Device>,
+	library: ShaderLibrary,
+	sources: Vec<ShaderSource>,
+	build: BuildPipeline<P>,
+	created: HashMap<ShaderFeatures, (Arc<Variant>, P)>,
+	pending: VecDeque<ShaderFeatures>,
+}
+
+impl<P: Clone> ShaderVariants<P> {
+	pub fn new<F>(
+		device: Arc<Device>,
+		library: ShaderLibrary,
+		sources: Vec<ShaderSource>,
+		build: F,
+	) -> Self
+	where
+		F: Fn(&Variant) -> P + 'static,
+	{
+		ShaderVariants {
+			device,
+			library,
+			sources,
+			build: Box::new(build),
+			created: HashMap::new(),
+			pending: VecDeque::new(),
+		}
+	}
+
+	/// The pipeline for `features`, compiled and built the first time it's
+	/// asked for.
+	pub fn get(&mut self, features: ShaderFeatures) -> Result<P, ShaderError> {
+		self.create(features).map(|(_, pipeline)| pipeline.clone())
+	}
+
+	/// The compiled shaders for `features`, compiled the first time it's
+	/// asked for.
+	pub fn variant(&mut self, features: ShaderFeatures) -> Result<Arc<Variant>, ShaderError> {
+		self.create(features).map(|(variant, _)| variant.clone())
+	}
+
+	pub fn is_created(&self, features: ShaderFeatures) -> bool {
+		self.created.contains_key(&features)
+	}
+
+	/// Number of permutations created so far.
+	pub fn len(&self) -> usize {
+		self.created.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.created.is_empty()
+	}
+
+	/// Creates every permutation in `features` now, eg. behind a loading
+	/// screen, so none of them stall a frame later on.
+	pub fn warm_up(
+		&mut self,
+		features: impl IntoIterator<Item = ShaderFeatures>,
+	) -> Result<(), ShaderError> {
+		for features in features {
+			self.create(features)?;
+		}
+		Ok(())
+	}
+
+	/// Queues permutations to be created a few at a time by
+	/// `process_warm_up`.
+	pub fn queue_warm_up(&mut self, features: impl IntoIterator<Item = ShaderFeatures>) {
+		self.pending.extend(features);
+	}
+
+	/// Creates queued permutations until `budget` runs out, at least one per
+	/// call. Returns how many are still queued.
+	pub fn process_warm_up(&mut self, budget: Duration) -> Result<usize, ShaderError> {
+		let start = Instant::now();
+		while let Some(features) = self.pending.pop_front() {
+			self.create(features)?;
+			if start.elapsed() >= budget {
+				break;
+			}
+		}
+		Ok(self.pending.len())
+	}
+
+	/// Drops every permutation, eg. after the sources or the render pass the
+	/// pipelines were built for changed.
+	pub fn clear(&mut self) {
+		self.created.clear();
+	}
+
+	/// Replaces the sources and drops every permutation.
+	pub fn set_sources(&mut self, sources: Vec<ShaderSource>) {
+		self.sources = sources;
+		self.clear();
+	}
+
+	fn create(&mut self, features: ShaderFeatures) -> Result<&(Arc<Variant>, P), ShaderError> {
+		if !self.created.contains_key(&features) {
+			let defines = features.defines();
+			let mut stages = Vec::with_capacity(self.sources.len());
+			for source in self.sources.iter() {
+				let spirv = compile(
+					&source.source,
+					source.stage,
+					&source.file_name,
+					&self.library,
+					&defines,
+				)?;
+				// safe because shaderc only outputs valid spir-v
+				let module =
+					unsafe { ShaderModule::from_words(self.device.clone(), &spirv) }.unwrap();
+				stages.push(VariantStage {
+					stage: source.stage,
+					module,
+					reflection: Reflection::parse(&spirv)?,
+				});
+			}
+			let reflections: Vec<_> = stages.iter().map(|stage| &stage.reflection).collect();
+			let variant = Variant {
+				features,
+				layout: ShaderLayout::new(&reflections)?,
+				stages,
+			};
+
+			let pipeline = (self.build)(&variant);
+			self.created.insert(features, (Arc::new(variant), pipeline));
+		}
+		Ok(&self.created[&features])
+	}
+}
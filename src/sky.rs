@@ -0,0 +1,553 @@
+// procedural daylight sky after preetham, shirley and smits' analytic model.
+// the sky is baked into a small cubemap when the sun moves, which is both
+// drawn behind the scene and used as the environment for image based light.
+
+use crate::compute::group_counts;
+use crate::math::{dot, mat4_inverse, mat4_mul, normalize, Mat4, Vec3};
+use crate::probes::{Sh9, PROBE_FORMAT};
+use crate::render2d::Texture;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract, GraphicsPipeline};
+use vulkano::sampler::Sampler;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// the cube is rendered again once the sun moved this far, in radians
+const SUN_EPSILON: f32 = 0.002;
+
+// angular radius of the sun disk drawn in the background
+const SUN_RADIUS: f32 = 0.0047;
+
+// bufferless draws need the concrete pipeline type
+type BackgroundPipeline = GraphicsPipeline<
+	BufferlessDefinition,
+	Box<dyn PipelineLayoutAbstract + Send + Sync>,
+	Arc<dyn RenderPassAbstract + Send + Sync>,
+>;
+
+mod bake {
+	vulkano_shaders::shader! {
+		ty: "compute",
+		src: "
+			#version 450
+
+			layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+			layout(set = 0, binding = 0, rgba16f) uniform writeonly image2DArray faces;
+
+			layout(push_constant) uniform PushConstants {
+				// perez coefficients a to e for luminance and both chromaticities
+				vec4 perez[5];
+				// zenith luminance and chromaticity, intensity
+				vec4 zenith;
+				// towards the sun
+				vec4 sun;
+				// color below the horizon, face size
+				vec4 ground;
+			} pc;
+
+			vec3 cube_direction(uint face, vec2 uv) {
+				switch (face) {
+					case 0u: return vec3(1.0, -uv.y, -uv.x);
+					case 1u: return vec3(-1.0, -uv.y, uv.x);
+					case 2u: return vec3(uv.x, 1.0, uv.y);
+					case 3u: return vec3(uv.x, -1.0, -uv.y);
+					case 4u: return vec3(uv.x, -uv.y, 1.0);
+					default: return vec3(-uv.x, -uv.y, -1.0);
+				}
+			}
+
+			vec3 perez(float cos_theta, float gamma, float cos_gamma) {
+				vec4 a = pc.perez[0], b = pc.perez[1], c = pc.perez[2], d = pc.perez[3], e = pc.perez[4];
+				return ((1.0 + a.xyz * exp(b.xyz / cos_theta))
+					* (1.0 + c.xyz * exp(d.xyz * gamma) + e.xyz * cos_gamma * cos_gamma));
+			}
+
+			vec3 sky(vec3 direction) {
+				float cos_theta = max(direction.y, 0.01);
+				float cos_gamma = clamp(dot(direction, pc.sun.xyz), -1.0, 1.0);
+				float cos_sun = max(pc.sun.y, 0.01);
+				vec3 yxy = pc.zenith.xyz * perez(cos_theta, acos(cos_gamma), cos_gamma)
+					/ perez(1.0, acos(cos_sun), cos_sun);
+
+				vec3 xyz = vec3(yxy.y / yxy.z * yxy.x, yxy.x, (1.0 - yxy.y - yxy.z) / yxy.z * yxy.x);
+				vec3 rgb = mat3(
+					3.2406, -0.9689, 0.0557,
+					-1.5372, 1.8758, -0.2040,
+					-0.4986, 0.0415, 1.0570
+				) * xyz;
+				return max(rgb, vec3(0.0)) * pc.zenith.w;
+			}
+
+			void main() {
+				uvec3 id = gl_GlobalInvocationID;
+				uint size = uint(pc.ground.w);
+				if (id.x >= size || id.y >= size) {
+					return;
+				}
+
+				vec2 uv = (vec2(id.xy) + 0.5) / float(size) * 2.0 - 1.0;
+				vec3 direction = normalize(cube_direction(id.z, uv));
+
+				// the ground takes a little of the horizon's light
+				vec3 horizon = sky(normalize(vec3(direction.x, 0.0, direction.z) + vec3(0.0, 1e-3, 0.0)));
+				vec3 color = direction.y >= 0.0
+					? sky(direction)
+					: mix(horizon, pc.ground.rgb * horizon.g, smoothstep(0.0, -0.1, direction.y));
+				imageStore(faces, ivec3(id), vec4(color, 1.0));
+			}
+		"
+	}
+}
+
+mod background_vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) out vec2 v_ndc;
+
+			void main() {
+				vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+				v_ndc = uv * 2.0 - 1.0;
+				// on the far plane, behind everything already drawn
+				gl_Position = vec4(v_ndc, 1.0, 1.0);
+			}
+		"
+	}
+}
+
+mod background_fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_ndc;
+
+			layout(set = 0, binding = 0) uniform samplerCube sky;
+
+			layout(push_constant) uniform PushConstants {
+				// inverse view projection without the camera position
+				mat4 inverse_view_projection;
+				// towards the sun, cosine of its angular radius
+				vec4 sun;
+				vec4 sun_color;
+			} pc;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				vec4 far = pc.inverse_view_projection * vec4(v_ndc, 1.0, 1.0);
+				vec3 direction = normalize(far.xyz / far.w);
+				vec3 color = texture(sky, direction).rgb;
+
+				float cos_gamma = dot(direction, pc.sun.xyz);
+				float edge = fwidth(cos_gamma);
+				color += pc.sun_color.rgb * smoothstep(pc.sun.w - edge, pc.sun.w + edge, cos_gamma);
+				f_color = vec4(color, 1.0);
+			}
+		"
+	}
+}
+
+/// Sun and atmosphere the sky is computed from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyParams {
+	/// Direction towards the sun, +y is up.
+	pub sun_direction: Vec3,
+	/// Haze in the air, 2 is a clear day and 10 a hazy one.
+	pub turbidity: f32,
+	/// Scale from the model's kilocandela per square meter to scene units.
+	pub intensity: f32,
+	/// Albedo of the ground below the horizon.
+	pub ground_color: Vec3,
+}
+
+impl Default for SkyParams {
+	fn default() -> Self {
+		SkyParams {
+			sun_direction: normalize([0.3, 0.6, 0.4]),
+			turbidity: 3.0,
+			intensity: 0.1,
+			ground_color: [0.3, 0.28, 0.25],
+		}
+	}
+}
+
+// coefficients of the model for one sun position, rows are luminance and
+// the two chromaticities
+struct Preetham {
+	perez: [Vec3; 5],
+	zenith: Vec3,
+	sun: Vec3,
+	intensity: f32,
+}
+
+impl Preetham {
+	fn new(params: &SkyParams) -> Self {
+		let t = params.turbidity.clamp(1.7, 10.0);
+		let sun = normalize(params.sun_direction);
+		// the model only holds up to the horizon, below it the sky fades out
+		let theta = sun[1].max(0.01).acos();
+		let night = smoothstep(-0.1, 0.05, sun[1]);
+
+		let perez = [
+			[
+				0.1787 * t - 1.4630,
+				-0.0193 * t - 0.2592,
+				-0.0167 * t - 0.2608,
+			],
+			[
+				-0.3554 * t + 0.4275,
+				-0.0665 * t + 0.0008,
+				-0.0950 * t + 0.0092,
+			],
+			[
+				-0.0227 * t + 5.3251,
+				-0.0004 * t + 0.2125,
+				-0.0079 * t + 0.2102,
+			],
+			[
+				0.1206 * t - 2.5771,
+				-0.0641 * t - 0.8989,
+				-0.0441 * t - 1.6537,
+			],
+			[
+				-0.0670 * t + 0.3703,
+				-0.0033 * t + 0.0452,
+				-0.0109 * t + 0.0529,
+			],
+		];
+
+		let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+		let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+		let (t2, th, th2, th3) = (t * t, theta, theta * theta, theta * theta * theta);
+		let x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+			+ t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+			+ (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+		let y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+			+ t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+			+ (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+		Preetham {
+			perez,
+			zenith: [luminance, x, y],
+			sun,
+			intensity: params.intensity * night,
+		}
+	}
+
+	fn perez(&self, cos_theta: f32, cos_gamma: f32) -> Vec3 {
+		let gamma = cos_gamma.clamp(-1.0, 1.0).acos();
+		let [a, b, c, d, e] = self.perez;
+		let mut out = [0.0; 3];
+		for i in 0..3 {
+			out[i] = (1.0 + a[i] * (b[i] / cos_theta).exp())
+				* (1.0 + c[i] * (d[i] * gamma).exp() + e[i] * cos_gamma * cos_gamma);
+		}
+		out
+	}
+
+	// linear rec. 709 radiance from `direction`, the cpu version of `sky` in
+	// the bake shader
+	fn radiance(&self, direction: Vec3) -> Vec3 {
+		let cos_theta = direction[1].max(0.01);
+		let cos_sun = self.sun[1].max(0.01);
+		let sky = self.perez(cos_theta, dot(direction, self.sun));
+		let reference = self.perez(1.0, cos_sun);
+		let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * sky[i] / reference[i]);
+
+		let xyz = [x / y * luminance, luminance, (1.0 - x - y) / y * luminance];
+		[
+			3.2406 * xyz[0] - 1.5372 * xyz[1] - 0.4986 * xyz[2],
+			-0.9689 * xyz[0] + 1.8758 * xyz[1] + 0.0415 * xyz[2],
+			0.0557 * xyz[0] - 0.2040 * xyz[1] + 1.0570 * xyz[2],
+		]
+		.map(|c| c.max(0.0) * self.intensity)
+	}
+}
+
+impl SkyParams {
+	/// Light arriving from `direction` above the horizon.
+	pub fn radiance(&self, direction: Vec3) -> Vec3 {
+		Preetham::new(self).radiance(normalize(direction))
+	}
+
+	/// Color of direct sunlight after passing through the atmosphere, reddens
+	/// towards the horizon. An approximation with the relative air mass of
+	/// kasten and young.
+	pub fn sun_color(&self) -> Vec3 {
+		let sun = normalize(self.sun_direction);
+		let elevation = sun[1].clamp(-1.0, 1.0).asin().to_degrees();
+		if elevation < -2.0 {
+			return [0.0; 3];
+		}
+		let zenith_angle = 90.0 - elevation.max(0.0);
+		let air_mass = 1.0
+			/ (zenith_angle.to_radians().cos() + 0.50572 * (96.07995 - zenith_angle).powf(-1.6364));
+		// rayleigh optical depth per channel plus haze growing with turbidity
+		let rayleigh = [0.058, 0.135, 0.331];
+		let haze = 0.02 * self.turbidity;
+		let fade = smoothstep(-2.0, 1.0, elevation);
+		rayleigh.map(|tau| (-(tau + haze) * air_mass).exp() * fade)
+	}
+}
+
+/// The sky baked into a cubemap, drawn behind the scene with
+/// `draw_background` and sampled as the environment.
+///
+/// Call `update` each frame, the cube is only baked again when the
+/// parameters changed, so moving the sun for a time of day is cheap.
+pub struct Sky {
+	bake: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+	background: Arc<BackgroundPipeline>,
+	sampler: Arc<Sampler>,
+	size: u32,
+	faces: Texture,
+	cubemap: Texture,
+	irradiance: Sh9,
+	baked: Option<SkyParams>,
+	pub params: SkyParams,
+	/// Brightness of the sun disk in the background relative to `sun_color`.
+	pub sun_intensity: f32,
+}
+
+impl Sky {
+	/// `subpass` is where the background is drawn and `size` the cube face
+	/// size, the sky is smooth so 64 to 128 is plenty.
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+		size: u32,
+	) -> Self {
+		let bake = bake::Shader::load(device.clone()).unwrap();
+		let bake = Arc::new(
+			ComputePipeline::new(device.clone(), &bake.main_entry_point(), &(), None).unwrap(),
+		) as Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+		let vs = background_vs::Shader::load(device.clone()).unwrap();
+		let fs = background_fs::Shader::load(device.clone()).unwrap();
+		let has_depth = subpass.has_depth();
+		let mut background = GraphicsPipeline::start()
+			.vertex_input(BufferlessDefinition)
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ());
+		if has_depth {
+			// only where nothing was drawn, the depth is still cleared to 1
+			background = background.depth_stencil(DepthStencil {
+				depth_write: false,
+				depth_compare: Compare::LessOrEqual,
+				..DepthStencil::simple_depth_test()
+			});
+		}
+		let background = Arc::new(
+			background
+				.render_pass(subpass)
+				.build(device.clone())
+				.unwrap(),
+		);
+
+		let image = StorageImage::with_usage(
+			device.clone(),
+			ImageDimensions::Dim2d {
+				width: size,
+				height: size,
+				array_layers: 6,
+			},
+			PROBE_FORMAT,
+			ImageUsage {
+				storage: true,
+				sampled: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags {
+				cube_compatible: true,
+				..ImageCreateFlags::none()
+			},
+			device.active_queue_families(),
+		)
+		.unwrap();
+
+		Sky {
+			bake,
+			background,
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device),
+			size,
+			faces: ImageView::with_type(image.clone(), ImageViewType::Dim2dArray).unwrap(),
+			cubemap: ImageView::with_type(image, ImageViewType::Cubemap).unwrap(),
+			irradiance: Sh9::ZERO,
+			baked: None,
+			params: SkyParams::default(),
+			sun_intensity: 200.0,
+		}
+	}
+
+	/// Bakes the cube again when `params` changed since the last bake and
+	/// returns whether it did, so anything derived from `cubemap` can be
+	/// refreshed. Must be recorded outside of a render pass.
+	pub fn update(&mut self, builder: &mut AutoCommandBufferBuilder) -> bool {
+		if let Some(baked) = &self.baked {
+			let moved = dot(
+				normalize(baked.sun_direction),
+				normalize(self.params.sun_direction),
+			)
+			.clamp(-1.0, 1.0)
+			.acos();
+			let same = SkyParams {
+				sun_direction: baked.sun_direction,
+				..self.params
+			} == *baked;
+			if same && moved < SUN_EPSILON {
+				return false;
+			}
+		}
+
+		let model = Preetham::new(&self.params);
+		let perez = model.perez.map(|c| [c[0], c[1], c[2], 0.0]);
+		let [r, g, b] = self.params.ground_color;
+		let push_constants = bake::ty::PushConstants {
+			perez,
+			zenith: [
+				model.zenith[0],
+				model.zenith[1],
+				model.zenith[2],
+				model.intensity,
+			],
+			sun: [model.sun[0], model.sun[1], model.sun[2], 0.0],
+			ground: [r, g, b, self.size as f32],
+		};
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.bake.descriptor_set_layout(0).unwrap().clone())
+				.add_image(self.faces.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+		builder
+			.dispatch(
+				group_counts([self.size, self.size, 6], [8, 8, 1]),
+				self.bake.clone(),
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+
+		self.irradiance = irradiance(&model, &self.params);
+		self.baked = Some(self.params);
+		true
+	}
+
+	/// The baked sky as a `samplerCube`, for reflections and the input of
+	/// prefiltering.
+	pub fn cubemap(&self) -> Texture {
+		self.cubemap.clone()
+	}
+
+	/// Diffuse light from the sky, computed on the cpu with each bake.
+	pub fn irradiance(&self) -> Sh9 {
+		self.irradiance
+	}
+
+	pub fn sun_color(&self) -> Vec3 {
+		self.params.sun_color()
+	}
+
+	/// Draws the sky wherever the depth is still cleared, call it after the
+	/// opaque geometry. Must be called inside the subpass the sky was
+	/// created for.
+	pub fn draw_background(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		view: Mat4,
+		projection: Mat4,
+	) {
+		// the sky is infinitely far away, only the camera's rotation matters
+		let mut rotation = view;
+		rotation[3] = [0.0, 0.0, 0.0, 1.0];
+		let inverse_view_projection =
+			mat4_inverse(mat4_mul(projection, rotation)).expect("camera can't be inverted");
+
+		let sun = normalize(self.params.sun_direction);
+		let sun_color = self.params.sun_color().map(|c| c * self.sun_intensity);
+		let push_constants = background_fs::ty::PushConstants {
+			inverse_view_projection,
+			sun: [sun[0], sun[1], sun[2], SUN_RADIUS.cos()],
+			sun_color: [sun_color[0], sun_color[1], sun_color[2], 0.0],
+		};
+		let set = Arc::new(
+			PersistentDescriptorSet::start(
+				self.background.descriptor_set_layout(0).unwrap().clone(),
+			)
+			.add_sampled_image(self.cubemap.clone(), self.sampler.clone())
+			.unwrap()
+			.build()
+			.unwrap(),
+		);
+		builder
+			.draw(
+				self.background.clone(),
+				dynamic_state,
+				BufferlessVertices {
+					vertices: 3,
+					instances: 1,
+				},
+				set,
+				push_constants,
+				vec![],
+			)
+			.unwrap();
+	}
+}
+
+// projects the sky onto spherical harmonics with a grid of directions
+fn irradiance(model: &Preetham, params: &SkyParams) -> Sh9 {
+	const STEPS: usize = 32;
+	let mut sh = Sh9::ZERO;
+	let ground_light = model.radiance([0.0, 1.0, 0.0]);
+	for i in 0..STEPS {
+		let theta = (i as f32 + 0.5) / STEPS as f32 * PI;
+		// solid angle of the cell shrinks towards the poles
+		let weight = theta.sin() * (PI / STEPS as f32) * (2.0 * PI / (STEPS * 2) as f32);
+		for j in 0..STEPS * 2 {
+			let phi = (j as f32 + 0.5) / (STEPS * 2) as f32 * 2.0 * PI;
+			let direction = [
+				theta.sin() * phi.cos(),
+				theta.cos(),
+				theta.sin() * phi.sin(),
+			];
+			let color = if direction[1] >= 0.0 {
+				model.radiance(direction)
+			} else {
+				let [r, g, b] = params.ground_color;
+				[
+					r * ground_light[1],
+					g * ground_light[1],
+					b * ground_light[1],
+				]
+			};
+			sh.add_sample(direction, color, weight);
+		}
+	}
+	sh
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+	let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+	t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,304 @@
+// tracks how images and buffers are used as they move between passes and
+// queues. nothing here records a pipeline barrier or layout transition:
+// inside a command buffer and along one chain of futures vulkano's builder
+// inserts those itself, and vulkano 0.22 has no way to record them by hand.
+// what it can't work out is that work on one queue has to wait for what
+// another queue wrote. `Submissions` orders command buffers on several queues
+// from the accesses they declare, waiting on semaphores only where a queue
+// hands a resource to another.
+
+use vulkano::buffer::BufferAccess;
+use vulkano::command_buffer::CommandBuffer;
+use vulkano::device::{Device, Queue};
+use vulkano::image::{ImageAccess, ImageLayout};
+use vulkano::sync::{self, GpuFuture};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How a pass uses a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+	/// Read through a sampler or as a sampled image.
+	Sampled,
+	StorageRead,
+	StorageWrite,
+	ColorAttachment,
+	DepthAttachment,
+	/// Depth test without writing, or sampling a depth buffer.
+	DepthRead,
+	TransferSrc,
+	TransferDst,
+	Uniform,
+	/// Vertex or index buffer.
+	Vertex,
+	/// Handed to the swapchain.
+	Present,
+}
+
+impl Access {
+	pub fn is_write(self) -> bool {
+		matches!(
+			self,
+			Access::StorageWrite
+				| Access::ColorAttachment
+				| Access::DepthAttachment
+				| Access::TransferDst
+		)
+	}
+
+	/// Layout an image has to be in for the access. Buffers ignore it.
+	pub fn layout(self) -> ImageLayout {
+		match self {
+			Access::Sampled => ImageLayout::ShaderReadOnlyOptimal,
+			Access::StorageRead | Access::StorageWrite => ImageLayout::General,
+			Access::ColorAttachment => ImageLayout::ColorAttachmentOptimal,
+			Access::DepthAttachment => ImageLayout::DepthStencilAttachmentOptimal,
+			Access::DepthRead => ImageLayout::DepthStencilReadOnlyOptimal,
+			Access::TransferSrc => ImageLayout::TransferSrcOptimal,
+			Access::TransferDst => ImageLayout::TransferDstOptimal,
+			Access::Uniform | Access::Vertex => ImageLayout::General,
+			Access::Present => ImageLayout::PresentSrc,
+		}
+	}
+}
+
+/// Identifies the memory behind an image or buffer, views of the same image
+/// share an id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceId {
+	Image(u64),
+	Buffer(u64, usize),
+}
+
+impl ResourceId {
+	pub fn image(image: &dyn ImageAccess) -> Self {
+		ResourceId::Image(image.conflict_key())
+	}
+
+	pub fn buffer(buffer: &dyn BufferAccess) -> Self {
+		let (key, offset) = buffer.conflict_key();
+		ResourceId::Buffer(key, offset)
+	}
+
+	fn is_image(self) -> bool {
+		matches!(self, ResourceId::Image(_))
+	}
+}
+
+/// Family and index of a queue.
+pub type QueueId = (u32, u32);
+
+pub fn queue_id(queue: &Queue) -> QueueId {
+	(queue.family().id(), queue.id_within_family())
+}
+
+/// A resource changing from one use to the next. The layouts are the ones
+/// vulkano transitions the image to, for logging and debugging, they aren't
+/// applied from here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+	pub resource: ResourceId,
+	/// `None` the first time the resource is seen.
+	pub from: Option<Access>,
+	pub to: Access,
+	pub old_layout: ImageLayout,
+	pub new_layout: ImageLayout,
+	pub from_queue: Option<QueueId>,
+	pub to_queue: QueueId,
+}
+
+impl Transition {
+	/// Whether the new use has to wait for the old one: anything after a
+	/// write, a write after a read, or a layout change.
+	pub fn is_hazard(&self) -> bool {
+		match self.from {
+			Some(from) => {
+				from.is_write() || self.to.is_write() || self.old_layout != self.new_layout
+			}
+			None => false,
+		}
+	}
+
+	pub fn crosses_queues(&self) -> bool {
+		self.from_queue.is_some_and(|from| from != self.to_queue)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+	access: Access,
+	queue: QueueId,
+}
+
+/// Last use of every resource it's told about. Only reports transitions,
+/// see `Submissions` for acting on the ones between queues.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceTracker {
+	states: HashMap<ResourceId, State>,
+}
+
+impl ResourceTracker {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Records `access` on `queue` and returns the transition from the last
+	/// use, `None` when nothing changed.
+	pub fn access(
+		&mut self,
+		resource: ResourceId,
+		access: Access,
+		queue: QueueId,
+	) -> Option<Transition> {
+		let previous = self.states.insert(resource, State { access, queue });
+		if let Some(previous) = previous {
+			if previous.access == access && previous.queue == queue && !access.is_write() {
+				return None;
+			}
+		}
+		let layout = |access: Access| {
+			if resource.is_image() {
+				access.layout()
+			} else {
+				ImageLayout::General
+			}
+		};
+		Some(Transition {
+			resource,
+			from: previous.map(|state| state.access),
+			to: access,
+			old_layout: previous.map_or(ImageLayout::Undefined, |state| layout(state.access)),
+			new_layout: layout(access),
+			from_queue: previous.map(|state| state.queue),
+			to_queue: queue,
+		})
+	}
+
+	pub fn last_access(&self, resource: ResourceId) -> Option<(Access, QueueId)> {
+		self.states
+			.get(&resource)
+			.map(|state| (state.access, state.queue))
+	}
+
+	/// Stops tracking a resource, eg. one that was dropped.
+	pub fn forget(&mut self, resource: ResourceId) {
+		self.states.remove(&resource);
+	}
+
+	pub fn clear(&mut self) {
+		self.states.clear();
+	}
+}
+
+/// Command buffers submitted to one or more queues in the order the passes
+/// need them.
+///
+/// Each queue keeps its own chain of futures. A submission joins the chains
+/// of the queues that last wrote, or last read what it writes, through a
+/// semaphore, so an async compute queue only waits where it shares
+/// resources with graphics:
+///
+/// ```text
+/// let mut submissions = Submissions::new(device.clone());
+/// // every frame
+/// submissions.submit(&compute_queue, culling, &[(ResourceId::buffer(&draws), Access::StorageWrite)]);
+/// submissions.submit(&graphics_queue, scene, &[(ResourceId::buffer(&draws), Access::Vertex)]);
+/// let future = submissions.finish();
+/// ```
+///
+/// Resources used by more than one queue family have to be created for
+/// all of them, eg. with `device.active_queue_families()`, the engine never
+/// transfers ownership between families.
+pub struct Submissions {
+	device: Arc<Device>,
+	tracker: ResourceTracker,
+	chains: Vec<(Arc<Queue>, Box<dyn GpuFuture>)>,
+	log: bool,
+}
+
+impl Submissions {
+	pub fn new(device: Arc<Device>) -> Self {
+		Submissions {
+			device,
+			tracker: ResourceTracker::new(),
+			chains: Vec::new(),
+			log: false,
+		}
+	}
+
+	/// Starts after `future`, eg. the swapchain acquire, on `queue`.
+	pub fn after(&mut self, queue: &Arc<Queue>, future: Box<dyn GpuFuture>) {
+		let chain = self.take_chain(queue);
+		self.chains
+			.push((queue.clone(), Box::new(chain.join(future))));
+	}
+
+	/// Logs every transition that needs synchronization, to see what the
+	/// passes wait on.
+	pub fn set_logging(&mut self, log: bool) {
+		self.log = log;
+	}
+
+	pub fn tracker(&self) -> &ResourceTracker {
+		&self.tracker
+	}
+
+	/// Executes `command_buffer` on `queue` once whatever it depends on in
+	/// `accesses` is done, and returns the transitions it caused. Only
+	/// transitions between queues add a semaphore wait, the barriers within
+	/// a queue are left to vulkano.
+	pub fn submit<C>(
+		&mut self,
+		queue: &Arc<Queue>,
+		command_buffer: C,
+		accesses: &[(ResourceId, Access)],
+	) -> Vec<Transition>
+	where
+		C: CommandBuffer + Send + Sync + 'static,
+	{
+		let id = queue_id(queue);
+		let transitions: Vec<Transition> = accesses
+			.iter()
+			.filter_map(|&(resource, access)| self.tracker.access(resource, access, id))
+			.collect();
+
+		let mut future = self.take_chain(queue);
+		for transition in transitions.iter().filter(|t| t.is_hazard()) {
+			if self.log {
+				log::info!("{:?}", transition);
+			}
+			if !transition.crosses_queues() {
+				continue;
+			}
+			let from = transition.from_queue.unwrap();
+			if let Some(index) = self.chains.iter().position(|(q, _)| queue_id(q) == from) {
+				let (_, other) = self.chains.swap_remove(index);
+				future = Box::new(future.join(other.then_signal_semaphore()));
+			}
+		}
+
+		let future = future.then_execute(queue.clone(), command_buffer).unwrap();
+		self.chains.push((queue.clone(), Box::new(future)));
+		transitions
+	}
+
+	/// Joins every queue's chain, flush or present the result. The tracker
+	/// keeps the last uses, so the next frame's submissions continue from
+	/// them.
+	pub fn finish(&mut self) -> Box<dyn GpuFuture> {
+		let mut future: Box<dyn GpuFuture> = Box::new(sync::now(self.device.clone()));
+		for (_, chain) in self.chains.drain(..) {
+			future = Box::new(future.join(chain.then_signal_semaphore()));
+		}
+		future
+	}
+
+	// removes the queue's chain, or starts a new one
+	fn take_chain(&mut self, queue: &Arc<Queue>) -> Box<dyn GpuFuture> {
+		match self.chains.iter().position(|(q, _)| q.is_same(queue)) {
+			Some(index) => self.chains.swap_remove(index).1,
+			None => Box::new(sync::now(self.device.clone())),
+		}
+	}
+}
@@ -0,0 +1,340 @@
+// landscapes displaced from a heightmap on the gpu. the terrain is a quadtree
+// of chunks that all share one grid mesh, far away chunks cover more ground
+// with the same number of vertices.
+
+pub mod render;
+
+use crate::math::{normalize, Aabb, Frustum, Vec2, Vec3};
+use crate::render2d::Texture;
+
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+/// Grid of heights from 0 to 1, x fastest then z.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+	width: u32,
+	depth: u32,
+	heights: Vec<f32>,
+}
+
+impl Heightmap {
+	pub fn new(width: u32, depth: u32, heights: Vec<f32>) -> Self {
+		assert!(
+			width >= 2 && depth >= 2,
+			"heightmap needs at least 2x2 samples"
+		);
+		assert_eq!(
+			heights.len(),
+			(width * depth) as usize,
+			"heights don't match the heightmap size"
+		);
+		Heightmap {
+			width,
+			depth,
+			heights,
+		}
+	}
+
+	/// Decodes a grayscale image, 16 bit pngs keep the full precision.
+	pub fn from_image(bytes: &[u8]) -> Result<Self, image::ImageError> {
+		let image = image::load_from_memory(bytes)?.into_luma16();
+		let (width, depth) = image.dimensions();
+		let heights = image
+			.into_raw()
+			.into_iter()
+			.map(|height| height as f32 / u16::MAX as f32)
+			.collect();
+		Ok(Heightmap::new(width, depth, heights))
+	}
+
+	/// Raw little endian 16 bit heights, the `.r16` / `.raw` files terrain
+	/// tools export.
+	pub fn from_r16(bytes: &[u8], width: u32, depth: u32) -> Self {
+		let heights = bytes
+			.chunks_exact(2)
+			.map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as f32 / u16::MAX as f32)
+			.collect();
+		Heightmap::new(width, depth, heights)
+	}
+
+	/// Number of samples along x and z.
+	pub fn dimensions(&self) -> [u32; 2] {
+		[self.width, self.depth]
+	}
+
+	pub fn heights(&self) -> &[f32] {
+		&self.heights
+	}
+
+	/// The sample at `x`, `z`, clamped to the edges.
+	pub fn get(&self, x: i64, z: i64) -> f32 {
+		let x = x.clamp(0, self.width as i64 - 1) as usize;
+		let z = z.clamp(0, self.depth as i64 - 1) as usize;
+		self.heights[z * self.width as usize + x]
+	}
+
+	/// Bilinearly filtered height between samples.
+	pub fn sample(&self, x: f32, z: f32) -> f32 {
+		let (x0, z0) = (x.floor(), z.floor());
+		let (tx, tz) = (x - x0, z - z0);
+		let (x0, z0) = (x0 as i64, z0 as i64);
+		let top = self.get(x0, z0) * (1.0 - tx) + self.get(x0 + 1, z0) * tx;
+		let bottom = self.get(x0, z0 + 1) * (1.0 - tx) + self.get(x0 + 1, z0 + 1) * tx;
+		top * (1.0 - tz) + bottom * tz
+	}
+
+	/// Per sample normals from central differences, `spacing` is the world
+	/// distance between samples along x and z and `height` the world height of 1.
+	pub fn normals(&self, spacing: Vec2, height: f32) -> Vec<Vec3> {
+		let mut normals = Vec::with_capacity(self.heights.len());
+		for z in 0..self.depth as i64 {
+			for x in 0..self.width as i64 {
+				let dx = (self.get(x + 1, z) - self.get(x - 1, z)) * height / (2.0 * spacing[0]);
+				let dz = (self.get(x, z + 1) - self.get(x, z - 1)) * height / (2.0 * spacing[1]);
+				normals.push(normalize([-dx, 1.0, -dz]));
+			}
+		}
+		normals
+	}
+
+	// lowest and highest sample in the inclusive range
+	fn range(&self, min: [i64; 2], max: [i64; 2]) -> (f32, f32) {
+		let mut range = (f32::MAX, f32::MIN);
+		for z in min[1]..=max[1] {
+			for x in min[0]..=max[0] {
+				let height = self.get(x, z);
+				range = (range.0.min(height), range.1.max(height));
+			}
+		}
+		range
+	}
+}
+
+/// How a heightmap is laid out in the world and split into chunks.
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainDesc {
+	/// World size along x and z, the terrain starts at the origin.
+	pub size: Vec2,
+	/// World height of a heightmap value of 1.
+	pub height: f32,
+	/// Quads along each edge of a chunk, the same at every level.
+	pub chunk_resolution: u32,
+	/// Quadtree depth, the smallest chunks are `size / 2^(levels - 1)`.
+	pub levels: u32,
+	/// Chunks closer to the eye than this many times their size split into four.
+	pub lod_distance: f32,
+	/// How far the skirts along chunk edges hang down, hides the cracks
+	/// between chunks of different levels.
+	pub skirt_depth: f32,
+	/// Times the splat layers repeat across the terrain.
+	pub layer_tiling: f32,
+}
+
+impl Default for TerrainDesc {
+	fn default() -> Self {
+		TerrainDesc {
+			size: [512.0, 512.0],
+			height: 64.0,
+			chunk_resolution: 32,
+			levels: 5,
+			lod_distance: 2.0,
+			skirt_depth: 4.0,
+			layer_tiling: 64.0,
+		}
+	}
+}
+
+/// A piece of the terrain to draw with the shared grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chunk {
+	/// Corner with the lowest x and z.
+	pub origin: Vec2,
+	pub size: Vec2,
+	/// 0 is the whole terrain.
+	pub level: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct QuadNode {
+	chunk: Chunk,
+	min_height: f32,
+	max_height: f32,
+	// index of the first of four children
+	children: Option<usize>,
+}
+
+/// A heightmap uploaded for `TerrainRenderer`, with the quadtree that picks
+/// chunks each frame.
+pub struct Terrain {
+	pub desc: TerrainDesc,
+	/// `R32Sfloat` heights from 0 to 1.
+	pub heights: Texture,
+	/// World space normals packed as `n * 0.5 + 0.5`.
+	pub normals: Texture,
+	heightmap: Heightmap,
+	nodes: Vec<QuadNode>,
+}
+
+impl Terrain {
+	/// Builds the quadtree and uploads the heights and generated normals.
+	/// The future has to be joined with the frame that first draws the terrain.
+	pub fn new(
+		queue: Arc<Queue>,
+		heightmap: Heightmap,
+		desc: TerrainDesc,
+	) -> (Self, impl GpuFuture) {
+		assert!(desc.levels >= 1, "terrain needs at least one level");
+		let [width, depth] = heightmap.dimensions();
+		let dimensions = ImageDimensions::Dim2d {
+			width,
+			height: depth,
+			array_layers: 1,
+		};
+
+		let (heights, height_future) = ImmutableImage::from_iter(
+			heightmap.heights.iter().cloned(),
+			dimensions,
+			MipmapsCount::One,
+			Format::R32Sfloat,
+			queue.clone(),
+		)
+		.unwrap();
+
+		let spacing = [
+			desc.size[0] / (width - 1) as f32,
+			desc.size[1] / (depth - 1) as f32,
+		];
+		let normals: Vec<u8> = heightmap
+			.normals(spacing, desc.height)
+			.into_iter()
+			.flat_map(|n| {
+				let pack = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+				[pack(n[0]), pack(n[1]), pack(n[2]), 255]
+			})
+			.collect();
+		let (normals, normal_future) = ImmutableImage::from_iter(
+			normals.into_iter(),
+			dimensions,
+			MipmapsCount::One,
+			Format::R8G8B8A8Unorm,
+			queue,
+		)
+		.unwrap();
+
+		let mut terrain = Terrain {
+			desc,
+			heights: ImageView::new(heights).unwrap(),
+			normals: ImageView::new(normals).unwrap(),
+			heightmap,
+			nodes: Vec::new(),
+		};
+		let root = terrain.node(Chunk {
+			origin: [0.0, 0.0],
+			size: desc.size,
+			level: 0,
+		});
+		terrain.nodes.push(root);
+		terrain.build_children(0);
+		(terrain, height_future.join(normal_future))
+	}
+
+	pub fn heightmap(&self) -> &Heightmap {
+		&self.heightmap
+	}
+
+	/// World height of the surface at `x`, `z`, clamped to the terrain's edges.
+	pub fn height_at(&self, x: f32, z: f32) -> f32 {
+		let [sample_x, sample_z] = self.to_samples([x, z]);
+		self.heightmap.sample(sample_x, sample_z) * self.desc.height
+	}
+
+	/// Chunks to draw this frame, finer close to `eye`. Chunks outside of
+	/// `frustum` are left out.
+	pub fn select(&self, eye: Vec3, frustum: &Frustum) -> Vec<Chunk> {
+		let mut chunks = Vec::new();
+		let mut stack = vec![0];
+		while let Some(index) = stack.pop() {
+			let node = &self.nodes[index];
+			let Chunk { origin, size, .. } = node.chunk;
+			let center = [
+				origin[0] + size[0] * 0.5,
+				(node.min_height + node.max_height) * 0.5 - self.desc.skirt_depth * 0.5,
+				origin[1] + size[1] * 0.5,
+			];
+			let extent = [
+				size[0] * 0.5,
+				(node.max_height - node.min_height + self.desc.skirt_depth) * 0.5,
+				size[1] * 0.5,
+			];
+			let bounds = Aabb::from_center_extent(center, extent);
+			if !frustum.intersects_aabb(&bounds) {
+				continue;
+			}
+
+			let split = bounds.distance(eye) < size[0].max(size[1]) * self.desc.lod_distance;
+			match node.children {
+				Some(first) if split => stack.extend(first..first + 4),
+				_ => chunks.push(node.chunk),
+			}
+		}
+		chunks
+	}
+
+	// world position to fractional heightmap samples
+	fn to_samples(&self, position: Vec2) -> Vec2 {
+		let [width, depth] = self.heightmap.dimensions();
+		[
+			position[0] / self.desc.size[0] * (width - 1) as f32,
+			position[1] / self.desc.size[1] * (depth - 1) as f32,
+		]
+	}
+
+	fn node(&self, chunk: Chunk) -> QuadNode {
+		let min = self.to_samples(chunk.origin);
+		let max = self.to_samples([
+			chunk.origin[0] + chunk.size[0],
+			chunk.origin[1] + chunk.size[1],
+		]);
+		let (min_height, max_height) = self.heightmap.range(
+			[min[0].floor() as i64, min[1].floor() as i64],
+			[max[0].ceil() as i64, max[1].ceil() as i64],
+		);
+		QuadNode {
+			chunk,
+			min_height: min_height * self.desc.height,
+			max_height: max_height * self.desc.height,
+			children: None,
+		}
+	}
+
+	// children of a node are next to each other so they can be found from the first
+	fn build_children(&mut self, index: usize) {
+		let chunk = self.nodes[index].chunk;
+		if chunk.level + 1 >= self.desc.levels {
+			return;
+		}
+		let first = self.nodes.len();
+		let half = [chunk.size[0] * 0.5, chunk.size[1] * 0.5];
+		for quadrant in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]] {
+			let child = self.node(Chunk {
+				origin: [
+					chunk.origin[0] + half[0] * quadrant[0],
+					chunk.origin[1] + half[1] * quadrant[1],
+				],
+				size: half,
+				level: chunk.level + 1,
+			});
+			self.nodes.push(child);
+		}
+		self.nodes[index].children = Some(first);
+		for child in first..first + 4 {
+			self.build_children(child);
+		}
+	}
+}
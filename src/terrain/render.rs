@@ -0,0 +1,309 @@
+use super::{Terrain, TerrainDesc};
+use crate::assets::Texture2DArray;
+use crate::math::{normalize, Frustum, Mat4, Vec3};
+use crate::render2d::Texture;
+
+use vulkano::buffer::{BufferUsage, ImmutableBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+/// Vertex of the grid every chunk is drawn with, `position` goes from 0 to 1
+/// across the chunk.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct GridVertex {
+	pub position: [f32; 2],
+	/// 1 for the bottom edge of a skirt.
+	pub skirt: f32,
+}
+vulkano::impl_vertex!(GridVertex, position, skirt);
+
+pub mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 position;
+			layout(location = 1) in float skirt;
+
+			layout(set = 0, binding = 0) uniform sampler2D heights;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				// origin and size of the chunk
+				vec4 chunk;
+				// size of the terrain, height scale, skirt depth
+				vec4 terrain;
+				// direction towards the sun, layer tiling
+				vec4 light;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+
+			void main() {
+				vec2 xz = push_constants.chunk.xy + position * push_constants.chunk.zw;
+				vec2 uv = clamp(xz / push_constants.terrain.xy, 0.0, 1.0);
+
+				// uv 0 and 1 land on the first and last sample, not the texel edges
+				vec2 samples = vec2(textureSize(heights, 0));
+				float height = textureLod(heights, (uv * (samples - 1.0) + 0.5) / samples, 0.0).r;
+				float y = height * push_constants.terrain.z - skirt * push_constants.terrain.w;
+
+				gl_Position = push_constants.view_projection * vec4(xz.x, y, xz.y, 1.0);
+				v_uv = uv;
+			}
+		"
+	}
+}
+
+pub mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+
+			layout(set = 0, binding = 1) uniform sampler2D normals;
+			layout(set = 0, binding = 2) uniform sampler2D splat;
+			layout(set = 0, binding = 3) uniform sampler2DArray layers;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 chunk;
+				vec4 terrain;
+				vec4 light;
+			} push_constants;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				vec2 samples = vec2(textureSize(normals, 0));
+				vec3 normal = texture(normals, (v_uv * (samples - 1.0) + 0.5) / samples).xyz;
+				normal = normalize(normal * 2.0 - 1.0);
+
+				// each splat channel weights one layer
+				vec4 weights = texture(splat, v_uv);
+				weights /= max(weights.r + weights.g + weights.b + weights.a, 0.0001);
+				vec2 tiled = v_uv * push_constants.light.w;
+				vec3 albedo = texture(layers, vec3(tiled, 0.0)).rgb * weights.r
+					+ texture(layers, vec3(tiled, 1.0)).rgb * weights.g
+					+ texture(layers, vec3(tiled, 2.0)).rgb * weights.b
+					+ texture(layers, vec3(tiled, 3.0)).rgb * weights.a;
+
+				float diffuse = max(dot(normal, push_constants.light.xyz), 0.0);
+				f_color = vec4(albedo * (0.2 + 0.8 * diffuse), 1.0);
+			}
+		"
+	}
+}
+
+/// How a terrain is textured: the red, green, blue and alpha channels of the
+/// splat map weight the first four layers, which tile `layer_tiling` times
+/// across the terrain.
+#[derive(Clone)]
+pub struct TerrainMaterial {
+	/// Linear rgba weights covering the whole terrain.
+	pub splat: Texture,
+	pub layers: Texture2DArray,
+}
+
+/// Draws a `Terrain` chunk by chunk with one shared grid.
+pub struct TerrainRenderer {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	vertices: Arc<ImmutableBuffer<[GridVertex]>>,
+	indices: Arc<ImmutableBuffer<[u32]>>,
+	clamp_sampler: Arc<Sampler>,
+	sampler: Arc<Sampler>,
+	resolution: u32,
+	/// Direction the sun light comes from.
+	pub sun: Vec3,
+}
+
+impl TerrainRenderer {
+	/// Builds the grid for `chunk_resolution` quads along each edge, terrains
+	/// drawn with it have to use the same resolution. The future has to be
+	/// joined with the frame that first draws.
+	pub fn new(
+		device: Arc<Device>,
+		queue: Arc<Queue>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+		chunk_resolution: u32,
+	) -> (Self, impl GpuFuture) {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input_single_buffer::<GridVertex>()
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ());
+		if has_depth {
+			pipeline = pipeline.depth_stencil_simple_depth();
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		let (vertices, indices) = grid(chunk_resolution);
+		let (vertices, vertex_future) = ImmutableBuffer::from_iter(
+			vertices.into_iter(),
+			BufferUsage::vertex_buffer(),
+			queue.clone(),
+		)
+		.unwrap();
+		let (indices, index_future) =
+			ImmutableBuffer::from_iter(indices.into_iter(), BufferUsage::index_buffer(), queue)
+				.unwrap();
+
+		let renderer = TerrainRenderer {
+			pipeline,
+			vertices,
+			indices,
+			clamp_sampler: Sampler::new(
+				device.clone(),
+				Filter::Linear,
+				Filter::Linear,
+				MipmapMode::Nearest,
+				SamplerAddressMode::ClampToEdge,
+				SamplerAddressMode::ClampToEdge,
+				SamplerAddressMode::ClampToEdge,
+				0.0,
+				1.0,
+				0.0,
+				0.0,
+			)
+			.unwrap(),
+			sampler: Sampler::simple_repeat_linear(device),
+			resolution: chunk_resolution,
+			sun: normalize([0.4, 1.0, 0.3]),
+		};
+		(renderer, vertex_future.join(index_future))
+	}
+
+	/// Records the chunks `terrain` picks for `eye`. Must be called inside the
+	/// subpass the renderer was created for.
+	pub fn draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		view_projection: Mat4,
+		eye: Vec3,
+		terrain: &Terrain,
+		material: &TerrainMaterial,
+	) {
+		let TerrainDesc {
+			size,
+			height,
+			chunk_resolution,
+			skirt_depth,
+			layer_tiling,
+			..
+		} = terrain.desc;
+		assert_eq!(
+			chunk_resolution, self.resolution,
+			"terrain chunk resolution doesn't match the renderer's grid"
+		);
+
+		let chunks = terrain.select(eye, &Frustum::from_view_projection(view_projection));
+		if chunks.is_empty() {
+			return;
+		}
+
+		let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+		let set = Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(terrain.heights.clone(), self.clamp_sampler.clone())
+				.unwrap()
+				.add_sampled_image(terrain.normals.clone(), self.clamp_sampler.clone())
+				.unwrap()
+				.add_sampled_image(material.splat.clone(), self.clamp_sampler.clone())
+				.unwrap()
+				.add_sampled_image(material.layers.texture.clone(), self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		);
+
+		let sun = normalize(self.sun);
+		for chunk in chunks {
+			let push_constants = vs::ty::PushConstants {
+				view_projection,
+				chunk: [
+					chunk.origin[0],
+					chunk.origin[1],
+					chunk.size[0],
+					chunk.size[1],
+				],
+				terrain: [size[0], size[1], height, skirt_depth],
+				light: [sun[0], sun[1], sun[2], layer_tiling],
+			};
+			builder
+				.draw_indexed(
+					self.pipeline.clone(),
+					dynamic_state,
+					vec![self.vertices.clone()],
+					self.indices.clone(),
+					set.clone(),
+					push_constants,
+					vec![],
+				)
+				.unwrap();
+		}
+	}
+}
+
+// a grid of `resolution` quads along each edge with a skirt hanging from
+// every edge
+fn grid(resolution: u32) -> (Vec<GridVertex>, Vec<u32>) {
+	assert!(resolution >= 1, "terrain grid needs at least one quad");
+	let n = resolution + 1;
+	let mut vertices = Vec::with_capacity((n * n + n * 4) as usize);
+	let mut indices = Vec::with_capacity((resolution * resolution * 6 + resolution * 24) as usize);
+	for z in 0..n {
+		for x in 0..n {
+			vertices.push(GridVertex {
+				position: [x as f32 / resolution as f32, z as f32 / resolution as f32],
+				skirt: 0.0,
+			});
+		}
+	}
+	for z in 0..resolution {
+		for x in 0..resolution {
+			let a = z * n + x;
+			let (b, c, d) = (a + 1, a + n, a + n + 1);
+			indices.extend_from_slice(&[a, c, b, b, c, d]);
+		}
+	}
+
+	// each edge walked as grid indices, the skirt is a copy of it pulled down
+	let edges: [Vec<u32>; 4] = [
+		(0..n).collect(),
+		(0..n).map(|x| resolution * n + x).collect(),
+		(0..n).map(|z| z * n).collect(),
+		(0..n).map(|z| z * n + resolution).collect(),
+	];
+	for edge in &edges {
+		let first = vertices.len() as u32;
+		for &top in edge {
+			vertices.push(GridVertex {
+				skirt: 1.0,
+				..vertices[top as usize]
+			});
+		}
+		for (i, pair) in edge.windows(2).enumerate() {
+			let (bottom, next_bottom) = (first + i as u32, first + i as u32 + 1);
+			indices.extend_from_slice(&[pair[0], bottom, pair[1], pair[1], bottom, next_bottom]);
+		}
+	}
+	(vertices, indices)
+}
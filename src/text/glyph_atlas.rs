@@ -0,0 +1,210 @@
+use crate::render2d::Texture;
+
+use rusttype::PositionedGlyph;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Font, glyph id and pixel size (as bits, so it can be hashed).
+pub type GlyphKey = (usize, u16, u32);
+
+/// Where a rasterized glyph lives in the atlas.
+#[derive(Debug, Clone, Copy)]
+pub struct CachedGlyph {
+	/// Normalized `[u, v, width, height]`.
+	pub region: [f32; 4],
+	/// Top left of the glyph's pixels relative to the pen position on the baseline.
+	pub offset: [f32; 2],
+	pub size: [f32; 2],
+}
+
+// empty space between glyphs so linear filtering doesn't pick up neighbours
+const PADDING: u32 = 1;
+
+/// Square texture that glyphs are rasterized into the first time they are drawn.
+///
+/// Pixels are white with the glyph coverage in alpha, so glyphs draw through
+/// the regular sprite pipeline and the sprite color tints them. Glyphs are
+/// packed in shelves and never evicted one by one, the whole atlas is
+/// cleared when it runs out of space.
+pub struct GlyphAtlas {
+	device: Arc<Device>,
+	size: u32,
+	pixels: Vec<u8>,
+	image: Arc<StorageImage<Format>>,
+	texture: Texture,
+	// glyphs without any pixels (eg. spaces) are cached as None
+	glyphs: HashMap<GlyphKey, Option<CachedGlyph>>,
+	cursor: [u32; 2],
+	shelf_height: u32,
+	// rows changed since the last upload
+	dirty: Option<[u32; 2]>,
+}
+
+/// The glyph doesn't fit in the space left in the atlas.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasFull;
+
+impl GlyphAtlas {
+	pub fn new(device: Arc<Device>, size: u32) -> Self {
+		let image = StorageImage::with_usage(
+			device.clone(),
+			ImageDimensions::Dim2d {
+				width: size,
+				height: size,
+				array_layers: 1,
+			},
+			Format::R8G8B8A8Unorm,
+			ImageUsage {
+				sampled: true,
+				transfer_destination: true,
+				..ImageUsage::none()
+			},
+			ImageCreateFlags::none(),
+			device.active_queue_families(),
+		)
+		.unwrap();
+
+		GlyphAtlas {
+			pixels: vec![0; (size * size * 4) as usize],
+			texture: ImageView::new(image.clone()).unwrap(),
+			image,
+			device,
+			size,
+			glyphs: HashMap::new(),
+			cursor: [0, 0],
+			shelf_height: 0,
+			// the image starts out undefined, so the first upload sends all of it
+			dirty: Some([0, size]),
+		}
+	}
+
+	pub fn texture(&self) -> &Texture {
+		&self.texture
+	}
+
+	/// Returns the cached glyph, rasterizing `glyph` into the atlas if it isn't there yet.
+	///
+	/// `glyph` has to be positioned at the origin.
+	pub fn get_or_insert(
+		&mut self,
+		key: GlyphKey,
+		glyph: &PositionedGlyph,
+	) -> Result<Option<CachedGlyph>, AtlasFull> {
+		if let Some(&cached) = self.glyphs.get(&key) {
+			return Ok(cached);
+		}
+
+		let bounds = match glyph.pixel_bounding_box() {
+			Some(bounds) => bounds,
+			None => {
+				self.glyphs.insert(key, None);
+				return Ok(None);
+			}
+		};
+		let width = bounds.width() as u32;
+		let height = bounds.height() as u32;
+		let position = self.allocate(width + PADDING, height + PADDING)?;
+
+		let size = self.size;
+		let pixels = &mut self.pixels;
+		glyph.draw(|x, y, coverage| {
+			let index = (((position[1] + y) * size + position[0] + x) * 4) as usize;
+			pixels[index..index + 4].copy_from_slice(&[
+				255,
+				255,
+				255,
+				(coverage.clamp(0.0, 1.0) * 255.0).round() as u8,
+			]);
+		});
+		self.mark_dirty(position[1], position[1] + height);
+
+		let atlas_size = self.size as f32;
+		let cached = CachedGlyph {
+			region: [
+				position[0] as f32 / atlas_size,
+				position[1] as f32 / atlas_size,
+				width as f32 / atlas_size,
+				height as f32 / atlas_size,
+			],
+			offset: [bounds.min.x as f32, bounds.min.y as f32],
+			size: [width as f32, height as f32],
+		};
+		self.glyphs.insert(key, Some(cached));
+		Ok(Some(cached))
+	}
+
+	/// Drops every cached glyph.
+	pub fn clear(&mut self) {
+		self.glyphs.clear();
+		self.pixels.iter_mut().for_each(|pixel| *pixel = 0);
+		self.cursor = [0, 0];
+		self.shelf_height = 0;
+		self.dirty = Some([0, self.size]);
+	}
+
+	/// Copies newly rasterized glyphs to the gpu. Has to be recorded outside of
+	/// a render pass, before the text is drawn.
+	pub fn upload(&mut self, builder: &mut AutoCommandBufferBuilder) {
+		let [start, end] = match self.dirty.take() {
+			Some(rows) => rows,
+			None => return,
+		};
+
+		let row_bytes = (self.size * 4) as usize;
+		let staging = CpuAccessibleBuffer::from_iter(
+			self.device.clone(),
+			BufferUsage::transfer_source(),
+			false,
+			self.pixels[start as usize * row_bytes..end as usize * row_bytes]
+				.iter()
+				.cloned(),
+		)
+		.unwrap();
+
+		builder
+			.copy_buffer_to_image_dimensions(
+				staging,
+				self.image.clone(),
+				[0, start, 0],
+				[self.size, end - start, 1],
+				0,
+				1,
+				0,
+			)
+			.unwrap();
+	}
+
+	fn allocate(&mut self, width: u32, height: u32) -> Result<[u32; 2], AtlasFull> {
+		if width > self.size {
+			return Err(AtlasFull);
+		}
+
+		// next shelf when the current one is full
+		if self.cursor[0] + width > self.size {
+			self.cursor = [0, self.cursor[1] + self.shelf_height];
+			self.shelf_height = 0;
+		}
+		if self.cursor[1] + height > self.size {
+			return Err(AtlasFull);
+		}
+
+		let position = self.cursor;
+		self.cursor[0] += width;
+		self.shelf_height = self.shelf_height.max(height);
+		Ok(position)
+	}
+
+	fn mark_dirty(&mut self, start: u32, end: u32) {
+		self.dirty = Some(match self.dirty {
+			Some([dirty_start, dirty_end]) => [dirty_start.min(start), dirty_end.max(end)],
+			None => [start, end],
+		});
+	}
+}
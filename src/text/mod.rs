@@ -0,0 +1,311 @@
+pub mod glyph_atlas;
+pub mod sdf;
+
+use crate::math::{Vec2, Vec4};
+use crate::render2d::{Sprite, SpriteBatch};
+use glyph_atlas::GlyphAtlas;
+
+use rusttype::{point, GlyphId, Scale};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+
+use std::sync::Arc;
+
+pub use rusttype::Font;
+
+/// Loads a ttf or otf font, `None` if the data isn't a font.
+pub fn load_font(data: Vec<u8>) -> Option<Font<'static>> {
+	Font::try_from_vec(data)
+}
+
+/// Horizontal alignment of every line relative to the section position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+	Left,
+	Center,
+	Right,
+}
+
+impl Align {
+	// where a line of `width` starts relative to the anchor
+	fn offset(self, width: f32) -> f32 {
+		match self {
+			Align::Left => 0.0,
+			Align::Center => -width / 2.0,
+			Align::Right => -width,
+		}
+	}
+}
+
+/// A run of text with a single style.
+#[derive(Debug, Clone, Copy)]
+pub struct TextSection<'a> {
+	pub text: &'a str,
+	/// Index returned by `TextRenderer::add_font`.
+	pub font: usize,
+	/// Top of the first line, on the left edge, center or right edge
+	/// depending on `align`.
+	pub position: Vec2,
+	/// Line height in pixels, from the lowest descender to the highest ascender.
+	pub size: f32,
+	pub color: Vec4,
+	pub align: Align,
+	/// Lines are wrapped between words to stay under this width. Words that
+	/// are wider on their own aren't broken up.
+	pub max_width: Option<f32>,
+	pub layer: i32,
+}
+
+impl<'a> TextSection<'a> {
+	pub fn new(text: &'a str, position: Vec2, size: f32) -> Self {
+		TextSection {
+			text,
+			font: 0,
+			position,
+			size,
+			color: [1.0; 4],
+			align: Align::Left,
+			max_width: None,
+			layer: 0,
+		}
+	}
+}
+
+/// Lays out text and draws it through a `SpriteBatch`, one sprite per glyph.
+///
+/// Every glyph comes from a single atlas texture, so all text on a layer ends
+/// up in one draw call. A frame looks like:
+///
+/// 1. `queue` the text sections into the sprite batch
+/// 2. `upload` the newly rasterized glyphs, outside of the render pass
+/// 3. flush the sprite batch inside the render pass
+///
+/// Sizes and positions are in the units of the sprite batch's camera. With a
+/// `Camera2d::scaled` camera those are logical pixels, set the same
+/// `scale_factor` here so glyphs are rasterized at the physical size and stay
+/// sharp on hidpi displays.
+pub struct TextRenderer {
+	fonts: Vec<Font<'static>>,
+	atlas: GlyphAtlas,
+	scale_factor: f32,
+}
+
+// a laid out glyph, x relative to the start of its line
+struct LineGlyph<G> {
+	id: G,
+	x: f32,
+	advance: f32,
+	whitespace: bool,
+}
+
+struct Line<G> {
+	glyphs: Vec<LineGlyph<G>>,
+}
+
+impl<G> Default for Line<G> {
+	fn default() -> Self {
+		Line { glyphs: Vec::new() }
+	}
+}
+
+impl<G> Line<G> {
+	// trailing whitespace doesn't count towards the width
+	fn width(&self) -> f32 {
+		self.glyphs
+			.iter()
+			.rev()
+			.find(|glyph| !glyph.whitespace)
+			.map_or(0.0, |glyph| glyph.x + glyph.advance)
+	}
+}
+
+impl TextRenderer {
+	/// `atlas_size` is the width and height of the glyph atlas in pixels, 1024
+	/// fits a few sizes of a latin font comfortably.
+	pub fn new(device: Arc<Device>, atlas_size: u32) -> Self {
+		TextRenderer {
+			fonts: Vec::new(),
+			atlas: GlyphAtlas::new(device, atlas_size),
+			scale_factor: 1.0,
+		}
+	}
+
+	pub fn scale_factor(&self) -> f32 {
+		self.scale_factor
+	}
+
+	/// Physical pixels per unit of text, eg. `WindowTarget::scale_factor`.
+	/// Glyphs of the old scale stay in the atlas until it fills up.
+	pub fn set_scale_factor(&mut self, scale_factor: f32) {
+		assert!(scale_factor > 0.0, "scale factor has to be positive");
+		self.scale_factor = scale_factor;
+	}
+
+	/// Returns the index to use for `TextSection::font`.
+	pub fn add_font(&mut self, font: Font<'static>) -> usize {
+		self.fonts.push(font);
+		self.fonts.len() - 1
+	}
+
+	pub fn font(&self, index: usize) -> &Font<'static> {
+		&self.fonts[index]
+	}
+
+	/// Size of the laid out text in pixels.
+	pub fn measure(&self, section: &TextSection) -> Vec2 {
+		let font = &self.fonts[section.font];
+		let lines = layout(font, section);
+		let line_height = line_height(font, section.size);
+
+		let width = lines.iter().map(Line::width).fold(0.0, f32::max);
+		[width, lines.len() as f32 * line_height]
+	}
+
+	/// Lays out the section and adds a sprite for every visible glyph to `batch`.
+	///
+	/// When the atlas fills up it is cleared and refilled, which can garble
+	/// text queued earlier in the same frame. Size the atlas so everything on
+	/// screen fits.
+	pub fn queue(&mut self, batch: &mut SpriteBatch, section: &TextSection) {
+		let font = &self.fonts[section.font];
+		// rasterized and snapped in physical pixels, laid out in logical ones
+		let factor = self.scale_factor;
+		let size = section.size * factor;
+		let scale = Scale::uniform(size);
+		let ascent = font.v_metrics(scale).ascent;
+		let line_height = line_height(font, size);
+
+		for (index, line) in layout(font, section).iter().enumerate() {
+			let offset = section.align.offset(line.width());
+			// snapping the pen keeps glyphs crisp, they are rasterized at whole pixels
+			let x = ((section.position[0] + offset) * factor).round();
+			let baseline =
+				(section.position[1] * factor + ascent + index as f32 * line_height).round();
+
+			for glyph in line.glyphs.iter().filter(|glyph| !glyph.whitespace) {
+				let key = (section.font, glyph.id.0, size.to_bits());
+				let positioned = font
+					.glyph(glyph.id)
+					.scaled(scale)
+					.positioned(point(0.0, 0.0));
+
+				let cached = match self.atlas.get_or_insert(key, &positioned) {
+					Ok(cached) => cached,
+					Err(_) => {
+						self.atlas.clear();
+						// still too big for an empty atlas, skip it
+						self.atlas.get_or_insert(key, &positioned).unwrap_or(None)
+					}
+				};
+
+				if let Some(cached) = cached {
+					batch.draw(Sprite {
+						region: cached.region,
+						origin: [0.0, 0.0],
+						color: section.color,
+						layer: section.layer,
+						..Sprite::new(
+							self.atlas.texture().clone(),
+							[
+								(x + (glyph.x * factor).round() + cached.offset[0]) / factor,
+								(baseline + cached.offset[1]) / factor,
+							],
+							[cached.size[0] / factor, cached.size[1] / factor],
+						)
+					});
+				}
+			}
+		}
+	}
+
+	/// Uploads glyphs rasterized since the last call. Record this outside of the
+	/// render pass, before flushing the batch the text was queued into.
+	pub fn upload(&mut self, builder: &mut AutoCommandBufferBuilder) {
+		self.atlas.upload(builder);
+	}
+}
+
+fn line_height(font: &Font, size: f32) -> f32 {
+	let metrics = font.v_metrics(Scale::uniform(size));
+	metrics.ascent - metrics.descent + metrics.line_gap
+}
+
+fn layout(font: &Font, section: &TextSection) -> Vec<Line<GlyphId>> {
+	let scale = Scale::uniform(section.size);
+	layout_lines(
+		section.text,
+		section.max_width,
+		|c| {
+			let glyph = font.glyph(c).scaled(scale);
+			(glyph.id(), glyph.h_metrics().advance_width)
+		},
+		|previous, next| font.pair_kerning(scale, previous, next),
+	)
+}
+
+// breaks the text into lines, wrapping at spaces when a line gets too wide.
+// `glyph` gives the id and advance of a character, `kerning` the adjustment
+// between two glyphs, both in the same units as `max_width`.
+fn layout_lines<G, A, K>(text: &str, max_width: Option<f32>, glyph: A, kerning: K) -> Vec<Line<G>>
+where
+	G: Copy,
+	A: Fn(char) -> (G, f32),
+	K: Fn(G, G) -> f32,
+{
+	let mut lines = Vec::new();
+
+	for paragraph in text.split('\n') {
+		let mut line = Line::default();
+		let mut pen = 0.0;
+		let mut previous = None;
+		// index of the first glyph after the last space on the line
+		let mut break_at = None;
+
+		for c in paragraph.chars() {
+			let (id, advance) = glyph(c);
+			if let Some(previous) = previous {
+				pen += kerning(previous, id);
+			}
+			previous = Some(id);
+
+			let whitespace = c.is_whitespace();
+
+			if !whitespace {
+				if let (Some(max_width), Some(index)) = (max_width, break_at) {
+					if pen + advance > max_width {
+						// move the word being typed onto a new line
+						let word = line.glyphs.split_off(index);
+						lines.push(std::mem::take(&mut line));
+						break_at = None;
+
+						let start = word.first().map_or(pen, |glyph| glyph.x);
+						line.glyphs = word
+							.into_iter()
+							.map(|glyph| LineGlyph {
+								x: glyph.x - start,
+								..glyph
+							})
+							.collect();
+						pen -= start;
+					}
+				}
+			}
+
+			line.glyphs.push(LineGlyph {
+				id,
+				x: pen,
+				advance,
+				whitespace,
+			});
+			pen += advance;
+
+			if whitespace {
+				break_at = Some(line.glyphs.len());
+			}
+		}
+
+		lines.push(line);
+	}
+
+	lines
+}
@@ -0,0 +1,714 @@
+// signed distance field text, for text that gets scaled, rotated or placed
+// in the 3d world where the bitmap glyphs of `TextRenderer` would blur.
+
+use super::{layout_lines, Font, Line, TextSection};
+use crate::math::{mat4_transform_point, Mat4, Vec2, Vec3, Vec4};
+use crate::render2d::atlas::AtlasError;
+use crate::render2d::{AtlasBuilder, Texture};
+
+use rusttype::{point, Scale};
+use vulkano::buffer::{BufferAccess, CpuBufferPool};
+use vulkano::command_buffer::{
+	AutoCommandBuffer, AutoCommandBufferBuilder, CommandBufferExecFuture, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+use vulkano::sync::NowFuture;
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// A glyph in an sdf font. Metrics are in units of the text size, so they
+/// get multiplied by `TextSection::size`.
+#[derive(Debug, Clone, Copy)]
+pub struct SdfGlyph {
+	/// Normalized `[u, v, width, height]` in the atlas.
+	pub region: [f32; 4],
+	/// `[left, top, right, bottom]` of the quad relative to the pen position
+	/// on the baseline, y down. Includes the distance field's margin.
+	pub bounds: [f32; 4],
+	pub advance: f32,
+}
+
+impl SdfGlyph {
+	/// Whitespace and other glyphs that only move the pen.
+	pub fn is_empty(&self) -> bool {
+		self.bounds[0] >= self.bounds[2] || self.bounds[1] >= self.bounds[3]
+	}
+}
+
+/// Glyph atlas of distance fields, either generated from a font or a pre-baked
+/// (multi-channel) sdf atlas.
+///
+/// A single channel field is stored in r, g and b, so both kinds go through
+/// the same median of three shader.
+pub struct SdfFont {
+	pub texture: Texture,
+	glyphs: HashMap<char, SdfGlyph>,
+	kerning: HashMap<(char, char), f32>,
+	/// Baseline of the first line below the top of the text.
+	pub ascent: f32,
+	pub line_height: f32,
+	/// Distance from fully outside to fully inside covered by the field, in atlas pixels.
+	pub distance_range: f32,
+	/// Atlas pixels per unit of text size.
+	pub unit_size: f32,
+}
+
+impl SdfFont {
+	/// Rasterizes `chars` at `glyph_size` pixels and turns them into distance fields.
+	///
+	/// `spread` is how far in pixels the field reaches outside of the glyph
+	/// edges, which also limits how wide outlines and shadow offsets can get.
+	/// Characters that aren't in `chars` are skipped when drawing.
+	pub fn generate<I>(
+		queue: Arc<Queue>,
+		font: &Font,
+		chars: I,
+		glyph_size: f32,
+		spread: u32,
+	) -> (
+		SdfFont,
+		CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+	)
+	where
+		I: IntoIterator<Item = char>,
+	{
+		let scale = Scale::uniform(glyph_size);
+		let mut chars: Vec<char> = chars.into_iter().collect();
+		chars.sort_unstable();
+		chars.dedup();
+
+		let mut builder = AtlasBuilder::new();
+		builder.srgb = false;
+
+		// (char, atlas index, bounds in pixels, advance in pixels)
+		let mut entries = Vec::with_capacity(chars.len());
+		for &c in &chars {
+			let glyph = font.glyph(c).scaled(scale);
+			let advance = glyph.h_metrics().advance_width;
+			let glyph = glyph.positioned(point(0.0, 0.0));
+
+			let bounds = match glyph.pixel_bounding_box() {
+				Some(bounds) => bounds,
+				None => {
+					entries.push((c, None, [0.0; 4], advance));
+					continue;
+				}
+			};
+
+			let width = (bounds.width() as u32 + spread * 2) as usize;
+			let height = (bounds.height() as u32 + spread * 2) as usize;
+			let mut coverage = vec![0.0; width * height];
+			glyph.draw(|x, y, value| {
+				coverage[(y + spread) as usize * width + (x + spread) as usize] = value;
+			});
+
+			let pixels = signed_distance_field(&coverage, width, height, spread as f32);
+			let index = builder.add(&c.to_string(), [width as u32, height as u32], pixels);
+
+			let spread = spread as f32;
+			entries.push((
+				c,
+				Some(index),
+				[
+					bounds.min.x as f32 - spread,
+					bounds.min.y as f32 - spread,
+					bounds.max.x as f32 + spread,
+					bounds.max.y as f32 + spread,
+				],
+				advance,
+			));
+		}
+
+		let (atlas, future) = builder.build(queue).unwrap();
+
+		let glyphs = entries
+			.into_iter()
+			.map(|(c, index, bounds, advance)| {
+				let glyph = SdfGlyph {
+					region: index.map_or([0.0; 4], |index| atlas.region(index)),
+					bounds: [
+						bounds[0] / glyph_size,
+						bounds[1] / glyph_size,
+						bounds[2] / glyph_size,
+						bounds[3] / glyph_size,
+					],
+					advance: advance / glyph_size,
+				};
+				(c, glyph)
+			})
+			.collect();
+
+		let mut kerning = HashMap::new();
+		for &first in &chars {
+			for &second in &chars {
+				let amount = font.pair_kerning(scale, first, second);
+				if amount != 0.0 {
+					kerning.insert((first, second), amount / glyph_size);
+				}
+			}
+		}
+
+		let metrics = font.v_metrics(scale);
+		let font = SdfFont {
+			texture: atlas.texture,
+			glyphs,
+			kerning,
+			ascent: metrics.ascent / glyph_size,
+			line_height: (metrics.ascent - metrics.descent + metrics.line_gap) / glyph_size,
+			distance_range: spread as f32 * 2.0,
+			unit_size: glyph_size,
+		};
+		(font, future)
+	}
+
+	/// Reads the json layout written by msdf-atlas-gen for an atlas that was
+	/// already loaded into `texture`.
+	///
+	/// The texture must not be srgb, distances have to be sampled as stored.
+	/// Works with sdf, psdf and msdf atlases.
+	pub fn from_msdf_json(texture: Texture, json: &str) -> Result<SdfFont, AtlasError> {
+		let layout: serde_json::Value = serde_json::from_str(json)?;
+		let number = |value: &serde_json::Value, what| {
+			value
+				.as_f64()
+				.map(|value| value as f32)
+				.ok_or(AtlasError::Format(what))
+		};
+
+		let atlas = &layout["atlas"];
+		let dimensions = texture.image().dimensions();
+		let (width, height) = (dimensions.width() as f32, dimensions.height() as f32);
+		let y_from_bottom = atlas["yOrigin"].as_str() != Some("top");
+		let em_size = number(&atlas["size"], "missing atlas size")?;
+
+		// em units, rescaled so the text size is the ascender to descender height like
+		// the rest of the text module
+		let metrics = &layout["metrics"];
+		let ascender = number(&metrics["ascender"], "missing ascender")?;
+		let descender = number(&metrics["descender"], "missing descender")?;
+		let unit = ascender - descender;
+
+		let mut glyphs = HashMap::new();
+		for glyph in layout["glyphs"]
+			.as_array()
+			.ok_or(AtlasError::Format("missing glyphs"))?
+		{
+			let c = glyph["unicode"]
+				.as_u64()
+				.and_then(|code| std::char::from_u32(code as u32))
+				.ok_or(AtlasError::Format("glyph without a unicode code point"))?;
+			let advance = number(&glyph["advance"], "glyph without an advance")? / unit;
+
+			let (plane, bounds) = (&glyph["planeBounds"], &glyph["atlasBounds"]);
+			let mut sdf_glyph = SdfGlyph {
+				region: [0.0; 4],
+				bounds: [0.0; 4],
+				advance,
+			};
+			if !plane.is_null() && !bounds.is_null() {
+				let side =
+					|rect: &serde_json::Value, key| number(&rect[key], "incomplete glyph bounds");
+				let (left, right) = (side(bounds, "left")?, side(bounds, "right")?);
+				let (top, bottom) = (side(bounds, "top")?, side(bounds, "bottom")?);
+				let v = if y_from_bottom { height - top } else { top };
+				sdf_glyph.region = [
+					left / width,
+					v / height,
+					(right - left) / width,
+					(top - bottom).abs() / height,
+				];
+				// plane bounds are y up from the baseline
+				sdf_glyph.bounds = [
+					side(plane, "left")? / unit,
+					-side(plane, "top")? / unit,
+					side(plane, "right")? / unit,
+					-side(plane, "bottom")? / unit,
+				];
+			}
+			glyphs.insert(c, sdf_glyph);
+		}
+
+		let mut kerning = HashMap::new();
+		if let Some(pairs) = layout["kerning"].as_array() {
+			for pair in pairs {
+				let code = |key| {
+					pair[key]
+						.as_u64()
+						.and_then(|code| std::char::from_u32(code as u32))
+						.ok_or(AtlasError::Format("kerning pair without code points"))
+				};
+				let amount = number(&pair["advance"], "kerning pair without an advance")?;
+				kerning.insert((code("unicode1")?, code("unicode2")?), amount / unit);
+			}
+		}
+
+		Ok(SdfFont {
+			texture,
+			glyphs,
+			kerning,
+			ascent: ascender / unit,
+			line_height: number(&metrics["lineHeight"], "missing line height")? / unit,
+			distance_range: number(&atlas["distanceRange"], "missing distance range")?,
+			unit_size: em_size * unit,
+		})
+	}
+
+	pub fn glyph(&self, c: char) -> Option<&SdfGlyph> {
+		self.glyphs.get(&c)
+	}
+
+	fn layout(&self, section: &TextSection) -> Vec<Line<char>> {
+		let size = section.size;
+		layout_lines(
+			section.text,
+			section.max_width,
+			|c| {
+				(
+					c,
+					self.glyphs
+						.get(&c)
+						.map_or(0.0, |glyph| glyph.advance * size),
+				)
+			},
+			|first, second| self.kerning.get(&(first, second)).copied().unwrap_or(0.0) * size,
+		)
+	}
+}
+
+/// Outline and drop shadow of sdf text. Widths and offsets are in units of
+/// the text size and can't reach further than the font's spread.
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+	pub outline_color: Vec4,
+	pub outline_width: f32,
+	pub shadow_color: Vec4,
+	/// Offset of the shadow, y down like the text layout.
+	pub shadow_offset: Vec2,
+	/// Blur of the shadow edge, 0 gives a hard shadow.
+	pub shadow_softness: f32,
+}
+
+impl Default for TextStyle {
+	fn default() -> Self {
+		TextStyle {
+			outline_color: [0.0, 0.0, 0.0, 1.0],
+			outline_width: 0.0,
+			shadow_color: [0.0, 0.0, 0.0, 0.0],
+			shadow_offset: [0.05, 0.05],
+			shadow_softness: 0.0,
+		}
+	}
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SdfVertex {
+	pub position: [f32; 3],
+	pub uv: [f32; 2],
+	pub color: [f32; 4],
+}
+vulkano::impl_vertex!(SdfVertex, position, uv, color);
+
+pub mod vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec2 uv;
+			layout(location = 2) in vec4 color;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 outline_color;
+				vec4 shadow_color;
+				// distance range in atlas pixels, outline width and shadow softness in
+				// normalized distance
+				vec4 params;
+				// shadow offset in uv
+				vec4 shadow_offset;
+			} push_constants;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+
+			void main() {
+				gl_Position = push_constants.view_projection * vec4(position, 1.0);
+				v_uv = uv;
+				v_color = color;
+			}
+		"
+	}
+}
+
+pub mod fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		src: "
+			#version 450
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+
+			layout(set = 0, binding = 0) uniform sampler2D glyphs;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_projection;
+				vec4 outline_color;
+				vec4 shadow_color;
+				vec4 params;
+				vec4 shadow_offset;
+			} push_constants;
+
+			layout(location = 0) out vec4 f_color;
+
+			float median(vec3 c) {
+				return max(min(c.r, c.g), min(max(c.r, c.g), c.b));
+			}
+
+			// signed distance from the edge, positive inside, 1 is the whole range
+			float distance_at(vec2 uv) {
+				return median(texture(glyphs, uv).rgb) - 0.5;
+			}
+
+			void main() {
+				// how many screen pixels one unit of distance covers, keeps the edge
+				// one pixel wide at any scale
+				vec2 unit_range = vec2(push_constants.params.x) / vec2(textureSize(glyphs, 0));
+				vec2 screen_size = vec2(1.0) / fwidth(v_uv);
+				float pixel_range = max(0.5 * dot(unit_range, screen_size), 1.0);
+
+				float outline_width = push_constants.params.y;
+				float distance = distance_at(v_uv);
+				float fill = clamp(pixel_range * distance + 0.5, 0.0, 1.0);
+				float outline = clamp(pixel_range * (distance + outline_width) + 0.5, 0.0, 1.0);
+
+				vec4 outline_color = push_constants.outline_color;
+				vec4 color = vec4(
+					mix(outline_color.rgb, v_color.rgb, fill),
+					outline * mix(outline_color.a, v_color.a, fill)
+				);
+
+				// shadow goes under the text and its outline
+				float softness = max(push_constants.params.z, 0.5 / pixel_range);
+				float shadow_distance = distance_at(v_uv - push_constants.shadow_offset.xy);
+				float shadow = smoothstep(-softness, softness, shadow_distance + outline_width)
+					* push_constants.shadow_color.a;
+
+				float alpha = color.a + shadow * (1.0 - color.a);
+				vec3 rgb = color.rgb * color.a + push_constants.shadow_color.rgb * shadow * (1.0 - color.a);
+				f_color = vec4(rgb / max(alpha, 1e-5), alpha);
+			}
+		"
+	}
+}
+
+// text queued with the same font and style
+struct Run {
+	font: usize,
+	style: TextStyle,
+	vertices: Range<usize>,
+}
+
+/// Draws sdf text, either as 2d ui text with a `Camera2d` projection or as
+/// labels in the 3d world.
+///
+/// When the subpass has a depth attachment the text is depth tested against
+/// the scene without writing depth, like particles.
+pub struct SdfTextRenderer {
+	pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+	vertices: CpuBufferPool<SdfVertex>,
+	sampler: Arc<Sampler>,
+	fonts: Vec<SdfFont>,
+	sets: Vec<Arc<dyn DescriptorSet + Send + Sync>>,
+	queued: Vec<SdfVertex>,
+	runs: Vec<Run>,
+}
+
+impl SdfTextRenderer {
+	pub fn new(
+		device: Arc<Device>,
+		subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+	) -> Self {
+		let vs = vs::Shader::load(device.clone()).unwrap();
+		let fs = fs::Shader::load(device.clone()).unwrap();
+
+		let has_depth = subpass.has_depth();
+		let mut pipeline = GraphicsPipeline::start()
+			.vertex_input_single_buffer::<SdfVertex>()
+			.vertex_shader(vs.main_entry_point(), ())
+			.triangle_list()
+			.viewports_dynamic_scissors_irrelevant(1)
+			.fragment_shader(fs.main_entry_point(), ())
+			.blend_alpha_blending();
+		if has_depth {
+			pipeline = pipeline.depth_stencil_simple_depth().depth_write(false);
+		}
+		let pipeline = Arc::new(pipeline.render_pass(subpass).build(device.clone()).unwrap())
+			as Arc<dyn GraphicsPipelineAbstract + Send + Sync>;
+
+		SdfTextRenderer {
+			pipeline,
+			vertices: CpuBufferPool::vertex_buffer(device.clone()),
+			sampler: Sampler::simple_repeat_linear_no_mipmap(device),
+			fonts: Vec::new(),
+			sets: Vec::new(),
+			queued: Vec::new(),
+			runs: Vec::new(),
+		}
+	}
+
+	/// Returns the index to use for `TextSection::font`.
+	pub fn add_font(&mut self, font: SdfFont) -> usize {
+		let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+		self.sets.push(Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_sampled_image(font.texture.clone(), self.sampler.clone())
+				.unwrap()
+				.build()
+				.unwrap(),
+		));
+		self.fonts.push(font);
+		self.fonts.len() - 1
+	}
+
+	pub fn font(&self, index: usize) -> &SdfFont {
+		&self.fonts[index]
+	}
+
+	/// Size of the laid out text in the units of `TextSection::size`.
+	pub fn measure(&self, section: &TextSection) -> Vec2 {
+		let font = &self.fonts[section.font];
+		let lines = font.layout(section);
+		let width = lines.iter().map(Line::width).fold(0.0, f32::max);
+		[width, lines.len() as f32 * font.line_height * section.size]
+	}
+
+	/// Lays out the section and queues it for the next `draw`.
+	///
+	/// The text is laid out in the xy plane with y down, like the 2d renderer,
+	/// then moved by `transform`. Use `IDENTITY` for ui text and
+	/// `label_transform` to place text in a y up world. `TextSection::layer`
+	/// isn't used, text is drawn in the order it was queued.
+	pub fn queue(&mut self, section: &TextSection, transform: Mat4, style: &TextStyle) {
+		let font = &self.fonts[section.font];
+		let size = section.size;
+		let start = self.queued.len();
+
+		for (index, line) in font.layout(section).iter().enumerate() {
+			let x = section.position[0] + section.align.offset(line.width());
+			let baseline =
+				section.position[1] + (font.ascent + index as f32 * font.line_height) * size;
+
+			for line_glyph in line.glyphs.iter().filter(|glyph| !glyph.whitespace) {
+				let glyph = match font.glyphs.get(&line_glyph.id) {
+					Some(glyph) if !glyph.is_empty() => glyph,
+					_ => continue,
+				};
+
+				let left = x + line_glyph.x + glyph.bounds[0] * size;
+				let right = x + line_glyph.x + glyph.bounds[2] * size;
+				let top = baseline + glyph.bounds[1] * size;
+				let bottom = baseline + glyph.bounds[3] * size;
+				let [u, v, w, h] = glyph.region;
+
+				let vertex = |x: f32, y: f32, uv: [f32; 2]| SdfVertex {
+					position: mat4_transform_point(transform, [x, y, 0.0]),
+					uv,
+					color: section.color,
+				};
+				let corners = [
+					vertex(left, top, [u, v]),
+					vertex(right, top, [u + w, v]),
+					vertex(left, bottom, [u, v + h]),
+					vertex(right, bottom, [u + w, v + h]),
+				];
+				self.queued.extend_from_slice(&[
+					corners[0], corners[1], corners[2], corners[2], corners[1], corners[3],
+				]);
+			}
+		}
+
+		if self.queued.len() > start {
+			self.runs.push(Run {
+				font: section.font,
+				style: *style,
+				vertices: start..self.queued.len(),
+			});
+		}
+	}
+
+	/// Records the queued text and clears the queue. Must be called inside the
+	/// subpass the renderer was created for.
+	///
+	/// Returns the number of draw calls recorded.
+	pub fn draw(
+		&mut self,
+		builder: &mut AutoCommandBufferBuilder,
+		dynamic_state: &DynamicState,
+		view_projection: Mat4,
+	) -> usize {
+		if self.runs.is_empty() {
+			return 0;
+		}
+
+		let vertices = Arc::new(self.vertices.chunk(self.queued.drain(..)).unwrap());
+
+		for run in &self.runs {
+			let font = &self.fonts[run.font];
+			let dimensions = font.texture.image().dimensions();
+			// style lengths from text units to normalized distance and uv
+			let to_distance = font.unit_size / font.distance_range;
+			let shadow_offset = [
+				run.style.shadow_offset[0] * font.unit_size / dimensions.width() as f32,
+				run.style.shadow_offset[1] * font.unit_size / dimensions.height() as f32,
+			];
+
+			let push_constants = vs::ty::PushConstants {
+				view_projection,
+				outline_color: run.style.outline_color,
+				shadow_color: run.style.shadow_color,
+				params: [
+					font.distance_range,
+					run.style.outline_width * to_distance,
+					run.style.shadow_softness * to_distance,
+					0.0,
+				],
+				shadow_offset: [shadow_offset[0], shadow_offset[1], 0.0, 0.0],
+			};
+
+			let range = vertices
+				.clone()
+				.into_buffer_slice()
+				.slice(run.vertices.clone())
+				.unwrap();
+
+			builder
+				.draw(
+					self.pipeline.clone(),
+					dynamic_state,
+					vec![Arc::new(range) as Arc<dyn BufferAccess + Send + Sync>],
+					self.sets[run.font].clone(),
+					push_constants,
+					vec![],
+				)
+				.unwrap();
+		}
+
+		let draws = self.runs.len();
+		self.runs.clear();
+		draws
+	}
+}
+
+/// Places text in a y up world: the text's x runs along `right` and its lines
+/// go down along `-up`, one unit of text size per world unit.
+///
+/// Pass the axes of a `BillboardCamera` to keep the label facing the camera.
+pub fn label_transform(position: Vec3, right: Vec3, up: Vec3) -> Mat4 {
+	let normal = crate::math::cross(right, up);
+	[
+		[right[0], right[1], right[2], 0.0],
+		[-up[0], -up[1], -up[2], 0.0],
+		[normal[0], normal[1], normal[2], 0.0],
+		[position[0], position[1], position[2], 1.0],
+	]
+}
+
+// distance field of a coverage bitmap, 0.5 on the edge and increasing inwards.
+// `spread` pixels on either side of the edge map to the full 0 to 1 range.
+fn signed_distance_field(coverage: &[f32], width: usize, height: usize, spread: f32) -> Vec<u8> {
+	let inside: Vec<bool> = coverage.iter().map(|&value| value >= 0.5).collect();
+	let to_inside = squared_distances(width, height, |i| inside[i]);
+	let to_outside = squared_distances(width, height, |i| !inside[i]);
+
+	let mut pixels = Vec::with_capacity(width * height * 4);
+	for i in 0..width * height {
+		// measured between pixel centers, the edge is half a pixel closer
+		let distance = if inside[i] {
+			to_outside[i].sqrt() - 0.5
+		} else {
+			0.5 - to_inside[i].sqrt()
+		};
+		let value = ((0.5 + distance / (spread * 2.0)).clamp(0.0, 1.0) * 255.0).round() as u8;
+		pixels.extend_from_slice(&[value, value, value, 255]);
+	}
+	pixels
+}
+
+// large enough to never be the minimum, small enough to not overflow in the parabola math
+const FAR: f32 = 1e20;
+
+// squared euclidean distance from every pixel to the nearest pixel where
+// `feature` is true, felzenszwalb and huttenlocher's separable transform
+fn squared_distances<F>(width: usize, height: usize, feature: F) -> Vec<f32>
+where
+	F: Fn(usize) -> bool,
+{
+	let mut grid: Vec<f32> = (0..width * height)
+		.map(|i| if feature(i) { 0.0 } else { FAR })
+		.collect();
+
+	let length = width.max(height);
+	let mut f = vec![0.0; length];
+	let mut d = vec![0.0; length];
+	let mut v = vec![0; length];
+	let mut z = vec![0.0; length + 1];
+
+	for x in 0..width {
+		for (y, value) in f.iter_mut().take(height).enumerate() {
+			*value = grid[y * width + x];
+		}
+		distance_1d(&f[..height], &mut d[..height], &mut v, &mut z);
+		for (y, &value) in d.iter().take(height).enumerate() {
+			grid[y * width + x] = value;
+		}
+	}
+
+	for row in grid.chunks_mut(width) {
+		f[..width].copy_from_slice(row);
+		distance_1d(&f[..width], &mut d[..width], &mut v, &mut z);
+		row.copy_from_slice(&d[..width]);
+	}
+
+	grid
+}
+
+// lower envelope of the parabolas rooted at every sample
+fn distance_1d(f: &[f32], d: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+	let intersection = |q: usize, p: usize| {
+		((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * q as f32 - 2.0 * p as f32)
+	};
+
+	let mut k = 0;
+	v[0] = 0;
+	z[0] = f32::NEG_INFINITY;
+	z[1] = f32::INFINITY;
+	for q in 1..f.len() {
+		let mut s = intersection(q, v[k]);
+		while s <= z[k] {
+			k -= 1;
+			s = intersection(q, v[k]);
+		}
+		k += 1;
+		v[k] = q;
+		z[k] = s;
+		z[k + 1] = f32::INFINITY;
+	}
+
+	k = 0;
+	for (q, distance) in d.iter_mut().enumerate() {
+		while z[k + 1] < q as f32 {
+			k += 1;
+		}
+		let offset = q as f32 - v[k] as f32;
+		*distance = offset * offset + f[v[k]];
+	}
+}
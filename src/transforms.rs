@@ -0,0 +1,142 @@
+// per object transforms packed into one dynamic uniform buffer each frame.
+// every object shares the same descriptor set and draws only change the
+// dynamic offset, instead of building a set per object.
+
+use crate::math::{mat4_inverse, Mat4, IDENTITY};
+
+use vulkano::buffer::{BufferSlice, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::{
+	DescriptorSet, PersistentDescriptorSet, UnsafeDescriptorSetLayout,
+};
+use vulkano::device::Device;
+
+use std::sync::Arc;
+
+/// Transforms of one object, matches this glsl block:
+///
+/// ```glsl
+/// layout(set = 1, binding = 0) uniform Object {
+///     mat4 model;
+///     mat4 normal_matrix;
+/// } object;
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectUniform {
+	pub model: Mat4,
+	/// Inverse transpose of `model`, stays correct under non-uniform scale.
+	pub normal_matrix: Mat4,
+}
+
+impl ObjectUniform {
+	pub fn new(model: Mat4) -> Self {
+		// a degenerate model has no inverse, its normals don't matter much
+		let inverse = mat4_inverse(model).unwrap_or(model);
+		let mut normal_matrix = IDENTITY;
+		for (c, column) in normal_matrix.iter_mut().enumerate().take(3) {
+			for (r, value) in column.iter_mut().enumerate().take(3) {
+				*value = inverse[r][c];
+			}
+		}
+		ObjectUniform {
+			model,
+			normal_matrix,
+		}
+	}
+}
+
+/// Collects the transforms of a frame's objects and uploads them together.
+///
+/// The binding has to be a dynamic uniform buffer in the pipeline layout.
+/// Pipelines built from `shader!` modules get one with
+/// `GraphicsPipeline::start()...with_auto_layout(device, &[(1, 0)])`,
+/// runtime shaders with `ShaderLayout::set_dynamic`. Each draw then passes
+/// the object's offset:
+///
+/// ```text
+/// let offset = transforms.push(model);
+/// ...
+/// let set = transforms.upload(&pipeline.descriptor_set_layout(1).unwrap());
+/// builder.draw(pipeline, &dynamic_state, vertices, (view_set, set), (), vec![offset])
+/// ```
+///
+/// Backed by a buffer pool so the previous frames' transforms can stay in use
+/// by the gpu while the next ones are written.
+pub struct ObjectUniforms {
+	pool: CpuBufferPool<ObjectUniform>,
+	// elements per object, more than one when the device's offset alignment
+	// is larger than an element
+	stride: usize,
+	objects: Vec<ObjectUniform>,
+}
+
+impl ObjectUniforms {
+	pub fn new(device: Arc<Device>) -> Self {
+		let alignment = device
+			.physical_device()
+			.limits()
+			.min_uniform_buffer_offset_alignment() as usize;
+		let size = std::mem::size_of::<ObjectUniform>();
+		ObjectUniforms {
+			pool: CpuBufferPool::uniform_buffer(device),
+			// the alignment is a power of two, so it's either a multiple of
+			// the element size or divides it
+			stride: alignment.div_ceil(size).max(1),
+			objects: Vec::new(),
+		}
+	}
+
+	/// Queues an object for the next `upload`, returns its dynamic offset.
+	pub fn push(&mut self, model: Mat4) -> u32 {
+		self.push_uniform(ObjectUniform::new(model))
+	}
+
+	/// `push` with a precomputed normal matrix.
+	pub fn push_uniform(&mut self, object: ObjectUniform) -> u32 {
+		let offset = self.offset(self.len());
+		self.objects.push(object);
+		self.objects
+			.extend(std::iter::repeat_n(object, self.stride - 1));
+		offset
+	}
+
+	/// Dynamic offset of the `index`th object pushed since the last upload.
+	pub fn offset(&self, index: usize) -> u32 {
+		(index * self.stride * std::mem::size_of::<ObjectUniform>()) as u32
+	}
+
+	/// Objects pushed since the last upload.
+	pub fn len(&self) -> usize {
+		self.objects.len() / self.stride
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.objects.is_empty()
+	}
+
+	/// Uploads every pushed object and returns a descriptor set with them at
+	/// binding 0, then starts over for the next frame.
+	///
+	/// `layout` comes from `pipeline.descriptor_set_layout(n)`.
+	pub fn upload(
+		&mut self,
+		layout: &Arc<UnsafeDescriptorSetLayout>,
+	) -> Arc<dyn DescriptorSet + Send + Sync> {
+		if self.objects.is_empty() {
+			self.push(IDENTITY);
+		}
+		let chunk = self.pool.chunk(self.objects.drain(..)).unwrap();
+
+		// the descriptor covers one object, the dynamic offset picks which
+		let slice = BufferSlice::from_typed_buffer_access(chunk)
+			.slice(0..1)
+			.unwrap();
+		Arc::new(
+			PersistentDescriptorSet::start(layout.clone())
+				.add_buffer(slice)
+				.unwrap()
+				.build()
+				.unwrap(),
+		)
+	}
+}
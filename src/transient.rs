@@ -0,0 +1,239 @@
+// intermediate render targets that only live for a few passes of a frame,
+// like ssao buffers or blur ping-pong images. targets whose passes don't
+// overlap share an image, and images are kept from frame to frame.
+//
+// vulkano gives every image its own memory, so targets alias by sharing an
+// image, which needs the same size, format and samples. chains of same sized
+// targets, the common case, still collapse into two or three images.
+
+use crate::render2d::Texture;
+
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageUsage};
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+// images no frame asked for in this many frames are dropped
+const UNUSED_FRAMES: u64 = 8;
+
+/// What a transient target has to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+	pub dimensions: [u32; 2],
+	pub format: Format,
+	pub samples: u32,
+	/// Also usable as a storage image, for compute passes.
+	pub storage: bool,
+}
+
+impl TransientDesc {
+	pub fn new(dimensions: [u32; 2], format: Format) -> Self {
+		TransientDesc {
+			dimensions,
+			format,
+			samples: 1,
+			storage: false,
+		}
+	}
+
+	pub fn with_samples(mut self, samples: u32) -> Self {
+		self.samples = samples;
+		self
+	}
+
+	pub fn with_storage(mut self) -> Self {
+		self.storage = true;
+		self
+	}
+
+	/// Rough size of the image in bytes.
+	pub fn bytes(&self) -> u64 {
+		let texel = self.format.size().unwrap_or(4) as u64;
+		texel * self.dimensions[0] as u64 * self.dimensions[1] as u64 * self.samples as u64
+	}
+}
+
+/// A target declared for the current frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientId(usize);
+
+/// Memory the last `allocate` asked for and used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransientStats {
+	pub targets: usize,
+	pub images: usize,
+	/// What the targets would take without sharing.
+	pub requested_bytes: u64,
+	pub allocated_bytes: u64,
+}
+
+struct Request {
+	desc: TransientDesc,
+	passes: RangeInclusive<usize>,
+}
+
+struct Slot {
+	image: Arc<AttachmentImage>,
+	last_frame: u64,
+	// last pass of the current frame using the image
+	busy_until: Option<usize>,
+}
+
+/// Transient targets of a frame, declared with the passes that use them.
+///
+/// Every frame: `begin_frame`, `declare` each target with the range of
+/// passes, in recording order, from the one writing it to the last one
+/// reading it, `allocate`, then record the passes with `image` or
+/// `texture`. Passes are numbered however the caller likes as long as the
+/// order matches the command buffer.
+///
+/// ```text
+/// targets.begin_frame();
+/// let ao = targets.declare(TransientDesc::new(size, Format::R8Unorm), 1..=2);
+/// let blurred = targets.declare(TransientDesc::new(size, Format::R8Unorm), 2..=3);
+/// let bloom = targets.declare(TransientDesc::new(size, Format::R8Unorm), 4..=5);
+/// targets.allocate();
+/// // `bloom` reuses the image `ao` had
+/// ```
+pub struct TransientTargets {
+	device: Arc<Device>,
+	slots: HashMap<TransientDesc, Vec<Slot>>,
+	requests: Vec<Request>,
+	assigned: Vec<Arc<AttachmentImage>>,
+	frame: u64,
+	stats: TransientStats,
+}
+
+impl TransientTargets {
+	pub fn new(device: Arc<Device>) -> Self {
+		TransientTargets {
+			device,
+			slots: HashMap::new(),
+			requests: Vec::new(),
+			assigned: Vec::new(),
+			frame: 0,
+			stats: TransientStats::default(),
+		}
+	}
+
+	/// Forgets last frame's targets and drops images that haven't been used
+	/// in a while.
+	pub fn begin_frame(&mut self) {
+		self.frame += 1;
+		self.requests.clear();
+		self.assigned.clear();
+		let frame = self.frame;
+		for slots in self.slots.values_mut() {
+			slots.retain(|slot| frame - slot.last_frame <= UNUSED_FRAMES);
+		}
+		self.slots.retain(|_, slots| !slots.is_empty());
+	}
+
+	/// Declares a target used by `passes`, both ends included.
+	pub fn declare(&mut self, desc: TransientDesc, passes: RangeInclusive<usize>) -> TransientId {
+		assert!(
+			self.assigned.is_empty(),
+			"targets have to be declared before allocate"
+		);
+		self.requests.push(Request { desc, passes });
+		TransientId(self.requests.len() - 1)
+	}
+
+	/// Assigns every declared target an image. Targets with the same
+	/// description whose passes don't overlap get the same image.
+	pub fn allocate(&mut self) {
+		for slots in self.slots.values_mut() {
+			for slot in slots.iter_mut() {
+				slot.busy_until = None;
+			}
+		}
+
+		let mut order: Vec<usize> = (0..self.requests.len()).collect();
+		order.sort_by_key(|&index| *self.requests[index].passes.start());
+
+		let mut assigned = vec![None; self.requests.len()];
+		let mut stats = TransientStats {
+			targets: self.requests.len(),
+			..TransientStats::default()
+		};
+		for index in order {
+			let request = &self.requests[index];
+			let (first, last) = (*request.passes.start(), *request.passes.end());
+			let slots = self.slots.entry(request.desc).or_default();
+			let free = slots
+				.iter()
+				.position(|slot| slot.busy_until.is_none_or(|until| until < first));
+			let slot = match free {
+				Some(slot) => slot,
+				None => {
+					slots.push(Slot {
+						image: create_image(self.device.clone(), &request.desc),
+						last_frame: self.frame,
+						busy_until: None,
+					});
+					slots.len() - 1
+				}
+			};
+
+			let slot = &mut slots[slot];
+			// counted the first time the image is handed out this frame
+			if slot.busy_until.is_none() {
+				stats.images += 1;
+				stats.allocated_bytes += request.desc.bytes();
+			}
+			slot.last_frame = self.frame;
+			slot.busy_until = Some(slot.busy_until.map_or(last, |until| until.max(last)));
+			stats.requested_bytes += request.desc.bytes();
+			assigned[index] = Some(slot.image.clone());
+		}
+
+		self.assigned = assigned.into_iter().map(Option::unwrap).collect();
+		self.stats = stats;
+	}
+
+	/// The image of a target, only valid after `allocate` for this frame.
+	pub fn image(&self, id: TransientId) -> Arc<AttachmentImage> {
+		self.assigned
+			.get(id.0)
+			.expect("transient targets weren't allocated")
+			.clone()
+	}
+
+	pub fn texture(&self, id: TransientId) -> Texture {
+		ImageView::new(self.image(id)).unwrap()
+	}
+
+	pub fn desc(&self, id: TransientId) -> TransientDesc {
+		self.requests[id.0].desc
+	}
+
+	pub fn stats(&self) -> TransientStats {
+		self.stats
+	}
+}
+
+fn create_image(device: Arc<Device>, desc: &TransientDesc) -> Arc<AttachmentImage> {
+	// attachment usage is added from the format
+	let usage = ImageUsage {
+		sampled: true,
+		storage: desc.storage,
+		transfer_source: true,
+		..ImageUsage::none()
+	};
+	if desc.samples > 1 {
+		AttachmentImage::multisampled_with_usage(
+			device,
+			desc.dimensions,
+			desc.samples,
+			desc.format,
+			usage,
+		)
+		.unwrap()
+	} else {
+		AttachmentImage::with_usage(device, desc.dimensions, desc.format, usage).unwrap()
+	}
+}
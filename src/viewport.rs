@@ -0,0 +1,168 @@
+// several cameras drawn into rectangles of the same image, for split-screen
+// and editor layouts with more than one view.
+
+use crate::math::{mat4_mul, Frustum, Mat4, Vec2, Vec3};
+use crate::scene::RenderLayers;
+
+use vulkano::buffer::cpu_pool::CpuBufferPoolSubbuffer;
+use vulkano::buffer::CpuBufferPool;
+use vulkano::command_buffer::DynamicState;
+use vulkano::device::Device;
+use vulkano::memory::pool::StdMemoryPool;
+use vulkano::pipeline::viewport::Viewport;
+
+use std::sync::Arc;
+
+/// Part of the target image, normalized so `[0, 0]` is the top left corner
+/// and `[1, 1]` the bottom right, so it survives window resizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportRect {
+	pub origin: Vec2,
+	pub size: Vec2,
+}
+
+impl ViewportRect {
+	pub const FULL: ViewportRect = ViewportRect {
+		origin: [0.0, 0.0],
+		size: [1.0, 1.0],
+	};
+
+	/// Pixel origin and size inside an image of `dimensions`, rounded so
+	/// neighbouring rects share edges without gaps.
+	pub fn pixels(&self, dimensions: [u32; 2]) -> ([u32; 2], [u32; 2]) {
+		let edge = |fraction: f32, size: u32| (fraction * size as f32).round() as u32;
+		let min = [
+			edge(self.origin[0], dimensions[0]),
+			edge(self.origin[1], dimensions[1]),
+		];
+		let max = [
+			edge(self.origin[0] + self.size[0], dimensions[0]),
+			edge(self.origin[1] + self.size[1], dimensions[1]),
+		];
+		(min, [max[0] - min[0], max[1] - min[1]])
+	}
+
+	pub fn contains(&self, point: Vec2) -> bool {
+		point[0] >= self.origin[0]
+			&& point[1] >= self.origin[1]
+			&& point[0] < self.origin[0] + self.size[0]
+			&& point[1] < self.origin[1] + self.size[1]
+	}
+}
+
+/// Standard split-screen layouts for one to four players: side by side for
+/// two, and a 2x2 grid for three or four with the fourth quarter left empty
+/// for three players.
+pub fn split_screen(players: usize) -> Vec<ViewportRect> {
+	let rect = |x, y, width, height| ViewportRect {
+		origin: [x, y],
+		size: [width, height],
+	};
+	match players {
+		0 => Vec::new(),
+		1 => vec![ViewportRect::FULL],
+		2 => vec![rect(0.0, 0.0, 0.5, 1.0), rect(0.5, 0.0, 0.5, 1.0)],
+		3 | 4 => [
+			rect(0.0, 0.0, 0.5, 0.5),
+			rect(0.5, 0.0, 0.5, 0.5),
+			rect(0.0, 0.5, 0.5, 0.5),
+			rect(0.5, 0.5, 0.5, 0.5),
+		][..players]
+			.to_vec(),
+		_ => panic!("split screen supports up to 4 players, got {}", players),
+	}
+}
+
+/// One camera and the part of the image it draws into.
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+	pub rect: ViewportRect,
+	pub view: Mat4,
+	/// Build this with the view's own `aspect_ratio`, not the window's.
+	pub projection: Mat4,
+	/// World space camera position.
+	pub position: Vec3,
+	/// Layers drawn into the view, usually the camera's `culling_mask`.
+	pub layers: RenderLayers,
+}
+
+impl View {
+	pub fn view_projection(&self) -> Mat4 {
+		mat4_mul(self.projection, self.view)
+	}
+
+	pub fn aspect_ratio(&self, dimensions: [u32; 2]) -> f32 {
+		let (_, size) = self.rect.pixels(dimensions);
+		size[0] as f32 / size[1].max(1) as f32
+	}
+
+	/// Whether an object on `layers` is drawn into the view.
+	pub fn sees(&self, layers: RenderLayers) -> bool {
+		layers.intersects(self.layers)
+	}
+
+	/// Frustum to cull against, each view culls on its own.
+	pub fn frustum(&self) -> Frustum {
+		Frustum::from_view_projection(self.view_projection())
+	}
+
+	/// Viewport limited to the view's rect, pass this to every draw of the
+	/// view. Works with the `viewports_dynamic_scissors_irrelevant` pipelines
+	/// used everywhere else, clipping keeps geometry inside the rect.
+	pub fn dynamic_state(&self, dimensions: [u32; 2]) -> DynamicState {
+		let (origin, size) = self.rect.pixels(dimensions);
+		DynamicState {
+			viewports: Some(vec![Viewport {
+				origin: [origin[0] as f32, origin[1] as f32],
+				dimensions: [size[0] as f32, size[1] as f32],
+				depth_range: 0.0..1.0,
+			}]),
+			..DynamicState::none()
+		}
+	}
+}
+
+/// Per view camera data, matches this glsl block:
+///
+/// ```glsl
+/// layout(set = 0, binding = 0) uniform View {
+///     mat4 view;
+///     mat4 projection;
+///     mat4 view_projection;
+///     vec4 position;
+/// } view;
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ViewUniform {
+	pub view: Mat4,
+	pub projection: Mat4,
+	pub view_projection: Mat4,
+	pub position: [f32; 4],
+}
+
+/// Hands out a uniform buffer per view per frame, so every view keeps its own
+/// camera data while all of them are recorded into one command buffer.
+pub struct ViewUniforms {
+	pool: CpuBufferPool<ViewUniform>,
+}
+
+impl ViewUniforms {
+	pub fn new(device: Arc<Device>) -> Self {
+		ViewUniforms {
+			pool: CpuBufferPool::uniform_buffer(device),
+		}
+	}
+
+	pub fn upload(&self, view: &View) -> CpuBufferPoolSubbuffer<ViewUniform, Arc<StdMemoryPool>> {
+		let position = view.position;
+		self.pool
+			.next(ViewUniform {
+				view: view.view,
+				projection: view.projection,
+				view_projection: view.view_projection(),
+				position: [position[0], position[1], position[2], 1.0],
+			})
+			.unwrap()
+	}
+}
@@ -0,0 +1,230 @@
+// flags frames that take longer than a budget, eg. 16.6ms for 60fps, and
+// keeps what each of them spent its time on, so a hitch seen while playing can
+// be traced to a pass afterwards.
+//
+// the time goes into spans, measured on the cpu between `begin_span` and
+// `end_span`. vulkano 0.22 can't record timestamp queries, so there are no
+// per pass gpu times. the renderer's "acquire" and "present" spans are where
+// the cpu waits for the gpu instead, a frame spending its time there was gpu
+// bound.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Keeps the last hitches, frames over the budget:
+///
+/// ```text
+/// watchdog.begin_frame();
+/// watchdog.begin_span("shadows");
+/// // record shadows
+/// watchdog.end_span();
+/// ```
+///
+/// The report is logged when the watchdog is dropped, eg. on exit, unless
+/// turned off with `set_report_on_exit`. `Renderer` keeps one when
+/// `EngineConfig::frame_budget` is set, see `Renderer::set_frame_budget`.
+pub struct Watchdog {
+	budget: Duration,
+	capacity: usize,
+	hitches: VecDeque<Hitch>,
+	frame: u64,
+	frame_start: Option<Instant>,
+	spans: Vec<Span>,
+	// indices into `spans` of the spans not ended yet, innermost last
+	open: Vec<(usize, Instant)>,
+	report_on_exit: bool,
+}
+
+/// Time spent between `begin_span` and `end_span`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+	pub name: String,
+	/// How many spans it's nested in.
+	pub depth: usize,
+	pub duration: Duration,
+}
+
+/// A frame that took longer than the budget.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hitch {
+	pub frame: u64,
+	/// From the frame's `begin_frame` to the next one.
+	pub time: Duration,
+	pub budget: Duration,
+	/// In the order they began.
+	pub spans: Vec<Span>,
+}
+
+impl Hitch {
+	/// The longest span not nested in another.
+	pub fn worst(&self) -> Option<&Span> {
+		self.spans
+			.iter()
+			.filter(|span| span.depth == 0)
+			.max_by_key(|span| span.duration)
+	}
+}
+
+impl Watchdog {
+	/// Flags frames over `budget`, keeping the last `capacity` of them.
+	pub fn new(budget: Duration, capacity: usize) -> Self {
+		Watchdog {
+			budget,
+			capacity: capacity.max(1),
+			hitches: VecDeque::new(),
+			frame: 0,
+			frame_start: None,
+			spans: Vec::new(),
+			open: Vec::new(),
+			report_on_exit: true,
+		}
+	}
+
+	pub fn budget(&self) -> Duration {
+		self.budget
+	}
+
+	pub fn set_budget(&mut self, budget: Duration) {
+		self.budget = budget;
+	}
+
+	/// Whether dropping the watchdog logs the hitches it still has.
+	pub fn set_report_on_exit(&mut self, report: bool) {
+		self.report_on_exit = report;
+	}
+
+	/// Ends the last frame, keeping it when it was over the budget, and
+	/// starts the next.
+	pub fn begin_frame(&mut self) {
+		let now = Instant::now();
+		// spans left open are cut off at the end of their frame
+		while !self.open.is_empty() {
+			self.end_span_at(now);
+		}
+		let spans = std::mem::take(&mut self.spans);
+		if let Some(start) = self.frame_start {
+			let time = now - start;
+			if time > self.budget {
+				if self.hitches.len() == self.capacity {
+					self.hitches.pop_front();
+				}
+				self.hitches.push_back(Hitch {
+					frame: self.frame,
+					time,
+					budget: self.budget,
+					spans,
+				});
+			}
+		}
+		self.frame += 1;
+		self.frame_start = Some(now);
+	}
+
+	/// Starts timing `name`, spans nest.
+	pub fn begin_span(&mut self, name: &str) {
+		if self.frame_start.is_none() {
+			return;
+		}
+		self.open.push((self.spans.len(), Instant::now()));
+		self.spans.push(Span {
+			name: name.to_string(),
+			depth: self.open.len() - 1,
+			duration: Duration::default(),
+		});
+	}
+
+	/// Ends the innermost span.
+	pub fn end_span(&mut self) {
+		self.end_span_at(Instant::now());
+	}
+
+	fn end_span_at(&mut self, now: Instant) {
+		if let Some((index, start)) = self.open.pop() {
+			self.spans[index].duration = now - start;
+		}
+	}
+
+	/// Times `f` as a span named `name`.
+	pub fn span<F, R>(&mut self, name: &str, f: F) -> R
+	where
+		F: FnOnce() -> R,
+	{
+		self.begin_span(name);
+		let result = f();
+		self.end_span();
+		result
+	}
+
+	/// The kept hitches, oldest first.
+	pub fn hitches(&self) -> impl Iterator<Item = &Hitch> {
+		self.hitches.iter()
+	}
+
+	/// Removes and returns the kept hitches, eg. to show them in game.
+	pub fn take_hitches(&mut self) -> Vec<Hitch> {
+		self.hitches.drain(..).collect()
+	}
+
+	pub fn report(&self) -> HitchReport {
+		HitchReport {
+			frames: self.frame.saturating_sub(1),
+			hitches: self.hitches.iter().cloned().collect(),
+		}
+	}
+}
+
+impl Drop for Watchdog {
+	fn drop(&mut self) {
+		if self.report_on_exit && !self.hitches.is_empty() {
+			log::warn!("{}", self.report());
+		}
+	}
+}
+
+/// What `Watchdog::report` found, printing it lists every hitch and where
+/// its time went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HitchReport {
+	/// Frames finished so far, over the budget or not.
+	pub frames: u64,
+	/// Oldest first.
+	pub hitches: Vec<Hitch>,
+}
+
+impl fmt::Display for HitchReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} of the last hitches in {} frames:",
+			self.hitches.len(),
+			self.frames
+		)?;
+		for hitch in &self.hitches {
+			write!(
+				f,
+				"\n  frame {}: {:.1}ms of {:.1}ms",
+				hitch.frame,
+				millis(hitch.time),
+				millis(hitch.budget)
+			)?;
+			if let Some(worst) = hitch.worst() {
+				write!(f, ", mostly {}", worst.name)?;
+			}
+			for span in &hitch.spans {
+				write!(
+					f,
+					"\n    {}{} {:.1}ms",
+					"  ".repeat(span.depth),
+					span.name,
+					millis(span.duration)
+				)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn millis(duration: Duration) -> f64 {
+	duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,910 @@
+use crate::cursor::{CursorMode, CursorState, CustomCursor};
+use crate::display::{DisplayOutput, Monitor};
+use crate::math::{Mat4, IDENTITY};
+use crate::render2d::Sprite;
+use crate::render_target::DEPTH_FORMAT;
+
+use vulkano::command_buffer::{
+	AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState, SubpassContents,
+};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageUsage, SwapchainImage};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::swapchain::{
+	self, AcquireError, Capabilities, ColorSpace, CompositeAlpha, FullscreenExclusive, PresentMode,
+	Surface, SurfaceTransform, Swapchain, SwapchainAcquireFuture, SwapchainCreationError,
+};
+use vulkano::sync::{self, FlushError, GpuFuture};
+use vulkano::{VulkanHandle, VulkanObject};
+
+use winit::dpi::LogicalPosition;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::{CursorIcon, Fullscreen, Window, WindowBuilder, WindowId};
+
+use std::sync::Arc;
+
+/// How a window's swapchain is set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapchainConfig {
+	/// Hdr output when the display supports it, see `WindowTarget::output`.
+	pub output: DisplayOutput,
+	/// Number of swapchain images, 2 for double and 3 for triple buffering.
+	/// Clamped to what the surface allows, `None` takes its minimum.
+	pub image_count: Option<u32>,
+	/// Keep the alpha of the rendered image so the desktop shows through.
+	/// The window has to be built with `with_transparent(true)` and the
+	/// image is expected to hold premultiplied alpha.
+	pub transparent: bool,
+	/// Render in the display's native orientation instead of letting the
+	/// compositor rotate every frame, which matters on rotated mobile screens.
+	/// Projections then have to be multiplied by `WindowTarget::pre_rotation`.
+	pub pre_transform: bool,
+	/// Wait for vertical blank to show a new image. Without it images are
+	/// replaced as they come (mailbox) or shown right away and may tear.
+	pub vsync: bool,
+}
+
+impl Default for SwapchainConfig {
+	fn default() -> Self {
+		SwapchainConfig {
+			output: DisplayOutput::default(),
+			image_count: None,
+			transparent: false,
+			pre_transform: false,
+			vsync: true,
+		}
+	}
+}
+
+/// A window with its own surface, swapchain and color+depth render pass.
+///
+/// Any number of these can share one device and queue, so tools can open
+/// extra windows for inspectors or previews and draw into them with the same
+/// pipelines and resources, as long as the pipelines were built for a
+/// compatible render pass. Each window keeps its own frame in flight.
+pub struct WindowTarget {
+	device: Arc<Device>,
+	queue: Arc<Queue>,
+	window: Arc<Window>,
+	// both `None` while suspended, the swapchain also while it's being
+	// replaced after a loss
+	surface: Option<Arc<Surface<Arc<Window>>>>,
+	swapchain: Option<Arc<Swapchain<Arc<Window>>>>,
+	// what the last swapchain was like, still there while suspended
+	info: SwapchainInfo,
+	suspended: bool,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	config: SwapchainConfig,
+	output: DisplayOutput,
+	framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+	dynamic_state: DynamicState,
+	recreate_swapchain: bool,
+	device_lost: bool,
+	scale_factor: f64,
+	cursor: CursorState,
+	previous_frame_end: Option<Box<dyn GpuFuture>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SwapchainInfo {
+	dimensions: [u32; 2],
+	format: Format,
+	image_count: u32,
+	composite_alpha: CompositeAlpha,
+	transform: SurfaceTransform,
+}
+
+impl SwapchainInfo {
+	fn of(swapchain: &Swapchain<Arc<Window>>) -> Self {
+		SwapchainInfo {
+			dimensions: swapchain.dimensions(),
+			format: swapchain.format(),
+			image_count: swapchain.num_images(),
+			composite_alpha: swapchain.composite_alpha(),
+			transform: swapchain.transform(),
+		}
+	}
+}
+
+/// A swapchain image acquired for drawing, hand it back with `present`.
+pub struct Frame {
+	image_num: usize,
+	acquire_future: SwapchainAcquireFuture<Arc<Window>>,
+}
+
+impl WindowTarget {
+	/// Builds the window and its swapchain. `queue` has to be able to present
+	/// to the new window, which any graphics queue can on desktop platforms.
+	pub fn new<T>(
+		builder: WindowBuilder,
+		event_loop: &EventLoopWindowTarget<T>,
+		device: Arc<Device>,
+		queue: Arc<Queue>,
+	) -> Self {
+		WindowTarget::with_config(
+			builder,
+			event_loop,
+			device,
+			queue,
+			SwapchainConfig::default(),
+		)
+	}
+
+	/// Like `new`, with an hdr swapchain when the display supports `output`.
+	/// Check `output` for what was picked, the instance needs the extensions
+	/// from `display::with_hdr_extensions` for hdr to be found.
+	pub fn with_output<T>(
+		builder: WindowBuilder,
+		event_loop: &EventLoopWindowTarget<T>,
+		device: Arc<Device>,
+		queue: Arc<Queue>,
+		output: DisplayOutput,
+	) -> Self {
+		let config = SwapchainConfig {
+			output,
+			..SwapchainConfig::default()
+		};
+		WindowTarget::with_config(builder, event_loop, device, queue, config)
+	}
+
+	/// Like `new`, with the swapchain set up from `config`.
+	pub fn with_config<T>(
+		builder: WindowBuilder,
+		event_loop: &EventLoopWindowTarget<T>,
+		device: Arc<Device>,
+		queue: Arc<Queue>,
+		config: SwapchainConfig,
+	) -> Self {
+		// the surface only borrows the window so it can be rebuilt when lost
+		let window = Arc::new(builder.build(event_loop).unwrap());
+		let surface = create_surface(&window, &device, &queue);
+		WindowTarget::from_surface(surface, device, queue, config)
+	}
+
+	// for a surface created before the device, eg. to pick a queue family
+	// that can present to it
+	pub(crate) fn from_surface(
+		surface: Arc<Surface<Arc<Window>>>,
+		device: Arc<Device>,
+		queue: Arc<Queue>,
+		config: SwapchainConfig,
+	) -> Self {
+		let window = surface.window().clone();
+		let (output, swapchain, images) =
+			create_swapchain(device.clone(), surface.clone(), &queue, config);
+		let render_pass = create_render_pass(device.clone(), swapchain.format());
+
+		let mut dynamic_state = DynamicState::none();
+		let framebuffers = window_size_dependent_setup(
+			device.clone(),
+			&images,
+			render_pass.clone(),
+			&mut dynamic_state,
+		);
+
+		WindowTarget {
+			previous_frame_end: Some(sync::now(device.clone()).boxed()),
+			scale_factor: window.scale_factor(),
+			cursor: CursorState::default(),
+			device,
+			queue,
+			window,
+			surface: Some(surface),
+			info: SwapchainInfo::of(&swapchain),
+			swapchain: Some(swapchain),
+			suspended: false,
+			render_pass,
+			config,
+			output,
+			framebuffers,
+			dynamic_state,
+			recreate_swapchain: false,
+			device_lost: false,
+		}
+	}
+
+	pub fn window(&self) -> &Window {
+		&self.window
+	}
+
+	pub fn id(&self) -> WindowId {
+		self.window.id()
+	}
+
+	pub fn render_pass(&self) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+		self.render_pass.clone()
+	}
+
+	/// Subpass that pipelines drawing into the window have to be built against.
+	pub fn subpass(&self) -> Subpass<Arc<dyn RenderPassAbstract + Send + Sync>> {
+		Subpass::from(self.render_pass.clone(), 0).unwrap()
+	}
+
+	pub fn dimensions(&self) -> [u32; 2] {
+		self.info.dimensions
+	}
+
+	/// How the swapchain is encoded, hand it to the pass that writes the
+	/// final image, eg. `DynamicResolution::output`.
+	pub fn output(&self) -> DisplayOutput {
+		self.output
+	}
+
+	pub fn config(&self) -> SwapchainConfig {
+		self.config
+	}
+
+	/// Rebuilds the swapchain with a new config. Waits for the queue to go
+	/// idle, so call it between frames. When the format changes, so does
+	/// `render_pass` and pipelines built against it have to be rebuilt.
+	pub fn set_config(&mut self, config: SwapchainConfig) {
+		if config == self.config {
+			return;
+		}
+		self.config = config;
+		if self.suspended {
+			// picked up by `resume`
+			return;
+		}
+		self.queue.wait().unwrap();
+		let format = self.info.format;
+		let images = self.replace_swapchain();
+		if self.swapchain().format() != format {
+			self.render_pass = create_render_pass(self.device.clone(), self.swapchain().format());
+		}
+		self.framebuffers = window_size_dependent_setup(
+			self.device.clone(),
+			&images,
+			self.render_pass.clone(),
+			&mut self.dynamic_state,
+		);
+	}
+
+	pub fn image_count(&self) -> u32 {
+		self.info.image_count
+	}
+
+	/// How the compositor blends the window with what's behind it.
+	pub fn composite_alpha(&self) -> CompositeAlpha {
+		self.info.composite_alpha
+	}
+
+	/// Rotation the images are rendered with, `Identity` unless
+	/// `SwapchainConfig::pre_transform` is set on a rotated display.
+	pub fn transform(&self) -> SurfaceTransform {
+		self.info.transform
+	}
+
+	/// Rotates clip space into the display's native orientation, multiply
+	/// projections by it from the left. Identity without a pre-transform.
+	pub fn pre_rotation(&self) -> Mat4 {
+		let (sin, cos) = match self.transform() {
+			SurfaceTransform::Rotate90 => (1.0, 0.0),
+			SurfaceTransform::Rotate180 => (0.0, -1.0),
+			SurfaceTransform::Rotate270 => (-1.0, 0.0),
+			_ => return IDENTITY,
+		};
+		[
+			[cos, sin, 0.0, 0.0],
+			[-sin, cos, 0.0, 0.0],
+			[0.0, 0.0, 1.0, 0.0],
+			[0.0, 0.0, 0.0, 1.0],
+		]
+	}
+
+	/// Aspect ratio of the window as the user sees it, for projections. Unlike
+	/// `dimensions` it doesn't change with the pre-transform.
+	pub fn aspect_ratio(&self) -> f32 {
+		let [width, height] = swapchain_dimensions(self.dimensions(), self.transform());
+		width as f32 / height as f32
+	}
+
+	/// The monitor the window is mostly on, `None` when the platform can't
+	/// tell.
+	pub fn monitor(&self) -> Option<Monitor> {
+		let primary = self.window.primary_monitor();
+		self.window.current_monitor().map(|handle| {
+			let is_primary = primary.as_ref() == Some(&handle);
+			Monitor::from_handle(handle, is_primary)
+		})
+	}
+
+	/// Makes the window fullscreen, eg. with `Monitor::borderless` or
+	/// `DisplayMode::exclusive`, or windowed again with `None`. The swapchain
+	/// follows on the next frame through the resize event.
+	pub fn set_fullscreen(&mut self, fullscreen: Option<Fullscreen>) {
+		self.window.set_fullscreen(fullscreen);
+		self.recreate_swapchain = true;
+	}
+
+	pub fn fullscreen(&self) -> Option<Fullscreen> {
+		self.window.fullscreen()
+	}
+
+	/// Physical pixels per logical pixel of the display the window is on,
+	/// eg. 2 on most hidpi displays.
+	pub fn scale_factor(&self) -> f64 {
+		self.scale_factor
+	}
+
+	/// Size of the window in logical pixels, what ui should be laid out in.
+	pub fn logical_dimensions(&self) -> [f32; 2] {
+		let size = self
+			.window
+			.inner_size()
+			.to_logical::<f32>(self.scale_factor);
+		[size.width, size.height]
+	}
+
+	/// Physical position, eg. from `WindowEvent::CursorMoved`, in logical pixels.
+	pub fn to_logical(&self, physical: [f32; 2]) -> [f32; 2] {
+		let scale = self.scale_factor as f32;
+		[physical[0] / scale, physical[1] / scale]
+	}
+
+	pub fn to_physical(&self, logical: [f32; 2]) -> [f32; 2] {
+		let scale = self.scale_factor as f32;
+		[logical[0] * scale, logical[1] * scale]
+	}
+
+	/// Format of the swapchain images, an srgb one whenever the surface has it.
+	pub fn format(&self) -> Format {
+		self.info.format
+	}
+
+	/// True when the swapchain format isn't srgb and the final pass has to
+	/// apply the srgb curve itself, eg. through `DynamicResolution::encode_gamma`.
+	pub fn needs_gamma(&self) -> bool {
+		self.output.needs_gamma(self.format())
+	}
+
+	/// Viewport covering the whole window.
+	pub fn dynamic_state(&self) -> &DynamicState {
+		&self.dynamic_state
+	}
+
+	/// Call on `WindowEvent::Resized`, the swapchain is recreated before the next frame.
+	pub fn resized(&mut self) {
+		self.recreate_swapchain = true;
+	}
+
+	/// Call on `WindowEvent::ScaleFactorChanged`, eg. when the window moved to
+	/// another display. The size winit suggests in the event is kept, so the
+	/// swapchain is recreated like after a resize.
+	pub fn scale_factor_changed(&mut self, scale_factor: f64) {
+		self.scale_factor = scale_factor;
+		self.recreate_swapchain = true;
+	}
+
+	pub fn cursor_mode(&self) -> CursorMode {
+		self.cursor.mode
+	}
+
+	/// Hides, confines or locks the cursor. Grabs are let go while the window
+	/// is out of focus and taken again when it comes back, see `focused`.
+	pub fn set_cursor_mode(&mut self, mode: CursorMode) {
+		self.cursor.mode = mode;
+		self.cursor.apply(&self.window);
+	}
+
+	/// Shows one of the system cursors, replacing a custom one.
+	pub fn set_cursor_icon(&mut self, icon: CursorIcon) {
+		self.cursor.icon = icon;
+		self.cursor.custom = None;
+		self.cursor.apply(&self.window);
+	}
+
+	/// Hides the system cursor in favor of `cursor`, which has to be drawn
+	/// with the ui from `cursor_sprite`. `None` brings the system one back.
+	pub fn set_custom_cursor(&mut self, cursor: Option<CustomCursor>) {
+		self.cursor.custom = cursor;
+		self.cursor.apply(&self.window);
+	}
+
+	/// The custom cursor where the cursor is, in physical pixels, `None`
+	/// without a custom cursor, while it's hidden or outside of the window.
+	pub fn cursor_sprite(&self) -> Option<Sprite> {
+		self.cursor.sprite(self.scale_factor as f32)
+	}
+
+	/// Last cursor position in physical pixels, `None` while it's outside
+	/// of the window.
+	pub fn cursor_position(&self) -> Option<[f32; 2]> {
+		self.cursor.position
+	}
+
+	/// Call on `WindowEvent::CursorMoved`.
+	pub fn cursor_moved(&mut self, position: [f32; 2]) {
+		self.cursor.moved(&self.window, position);
+	}
+
+	/// Call on `WindowEvent::CursorLeft`.
+	pub fn cursor_left(&mut self) {
+		self.cursor.position = None;
+	}
+
+	/// Places the ime candidate box, eg. under the caret of the text field
+	/// being edited, in logical pixels.
+	pub fn set_ime_position(&self, position: [f32; 2]) {
+		self.window
+			.set_ime_position(LogicalPosition::new(position[0], position[1]));
+	}
+
+	/// Call on `WindowEvent::Focused`, releases the cursor while the window
+	/// isn't focused.
+	pub fn focused(&mut self, focused: bool) {
+		self.cursor.focused = focused;
+		self.cursor.apply(&self.window);
+	}
+
+	/// Acquires the next swapchain image. Returns `None` when there is nothing
+	/// to draw this time, eg. while the window is minimized.
+	///
+	/// Lost surfaces are rebuilt on the spot. After the device is lost this
+	/// keeps returning `None` until `recreate_device`, see `is_device_lost`.
+	pub fn acquire(&mut self) -> Option<Frame> {
+		if self.device_lost || self.suspended {
+			return None;
+		}
+		self.previous_frame_end.as_mut().unwrap().cleanup_finished();
+
+		if self.recreate_swapchain {
+			let caps = self
+				.surface()
+				.capabilities(self.device.physical_device())
+				.unwrap();
+			let transform = pre_transform(&caps, self.config.pre_transform);
+			let dimensions = swapchain_dimensions(self.window.inner_size().into(), transform);
+			let recreated = if transform != self.swapchain().transform() && !self.output.is_hdr() {
+				// the display was rotated. vulkano 0.22 only keeps the color
+				// space when recreating with the same transform, so hdr
+				// swapchains stay in their original orientation
+				Swapchain::with_old_swapchain(
+					self.device.clone(),
+					self.surface().clone(),
+					self.swapchain().num_images(),
+					self.swapchain().format(),
+					dimensions,
+					1,
+					ImageUsage::color_attachment(),
+					&self.queue,
+					transform,
+					self.swapchain().composite_alpha(),
+					self.swapchain().present_mode(),
+					FullscreenExclusive::Default,
+					true,
+					ColorSpace::SrgbNonLinear,
+					self.swapchain().clone(),
+				)
+			} else {
+				self.swapchain()
+					.recreate_with_dimensions(swapchain_dimensions(
+						self.window.inner_size().into(),
+						self.swapchain().transform(),
+					))
+			};
+			let (swapchain, images) = match recreated {
+				Ok(r) => r,
+				Err(SwapchainCreationError::UnsupportedDimensions) => return None,
+				Err(SwapchainCreationError::SurfaceLost) => {
+					self.recreate_surface();
+					return None;
+				}
+				Err(SwapchainCreationError::DeviceLost) => {
+					self.device_lost = true;
+					return None;
+				}
+				Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+			};
+			self.info = SwapchainInfo::of(&swapchain);
+			self.swapchain = Some(swapchain);
+			self.framebuffers = window_size_dependent_setup(
+				self.device.clone(),
+				&images,
+				self.render_pass.clone(),
+				&mut self.dynamic_state,
+			);
+			self.recreate_swapchain = false;
+		}
+
+		let (image_num, suboptimal, acquire_future) =
+			match swapchain::acquire_next_image(self.swapchain().clone(), None) {
+				Ok(r) => r,
+				Err(AcquireError::OutOfDate) => {
+					self.recreate_swapchain = true;
+					return None;
+				}
+				Err(AcquireError::SurfaceLost) => {
+					self.recreate_surface();
+					return None;
+				}
+				Err(AcquireError::DeviceLost) => {
+					self.device_lost = true;
+					return None;
+				}
+				Err(e) => panic!("Failed to acquire next image: {:?}", e),
+			};
+
+		if suboptimal {
+			self.recreate_swapchain = true;
+		}
+
+		Some(Frame {
+			image_num,
+			acquire_future,
+		})
+	}
+
+	/// Begins the window's render pass on the acquired image, clearing depth to 1.
+	pub fn begin_render_pass(
+		&self,
+		builder: &mut AutoCommandBufferBuilder,
+		frame: &Frame,
+		clear_color: ClearValue,
+	) {
+		builder
+			.begin_render_pass(
+				self.framebuffers[frame.image_num].clone(),
+				SubpassContents::Inline,
+				vec![clear_color, 1f32.into()],
+			)
+			.unwrap();
+	}
+
+	/// Makes the next frame wait for `future` too, eg. a texture upload.
+	pub fn join<F>(&mut self, future: F)
+	where
+		F: GpuFuture + 'static,
+	{
+		let previous = self.previous_frame_end.take().unwrap();
+		self.previous_frame_end = Some(previous.join(future).boxed());
+	}
+
+	/// Submits the frame's commands and presents the image.
+	pub fn present(&mut self, frame: Frame, command_buffer: AutoCommandBuffer) {
+		let future = self
+			.previous_frame_end
+			.take()
+			.unwrap()
+			.join(frame.acquire_future)
+			.then_execute(self.queue.clone(), command_buffer)
+			.unwrap()
+			.then_swapchain_present(
+				self.queue.clone(),
+				self.swapchain().clone(),
+				frame.image_num,
+			)
+			.then_signal_fence_and_flush();
+
+		match future {
+			Ok(future) => {
+				self.previous_frame_end = Some(future.boxed());
+			}
+			Err(FlushError::OutOfDate) => {
+				self.recreate_swapchain = true;
+				self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+			}
+			Err(FlushError::SurfaceLost) => {
+				self.recreate_surface();
+			}
+			Err(FlushError::DeviceLost) => {
+				self.device_lost = true;
+				self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+			}
+			Err(e) => {
+				log::warn!("Failed to flush future: {:?}", e);
+				self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+			}
+		}
+	}
+
+	/// True once the device stopped responding, after a driver reset or the
+	/// gpu going away. Nothing drawn with it will show up again, build a new
+	/// device (eg. with `recovery::DeviceRecovery`) and hand it to `recreate_device`.
+	pub fn is_device_lost(&self) -> bool {
+		self.device_lost
+	}
+
+	/// Rebuilds the swapchain and render pass on a new device. Pipelines built
+	/// against the old `subpass` have to be rebuilt too.
+	pub fn recreate_device(&mut self, device: Arc<Device>, queue: Arc<Queue>) {
+		self.device = device;
+		self.queue = queue;
+		self.device_lost = false;
+		if self.suspended {
+			self.render_pass = create_render_pass(self.device.clone(), self.info.format);
+			self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+			return;
+		}
+		let images = self.replace_swapchain();
+		self.render_pass = create_render_pass(self.device.clone(), self.swapchain().format());
+		self.framebuffers = window_size_dependent_setup(
+			self.device.clone(),
+			&images,
+			self.render_pass.clone(),
+			&mut self.dynamic_state,
+		);
+	}
+
+	/// Whether `suspend` was called without a `resume` yet.
+	pub fn is_suspended(&self) -> bool {
+		self.suspended
+	}
+
+	/// Call on `Event::Suspended`. Destroys the swapchain and surface, which
+	/// android takes away from apps in the background, `acquire` returns
+	/// `None` until `resume`. Everything else, including the render pass,
+	/// stays as it is.
+	pub fn suspend(&mut self) {
+		if self.suspended {
+			return;
+		}
+		self.queue.wait().unwrap();
+		self.framebuffers.clear();
+		self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+		self.swapchain = None;
+		self.surface = None;
+		self.suspended = true;
+	}
+
+	/// Call on `Event::Resumed`, builds a surface and swapchain for the
+	/// window again. Does nothing unless suspended, desktop platforms send
+	/// it once at startup too.
+	pub fn resume(&mut self) {
+		if !self.suspended {
+			return;
+		}
+		self.suspended = false;
+		let format = self.info.format;
+		let images = self.replace_swapchain();
+		if self.swapchain().format() != format {
+			self.render_pass = create_render_pass(self.device.clone(), self.swapchain().format());
+		}
+		self.framebuffers = window_size_dependent_setup(
+			self.device.clone(),
+			&images,
+			self.render_pass.clone(),
+			&mut self.dynamic_state,
+		);
+	}
+
+	fn swapchain(&self) -> &Arc<Swapchain<Arc<Window>>> {
+		self.swapchain.as_ref().unwrap()
+	}
+
+	// raw handle for the extensions vulkano doesn't wrap, none while suspended
+	pub(crate) fn swapchain_handle(&self) -> Option<u64> {
+		self.swapchain
+			.as_ref()
+			.map(|swapchain| swapchain.internal_object().value())
+	}
+
+	fn surface(&self) -> &Arc<Surface<Arc<Window>>> {
+		self.surface.as_ref().unwrap()
+	}
+
+	// the surface went away, eg. when an android app is sent to the background
+	// or the display driver restarted, while the device itself is fine
+	fn recreate_surface(&mut self) {
+		let format = self.swapchain().format();
+		let images = self.replace_swapchain();
+		assert!(
+			self.swapchain().format() == format,
+			"surface format changed after the surface was lost"
+		);
+		self.framebuffers = window_size_dependent_setup(
+			self.device.clone(),
+			&images,
+			self.render_pass.clone(),
+			&mut self.dynamic_state,
+		);
+	}
+
+	// a new surface and swapchain, the old swapchain has to be gone before
+	// the window can get another one
+	fn replace_swapchain(&mut self) -> SwapchainImages {
+		self.framebuffers.clear();
+		self.previous_frame_end = None;
+		self.swapchain = None;
+		let surface = create_surface(&self.window, &self.device, &self.queue);
+		let (output, swapchain, images) = create_swapchain(
+			self.device.clone(),
+			surface.clone(),
+			&self.queue,
+			self.config,
+		);
+		self.surface = Some(surface);
+		self.output = output;
+		self.info = SwapchainInfo::of(&swapchain);
+		self.swapchain = Some(swapchain);
+		self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+		self.recreate_swapchain = false;
+		images
+	}
+}
+
+type SwapchainImages = Vec<Arc<SwapchainImage<Arc<Window>>>>;
+
+fn create_surface(
+	window: &Arc<Window>,
+	device: &Arc<Device>,
+	queue: &Queue,
+) -> Arc<Surface<Arc<Window>>> {
+	let surface =
+		vulkano_win::create_vk_surface(window.clone(), device.instance().clone()).unwrap();
+	assert!(
+		surface.is_supported(queue.family()).unwrap_or(false),
+		"queue can't present to the window"
+	);
+	surface
+}
+
+fn create_swapchain(
+	device: Arc<Device>,
+	surface: Arc<Surface<Arc<Window>>>,
+	queue: &Arc<Queue>,
+	config: SwapchainConfig,
+) -> (DisplayOutput, Arc<Swapchain<Arc<Window>>>, SwapchainImages) {
+	let caps = surface.capabilities(device.physical_device()).unwrap();
+	let output = config.output.resolve(&caps);
+	let (format, color_space) = output.surface_format(&caps).unwrap();
+	let transform = pre_transform(&caps, config.pre_transform);
+	let dimensions = swapchain_dimensions(surface.window().inner_size().into(), transform);
+
+	let (swapchain, images) = Swapchain::new(
+		device,
+		surface.clone(),
+		image_count(&caps, config.image_count),
+		format,
+		dimensions,
+		1,
+		ImageUsage::color_attachment(),
+		queue,
+		transform,
+		composite_alpha(&caps, config.transparent),
+		present_mode(&caps, config.vsync),
+		FullscreenExclusive::Default,
+		true,
+		color_space,
+	)
+	.unwrap();
+	(output, swapchain, images)
+}
+
+fn create_render_pass(
+	device: Arc<Device>,
+	format: Format,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+	Arc::new(
+		vulkano::single_pass_renderpass!(
+			device,
+			attachments: {
+				color: {
+					load: Clear,
+					store: Store,
+					format: format,
+					samples: 1,
+				},
+				depth: {
+					load: Clear,
+					store: DontCare,
+					format: DEPTH_FORMAT,
+					samples: 1,
+				}
+			},
+			pass: {
+				color: [color],
+				depth_stencil: {depth}
+			}
+		)
+		.unwrap(),
+	)
+}
+
+fn image_count(caps: &Capabilities, requested: Option<u32>) -> u32 {
+	let count = requested
+		.unwrap_or(caps.min_image_count)
+		.max(caps.min_image_count);
+	match caps.max_image_count {
+		Some(max) => count.min(max),
+		None => count,
+	}
+}
+
+fn present_mode(caps: &Capabilities, vsync: bool) -> PresentMode {
+	let modes = caps.present_modes;
+	if vsync {
+		PresentMode::Fifo
+	} else if modes.supports(PresentMode::Mailbox) {
+		PresentMode::Mailbox
+	} else if modes.supports(PresentMode::Immediate) {
+		PresentMode::Immediate
+	} else {
+		PresentMode::Fifo
+	}
+}
+
+fn composite_alpha(caps: &Capabilities, transparent: bool) -> CompositeAlpha {
+	let preferred: &[CompositeAlpha] = if transparent {
+		&[
+			CompositeAlpha::PreMultiplied,
+			CompositeAlpha::PostMultiplied,
+			CompositeAlpha::Inherit,
+		]
+	} else {
+		&[CompositeAlpha::Opaque, CompositeAlpha::Inherit]
+	};
+	preferred
+		.iter()
+		.copied()
+		.find(|&alpha| caps.supported_composite_alpha.supports(alpha))
+		.unwrap_or_else(|| caps.supported_composite_alpha.iter().next().unwrap())
+}
+
+// only rotations are pre-transformed, anything else is left to the compositor
+fn pre_transform(caps: &Capabilities, enabled: bool) -> SurfaceTransform {
+	let current = caps.current_transform;
+	let rotated = matches!(
+		current,
+		SurfaceTransform::Rotate90 | SurfaceTransform::Rotate180 | SurfaceTransform::Rotate270
+	);
+	if (enabled && rotated)
+		|| !caps
+			.supported_transforms
+			.supports(SurfaceTransform::Identity)
+	{
+		current
+	} else {
+		SurfaceTransform::Identity
+	}
+}
+
+// swapchain images are in the display's native orientation, the window size
+// is in the rotated one
+fn swapchain_dimensions(dimensions: [u32; 2], transform: SurfaceTransform) -> [u32; 2] {
+	match transform {
+		SurfaceTransform::Rotate90 | SurfaceTransform::Rotate270 => [dimensions[1], dimensions[0]],
+		_ => dimensions,
+	}
+}
+
+fn window_size_dependent_setup(
+	device: Arc<Device>,
+	images: &[Arc<SwapchainImage<Arc<Window>>>],
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	dynamic_state: &mut DynamicState,
+) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
+	let dimensions = images[0].dimensions();
+
+	let viewport = Viewport {
+		origin: [0.0, 0.0],
+		dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+		depth_range: 0.0..1.0,
+	};
+	dynamic_state.viewports = Some(vec![viewport]);
+
+	// one depth buffer is enough, only one image is drawn at a time
+	let depth =
+		ImageView::new(AttachmentImage::transient(device, dimensions, DEPTH_FORMAT).unwrap())
+			.unwrap();
+
+	images
+		.iter()
+		.map(|image| {
+			let view = ImageView::new(image.clone()).unwrap();
+
+			Arc::new(
+				Framebuffer::start(render_pass.clone())
+					.add(view)
+					.unwrap()
+					.add(depth.clone())
+					.unwrap()
+					.build()
+					.unwrap(),
+			) as Arc<dyn FramebufferAbstract + Send + Sync>
+		})
+		.collect::<Vec<_>>()
+}